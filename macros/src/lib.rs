@@ -0,0 +1,135 @@
+//! Attribute macros for [torch-web](https://docs.rs/torch-web), which
+//! re-exports them with its `macros` feature
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Error, ItemFn, LitStr, Token, Visibility};
+
+/// Caches a handler's successful `GET` responses for `ttl`:
+///
+/// ```rust,ignore
+/// use torch_web::{cache, extractors::Path, Response};
+///
+/// #[cache(ttl = "60s", vary = ["Accept-Language"])]
+/// pub async fn show(Path(id): Path<u32>) -> Response {
+///     Response::ok().body(format!("Post {}", id))
+/// }
+/// ```
+///
+/// `ttl` takes `ms`, `s`, `m` or `h`, or a bare number of seconds.
+/// Requests with different values for the `vary` headers get their own
+/// entries. The handler becomes an `async fn(Request) -> Response` that runs
+/// the original through `torch_web::cache::Cached`, so it still registers
+/// with `App::get`; its body can't refer to `Self`.
+#[proc_macro_attribute]
+pub fn cache(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = TokenStream2::from(args);
+    let item = TokenStream2::from(item);
+    match parse_args(args).and_then(|(ttl, vary)| expand(ttl, vary, syn::parse2(item)?)) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// `ttl` in milliseconds and the `vary` headers
+fn parse_args(args: TokenStream2) -> syn::Result<(u64, Vec<String>)> {
+    let mut ttl = None;
+    let mut vary = Vec::new();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("ttl") {
+            let value: LitStr = meta.value()?.parse()?;
+            let millis = parse_duration(&value.value()).map_err(|message| Error::new(value.span(), message))?;
+            ttl = Some(millis);
+            Ok(())
+        } else if meta.path.is_ident("vary") {
+            let input = meta.value()?;
+            let content;
+            syn::bracketed!(content in input);
+            for header in Punctuated::<LitStr, Token![,]>::parse_terminated(&content)? {
+                let name = header.value();
+                if name.is_empty() || !name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)) {
+                    return Err(Error::new(header.span(), format!("invalid header name '{}'", name)));
+                }
+                vary.push(name);
+            }
+            Ok(())
+        } else {
+            Err(meta.error("expected `ttl` or `vary`"))
+        }
+    });
+    parser.parse2(args)?;
+
+    let ttl = ttl.ok_or_else(|| Error::new(Span::call_site(), "#[cache] needs a `ttl`, e.g. #[cache(ttl = \"60s\")]"))?;
+    Ok((ttl, vary))
+}
+
+/// Parse durations such as `30s`, `2m`, `500ms` or a bare number of
+/// seconds into milliseconds
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+    let millis = match unit {
+        "ms" => number,
+        "" | "s" => number * 1000.0,
+        "m" => number * 60_000.0,
+        "h" => number * 3_600_000.0,
+        _ => return Err(format!("invalid duration unit '{}', expected ms, s, m or h", unit)),
+    };
+    if millis < 1.0 {
+        return Err("duration must be at least 1ms".to_string());
+    }
+    Ok(millis as u64)
+}
+
+fn expand(ttl: u64, vary: Vec<String>, mut function: ItemFn) -> syn::Result<TokenStream2> {
+    if function.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(function.sig.fn_token, "#[cache] handlers must be `async fn`"));
+    }
+    if let Some(receiver) = function.sig.receiver() {
+        return Err(Error::new_spanned(receiver, "#[cache] handlers can't take `self`"));
+    }
+    if !function.sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(&function.sig.generics, "#[cache] handlers can't be generic"));
+    }
+
+    // The original keeps its name inside the wrapper, which shadows it there
+    let attrs = std::mem::take(&mut function.attrs);
+    let vis = std::mem::replace(&mut function.vis, Visibility::Inherited);
+    let name = function.sig.ident.clone();
+    let request = format_ident!("__torch_request");
+    Ok(quote! {
+        #(#attrs)*
+        #vis async fn #name(#request: ::torch_web::Request) -> ::torch_web::Response {
+            #function
+
+            ::torch_web::cache::Cached::new(::std::time::Duration::from_millis(#ttl))
+                .vary(&[#(#vary),*])
+                .call(#request, #name)
+                .await
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let (ttl, vary) = parse_args(quote!(ttl = "1.5m", vary = ["Accept-Language", "X-Tenant"])).unwrap();
+        assert_eq!(ttl, 90_000);
+        assert_eq!(vary, ["Accept-Language", "X-Tenant"]);
+
+        assert_eq!(parse_args(quote!(ttl = "250ms")).unwrap(), (250, vec![]));
+        assert_eq!(parse_args(quote!(ttl = "2")).unwrap().0, 2000);
+        assert!(parse_args(quote!(vary = ["Accept"])).is_err());
+        assert!(parse_args(quote!(ttl = "5w")).is_err());
+        assert!(parse_args(quote!(ttl = "60s", vary = ["Bad Header"])).is_err());
+        assert!(parse_args(quote!(expires = "60s")).is_err());
+    }
+}
@@ -14,6 +14,8 @@
 //! - **API Testing**: Built-in testing utilities for API endpoints
 //! - **Rate Limiting**: Per-endpoint rate limiting configuration
 //! - **Authentication**: API key and JWT authentication support
+//! - **Response Envelopes**: Wrap a route group's JSON in `data` and `meta` with [`ApiEnvelope`]
+//! - **Conditional GETs**: `ETag`s and `304 Not Modified` for polled JSON with [`ConditionalGet`]
 //!
 //! **Note**: This module requires the `api` feature to be enabled.
 //!
@@ -141,6 +143,15 @@
 use std::collections::HashMap;
 use crate::{Request, Response, App, Handler};
 
+#[cfg(feature = "json")]
+mod envelope;
+#[cfg(feature = "json")]
+mod etag;
+#[cfg(feature = "json")]
+pub use envelope::{ApiEnvelope, Envelope};
+#[cfg(feature = "json")]
+pub use etag::ConditionalGet;
+
 #[cfg(feature = "json")]
 use serde_json::{json, Value};
 
@@ -0,0 +1,241 @@
+//! Response envelopes for API route groups
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use serde_json::{Map, Value};
+
+use crate::clock::unix_now_ms;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+/// How one group's JSON responses are wrapped. By default a success becomes
+/// `{"data": ..., "meta": {"request_id": ..., "duration_ms": ...}}` and an
+/// error `{"error": ..., "meta": {...}}`.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    data_key: String,
+    error_key: String,
+    meta_key: String,
+    request_id: bool,
+    duration: bool,
+    meta: Map<String, Value>,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            data_key: "data".to_string(),
+            error_key: "error".to_string(),
+            meta_key: "meta".to_string(),
+            request_id: true,
+            duration: true,
+            meta: Map::new(),
+        }
+    }
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Key holding successful responses (default `data`)
+    pub fn data_key(mut self, key: &str) -> Self {
+        self.data_key = key.to_string();
+        self
+    }
+
+    /// Key holding `4xx` and `5xx` responses (default `error`)
+    pub fn error_key(mut self, key: &str) -> Self {
+        self.error_key = key.to_string();
+        self
+    }
+
+    /// Key holding the metadata (default `meta`)
+    pub fn meta_key(mut self, key: &str) -> Self {
+        self.meta_key = key.to_string();
+        self
+    }
+
+    /// Add a fixed metadata field to every response, e.g. the API version
+    pub fn meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Whether to include `request_id` (default `true`)
+    pub fn request_id(mut self, include: bool) -> Self {
+        self.request_id = include;
+        self
+    }
+
+    /// Whether to include `duration_ms` (default `true`)
+    pub fn duration(mut self, include: bool) -> Self {
+        self.duration = include;
+        self
+    }
+
+    fn wrap(&self, body: Value, success: bool, request_id: &str, duration_ms: f64) -> Value {
+        let mut meta = self.meta.clone();
+        if self.request_id {
+            meta.insert("request_id".to_string(), Value::from(request_id));
+        }
+        if self.duration {
+            meta.insert("duration_ms".to_string(), Value::from((duration_ms * 1000.0).round() / 1000.0));
+        }
+        let mut envelope = Map::new();
+        envelope.insert(if success { &self.data_key } else { &self.error_key }.clone(), body);
+        envelope.insert(self.meta_key.clone(), Value::Object(meta));
+        Value::Object(envelope)
+    }
+}
+
+/// Middleware wrapping the JSON responses of route groups in an
+/// [`Envelope`], picked by the longest matching path prefix:
+///
+/// ```rust
+/// use torch_web::App;
+/// use torch_web::api::{ApiEnvelope, Envelope};
+///
+/// let app = App::new()
+///     .middleware(
+///         ApiEnvelope::new()
+///             .group("/api/v1", Envelope::new().meta("version", "1"))
+///             .group("/api/v2", Envelope::new().data_key("result").meta("version", "2")),
+///     )
+///     .get("/api/v2/users", |_req: torch_web::Request| async {
+///         torch_web::Response::ok().json(&["ada", "grace"]).unwrap()
+///     });
+/// // GET /api/v2/users -> {"result":["ada","grace"],"meta":{"version":"2","request_id":"...","duration_ms":0.1}}
+/// ```
+///
+/// The request id is the `X-Request-Id` header, or a new id that is also
+/// sent back in that header. Responses that aren't JSON, are streamed, or
+/// are sent with `X-Envelope: skip` are left alone; the marker header is
+/// removed.
+#[derive(Debug, Clone, Default)]
+pub struct ApiEnvelope {
+    groups: Vec<(String, Envelope)>,
+}
+
+impl ApiEnvelope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap responses to paths under `prefix` in `envelope`
+    pub fn group(mut self, prefix: &str, envelope: Envelope) -> Self {
+        self.groups.push((prefix.trim_end_matches('/').to_string(), envelope));
+        self
+    }
+
+    fn envelope_for(&self, path: &str) -> Option<&Envelope> {
+        self.groups
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, envelope)| envelope)
+    }
+}
+
+impl Middleware for ApiEnvelope {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(envelope) = self.envelope_for(req.path()).cloned() else {
+            return next(req);
+        };
+        let started = Instant::now();
+        let given_id = req.header("x-request-id").map(str::to_string);
+        Box::pin(async move {
+            let mut response = next(req).await;
+            if response.headers_mut().remove("x-envelope").is_some_and(|value| value == "skip") {
+                return response;
+            }
+            let is_json = response
+                .headers()
+                .get("content-type")
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.trim_start().to_ascii_lowercase().starts_with("application/json"));
+            if !is_json || response.is_streaming() || response.headers().contains_key("content-encoding") {
+                return response;
+            }
+            let Ok(body) = serde_json::from_slice::<Value>(response.body_data()) else {
+                return response;
+            };
+
+            let request_id = given_id.clone().unwrap_or_else(|| {
+                format!("{:x}-{:x}", unix_now_ms(), NEXT_REQUEST.fetch_add(1, Ordering::Relaxed))
+            });
+            let success = !response.status_code().is_client_error() && !response.status_code().is_server_error();
+            let wrapped = envelope.wrap(body, success, &request_id, started.elapsed().as_secs_f64() * 1000.0);
+            let bytes = serde_json::to_vec(&wrapped).unwrap_or_default();
+            let length = bytes.len();
+            let mut response = response.body_from_bytes(bytes);
+            if response.headers().contains_key("content-length") {
+                response.headers_mut().insert("content-length", length.into());
+            }
+            if given_id.is_none() {
+                response = response.header("X-Request-Id", &request_id);
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+
+    #[tokio::test]
+    async fn test_api_envelope_wraps_json_per_group() {
+        let app = App::new()
+            .middleware(
+                ApiEnvelope::new()
+                    .group("/api", Envelope::new())
+                    .group("/api/v2", Envelope::new().data_key("result").duration(false).meta("version", "2")),
+            )
+            .get("/api/v1/users", |_req: Request| async { Response::ok().json(&["ada"]).unwrap() })
+            .get("/api/v2/users", |_req: Request| async { Response::ok().json(&["grace"]).unwrap() })
+            .get("/api/v2/missing", |_req: Request| async {
+                Response::not_found().json(&serde_json::json!({ "message": "No such user" })).unwrap()
+            })
+            .get("/api/v2/raw", |_req: Request| async { Response::ok().json(&1).unwrap().header("X-Envelope", "skip") })
+            .get("/api/v2/text", |_req: Request| async { "plain" })
+            .get("/apiary", |_req: Request| async { Response::ok().json(&[1]).unwrap() });
+        let get = |path: &str| app.handle_request(Request::mock(Method::GET, path).with_header("x-request-id", "req-7"));
+        let json = |response: Response| serde_json::from_slice::<Value>(response.body_data()).unwrap();
+
+        let v1 = json(get("/api/v1/users").await);
+        assert_eq!(v1["data"], serde_json::json!(["ada"]));
+        assert_eq!(v1["meta"]["request_id"], "req-7");
+        assert!(v1["meta"]["duration_ms"].is_number());
+
+        let v2 = json(get("/api/v2/users").await);
+        assert_eq!(v2, serde_json::json!({ "result": ["grace"], "meta": { "version": "2", "request_id": "req-7" } }));
+        let missing = get("/api/v2/missing").await;
+        assert_eq!(missing.status_code().as_u16(), 404);
+        assert_eq!(json(missing)["error"]["message"], "No such user");
+
+        let raw = get("/api/v2/raw").await;
+        assert!(!raw.headers().contains_key("x-envelope"));
+        assert_eq!(raw.body_data(), b"1");
+        assert_eq!(get("/api/v2/text").await.body_data(), b"plain");
+        assert_eq!(get("/apiary").await.body_data(), b"[1]");
+
+        let generated = app.handle_request(Request::mock(Method::GET, "/api/v1/users")).await;
+        let id = generated.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+        assert_eq!(json(generated)["meta"]["request_id"], id.as_str());
+    }
+}
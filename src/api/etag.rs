@@ -0,0 +1,153 @@
+//! Conditional GETs for JSON route groups
+
+use std::future::Future;
+use std::pin::Pin;
+
+use http::StatusCode;
+
+use crate::middleware::Middleware;
+use crate::{Method, Request, Response};
+
+/// FNV-1a, so ETags stay the same across restarts and instances
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Whether an `If-None-Match` header matches `etag`, comparing weakly
+fn none_match(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    header.trim() == "*" || header.split(',').any(|candidate| opaque(candidate) == opaque(etag))
+}
+
+/// Middleware giving `200` JSON responses of route groups a weak `ETag`
+/// derived from the body, and answering `304 Not Modified` when the client's
+/// `If-None-Match` already has it:
+///
+/// ```rust
+/// use torch_web::App;
+/// use torch_web::api::{ApiEnvelope, ConditionalGet, Envelope};
+///
+/// let app = App::new()
+///     .middleware(ApiEnvelope::new().group("/api", Envelope::new()))
+///     .middleware(ConditionalGet::new().group("/api").skip("/api/ticker"))
+///     .get("/api/users", |_req: torch_web::Request| async {
+///         torch_web::Response::ok().json(&["ada", "grace"]).unwrap()
+///     });
+/// ```
+///
+/// The body still has to be built, but polling clients skip the download
+/// and parsing. Endpoints that change on nearly every request can opt out
+/// with [`skip`](Self::skip), and single responses with
+/// `Cache-Control: no-store`. Responses that already have an `ETag` keep
+/// it and are still checked against `If-None-Match`.
+///
+/// Register it after [`ApiEnvelope`](super::ApiEnvelope), so it sees the
+/// handler's body rather than the envelope with its per-request metadata.
+#[derive(Debug, Clone, Default)]
+pub struct ConditionalGet {
+    groups: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl ConditionalGet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tag responses to paths under `prefix`
+    pub fn group(mut self, prefix: &str) -> Self {
+        self.groups.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Leave responses to paths under `prefix` alone
+    pub fn skip(mut self, prefix: &str) -> Self {
+        self.skipped.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    fn applies(&self, path: &str) -> bool {
+        let under = |prefix: &String| path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        self.groups.iter().any(under) && !self.skipped.iter().any(under)
+    }
+}
+
+impl Middleware for ConditionalGet {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) || !self.applies(req.path()) {
+            return next(req);
+        }
+        let if_none_match = req.header("if-none-match").map(str::to_string);
+        Box::pin(async move {
+            let response = next(req).await;
+            let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+            let is_json = header("content-type").is_some_and(|value| value.trim_start().to_ascii_lowercase().starts_with("application/json"));
+            let no_store = header("cache-control").is_some_and(|value| value.to_ascii_lowercase().contains("no-store"));
+            if response.status_code() != StatusCode::OK || !is_json || no_store || response.is_streaming() {
+                return response;
+            }
+
+            let etag = match header("etag") {
+                Some(etag) => etag,
+                None => format!("W/\"{:016x}\"", fnv1a(response.body_data())),
+            };
+            if if_none_match.is_some_and(|header| none_match(&header, &etag)) {
+                let mut not_modified = Response::with_status(StatusCode::NOT_MODIFIED).header("ETag", &etag);
+                for name in ["cache-control", "vary", "expires", "content-location"] {
+                    if let Some(value) = response.headers().get(name) {
+                        not_modified.headers_mut().insert(name, value.clone());
+                    }
+                }
+                *not_modified.extensions_mut() = response.extensions().clone();
+                return not_modified;
+            }
+            response.header("ETag", &etag)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{ApiEnvelope, Envelope};
+    use crate::App;
+
+    #[tokio::test]
+    async fn test_conditional_get_answers_304_for_unchanged_json() {
+        let app = App::new()
+            .middleware(ApiEnvelope::new().group("/api", Envelope::new()))
+            .middleware(ConditionalGet::new().group("/api").skip("/api/ticker"))
+            .get("/api/users", |_req: Request| async { Response::ok().json(&["ada"]).unwrap().header("Cache-Control", "private") })
+            .get("/api/ticker", |_req: Request| async { Response::ok().json(&[42]).unwrap() })
+            .get("/api/live", |_req: Request| async { Response::ok().json(&[1]).unwrap().header("Cache-Control", "no-store") });
+        let get = |path: &str, etag: Option<&str>| {
+            let req = Request::mock(Method::GET, path);
+            app.handle_request(match etag {
+                Some(etag) => req.with_header("if-none-match", etag),
+                None => req,
+            })
+        };
+
+        let first = get("/api/users", None).await;
+        let etag = first.headers().get("etag").unwrap().to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""), "{}", etag);
+        assert!(String::from_utf8_lossy(first.body_data()).contains("\"data\":[\"ada\"]"));
+
+        // Same ETag despite the envelope's per-request metadata, matched
+        // weakly so the strong form of the tag matches too
+        let cached = get("/api/users", Some(&format!("\"stale\", {}", etag))).await;
+        assert_eq!(cached.status_code(), StatusCode::NOT_MODIFIED);
+        assert!(cached.body_data().is_empty());
+        assert_eq!(cached.headers().get("etag").unwrap(), etag.as_str());
+        assert_eq!(cached.headers().get("cache-control").unwrap(), "private");
+        assert_eq!(get("/api/users", Some(etag.trim_start_matches("W/"))).await.status_code(), StatusCode::NOT_MODIFIED);
+        assert_eq!(get("/api/users", Some("\"stale\"")).await.status_code(), StatusCode::OK);
+
+        assert!(!get("/api/ticker", None).await.headers().contains_key("etag"));
+        assert!(!get("/api/live", None).await.headers().contains_key("etag"));
+    }
+}
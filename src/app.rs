@@ -13,6 +13,17 @@ use crate::{
     extractors::state::{StateMap, RequestStateExt},
 };
 
+/// Environment variable that overrides the address passed to [`App::listen`]
+pub const SERVE_ADDR_ENV: &str = "TORCH_SERVE_ADDR";
+
+/// Set by `torch serve --hot` on the app it runs. Enables development-only
+/// output such as the Ember error overlay.
+pub const DEV_SERVER_ENV: &str = "TORCH_DEV_SERVER";
+
+/// When set, [`App::listen`] writes the route table as JSON to this path and
+/// exits instead of serving; `torch route cache` relies on it
+pub const ROUTE_DUMP_ENV: &str = "TORCH_ROUTE_DUMP";
+
 /// The main application builder for Torch web framework.
 ///
 /// `App` is the central component that ties together routing, middleware, state management,
@@ -84,14 +95,36 @@ use crate::{
 pub struct App {
     router: Router,
     middleware: MiddlewareStack,
+    middleware_names: Vec<String>,
     error_pages: ErrorPages,
     state: StateMap,
+    /// Named routes, collected when the first request comes in
+    urls: std::sync::OnceLock<crate::router::Urls>,
+    /// The routing table for [`App::route_list`], collected like `urls`
+    #[cfg(feature = "json")]
+    route_table: Option<std::sync::OnceLock<crate::dev::routes::RouteTable>>,
+    #[cfg(feature = "websocket")]
+    websockets: Vec<WebSocketRoute>,
     #[cfg(feature = "api")]
     pub(crate) api_docs: Option<crate::api::ApiDocBuilder>,
     #[cfg(not(feature = "api"))]
     _phantom: std::marker::PhantomData<()>,
 }
 
+/// A route registered with [`App::websocket`]
+#[cfg(feature = "websocket")]
+struct WebSocketRoute {
+    pattern: crate::router::RoutePattern,
+    handler: crate::websocket::WebSocketHandlerFn,
+    /// Set by [`App::on_upgrade`]; shared with the route's upgrade handler
+    authorizer: std::sync::Arc<std::sync::OnceLock<crate::websocket::UpgradeAuthorizer>>,
+    /// Set by [`App::subprotocols`], in order of preference
+    subprotocols: std::sync::Arc<std::sync::OnceLock<Vec<String>>>,
+    /// Routes registered once this one was, so [`App::on_upgrade`] can tell
+    /// whether it directly follows it
+    registered: usize,
+}
+
 impl App {
     /// Creates a new application instance with default configuration.
     ///
@@ -112,8 +145,14 @@ impl App {
         Self {
             router: Router::new(),
             middleware: MiddlewareStack::new(),
+            middleware_names: Vec::new(),
             error_pages: ErrorPages::new(),
             state: StateMap::new(),
+            urls: std::sync::OnceLock::new(),
+            #[cfg(feature = "json")]
+            route_table: None,
+            #[cfg(feature = "websocket")]
+            websockets: Vec::new(),
             #[cfg(feature = "api")]
             api_docs: None,
             #[cfg(not(feature = "api"))]
@@ -162,6 +201,44 @@ impl App {
         self
     }
 
+    /// Decodes request bodies of `content_type` with `decoder` for the
+    /// [`Decoded`](crate::extractors::Decoded) extractor.
+    ///
+    /// ```rust,no_run
+    /// use torch_web::App;
+    ///
+    /// let app = App::new().body_decoder("application/x-ndjson", |body: &[u8]| {
+    ///     body.split(|byte| *byte == b'\n')
+    ///         .filter(|line| !line.is_empty())
+    ///         .map(|line| serde_json::from_slice::<serde_json::Value>(line).map_err(|e| e.to_string()))
+    ///         .collect()
+    /// });
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn body_decoder<D>(mut self, content_type: &str, decoder: D) -> Self
+    where
+        D: crate::extractors::BodyDecoder,
+    {
+        let mut decoders = self.state.get::<crate::extractors::BodyDecoders>().cloned().unwrap_or_default();
+        decoders.register(content_type, decoder);
+        self.state.insert(decoders);
+        self
+    }
+
+    /// Registers the policy deciding what users may do with models of type
+    /// `M`, checked by the [`Can`](crate::policy::Can) extractor and
+    /// [`authorize`](crate::policy::authorize).
+    ///
+    /// See the [`policy`](crate::policy) module for an example.
+    pub fn policy<M, P>(mut self, policy: P) -> Self
+    where
+        M: 'static,
+        P: crate::policy::Policy<M>,
+    {
+        self.state.insert(crate::policy::Registered::<M>::new(policy));
+        self
+    }
+
     /// Adds middleware to the application's middleware stack.
     ///
     /// Middleware is executed in the order it's added, wrapping the final route handler.
@@ -215,6 +292,7 @@ impl App {
         M: Middleware,
     {
         self.middleware.add(middleware);
+        self.middleware_names.push(std::any::type_name::<M>().to_string());
         self
     }
 
@@ -250,15 +328,81 @@ impl App {
     ///         Response::created().body("User created")
     ///     });
     /// ```
+    #[track_caller]
     pub fn route<H, T>(mut self, method: Method, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
     {
         let handler_fn = crate::handler::into_handler_fn(handler);
-        self.router.route(method, path, handler_fn);
+        let handler_name = std::any::type_name::<H>().to_string();
+        let location = std::panic::Location::caller();
+        self.router.add_route(method, path, handler_fn, handler_name, None, location);
+        self
+    }
+
+    /// Names the route registered just before this call.
+    ///
+    /// Route names are shown by `torch route list` and can be filtered on
+    /// with `--name`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::{App, Request, Response};
+    ///
+    /// let app = App::new()
+    ///     .get("/users", |_req: Request| async { Response::ok() })
+    ///     .name("users.index");
+    ///
+    /// assert_eq!(app.routes()[0].name.as_deref(), Some("users.index"));
+    /// ```
+    pub fn name(mut self, name: &str) -> Self {
+        self.router.name(name);
         self
     }
 
+    /// URL for the route called `name`; see [`Router::url`]
+    pub fn url(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.router.url(name, params)
+    }
+
+    /// Signed URL for the route called `name`, valid for `expires_in`
+    ///
+    /// Handlers can build the same links with the [`Urls`](crate::router::Urls)
+    /// extractor.
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use torch_web::{App, Request, Response};
+    ///
+    /// let app = App::new()
+    ///     .get("/email/verify/:id", |_req: Request| async { Response::ok() })
+    ///     .name("verification.verify");
+    ///
+    /// let link = app.url_signed("verification.verify", &[("id", "42")], Duration::from_secs(3600)).unwrap();
+    /// ```
+    #[cfg(feature = "security")]
+    pub fn url_signed(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        expires_in: std::time::Duration,
+    ) -> crate::security::SecurityResult<String> {
+        self.router.urls().url_signed(name, params, expires_in)
+    }
+
+    /// Lists every route with its name, handler and the middleware it runs through
+    pub fn routes(&self) -> Vec<crate::router::RouteInfo> {
+        self.router
+            .routes()
+            .into_iter()
+            .map(|mut route| {
+                route.middleware = self.middleware_names.clone();
+                route
+            })
+            .collect()
+    }
+
     /// Registers a GET route handler.
     ///
     /// GET requests are typically used for retrieving data and should be idempotent
@@ -286,6 +430,7 @@ impl App {
     ///         Response::ok().json(&serde_json::json!({"users": []}))
     ///     });
     /// ```
+    #[track_caller]
     pub fn get<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -324,6 +469,7 @@ impl App {
     ///         Response::ok().body("Login successful")
     ///     });
     /// ```
+    #[track_caller]
     pub fn post<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -359,6 +505,7 @@ impl App {
     ///         Response::ok().json(&user)
     ///     });
     /// ```
+    #[track_caller]
     pub fn put<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -390,6 +537,7 @@ impl App {
     ///         Response::ok().body(format!("Deleted post {}", id))
     ///     });
     /// ```
+    #[track_caller]
     pub fn delete<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -425,6 +573,7 @@ impl App {
     ///         Response::ok().json(&patch)
     ///     });
     /// ```
+    #[track_caller]
     pub fn patch<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -434,8 +583,9 @@ impl App {
 
     /// Registers an OPTIONS route handler.
     ///
-    /// OPTIONS requests are typically used for CORS preflight requests to check
-    /// what methods and headers are allowed for cross-origin requests.
+    /// Paths with routes already answer OPTIONS on their own: with the `Allow`
+    /// list of their methods, or as a CORS preflight when [`App::cors`] is
+    /// set. Register a handler only to answer differently.
     ///
     /// # Parameters
     ///
@@ -455,6 +605,7 @@ impl App {
     ///             .body("")
     ///     });
     /// ```
+    #[track_caller]
     pub fn options<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -462,6 +613,31 @@ impl App {
         self.route(Method::OPTIONS, path, handler)
     }
 
+    /// Allows cross-origin requests as `config` describes: preflight
+    /// `OPTIONS` requests are answered for every registered path, and
+    /// responses to allowed origins get the `Access-Control-*` headers.
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    /// use torch_web::security::CorsConfig;
+    ///
+    /// let app = App::new()
+    ///     .cors(CorsConfig {
+    ///         allowed_origins: vec!["https://app.example.com".to_string()],
+    ///         ..Default::default()
+    ///     })
+    ///     .delete("/api/orders/:id", |_req: torch_web::Request| async { "deleted" });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config` allows credentials from every origin (`*`), see
+    /// [`Cors::new`](crate::security::Cors::new).
+    #[cfg(feature = "security")]
+    pub fn cors(self, config: crate::security::CorsConfig) -> Self {
+        self.with_state(config.clone()).middleware(crate::security::Cors::new(config))
+    }
+
     /// Registers a HEAD route handler.
     ///
     /// HEAD requests are identical to GET requests except that the server must not
@@ -486,6 +662,7 @@ impl App {
     ///             .header("Content-Length", "42")
     ///     });
     /// ```
+    #[track_caller]
     pub fn head<H, T>(self, path: &str, handler: H) -> Self
     where
         H: Handler<T>,
@@ -563,21 +740,137 @@ impl App {
     /// // GET /api/users/:id -> "Get user"
     /// ```
     pub fn mount(mut self, prefix: &str, other: Router) -> Self {
-        // Merge routes from the other router with the prefix
-        let prefix = prefix.trim_end_matches('/');
-
-        // Get all routes from the other router and add them with prefix
-        for (method, path, handler) in other.get_all_routes() {
-            let prefixed_path = if prefix.is_empty() {
-                path
-            } else {
-                format!("{}{}", prefix, path)
-            };
-
-            self.router.route(method, &prefixed_path, handler);
+        self.router.merge(prefix, &other);
+        self
+    }
+
+    /// Serves the mailables registered with
+    /// [`Mail::preview`](crate::mail::Mail::preview) at
+    /// `/torch/mail-preview/:mailable`, with an index at `/torch/mail-preview`.
+    ///
+    /// The routes only answer in debug builds or under `torch serve`, so it
+    /// is safe to leave this call in production code.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    ///
+    /// let app = App::new().mail_previews();
+    /// ```
+    pub fn mail_previews(self) -> Self {
+        self.get("/torch/mail-preview", crate::mail::preview)
+            .get("/torch/mail-preview/:mailable", crate::mail::preview)
+    }
+
+    /// Serves the queue backlog from [`Queue::backlog`](crate::queue::Queue::backlog)
+    /// as JSON at `path`, for autoscalers such as KEDA's metrics API scaler
+    /// to size the worker pool by `pending` or `latency`.
+    ///
+    /// The route has no auth of its own, so keep it off the public internet
+    /// or put auth middleware in front of it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    ///
+    /// // {"pending":12,"latency":30,"queues":[{"queue":"default","pending":12,...}]}
+    /// let app = App::new().queue_backlog("/internal/queue");
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn queue_backlog(self, path: &str) -> Self {
+        self.get(path, crate::queue::serve_backlog)
+    }
+
+    /// Serves an admin API for `bans` at `path`: `GET` lists the bans,
+    /// `POST` adds one and `DELETE ?net=` lifts one; see
+    /// [`security::bans`](crate::security::bans).
+    ///
+    /// `authorize` is asked about every request, e.g. to check the signed-in
+    /// user is an admin; requests it refuses get `403 Forbidden`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    /// use torch_web::security::bans::{BanList, IpGuard};
+    ///
+    /// let bans = BanList::new();
+    /// let app = App::new()
+    ///     .middleware(IpGuard::new(bans.clone()))
+    ///     .ban_admin("/admin/bans", bans, |req| req.header("authorization") == Some("Bearer admin-token"));
+    /// ```
+    #[cfg(all(feature = "security", feature = "json"))]
+    pub fn ban_admin<F>(self, path: &str, bans: crate::security::bans::BanList, authorize: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        let authorize = std::sync::Arc::new(authorize);
+        let serve = move |req: Request| {
+            let bans = bans.clone();
+            let allowed = authorize(&req);
+            async move {
+                match allowed {
+                    true => bans.serve_admin(req).await,
+                    false => Response::forbidden().body("Not allowed to manage bans"),
+                }
+            }
+        };
+        let (add, lift) = (serve.clone(), serve.clone());
+        self.get(path, serve).post(path, add).delete(path, lift)
+    }
+
+    /// Records requests, queries, cache operations, jobs and errors, and
+    /// shows them on a dashboard at `/torch`; see
+    /// [`dev::inspector`](crate::dev::inspector).
+    ///
+    /// Nothing is recorded or served in release builds unless the app runs
+    /// under `torch serve`, so it is safe to leave this call in production
+    /// code.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    /// use torch_web::dev::inspector::Inspector;
+    ///
+    /// let app = App::new().inspector(Inspector::new());
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn inspector(self, inspector: crate::dev::inspector::Inspector) -> Self {
+        if !inspector.install() {
+            return self;
         }
+        let (list, clear) = (inspector.clone(), inspector.clone());
+        self.middleware(crate::dev::inspector::InspectorMiddleware)
+            .get("/torch", move |req: Request| inspector.clone().dashboard(req))
+            .get("/torch/entries", move |req: Request| list.clone().serve_entries(req))
+            .delete("/torch/entries", move |req: Request| clear.clone().serve_clear(req))
+    }
 
-        self
+    /// Serves the routing table at `/torch/routes`, and as JSON at
+    /// `/torch/routes.json` for `torch route list --server`; see
+    /// [`dev::routes`](crate::dev::routes).
+    ///
+    /// The routes only answer local clients in debug builds or under
+    /// `torch serve`, so it is safe to leave this call in production code.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    ///
+    /// let app = App::new().route_list();
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn route_list(mut self) -> Self {
+        if !crate::dev::inspector::dev_mode() {
+            return self;
+        }
+        self.route_table = Some(std::sync::OnceLock::new());
+        self.get("/torch/routes", crate::dev::routes::serve)
+            .get("/torch/routes.json", crate::dev::routes::serve)
     }
 
     /// Configures custom error pages for the application.
@@ -735,25 +1028,256 @@ impl App {
     ///     });
     /// ```
     #[cfg(feature = "websocket")]
-    pub fn websocket<F, Fut>(self, path: &str, handler: F) -> Self
+    pub fn websocket<F, Fut>(mut self, path: &str, handler: F) -> Self
+    where
+        F: Fn(crate::websocket::WebSocketConnection) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'static,
+    {
+        let handler: crate::websocket::WebSocketHandlerFn =
+            std::sync::Arc::new(move |connection| Box::pin(handler(connection)));
+        let authorizer = std::sync::Arc::new(std::sync::OnceLock::new());
+        let subprotocols = std::sync::Arc::new(std::sync::OnceLock::new());
+        self.websockets.push(WebSocketRoute {
+            pattern: crate::router::RoutePattern::parse(path),
+            handler,
+            authorizer: authorizer.clone(),
+            subprotocols: subprotocols.clone(),
+            registered: 0,
+        });
+
+        // Connections register with the manager in app state so handlers and
+        // HTTP routes share rooms; provide one if the app didn't
+        if !self.state.contains::<crate::websocket::WebSocketManager>() {
+            self.state.insert(crate::websocket::WebSocketManager::new());
+        }
+
+        let mut app = self.get::<_, (Request,)>(path, move |req: Request| {
+            let authorize = authorizer.get().cloned();
+            let subprotocols = subprotocols.clone();
+            async move {
+                let offered = subprotocols.get().map(Vec::as_slice).unwrap_or_default();
+                crate::websocket::authorized_upgrade(req, authorize, offered).await
+            }
+        });
+        let registered = app.router.registered();
+        if let Some(route) = app.websockets.last_mut() {
+            route.registered = registered;
+        }
+        app
+    }
+
+    /// Registers a WebSocket route whose upgrades are authorized by
+    /// `authorize`; the same as [`websocket`](App::websocket) followed by
+    /// [`on_upgrade`](App::on_upgrade).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::{App, Request, Response};
+    ///
+    /// let app = App::new().websocket_with_auth(
+    ///     "/admin/live",
+    ///     |req: Request| async move {
+    ///         match req.header("authorization") {
+    ///             Some("Bearer secret") => Ok("admin".to_string()),
+    ///             _ => Err(Response::unauthorized()),
+    ///         }
+    ///     },
+    ///     |mut connection| async move {
+    ///         connection.send_text("Welcome").await?;
+    ///         Ok(())
+    ///     },
+    /// );
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn websocket_with_auth<A, AFut, T, F, Fut>(self, path: &str, authorize: A, handler: F) -> Self
     where
+        A: Fn(Request) -> AFut + Send + Sync + 'static,
+        AFut: std::future::Future<Output = Result<T, Response>> + Send + 'static,
+        T: Send + Sync + 'static,
         F: Fn(crate::websocket::WebSocketConnection) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'static,
     {
-        let handler = std::sync::Arc::new(handler);
+        self.websocket(path, handler).on_upgrade(authorize)
+    }
+
+    /// Sets the subprotocols the WebSocket route registered last accepts,
+    /// in order of preference.
+    ///
+    /// The handshake accepts the first of these the client lists in
+    /// `Sec-WebSocket-Protocol`, and the handler reads it with
+    /// [`WebSocketConnection::protocol`](crate::websocket::WebSocketConnection::protocol).
+    /// Clients that offer none of them still connect, without a subprotocol.
+    /// `"msgpack"`, `"cbor"` and `"json"` also select the format used by
+    /// [`send_value`](crate::websocket::WebSocketConnection::send_value) and
+    /// [`receive_value`](crate::websocket::WebSocketConnection::receive_value).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::App;
+    ///
+    /// let app = App::new()
+    ///     .websocket("/ticker", |mut connection| async move {
+    ///         while let Some(symbol) = connection.receive_value::<String>().await? {
+    ///             connection.send_value(&(symbol, 101.5)).await?;
+    ///         }
+    ///         Ok(())
+    ///     })
+    ///     .subprotocols(&["msgpack", "json"]);
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn subprotocols(self, protocols: &[&str]) -> Self {
+        let Some(route) = self.websockets.last() else {
+            eprintln!("⚠️  subprotocols() has no effect without a websocket() route before it");
+            return self;
+        };
+        let protocols = protocols.iter().map(|protocol| protocol.to_string()).collect();
+        if route.subprotocols.set(protocols).is_err() {
+            eprintln!("⚠️  subprotocols() was already set for this websocket route; keeping the first list");
+        }
+        self
+    }
+
+    /// Authorizes upgrades to the WebSocket route registered last.
+    ///
+    /// Must directly follow the [`websocket`](App::websocket) call it
+    /// protects, with no other route in between, and can only be set once
+    /// per route; anything else panics rather than leaving a route
+    /// unprotected. [`websocket_with_auth`](App::websocket_with_auth) does
+    /// both in one call.
+    ///
+    /// `authorize` sees the upgrade request (cookies, `Authorization`, query
+    /// tokens) before the handshake completes. Returning `Ok(user)` accepts
+    /// the connection and attaches `user` to it, for the handler to read
+    /// with [`WebSocketConnection::user`](crate::websocket::WebSocketConnection::user).
+    /// Returning `Err(response)` refuses the upgrade with that response,
+    /// typically a 401 or 403.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::{App, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// let app = App::new()
+    ///     .websocket("/ws", |mut connection| async move {
+    ///         let name = connection.user::<User>().map(|user| user.name.clone()).unwrap_or_default();
+    ///         connection.send_text(&format!("Hello, {}", name)).await?;
+    ///         Ok(())
+    ///     })
+    ///     .on_upgrade(|req: Request| async move {
+    ///         match req.header("authorization") {
+    ///             Some("Bearer secret") => Ok(User { name: "Ada".to_string() }),
+    ///             Some(_) => Err(Response::forbidden()),
+    ///             None => Err(Response::unauthorized()),
+    ///         }
+    ///     });
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn on_upgrade<F, Fut, T>(self, authorize: F) -> Self
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<T, Response>> + Send + 'static,
+        T: Send + Sync + 'static,
+    {
+        let route = match self.websockets.last() {
+            Some(route) if route.registered == self.router.registered() => route,
+            _ => panic!("on_upgrade() must directly follow the websocket() route it protects; use websocket_with_auth() instead"),
+        };
+
+        let authorize = std::sync::Arc::new(authorize);
+        let authorizer: crate::websocket::UpgradeAuthorizer = std::sync::Arc::new(move |req| {
+            let authorize = authorize.clone();
+            Box::pin(async move {
+                let user = authorize(req).await?;
+                Ok(crate::websocket::UpgradeIdentity(std::sync::Arc::new(user)))
+            })
+        });
+        if route.authorizer.set(authorizer).is_err() {
+            panic!("on_upgrade() was already set for this websocket route");
+        }
+        self
+    }
+
+    /// Serves Server-Sent Events at `path` from the app's WebSocket manager.
+    ///
+    /// Clients pick channels with `?channels=orders,stats` (or repeated
+    /// `?channel=`) and receive broadcasts to those rooms as events named
+    /// after the channel, plus broadcasts to everyone as `message` events.
+    /// `allow` is asked about every requested channel, with the request so
+    /// it can check the signed-in user; asking for a channel it refuses
+    /// answers `403 Forbidden`. For per-user channels picked by the server,
+    /// write a handler that calls
+    /// [`WebSocketManager::sse`](crate::websocket::WebSocketManager::sse)
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::{App, Request, extractors::State, websocket::WebSocketManager};
+    ///
+    /// let app = App::new()
+    ///     .sse("/events", |_req: &Request, channel: &str| matches!(channel, "deploys" | "status"))
+    ///     .post("/deploys", |State(manager): State<WebSocketManager>| async move {
+    ///         let _ = manager.broadcast_to_room("deploys", "started").await;
+    ///         "ok"
+    ///     });
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn sse<F>(mut self, path: &str, allow: F) -> Self
+    where
+        F: Fn(&Request, &str) -> bool + Send + Sync + 'static,
+    {
+        if !self.state.contains::<crate::websocket::WebSocketManager>() {
+            self.state.insert(crate::websocket::WebSocketManager::new());
+        }
+        let manager = self.websocket_manager();
+        let allow = std::sync::Arc::new(allow);
+
         self.get::<_, (Request,)>(path, move |req: Request| {
-            let _handler = handler.clone();
+            let manager = manager.clone();
+            let channels = crate::websocket::sse::requested_channels(&req);
+            let refused = channels.iter().find(|channel| !allow(&req, channel.as_str())).cloned();
             async move {
-                // Check if this is a WebSocket upgrade request
-                if crate::websocket::is_websocket_upgrade_request(&req) {
-                    crate::websocket::websocket_upgrade(req).await
-                } else {
-                    Response::bad_request().body("WebSocket upgrade required")
+                match refused {
+                    Some(channel) => Response::forbidden().body(format!("Not allowed to follow {}", channel)),
+                    None => manager.sse(&channels).await,
                 }
             }
         })
     }
 
+    /// The WebSocket manager connections on this app register with
+    #[cfg(feature = "websocket")]
+    pub(crate) fn websocket_manager(&self) -> crate::websocket::WebSocketManager {
+        self.state
+            .get::<crate::websocket::WebSocketManager>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Hand an upgraded stream to the WebSocket handler registered for `path`,
+    /// with the extensions of the 101 response that accepted it.
+    ///
+    /// Returns the new connection's id, or `None` if no handler matches.
+    #[cfg(feature = "websocket")]
+    pub(crate) async fn accept_websocket<S>(&self, path: &str, io: S, upgrade: &http::Extensions) -> Option<String>
+    where
+        S: crate::websocket::WebSocketIo,
+    {
+        let handler = self.websockets
+            .iter()
+            .find(|route| route.pattern.matches(path).is_some())
+            .map(|route| route.handler.clone())?;
+
+        Some(crate::websocket::serve_connection(io, handler, self.websocket_manager(), upgrade).await)
+    }
+
     /// No-op WebSocket method when the websocket feature is disabled.
     ///
     /// This method exists to provide a consistent API regardless of whether
@@ -769,6 +1293,43 @@ impl App {
         self
     }
 
+    /// No-op upgrade authorization when the websocket feature is disabled.
+    #[cfg(not(feature = "websocket"))]
+    pub fn on_upgrade<F, Fut, T>(self, _authorize: F) -> Self
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<T, Response>> + Send + 'static,
+    {
+        self
+    }
+
+    /// No-op authorized WebSocket route when the websocket feature is disabled.
+    #[cfg(not(feature = "websocket"))]
+    pub fn websocket_with_auth<A, AFut, T, F, Fut>(self, _path: &str, _authorize: A, _handler: F) -> Self
+    where
+        A: Fn(Request) -> AFut + Send + Sync + 'static,
+        AFut: std::future::Future<Output = Result<T, Response>> + Send + 'static,
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'static,
+    {
+        self
+    }
+
+    /// No-op event stream route when the websocket feature is disabled.
+    #[cfg(not(feature = "websocket"))]
+    pub fn sse<F>(self, _path: &str, _allow: F) -> Self
+    where
+        F: Fn(&Request, &str) -> bool + Send + Sync + 'static,
+    {
+        self
+    }
+
+    /// No-op subprotocol negotiation when the websocket feature is disabled.
+    #[cfg(not(feature = "websocket"))]
+    pub fn subprotocols(self, _protocols: &[&str]) -> Self {
+        self
+    }
+
     /// Starts the HTTP server and begins listening for incoming requests.
     ///
     /// This method consumes the `App` and starts the server on the specified address.
@@ -778,6 +1339,14 @@ impl App {
     ///
     /// * `addr` - The address to bind to (e.g., "127.0.0.1:3000" or "0.0.0.0:8080")
     ///
+    /// When the [`SERVE_ADDR_ENV`] environment variable is set it takes precedence
+    /// over `addr`; `torch serve --hot` uses this to run the app behind its
+    /// live-reload proxy. If [`ROUTE_DUMP_ENV`] is set the route table is
+    /// written there instead and the server never starts.
+    ///
+    /// Routes that could never run and route names used twice are an error,
+    /// see [`check_routes`](crate::router::check_routes).
+    ///
     /// # Returns
     ///
     /// Returns `Ok(())` if the server shuts down gracefully, or an error if
@@ -818,18 +1387,52 @@ impl App {
     /// }
     /// ```
     pub async fn listen(self, addr: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let addr: SocketAddr = addr.parse()?;
+        if let Ok(path) = std::env::var(ROUTE_DUMP_ENV) {
+            return self.dump_routes(&path);
+        }
+        let conflicts = crate::router::check_routes(&self.routes());
+        if !conflicts.is_empty() {
+            let report: Vec<String> = conflicts.iter().map(|conflict| format!("  - {}", conflict)).collect();
+            return Err(format!("Conflicting route definitions:\n{}", report.join("\n")).into());
+        }
+
+        let addr: SocketAddr = match std::env::var(SERVE_ADDR_ENV) {
+            Ok(overridden) => overridden.parse()?,
+            Err(_) => addr.parse()?,
+        };
         println!("🔥 Torch server starting on http://{}", addr);
         serve(addr, self).await
     }
 
+    #[cfg(feature = "json")]
+    fn dump_routes(&self, path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.routes())?)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn dump_routes(&self, _path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err("dumping routes requires the `json` feature".into())
+    }
+
     /// Process incoming requests through middleware and routing
-    pub(crate) async fn handle_request(&self, mut req: Request) -> Response {
+    ///
+    /// This is what the server calls for every connection; tests can call it
+    /// directly to exercise routes without binding a port.
+    pub async fn handle_request(&self, mut req: Request) -> Response {
         // Inject application state into the request
         req.set_state_map(self.state.clone());
+        req.insert_extension(self.urls.get_or_init(|| self.router.urls()).clone());
+        #[cfg(feature = "json")]
+        if let Some(table) = &self.route_table {
+            let routes = || crate::dev::routes::RouteTable(std::sync::Arc::new(self.routes()));
+            req.insert_extension(table.get_or_init(routes).clone());
+        }
 
         let router = self.router.clone();
         let error_pages = self.error_pages.clone();
+        // Kept so error pages can negotiate HTML or JSON after the request is consumed
+        let accept = req.header("accept").map(str::to_string);
 
         let response = self.middleware
             .execute(req, move |req| {
@@ -841,9 +1444,18 @@ impl App {
         // Check if this is an error response that should be rendered with error pages
         let status_code = response.status_code().as_u16();
         if status_code >= 400 && self.should_render_error_page(&response) {
-            // Create a simple request for error page rendering
-            let dummy_req = Request::new();
-            error_pages.render_error(status_code, None, &dummy_req)
+            let mut page_req = Request::new();
+            if let Some(accept) = accept.as_deref().and_then(|value| http::HeaderValue::from_str(value).ok()) {
+                page_req.headers_mut().insert(http::header::ACCEPT, accept);
+            }
+            let retry_after = response.headers().get(http::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok());
+            let mut page = error_pages.render_error_with_retry_after(status_code, None, retry_after, &page_req);
+            // A 401 has to keep its challenge
+            if let Some(challenge) = response.headers().get(http::header::WWW_AUTHENTICATE) {
+                page.headers_mut().insert(http::header::WWW_AUTHENTICATE, challenge.clone());
+            }
+            page
         } else {
             response
         }
@@ -979,12 +1591,55 @@ impl App {
             .middleware(crate::production::health_check())
     }
 
-    /// Creates an app with essential security middleware enabled.
+    /// Creates an app like [`App::with_defaults`], with the timeout and
+    /// body size limit taken from `config`, and HTML responses minified
+    /// when [`minify_assets`](crate::production::ProductionConfig::minify_assets)
+    /// is set.
     ///
-    /// This includes:
-    /// - Security headers (HSTS, CSP, X-Frame-Options, etc.)
-    /// - Request ID generation for tracking
-    /// - Input validation to prevent common attacks
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use torch_web::{App, config::TorchConfig, production::ProductionConfig};
+    ///
+    /// let config = TorchConfig::from_env();
+    /// let app = App::with_production(&ProductionConfig::from_torch_config(&config));
+    /// ```
+    pub fn with_production(config: &crate::production::ProductionConfig) -> Self {
+        let app = Self::new()
+            .middleware(crate::middleware::logger())
+            .middleware(crate::production::MetricsCollector::new())
+            .middleware(crate::production::PerformanceMonitor)
+            .middleware(crate::middleware::cors())
+            .middleware(crate::production::RequestTimeout::new(config.request_timeout))
+            .middleware(crate::production::RequestSizeLimit::new(config.max_body_size))
+            .middleware(crate::production::health_check());
+        if config.minify_assets {
+            app.middleware(crate::production::MinifyHtml::from_config(config))
+        } else {
+            app
+        }
+    }
+
+    /// Creates an app that validates request input.
+    ///
+    /// With the `security` feature this installs [`InputValidator::new()`]
+    /// and nothing else; without it the app is the same as [`App::new`].
+    /// The validator's default [`InputRules`] reject with `400 Bad Request`
+    /// any request whose:
+    ///
+    /// - path contains `..` segments, plain or percent-encoded, or a null byte
+    /// - query parameter values contain `../`, e.g. `?file=../secrets`
+    /// - path or query string has percent-encoding that is truncated, not
+    ///   hex, or decodes to invalid UTF-8, e.g. `?q=%C3`
+    /// - path, query string or headers are longer than the default limits
+    ///
+    /// Routes that take such input on purpose need their own rules; build
+    /// the app with [`App::new`] and add a configured [`InputValidator`].
+    /// Security headers are not added.
+    ///
+    /// [`InputValidator::new()`]: crate::security::InputValidator::new
+    /// [`InputValidator`]: crate::security::InputValidator
+    /// [`InputRules`]: crate::security::InputRules
     ///
     /// # Examples
     ///
@@ -992,16 +1647,15 @@ impl App {
     /// use torch_web::{App, Request, Response};
     ///
     /// let app = App::with_security()
-    ///     .get("/secure-endpoint", |_req: Request| async {
-    ///         Response::ok().body("This endpoint has security headers")
+    ///     .get("/files", |_req: Request| async {
+    ///         Response::ok().body("`/files?name=../etc/passwd` never gets here")
     ///     });
     /// ```
     pub fn with_security() -> Self {
-        // TODO: Implement proper security middleware integration
-        Self::new()
-            // .middleware(crate::security::SecurityHeaders::new())
-            // .middleware(crate::security::RequestId)
-            // .middleware(crate::security::InputValidator)
+        let app = Self::new();
+        #[cfg(feature = "security")]
+        let app = app.middleware(crate::security::InputValidator::new());
+        app
     }
 
     /// Creates an app with monitoring and metrics collection enabled.
@@ -1049,7 +1703,7 @@ mod tests {
             });
 
         // Test GET route
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -1058,9 +1712,7 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = app.handle_request(req).await;
         assert_eq!(response.body_data(), b"Hello, World!");
@@ -1080,7 +1732,7 @@ mod tests {
                 Response::ok().body("Hello")
             });
 
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -1089,14 +1741,28 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = app.handle_request(req).await;
         assert_eq!(response.headers().get("X-Test").unwrap(), "middleware");
     }
 
+    #[cfg(feature = "security")]
+    #[tokio::test]
+    async fn test_with_security_rejects_what_its_docs_list() {
+        let app = App::with_security().get("/files", |_req: Request| async { Response::ok().body("file") });
+        let status = |uri: &str| {
+            let app = &app;
+            let uri = uri.to_string();
+            async move { app.handle_request(Request::mock(http::Method::GET, &uri)).await.status_code() }
+        };
+
+        assert_eq!(status("/files?name=report.pdf").await, http::StatusCode::OK);
+        for rejected in ["/files?name=../etc/passwd", "/files?q=%C3", "/files/%2e%2e/secret", "/files?q=a%00b"] {
+            assert_eq!(status(rejected).await, http::StatusCode::BAD_REQUEST, "{}", rejected);
+        }
+    }
+
     #[test]
     fn test_app_builder_pattern() {
         let _app = App::new()
@@ -1113,4 +1779,31 @@ mod tests {
         let _app3 = App::with_security();
         let _app4 = App::with_defaults();
     }
+
+    #[test]
+    fn test_routes_listing() {
+        let mut api = Router::new();
+        api.get("/users/:id", crate::handler::into_handler_fn(|_req: Request| async { Response::ok() }));
+        api.name("users.show");
+
+        let app = App::new()
+            .middleware(crate::middleware::logger())
+            .get("/", |_req: Request| async { Response::ok() })
+            .name("home")
+            .post("/login", |_req: Request| async { Response::ok() })
+            .mount("/api", api);
+
+        let routes = app.routes();
+        let summary: Vec<(&str, &str, Option<&str>)> = routes
+            .iter()
+            .map(|route| (route.method.as_str(), route.path.as_str(), route.name.as_deref()))
+            .collect();
+        assert_eq!(summary, vec![
+            ("GET", "/", Some("home")),
+            ("POST", "/login", None),
+            ("GET", "/api/users/:id", Some("users.show")),
+        ]);
+        assert!(routes[0].handler.contains("test_routes_listing"));
+        assert_eq!(routes[2].middleware.len(), 1);
+    }
 }
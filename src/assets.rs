@@ -0,0 +1,360 @@
+//! # Assets
+//!
+//! Cache-busted URLs for frontend builds, and a handler that serves them.
+//!
+//! Bundlers write files with a content hash in their name (`app-4ed993c7.css`)
+//! and list them in a manifest. [`asset`] and [`vite`] look names up in
+//! that manifest, so templates refer to `resources/css/app.css` and always
+//! get the current build, in Ember as `@asset('resources/css/app.css')` and
+//! `@vite('resources/js/app.js')`.
+//!
+//! Both the Vite manifest (`public/build/.vite/manifest.json` or
+//! `public/build/manifest.json`) and the flat `{"app.css": "app.4ed9.css"}`
+//! manifest of webpack are understood. Set `build.manifest: true` and
+//! `build.outDir: "public/build"` in `vite.config.js`.
+//!
+//! ```rust,no_run
+//! use torch_web::{App, assets};
+//!
+//! let app = App::new()
+//!     // Hashed build output is cached for a year, anything else is revalidated
+//!     .get("/build/*", assets::serve)
+//!     .get("/favicon.ico", assets::serve);
+//!
+//! let stylesheet = assets::asset("resources/css/app.css"); // "/build/assets/app-4ed993c7.css"
+//! ```
+//!
+//! While `vite` runs its dev server it writes its URL to `public/hot` (the
+//! `laravel-vite-plugin` does this, or write it yourself), and [`vite`]
+//! points at the dev server instead, with hot module replacement.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::{CacheControl, Method, Request, Response, StatusCode};
+
+/// Where the built assets live
+#[derive(Debug, Clone)]
+pub struct AssetConfig {
+    /// Directory served at the site root
+    pub public_dir: PathBuf,
+    /// Directory under `public_dir` the bundler writes to, also its URL path
+    pub build_dir: String,
+}
+
+impl Default for AssetConfig {
+    fn default() -> Self {
+        Self {
+            public_dir: PathBuf::from("public"),
+            build_dir: "build".to_string(),
+        }
+    }
+}
+
+impl AssetConfig {
+    fn build_path(&self) -> PathBuf {
+        self.public_dir.join(&self.build_dir)
+    }
+
+    fn build_url(&self) -> String {
+        format!("/{}/", self.build_dir.trim_matches('/'))
+    }
+}
+
+static CONFIG: RwLock<Option<AssetConfig>> = RwLock::new(None);
+
+/// The manifest last read, with the modification time of its file
+static MANIFEST: Mutex<Option<(PathBuf, SystemTime, Arc<Manifest>)>> = Mutex::new(None);
+
+/// Use other directories than `public/` and `public/build/`
+pub fn configure(config: AssetConfig) {
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = Some(config);
+    *MANIFEST.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn config() -> AssetConfig {
+    CONFIG.read().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_default()
+}
+
+/// One file of a build
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ManifestEntry {
+    /// The built file, relative to the build directory
+    pub file: String,
+    /// Stylesheets extracted from a script entry
+    pub css: Vec<String>,
+    /// Manifest keys of the chunks the entry imports
+    pub imports: Vec<String>,
+}
+
+/// A bundler manifest mapping source names to built files
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    entries: HashMap<String, ManifestEntry>,
+    base_url: String,
+}
+
+impl Manifest {
+    /// Parse a Vite or webpack manifest whose files are served under
+    /// `base_url`, e.g. `/build/`
+    pub fn parse(json: &str, base_url: &str) -> Result<Self, serde_json::Error> {
+        let raw: HashMap<String, serde_json::Value> = serde_json::from_str(json)?;
+        let strings = |value: &serde_json::Value| -> Vec<String> {
+            value
+                .as_array()
+                .map(|items| items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect())
+                .unwrap_or_default()
+        };
+
+        let entries = raw
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let entry = match &value {
+                    serde_json::Value::String(file) => ManifestEntry { file: file.clone(), ..Default::default() },
+                    serde_json::Value::Object(fields) => ManifestEntry {
+                        file: fields.get("file")?.as_str()?.to_string(),
+                        css: fields.get("css").map(strings).unwrap_or_default(),
+                        imports: fields.get("imports").map(strings).unwrap_or_default(),
+                    },
+                    _ => return None,
+                };
+                Some((name, entry))
+            })
+            .collect();
+
+        Ok(Self {
+            entries,
+            base_url: format!("{}/", base_url.trim_end_matches('/')),
+        })
+    }
+
+    /// The entry for `name`, matching either the whole manifest key or its
+    /// last path segments (`app.css` finds `resources/css/app.css`)
+    pub fn entry(&self, name: &str) -> Option<&ManifestEntry> {
+        let name = name.trim_start_matches('/');
+        self.entries.get(name).or_else(|| {
+            let suffix = format!("/{}", name);
+            let mut matches = self.entries.iter().filter(|(key, _)| key.ends_with(&suffix));
+            match (matches.next(), matches.next()) {
+                (Some((_, entry)), None) => Some(entry),
+                _ => None,
+            }
+        })
+    }
+
+    /// URL of the built file for `name`
+    pub fn url(&self, name: &str) -> Option<String> {
+        self.entry(name).map(|entry| self.file_url(&entry.file))
+    }
+
+    fn file_url(&self, file: &str) -> String {
+        if file.starts_with('/') || file.contains("://") {
+            file.to_string()
+        } else {
+            format!("{}{}", self.base_url, file)
+        }
+    }
+
+    /// `<script>` and `<link>` tags loading `entries` with their stylesheets
+    /// and imported chunks
+    pub fn tags(&self, entries: &[&str]) -> String {
+        let mut styles = Vec::new();
+        let mut preloads = Vec::new();
+        let mut scripts = Vec::new();
+        let mut missing = Vec::new();
+
+        for name in entries {
+            let Some(entry) = self.entry(name) else {
+                missing.push(format!("<!-- asset '{}' is not in the manifest -->", name));
+                continue;
+            };
+            if is_stylesheet(&entry.file) {
+                styles.push(self.file_url(&entry.file));
+                continue;
+            }
+            scripts.push(self.file_url(&entry.file));
+            styles.extend(entry.css.iter().map(|file| self.file_url(file)));
+
+            let mut pending: Vec<&String> = entry.imports.iter().collect();
+            let mut seen = Vec::new();
+            while let Some(key) = pending.pop() {
+                if seen.contains(&key) {
+                    continue;
+                }
+                seen.push(key);
+                if let Some(chunk) = self.entries.get(key) {
+                    preloads.push(self.file_url(&chunk.file));
+                    styles.extend(chunk.css.iter().map(|file| self.file_url(file)));
+                    pending.extend(chunk.imports.iter());
+                }
+            }
+        }
+
+        let mut html = Vec::new();
+        let mut emitted: Vec<String> = Vec::new();
+        let mut emit = |tag: String, url: &String| {
+            if !emitted.contains(url) {
+                emitted.push(url.clone());
+                html.push(tag);
+            }
+        };
+        for url in &styles {
+            emit(format!(r#"<link rel="stylesheet" href="{}">"#, url), url);
+        }
+        for url in &preloads {
+            emit(format!(r#"<link rel="modulepreload" href="{}">"#, url), url);
+        }
+        for url in &scripts {
+            emit(format!(r#"<script type="module" src="{}"></script>"#, url), url);
+        }
+        html.extend(missing);
+        html.join("\n")
+    }
+}
+
+fn is_stylesheet(file: &str) -> bool {
+    let file = file.split('?').next().unwrap_or(file);
+    [".css", ".scss", ".sass", ".less"].iter().any(|extension| file.ends_with(extension))
+}
+
+/// The current manifest, read again whenever the file changes
+pub fn manifest() -> Option<Arc<Manifest>> {
+    let config = config();
+    let build = config.build_path();
+    let path = [build.join(".vite/manifest.json"), build.join("manifest.json")]
+        .into_iter()
+        .find(|path| path.is_file())?;
+    let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()?;
+
+    let mut cached = MANIFEST.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_path, cached_at, manifest)) = cached.as_ref() {
+        if *cached_path == path && *cached_at == modified {
+            return Some(manifest.clone());
+        }
+    }
+
+    let contents = std::fs::read_to_string(&path).ok()?;
+    match Manifest::parse(&contents, &config.build_url()) {
+        Ok(manifest) => {
+            let manifest = Arc::new(manifest);
+            *cached = Some((path, modified, manifest.clone()));
+            Some(manifest)
+        }
+        Err(err) => {
+            eprintln!("⚠️  Invalid asset manifest {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// URL of the Vite dev server, from the `hot` file in the public directory
+fn dev_server() -> Option<String> {
+    let url = std::fs::read_to_string(config().public_dir.join("hot")).ok()?;
+    let url = url.trim().trim_end_matches('/');
+    (!url.is_empty()).then(|| url.to_string())
+}
+
+/// URL for an asset: the hashed file from the manifest, or the file in the
+/// public directory when the manifest doesn't list it
+pub fn asset(name: &str) -> String {
+    if let Some(server) = dev_server() {
+        return format!("{}/{}", server, name.trim_start_matches('/'));
+    }
+    manifest()
+        .and_then(|manifest| manifest.url(name))
+        .unwrap_or_else(|| format!("/{}", name.trim_start_matches('/')))
+}
+
+/// Tags loading the Vite entry points `entries`, from the dev server while
+/// it runs and from the build otherwise
+pub fn vite(entries: &[&str]) -> String {
+    if let Some(server) = dev_server() {
+        let mut html = vec![format!(r#"<script type="module" src="{}/@vite/client"></script>"#, server)];
+        for entry in entries {
+            let url = format!("{}/{}", server, entry.trim_start_matches('/'));
+            html.push(if is_stylesheet(entry) {
+                format!(r#"<link rel="stylesheet" href="{}">"#, url)
+            } else {
+                format!(r#"<script type="module" src="{}"></script>"#, url)
+            });
+        }
+        return html.join("\n");
+    }
+
+    match manifest() {
+        Some(manifest) => manifest.tags(entries),
+        None => format!("<!-- no asset manifest in {} -->", config().build_path().display()),
+    }
+}
+
+/// Serve a file from the public directory for the request path. Use it as
+/// the handler for `/build/*` and other static paths.
+pub async fn serve(req: Request) -> Response {
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return Response::with_status(StatusCode::METHOD_NOT_ALLOWED).header("Allow", "GET, HEAD");
+    }
+    let config = config();
+    let Some(file) = resolve(&config.public_dir, req.path()) else {
+        return Response::not_found().body("Not Found");
+    };
+    // Build output has its content hash in the name, so it never changes
+    let cache = if req.path().starts_with(&config.build_url()) && !file.ends_with("manifest.json") {
+        CacheControl::new().public().max_age(Duration::from_secs(31_536_000)).immutable()
+    } else {
+        CacheControl::new().public().no_cache()
+    };
+    let response = Response::file(&req, &file).await;
+    if response.status_code().is_client_error() {
+        return response;
+    }
+    response.cache_control(cache)
+}
+
+/// Map a request path to a file under `root`, refusing anything that
+/// would escape it
+fn resolve(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(request_path.trim_start_matches('/'));
+    if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    let file = root.join(relative);
+    file.is_file().then_some(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_urls_and_tags() {
+        let vite = Manifest::parse(
+            r#"{
+                "resources/js/app.js": {"file": "assets/app-4ed993c7.js", "isEntry": true, "css": ["assets/app-5b1a.css"], "imports": ["_vendor.js"]},
+                "_vendor.js": {"file": "assets/vendor-9c2e.js", "css": ["assets/vendor-77aa.css"]},
+                "resources/css/app.css": {"file": "assets/app-0d3f.css", "isEntry": true}
+            }"#,
+            "/build",
+        )
+        .unwrap();
+
+        assert_eq!(vite.url("resources/css/app.css").as_deref(), Some("/build/assets/app-0d3f.css"));
+        assert_eq!(vite.url("app.js").as_deref(), Some("/build/assets/app-4ed993c7.js"));
+        assert_eq!(vite.url("missing.js"), None);
+        assert_eq!(
+            vite.tags(&["resources/css/app.css", "resources/js/app.js"]),
+            "<link rel=\"stylesheet\" href=\"/build/assets/app-0d3f.css\">\n\
+             <link rel=\"stylesheet\" href=\"/build/assets/app-5b1a.css\">\n\
+             <link rel=\"stylesheet\" href=\"/build/assets/vendor-77aa.css\">\n\
+             <link rel=\"modulepreload\" href=\"/build/assets/vendor-9c2e.js\">\n\
+             <script type=\"module\" src=\"/build/assets/app-4ed993c7.js\"></script>"
+        );
+
+        let webpack = Manifest::parse(r#"{"app.css": "app.81c2.css", "logo.svg": "/static/logo.1f2e.svg"}"#, "/build/").unwrap();
+        assert_eq!(webpack.url("app.css").as_deref(), Some("/build/app.81c2.css"));
+        assert_eq!(webpack.url("logo.svg").as_deref(), Some("/static/logo.1f2e.svg"));
+
+        assert_eq!(resolve(Path::new("public"), "/../Cargo.toml"), None);
+    }
+}
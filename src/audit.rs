@@ -0,0 +1,546 @@
+//! # Audit Logging
+//!
+//! Record security-relevant events (logins, permission changes, changes to
+//! models that opt in) with who did it, from where, in which request and what
+//! changed.
+//!
+//! ## Sinks
+//!
+//! Events go to the sink set with [`Audit::set_sink`]; nothing is recorded
+//! until one is set.
+//!
+//! - [`MemorySink`] keeps events in process.
+//! - [`JsonFileSink`] appends one JSON object per line to a file.
+//! - [`WebhookSink`] POSTs each event as JSON to an HTTP endpoint.
+//! - `DatabaseSink` (with the `database` feature) stores events in the
+//!   `audit_logs` table. Models implementing `Auditable` record their
+//!   creation, updates and deletion there too.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use torch_web::audit::{self, Audit, AuditEvent, CaptureAuditContext, JsonFileSink};
+//! use torch_web::{App, Request, Response};
+//!
+//! Audit::set_sink(JsonFileSink::new("storage/logs/audit.log"));
+//!
+//! let app = App::new()
+//!     .middleware(CaptureAuditContext)
+//!     .post("/users/:id/role", |req: Request| async move {
+//!         let before = serde_json::json!({ "role": "member" });
+//!         let after = serde_json::json!({ "role": "admin" });
+//!
+//!         // Actor, IP address and request ID come from the request
+//!         Audit::set_actor("7");
+//!         Audit::record(
+//!             AuditEvent::new(audit::PERMISSION_CHANGED)
+//!                 .subject("User", req.param("id").unwrap_or_default())
+//!                 .changes(&before, &after),
+//!         )
+//!         .await
+//!         .ok();
+//!
+//!         Response::ok()
+//!     });
+//! ```
+//!
+//! ## Testing
+//!
+//! [`Audit::fake`] records events in memory instead of sending them to the
+//! sink. Events recorded on any thread or task end up in the fake, and only
+//! one fake is active at a time:
+//!
+//! ```rust,no_run
+//! use torch_web::audit::{self, Audit, AuditEvent};
+//!
+//! # async fn example() {
+//! let audit_log = Audit::fake();
+//!
+//! Audit::record(AuditEvent::new(audit::LOGIN).actor("7")).await.unwrap();
+//!
+//! audit_log.assert_recorded(audit::LOGIN);
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::clock::unix_now;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+#[cfg(feature = "database")]
+mod database;
+
+#[cfg(feature = "database")]
+pub use database::{Auditable, DatabaseSink, AUDIT_TABLE};
+
+/// A user logged in
+pub const LOGIN: &str = "auth.login";
+/// A login attempt was rejected
+pub const LOGIN_FAILED: &str = "auth.login_failed";
+pub const LOGOUT: &str = "auth.logout";
+/// An IP address or account was locked out after repeated failed logins
+pub const LOCKOUT: &str = "auth.lockout";
+/// A user's roles or permissions changed
+pub const PERMISSION_CHANGED: &str = "auth.permission_changed";
+pub const MODEL_CREATED: &str = "model.created";
+pub const MODEL_UPDATED: &str = "model.updated";
+pub const MODEL_DELETED: &str = "model.deleted";
+
+/// Errors raised while recording audit events
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditError {
+    /// The sink could not store the event
+    Sink(String),
+}
+
+impl std::fmt::Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditError::Sink(message) => write!(f, "Audit sink error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+/// Value of one attribute before and after a change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Something that happened, who did it and what changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// What happened, e.g. [`LOGIN`] or `"invoice.refunded"`
+    pub action: String,
+    /// Who did it, usually a user id
+    pub actor: Option<String>,
+    pub ip: Option<String>,
+    pub request_id: Option<String>,
+    /// What it happened to, as `Type#id`
+    pub subject: Option<String>,
+    /// Changed attributes, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changes: BTreeMap<String, Change>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, Value>,
+    /// Unix timestamp in seconds
+    pub occurred_at: u64,
+}
+
+impl AuditEvent {
+    pub fn new(action: &str) -> Self {
+        Self {
+            action: action.to_string(),
+            actor: None,
+            ip: None,
+            request_id: None,
+            subject: None,
+            changes: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+            occurred_at: unix_now(),
+        }
+    }
+
+    pub fn actor(mut self, actor: impl ToString) -> Self {
+        self.actor = Some(actor.to_string());
+        self
+    }
+
+    pub fn ip(mut self, ip: impl ToString) -> Self {
+        self.ip = Some(ip.to_string());
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl ToString) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// What the event happened to, recorded as `kind#id`
+    pub fn subject(mut self, kind: &str, id: impl std::fmt::Display) -> Self {
+        self.subject = Some(format!("{}#{}", kind, id));
+        self
+    }
+
+    /// Attach extra detail
+    pub fn with(mut self, key: &str, value: impl Serialize) -> Self {
+        self.metadata.insert(key.to_string(), serde_json::to_value(value).unwrap_or(Value::Null));
+        self
+    }
+
+    /// Record the top-level attributes that differ between `before` and `after`
+    pub fn changes(mut self, before: &impl Serialize, after: &impl Serialize) -> Self {
+        let before = serde_json::to_value(before).unwrap_or(Value::Null);
+        let after = serde_json::to_value(after).unwrap_or(Value::Null);
+        self.changes = diff(&before, &after);
+        self
+    }
+
+    /// Leave attributes such as password hashes out of the recorded changes
+    pub fn except(mut self, attributes: &[&str]) -> Self {
+        self.changes.retain(|name, _| !attributes.contains(&name.as_str()));
+        self
+    }
+}
+
+/// Changed top-level attributes of two JSON objects; attributes missing on
+/// one side count as `null`. Anything other than an object is compared as a
+/// whole under the empty name.
+pub fn diff(before: &Value, after: &Value) -> BTreeMap<String, Change> {
+    let (Value::Object(old), Value::Object(new)) = (before, after) else {
+        let mut changes = BTreeMap::new();
+        if before != after {
+            changes.insert(String::new(), Change { before: before.clone(), after: after.clone() });
+        }
+        return changes;
+    };
+
+    old.keys()
+        .chain(new.keys())
+        .filter_map(|name| {
+            let before = old.get(name).unwrap_or(&Value::Null);
+            let after = new.get(name).unwrap_or(&Value::Null);
+            (before != after).then(|| (name.clone(), Change { before: before.clone(), after: after.clone() }))
+        })
+        .collect()
+}
+
+/// Backend that stores audit events
+pub trait AuditSink: Send + Sync + 'static {
+    fn record(&self, event: AuditEvent) -> Pin<Box<dyn Future<Output = Result<(), AuditError>> + Send + '_>>;
+}
+
+/// Sink that keeps events in process
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    events: Arc<Mutex<Vec<AuditEvent>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events recorded so far, oldest first
+    pub fn events(&self) -> Vec<AuditEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl AuditSink for MemorySink {
+    fn record(&self, event: AuditEvent) -> Pin<Box<dyn Future<Output = Result<(), AuditError>> + Send + '_>> {
+        self.events.lock().unwrap().push(event);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Sink that appends events to a file as JSON lines
+#[derive(Debug, Clone)]
+pub struct JsonFileSink {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl JsonFileSink {
+    /// Append to `path`, creating it and its directory when missing
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Arc::default(),
+        }
+    }
+}
+
+impl AuditSink for JsonFileSink {
+    fn record(&self, event: AuditEvent) -> Pin<Box<dyn Future<Output = Result<(), AuditError>> + Send + '_>> {
+        let path = self.path.clone();
+        let lock = self.lock.clone();
+        Box::pin(async move {
+            let mut line = serde_json::to_string(&event).map_err(|error| AuditError::Sink(error.to_string()))?;
+            line.push('\n');
+
+            tokio::task::spawn_blocking(move || {
+                use std::io::Write;
+
+                let _guard = lock.lock().unwrap();
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)?
+                    .write_all(line.as_bytes())
+            })
+            .await
+            .map_err(|error| AuditError::Sink(error.to_string()))?
+            .map_err(|error| AuditError::Sink(error.to_string()))
+        })
+    }
+}
+
+/// Sink that POSTs each event as JSON to an HTTP endpoint.
+///
+/// `https://` endpoints need the `tls` feature.
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    url: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Send an extra header with every event, e.g. an authorization token
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// How long to wait for the endpoint, 5 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub(crate) async fn send(&self, body: Vec<u8>) -> Result<(), crate::http_client::BoxError> {
+        let mut request = http::Request::post(&self.url).header(http::header::CONTENT_TYPE, "application/json");
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let (status, _) = crate::http_client::send(&self.url, request, body).await?;
+        if !status.is_success() {
+            return Err(format!("webhook answered {}", status).into());
+        }
+        Ok(())
+    }
+}
+
+impl AuditSink for WebhookSink {
+    fn record(&self, event: AuditEvent) -> Pin<Box<dyn Future<Output = Result<(), AuditError>> + Send + '_>> {
+        Box::pin(async move {
+            let body = serde_json::to_vec(&event).map_err(|error| AuditError::Sink(error.to_string()))?;
+            match tokio::time::timeout(self.timeout, self.send(body)).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(error)) => Err(AuditError::Sink(error.to_string())),
+                Err(_) => Err(AuditError::Sink(format!("webhook {} timed out", self.url))),
+            }
+        })
+    }
+}
+
+/// Who is acting and from where, filled into events recorded while handling
+/// a request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditContext {
+    pub actor: Option<String>,
+    pub ip: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl AuditContext {
+    /// Client IP address and `x-request-id` of a request
+    pub fn from_request(req: &Request) -> Self {
+        Self {
+            actor: None,
+            ip: req.remote_addr().map(|addr| addr.ip().to_string()),
+            request_id: req.header("x-request-id").map(str::to_string),
+        }
+    }
+}
+
+tokio::task_local! {
+    static CONTEXT: Arc<Mutex<AuditContext>>;
+}
+
+static SINK: RwLock<Option<Arc<dyn AuditSink>>> = RwLock::new(None);
+
+type RecordedEvents = Arc<Mutex<Vec<AuditEvent>>>;
+
+static FAKE: Mutex<Option<RecordedEvents>> = Mutex::new(None);
+
+/// Held by the active [`AuditFake`], so fakes take turns
+static FAKE_TURN: Mutex<()> = Mutex::new(());
+
+/// Audit log facade
+pub struct Audit;
+
+impl Audit {
+    /// Send every event recorded from now on to `sink`
+    pub fn set_sink<S: AuditSink>(sink: S) {
+        *SINK.write().unwrap() = Some(Arc::new(sink));
+    }
+
+    /// Record an event, filling in the actor, IP address and request ID of
+    /// the current [`Audit::scope`] where the event leaves them out
+    pub async fn record(mut event: AuditEvent) -> Result<(), AuditError> {
+        if let Some(context) = Self::context() {
+            event.actor = event.actor.or(context.actor);
+            event.ip = event.ip.or(context.ip);
+            event.request_id = event.request_id.or(context.request_id);
+        }
+
+        let fake = FAKE.lock().unwrap().clone();
+        if let Some(events) = fake {
+            events.lock().unwrap().push(event);
+            return Ok(());
+        }
+
+        let sink = SINK.read().unwrap().clone();
+        match sink {
+            Some(sink) => sink.record(event).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Run `future` with `context` available to [`Audit::record`]
+    pub async fn scope<F: Future>(context: AuditContext, future: F) -> F::Output {
+        CONTEXT.scope(Arc::new(Mutex::new(context)), future).await
+    }
+
+    /// Context of the current scope, if any
+    pub fn context() -> Option<AuditContext> {
+        CONTEXT.try_with(|context| context.lock().unwrap().clone()).ok()
+    }
+
+    /// Set who is acting for the rest of the current scope, e.g. right after
+    /// logging a user in
+    pub fn set_actor(actor: impl ToString) {
+        let _ = CONTEXT.try_with(|context| context.lock().unwrap().actor = Some(actor.to_string()));
+    }
+
+    /// Record events in memory instead of sending them to the sink until the
+    /// returned guard is dropped, waiting first for any other fake to be
+    /// dropped
+    pub fn fake() -> AuditFake {
+        let turn = FAKE_TURN.lock().unwrap_or_else(PoisonError::into_inner);
+        let events = RecordedEvents::default();
+        *FAKE.lock().unwrap() = Some(events.clone());
+        AuditFake { events, _turn: turn }
+    }
+}
+
+/// Guard returned by [`Audit::fake`]; events go to the sink again once it
+/// is dropped
+#[must_use = "the audit fake is removed when this guard is dropped"]
+pub struct AuditFake {
+    events: RecordedEvents,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl AuditFake {
+    /// Events recorded so far, oldest first
+    pub fn events(&self) -> Vec<AuditEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Assert at least one event with `action` was recorded
+    pub fn assert_recorded(&self, action: &str) {
+        let events = self.events();
+        assert!(
+            events.iter().any(|event| event.action == action),
+            "expected a {} audit event, recorded: {:?}",
+            action,
+            events.iter().map(|event| event.action.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    pub fn assert_nothing_recorded(&self) {
+        let events = self.events();
+        assert!(events.is_empty(), "expected no audit events, recorded {}", events.len());
+    }
+}
+
+impl Drop for AuditFake {
+    fn drop(&mut self) {
+        *FAKE.lock().unwrap() = None;
+    }
+}
+
+/// Middleware that makes each request's IP address and `x-request-id`
+/// available to events recorded while handling it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureAuditContext;
+
+impl Middleware for CaptureAuditContext {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let context = AuditContext::from_request(&req);
+        Box::pin(Audit::scope(context, next(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+
+    #[test]
+    fn test_changes_only_keep_differing_attributes() {
+        let before = serde_json::json!({ "name": "Ada", "role": "member", "password": "x" });
+        let after = serde_json::json!({ "name": "Ada", "role": "admin", "password": "y", "team": 3 });
+
+        let event = AuditEvent::new(PERMISSION_CHANGED).changes(&before, &after).except(&["password"]);
+        assert_eq!(event.changes.keys().collect::<Vec<_>>(), ["role", "team"]);
+        assert_eq!(event.changes["role"], Change { before: "member".into(), after: "admin".into() });
+        assert_eq!(event.changes["team"].before, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_events_pick_up_the_request_context() {
+        let audit_log = Audit::fake();
+        let app = crate::App::new().middleware(CaptureAuditContext).post("/login", |_req: Request| async {
+            Audit::set_actor(7);
+            Audit::record(AuditEvent::new(LOGIN)).await.unwrap();
+            Response::ok()
+        });
+
+        let req = Request::mock(Method::POST, "/login")
+            .with_header("x-request-id", "req-1")
+            .with_remote_addr("203.0.113.9:4000".parse().unwrap());
+        app.handle_request(req).await;
+
+        audit_log.assert_recorded(LOGIN);
+        let event = &audit_log.events()[0];
+        assert_eq!(event.actor.as_deref(), Some("7"));
+        assert_eq!(event.ip.as_deref(), Some("203.0.113.9"));
+        assert_eq!(event.request_id.as_deref(), Some("req-1"));
+    }
+
+    #[tokio::test]
+    async fn test_json_file_sink_appends_lines() {
+        let path = std::env::temp_dir().join(format!("torch-audit-{}.log", std::process::id()));
+        let sink = JsonFileSink::new(&path);
+
+        sink.record(AuditEvent::new(LOGIN).actor(1)).await.unwrap();
+        sink.record(AuditEvent::new(LOGOUT).actor(1)).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let events: Vec<AuditEvent> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(events.iter().map(|event| event.action.as_str()).collect::<Vec<_>>(), [LOGIN, LOGOUT]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Audit sink backed by the ORM connection pool, and auditing for models
+//!
+//! Events are stored in the `audit_logs` table with their changes and
+//! metadata as JSON text.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+
+use super::{diff, Audit, AuditError, AuditEvent, AuditSink, MODEL_CREATED, MODEL_DELETED, MODEL_UPDATED};
+use crate::orm::connection::{try_get_pool, placeholders};
+use crate::orm::migration::Schema;
+use crate::orm::Model;
+
+/// Table holding audit events
+pub const AUDIT_TABLE: &str = "audit_logs";
+
+/// Sink that stores events in the database configured for the ORM
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseSink;
+
+fn database_error(error: impl std::fmt::Display) -> AuditError {
+    AuditError::Sink(error.to_string())
+}
+
+impl DatabaseSink {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Create the `audit_logs` table if it doesn't exist yet
+    pub async fn install(&self) -> Result<(), AuditError> {
+        if Schema::has_table(AUDIT_TABLE).await.map_err(database_error)? {
+            return Ok(());
+        }
+
+        Schema::create_table(AUDIT_TABLE, |table| {
+            table.id("id");
+            table.string("action", None);
+            table.string("actor", None).nullable();
+            table.string("ip", None).nullable();
+            table.string("request_id", None).nullable();
+            table.string("subject", None).nullable();
+            table.text("changes");
+            table.text("metadata");
+            table.big_integer("occurred_at");
+            table.index(&["subject"], None);
+            table.index(&["actor", "occurred_at"], None);
+        })
+        .execute()
+        .await
+        .map_err(database_error)
+    }
+}
+
+impl AuditSink for DatabaseSink {
+    fn record(&self, event: AuditEvent) -> Pin<Box<dyn Future<Output = Result<(), AuditError>> + Send + '_>> {
+        Box::pin(async move {
+            let changes = serde_json::to_string(&event.changes).map_err(database_error)?;
+            let metadata = serde_json::to_string(&event.metadata).map_err(database_error)?;
+            let sql = placeholders(&format!(
+                "INSERT INTO {} (action, actor, ip, request_id, subject, changes, metadata, occurred_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                AUDIT_TABLE
+            ));
+            sqlx::query(&sql)
+                .bind(event.action)
+                .bind(event.actor)
+                .bind(event.ip)
+                .bind(event.request_id)
+                .bind(event.subject)
+                .bind(changes)
+                .bind(metadata)
+                .bind(event.occurred_at as i64)
+                .execute(&try_get_pool().map_err(database_error)?)
+                .await
+                .map_err(database_error)?;
+            Ok(())
+        })
+    }
+}
+
+/// Models whose changes are written to the audit log.
+///
+/// Opt a model in with an empty impl, then save and delete it through
+/// [`save_audited`](Auditable::save_audited) and
+/// [`delete_audited`](Auditable::delete_audited):
+///
+/// ```rust,ignore
+/// impl Auditable for User {
+///     fn audit_exclude() -> &'static [&'static str] {
+///         &["password"]
+///     }
+/// }
+///
+/// let original = user.clone();
+/// user.role = "admin".to_string();
+/// user.save_audited(Some(&original)).await?;
+/// ```
+#[async_trait]
+pub trait Auditable: Model {
+    /// Attributes left out of the recorded changes, such as password hashes
+    fn audit_exclude() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Save the model, recording `model.created` or `model.updated`.
+    ///
+    /// Pass the model as it was loaded to record only what changed; without
+    /// it every attribute is recorded. A failure to record the event is
+    /// logged rather than returned, since the model has been saved by then.
+    async fn save_audited(&mut self, original: Option<&Self>) -> crate::orm::Result<()> {
+        let action = if self.is_new() { MODEL_CREATED } else { MODEL_UPDATED };
+        let before = original.map(serde_json::to_value).transpose()?.unwrap_or_default();
+        self.save().await?;
+
+        let after = serde_json::to_value(&*self)?;
+        record(model_event(action, self, &before, &after)).await;
+        Ok(())
+    }
+
+    /// Delete the model, recording `model.deleted` with its last attributes
+    async fn delete_audited(&mut self) -> crate::orm::Result<()> {
+        let before = serde_json::to_value(&*self)?;
+        self.delete().await?;
+
+        record(model_event(MODEL_DELETED, self, &before, &serde_json::Value::Null)).await;
+        Ok(())
+    }
+}
+
+fn model_event<M: Auditable>(action: &str, model: &M, before: &serde_json::Value, after: &serde_json::Value) -> AuditEvent {
+    let id = match model.id().map(|id| serde_json::to_value(id).unwrap_or_default()) {
+        Some(serde_json::Value::String(id)) => id,
+        Some(id) => id.to_string(),
+        None => "new".to_string(),
+    };
+    let before = if before.is_null() { serde_json::json!({}) } else { before.clone() };
+    let after = if after.is_null() { serde_json::json!({}) } else { after.clone() };
+
+    let mut event = AuditEvent::new(action).subject(M::table_name(), id);
+    event.changes = diff(&before, &after);
+    event.except(M::audit_exclude())
+}
+
+async fn record(event: AuditEvent) {
+    if let Err(error) = Audit::record(event).await {
+        eprintln!("⚠️  Failed to record audit event: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{LOGIN, PERMISSION_CHANGED};
+    use crate::orm::connection::sqlite_test_pool;
+    use serde::{Deserialize, Serialize};
+    use sqlx::Row;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AuditedNote {
+        id: Option<i64>,
+        body: String,
+        secret: String,
+    }
+
+    crate::impl_model!(AuditedNote, table = "audited_notes", primary_key = "id", primary_key_type = i64);
+    crate::impl_from_row!(AuditedNote, { id, body, secret });
+
+    impl Auditable for AuditedNote {
+        fn audit_exclude() -> &'static [&'static str] {
+            &["secret"]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_sink_records_events_that_can_be_queried() {
+        let pool = sqlite_test_pool().await;
+        let sink = DatabaseSink::new();
+        sink.install().await.unwrap();
+        sink.install().await.unwrap();
+
+        let mut event = AuditEvent::new(PERMISSION_CHANGED)
+            .actor(7)
+            .subject("users", 42)
+            .changes(&serde_json::json!({ "role": "member" }), &serde_json::json!({ "role": "admin" }))
+            .with("reason", "promotion");
+        event.ip = Some("203.0.113.9".to_string());
+        sink.record(event.clone()).await.unwrap();
+        sink.record(AuditEvent::new(LOGIN).actor(8)).await.unwrap();
+
+        let sql = placeholders(&format!("SELECT * FROM {} WHERE subject = ?", AUDIT_TABLE));
+        let rows = sqlx::query(&sql).bind("users#42").fetch_all(&pool).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.get::<String, _>("action"), event.action);
+        assert_eq!(row.get::<Option<String>, _>("actor").as_deref(), Some("7"));
+        assert_eq!(row.get::<Option<String>, _>("ip").as_deref(), Some("203.0.113.9"));
+        assert_eq!(row.get::<Option<String>, _>("request_id"), None);
+        assert_eq!(row.get::<i64, _>("occurred_at"), event.occurred_at as i64);
+        let changes: serde_json::Value = serde_json::from_str(&row.get::<String, _>("changes")).unwrap();
+        assert_eq!(changes, serde_json::json!({ "role": { "before": "member", "after": "admin" } }));
+        let metadata: serde_json::Value = serde_json::from_str(&row.get::<String, _>("metadata")).unwrap();
+        assert_eq!(metadata, serde_json::json!({ "reason": "promotion" }));
+
+        let sql = placeholders(&format!("SELECT action FROM {} WHERE actor = ? ORDER BY occurred_at", AUDIT_TABLE));
+        let actions: Vec<String> = sqlx::query(&sql).bind("8").fetch_all(&pool).await.unwrap().iter().map(|row| row.get("action")).collect();
+        assert_eq!(actions, [LOGIN]);
+    }
+
+    #[tokio::test]
+    async fn test_auditable_models_record_their_changes() {
+        let pool = sqlite_test_pool().await;
+        sqlx::query("CREATE TABLE IF NOT EXISTS audited_notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL, secret TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let audit_log = Audit::fake();
+
+        let mut note = AuditedNote { id: None, body: "draft".to_string(), secret: "a".to_string() };
+        note.save_audited(None).await.unwrap();
+        let original = note.clone();
+        note.body = "published".to_string();
+        note.secret = "b".to_string();
+        note.save_audited(Some(&original)).await.unwrap();
+        note.delete_audited().await.unwrap();
+
+        let events = audit_log.events();
+        let subject = format!("audited_notes#{}", note.id.unwrap());
+        assert_eq!(events.iter().map(|event| event.action.as_str()).collect::<Vec<_>>(), [MODEL_CREATED, MODEL_UPDATED, MODEL_DELETED]);
+        assert!(events.iter().all(|event| event.subject.as_deref() == Some(subject.as_str())));
+        assert!(events.iter().all(|event| !event.changes.contains_key("secret")));
+        assert_eq!(events[0].changes["body"].after, "draft");
+        assert_eq!(events[1].changes.keys().collect::<Vec<_>>(), ["body"]);
+        assert_eq!((&events[1].changes["body"].before, &events[1].changes["body"].after), (&"draft".into(), &"published".into()));
+        assert_eq!(events[2].changes["body"].before, "published");
+        assert_eq!(events[2].changes["body"].after, serde_json::Value::Null);
+    }
+}
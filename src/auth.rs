@@ -0,0 +1,29 @@
+//! # Authentication helpers
+//!
+//! Building blocks for signing users in that don't depend on how the app
+//! stores its users or sessions. Password hashing lives in
+//! [`security::auth`](crate::security::auth).
+//!
+//! - [`totp`] - Time-based one-time passwords for two-factor authentication
+//! - [`ldap`] - Checking credentials against LDAP or Active Directory
+//!   (`ldap` feature)
+//! - [`jwt`] - Bearer token middleware and the [`CurrentUser`] extractor
+//!   (`oidc` feature)
+//! - [`oidc`] - OpenID Connect discovery and signing key caching (`oidc`
+//!   feature)
+//! - [`saml`] - SAML 2.0 single sign-on as a service provider (`saml`
+//!   feature)
+
+#[cfg(feature = "oidc")]
+pub mod jwt;
+#[cfg(feature = "ldap")]
+pub mod ldap;
+#[cfg(feature = "oidc")]
+pub mod oidc;
+#[cfg(feature = "saml")]
+pub mod saml;
+pub mod totp;
+
+#[cfg(feature = "oidc")]
+pub use jwt::{CurrentUser, JwtAuth, JwtError};
+pub use totp::{RequireTwoFactor, Totp, TwoFactorVerified};
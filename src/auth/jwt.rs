@@ -0,0 +1,564 @@
+//! # Bearer token authentication
+//!
+//! [`JwtAuth`] checks the JSON Web Token in each request's
+//! `Authorization: Bearer` header and hands the signed-in user to handlers
+//! as [`CurrentUser`]. Point it at an OpenID Connect issuer and it finds and
+//! caches the signing keys itself (see [`oidc`](super::oidc)), so Auth0,
+//! Keycloak or Entra ID only need configuring:
+//!
+//! ```toml
+//! [security.oidc]
+//! issuer = "https://acme.eu.auth0.com/"
+//! audience = ["https://api.acme.com"]
+//! roles_claim = "https://acme.com/roles"   # Keycloak: "realm_access.roles"
+//! ```
+//!
+//! ```rust,no_run
+//! use torch_web::auth::jwt::{CurrentUser, JwtAuth};
+//! use torch_web::config::OidcConfig;
+//! use torch_web::{App, Response};
+//!
+//! # fn example(config: &OidcConfig) -> Result<(), torch_web::auth::JwtError> {
+//! let app = App::new()
+//!     .middleware(JwtAuth::from_config(config)?)
+//!     .get("/me", |user: CurrentUser| async move {
+//!         if !user.has_role("admin") {
+//!             return Response::forbidden();
+//!         }
+//!         Response::ok().body(format!("Hello {}", user.name.as_deref().unwrap_or(&user.id)))
+//!     });
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Tokens must be signed with an asymmetric key from the issuer's key set
+//! (`RS*`, `PS*`, `ES256`, `ES384` or `EdDSA`), or with HMAC-SHA256 for
+//! [`JwtAuth::hs256`]. `exp` is required and `aud` must name one of the
+//! configured audiences, so tokens the issuer signed for other clients or
+//! APIs are refused; `nbf` and `iss` are checked as configured, with a
+//! minute of leeway for clock skew.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::Deserialize;
+
+use super::oidc::OidcProvider;
+use crate::clock::unix_now;
+use crate::config::OidcConfig;
+use crate::extractors::FromRequestParts;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+/// Why a token was refused
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum JwtError {
+    #[error("malformed token: {0}")]
+    Malformed(String),
+
+    #[error("unsupported signing algorithm {0}")]
+    UnsupportedAlgorithm(String),
+
+    #[error("unknown signing key {0}")]
+    UnknownKey(String),
+
+    #[error("invalid token signature")]
+    InvalidSignature,
+
+    #[error("token has expired")]
+    Expired,
+
+    #[error("token is not valid yet")]
+    NotYetValid,
+
+    #[error("token was issued by {0}")]
+    InvalidIssuer(String),
+
+    #[error("token is not meant for this audience")]
+    InvalidAudience,
+
+    #[error("no audience configured; tokens are only accepted when `aud` names this API")]
+    MissingAudience,
+
+    #[error("OpenID discovery failed: {0}")]
+    Discovery(String),
+}
+
+enum Keys {
+    Secret(Vec<u8>),
+    Oidc(OidcProvider),
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// Middleware authenticating bearer tokens, see the [module docs](self)
+#[derive(Clone)]
+pub struct JwtAuth {
+    keys: Arc<Keys>,
+    issuer: Option<String>,
+    audiences: Vec<String>,
+    leeway: Duration,
+    roles_claim: String,
+    optional: bool,
+}
+
+impl JwtAuth {
+    /// Accept tokens from an OpenID Connect `issuer` that are meant for
+    /// `audience`, usually the API's identifier or client id. Fails with
+    /// [`JwtError::MissingAudience`] when `audience` is empty.
+    pub fn oidc(issuer: &str, audience: &str) -> Result<Self, JwtError> {
+        if audience.trim().is_empty() {
+            return Err(JwtError::MissingAudience);
+        }
+        Ok(Self::with_provider(OidcProvider::new(issuer)).audience(audience))
+    }
+
+    /// Accept tokens signed with a shared HMAC-SHA256 secret. No token is
+    /// accepted until an [`audience`](JwtAuth::audience) is set.
+    pub fn hs256(secret: impl AsRef<[u8]>) -> Self {
+        Self::with_keys(Keys::Secret(secret.as_ref().to_vec()))
+    }
+
+    /// Set up from the `[security.oidc]` section of `torch.toml`. Fails
+    /// with [`JwtError::MissingAudience`] when `audience` lists none.
+    pub fn from_config(config: &OidcConfig) -> Result<Self, JwtError> {
+        if config.audience.iter().all(|audience| audience.trim().is_empty()) {
+            return Err(JwtError::MissingAudience);
+        }
+        let mut auth = Self::with_provider(OidcProvider::new(&config.issuer))
+            .leeway(Duration::from_secs(config.leeway_secs))
+            .roles_claim(&config.roles_claim)
+            .optional(config.optional);
+        for audience in config.audience.iter().filter(|audience| !audience.trim().is_empty()) {
+            auth = auth.audience(audience);
+        }
+        Ok(auth)
+    }
+
+    /// Accept tokens from the issuer of `provider`, signed with its keys
+    pub(super) fn with_provider(provider: OidcProvider) -> Self {
+        let issuer = provider.issuer().to_string();
+        Self::with_keys(Keys::Oidc(provider)).issuer(&issuer)
+    }
+
+    fn with_keys(keys: Keys) -> Self {
+        Self {
+            keys: Arc::new(keys),
+            issuer: None,
+            audiences: Vec::new(),
+            leeway: Duration::from_secs(60),
+            roles_claim: "roles".to_string(),
+            optional: false,
+        }
+    }
+
+    /// Require `iss` to be `issuer`
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.issuer = Some(issuer.to_string());
+        self
+    }
+
+    /// Require `aud` to include `audience`, or any one of several
+    pub fn audience(mut self, audience: &str) -> Self {
+        self.audiences.push(audience.to_string());
+        self
+    }
+
+    /// Clock skew allowed on `exp` and `nbf` (default one minute)
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Claim holding the user's roles (default `roles`). Nested claims are
+    /// written with dots, like Keycloak's `realm_access.roles`.
+    pub fn roles_claim(mut self, claim: &str) -> Self {
+        self.roles_claim = claim.to_string();
+        self
+    }
+
+    /// Let requests without a token through, with no [`CurrentUser`].
+    /// Invalid tokens are still refused.
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    /// Check a token and read its user
+    pub async fn verify(&self, token: &str) -> Result<CurrentUser, JwtError> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(JwtError::Malformed("expected three dot-separated parts".to_string()));
+        };
+        let decode = |part: &str, what: &str| {
+            general_purpose::URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| JwtError::Malformed(format!("{} is not base64url", what)))
+        };
+        let header: Header = serde_json::from_slice(&decode(header, "header")?)
+            .map_err(|error| JwtError::Malformed(format!("header: {}", error)))?;
+        let message = &token[..token.len() - signature.len() - 1];
+        let signature = decode(signature, "signature")?;
+
+        match &*self.keys {
+            Keys::Secret(secret) => {
+                if header.alg != "HS256" {
+                    return Err(JwtError::UnsupportedAlgorithm(header.alg));
+                }
+                let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret);
+                ring::hmac::verify(&key, message.as_bytes(), &signature).map_err(|_| JwtError::InvalidSignature)?;
+            }
+            Keys::Oidc(provider) => {
+                let key = provider.key(header.kid.as_deref()).await?;
+                key.verify(&header.alg, message.as_bytes(), &signature)?;
+            }
+        }
+
+        let claims: serde_json::Value = serde_json::from_slice(&decode(payload, "payload")?)
+            .map_err(|error| JwtError::Malformed(format!("payload: {}", error)))?;
+        self.check_claims(&claims)?;
+        Ok(CurrentUser::from_claims(claims, &self.roles_claim))
+    }
+
+    fn check_claims(&self, claims: &serde_json::Value) -> Result<(), JwtError> {
+        let now = unix_now();
+        let leeway = self.leeway.as_secs();
+        let time = |claim: &str| claims.get(claim).and_then(|value| value.as_f64()).map(|value| value as u64);
+
+        let expires = time("exp").ok_or_else(|| JwtError::Malformed("missing exp".to_string()))?;
+        if now > expires.saturating_add(leeway) {
+            return Err(JwtError::Expired);
+        }
+        if time("nbf").is_some_and(|not_before| not_before > now.saturating_add(leeway)) {
+            return Err(JwtError::NotYetValid);
+        }
+
+        let expected_issuer = match &*self.keys {
+            Keys::Oidc(provider) => Some(provider.issuer()),
+            Keys::Secret(_) => self.issuer.as_deref(),
+        };
+        if let Some(expected) = expected_issuer {
+            let issuer = claims.get("iss").and_then(|value| value.as_str()).unwrap_or("");
+            if issuer.trim_end_matches('/') != expected.trim_end_matches('/') {
+                return Err(JwtError::InvalidIssuer(issuer.to_string()));
+            }
+        }
+
+        if self.audiences.is_empty() {
+            return Err(JwtError::MissingAudience);
+        }
+        let audiences: Vec<&str> = match claims.get("aud") {
+            Some(serde_json::Value::String(audience)) => vec![audience.as_str()],
+            Some(serde_json::Value::Array(audiences)) => audiences.iter().filter_map(|value| value.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !audiences.iter().any(|audience| self.audiences.iter().any(|expected| expected == audience)) {
+            return Err(JwtError::InvalidAudience);
+        }
+        Ok(())
+    }
+}
+
+fn unauthorized(error: Option<&JwtError>) -> Response {
+    let challenge = match error {
+        Some(error) => format!("Bearer error=\"invalid_token\", error_description=\"{}\"", error.to_string().replace('"', "'")),
+        None => "Bearer".to_string(),
+    };
+    Response::unauthorized()
+        .header("www-authenticate", challenge)
+        .body(match error {
+            Some(error) => format!("Unauthorized: {}", error),
+            None => "Unauthorized".to_string(),
+        })
+}
+
+impl Middleware for JwtAuth {
+    fn call(
+        &self,
+        mut req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let token = req
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer ").or_else(|| value.strip_prefix("bearer ")))
+            .map(|token| token.trim().to_string());
+        let Some(token) = token else {
+            if self.optional {
+                return next(req);
+            }
+            return Box::pin(async { unauthorized(None) });
+        };
+
+        let auth = self.clone();
+        Box::pin(async move {
+            match auth.verify(&token).await {
+                Ok(user) => {
+                    req.insert_extension(user);
+                    next(req).await
+                }
+                Err(error) => unauthorized(Some(&error)),
+            }
+        })
+    }
+}
+
+/// The user a verified token was issued to
+///
+/// Needs [`JwtAuth`] in the middleware stack; requests without a valid
+/// token are rejected with `401 Unauthorized`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrentUser {
+    /// The `sub` claim
+    pub id: String,
+    pub email: Option<String>,
+    /// `name`, or `preferred_username` when there is no name
+    pub name: Option<String>,
+    /// From the configured roles claim
+    pub roles: Vec<String>,
+    /// From `scope` or `scp`
+    pub scopes: Vec<String>,
+    /// Every claim in the token
+    pub claims: serde_json::Value,
+}
+
+impl CurrentUser {
+    fn from_claims(claims: serde_json::Value, roles_claim: &str) -> Self {
+        let text = |claim: &str| claims.get(claim).and_then(|value| value.as_str()).map(str::to_string);
+        let list = |value: Option<&serde_json::Value>| match value {
+            Some(serde_json::Value::String(list)) => list.split_whitespace().map(str::to_string).collect(),
+            Some(serde_json::Value::Array(items)) => items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        };
+        Self {
+            id: text("sub").unwrap_or_default(),
+            email: text("email"),
+            name: text("name").or_else(|| text("preferred_username")),
+            roles: list(claim(&claims, roles_claim)),
+            scopes: list(claims.get("scope").or_else(|| claims.get("scp"))),
+            claims,
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|granted| granted == role)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /// A claim by name, or by a dotted path into nested objects
+    pub fn claim(&self, name: &str) -> Option<&serde_json::Value> {
+        claim(&self.claims, name)
+    }
+}
+
+/// Claims like Auth0's `https://acme.com/roles` contain dots themselves, so
+/// the whole name is tried before the path
+fn claim<'a>(claims: &'a serde_json::Value, name: &str) -> Option<&'a serde_json::Value> {
+    claims
+        .get(name)
+        .or_else(|| name.split('.').try_fold(claims, |value, key| value.get(key)))
+}
+
+impl FromRequestParts for CurrentUser {
+    type Error = Response;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let user = req.get_extension::<CurrentUser>().cloned();
+        Box::pin(async move { user.ok_or_else(|| unauthorized(None)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn b64(bytes: &[u8]) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    struct SigningKey {
+        kid: &'static str,
+        pair: EcdsaKeyPair,
+    }
+
+    impl SigningKey {
+        fn generate(kid: &'static str) -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+            Self { kid, pair }
+        }
+
+        fn jwk(&self) -> serde_json::Value {
+            let point = self.pair.public_key().as_ref();
+            json!({"kty": "EC", "crv": "P-256", "kid": self.kid, "use": "sig", "alg": "ES256", "x": b64(&point[1..33]), "y": b64(&point[33..65])})
+        }
+
+        fn sign(&self, claims: serde_json::Value) -> String {
+            let header = json!({"alg": "ES256", "typ": "JWT", "kid": self.kid});
+            let message = format!("{}.{}", b64(header.to_string().as_bytes()), b64(claims.to_string().as_bytes()));
+            let signature = self.pair.sign(&SystemRandom::new(), message.as_bytes()).unwrap();
+            format!("{}.{}", message, b64(signature.as_ref()))
+        }
+    }
+
+    /// Serves discovery and whatever keys are in `jwks`, counting JWKS fetches
+    /// and answering them after `delay` milliseconds
+    async fn provider(jwks: Arc<Mutex<Vec<serde_json::Value>>>, fetches: Arc<AtomicUsize>, delay: Arc<AtomicU64>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let discovery = json!({"issuer": issuer, "jwks_uri": format!("{}/jwks", issuer)}).to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (jwks, fetches, delay, discovery) = (jwks.clone(), fetches.clone(), delay.clone(), discovery.clone());
+                tokio::spawn(async move {
+                    let mut request = vec![0; 4096];
+                    let read = stream.read(&mut request).await.unwrap();
+                    let body = if String::from_utf8_lossy(&request[..read]).starts_with("GET /jwks ") {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        let keys = json!({"keys": *jwks.lock().unwrap()}).to_string();
+                        tokio::time::sleep(Duration::from_millis(delay.load(Ordering::SeqCst))).await;
+                        keys
+                    } else {
+                        discovery
+                    };
+                    let response = format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", body.len(), body);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        issuer
+    }
+
+    #[test]
+    fn test_an_audience_is_required() {
+        let mut config = OidcConfig { issuer: "https://idp.example.com/".to_string(), ..OidcConfig::default() };
+        assert!(matches!(JwtAuth::from_config(&config), Err(JwtError::MissingAudience)));
+        config.audience = vec![" ".to_string()];
+        assert!(matches!(JwtAuth::from_config(&config), Err(JwtError::MissingAudience)));
+        config.audience = vec!["api".to_string()];
+        assert!(JwtAuth::from_config(&config).is_ok());
+        assert!(matches!(JwtAuth::oidc("https://idp.example.com/", ""), Err(JwtError::MissingAudience)));
+    }
+
+    #[tokio::test]
+    async fn test_shared_secret_tokens_need_a_matching_audience() {
+        let sign = |claims: serde_json::Value| {
+            let message = format!("{}.{}", b64(br#"{"alg":"HS256","typ":"JWT"}"#), b64(claims.to_string().as_bytes()));
+            let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"secret");
+            format!("{}.{}", message, b64(ring::hmac::sign(&key, message.as_bytes()).as_ref()))
+        };
+        let exp = unix_now() + 300;
+
+        let token = sign(json!({"sub": "user-1", "aud": "api", "exp": exp}));
+        assert_eq!(JwtAuth::hs256("secret").verify(&token).await, Err(JwtError::MissingAudience));
+        let auth = JwtAuth::hs256("secret").audience("api");
+        assert_eq!(auth.verify(&token).await.unwrap().id, "user-1");
+        let without_aud = sign(json!({"sub": "user-1", "exp": exp}));
+        assert_eq!(auth.verify(&without_aud).await, Err(JwtError::InvalidAudience));
+    }
+
+    #[tokio::test]
+    async fn test_cached_keys_dont_wait_on_a_slow_refetch() {
+        let first = SigningKey::generate("k1");
+        let jwks = Arc::new(Mutex::new(vec![first.jwk()]));
+        let (fetches, delay) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicU64::new(0)));
+        let issuer = provider(jwks, fetches.clone(), delay.clone()).await;
+        let now = unix_now();
+        let claims = json!({"iss": issuer, "aud": "api", "sub": "user-1", "exp": now + 300});
+        let auth = JwtAuth::oidc(&issuer, "api").unwrap();
+        auth.verify(&first.sign(claims.clone())).await.unwrap();
+
+        // An unknown key id refetches the set from a provider that is slow
+        // to answer, while tokens signed with the cached key go through
+        delay.store(2000, Ordering::SeqCst);
+        let refetching = auth.clone();
+        let unknown = SigningKey::generate("k2").sign(claims.clone());
+        let refetch = tokio::spawn(async move { refetching.verify(&unknown).await });
+        while fetches.load(Ordering::SeqCst) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let cached = tokio::time::timeout(Duration::from_millis(500), auth.verify(&first.sign(claims))).await;
+        assert_eq!(cached.expect("waited on the refetch").unwrap().id, "user-1");
+        assert_eq!(refetch.await.unwrap(), Err(JwtError::UnknownKey("k2".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_oidc_tokens_with_key_rotation() {
+        let (first, rotated) = (SigningKey::generate("k1"), SigningKey::generate("k2"));
+        let jwks = Arc::new(Mutex::new(vec![first.jwk()]));
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let issuer = provider(jwks.clone(), fetches.clone(), Arc::new(AtomicU64::new(0))).await;
+        let now = unix_now();
+        let claims = |overrides: serde_json::Value| {
+            let mut claims = json!({"iss": issuer, "aud": ["api", "other"], "sub": "user-1", "exp": now + 300,
+                "name": "Ada", "scope": "read:posts write:posts", "realm_access": {"roles": ["admin"]}});
+            claims.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+            claims
+        };
+
+        let auth = JwtAuth::oidc(&issuer, "api").unwrap().roles_claim("realm_access.roles");
+        let user = auth.verify(&first.sign(claims(json!({})))).await.unwrap();
+        assert_eq!((user.id.as_str(), user.name.as_deref()), ("user-1", Some("Ada")));
+        assert!(user.has_role("admin") && user.has_scope("write:posts"));
+
+        // The provider rotated its key: an unknown kid refetches the set once
+        jwks.lock().unwrap().push(rotated.jwk());
+        auth.verify(&rotated.sign(claims(json!({})))).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        let unknown = SigningKey::generate("k3");
+        assert_eq!(auth.verify(&unknown.sign(claims(json!({})))).await, Err(JwtError::UnknownKey("k3".to_string())));
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        let refused = [
+            (json!({"exp": now - 120}), JwtError::Expired),
+            (json!({"nbf": now + 120}), JwtError::NotYetValid),
+            (json!({"aud": "someone-else"}), JwtError::InvalidAudience),
+            (json!({"aud": null}), JwtError::InvalidAudience),
+            (json!({"iss": "https://evil.example.com"}), JwtError::InvalidIssuer("https://evil.example.com".to_string())),
+        ];
+        for (overrides, error) in refused {
+            assert_eq!(auth.verify(&first.sign(claims(overrides))).await, Err(error));
+        }
+        let mut tampered = first.sign(claims(json!({})));
+        tampered.insert_str(tampered.find('.').unwrap() + 1, "eyJ");
+        assert!(auth.verify(&tampered).await.is_err());
+
+        let app = App::new()
+            .middleware(auth)
+            .get("/me", |user: CurrentUser| async move { user.id });
+        let request = |token: Option<String>| {
+            let req = Request::mock(Method::GET, "/me");
+            match token {
+                Some(token) => req.with_header("authorization", &format!("Bearer {}", token)),
+                None => req,
+            }
+        };
+        let response = app.handle_request(request(Some(first.sign(claims(json!({})))))).await;
+        assert_eq!(response.body_data(), b"user-1");
+        let response = app.handle_request(request(None)).await;
+        assert_eq!(response.status_code(), http::StatusCode::UNAUTHORIZED);
+        let response = app.handle_request(request(Some(first.sign(claims(json!({"exp": now - 120})))))).await;
+        assert!(response.headers()["www-authenticate"].to_str().unwrap().contains("token has expired"));
+    }
+}
@@ -0,0 +1,584 @@
+//! # LDAP and Active Directory sign-in
+//!
+//! [`LdapAuth`] checks a username and password against a directory and
+//! reads the user's groups, mapped to the app's roles. It answers like the
+//! user repository from `torch make auth`, so it drops into the same login
+//! flow: on success, find or create the local user and start a session.
+//!
+//! ```rust,no_run
+//! use torch_web::auth::ldap::LdapAuth;
+//!
+//! # async fn example(username: &str, password: &str) -> Result<(), torch_web::auth::ldap::LdapError> {
+//! let ldap = LdapAuth::new("ldaps://dc1.acme.local", "DC=acme,DC=local")
+//!     .active_directory()
+//!     .bind_as("CN=torch-svc,OU=Service Accounts,DC=acme,DC=local", std::env::var("LDAP_PASSWORD").unwrap())
+//!     .map_group("Domain Admins", "admin")
+//!     .map_group("CN=Support,OU=Groups,DC=acme,DC=local", "support");
+//!
+//! match ldap.attempt(username, password).await? {
+//!     // users.find_or_create(&user.username, user.email.as_deref()), then
+//!     // sessions.start(local_user.id) as with database users
+//!     Some(user) => println!("{} signed in with roles {:?}", user.username, user.roles),
+//!     None => println!("These credentials do not match our records."),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each attempt finds the user's entry with the service account, binds as
+//! that entry with the password, and reads the groups. Connections are
+//! pooled and stay bound as the service account between attempts; a
+//! connection the directory closed is replaced on the next checkout.
+//!
+//! Empty passwords are refused before reaching the directory, since LDAP
+//! treats a bind with a DN and no password as an anonymous bind that
+//! succeeds.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::LdapConfig;
+
+/// Result code of a bind with the wrong password
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// AD's `LDAP_MATCHING_RULE_IN_CHAIN`, which follows nested groups
+const IN_CHAIN: &str = "1.2.840.113556.1.4.1941";
+
+/// Why the directory couldn't answer. Wrong credentials aren't an error;
+/// [`LdapAuth::attempt`] returns `None` for them.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LdapError {
+    #[error("could not reach the directory: {0}")]
+    Connection(String),
+
+    #[error("the directory refused the service account: {0}")]
+    ServiceAccount(String),
+
+    #[error("directory error: {0}")]
+    Directory(String),
+}
+
+/// A user the directory accepted
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdapUser {
+    pub dn: String,
+    /// The name they signed in with
+    pub username: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    /// DNs of the user's groups
+    pub groups: Vec<String>,
+    /// App roles of those groups, from [`LdapAuth::map_group`]
+    pub roles: Vec<String>,
+    /// Every attribute read from the user's entry
+    pub attributes: HashMap<String, Vec<String>>,
+}
+
+impl LdapUser {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|candidate| candidate == role)
+    }
+
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|dn| group_matches(dn, group))
+    }
+}
+
+/// Whether `dn` is `group`, given as a full DN or just its common name
+fn group_matches(dn: &str, group: &str) -> bool {
+    if dn.eq_ignore_ascii_case(group) {
+        return true;
+    }
+    let first = dn.split(',').next().unwrap_or_default();
+    first
+        .split_once('=')
+        .is_some_and(|(attribute, value)| attribute.trim().eq_ignore_ascii_case("cn") && value.trim().eq_ignore_ascii_case(group))
+}
+
+/// Where a user's groups come from
+#[derive(Debug, Clone, PartialEq)]
+enum GroupLookup {
+    /// An attribute of the user's entry, such as `memberOf`
+    Attribute(String),
+    /// Entries under `base` matching `filter`, with `{dn}` and `{username}`
+    /// filled in
+    Search { base: String, filter: String },
+}
+
+/// Credentials check against an LDAP directory or Active Directory
+#[derive(Clone)]
+pub struct LdapAuth {
+    settings: Arc<Settings>,
+    pool: Arc<Pool>,
+}
+
+#[derive(Debug, Clone)]
+struct Settings {
+    url: String,
+    base_dn: String,
+    service: Option<(String, String)>,
+    user_filter: String,
+    email_attribute: String,
+    name_attribute: String,
+    groups: GroupLookup,
+    group_roles: Vec<(String, String)>,
+    timeout: Duration,
+    start_tls: bool,
+}
+
+impl LdapAuth {
+    /// Users are looked up below `base_dn` on the server at `url`
+    /// (`ldap://` or `ldaps://`), by `uid` unless
+    /// [`user_filter`](Self::user_filter) says otherwise
+    pub fn new(url: impl Into<String>, base_dn: impl Into<String>) -> Self {
+        Self::with_settings(Settings {
+            url: url.into(),
+            base_dn: base_dn.into(),
+            service: None,
+            user_filter: "(uid={username})".to_string(),
+            email_attribute: "mail".to_string(),
+            name_attribute: "cn".to_string(),
+            groups: GroupLookup::Attribute("memberOf".to_string()),
+            group_roles: Vec::new(),
+            timeout: Duration::from_secs(5),
+            start_tls: false,
+        })
+    }
+
+    /// Set up from the `[security.ldap]` section of `torch.toml`
+    pub fn from_config(config: &LdapConfig) -> Self {
+        let mut auth = Self::new(&config.url, &config.base_dn);
+        if config.active_directory {
+            auth = auth.active_directory();
+        }
+        if let Some(filter) = &config.user_filter {
+            auth = auth.user_filter(filter);
+        }
+        if let (Some(dn), Some(password)) = (&config.bind_dn, &config.bind_password) {
+            auth = auth.bind_as(dn, password);
+        }
+        if let Some(filter) = &config.group_filter {
+            let base = config.group_base_dn.as_deref().unwrap_or(&config.base_dn);
+            auth = auth.group_search(base, filter);
+        }
+        for (group, role) in &config.group_roles {
+            auth = auth.map_group(group, role);
+        }
+        auth.pool_size(config.pool_size)
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .start_tls(config.start_tls)
+    }
+
+    fn with_settings(settings: Settings) -> Self {
+        Self {
+            settings: Arc::new(settings),
+            pool: Arc::new(Pool::new(5)),
+        }
+    }
+
+    fn update(mut self, change: impl FnOnce(&mut Settings)) -> Self {
+        change(Arc::make_mut(&mut self.settings));
+        self
+    }
+
+    /// Active Directory defaults: users by `sAMAccountName`, names from
+    /// `displayName` and groups, nested ones included, from a search
+    pub fn active_directory(self) -> Self {
+        self.update(|settings| {
+            settings.user_filter = "(&(objectCategory=person)(objectClass=user)(sAMAccountName={username}))".to_string();
+            settings.name_attribute = "displayName".to_string();
+            settings.groups = GroupLookup::Search {
+                base: settings.base_dn.clone(),
+                filter: format!("(&(objectClass=group)(member:{}:={{dn}}))", IN_CHAIN),
+            };
+        })
+    }
+
+    /// Search with this account instead of anonymously
+    pub fn bind_as(self, dn: impl Into<String>, password: impl Into<String>) -> Self {
+        let service = (dn.into(), password.into());
+        self.update(|settings| settings.service = Some(service))
+    }
+
+    /// Filter finding a user's entry, with `{username}` standing for the
+    /// escaped username
+    pub fn user_filter(self, filter: impl Into<String>) -> Self {
+        let filter = filter.into();
+        self.update(|settings| settings.user_filter = filter)
+    }
+
+    /// Attributes holding the email address and display name (default
+    /// `mail` and `cn`)
+    pub fn attributes(self, email: impl Into<String>, name: impl Into<String>) -> Self {
+        let (email, name) = (email.into(), name.into());
+        self.update(|settings| {
+            settings.email_attribute = email;
+            settings.name_attribute = name;
+        })
+    }
+
+    /// Read groups from this attribute of the user's entry (default
+    /// `memberOf`)
+    pub fn group_attribute(self, attribute: impl Into<String>) -> Self {
+        let attribute = attribute.into();
+        self.update(|settings| settings.groups = GroupLookup::Attribute(attribute))
+    }
+
+    /// Find groups by searching under `base` with `filter`, where `{dn}`
+    /// and `{username}` stand for the user, e.g.
+    /// `(&(objectClass=groupOfNames)(member={dn}))`
+    pub fn group_search(self, base: impl Into<String>, filter: impl Into<String>) -> Self {
+        let groups = GroupLookup::Search { base: base.into(), filter: filter.into() };
+        self.update(|settings| settings.groups = groups)
+    }
+
+    /// Give members of `group` (a DN, or a common name) the app role `role`
+    pub fn map_group(self, group: impl Into<String>, role: impl Into<String>) -> Self {
+        let mapping = (group.into(), role.into());
+        self.update(|settings| settings.group_roles.push(mapping))
+    }
+
+    /// Most connections kept open to the directory (default 5). Attempts
+    /// beyond that wait for a free connection.
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.pool = Arc::new(Pool::new(size.max(1)));
+        self
+    }
+
+    /// Limit on connecting and on each operation (default 5 seconds)
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.update(|settings| settings.timeout = timeout)
+    }
+
+    /// Upgrade `ldap://` connections with StartTLS
+    pub fn start_tls(self, start_tls: bool) -> Self {
+        self.update(|settings| settings.start_tls = start_tls)
+    }
+
+    /// The user with these credentials, or `None` when the username is
+    /// unknown or the password is wrong
+    pub async fn attempt(&self, username: &str, password: &str) -> Result<Option<LdapUser>, LdapError> {
+        if username.trim().is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let mut connection = self.pool.get(&self.settings).await?;
+        let result = self.check(&mut connection.ldap, username, password).await;
+        // A failed operation may have left the connection bound as the
+        // user, or broken; only clean ones go back
+        if result.is_ok() {
+            self.pool.put(connection);
+        }
+        result
+    }
+
+    async fn check(&self, ldap: &mut Ldap, username: &str, password: &str) -> Result<Option<LdapUser>, LdapError> {
+        let settings = &self.settings;
+        let filter = settings.user_filter.replace("{username}", &ldap_escape(username));
+        let mut attributes = vec![settings.email_attribute.as_str(), settings.name_attribute.as_str()];
+        if let GroupLookup::Attribute(attribute) = &settings.groups {
+            attributes.push(attribute);
+        }
+        let entries = search(ldap, settings, &settings.base_dn, &filter, attributes).await?;
+        let entry = match <[SearchEntry; 1]>::try_from(entries) {
+            Ok([entry]) => entry,
+            Err(entries) if entries.is_empty() => return Ok(None),
+            Err(entries) => {
+                eprintln!("⚠️  LDAP filter {} matches {} entries, refusing to pick one", filter, entries.len());
+                return Ok(None);
+            }
+        };
+
+        let bind = ldap
+            .with_timeout(settings.timeout)
+            .simple_bind(&entry.dn, password)
+            .await
+            .map_err(|error| LdapError::Connection(error.to_string()))?;
+        let accepted = match bind.rc {
+            0 => true,
+            INVALID_CREDENTIALS => false,
+            _ => return Err(LdapError::Directory(format!("binding as {}: {}", entry.dn, bind))),
+        };
+        // Back to the service account before anything else is read
+        bind_service(ldap, settings).await?;
+        if !accepted {
+            return Ok(None);
+        }
+
+        let groups = match &settings.groups {
+            GroupLookup::Attribute(attribute) => entry.attrs.get(attribute).cloned().unwrap_or_default(),
+            GroupLookup::Search { base, filter } => {
+                let filter = filter.replace("{dn}", &ldap_escape(&entry.dn)).replace("{username}", &ldap_escape(username));
+                search(ldap, settings, base, &filter, vec!["cn"]).await?.into_iter().map(|group| group.dn).collect()
+            }
+        };
+        let mut roles = Vec::new();
+        for (group, role) in &settings.group_roles {
+            if groups.iter().any(|dn| group_matches(dn, group)) && !roles.contains(role) {
+                roles.push(role.clone());
+            }
+        }
+
+        let first = |attribute: &str| entry.attrs.get(attribute).and_then(|values| values.first()).cloned();
+        Ok(Some(LdapUser {
+            email: first(&settings.email_attribute),
+            name: first(&settings.name_attribute),
+            dn: entry.dn.clone(),
+            username: username.to_string(),
+            groups,
+            roles,
+            attributes: entry.attrs,
+        }))
+    }
+}
+
+async fn search(ldap: &mut Ldap, settings: &Settings, base: &str, filter: &str, attributes: Vec<&str>) -> Result<Vec<SearchEntry>, LdapError> {
+    let (entries, _) = ldap
+        .with_timeout(settings.timeout)
+        .search(base, Scope::Subtree, filter, attributes)
+        .await
+        .map_err(|error| LdapError::Connection(error.to_string()))?
+        .success()
+        .map_err(|error| LdapError::Directory(format!("searching {} for {}: {}", base, filter, error)))?;
+    Ok(entries.into_iter().filter(|entry| !entry.is_ref()).map(SearchEntry::construct).collect())
+}
+
+/// Bind as the service account, or anonymously without one
+async fn bind_service(ldap: &mut Ldap, settings: &Settings) -> Result<(), LdapError> {
+    let (dn, password) = settings.service.as_ref().map_or(("", ""), |(dn, password)| (dn.as_str(), password.as_str()));
+    ldap.with_timeout(settings.timeout)
+        .simple_bind(dn, password)
+        .await
+        .map_err(|error| LdapError::Connection(error.to_string()))?
+        .success()
+        .map_err(|error| LdapError::ServiceAccount(error.to_string()))?;
+    Ok(())
+}
+
+struct Connection {
+    ldap: Ldap,
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Connections bound as the service account, at most `size` of them open
+struct Pool {
+    idle: Mutex<Vec<Ldap>>,
+    permits: Arc<Semaphore>,
+}
+
+impl Pool {
+    fn new(size: usize) -> Self {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            permits: Arc::new(Semaphore::new(size)),
+        }
+    }
+
+    async fn get(&self, settings: &Settings) -> Result<Connection, LdapError> {
+        let permit = self.permits.clone().acquire_owned().await.expect("the pool's semaphore is never closed");
+        loop {
+            let Some(mut ldap) = self.idle.lock().unwrap().pop() else {
+                break;
+            };
+            if !ldap.is_closed() {
+                return Ok(Connection { ldap, _permit: permit });
+            }
+        }
+
+        let connection_settings = LdapConnSettings::new().set_conn_timeout(settings.timeout).set_starttls(settings.start_tls);
+        let (driver, mut ldap) = LdapConnAsync::with_settings(connection_settings, &settings.url)
+            .await
+            .map_err(|error| LdapError::Connection(format!("{}: {}", settings.url, error)))?;
+        ldap3::drive!(driver);
+        bind_service(&mut ldap, settings).await?;
+        Ok(Connection { ldap, _permit: permit })
+    }
+
+    fn put(&self, connection: Connection) {
+        self.idle.lock().unwrap().push(connection.ldap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3::asn1::{parse_tag, StructureTag, TagClass, PL};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const SERVICE: (&str, &str) = ("cn=svc,dc=acme,dc=test", "svc-pass");
+    const ADA: (&str, &str) = ("uid=ada,ou=people,dc=acme,dc=test", "secret");
+
+    fn primitive(class: TagClass, id: u64, value: &[u8]) -> StructureTag {
+        StructureTag { class, id, payload: PL::P(value.to_vec()) }
+    }
+
+    fn constructed(class: TagClass, id: u64, inner: Vec<StructureTag>) -> StructureTag {
+        StructureTag { class, id, payload: PL::C(inner) }
+    }
+
+    fn string(value: &str) -> StructureTag {
+        primitive(TagClass::Universal, 4, value.as_bytes())
+    }
+
+    fn encode(tag: &StructureTag, out: &mut Vec<u8>) {
+        let class = match tag.class {
+            TagClass::Universal => 0x00,
+            TagClass::Application => 0x40,
+            TagClass::Context => 0x80,
+            TagClass::Private => 0xc0,
+        };
+        let mut content = Vec::new();
+        let form = match &tag.payload {
+            PL::P(value) => {
+                content.extend_from_slice(value);
+                0x00
+            }
+            PL::C(inner) => {
+                inner.iter().for_each(|tag| encode(tag, &mut content));
+                0x20
+            }
+        };
+        out.push(class | form | tag.id as u8);
+        if content.len() < 0x80 {
+            out.push(content.len() as u8);
+        } else {
+            out.extend_from_slice(&[0x82, (content.len() >> 8) as u8, content.len() as u8]);
+        }
+        out.extend_from_slice(&content);
+    }
+
+    fn message(id: &[u8], op: StructureTag) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode(&constructed(TagClass::Universal, 16, vec![primitive(TagClass::Universal, 2, id), op]), &mut out);
+        out
+    }
+
+    fn result(id: u64, code: u8) -> StructureTag {
+        constructed(
+            TagClass::Application,
+            id,
+            vec![primitive(TagClass::Universal, 10, &[code]), string(""), string("")],
+        )
+    }
+
+    fn values(tag: &StructureTag, found: &mut Vec<String>) {
+        match &tag.payload {
+            PL::P(value) => found.push(String::from_utf8_lossy(value).into_owned()),
+            PL::C(inner) => inner.iter().for_each(|tag| values(tag, found)),
+        }
+    }
+
+    /// Answers binds for the service account and Ada, and finds Ada's
+    /// entry for any search mentioning `ada`
+    async fn directory(mut stream: TcpStream) {
+        let mut buffer = Vec::new();
+        loop {
+            let (rest, tag) = match parse_tag(&buffer) {
+                Ok((rest, tag)) => (rest.len(), tag),
+                Err(_) => {
+                    let mut chunk = [0; 4096];
+                    match stream.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                    }
+                    continue;
+                }
+            };
+            buffer.drain(..buffer.len() - rest);
+
+            let mut parts = tag.expect_constructed().unwrap().into_iter();
+            let id = parts.next().unwrap().expect_primitive().unwrap();
+            let op = parts.next().unwrap();
+            let mut reply = Vec::new();
+            match op.id {
+                0 => {
+                    let mut fields = Vec::new();
+                    values(&op, &mut fields);
+                    let credentials = (fields[1].as_str(), fields[2].as_str());
+                    let code = if credentials == SERVICE || credentials == ADA { 0 } else { 49 };
+                    reply.extend(message(&id, result(1, code)));
+                }
+                2 => return,
+                3 => {
+                    let mut fields = Vec::new();
+                    values(&op, &mut fields);
+                    if fields.iter().any(|field| field == "ada") {
+                        let attribute = |name: &str, values: &[&str]| {
+                            constructed(
+                                TagClass::Universal,
+                                16,
+                                vec![string(name), constructed(TagClass::Universal, 17, values.iter().map(|value| string(value)).collect())],
+                            )
+                        };
+                        let entry = constructed(
+                            TagClass::Application,
+                            4,
+                            vec![
+                                string(ADA.0),
+                                constructed(
+                                    TagClass::Universal,
+                                    16,
+                                    vec![
+                                        attribute("mail", &["ada@acme.test"]),
+                                        attribute("cn", &["Ada Lovelace"]),
+                                        attribute("memberOf", &["cn=admins,ou=groups,dc=acme,dc=test", "cn=staff,ou=groups,dc=acme,dc=test"]),
+                                    ],
+                                ),
+                            ],
+                        );
+                        reply.extend(message(&id, entry));
+                    }
+                    reply.extend(message(&id, result(5, 0)));
+                }
+                _ => return,
+            }
+            if stream.write_all(&reply).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ldap_attempts_with_group_roles_and_pooling() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(directory(stream));
+            }
+        });
+
+        let ldap = LdapAuth::new(url, "dc=acme,dc=test")
+            .bind_as(SERVICE.0, SERVICE.1)
+            .map_group("admins", "admin")
+            .map_group("cn=staff,ou=groups,dc=acme,dc=test", "staff")
+            .map_group("auditors", "auditor");
+
+        let user = ldap.attempt("ada", "secret").await.unwrap().unwrap();
+        assert_eq!(user.dn, ADA.0);
+        assert_eq!(user.email.as_deref(), Some("ada@acme.test"));
+        assert_eq!(user.name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(user.roles, vec!["admin", "staff"]);
+        assert!(user.has_role("admin") && !user.has_role("auditor"));
+        assert!(user.in_group("staff") && user.in_group("CN=Admins,OU=Groups,DC=acme,DC=test"));
+
+        assert_eq!(ldap.attempt("ada", "wrong").await.unwrap(), None);
+        assert_eq!(ldap.attempt("grace", "secret").await.unwrap(), None);
+        assert_eq!(ldap.attempt("ada", "").await.unwrap(), None);
+        assert!(ldap.attempt("ada", "secret").await.unwrap().is_some());
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        let refused = LdapAuth::new(format!("ldap://{}", "127.0.0.1:1"), "dc=acme,dc=test");
+        assert!(matches!(refused.attempt("ada", "secret").await, Err(LdapError::Connection(_))));
+    }
+}
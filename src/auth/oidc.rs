@@ -0,0 +1,421 @@
+//! # OpenID Connect discovery
+//!
+//! Finds an issuer's signing keys through
+//! `{issuer}/.well-known/openid-configuration` and keeps them cached for
+//! [`JwtAuth`](super::jwt::JwtAuth):
+//!
+//! - Keys are fetched on the first token and kept for an hour.
+//! - A token signed with a key id that isn't cached triggers a refetch, so
+//!   key rotation at the provider needs no restart. Refetches happen at most
+//!   every 10 seconds however many unknown key ids arrive.
+//! - If the provider can't be reached the cached keys stay in use.
+//! - Tokens signed with a cached key never wait on a fetch; requests that
+//!   need the set while it is being fetched wait for that one fetch.
+//!
+//! Issuers look like `https://{tenant}.auth0.com/`,
+//! `https://{host}/realms/{realm}` for Keycloak or
+//! `https://login.microsoftonline.com/{tenant}/v2.0` for Entra ID.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose, Engine as _};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+
+use super::jwt::JwtError;
+use crate::http_client::BoxError;
+
+/// How long fetched keys are used before they are fetched again
+const KEYS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Least time between two fetches of the key set
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long to wait for the provider
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One key from a JWKS document
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub kid: Option<String>,
+    pub alg: Option<String>,
+    /// `sig` for signing keys
+    #[serde(rename = "use")]
+    pub key_use: Option<String>,
+    pub n: Option<String>,
+    pub e: Option<String>,
+    pub crv: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    jwks_uri: String,
+}
+
+fn decode(value: Option<&str>, kid: &str) -> Result<Vec<u8>, JwtError> {
+    let value = value.ok_or_else(|| JwtError::UnknownKey(format!("key {} is incomplete", kid)))?;
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| JwtError::UnknownKey(format!("key {} is not base64url", kid)))
+}
+
+impl Jwk {
+    /// Check `signature` over `message` for the token's `alg`
+    pub(crate) fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> Result<(), JwtError> {
+        let kid = self.kid.as_deref().unwrap_or("without id");
+        if self.alg.as_deref().is_some_and(|expected| expected != alg) {
+            return Err(JwtError::UnsupportedAlgorithm(format!("{} for key {}", alg, kid)));
+        }
+
+        let verified = match (self.kty.as_str(), alg) {
+            ("RSA", "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512") => {
+                let parameters = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                    "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                    "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                    _ => &signature::RSA_PSS_2048_8192_SHA512,
+                };
+                let key = RsaPublicKeyComponents {
+                    n: decode(self.n.as_deref(), kid)?,
+                    e: decode(self.e.as_deref(), kid)?,
+                };
+                key.verify(parameters, message, signature)
+            }
+            ("EC", "ES256" | "ES384") => {
+                let (curve, algorithm) = match alg {
+                    "ES256" => ("P-256", &signature::ECDSA_P256_SHA256_FIXED),
+                    _ => ("P-384", &signature::ECDSA_P384_SHA384_FIXED),
+                };
+                if self.crv.as_deref() != Some(curve) {
+                    return Err(JwtError::UnsupportedAlgorithm(format!("{} for key {}", alg, kid)));
+                }
+                // Uncompressed point: 0x04 || x || y
+                let mut point = vec![4];
+                point.extend(decode(self.x.as_deref(), kid)?);
+                point.extend(decode(self.y.as_deref(), kid)?);
+                UnparsedPublicKey::new(algorithm, point).verify(message, signature)
+            }
+            ("OKP", "EdDSA") if self.crv.as_deref() == Some("Ed25519") => {
+                UnparsedPublicKey::new(&signature::ED25519, decode(self.x.as_deref(), kid)?).verify(message, signature)
+            }
+            _ => return Err(JwtError::UnsupportedAlgorithm(format!("{} for {} key {}", alg, self.kty, kid))),
+        };
+        verified.map_err(|_| JwtError::InvalidSignature)
+    }
+}
+
+#[derive(Default)]
+struct KeyCache {
+    jwks_uri: Option<String>,
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+    attempted_at: Option<Instant>,
+    /// Last refetch for a key id that wasn't in a fresh set
+    missed_at: Option<Instant>,
+}
+
+/// GETs a URL, answering with the body of a successful response
+type Fetch = Arc<dyn Fn(String) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, BoxError>> + Send>> + Send + Sync>;
+
+/// An issuer's discovered key set
+pub(crate) struct OidcProvider {
+    issuer: String,
+    fetch: Fetch,
+    /// Never held across a fetch, so tokens whose key is cached don't wait
+    /// on a slow provider
+    cache: std::sync::Mutex<KeyCache>,
+    /// Held while fetching, so one request fetches and the others needing
+    /// the set wait for its answer
+    fetching: tokio::sync::Mutex<()>,
+}
+
+impl OidcProvider {
+    pub(crate) fn new(issuer: &str) -> Self {
+        Self {
+            issuer: issuer.to_string(),
+            fetch: Arc::new(|url| Box::pin(async move { get(&url).await })),
+            cache: std::sync::Mutex::new(KeyCache::default()),
+            fetching: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The key with id `kid`, fetching the key set when it isn't cached
+    pub(crate) async fn key(&self, kid: Option<&str>) -> Result<Jwk, JwtError> {
+        let seen = {
+            let cache = self.cache.lock().unwrap();
+            if cache.is_fresh() {
+                if let Some(key) = find_key(&cache.keys, kid) {
+                    return Ok(key.clone());
+                }
+            }
+            cache.fetched_at
+        };
+
+        let _fetching = self.fetching.lock().await;
+        let jwks_uri = {
+            let mut cache = self.cache.lock().unwrap();
+            // Another request fetched the set while this one waited
+            if cache.fetched_at != seen {
+                return find_key(&cache.keys, kid).cloned().ok_or_else(|| unknown_key(kid));
+            }
+
+            // A fresh set without the key means the provider may have
+            // rotated its keys; otherwise the set is missing or old
+            let fresh = cache.is_fresh();
+            let last = if fresh { cache.missed_at } else { cache.attempted_at };
+            if !last.map_or(true, |last| last.elapsed() >= MIN_REFRESH_INTERVAL) {
+                return find_key(&cache.keys, kid).cloned().ok_or_else(|| unknown_key(kid));
+            }
+            cache.attempted_at = Some(Instant::now());
+            if fresh {
+                cache.missed_at = cache.attempted_at;
+            }
+            cache.jwks_uri.clone()
+        };
+
+        let fetched = self.fetch_keys(jwks_uri).await;
+        let mut cache = self.cache.lock().unwrap();
+        match fetched {
+            Ok((jwks_uri, keys)) => {
+                cache.jwks_uri = Some(jwks_uri);
+                cache.keys = keys;
+                cache.fetched_at = Some(Instant::now());
+            }
+            Err(error) if cache.keys.is_empty() => return Err(error),
+            Err(error) => eprintln!("⚠️  Could not refresh signing keys of {}: {}", self.issuer, error),
+        }
+        find_key(&cache.keys, kid).cloned().ok_or_else(|| unknown_key(kid))
+    }
+
+    /// The key set and where it was found, discovering `jwks_uri` when it
+    /// isn't known yet
+    async fn fetch_keys(&self, jwks_uri: Option<String>) -> Result<(String, Vec<Jwk>), JwtError> {
+        let jwks_uri = match jwks_uri {
+            Some(uri) => uri,
+            None => {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer.trim_end_matches('/'));
+                let discovery: Discovery = self.get_json(&url).await?;
+                if discovery.issuer.trim_end_matches('/') != self.issuer.trim_end_matches('/') {
+                    return Err(JwtError::Discovery(format!(
+                        "{} describes issuer {}, expected {}",
+                        url, discovery.issuer, self.issuer
+                    )));
+                }
+                discovery.jwks_uri
+            }
+        };
+        let set: JwkSet = self.get_json(&jwks_uri).await?;
+        let keys = set.keys.into_iter().filter(|key| key.key_use.as_deref().map_or(true, |key_use| key_use == "sig")).collect();
+        Ok((jwks_uri, keys))
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, JwtError> {
+        let body = tokio::time::timeout(FETCH_TIMEOUT, (self.fetch)(url.to_string()))
+            .await
+            .map_err(|_| JwtError::Discovery(format!("{} timed out", url)))?
+            .map_err(|error| JwtError::Discovery(format!("{}: {}", url, error)))?;
+        serde_json::from_slice(&body).map_err(|error| JwtError::Discovery(format!("{}: {}", url, error)))
+    }
+}
+
+impl KeyCache {
+    fn is_fresh(&self) -> bool {
+        self.fetched_at.is_some_and(|fetched| fetched.elapsed() < KEYS_MAX_AGE)
+    }
+}
+
+fn unknown_key(kid: Option<&str>) -> JwtError {
+    JwtError::UnknownKey(kid.unwrap_or("without id").to_string())
+}
+
+/// With no `kid` in the token, a set holding a single key still matches
+fn find_key<'a>(keys: &'a [Jwk], kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.iter().find(|key| key.kid.as_deref() == Some(kid)),
+        None if keys.len() == 1 => keys.first(),
+        None => None,
+    }
+}
+
+/// GET `url` over HTTP/1.1, with TLS for `https://` URLs
+async fn get(url: &str) -> Result<Vec<u8>, BoxError> {
+    let request = http::Request::get(url).header(http::header::ACCEPT, "application/json");
+    let (status, body) = crate::http_client::send(url, request, Vec::new()).await?;
+    if !status.is_success() {
+        return Err(format!("answered {}", status).into());
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::jwt::JwtAuth;
+    use crate::clock::unix_now;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    const ISSUER: &str = "https://idp.example.com/";
+    const JWKS_URI: &str = "https://idp.example.com/keys";
+
+    fn b64(bytes: &[u8]) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    struct SigningKey {
+        kid: &'static str,
+        pair: EcdsaKeyPair,
+    }
+
+    impl SigningKey {
+        fn generate(kid: &'static str) -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+            Self { kid, pair }
+        }
+
+        fn jwk(&self) -> serde_json::Value {
+            let point = self.pair.public_key().as_ref();
+            json!({"kty": "EC", "crv": "P-256", "kid": self.kid, "use": "sig", "alg": "ES256", "x": b64(&point[1..33]), "y": b64(&point[33..65])})
+        }
+
+        fn sign(&self, claims: serde_json::Value) -> String {
+            let header = json!({"alg": "ES256", "typ": "JWT", "kid": self.kid});
+            let message = format!("{}.{}", b64(header.to_string().as_bytes()), b64(claims.to_string().as_bytes()));
+            let signature = self.pair.sign(&SystemRandom::new(), message.as_bytes()).unwrap();
+            format!("{}.{}", message, b64(signature.as_ref()))
+        }
+    }
+
+    /// Stands in for the provider's discovery document and key set
+    struct Stub {
+        issuer: &'static str,
+        keys: Mutex<Vec<serde_json::Value>>,
+        fetches: AtomicUsize,
+    }
+
+    impl Stub {
+        fn new(issuer: &'static str, keys: Vec<serde_json::Value>) -> Arc<Self> {
+            Arc::new(Self { issuer, keys: Mutex::new(keys), fetches: AtomicUsize::new(0) })
+        }
+
+        fn fetches(&self) -> usize {
+            self.fetches.load(Ordering::SeqCst)
+        }
+
+        fn provider(self: &Arc<Self>) -> OidcProvider {
+            let stub = self.clone();
+            let fetch: Fetch = Arc::new(move |url| {
+                let stub = stub.clone();
+                Box::pin(async move {
+                    let body = match url.as_str() {
+                        "https://idp.example.com/.well-known/openid-configuration" => {
+                            json!({"issuer": stub.issuer, "jwks_uri": JWKS_URI})
+                        }
+                        JWKS_URI => {
+                            stub.fetches.fetch_add(1, Ordering::SeqCst);
+                            json!({"keys": *stub.keys.lock().unwrap()})
+                        }
+                        _ => return Err(format!("{} answered 404 Not Found", url).into()),
+                    };
+                    Ok(body.to_string().into_bytes())
+                })
+            });
+            OidcProvider { fetch, ..OidcProvider::new(ISSUER) }
+        }
+    }
+
+    fn kid(key: &Jwk) -> &str {
+        key.kid.as_deref().unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_a_rotated_key_is_fetched_once() {
+        let stub = Stub::new(ISSUER, vec![SigningKey::generate("k1").jwk()]);
+        let provider = stub.provider();
+
+        assert_eq!(kid(&provider.key(Some("k1")).await.unwrap()), "k1");
+        assert_eq!(kid(&provider.key(None).await.unwrap()), "k1");
+        assert_eq!(stub.fetches(), 1);
+
+        // The provider starts signing with a new key: requests carrying it
+        // at the same time share one refetch
+        stub.keys.lock().unwrap().push(SigningKey::generate("k2").jwk());
+        let (first, second) = tokio::join!(provider.key(Some("k2")), provider.key(Some("k2")));
+        assert_eq!((kid(&first.unwrap()), kid(&second.unwrap())), ("k2", "k2"));
+        assert_eq!(stub.fetches(), 2);
+        assert_eq!(kid(&provider.key(Some("k1")).await.unwrap()), "k1");
+        assert_eq!(kid(&provider.key(Some("k2")).await.unwrap()), "k2");
+        assert_eq!(stub.fetches(), 2);
+
+        // With two keys a token has to say which one signed it
+        assert_eq!(provider.key(None).await.unwrap_err(), JwtError::UnknownKey("without id".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_ids_are_refused_without_refetching_within_the_throttle_window() {
+        let stub = Stub::new(ISSUER, vec![SigningKey::generate("k1").jwk()]);
+        let provider = stub.provider();
+        provider.key(Some("k1")).await.unwrap();
+
+        assert_eq!(provider.key(Some("forged")).await.unwrap_err(), JwtError::UnknownKey("forged".to_string()));
+        assert_eq!(stub.fetches(), 2);
+
+        // A flood of made-up key ids doesn't turn into a flood of fetches,
+        // even once the provider has published one of them
+        stub.keys.lock().unwrap().push(SigningKey::generate("k2").jwk());
+        for forged in ["forged", "other", "k2"] {
+            assert_eq!(provider.key(Some(forged)).await.unwrap_err(), JwtError::UnknownKey(forged.to_string()));
+        }
+        assert_eq!(provider.key(Some("k1")).await.map(|key| key.kid), Ok(Some("k1".to_string())));
+        assert_eq!(stub.fetches(), 2);
+
+        provider.cache.lock().unwrap().missed_at = Some(Instant::now() - MIN_REFRESH_INTERVAL);
+        assert_eq!(kid(&provider.key(Some("k2")).await.unwrap()), "k2");
+        assert_eq!(stub.fetches(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_issuer_and_audience_mismatches_are_refused() {
+        // Discovery describing another issuer is never trusted for keys
+        let impostor = Stub::new("https://evil.example.com/", vec![SigningKey::generate("k1").jwk()]);
+        let error = impostor.provider().key(Some("k1")).await.unwrap_err();
+        assert!(matches!(&error, JwtError::Discovery(message) if message.contains("describes issuer https://evil.example.com/")), "{}", error);
+        assert_eq!(impostor.fetches(), 0);
+
+        let key = SigningKey::generate("k1");
+        let auth = JwtAuth::with_provider(Stub::new(ISSUER, vec![key.jwk()]).provider()).audience("api");
+        let exp = unix_now() + 300;
+        let token = |iss: &str, aud: serde_json::Value| key.sign(json!({"iss": iss, "aud": aud, "sub": "user-1", "exp": exp}));
+
+        assert_eq!(auth.verify(&token("https://idp.example.com", json!("api"))).await.unwrap().id, "user-1");
+        assert_eq!(
+            auth.verify(&token("https://evil.example.com/", json!("api"))).await,
+            Err(JwtError::InvalidIssuer("https://evil.example.com/".to_string()))
+        );
+        assert_eq!(auth.verify(&token(ISSUER, json!("billing"))).await, Err(JwtError::InvalidAudience));
+        assert_eq!(auth.verify(&token(ISSUER, json!(["billing", "reports"]))).await, Err(JwtError::InvalidAudience));
+    }
+}
@@ -0,0 +1,1172 @@
+//! # SAML single sign-on
+//!
+//! A SAML 2.0 service provider, for customers whose users sign in through
+//! Okta, Entra ID, ADFS, Google Workspace or another identity provider:
+//!
+//! ```rust,no_run
+//! use torch_web::auth::saml::{IdentityProvider, ServiceProvider, SamlUser};
+//! use torch_web::{App, Response};
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let idp = IdentityProvider::from_metadata(&std::fs::read_to_string("storage/saml/okta.xml")?)?;
+//! let sp = ServiceProvider::new("https://app.acme.com/saml", "https://app.acme.com/saml/acs", idp)
+//!     .map_attribute("email", "email")
+//!     .map_attribute("http://schemas.microsoft.com/identity/claims/displayname", "name")
+//!     .map_attribute_list("groups", "roles");
+//!
+//! let app = App::new()
+//!     .get("/saml/metadata", sp.metadata_handler())
+//!     .get("/saml/login", sp.login_handler())
+//!     .post("/saml/acs", sp.acs_handler(|user: SamlUser, _relay_state| async move {
+//!         // Find or create the account for `user.name_id`, then start a session
+//!         Response::redirect_found("/dashboard")
+//!     }));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The identity provider is set up from the SP metadata served at
+//! `/saml/metadata`. Sign-in starts at `/saml/login?next=/path` (the
+//! `next` value comes back as the relay state) or from the identity
+//! provider's dashboard.
+//!
+//! Responses posted to the assertion consumer service are accepted when:
+//!
+//! - the response or its assertion carries an XML signature made with a
+//!   certificate from the identity provider's metadata (RSA with SHA-256 or
+//!   SHA-512, exclusive canonicalization); certificates inside the message
+//!   are ignored
+//! - the issuer is the identity provider, an `AudienceRestriction` names
+//!   this service provider and the destination and recipient are the ACS
+//!   URL
+//! - the bearer confirmation has a `Recipient` and a `NotOnOrAfter`, as the
+//!   Web SSO profile requires
+//! - `NotBefore` and `NotOnOrAfter` hold, with a minute of clock skew
+//! - a reply names a request this service provider made, and each assertion
+//!   is used only once
+//!
+//! Encrypted assertions are not supported; ask the identity provider to
+//! sign instead. Issued requests and used assertion ids live in process
+//! memory unless [`ServiceProvider::store`] is given a shared cache.
+
+mod xml;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::signature::{self, UnparsedPublicKey};
+use serde::de::DeserializeOwned;
+
+use self::xml::Element;
+use crate::cache::{Cache, MemoryCache};
+use crate::{Request, Response};
+
+const NS_PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const NS_ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const NS_METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const NS_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const NS_EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+
+const BINDING_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const BINDING_REDIRECT: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+/// `NameIDFormat` asked for unless [`ServiceProvider::name_id_format`]
+/// picks another
+pub const NAME_ID_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified";
+
+/// How long an issued request can be answered
+const REQUEST_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Largest `SAMLResponse` accepted, before base64 decoding
+const MAX_RESPONSE_SIZE: usize = 512 * 1024;
+
+/// Why a SAML message was refused
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum SamlError {
+    #[error("malformed SAML message: {0}")]
+    Malformed(String),
+
+    #[error("unsupported SAML feature: {0}")]
+    Unsupported(String),
+
+    #[error("identity provider metadata: {0}")]
+    Metadata(String),
+
+    #[error("sign-in failed at the identity provider: {0}")]
+    Status(String),
+
+    #[error("neither the response nor the assertion is signed")]
+    Unsigned,
+
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("assertion issued by {0}")]
+    InvalidIssuer(String),
+
+    #[error("assertion is not meant for this service provider")]
+    InvalidAudience,
+
+    #[error("response was sent to {0}")]
+    InvalidDestination(String),
+
+    #[error("assertion has expired")]
+    Expired,
+
+    #[error("assertion is not valid yet")]
+    NotYetValid,
+
+    #[error("response answers a request this service provider didn't make")]
+    UnknownRequest,
+
+    #[error("assertion has already been used")]
+    Replayed,
+
+    #[error("replay store unavailable: {0}")]
+    Store(String),
+}
+
+/// How sign-in requests reach the identity provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SsoBinding {
+    /// A redirect with the deflated request in the query string
+    Redirect,
+    /// A form posted from the browser
+    Post,
+}
+
+/// The identity provider a [`ServiceProvider`] trusts
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentityProvider {
+    pub entity_id: String,
+    /// Where sign-in requests are sent
+    pub sso_url: String,
+    pub binding: SsoBinding,
+    /// DER certificates the identity provider signs with; more than one
+    /// during a certificate rollover
+    pub certificates: Vec<Vec<u8>>,
+}
+
+impl IdentityProvider {
+    /// An identity provider set up by hand. `certificate` is PEM or the
+    /// bare base64 most admin consoles show.
+    pub fn new(entity_id: impl Into<String>, sso_url: impl Into<String>, certificate: &str) -> Result<Self, SamlError> {
+        Ok(Self {
+            entity_id: entity_id.into(),
+            sso_url: sso_url.into(),
+            binding: SsoBinding::Redirect,
+            certificates: vec![decode_certificate(certificate)?],
+        })
+    }
+
+    /// Read the identity provider's metadata XML, as downloaded from its
+    /// admin console or metadata URL
+    pub fn from_metadata(metadata: &str) -> Result<Self, SamlError> {
+        let root = xml::parse(metadata).map_err(SamlError::Metadata)?;
+        let mut descriptors = Vec::new();
+        root.descendants(NS_METADATA, "EntityDescriptor", &mut descriptors);
+        let (entity, idp) = descriptors
+            .into_iter()
+            .find_map(|entity| Some((entity, entity.child(NS_METADATA, "IDPSSODescriptor")?)))
+            .ok_or_else(|| SamlError::Metadata("no IDPSSODescriptor".to_string()))?;
+        let entity_id = entity
+            .attribute("entityID")
+            .ok_or_else(|| SamlError::Metadata("EntityDescriptor has no entityID".to_string()))?;
+
+        let services: Vec<&Element> = idp.children_named(NS_METADATA, "SingleSignOnService").collect();
+        let service = |binding: &str| {
+            services.iter().find(|service| service.attribute("Binding") == Some(binding)).and_then(|service| service.attribute("Location"))
+        };
+        let (sso_url, binding) = match (service(BINDING_REDIRECT), service(BINDING_POST)) {
+            (Some(location), _) => (location, SsoBinding::Redirect),
+            (None, Some(location)) => (location, SsoBinding::Post),
+            (None, None) => return Err(SamlError::Metadata("no HTTP-Redirect or HTTP-POST SingleSignOnService".to_string())),
+        };
+
+        let mut certificates = Vec::new();
+        for key in idp.children_named(NS_METADATA, "KeyDescriptor") {
+            if key.attribute("use").is_some_and(|key_use| key_use != "signing") {
+                continue;
+            }
+            let mut found = Vec::new();
+            key.descendants(NS_DSIG, "X509Certificate", &mut found);
+            for certificate in found {
+                certificates.push(decode_certificate(&certificate.text()).map_err(|error| SamlError::Metadata(error.to_string()))?);
+            }
+        }
+        if certificates.is_empty() {
+            return Err(SamlError::Metadata("no signing certificate".to_string()));
+        }
+
+        Ok(Self { entity_id: entity_id.to_string(), sso_url: sso_url.to_string(), binding, certificates })
+    }
+}
+
+fn decode_certificate(certificate: &str) -> Result<Vec<u8>, SamlError> {
+    let base64: String = certificate
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.chars())
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let der = general_purpose::STANDARD
+        .decode(base64)
+        .map_err(|_| SamlError::Metadata("certificate is not base64".to_string()))?;
+    rsa_public_key(&der)?;
+    Ok(der)
+}
+
+/// Read one DER element, returning its tag, contents and what follows it
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let length = rest[..count].iter().fold(0usize, |length, &byte| (length << 8) | byte as usize);
+        (length, &rest[count..])
+    };
+    (rest.len() >= length).then(|| (tag, &rest[..length], &rest[length..]))
+}
+
+/// The `RSAPublicKey` inside an X.509 certificate
+fn rsa_public_key(certificate: &[u8]) -> Result<&[u8], SamlError> {
+    const RSA_ENCRYPTION: &[u8] = &[0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+    let invalid = || SamlError::Metadata("certificate is not an X.509 certificate with an RSA key".to_string());
+
+    let (_, certificate, _) = der(certificate).filter(|(tag, _, _)| *tag == 0x30).ok_or_else(invalid)?;
+    let (_, mut tbs, _) = der(certificate).filter(|(tag, _, _)| *tag == 0x30).ok_or_else(invalid)?;
+    // version (optional), serialNumber, signature, issuer, validity, subject
+    let mut skipped = 0;
+    while skipped < 5 {
+        let (tag, _, rest) = der(tbs).ok_or_else(invalid)?;
+        if tag != 0xa0 {
+            skipped += 1;
+        }
+        tbs = rest;
+    }
+    let (_, public_key_info, _) = der(tbs).filter(|(tag, _, _)| *tag == 0x30).ok_or_else(invalid)?;
+    let (_, algorithm, rest) = der(public_key_info).ok_or_else(invalid)?;
+    if !algorithm.starts_with(RSA_ENCRYPTION) {
+        return Err(invalid());
+    }
+    match der(rest) {
+        Some((0x03, [0, key @ ..], _)) => Ok(key),
+        _ => Err(invalid()),
+    }
+}
+
+/// The user an identity provider signed in
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamlUser {
+    /// The subject's `NameID`, usually an email address or a stable id
+    pub name_id: String,
+    pub name_id_format: Option<String>,
+    /// Needed for single logout
+    pub session_index: Option<String>,
+    /// Every attribute by `Name`, and by `FriendlyName` where one is given
+    pub attributes: HashMap<String, Vec<String>>,
+    /// `id` (the name id) and the attributes picked with
+    /// [`ServiceProvider::map_attribute`], read with [`SamlUser::map`]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl SamlUser {
+    /// An attribute's first value
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).and_then(|values| values.first()).map(String::as_str)
+    }
+
+    /// Every value of an attribute
+    pub fn attribute_values(&self, name: &str) -> &[String] {
+        self.attributes.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The mapped [`fields`](SamlUser::fields) as the app's own type
+    pub fn map<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(serde_json::Value::Object(self.fields.clone()))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AttributeMapping {
+    attribute: String,
+    field: String,
+    list: bool,
+}
+
+/// This app as a SAML service provider
+#[derive(Clone)]
+pub struct ServiceProvider {
+    entity_id: String,
+    acs_url: String,
+    idp: Arc<IdentityProvider>,
+    name_id_format: String,
+    mappings: Vec<AttributeMapping>,
+    clock_skew: Duration,
+    idp_initiated: bool,
+    store: Arc<dyn Cache>,
+    prefix: String,
+}
+
+impl ServiceProvider {
+    /// `entity_id` names this app to the identity provider, and `acs_url`
+    /// is the absolute URL the [`acs_handler`](Self::acs_handler) is
+    /// routed at
+    pub fn new(entity_id: impl Into<String>, acs_url: impl Into<String>, idp: IdentityProvider) -> Self {
+        Self {
+            entity_id: entity_id.into(),
+            acs_url: acs_url.into(),
+            idp: Arc::new(idp),
+            name_id_format: NAME_ID_UNSPECIFIED.to_string(),
+            mappings: Vec::new(),
+            clock_skew: Duration::from_secs(60),
+            idp_initiated: true,
+            store: Arc::new(MemoryCache::new(None)),
+            prefix: "saml:".to_string(),
+        }
+    }
+
+    /// Ask for a `NameID` format, such as
+    /// `urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress`
+    pub fn name_id_format(mut self, format: impl Into<String>) -> Self {
+        self.name_id_format = format.into();
+        self
+    }
+
+    /// Copy the first value of `attribute` (its `Name` or `FriendlyName`)
+    /// into `field` of [`SamlUser::fields`]
+    pub fn map_attribute(mut self, attribute: impl Into<String>, field: impl Into<String>) -> Self {
+        self.mappings.push(AttributeMapping { attribute: attribute.into(), field: field.into(), list: false });
+        self
+    }
+
+    /// Copy every value of `attribute` into `field` as a list, for groups
+    /// and roles
+    pub fn map_attribute_list(mut self, attribute: impl Into<String>, field: impl Into<String>) -> Self {
+        self.mappings.push(AttributeMapping { attribute: attribute.into(), field: field.into(), list: true });
+        self
+    }
+
+    /// Clock difference allowed with the identity provider (default a minute)
+    pub fn clock_skew(mut self, skew: Duration) -> Self {
+        self.clock_skew = skew;
+        self
+    }
+
+    /// Accept responses that don't answer a request of ours, which is how
+    /// sign-in from the identity provider's dashboard arrives (default on)
+    pub fn allow_idp_initiated(mut self, allow: bool) -> Self {
+        self.idp_initiated = allow;
+        self
+    }
+
+    /// Keep issued request ids and used assertion ids in `store`, so every
+    /// instance of the app shares them. Sign-ins are refused while the store
+    /// is unavailable.
+    pub fn store(mut self, store: Arc<dyn Cache>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Prefix for keys in the store (default `saml:`)
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// SP metadata to give the identity provider
+    pub fn metadata(&self) -> String {
+        format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<md:EntityDescriptor xmlns:md=\"{}\" entityID=\"{}\">\n",
+                "  <md:SPSSODescriptor AuthnRequestsSigned=\"false\" WantAssertionsSigned=\"true\" protocolSupportEnumeration=\"{}\">\n",
+                "    <md:NameIDFormat>{}</md:NameIDFormat>\n",
+                "    <md:AssertionConsumerService Binding=\"{}\" Location=\"{}\" index=\"0\" isDefault=\"true\"/>\n",
+                "  </md:SPSSODescriptor>\n",
+                "</md:EntityDescriptor>\n"
+            ),
+            NS_METADATA,
+            xml::escape(&self.entity_id),
+            NS_PROTOCOL,
+            xml::escape(&self.name_id_format),
+            BINDING_POST,
+            xml::escape(&self.acs_url),
+        )
+    }
+
+    /// Route handler serving [`metadata`](Self::metadata)
+    pub fn metadata_handler(
+        &self,
+    ) -> impl Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Clone + Send + Sync + 'static {
+        let metadata = Arc::new(self.metadata());
+        move |_req: Request| {
+            let metadata = metadata.clone();
+            Box::pin(async move {
+                Response::ok().header("content-type", "application/samlmetadata+xml").body(metadata.as_bytes().to_vec())
+            })
+        }
+    }
+
+    /// Start sign-in: a redirect to the identity provider, or a page that
+    /// posts the request there. `relay_state` comes back to the ACS handler.
+    pub async fn login(&self, relay_state: Option<&str>) -> Response {
+        let id = format!("_{}", uuid::Uuid::new_v4().simple());
+        let key = format!("{}request:{}", self.prefix, id);
+        if let Err(error) = self.store.set(&key, "1", Some(REQUEST_TTL)).await.map_err(|e| e.to_string()) {
+            eprintln!("⚠️  Could not record SAML request {}: {}", id, error);
+        }
+
+        let request = format!(
+            concat!(
+                "<samlp:AuthnRequest xmlns:samlp=\"{}\" xmlns:saml=\"{}\" ID=\"{}\" Version=\"2.0\" IssueInstant=\"{}\" ",
+                "Destination=\"{}\" AssertionConsumerServiceURL=\"{}\" ProtocolBinding=\"{}\">",
+                "<saml:Issuer>{}</saml:Issuer>",
+                "<samlp:NameIDPolicy Format=\"{}\" AllowCreate=\"true\"/>",
+                "</samlp:AuthnRequest>"
+            ),
+            NS_PROTOCOL,
+            NS_ASSERTION,
+            id,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            xml::escape(&self.idp.sso_url),
+            xml::escape(&self.acs_url),
+            BINDING_POST,
+            xml::escape(&self.entity_id),
+            xml::escape(&self.name_id_format),
+        );
+
+        match self.idp.binding {
+            SsoBinding::Redirect => {
+                let deflated = miniz_oxide::deflate::compress_to_vec(request.as_bytes(), 6);
+                let mut url = format!(
+                    "{}{}SAMLRequest={}",
+                    self.idp.sso_url,
+                    if self.idp.sso_url.contains('?') { '&' } else { '?' },
+                    urlencoding::encode(&general_purpose::STANDARD.encode(deflated))
+                );
+                if let Some(relay_state) = relay_state {
+                    url.push_str("&RelayState=");
+                    url.push_str(&urlencoding::encode(relay_state));
+                }
+                Response::redirect_found(&url)
+            }
+            SsoBinding::Post => {
+                let relay_state = relay_state
+                    .map(|relay_state| format!("<input type=\"hidden\" name=\"RelayState\" value=\"{}\">", xml::escape(relay_state)))
+                    .unwrap_or_default();
+                Response::ok().html(format!(
+                    concat!(
+                        "<!DOCTYPE html><html><body onload=\"document.forms[0].submit()\">",
+                        "<form method=\"post\" action=\"{}\">",
+                        "<input type=\"hidden\" name=\"SAMLRequest\" value=\"{}\">{}",
+                        "<noscript><button type=\"submit\">Continue</button></noscript>",
+                        "</form></body></html>"
+                    ),
+                    xml::escape(&self.idp.sso_url),
+                    general_purpose::STANDARD.encode(request),
+                    relay_state,
+                ))
+            }
+        }
+    }
+
+    /// Route handler for [`login`](Self::login), passing `?next=` on as the
+    /// relay state
+    pub fn login_handler(
+        &self,
+    ) -> impl Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Clone + Send + Sync + 'static {
+        let sp = self.clone();
+        move |req: Request| {
+            let sp = sp.clone();
+            Box::pin(async move { sp.login(req.query("next")).await })
+        }
+    }
+
+    /// Route handler for the assertion consumer service: checks the posted
+    /// `SAMLResponse` and hands the user and relay state to `handle`, which
+    /// signs them in. Refused responses get a plain `403`; the reason is
+    /// logged.
+    ///
+    /// The relay state is whatever the browser sent; check it is a local
+    /// path before redirecting to it.
+    pub fn acs_handler<F, Fut>(
+        &self,
+        handle: F,
+    ) -> impl Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Clone + Send + Sync + 'static
+    where
+        F: Fn(SamlUser, Option<String>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        let sp = self.clone();
+        move |req: Request| {
+            let sp = sp.clone();
+            let handle = handle.clone();
+            Box::pin(async move {
+                let form = form_fields(req.body());
+                let Some(saml_response) = form.get("SAMLResponse") else {
+                    return Response::bad_request().body("Missing SAMLResponse");
+                };
+                match sp.validate(saml_response).await {
+                    Ok(user) => handle(user, form.get("RelayState").cloned()).await,
+                    // The reason is for the logs, not the browser
+                    Err(error) => {
+                        eprintln!("⚠️  SAML response refused: {}", error);
+                        Response::forbidden().body("Sign-in refused")
+                    }
+                }
+            })
+        }
+    }
+
+    /// Check a base64 `SAMLResponse` and read the user from it
+    pub async fn validate(&self, saml_response: &str) -> Result<SamlUser, SamlError> {
+        let verified = self.verify(saml_response, Utc::now())?;
+
+        match &verified.in_response_to {
+            Some(request) => {
+                let key = format!("{}request:{}", self.prefix, request);
+                if !matches!(self.store.delete(&key).await.map_err(|e| e.to_string()), Ok(true)) {
+                    return Err(SamlError::UnknownRequest);
+                }
+            }
+            None if !self.idp_initiated => return Err(SamlError::UnknownRequest),
+            None => {}
+        }
+
+        let key = format!("{}assertion:{}", self.prefix, verified.assertion_id);
+        let ttl = (verified.expires - Utc::now()).to_std().unwrap_or_default() + self.clock_skew;
+        match self.store.add(&key, "1", Some(ttl)).await.map_err(|e| e.to_string()) {
+            Ok(true) => {}
+            Ok(false) => return Err(SamlError::Replayed),
+            // Without the store a replay can't be told apart, so refuse
+            Err(error) => return Err(SamlError::Store(error)),
+        }
+        Ok(verified.user)
+    }
+
+    /// Every check that doesn't need the store
+    fn verify(&self, saml_response: &str, now: DateTime<Utc>) -> Result<Verified, SamlError> {
+        if saml_response.len() > MAX_RESPONSE_SIZE {
+            return Err(SamlError::Malformed("response is too large".to_string()));
+        }
+        let compact: String = saml_response.chars().filter(|c| !c.is_whitespace()).collect();
+        let decoded = general_purpose::STANDARD
+            .decode(compact)
+            .map_err(|_| SamlError::Malformed("SAMLResponse is not base64".to_string()))?;
+        let document = String::from_utf8(decoded).map_err(|_| SamlError::Malformed("response is not UTF-8".to_string()))?;
+        let response = xml::parse(&document).map_err(SamlError::Malformed)?;
+        if !response.is(NS_PROTOCOL, "Response") {
+            return Err(SamlError::Malformed(format!("expected a Response, got {}", response.name)));
+        }
+
+        if let Some(destination) = response.attribute("Destination") {
+            if destination != self.acs_url {
+                return Err(SamlError::InvalidDestination(destination.to_string()));
+            }
+        }
+        let status = response
+            .child(NS_PROTOCOL, "Status")
+            .and_then(|status| status.child(NS_PROTOCOL, "StatusCode"))
+            .and_then(|code| code.attribute("Value"));
+        if status != Some(STATUS_SUCCESS) {
+            let message = response
+                .child(NS_PROTOCOL, "Status")
+                .and_then(|status| status.child(NS_PROTOCOL, "StatusMessage"))
+                .map(|message| message.text());
+            return Err(SamlError::Status(message.or(status.map(str::to_string)).unwrap_or_else(|| "no status".to_string())));
+        }
+
+        let assertions: Vec<&Element> = response.children_named(NS_ASSERTION, "Assertion").collect();
+        let assertion = match assertions.as_slice() {
+            [assertion] => *assertion,
+            [] if response.child(NS_ASSERTION, "EncryptedAssertion").is_some() => {
+                return Err(SamlError::Unsupported("encrypted assertions".to_string()))
+            }
+            [] => return Err(SamlError::Malformed("no assertion".to_string())),
+            _ => return Err(SamlError::Malformed("more than one assertion".to_string())),
+        };
+        let response_signed = self.verify_signature(&response)?;
+        let assertion_signed = self.verify_signature(assertion)?;
+        if !response_signed && !assertion_signed {
+            return Err(SamlError::Unsigned);
+        }
+
+        for issuer in [response.child(NS_ASSERTION, "Issuer"), assertion.child(NS_ASSERTION, "Issuer")].into_iter().flatten() {
+            let issuer = issuer.text();
+            if issuer.trim() != self.idp.entity_id {
+                return Err(SamlError::InvalidIssuer(issuer.trim().to_string()));
+            }
+        }
+        if assertion.child(NS_ASSERTION, "Issuer").is_none() {
+            return Err(SamlError::Malformed("assertion has no issuer".to_string()));
+        }
+
+        // The Web SSO profile requires an AudienceRestriction naming this
+        // SP, else the assertion would be accepted by every SP trusting the
+        // IdP
+        let skew = chrono::Duration::from_std(self.clock_skew).unwrap_or_default();
+        let conditions = assertion.child(NS_ASSERTION, "Conditions").ok_or(SamlError::InvalidAudience)?;
+        let mut expires = None;
+        if let Some(not_before) = conditions.attribute("NotBefore") {
+            if now + skew < instant(not_before)? {
+                return Err(SamlError::NotYetValid);
+            }
+        }
+        if let Some(not_on_or_after) = conditions.attribute("NotOnOrAfter") {
+            let not_on_or_after = instant(not_on_or_after)?;
+            if now - skew >= not_on_or_after {
+                return Err(SamlError::Expired);
+            }
+            expires = Some(not_on_or_after);
+        }
+        let mut restrictions = conditions.children_named(NS_ASSERTION, "AudienceRestriction").peekable();
+        if restrictions.peek().is_none() {
+            return Err(SamlError::InvalidAudience);
+        }
+        for restriction in restrictions {
+            if !restriction.children_named(NS_ASSERTION, "Audience").any(|audience| audience.text().trim() == self.entity_id) {
+                return Err(SamlError::InvalidAudience);
+            }
+        }
+
+        let subject = assertion
+            .child(NS_ASSERTION, "Subject")
+            .ok_or_else(|| SamlError::Malformed("assertion has no subject".to_string()))?;
+        let name_id = subject
+            .child(NS_ASSERTION, "NameID")
+            .ok_or_else(|| SamlError::Unsupported("subjects without a plain NameID".to_string()))?;
+        let mut in_response_to = response.attribute("InResponseTo").map(str::to_string);
+        let mut bearer = false;
+        for confirmation in subject.children_named(NS_ASSERTION, "SubjectConfirmation") {
+            if confirmation.attribute("Method") != Some("urn:oasis:names:tc:SAML:2.0:cm:bearer") {
+                continue;
+            }
+            bearer = true;
+            // Bearer confirmations must say where they may be presented and
+            // until when, which also bounds how long the ID is kept for
+            // replay detection
+            let missing = |what: &str| SamlError::Malformed(format!("bearer confirmation has no {}", what));
+            let data = confirmation
+                .child(NS_ASSERTION, "SubjectConfirmationData")
+                .ok_or_else(|| missing("SubjectConfirmationData"))?;
+            let recipient = data.attribute("Recipient").ok_or_else(|| missing("Recipient"))?;
+            if recipient != self.acs_url {
+                return Err(SamlError::InvalidDestination(recipient.to_string()));
+            }
+            let not_on_or_after = instant(data.attribute("NotOnOrAfter").ok_or_else(|| missing("NotOnOrAfter"))?)?;
+            if now - skew >= not_on_or_after {
+                return Err(SamlError::Expired);
+            }
+            expires = Some(expires.map_or(not_on_or_after, |expires: DateTime<Utc>| expires.min(not_on_or_after)));
+            if let Some(request) = data.attribute("InResponseTo") {
+                if in_response_to.as_deref().is_some_and(|existing| existing != request) {
+                    return Err(SamlError::UnknownRequest);
+                }
+                in_response_to = Some(request.to_string());
+            }
+        }
+        let (true, Some(expires)) = (bearer, expires) else {
+            return Err(SamlError::Unsupported("subjects without bearer confirmation".to_string()));
+        };
+
+        let mut attributes: HashMap<String, Vec<String>> = HashMap::new();
+        for statement in assertion.children_named(NS_ASSERTION, "AttributeStatement") {
+            for attribute in statement.children_named(NS_ASSERTION, "Attribute") {
+                let values: Vec<String> = attribute.children_named(NS_ASSERTION, "AttributeValue").map(|value| value.text()).collect();
+                for name in [attribute.attribute("Name"), attribute.attribute("FriendlyName")].into_iter().flatten() {
+                    attributes.entry(name.to_string()).or_default().extend(values.iter().cloned());
+                }
+            }
+        }
+
+        let name_id_text = name_id.text().trim().to_string();
+        let mut fields = serde_json::Map::new();
+        fields.insert("id".to_string(), serde_json::Value::String(name_id_text.clone()));
+        for mapping in &self.mappings {
+            let values = attributes.get(&mapping.attribute).map_or(&[][..], Vec::as_slice);
+            let value = if mapping.list {
+                serde_json::Value::Array(values.iter().cloned().map(serde_json::Value::String).collect())
+            } else {
+                match values.first() {
+                    Some(value) => serde_json::Value::String(value.clone()),
+                    None => continue,
+                }
+            };
+            fields.insert(mapping.field.clone(), value);
+        }
+
+        let assertion_id = assertion
+            .attribute("ID")
+            .ok_or_else(|| SamlError::Malformed("assertion has no ID".to_string()))?
+            .to_string();
+        let session_index = assertion
+            .child(NS_ASSERTION, "AuthnStatement")
+            .and_then(|statement| statement.attribute("SessionIndex"))
+            .map(str::to_string);
+
+        Ok(Verified {
+            user: SamlUser {
+                name_id: name_id_text,
+                name_id_format: name_id.attribute("Format").map(str::to_string),
+                session_index,
+                attributes,
+                fields,
+            },
+            assertion_id,
+            in_response_to,
+            expires,
+        })
+    }
+
+    /// Check the enveloped signature of `signed`, if it has one
+    fn verify_signature(&self, signed: &Element) -> Result<bool, SamlError> {
+        let Some(signature) = signed.child(NS_DSIG, "Signature") else {
+            return Ok(false);
+        };
+        let invalid = |reason: &str| SamlError::InvalidSignature(reason.to_string());
+        let signed_info = signature.child(NS_DSIG, "SignedInfo").ok_or_else(|| invalid("no SignedInfo"))?;
+
+        let method = signed_info.child(NS_DSIG, "CanonicalizationMethod").ok_or_else(|| invalid("no CanonicalizationMethod"))?;
+        let signed_info_prefixes = exclusive_prefixes(method)?;
+        let algorithm: &signature::RsaParameters = match signed_info
+            .child(NS_DSIG, "SignatureMethod")
+            .and_then(|method| method.attribute("Algorithm"))
+        {
+            Some("http://www.w3.org/2001/04/xmldsig-more#rsa-sha256") => &signature::RSA_PKCS1_2048_8192_SHA256,
+            Some("http://www.w3.org/2001/04/xmldsig-more#rsa-sha384") => &signature::RSA_PKCS1_2048_8192_SHA384,
+            Some("http://www.w3.org/2001/04/xmldsig-more#rsa-sha512") => &signature::RSA_PKCS1_2048_8192_SHA512,
+            other => return Err(SamlError::Unsupported(format!("signature method {}", other.unwrap_or("none")))),
+        };
+
+        let references: Vec<&Element> = signed_info.children_named(NS_DSIG, "Reference").collect();
+        let [reference] = references.as_slice() else {
+            return Err(invalid("expected exactly one Reference"));
+        };
+        let id = signed.attribute("ID").ok_or_else(|| invalid("the signed element has no ID"))?;
+        if reference.attribute("URI") != Some(&format!("#{}", id)) {
+            return Err(invalid("the signature is for another element"));
+        }
+
+        let mut prefixes = Vec::new();
+        let mut enveloped = false;
+        let mut canonicalized = false;
+        if let Some(transforms) = reference.child(NS_DSIG, "Transforms") {
+            for transform in transforms.children_named(NS_DSIG, "Transform") {
+                match transform.attribute("Algorithm") {
+                    Some(ENVELOPED_SIGNATURE) => enveloped = true,
+                    Some(NS_EXC_C14N) => {
+                        prefixes = exclusive_prefixes(transform)?;
+                        canonicalized = true;
+                    }
+                    other => return Err(SamlError::Unsupported(format!("transform {}", other.unwrap_or("none")))),
+                }
+            }
+        }
+        if !enveloped {
+            return Err(SamlError::Unsupported("signatures that aren't enveloped".to_string()));
+        }
+        // Without the transform the reference would be canonicalized
+        // inclusively, which identity providers don't do
+        if !canonicalized {
+            return Err(SamlError::Unsupported("inclusive canonicalization".to_string()));
+        }
+
+        let digest_algorithm = match reference.child(NS_DSIG, "DigestMethod").and_then(|method| method.attribute("Algorithm")) {
+            Some("http://www.w3.org/2001/04/xmlenc#sha256") => &ring::digest::SHA256,
+            Some("http://www.w3.org/2001/04/xmldsig-more#sha384") => &ring::digest::SHA384,
+            Some("http://www.w3.org/2001/04/xmlenc#sha512") => &ring::digest::SHA512,
+            other => return Err(SamlError::Unsupported(format!("digest method {}", other.unwrap_or("none")))),
+        };
+        let expected = reference
+            .child(NS_DSIG, "DigestValue")
+            .map(base64_text)
+            .ok_or_else(|| invalid("no DigestValue"))??;
+        let digest = ring::digest::digest(digest_algorithm, xml::canonicalize(signed, Some(signature), &prefixes).as_bytes());
+        if digest.as_ref() != expected.as_slice() {
+            return Err(invalid("the signed element was changed"));
+        }
+
+        let signature_value = signature
+            .child(NS_DSIG, "SignatureValue")
+            .map(base64_text)
+            .ok_or_else(|| invalid("no SignatureValue"))??;
+        let signed_bytes = xml::canonicalize(signed_info, None, &signed_info_prefixes);
+        for certificate in &self.idp.certificates {
+            let key = rsa_public_key(certificate)?;
+            if UnparsedPublicKey::new(algorithm, key).verify(signed_bytes.as_bytes(), &signature_value).is_ok() {
+                return Ok(true);
+            }
+        }
+        Err(invalid("not signed by a certificate of the identity provider"))
+    }
+}
+
+struct Verified {
+    user: SamlUser,
+    assertion_id: String,
+    in_response_to: Option<String>,
+    expires: DateTime<Utc>,
+}
+
+/// The `InclusiveNamespaces PrefixList` of an exclusive canonicalization
+/// method or transform
+fn exclusive_prefixes(method: &Element) -> Result<Vec<String>, SamlError> {
+    match method.attribute("Algorithm") {
+        Some(NS_EXC_C14N) => Ok(method
+            .child(NS_EXC_C14N, "InclusiveNamespaces")
+            .and_then(|inclusive| inclusive.attribute("PrefixList"))
+            .map(|list| list.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default()),
+        other => Err(SamlError::Unsupported(format!("canonicalization {}", other.unwrap_or("none")))),
+    }
+}
+
+fn base64_text(element: &Element) -> Result<Vec<u8>, SamlError> {
+    let text: String = element.text().chars().filter(|c| !c.is_whitespace()).collect();
+    general_purpose::STANDARD
+        .decode(text)
+        .map_err(|_| SamlError::Malformed(format!("{} is not base64", element.name)))
+}
+
+fn instant(value: &str) -> Result<DateTime<Utc>, SamlError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|instant| instant.with_timezone(&Utc))
+        .map_err(|_| SamlError::Malformed(format!("invalid time {}", value)))
+}
+
+/// Fields of an `application/x-www-form-urlencoded` body
+fn form_fields(body: &[u8]) -> HashMap<String, String> {
+    String::from_utf8_lossy(body)
+        .split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let decode = |part: &str| urlencoding::decode(&part.replace('+', " ")).ok().map(|part| part.into_owned());
+            Some((decode(key)?, decode(value)?))
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+    use ring::rand::SystemRandom;
+    use ring::signature::RsaKeyPair;
+
+    /// A 2048-bit RSA key (PKCS#8) and a self-signed certificate for it
+    const IDP_KEY: &str = concat!(
+        "MIIEvQIBADANBgkqhkiG9w0BAQEFAASCBKcwggSjAgEAAoIBAQC3BXOSci+CH16Pf8N4yN9EnaKVGbUe7PlFw1cWPQhsRl17",
+        "dw095UPtdgVm59ThiKN0N8nsS9QMqaVqvjvu/ODttwggl1o8aYyh6pyNqov2AubVdIoHY2pnd1JZDNrFtljAG+2V736ZnBI+",
+        "yJflqGHfJpzgjSE90ntlMZqC8DLbCjvp9yC1KK2ildH4wpbvscGNITSphSBV2oC6saJhph4m1HQpedUjIETuuf4fKx85XVii",
+        "cgEOw8we1ng/kY3SRSdrjKvzcLhMqW9vbRREtdpkRV042gdQGgZv6bU3dokC6o+dbV7mejzUI02UoChXLrJCXh032cYCMKlJ",
+        "z9fN2yLVAgMBAAECggEAHaW6LEOnewBqYLv9VexSMIsJinYc3yrbkWe8jHtsCSAX5hdps3hXWdjXtK0C0NsBwWKFKOw6qCd2",
+        "BpXU3xijNhbDcAYchbNCZvs6i39QR1R+gJ7tehdqZeLQGeFJciQg+dO8V6CxZSBBW17Crl7ppcybNoveEeXt+70+jo3q6wzV",
+        "9SHt/rYm2nlyET/y96AAvlGhBIwT+U+8BAU7BZN9fDgYQQmU3LBDxlrLR4gqNuAJnxKZs+J8WfE53rW3FEQlzIpQ4U+Zl6ee",
+        "RlzlFJ7qgm97jfl1+2zfLhermIFTsQScSwaVdOJ91GaqzN3AWgXtPMgoJ76Y+KdPAIn6VBad4QKBgQDdRwFTMqsMjlUcO0wT",
+        "Q2tKrcJPqzE8pulh+9eEwk+slGf/jxnq0rPNLDsChCjI844qSRtEOKaIlgMfqDYT7GctPJOnheWOr1xeGgZM1a5A/Bm1ZHBO",
+        "bc6bisRSW5hrlAySyQo6ILmsOZE/5IlWLqHVzsHGiW+baXxQn7/5fXeXYQKBgQDTvaap5WDtDZ09glXHyYCKPYhmCouOVakv",
+        "re+njZC82csGbdooURVyh7j60F5hUOCoYZGCQgOHS+ZVlaYyytLmv8Y9v/KT4neHMOzMwzSkGVkg9hgDVq8pmGagxfZCqB/g",
+        "qUZC9TjJM66qdsyTJFd3bVZr8K4Kwo9BWxVKjdUj9QKBgQCsHulW8BjBYY2usilQ6qZwpox5MC7SgPsVLrLMH+Gd2qqyzXtZ",
+        "f6BAvmEAahDXjyxMlCVTgcV4hUfUviGiiccQFwi7zJltzdZJlHDGH4i2H+NXh9McDqohr2VassjzFWxaWWtNH9uNrhHf40M4",
+        "+HkGORfd+VYJS5pZlInd5cy6wQKBgE3DeCMrZ+aHunjtlh1yxq9+m8qGEuzqTuGA6uRJXJ/PCP5Kd/WYmdzfNbN/tTneXzPX",
+        "6X3CStN0r5o9BLXgwYYXZnIlPazMXoObF0y4YGvoF1DAsN8BBgGrw/1F1wRyBIsZAAH1V66lOX/Hc6iSj0EAOd4YnYK8I6qo",
+        "DfC8mEvFAoGADq96vZgMBT7ck7jh6xJhUJD+HqrrisUuhaALRvxLz9e6UYwAn5BhaV5Imuif28GZ91ig7odBBqIVs3ukBaEO",
+        "CjrOmF9oL+ovZ0pXWcZU5WTrrssXnSRCqMDqQ4BpSc7n3Zjaa1qGCY/nHxv1ZICeVqoIXu39lRxrplLvnKuGpIQ=",
+    );
+    const IDP_CERTIFICATE: &str = concat!(
+        "MIIDFzCCAf+gAwIBAgIUYBIkCyBRFF0kv/3MWTS5vfVWTcwwDQYJKoZIhvcNAQELBQAwGjEYMBYGA1UEAwwPaWRwLmV4YW1w",
+        "bGUuY29tMCAXDTI2MTAxNjE3NDQ0NloYDzIxMjYwOTIyMTc0NDQ2WjAaMRgwFgYDVQQDDA9pZHAuZXhhbXBsZS5jb20wggEi",
+        "MA0GCSqGSIb3DQEBAQUAA4IBDwAwggEKAoIBAQC3BXOSci+CH16Pf8N4yN9EnaKVGbUe7PlFw1cWPQhsRl17dw095UPtdgVm",
+        "59ThiKN0N8nsS9QMqaVqvjvu/ODttwggl1o8aYyh6pyNqov2AubVdIoHY2pnd1JZDNrFtljAG+2V736ZnBI+yJflqGHfJpzg",
+        "jSE90ntlMZqC8DLbCjvp9yC1KK2ildH4wpbvscGNITSphSBV2oC6saJhph4m1HQpedUjIETuuf4fKx85XViicgEOw8we1ng/",
+        "kY3SRSdrjKvzcLhMqW9vbRREtdpkRV042gdQGgZv6bU3dokC6o+dbV7mejzUI02UoChXLrJCXh032cYCMKlJz9fN2yLVAgMB",
+        "AAGjUzBRMB0GA1UdDgQWBBRI6QMQ2P8WyeQOoO4xRym3MZ8EMDAfBgNVHSMEGDAWgBRI6QMQ2P8WyeQOoO4xRym3MZ8EMDAP",
+        "BgNVHRMBAf8EBTADAQH/MA0GCSqGSIb3DQEBCwUAA4IBAQBk/lz2r36PZfBiNyaXcsdDSTy3z8GSWTHcC1uIUYROyviFIQON",
+        "hejmfOi44Ht02RUzKwqZHleE4duTeh+aZPvnEo9EYX5YflSfwOTfjKUKLZuiMwO8uH1hiIg6mchaf98iFgU16H5ENjZ1FInW",
+        "1n7/QnQn0Gi0PSEDEEPgNYbCnCZNLnKPUKLpNiKReWVlb4AZijpT9Q5pFiL333iTNlU8JtumKma/+l8zJVGVY82AKfgj2pjP",
+        "OkLwlhimywHKqgt7aCyAe6+iQ4tLbAUnKF75ggHiE0ZFm74q4oSuIK8NpLnp5iPVm0OOthuKbXgUqECOAEbBx9spg8Gokqhq",
+        "VgJW",
+    );
+
+    const ACS: &str = "https://app.example.com/saml/acs";
+
+    fn idp_metadata() -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<md:EntityDescriptor xmlns:md="{}" xmlns:ds="{}" entityID="https://idp.example.com">
+  <md:IDPSSODescriptor protocolSupportEnumeration="{}">
+    <md:KeyDescriptor use="signing"><ds:KeyInfo><ds:X509Data><ds:X509Certificate>
+      {}
+    </ds:X509Certificate></ds:X509Data></ds:KeyInfo></md:KeyDescriptor>
+    <md:SingleSignOnService Binding="{}" Location="https://idp.example.com/sso/post"/>
+    <md:SingleSignOnService Binding="{}" Location="https://idp.example.com/sso"/>
+  </md:IDPSSODescriptor>
+</md:EntityDescriptor>"#,
+            NS_METADATA, NS_DSIG, NS_PROTOCOL, IDP_CERTIFICATE, BINDING_POST, BINDING_REDIRECT
+        )
+    }
+
+    /// A base64 response whose assertion is signed with [`IDP_KEY`]
+    fn response(in_response_to: Option<&str>, assertion_id: &str, audience: &str, expires: DateTime<Utc>) -> String {
+        edited_response(assertion_id, audience, expires, in_response_to, |document| document)
+    }
+
+    /// [`response`] with `edit` applied to the XML before it is signed
+    fn edited_response(
+        assertion_id: &str,
+        audience: &str,
+        expires: DateTime<Utc>,
+        in_response_to: Option<&str>,
+        edit: impl Fn(String) -> String,
+    ) -> String {
+        let in_response_to = in_response_to.map(|id| format!(" InResponseTo=\"{}\"", id)).unwrap_or_default();
+        let document = |signature: &str| {
+            edit(format!(
+                r#"<samlp:Response xmlns:samlp="{NS_PROTOCOL}" xmlns:saml="{NS_ASSERTION}" ID="_response" Version="2.0" Destination="{ACS}"{in_response_to}>
+  <saml:Issuer>https://idp.example.com</saml:Issuer>
+  <samlp:Status><samlp:StatusCode Value="{STATUS_SUCCESS}"/></samlp:Status>
+  <saml:Assertion ID="{assertion_id}" Version="2.0">
+    <saml:Issuer>https://idp.example.com</saml:Issuer>{signature}
+    <saml:Subject>
+      <saml:NameID>ada@example.com</saml:NameID>
+      <saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer">
+        <saml:SubjectConfirmationData Recipient="{ACS}" NotOnOrAfter="{expires}"{in_response_to}/>
+      </saml:SubjectConfirmation>
+    </saml:Subject>
+    <saml:Conditions NotOnOrAfter="{expires}">
+      <saml:AudienceRestriction><saml:Audience>{audience}</saml:Audience></saml:AudienceRestriction>
+    </saml:Conditions>
+    <saml:AttributeStatement>
+      <saml:Attribute Name="email"><saml:AttributeValue>ada@example.com</saml:AttributeValue></saml:Attribute>
+      <saml:Attribute Name="groups"><saml:AttributeValue>admins</saml:AttributeValue><saml:AttributeValue>staff</saml:AttributeValue></saml:Attribute>
+    </saml:AttributeStatement>
+  </saml:Assertion>
+</samlp:Response>"#,
+                expires = expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+            ))
+        };
+
+        let unsigned = xml::parse(&document("")).unwrap();
+        let assertion = unsigned.child(NS_ASSERTION, "Assertion").unwrap();
+        let digest = ring::digest::digest(&ring::digest::SHA256, xml::canonicalize(assertion, None, &[]).as_bytes());
+        let signed_info = format!(
+            concat!(
+                "<ds:SignedInfo><ds:CanonicalizationMethod Algorithm=\"{exc}\"/>",
+                "<ds:SignatureMethod Algorithm=\"http://www.w3.org/2001/04/xmldsig-more#rsa-sha256\"/>",
+                "<ds:Reference URI=\"#{id}\"><ds:Transforms><ds:Transform Algorithm=\"{enveloped}\"/><ds:Transform Algorithm=\"{exc}\"/></ds:Transforms>",
+                "<ds:DigestMethod Algorithm=\"http://www.w3.org/2001/04/xmlenc#sha256\"/><ds:DigestValue>{digest}</ds:DigestValue></ds:Reference></ds:SignedInfo>"
+            ),
+            exc = NS_EXC_C14N,
+            id = assertion_id,
+            enveloped = ENVELOPED_SIGNATURE,
+            digest = general_purpose::STANDARD.encode(digest),
+        );
+        let signature = xml::parse(&format!("<ds:Signature xmlns:ds=\"{}\">{}</ds:Signature>", NS_DSIG, signed_info)).unwrap();
+        let canonical = xml::canonicalize(signature.child(NS_DSIG, "SignedInfo").unwrap(), None, &[]);
+
+        let key = RsaKeyPair::from_pkcs8(&general_purpose::STANDARD.decode(IDP_KEY).unwrap()).unwrap();
+        let mut value = vec![0; key.public().modulus_len()];
+        key.sign(&signature::RSA_PKCS1_SHA256, &SystemRandom::new(), canonical.as_bytes(), &mut value).unwrap();
+        let signature = format!(
+            "<ds:Signature xmlns:ds=\"{}\">{}<ds:SignatureValue>{}</ds:SignatureValue></ds:Signature>",
+            NS_DSIG,
+            signed_info,
+            general_purpose::STANDARD.encode(value)
+        );
+        general_purpose::STANDARD.encode(document(&signature))
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct AppUser {
+        id: String,
+        email: String,
+        roles: Vec<String>,
+    }
+
+    #[tokio::test]
+    async fn test_saml_sign_in() {
+        let idp = IdentityProvider::from_metadata(&idp_metadata()).unwrap();
+        assert_eq!((idp.entity_id.as_str(), idp.sso_url.as_str(), idp.binding), ("https://idp.example.com", "https://idp.example.com/sso", SsoBinding::Redirect));
+        let sp = ServiceProvider::new("https://app.example.com/saml", ACS, idp)
+            .map_attribute("email", "email")
+            .map_attribute_list("groups", "roles");
+        assert!(sp.metadata().contains(r#"entityID="https://app.example.com/saml""#) && sp.metadata().contains(ACS));
+
+        // SP-initiated: the request id comes back in the response
+        let login = sp.login(Some("/dashboard")).await;
+        let location = login.headers()["location"].to_str().unwrap().to_string();
+        let request = location.strip_prefix("https://idp.example.com/sso?SAMLRequest=").unwrap().split('&').next().unwrap();
+        let request = general_purpose::STANDARD.decode(urlencoding::decode(request).unwrap().as_bytes()).unwrap();
+        let request = String::from_utf8(miniz_oxide::inflate::decompress_to_vec(&request).unwrap()).unwrap();
+        let request_id = xml::parse(&request).unwrap().attribute("ID").unwrap().to_string();
+        assert!(location.ends_with("&RelayState=%2Fdashboard"));
+
+        let expires = Utc::now() + chrono::Duration::minutes(5);
+        let user = sp.validate(&response(Some(&request_id), "_a1", "https://app.example.com/saml", expires)).await.unwrap();
+        assert_eq!(user.name_id, "ada@example.com");
+        assert_eq!(user.map::<AppUser>().unwrap(), AppUser {
+            id: "ada@example.com".to_string(),
+            email: "ada@example.com".to_string(),
+            roles: vec!["admins".to_string(), "staff".to_string()],
+        });
+        let answered_twice = sp.validate(&response(Some(&request_id), "_a2", "https://app.example.com/saml", expires)).await;
+        assert_eq!(answered_twice, Err(SamlError::UnknownRequest));
+
+        // IdP-initiated, then replayed
+        let unsolicited = response(None, "_a3", "https://app.example.com/saml", expires);
+        assert!(sp.validate(&unsolicited).await.is_ok());
+        assert_eq!(sp.validate(&unsolicited).await, Err(SamlError::Replayed));
+
+        let refused = [
+            (response(None, "_a4", "https://other.example.com", expires), SamlError::InvalidAudience),
+            (response(None, "_a5", "https://app.example.com/saml", Utc::now() - chrono::Duration::minutes(5)), SamlError::Expired),
+        ];
+        for (saml_response, error) in refused {
+            assert_eq!(sp.validate(&saml_response).await, Err(error));
+        }
+        let signed = String::from_utf8(general_purpose::STANDARD.decode(response(None, "_a6", "https://app.example.com/saml", expires)).unwrap()).unwrap();
+        let tampered = general_purpose::STANDARD.encode(signed.replace("admins", "owners"));
+        assert_eq!(sp.validate(&tampered).await, Err(SamlError::InvalidSignature("the signed element was changed".to_string())));
+        let unsigned = signed.split("<ds:Signature").next().unwrap().to_string() + &signed[signed.find("</ds:Signature>").unwrap() + 15..];
+        assert_eq!(sp.validate(&general_purpose::STANDARD.encode(unsigned)).await, Err(SamlError::Unsigned));
+
+        let app = App::new().post("/saml/acs", sp.acs_handler(|user: SamlUser, relay_state: Option<String>| async move {
+            Response::ok().body(format!("{} {}", user.name_id, relay_state.unwrap_or_default()))
+        }));
+        let body = format!(
+            "SAMLResponse={}&RelayState=%2Fdashboard",
+            urlencoding::encode(&response(None, "_a7", "https://app.example.com/saml", expires))
+        );
+        let request = Request::mock(Method::POST, "/saml/acs").with_header("content-type", "application/x-www-form-urlencoded").with_body(body.clone());
+        assert_eq!(app.handle_request(request).await.body_data(), b"ada@example.com /dashboard");
+        let replayed = Request::mock(Method::POST, "/saml/acs").with_header("content-type", "application/x-www-form-urlencoded").with_body(body);
+        let replayed = app.handle_request(replayed).await;
+        assert_eq!(replayed.status_code(), http::StatusCode::FORBIDDEN);
+        assert!(!String::from_utf8_lossy(replayed.body_data()).contains("already been used"));
+    }
+
+    #[tokio::test]
+    async fn test_saml_rejects_assertions_changed_after_signing() {
+        let idp = IdentityProvider::from_metadata(&idp_metadata()).unwrap();
+        let sp = ServiceProvider::new("https://app.example.com/saml", ACS, idp);
+        let expires = Utc::now() + chrono::Duration::minutes(5);
+        let signed = |id: &str| String::from_utf8(general_purpose::STANDARD.decode(response(None, id, "https://app.example.com/saml", expires)).unwrap()).unwrap();
+        let changed = SamlError::InvalidSignature("the signed element was changed".to_string());
+
+        let edits: [fn(String) -> String; 5] = [
+            |document| document.replace("<saml:NameID>ada@", "<saml:NameID>eve@"),
+            |document| document.replace("<saml:Assertion ID", "<saml:Assertion Role=\"admin\" ID"),
+            |document| document.replace("<saml:AttributeValue>staff", "<saml:AttributeValue>owners</saml:AttributeValue><saml:AttributeValue>staff"),
+            |document| document.replace("<saml:NameID>", "<saml:NameID xmlns:saml=\"urn:evil\">"),
+            |document| document.replace("ada@example.com</saml:NameID>", "ada@example.com<!---->.evil.com</saml:NameID>"),
+        ];
+        for (n, edit) in edits.into_iter().enumerate() {
+            let tampered = general_purpose::STANDARD.encode(edit(signed(&format!("_t{}", n))));
+            assert_eq!(sp.validate(&tampered).await, Err(changed.clone()), "edit {}", n);
+        }
+
+        // The signature itself can't be touched either
+        let digest = |document: String| {
+            let start = document.find("<ds:DigestValue>").unwrap() + 16;
+            let mut document = document.into_bytes();
+            document[start] = if document[start] == b'A' { b'B' } else { b'A' };
+            String::from_utf8(document).unwrap()
+        };
+        assert!(matches!(sp.validate(&general_purpose::STANDARD.encode(digest(signed("_t5")))).await, Err(SamlError::InvalidSignature(_))));
+
+        // Changes canonicalization discards keep the signature valid
+        let reserialized = signed("_t6").replace("<saml:Subject>", "<!-- note --><saml:Subject >").replace(" Version=\"2.0\">\n    <saml:Issuer>", " Version='2.0'>\n    <saml:Issuer>");
+        assert!(sp.validate(&general_purpose::STANDARD.encode(reserialized)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_saml_refuses_assertions_missing_required_conditions() {
+        let idp = IdentityProvider::from_metadata(&idp_metadata()).unwrap();
+        let sp = ServiceProvider::new("https://app.example.com/saml", ACS, idp);
+        let expires = Utc::now() + chrono::Duration::minutes(5);
+        let until = format!(" NotOnOrAfter=\"{}\"", expires.to_rfc3339_opts(SecondsFormat::Secs, true));
+        let restriction = "<saml:AudienceRestriction><saml:Audience>https://app.example.com/saml</saml:Audience></saml:AudienceRestriction>";
+        let missing = |what: &str| SamlError::Malformed(format!("bearer confirmation has no {}", what));
+
+        type Edit<'a> = Box<dyn Fn(String) -> String + 'a>;
+        let cases: Vec<(&str, Edit, SamlError)> = vec![
+            ("_c1", Box::new(|document: String| document.replace(restriction, "")), SamlError::InvalidAudience),
+            (
+                "_c2",
+                Box::new(|document: String| {
+                    let start = document.find("<saml:Conditions").unwrap();
+                    let end = document.find("</saml:Conditions>").unwrap() + "</saml:Conditions>".len();
+                    format!("{}{}", &document[..start], &document[end..])
+                }),
+                SamlError::InvalidAudience,
+            ),
+            ("_c3", Box::new(|document: String| document.replace(&format!(" Recipient=\"{}\"", ACS), "")), missing("Recipient")),
+            (
+                "_c4",
+                Box::new(|document: String| document.replacen(&until, "", 1)),
+                missing("NotOnOrAfter"),
+            ),
+            (
+                "_c5",
+                Box::new(|document: String| {
+                    let start = document.find("<saml:SubjectConfirmationData").unwrap();
+                    let end = start + document[start..].find("/>").unwrap() + 2;
+                    format!("{}{}", &document[..start], &document[end..])
+                }),
+                missing("SubjectConfirmationData"),
+            ),
+        ];
+        for (assertion_id, edit, error) in cases {
+            let saml_response = edited_response(assertion_id, "https://app.example.com/saml", expires, None, edit);
+            assert_eq!(sp.validate(&saml_response).await, Err(error), "{}", assertion_id);
+        }
+
+        // The untouched assertion is accepted
+        assert!(sp.validate(&response(None, "_c6", "https://app.example.com/saml", expires)).await.is_ok());
+    }
+
+    /// A store whose backend is down
+    struct UnavailableStore;
+
+    impl Cache for UnavailableStore {
+        fn get(&self, _key: &str) -> crate::cache::CacheFuture<'_, Option<String>> {
+            Box::pin(async { None })
+        }
+
+        fn set(&self, _key: &str, _value: &str, _ttl: Option<Duration>) -> crate::cache::CacheFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+            Box::pin(async { Err("connection refused".into()) })
+        }
+
+        fn delete(&self, _key: &str) -> crate::cache::CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+            Box::pin(async { Err("connection refused".into()) })
+        }
+
+        fn add(&self, _key: &str, _value: &str, _ttl: Option<Duration>) -> crate::cache::CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+            Box::pin(async { Err("connection refused".into()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_saml_refuses_sign_in_when_the_replay_store_is_down() {
+        let idp = IdentityProvider::from_metadata(&idp_metadata()).unwrap();
+        let sp = ServiceProvider::new("https://app.example.com/saml", ACS, idp).store(Arc::new(UnavailableStore));
+        let expires = Utc::now() + chrono::Duration::minutes(5);
+        let unsolicited = response(None, "_b1", "https://app.example.com/saml", expires);
+        assert_eq!(sp.validate(&unsolicited).await, Err(SamlError::Store("connection refused".to_string())));
+    }
+}
@@ -0,0 +1,547 @@
+//! Just enough XML for SAML: a namespace-aware parser that refuses
+//! document type declarations, and Exclusive XML Canonicalization
+//! (`http://www.w3.org/2001/10/xml-exc-c14n#`, without comments) for
+//! checking signatures
+
+use std::collections::BTreeMap;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Deepest nesting accepted, which keeps recursion bounded
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Attribute {
+    /// As written, e.g. `xsi:type`
+    pub name: String,
+    pub namespace: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Element {
+    /// As written, e.g. `saml:Assertion`
+    pub name: String,
+    pub namespace: String,
+    /// Attributes other than namespace declarations
+    pub attributes: Vec<Attribute>,
+    /// Every namespace in scope, declared here or on an ancestor, by prefix
+    /// (`""` for the default namespace)
+    pub scope: BTreeMap<String, String>,
+    pub children: Vec<Node>,
+}
+
+fn split(name: &str) -> (&str, &str) {
+    name.split_once(':').unwrap_or(("", name))
+}
+
+impl Element {
+    pub fn local_name(&self) -> &str {
+        split(&self.name).1
+    }
+
+    pub fn is(&self, namespace: &str, local_name: &str) -> bool {
+        self.namespace == namespace && self.local_name() == local_name
+    }
+
+    /// An unqualified attribute's value
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|attribute| attribute.name == name).map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(&'a self, namespace: &'a str, local_name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |element| element.is(namespace, local_name))
+    }
+
+    pub fn child(&self, namespace: &str, local_name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.is(namespace, local_name))
+    }
+
+    /// Every element named so at any depth, this one included
+    pub fn descendants<'a>(&'a self, namespace: &str, local_name: &str, found: &mut Vec<&'a Element>) {
+        if self.is(namespace, local_name) {
+            found.push(self);
+        }
+        for element in self.elements() {
+            element.descendants(namespace, local_name, found);
+        }
+    }
+
+    /// Concatenated text of the element and its descendants
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Text(value) => text.push_str(value),
+                Node::Element(element) => text.push_str(&element.text()),
+            }
+        }
+        text
+    }
+}
+
+/// Parse a document, returning its root element
+pub(crate) fn parse(input: &str) -> Result<Element, String> {
+    // XML end-of-line handling
+    let input = input.replace("\r\n", "\n").replace('\r', "\n");
+    let mut parser = Parser { input: &input, position: 0 };
+    parser.skip_prolog()?;
+    if !parser.rest().starts_with('<') {
+        return Err("no root element".to_string());
+    }
+    let root = parser.element(&BTreeMap::new(), 0)?;
+    parser.skip_misc()?;
+    if !parser.rest().is_empty() {
+        return Err("content after the root element".to_string());
+    }
+    Ok(root)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start_matches([' ', '\t', '\n']).len();
+    }
+
+    /// Move past the next `end`, returning what came before it
+    fn until(&mut self, end: &str) -> Result<&'a str, String> {
+        let rest = self.rest();
+        let index = rest.find(end).ok_or_else(|| format!("missing {}", end))?;
+        self.position += index + end.len();
+        Ok(&rest[..index])
+    }
+
+    fn skip_prolog(&mut self) -> Result<(), String> {
+        if self.rest().starts_with("\u{feff}") {
+            self.position += 3;
+        }
+        self.skip_misc()
+    }
+
+    /// Whitespace, comments and processing instructions
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.until("-->")?;
+            } else if rest.starts_with("<?") {
+                self.until("?>")?;
+            } else if rest.starts_with("<!") {
+                return Err("document type declarations are not allowed".to_string());
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, String> {
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '/' | '>'))
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return Err(format!("expected a name at byte {}", self.position));
+        }
+        self.position += length;
+        Ok(&rest[..length])
+    }
+
+    fn element(&mut self, parent_scope: &BTreeMap<String, String>, depth: usize) -> Result<Element, String> {
+        if depth > MAX_DEPTH {
+            return Err("elements are nested too deeply".to_string());
+        }
+        self.position += 1;
+        let name = self.name()?.to_string();
+
+        let mut raw_attributes = Vec::new();
+        let self_closing = loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.position += 2;
+                break true;
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                break false;
+            }
+            let attribute = self.name()?.to_string();
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(format!("attribute {} has no value", attribute));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(format!("attribute {} is not quoted", attribute)),
+            };
+            self.position += 1;
+            let raw = self.until(&quote.to_string())?;
+            if raw.contains('<') {
+                return Err(format!("attribute {} contains <", attribute));
+            }
+            // Attribute-value normalization: literal whitespace becomes spaces
+            let value = unescape(&raw.replace(['\t', '\n'], " "))?;
+            if raw_attributes.iter().any(|(existing, _): &(String, String)| *existing == attribute) {
+                return Err(format!("duplicate attribute {}", attribute));
+            }
+            raw_attributes.push((attribute, value));
+        };
+
+        let mut scope = parent_scope.clone();
+        for (attribute, value) in &raw_attributes {
+            if attribute == "xmlns" {
+                scope.insert(String::new(), value.clone());
+            } else if let Some(prefix) = attribute.strip_prefix("xmlns:") {
+                scope.insert(prefix.to_string(), value.clone());
+            }
+        }
+        let resolve = |prefix: &str| -> Result<String, String> {
+            match prefix {
+                "xml" => Ok(XML_NAMESPACE.to_string()),
+                _ => scope.get(prefix).cloned().ok_or_else(|| format!("undeclared namespace prefix {}", prefix)),
+            }
+        };
+
+        let (prefix, _) = split(&name);
+        let namespace = if prefix.is_empty() { scope.get("").cloned().unwrap_or_default() } else { resolve(prefix)? };
+        let mut attributes = Vec::new();
+        for (attribute, value) in raw_attributes {
+            if attribute == "xmlns" || attribute.starts_with("xmlns:") {
+                continue;
+            }
+            let (prefix, _) = split(&attribute);
+            // Unprefixed attributes are in no namespace
+            let namespace = if prefix.is_empty() { String::new() } else { resolve(prefix)? };
+            attributes.push(Attribute { name: attribute, namespace, value });
+        }
+
+        let mut element = Element { name, namespace, attributes, scope, children: Vec::new() };
+        if self_closing {
+            return Ok(element);
+        }
+
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(format!("{} is not closed", element.name));
+            }
+            if rest.starts_with("</") {
+                self.position += 2;
+                let closing = self.name()?;
+                if closing != element.name {
+                    return Err(format!("{} is closed by {}", element.name, closing));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(format!("malformed end tag for {}", element.name));
+                }
+                self.position += 1;
+                break;
+            }
+            if rest.starts_with("<!--") {
+                self.until("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.position += "<![CDATA[".len();
+                text.push_str(self.until("]]>")?);
+            } else if rest.starts_with("<?") {
+                self.until("?>")?;
+            } else if rest.starts_with("<!") {
+                return Err("document type declarations are not allowed".to_string());
+            } else if rest.starts_with('<') {
+                if !text.is_empty() {
+                    element.children.push(Node::Text(std::mem::take(&mut text)));
+                }
+                let child = self.element(&element.scope, depth + 1)?;
+                element.children.push(Node::Element(child));
+            } else {
+                let length = rest.find('<').unwrap_or(rest.len());
+                text.push_str(&unescape(&rest[..length])?);
+                self.position += length;
+            }
+        }
+        if !text.is_empty() {
+            element.children.push(Node::Text(text));
+        }
+        Ok(element)
+    }
+}
+
+fn unescape(raw: &str) -> Result<String, String> {
+    if !raw.contains('&') {
+        return Ok(raw.to_string());
+    }
+    let mut output = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or("unterminated entity reference")? + start;
+        let entity = &rest[start + 1..end];
+        let character = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(decimal) = entity.strip_prefix('#') {
+                    decimal.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32).ok_or_else(|| format!("unknown entity &{};", entity))?
+            }
+        };
+        output.push(character);
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Exclusive canonical form of `element`, leaving out `exclude` (the
+/// enveloped signature) and treating the prefixes in `inclusive` (from
+/// `InclusiveNamespaces PrefixList`, `#default` for the default namespace)
+/// as if they were used
+pub(crate) fn canonicalize(element: &Element, exclude: Option<&Element>, inclusive: &[String]) -> String {
+    let mut output = String::new();
+    write_element(element, exclude, inclusive, &BTreeMap::new(), &mut output);
+    output
+}
+
+fn write_element(
+    element: &Element,
+    exclude: Option<&Element>,
+    inclusive: &[String],
+    rendered: &BTreeMap<String, String>,
+    output: &mut String,
+) {
+    let mut utilized: Vec<&str> = vec![split(&element.name).0];
+    for attribute in &element.attributes {
+        let (prefix, _) = split(&attribute.name);
+        if !prefix.is_empty() && prefix != "xml" {
+            utilized.push(prefix);
+        }
+    }
+    for prefix in inclusive {
+        let prefix = if prefix == "#default" { "" } else { prefix.as_str() };
+        if element.scope.contains_key(prefix) {
+            utilized.push(prefix);
+        }
+    }
+
+    let mut declarations = BTreeMap::new();
+    for prefix in utilized {
+        let namespace = element.scope.get(prefix).map(String::as_str).unwrap_or("");
+        let current = rendered.get(prefix).map(String::as_str);
+        let needed = if prefix.is_empty() {
+            // `xmlns=""` only undoes a default an output ancestor declared
+            namespace != current.unwrap_or("")
+        } else {
+            current != Some(namespace)
+        };
+        if needed {
+            declarations.insert(prefix, namespace);
+        }
+    }
+
+    output.push('<');
+    output.push_str(&element.name);
+    for (prefix, namespace) in &declarations {
+        if prefix.is_empty() {
+            output.push_str(" xmlns=\"");
+        } else {
+            output.push_str(" xmlns:");
+            output.push_str(prefix);
+            output.push_str("=\"");
+        }
+        escape_attribute(namespace, output);
+        output.push('"');
+    }
+    let mut attributes: Vec<&Attribute> = element.attributes.iter().collect();
+    attributes.sort_by(|a, b| (a.namespace.as_str(), split(&a.name).1).cmp(&(b.namespace.as_str(), split(&b.name).1)));
+    for attribute in attributes {
+        output.push(' ');
+        output.push_str(&attribute.name);
+        output.push_str("=\"");
+        escape_attribute(&attribute.value, output);
+        output.push('"');
+    }
+    output.push('>');
+
+    let mut rendered = rendered.clone();
+    for (prefix, namespace) in declarations {
+        rendered.insert(prefix.to_string(), namespace.to_string());
+    }
+    for child in &element.children {
+        match child {
+            Node::Text(text) => escape_text(text, output),
+            Node::Element(child) if exclude.is_some_and(|exclude| std::ptr::eq(child, exclude)) => {}
+            Node::Element(child) => write_element(child, exclude, inclusive, &rendered, output),
+        }
+    }
+    output.push_str("</");
+    output.push_str(&element.name);
+    output.push('>');
+}
+
+fn escape_text(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\r' => output.push_str("&#xD;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, output: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' => output.push_str("&#x9;"),
+            '\n' => output.push_str("&#xA;"),
+            '\r' => output.push_str("&#xD;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+/// Escape `value` for use in text or a double-quoted attribute
+pub(crate) fn escape(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    escape_attribute(value, &mut output);
+    output.replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(document: &str, inclusive: &[&str]) -> String {
+        let inclusive: Vec<String> = inclusive.iter().map(|prefix| prefix.to_string()).collect();
+        canonicalize(&parse(document).unwrap(), None, &inclusive)
+    }
+
+    #[test]
+    fn test_exclusive_c14n_declares_namespaces_where_they_are_used() {
+        // The example from the Exclusive XML Canonicalization spec
+        let document = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"/></n1:elem2></n0:local>"#;
+        assert_eq!(
+            canonical(document, &[]),
+            r#"<n0:local xmlns:n0="foo:bar"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"></n3:stuff></n1:elem2></n0:local>"#
+        );
+
+        // A subtree carries the declarations it needs from its ancestors
+        let root = parse(document).unwrap();
+        let elem2 = root.elements().next().unwrap();
+        assert_eq!(
+            canonicalize(elem2, None, &[]),
+            r#"<n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"></n3:stuff></n1:elem2>"#
+        );
+
+        // Default namespaces are only redeclared when they change
+        assert_eq!(
+            canonical(r#"<a xmlns="urn:a"><b xmlns="urn:a"><c xmlns=""/></b></a>"#, &[]),
+            r#"<a xmlns="urn:a"><b><c xmlns=""></c></b></a>"#
+        );
+        assert_eq!(canonical(r#"<a><b xmlns=""/></a>"#, &[]), "<a><b></b></a>");
+    }
+
+    #[test]
+    fn test_exclusive_c14n_inclusive_prefixes() {
+        let document = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org" xmlns:unused="urn:unused"><n3:stuff/></n0:local>"#;
+        assert_eq!(
+            canonical(document, &["n3"]),
+            r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org"><n3:stuff></n3:stuff></n0:local>"#
+        );
+        assert_eq!(
+            canonical(r#"<p:a xmlns:p="urn:p" xmlns="urn:default"><p:b/></p:a>"#, &["#default"]),
+            r#"<p:a xmlns="urn:default" xmlns:p="urn:p"><p:b></p:b></p:a>"#
+        );
+        // Prefixes that aren't in scope are ignored
+        assert_eq!(canonical(r#"<a/>"#, &["missing", "#default"]), "<a></a>");
+    }
+
+    #[test]
+    fn test_exclusive_c14n_orders_and_escapes_attributes_and_text() {
+        let document = "<e xmlns=\"urn:a\" xmlns:b=\"urn:b\" z='1' b:a=\"2\" a=\"&lt;&quot;&#9;&#10;' >\nx\">text &amp; &lt; &gt; \"q\" &#13;<!-- gone --><![CDATA[x<y]]><empty/></e>";
+        assert_eq!(
+            canonical(document, &[]),
+            "<e xmlns=\"urn:a\" xmlns:b=\"urn:b\" a=\"&lt;&quot;&#x9;&#xA;' > x\" z=\"1\" b:a=\"2\">text &amp; &lt; &gt; \"q\" &#xD;x&lt;y<empty></empty></e>"
+        );
+
+        // Attributes sort by namespace URI, then local name
+        assert_eq!(
+            canonical(r#"<e xmlns:z="urn:a" xmlns:a="urn:b" a:x="1" z:y="2" b="3"/>"#, &[]),
+            r#"<e xmlns:a="urn:b" xmlns:z="urn:a" b="3" z:y="2" a:x="1"></e>"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_form_only_changes_with_the_content() {
+        let original = canonical(r#"<a xmlns="urn:a" id="1"><b>ada</b></a>"#, &[]);
+        assert_eq!(canonical("<?xml version=\"1.0\"?>\r\n<a id='1'  xmlns='urn:a'><b>ada</b><!-- note --></a>", &[]), original);
+
+        for changed in [
+            r#"<a xmlns="urn:a" id="2"><b>ada</b></a>"#,
+            r#"<a xmlns="urn:a" id="1"><b>eve</b></a>"#,
+            r#"<a xmlns="urn:other" id="1"><b>ada</b></a>"#,
+            r#"<a xmlns="urn:a" id="1"><b>ada</b><b/></a>"#,
+        ] {
+            assert_ne!(canonical(changed, &[]), original, "{}", changed);
+        }
+    }
+
+    #[test]
+    fn test_parse_refuses_doctypes_and_entities() {
+        let doctype = parse(r#"<?xml version="1.0"?><!DOCTYPE a [<!ENTITY x "boom">]><a>&x;</a>"#).unwrap_err();
+        assert!(doctype.contains("document type declarations"), "{}", doctype);
+        assert!(parse(r#"<a><!ENTITY x "boom"></a>"#).unwrap_err().contains("document type declarations"));
+        assert!(parse("<a>&x;</a>").unwrap_err().contains("unknown entity"));
+        assert!(parse(r#"<a b="&x;"/>"#).unwrap_err().contains("unknown entity"));
+        assert!(parse("<a>&amp</a>").is_err());
+
+        assert_eq!(parse("<a>&lt;&#x41;&#66;</a>").unwrap().text(), "<AB");
+    }
+
+    #[test]
+    fn test_parse_refuses_malformed_documents() {
+        for document in ["", "<a>", "<a></b>", "<a/><b/>", "<p:a/>", r#"<a b="1" b="2"/>"#, "<a b=1/>", r#"<a b="<"/>"#] {
+            assert!(parse(document).is_err(), "{}", document);
+        }
+        let deep = "<a>".repeat(MAX_DEPTH + 2) + &"</a>".repeat(MAX_DEPTH + 2);
+        assert!(parse(&deep).unwrap_err().contains("nested too deeply"));
+    }
+}
@@ -0,0 +1,536 @@
+//! # Two-factor authentication with TOTP
+//!
+//! Time-based one-time passwords (RFC 6238) as used by Google Authenticator,
+//! 1Password, Authy and friends.
+//!
+//! 1. Generate a secret with [`generate_secret`] and show it to the user as a
+//!    QR code ([`Totp::qr_svg`]) or an `otpauth://` link ([`Totp::uri`]).
+//! 2. Once they confirm a code, store the secret with the user, along with the
+//!    hashes of a set of [recovery codes](generate_recovery_codes).
+//! 3. At sign-in, check codes with [`Totp::verify`] and let [`RequireTwoFactor`]
+//!    remember the verification for sensitive routes.
+//!
+//! ```rust
+//! use torch_web::auth::totp::{generate_secret, Totp};
+//!
+//! let secret = generate_secret();
+//! let totp = Totp::new(&secret).unwrap();
+//!
+//! let uri = totp.uri("Acme", "ada@example.com");
+//! assert!(uri.starts_with("otpauth://totp/Acme:ada%40example.com?secret="));
+//!
+//! let code = totp.current_code();
+//! assert!(totp.verify(&code));
+//! ```
+
+use crate::clock::unix_now;
+use crate::extractors::FromRequestParts;
+use crate::middleware::Middleware;
+use crate::security::encryption::constant_time_eq;
+use crate::{Request, Response};
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Cookie that remembers a verified second factor
+pub const TWO_FACTOR_COOKIE: &str = "torch_two_factor";
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Errors from setting up a [`Totp`]
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TotpError {
+    #[error("TOTP secret is not valid base32")]
+    InvalidSecret,
+
+    #[error("TOTP secret must be at least 16 bytes, got {0}")]
+    SecretTooShort(usize),
+}
+
+/// Generate a random 160-bit secret, base32 encoded for authenticator apps
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// A TOTP generator and verifier for one secret
+#[derive(Clone)]
+pub struct Totp {
+    secret: Vec<u8>,
+    digits: u32,
+    period: u64,
+    window: u64,
+}
+
+impl std::fmt::Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Totp")
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .field("window", &self.window)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Totp {
+    /// Create a verifier from a base32 secret, spaces and case ignored
+    ///
+    /// Uses the settings every authenticator app supports: SHA-1, 6 digits
+    /// and a 30 second period, accepting codes one period either side of now.
+    pub fn new(secret: &str) -> Result<Self, TotpError> {
+        let secret = base32_decode(secret).ok_or(TotpError::InvalidSecret)?;
+        if secret.len() < 16 {
+            return Err(TotpError::SecretTooShort(secret.len()));
+        }
+        Ok(Self {
+            secret,
+            digits: 6,
+            period: 30,
+            window: 1,
+        })
+    }
+
+    /// Code length, 6 to 8 digits
+    pub fn digits(mut self, digits: u32) -> Self {
+        self.digits = digits.clamp(6, 8);
+        self
+    }
+
+    /// Seconds each code is valid for
+    pub fn period(mut self, seconds: u64) -> Self {
+        self.period = seconds.max(1);
+        self
+    }
+
+    /// How many periods of clock drift to accept either side of now
+    pub fn window(mut self, periods: u64) -> Self {
+        self.window = periods;
+        self
+    }
+
+    /// The time step a Unix timestamp falls in
+    pub fn step_at(&self, unix_time: u64) -> u64 {
+        unix_time / self.period
+    }
+
+    /// The code for a Unix timestamp
+    pub fn code_at(&self, unix_time: u64) -> String {
+        self.code_for_step(self.step_at(unix_time))
+    }
+
+    /// The code for right now
+    pub fn current_code(&self) -> String {
+        self.code_at(unix_now())
+    }
+
+    fn code_for_step(&self, step: u64) -> String {
+        let mut mac = Hmac::<sha1::Sha1>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation from RFC 4226
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+        format!("{:0width$}", binary % 10u32.pow(self.digits), width = self.digits as usize)
+    }
+
+    /// Check a code against the current time
+    pub fn verify(&self, code: &str) -> bool {
+        self.verify_at(code, unix_now(), None).is_some()
+    }
+
+    /// Check a code that hasn't been used before
+    ///
+    /// Returns the time step the code belongs to. Store it with the user and
+    /// pass it back as `last_step` next time, so the same code can't be
+    /// replayed while it is still valid.
+    pub fn verify_unused(&self, code: &str, last_step: Option<u64>) -> Option<u64> {
+        self.verify_at(code, unix_now(), last_step)
+    }
+
+    /// Check a code against a Unix timestamp, skipping steps up to `last_step`
+    pub fn verify_at(&self, code: &str, unix_time: u64, last_step: Option<u64>) -> Option<u64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != self.digits as usize {
+            return None;
+        }
+
+        let current = self.step_at(unix_time);
+        let first = current.saturating_sub(self.window).max(last_step.map_or(0, |step| step + 1));
+        (first..=current + self.window).find(|step| constant_time_eq(&self.code_for_step(*step), &code))
+    }
+
+    /// `otpauth://` URI for authenticator apps
+    pub fn uri(&self, issuer: &str, account: &str) -> String {
+        let issuer = urlencoding::encode(issuer);
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            issuer,
+            urlencoding::encode(account),
+            base32_encode(&self.secret),
+            issuer,
+            self.digits,
+            self.period
+        )
+    }
+
+    /// QR code for [`Totp::uri`] as an SVG image, ready to inline in a page
+    pub fn qr_svg(&self, issuer: &str, account: &str) -> String {
+        qrcode::QrCode::new(self.uri(issuer, account).as_bytes())
+            .expect("an otpauth URI fits in a QR code")
+            .render::<qrcode::render::svg::Color<'_>>()
+            .min_dimensions(200, 200)
+            .build()
+    }
+}
+
+/// Generate one-time recovery codes like `k7tq2-m9xwe` to show the user once
+///
+/// Store only their [hashes](hash_recovery_code) and use
+/// [`redeem_recovery_code`] when the user has lost their device.
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+    let mut rng = rand::thread_rng();
+    (0..count)
+        .map(|_| {
+            let mut code: String = (0..10).map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char).collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Hash a recovery code for storage, ignoring case, dashes and spaces
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// Use up a recovery code, removing its hash from `hashes` when it matches
+pub fn redeem_recovery_code(code: &str, hashes: &mut Vec<String>) -> bool {
+    let hash = hash_recovery_code(code);
+    match hashes.iter().position(|stored| constant_time_eq(stored, &hash)) {
+        Some(index) => {
+            hashes.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+type SubjectFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Middleware that requires a verified second factor on sensitive routes
+///
+/// After checking a code, the app sets [`RequireTwoFactor::verified_cookie`]
+/// on its response. Requests under the protected prefixes need that signed
+/// cookie, and it must belong to the signed-in user returned by the subject
+/// function. Without it, page visits are redirected to the challenge page and
+/// other requests get `403 Forbidden`.
+///
+/// The subject has to come from the authenticated session, e.g. an extension
+/// set by the session or token middleware that runs before this one, never
+/// from anything the client can set directly like a request header. With the
+/// `oidc` feature, [`CurrentUser`](crate::auth::jwt::CurrentUser) works:
+/// `|req| req.get_extension::<CurrentUser>().map(|user| user.id.clone())`.
+///
+/// Valid cookies also make the [`TwoFactorVerified`] extractor available to
+/// handlers anywhere in the app.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use torch_web::{App, Request, Response};
+/// use torch_web::auth::RequireTwoFactor;
+///
+/// /// Inserted by the app's session middleware once the password checks out
+/// #[derive(Clone)]
+/// struct SignedIn(u64);
+///
+/// let signed_in = |req: &Request| req.get_extension::<SignedIn>().map(|user| user.0.to_string());
+/// let two_factor = RequireTwoFactor::new(b"a long random signing key from config", signed_in)
+///     .protect("/admin")
+///     .protect("/settings/billing")
+///     .challenge("/two-factor")
+///     .remember_for(Duration::from_secs(12 * 60 * 60));
+///
+/// let verified = two_factor.clone();
+/// let app = App::new()
+///     .middleware(two_factor)
+///     .post("/two-factor", move |req: Request| {
+///         let verified = verified.clone();
+///         async move {
+///             // ...check the submitted code with Totp::verify first
+///             let Some(user) = signed_in(&req) else {
+///                 return Response::unauthorized();
+///             };
+///             Response::redirect_found("/admin").header("set-cookie", &verified.verified_cookie(&user))
+///         }
+///     });
+/// ```
+#[derive(Clone)]
+pub struct RequireTwoFactor {
+    key: Arc<Vec<u8>>,
+    prefixes: Vec<String>,
+    challenge: Option<String>,
+    remember: Duration,
+    subject: SubjectFn,
+}
+
+impl RequireTwoFactor {
+    /// Create the guard with the key used to sign verification cookies and
+    /// a function returning the signed-in user from the authenticated session,
+    /// so a verification can't be reused by another account
+    pub fn new<F>(key: impl AsRef<[u8]>, subject: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            key: Arc::new(key.as_ref().to_vec()),
+            prefixes: Vec::new(),
+            challenge: None,
+            remember: Duration::from_secs(24 * 60 * 60),
+            subject: Arc::new(subject),
+        }
+    }
+
+    /// Require a second factor under `prefix`; with none set, every route except the challenge does
+    pub fn protect(mut self, prefix: &str) -> Self {
+        self.prefixes.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Page that asks for a code, where unverified page visits are sent
+    pub fn challenge(mut self, path: &str) -> Self {
+        self.challenge = Some(path.to_string());
+        self
+    }
+
+    /// How long a verification lasts, default one day
+    pub fn remember_for(mut self, duration: Duration) -> Self {
+        self.remember = duration;
+        self
+    }
+
+    /// `Set-Cookie` value recording that `subject` passed the second factor
+    pub fn verified_cookie(&self, subject: &str) -> String {
+        let expires = unix_now() + self.remember.as_secs();
+        let payload = format!("{}.{}", expires, urlencoding::encode(subject));
+        format!(
+            "{}={}.{}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+            TWO_FACTOR_COOKIE,
+            self.sign(&payload),
+            payload,
+            self.remember.as_secs()
+        )
+    }
+
+    /// `Set-Cookie` value that forgets the verification, e.g. on sign-out
+    pub fn forget_cookie(&self) -> String {
+        format!("{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax", TWO_FACTOR_COOKIE)
+    }
+
+    /// The subject of a valid, unexpired verification cookie on `req`
+    pub fn verified(&self, req: &Request) -> Option<String> {
+        let value = req
+            .header("cookie")?
+            .split(';')
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, _)| *name == TWO_FACTOR_COOKIE)?
+            .1;
+        let (signature, payload) = value.split_once('.')?;
+        if !constant_time_eq(signature, &self.sign(payload)) {
+            return None;
+        }
+
+        let (expires, subject) = payload.split_once('.')?;
+        if expires.parse::<u64>().ok()? <= unix_now() {
+            return None;
+        }
+        let subject = urlencoding::decode(subject).ok()?.into_owned();
+        ((self.subject)(req)? == subject).then_some(subject)
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn is_protected(&self, path: &str) -> bool {
+        if self.challenge.as_deref() == Some(path) {
+            return false;
+        }
+        self.prefixes.is_empty()
+            || self.prefixes.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+impl Middleware for RequireTwoFactor {
+    fn call(
+        &self,
+        mut req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if let Some(subject) = self.verified(&req) {
+            req.insert_extension(TwoFactorVerified(subject));
+            return next(req);
+        }
+        if !self.is_protected(req.path()) {
+            return next(req);
+        }
+
+        let response = match &self.challenge {
+            Some(challenge) if matches!(*req.method(), http::Method::GET | http::Method::HEAD) => {
+                let target = req.uri().path_and_query().map_or(req.path(), |target| target.as_str());
+                Response::redirect(http::StatusCode::SEE_OTHER, &format!("{}?next={}", challenge, urlencoding::encode(target)))
+            }
+            _ => Response::forbidden().body("Two-factor authentication required"),
+        };
+        Box::pin(async move { response })
+    }
+}
+
+/// Extractor for requests with a verified second factor, holding its subject
+///
+/// Needs [`RequireTwoFactor`] in the middleware stack; without a valid
+/// verification the request is rejected with `403 Forbidden`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoFactorVerified(pub String);
+
+impl FromRequestParts for TwoFactorVerified {
+    type Error = Response;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let verified = req.get_extension::<TwoFactorVerified>().cloned();
+        Box::pin(async move {
+            verified.ok_or_else(|| Response::forbidden().body("Two-factor authentication required"))
+        })
+    }
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len().div_ceil(5) * 8);
+    for chunk in data.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes([0, 0, 0, buffer[0], buffer[1], buffer[2], buffer[3], buffer[4]]);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            output.push(BASE32_ALPHABET[((bits >> (35 - i * 5)) & 0x1f) as usize] as char);
+        }
+    }
+    output
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer = 0u64;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=' && *c != '-') {
+        let value = BASE32_ALPHABET.iter().position(|&b| b == c.to_ascii_uppercase() as u8)?;
+        buffer = (buffer << 5) | value as u64;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totp_rfc6238_vectors_and_drift() {
+        // RFC 6238 appendix B secret, "12345678901234567890"
+        let totp = Totp::new("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap().digits(8);
+        assert_eq!(totp.code_at(59), "94287082");
+        assert_eq!(totp.code_at(1111111109), "07081804");
+        assert_eq!(totp.code_at(2000000000), "69279037");
+        assert_eq!(base32_encode(b"12345678901234567890"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+
+        let totp = totp.digits(6);
+        let now = 1_700_000_000;
+        let step = totp.step_at(now);
+        let previous = totp.code_at(now - 30);
+        assert_eq!(totp.verify_at(&previous, now, None), Some(step - 1));
+        assert_eq!(totp.verify_at(&totp.code_at(now - 90), now, None), None);
+        // A code can't be replayed once its step has been used
+        assert_eq!(totp.verify_at(&previous, now, Some(step - 1)), None);
+        assert_eq!(Totp::new("not base32!").unwrap_err(), TotpError::InvalidSecret);
+        assert_eq!(Totp::new("GEZDGNBV").unwrap_err(), TotpError::SecretTooShort(5));
+        assert!(totp.qr_svg("Acme", "ada").starts_with("<?xml"));
+    }
+
+    #[test]
+    fn test_recovery_codes_redeem_once() {
+        let codes = generate_recovery_codes(8);
+        assert_eq!(codes.len(), 8);
+        assert!(codes.iter().all(|code| code.len() == 11 && code.as_bytes()[5] == b'-'));
+
+        let mut hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+        assert!(redeem_recovery_code(&codes[3].to_uppercase().replace('-', " "), &mut hashes));
+        assert!(!redeem_recovery_code(&codes[3], &mut hashes));
+        assert_eq!(hashes.len(), 7);
+    }
+
+    type Next = Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>;
+
+    #[tokio::test]
+    async fn test_require_two_factor_cookie() {
+        #[derive(Clone)]
+        struct SignedIn(&'static str);
+
+        let guard = RequireTwoFactor::new("key", |req: &Request| req.get_extension::<SignedIn>().map(|user| user.0.to_string()))
+            .protect("/admin")
+            .challenge("/two-factor");
+        let signed_in = |user: &'static str, cookie: &str| {
+            let mut req = Request::mock(http::Method::GET, "/admin").with_header("cookie", cookie);
+            req.insert_extension(SignedIn(user));
+            req
+        };
+        let next = || -> Next {
+            Box::new(|mut req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                Box::pin(async move {
+                    match TwoFactorVerified::from_request_parts(&mut req).await {
+                        Ok(TwoFactorVerified(subject)) => Response::ok().body(subject),
+                        Err(response) => response,
+                    }
+                })
+            })
+        };
+
+        let redirected = guard.call(Request::mock(http::Method::GET, "/admin/users?page=2"), next()).await;
+        assert_eq!(redirected.status_code(), http::StatusCode::SEE_OTHER);
+        assert_eq!(redirected.headers().get("location").unwrap(), "/two-factor?next=%2Fadmin%2Fusers%3Fpage%3D2");
+        let posted = guard.call(Request::mock(http::Method::POST, "/admin/users"), next()).await;
+        assert_eq!(posted.status_code(), http::StatusCode::FORBIDDEN);
+
+        let set_cookie = guard.verified_cookie("42");
+        let cookie = set_cookie.split(';').next().unwrap();
+        assert_eq!(guard.call(signed_in("42", cookie), next()).await.body_data(), b"42");
+
+        // Someone else's session, no session at all, or a tampered cookie isn't verified
+        assert_eq!(guard.call(signed_in("7", cookie), next()).await.status_code(), http::StatusCode::SEE_OTHER);
+        let anonymous = Request::mock(http::Method::GET, "/admin").with_header("cookie", cookie);
+        assert_eq!(guard.call(anonymous, next()).await.status_code(), http::StatusCode::SEE_OTHER);
+        let forged = signed_in("7", &cookie.replace(".42", ".7"));
+        assert_eq!(guard.call(forged, next()).await.status_code(), http::StatusCode::SEE_OTHER);
+    }
+}
@@ -225,6 +225,7 @@ impl CacheEntry {
 /// // Clear all entries
 /// cache.clear().await;
 /// ```
+#[derive(Clone)]
 pub struct MemoryCache {
     store: Arc<RwLock<HashMap<String, CacheEntry>>>,
     default_ttl: Option<Duration>,
@@ -239,25 +240,75 @@ impl MemoryCache {
     }
 
     pub async fn get(&self, key: &str) -> Option<String> {
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
         let store = self.store.read().await;
-        if let Some(entry) = store.get(key) {
-            if !entry.is_expired() {
-                return Some(entry.value.clone());
-            }
-        }
-        None
+        let value = store.get(key).filter(|entry| !entry.is_expired()).map(|entry| entry.value.clone());
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_cache("get", key, Some(value.is_some()));
+        #[cfg(feature = "otel")]
+        crate::otel::record_cache("get", key, Some(value.is_some()), started);
+        value
     }
 
     pub async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), Box<dyn std::error::Error>> {
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_cache("set", key, None);
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
         let mut store = self.store.write().await;
         let ttl = ttl.or(self.default_ttl);
         store.insert(key.to_string(), CacheEntry::new(value.to_string(), ttl));
+        #[cfg(feature = "otel")]
+        crate::otel::record_cache("set", key, None, started);
         Ok(())
     }
 
     pub async fn delete(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_cache("delete", key, None);
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
+        let mut store = self.store.write().await;
+        let removed = store.remove(key).is_some();
+        #[cfg(feature = "otel")]
+        crate::otel::record_cache("delete", key, None, started);
+        Ok(removed)
+    }
+
+    /// Store `value` only if `key` is missing or expired, returning whether it was stored
+    pub async fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut store = self.store.write().await;
+        if store.get(key).is_some_and(|entry| !entry.is_expired()) {
+            return Ok(false);
+        }
+        let ttl = ttl.or(self.default_ttl);
+        store.insert(key.to_string(), CacheEntry::new(value.to_string(), ttl));
+        Ok(true)
+    }
+
+    /// Add one to the count under `key`, starting from 0 when it is missing
+    /// or expired, and return the new count. `ttl` applies when the count
+    /// starts.
+    pub async fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<i64, Box<dyn std::error::Error>> {
         let mut store = self.store.write().await;
-        Ok(store.remove(key).is_some())
+        if let Some(entry) = store.get_mut(key).filter(|entry| !entry.is_expired()) {
+            let count = entry.value.parse::<i64>()? + 1;
+            entry.value = count.to_string();
+            return Ok(count);
+        }
+        store.insert(key.to_string(), CacheEntry::new("1".to_string(), ttl.or(self.default_ttl)));
+        Ok(1)
+    }
+
+    /// Keys starting with `prefix` that hold unexpired values
+    pub async fn keys(&self, prefix: &str) -> Vec<String> {
+        let store = self.store.read().await;
+        store
+            .iter()
+            .filter(|(key, entry)| key.starts_with(prefix) && !entry.is_expired())
+            .map(|(key, _)| key.clone())
+            .collect()
     }
 
     pub async fn clear(&self) -> Result<(), Box<dyn std::error::Error>> {
@@ -308,8 +359,14 @@ impl RedisCache {
 
     #[cfg(feature = "cache")]
     pub async fn get(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
         let mut conn = self.client.get_connection()?;
         let result: Option<String> = conn.get(key)?;
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_cache("get", key, Some(result.is_some()));
+        #[cfg(feature = "otel")]
+        crate::otel::record_cache("get", key, Some(result.is_some()), started);
         Ok(result)
     }
 
@@ -320,12 +377,18 @@ impl RedisCache {
 
     #[cfg(feature = "cache")]
     pub async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), redis::RedisError> {
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_cache("set", key, None);
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
         let mut conn = self.client.get_connection()?;
         if let Some(ttl) = ttl.or(self.default_ttl) {
             conn.set_ex::<_, _, ()>(key, value, ttl.as_secs())?;
         } else {
             conn.set::<_, _, ()>(key, value)?;
         }
+        #[cfg(feature = "otel")]
+        crate::otel::record_cache("set", key, None, started);
         Ok(())
     }
 
@@ -336,8 +399,14 @@ impl RedisCache {
 
     #[cfg(feature = "cache")]
     pub async fn delete(&self, key: &str) -> Result<bool, redis::RedisError> {
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_cache("delete", key, None);
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
         let mut conn = self.client.get_connection()?;
         let result: i32 = conn.del(key)?;
+        #[cfg(feature = "otel")]
+        crate::otel::record_cache("delete", key, None, started);
         Ok(result > 0)
     }
 
@@ -345,31 +414,359 @@ impl RedisCache {
     pub async fn delete(&self, _key: &str) -> Result<bool, Box<dyn std::error::Error>> {
         Err("Redis cache feature not enabled".into())
     }
+
+    /// Store `value` only if `key` is missing, using `SET NX`
+    #[cfg(feature = "cache")]
+    pub async fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<bool, redis::RedisError> {
+        let mut conn = self.client.get_connection()?;
+        let mut command = redis::cmd("SET");
+        command.arg(key).arg(value).arg("NX");
+        if let Some(ttl) = ttl.or(self.default_ttl) {
+            command.arg("EX").arg(ttl.as_secs().max(1));
+        }
+        let stored: Option<String> = command.query(&mut conn)?;
+        Ok(stored.is_some())
+    }
+
+    /// Add one to the count under `key` with `INCR`, setting `ttl` when the
+    /// count starts. Both run in one script, so a count is never left
+    /// without its expiry.
+    #[cfg(feature = "cache")]
+    pub async fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<i64, redis::RedisError> {
+        let mut conn = self.client.get_connection()?;
+        let ttl_ms = ttl.or(self.default_ttl).map_or(0, |ttl| ttl.as_millis().max(1) as i64);
+        redis::Script::new(
+            "local count = redis.call('INCR', KEYS[1]) \
+             if count == 1 and tonumber(ARGV[1]) > 0 then redis.call('PEXPIRE', KEYS[1], ARGV[1]) end \
+             return count",
+        )
+        .key(key)
+        .arg(ttl_ms)
+        .invoke(&mut conn)
+    }
+
+    /// Keys starting with `prefix`, found with `SCAN`
+    #[cfg(feature = "cache")]
+    pub async fn keys(&self, prefix: &str) -> Result<Vec<String>, redis::RedisError> {
+        let mut conn = self.client.get_connection()?;
+        let mut pattern = String::with_capacity(prefix.len() + 1);
+        for c in prefix.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('*');
+        let keys = conn.scan_match::<_, String>(pattern)?.collect();
+        Ok(keys)
+    }
 }
 
+pub(crate) type CacheFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
 /// Cache trait for unified interface
 pub trait Cache: Send + Sync {
-    fn get(&self, key: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + '_>>;
-    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + '_>>;
-    fn delete(&self, key: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<bool, Box<dyn std::error::Error>>> + Send + '_>>;
+    fn get(&self, key: &str) -> CacheFuture<'_, Option<String>>;
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<(), Box<dyn std::error::Error>>>;
+    fn delete(&self, key: &str) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>>;
+
+    /// Store `value` only if `key` is missing, returning whether it was stored.
+    ///
+    /// The default checks then sets, which can race; the built-in caches
+    /// override it with an atomic version.
+    fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        let value = value.to_string();
+        Box::pin(async move {
+            if self.get(&key).await.is_some() {
+                return Ok(false);
+            }
+            self.set(&key, &value, ttl).await?;
+            Ok(true)
+        })
+    }
+
+    /// Add one to the count under `key`, starting from 0, and return the new
+    /// count; `ttl` applies when the count starts.
+    ///
+    /// Like [`add`](Cache::add), the default reads then writes, and the
+    /// built-in caches override it with an atomic version.
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<i64, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move {
+            let count = match self.get(&key).await {
+                Some(count) => count.parse::<i64>()? + 1,
+                None => 1,
+            };
+            self.set(&key, &count.to_string(), ttl).await?;
+            Ok(count)
+        })
+    }
+
+    /// Keys starting with `prefix`. Caches that can't list their keys
+    /// return an error, which the default does.
+    fn keys(&self, prefix: &str) -> CacheFuture<'_, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        let _ = prefix;
+        Box::pin(async { Err("this cache can't list its keys".into()) })
+    }
 }
 
 impl Cache for MemoryCache {
-    fn get(&self, key: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + '_>> {
+    fn get(&self, key: &str) -> CacheFuture<'_, Option<String>> {
         let key = key.to_string();
         Box::pin(async move { self.get(&key).await })
     }
 
-    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + '_>> {
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<(), Box<dyn std::error::Error>>> {
         let key = key.to_string();
         let value = value.to_string();
         Box::pin(async move { self.set(&key, &value, ttl).await })
     }
 
-    fn delete(&self, key: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<bool, Box<dyn std::error::Error>>> + Send + '_>> {
+    fn delete(&self, key: &str) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
         let key = key.to_string();
         Box::pin(async move { self.delete(&key).await })
     }
+
+    fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        let value = value.to_string();
+        Box::pin(async move { self.add(&key, &value, ttl).await })
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<i64, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move { self.increment(&key, ttl).await })
+    }
+
+    fn keys(&self, prefix: &str) -> CacheFuture<'_, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        let prefix = prefix.to_string();
+        Box::pin(async move { Ok(self.keys(&prefix).await) })
+    }
+}
+
+#[cfg(feature = "cache")]
+impl Cache for RedisCache {
+    /// Connection errors read as a miss
+    fn get(&self, key: &str) -> CacheFuture<'_, Option<String>> {
+        let key = key.to_string();
+        Box::pin(async move { self.get(&key).await.ok().flatten() })
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        let value = value.to_string();
+        Box::pin(async move { Ok(self.set(&key, &value, ttl).await?) })
+    }
+
+    fn delete(&self, key: &str) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move { Ok(self.delete(&key).await?) })
+    }
+
+    fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        let value = value.to_string();
+        Box::pin(async move { Ok(self.add(&key, &value, ttl).await?) })
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<i64, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move { Ok(self.increment(&key, ttl).await?) })
+    }
+
+    fn keys(&self, prefix: &str) -> CacheFuture<'_, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        let prefix = prefix.to_string();
+        Box::pin(async move { Ok(self.keys(&prefix).await?) })
+    }
+}
+
+/// In-memory cache that records every write and delete.
+///
+/// This is the `cache = "array"` driver from the `[testing]` section of
+/// `torch.toml`: hand it to anything that takes an `Arc<dyn Cache>` and assert
+/// on what was cached afterwards.
+///
+/// ```rust
+/// use torch_web::cache::{Cache, CacheFake};
+///
+/// # tokio_test::block_on(async {
+/// let cache = CacheFake::new();
+/// Cache::set(&cache, "user:1", "Ada", None).await.unwrap();
+///
+/// cache.assert_stored("user:1", "Ada");
+/// cache.assert_has("user:1").await;
+/// cache.assert_missing("user:2").await;
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct CacheFake {
+    cache: MemoryCache,
+    writes: Arc<std::sync::Mutex<Vec<(String, String)>>>,
+    deletes: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl CacheFake {
+    pub fn new() -> Self {
+        Self {
+            cache: MemoryCache::new(None),
+            writes: Arc::new(std::sync::Mutex::new(Vec::new())),
+            deletes: Arc::new(std::sync::Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Keys and values written so far, in order
+    pub fn writes(&self) -> Vec<(String, String)> {
+        self.writes.lock().unwrap().clone()
+    }
+
+    /// Assert `key` currently holds an unexpired value
+    pub async fn assert_has(&self, key: &str) {
+        assert!(self.cache.get(key).await.is_some(), "expected cache key {} to be present", key);
+    }
+
+    /// Assert `key` is not in the cache
+    pub async fn assert_missing(&self, key: &str) {
+        assert!(self.cache.get(key).await.is_none(), "expected cache key {} to be missing", key);
+    }
+
+    /// Assert `value` was written to `key` at some point
+    pub fn assert_stored(&self, key: &str, value: &str) {
+        let writes = self.writes();
+        assert!(
+            writes.iter().any(|(k, v)| k == key && v == value),
+            "expected {:?} to be stored under cache key {}; writes: {:?}",
+            value,
+            key,
+            writes
+        );
+    }
+
+    /// Assert `key` was deleted at some point
+    pub fn assert_deleted(&self, key: &str) {
+        let deletes = self.deletes.lock().unwrap();
+        assert!(deletes.iter().any(|k| k == key), "expected cache key {} to be deleted", key);
+    }
+
+    /// Assert nothing was written to the cache
+    pub fn assert_nothing_written(&self) {
+        let writes = self.writes();
+        assert!(writes.is_empty(), "expected no cache writes, but found {:?}", writes);
+    }
+}
+
+impl Default for CacheFake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cache for CacheFake {
+    fn get(&self, key: &str) -> CacheFuture<'_, Option<String>> {
+        Cache::get(&self.cache, key)
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        self.writes.lock().unwrap().push((key.to_string(), value.to_string()));
+        Cache::set(&self.cache, key, value, ttl)
+    }
+
+    fn delete(&self, key: &str) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        self.deletes.lock().unwrap().push(key.to_string());
+        Cache::delete(&self.cache, key)
+    }
+
+    fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        let value = value.to_string();
+        Box::pin(async move {
+            let stored = self.cache.add(&key, &value, ttl).await?;
+            if stored {
+                self.writes.lock().unwrap().push((key, value));
+            }
+            Ok(stored)
+        })
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<i64, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move {
+            let count = self.cache.increment(&key, ttl).await?;
+            self.writes.lock().unwrap().push((key, count.to_string()));
+            Ok(count)
+        })
+    }
+
+    fn keys(&self, prefix: &str) -> CacheFuture<'_, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        Cache::keys(&self.cache, prefix)
+    }
+}
+
+static FAKE: std::sync::Mutex<Option<CacheFake>> = std::sync::Mutex::new(None);
+
+/// Held by the active [`CacheFakeGuard`], so fakes take turns
+static FAKE_TURN: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+impl dyn Cache {
+    /// Swap the caches Torch picks for itself for a [`CacheFake`] until the
+    /// returned guard is dropped: the store behind [`Cached`] handlers,
+    /// `@cache` template fragments, job locks and rate limits, and import
+    /// progress. Like `Mail::fake`, the fake applies to the whole process
+    /// and fakes in tests running in parallel take turns.
+    ///
+    /// ```rust
+    /// use torch_web::cache::Cache;
+    ///
+    /// let cache = <dyn Cache>::fake();
+    /// // ... run the code under test, then assert on what it cached
+    /// assert!(cache.writes().iter().all(|(key, _)| !key.starts_with("secret:")));
+    /// ```
+    pub fn fake() -> CacheFakeGuard {
+        let turn = FAKE_TURN.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let fake = CacheFake::new();
+        *FAKE.lock().unwrap() = Some(fake.clone());
+        CacheFakeGuard { fake, _turn: turn }
+    }
+}
+
+/// Keep fakes out while a test uses Torch's own caches for real
+#[cfg(test)]
+pub(crate) fn without_fake() -> NoFake {
+    NoFake { _turn: FAKE_TURN.lock().unwrap_or_else(std::sync::PoisonError::into_inner) }
+}
+
+/// Held by tests that must not run under another test's fake
+#[cfg(test)]
+pub(crate) struct NoFake {
+    _turn: std::sync::MutexGuard<'static, ()>,
+}
+
+/// The fake set up by `Cache::fake`, while it is active
+pub(crate) fn active_fake() -> Option<Arc<dyn Cache>> {
+    let fake = FAKE.lock().unwrap().clone()?;
+    Some(Arc::new(fake))
+}
+
+/// Guard returned by `Cache::fake`, dereferencing to the [`CacheFake`] for
+/// assertions; Torch's own caches go back to normal once it is dropped
+#[must_use = "the cache fake is removed when this guard is dropped"]
+pub struct CacheFakeGuard {
+    fake: CacheFake,
+    _turn: std::sync::MutexGuard<'static, ()>,
+}
+
+impl std::ops::Deref for CacheFakeGuard {
+    type Target = CacheFake;
+
+    fn deref(&self) -> &CacheFake {
+        &self.fake
+    }
+}
+
+impl Drop for CacheFakeGuard {
+    fn drop(&mut self) {
+        *FAKE.lock().unwrap() = None;
+    }
 }
 
 /// Response caching middleware
@@ -377,6 +774,7 @@ pub struct CacheMiddleware {
     cache: Arc<dyn Cache>,
     cache_duration: Duration,
     cache_key_prefix: String,
+    vary: Vec<http::HeaderName>,
 }
 
 impl CacheMiddleware {
@@ -385,6 +783,7 @@ impl CacheMiddleware {
             cache,
             cache_duration,
             cache_key_prefix: "torch_cache:".to_string(),
+            vary: Vec::new(),
         }
     }
 
@@ -393,8 +792,30 @@ impl CacheMiddleware {
         self
     }
 
+    /// Cache a separate response per value of the `header` request header,
+    /// and tell clients so with `Vary`
+    pub fn vary(mut self, header: &str) -> Self {
+        self.vary.push(http::HeaderName::from_bytes(header.as_bytes()).expect("Invalid header name"));
+        self
+    }
+
+    /// Key for a request's response, kept apart per tenant
     fn generate_cache_key(&self, req: &Request) -> String {
-        format!("{}{}:{}", self.cache_key_prefix, req.method(), req.path())
+        let tenant = req
+            .get_extension::<crate::tenancy::Tenant>()
+            .cloned()
+            .or_else(crate::tenancy::Tenancy::current)
+            .map(|tenant| tenant.cache_prefix())
+            .unwrap_or_default();
+        let path = req.uri().path_and_query().map_or(req.path(), |pq| pq.as_str());
+        let mut key = format!("{}{}{}:{}", self.cache_key_prefix, tenant, req.method(), path);
+        for name in &self.vary {
+            key.push('|');
+            key.push_str(name.as_str());
+            key.push('=');
+            key.push_str(&req.headers().get(name).map(|value| String::from_utf8_lossy(value.as_bytes())).unwrap_or_default());
+        }
+        key
     }
 }
 
@@ -407,6 +828,7 @@ impl Middleware for CacheMiddleware {
         let cache = self.cache.clone();
         let cache_duration = self.cache_duration;
         let cache_key = self.generate_cache_key(&req);
+        let vary = self.vary.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", ");
 
         Box::pin(async move {
             let is_get_request = req.method() == &http::Method::GET;
@@ -443,10 +865,17 @@ impl Middleware for CacheMiddleware {
             }
 
             // Execute the request
-            let response = next(req).await;
+            let mut response = next(req).await;
+            if !vary.is_empty() {
+                let vary = match response.headers().get("vary").and_then(|value| value.to_str().ok()) {
+                    Some(existing) => format!("{}, {}", existing, vary),
+                    None => vary,
+                };
+                response = response.header("Vary", vary);
+            }
 
             // Cache successful GET responses
-            if is_get_request && response.status_code().is_success() {
+            if is_get_request && response.status_code().is_success() && !response.is_streaming() {
                 #[cfg(feature = "json")]
                 {
                     let cached_response = CachedResponse {
@@ -479,6 +908,470 @@ impl Middleware for CacheMiddleware {
     }
 }
 
+/// Runs a single handler through [`CacheMiddleware`]. This is what the
+/// `#[cache]` attribute expands to (with the `macros` feature):
+///
+/// ```rust,ignore
+/// use torch_web::{cache, extractors::Path, Response};
+///
+/// #[cache(ttl = "60s", vary = ["Accept-Language"])]
+/// pub async fn show(Path(id): Path<u32>) -> Response {
+///     Response::ok().body(format!("Post {}", id))
+/// }
+/// ```
+///
+/// Responses are stored in the `Arc<dyn Cache>` registered with
+/// [`App::with_state`](crate::App::with_state), or in a process-wide
+/// [`MemoryCache`] when there is none.
+#[derive(Debug, Clone)]
+pub struct Cached {
+    ttl: Duration,
+    vary: Vec<String>,
+}
+
+impl Cached {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, vary: Vec::new() }
+    }
+
+    /// Cache a separate response per value of each of `headers`
+    pub fn vary(mut self, headers: &[&str]) -> Self {
+        self.vary.extend(headers.iter().map(|header| header.to_string()));
+        self
+    }
+
+    /// Answer `req` from the cache, or with `handler` and cache its response
+    pub async fn call<H, T>(&self, req: Request, handler: H) -> Response
+    where
+        H: crate::Handler<T>,
+    {
+        static FALLBACK: std::sync::OnceLock<Arc<dyn Cache>> = std::sync::OnceLock::new();
+
+        use crate::extractors::state::RequestStateExt;
+        let store = active_fake()
+            .or_else(|| {
+                req.get_state(std::any::TypeId::of::<Arc<dyn Cache>>())
+                    .and_then(|state| state.downcast_ref::<Arc<dyn Cache>>())
+                    .cloned()
+            })
+            .unwrap_or_else(|| FALLBACK.get_or_init(|| Arc::new(MemoryCache::new(None))).clone());
+        let middleware = self
+            .vary
+            .iter()
+            .fold(CacheMiddleware::new(store, self.ttl), |middleware, header| middleware.vary(header));
+        middleware.call(req, Box::new(move |req| Box::pin(handler.call(req)))).await
+    }
+}
+
+/// Coalesces concurrent identical `GET` and `HEAD` requests, so only one of
+/// them runs the handler and the rest wait for and share its response.
+///
+/// Requests are identical when their method, path, query string and the
+/// `Authorization` and `Cookie` headers match; add more headers with
+/// [`vary`](SingleFlight::vary). Responses that set cookies are never shared,
+/// so the waiting requests run the handler themselves, as they also do if the
+/// first request is cancelled.
+///
+/// Add it after [`CacheMiddleware`] so only cache misses are coalesced, which
+/// keeps an expiring entry from sending every waiting request to the database:
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use torch_web::{App, cache::{CacheMiddleware, MemoryCache, SingleFlight}};
+///
+/// let cache = Arc::new(MemoryCache::new(None));
+/// let app = App::new()
+///     .middleware(CacheMiddleware::new(cache, Duration::from_secs(60)))
+///     .middleware(SingleFlight::new());
+/// ```
+#[derive(Clone)]
+pub struct SingleFlight {
+    in_flight: Flights,
+    vary: Vec<http::HeaderName>,
+}
+
+/// Requests currently running the handler, by flight key
+type Flights = Arc<std::sync::Mutex<HashMap<String, tokio::sync::watch::Receiver<Option<Arc<SharedResponse>>>>>>;
+
+/// A finished response that waiting requests can copy
+struct SharedResponse {
+    status: http::StatusCode,
+    headers: http::HeaderMap,
+    body: Vec<u8>,
+}
+
+impl SharedResponse {
+    fn to_response(&self) -> Response {
+        let mut response = Response::with_status(self.status).body(self.body.clone());
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// Removes the leader's entry however its request ends
+struct FlightGuard {
+    in_flight: Flights,
+    key: String,
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self {
+            in_flight: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            vary: vec![http::header::AUTHORIZATION, http::header::COOKIE],
+        }
+    }
+
+    /// Also require `header` to match before sharing a response
+    pub fn vary(mut self, header: &str) -> Self {
+        self.vary.push(http::HeaderName::from_bytes(header.as_bytes()).expect("Invalid header name"));
+        self
+    }
+
+    fn flight_key(&self, req: &Request) -> String {
+        let mut key = format!("{} {}", req.method(), req.uri().path_and_query().map_or(req.path(), |pq| pq.as_str()));
+        for name in &self.vary {
+            for value in req.headers().get_all(name) {
+                key.push('\0');
+                key.push_str(name.as_str());
+                key.push(':');
+                key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+            }
+        }
+        key
+    }
+}
+
+impl Default for SingleFlight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for SingleFlight {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        if req.method() != http::Method::GET && req.method() != http::Method::HEAD {
+            return next(req);
+        }
+
+        let key = self.flight_key(&req);
+        let in_flight = self.in_flight.clone();
+
+        Box::pin(async move {
+            let waiting = {
+                let mut flights = in_flight.lock().unwrap();
+                match flights.get(&key) {
+                    Some(receiver) => Err(receiver.clone()),
+                    None => {
+                        let (sender, receiver) = tokio::sync::watch::channel(None);
+                        flights.insert(key.clone(), receiver);
+                        Ok(sender)
+                    }
+                }
+            };
+
+            let sender = match waiting {
+                Ok(sender) => sender,
+                Err(mut receiver) => {
+                    loop {
+                        if let Some(shared) = receiver.borrow().as_ref() {
+                            return shared.to_response();
+                        }
+                        if receiver.changed().await.is_err() {
+                            break;
+                        }
+                    }
+                    // The first request was cancelled or its response can't be shared
+                    return next(req).await;
+                }
+            };
+
+            let guard = FlightGuard { in_flight, key };
+            let response = next(req).await;
+            if !response.headers().contains_key(http::header::SET_COOKIE) && !response.is_streaming() {
+                let _ = sender.send(Some(Arc::new(SharedResponse {
+                    status: response.status_code(),
+                    headers: response.headers().clone(),
+                    body: response.body_data().to_vec(),
+                })));
+            }
+            drop(guard);
+
+            response
+        })
+    }
+}
+
+/// Replays the stored response for retried `POST` and `PATCH` requests that
+/// carry the same `Idempotency-Key` header, so a client retrying after a
+/// timeout doesn't charge a card twice.
+///
+/// - The first request with a key runs normally and its response is stored
+///   for the TTL (24 hours by default).
+/// - Retries from the same caller with the same key, method, path, query
+///   string and body get the stored response back with
+///   `Idempotent-Replayed: true`.
+/// - Reusing a key for a different request is rejected with `422`, and a
+///   retry that arrives while the first is still running gets `409`.
+/// - Server errors and `429` responses are not stored, so the client can retry.
+/// - `Set-Cookie`, hop-by-hop and other per-connection headers are not
+///   replayed.
+///
+/// Keys belong to the caller that sent them, so two clients picking the same
+/// key never see each other's responses. Callers are told apart by their
+/// `Authorization` and `Cookie` headers; when a session cookie changes
+/// between retries, name the caller with [`subject`](IdempotencyKey::subject)
+/// instead.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use torch_web::{App, Request, Response, cache::{IdempotencyKey, MemoryCache}};
+///
+/// let cache = Arc::new(MemoryCache::new(None));
+/// let app = App::new()
+///     .middleware(IdempotencyKey::new(cache).ttl(Duration::from_secs(3600)))
+///     .post("/payments", |_req: Request| async { Response::created().body("charged") });
+/// ```
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+pub struct IdempotencyKey {
+    cache: Arc<dyn Cache>,
+    ttl: Duration,
+    lock_timeout: Duration,
+    prefix: String,
+    subject: Option<Subject>,
+}
+
+/// Names the caller a request comes from
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+type Subject = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+impl IdempotencyKey {
+    /// Name of the request header carrying the key
+    pub const HEADER: &'static str = "idempotency-key";
+
+    pub fn new(cache: Arc<dyn Cache>) -> Self {
+        Self {
+            cache,
+            ttl: Duration::from_secs(24 * 60 * 60),
+            lock_timeout: Duration::from_secs(60),
+            prefix: "torch_idempotency:".to_string(),
+            subject: None,
+        }
+    }
+
+    /// Tell callers apart by what `subject` returns, such as the signed-in
+    /// user's id, instead of by their `Authorization` and `Cookie` headers.
+    /// Requests it returns `None` for share one scope.
+    pub fn subject<F>(mut self, subject: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.subject = Some(Arc::new(subject));
+        self
+    }
+
+    /// The cache key for `key` sent by the caller of `req`
+    fn cache_key(&self, req: &Request, key: &str) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        match &self.subject {
+            Some(subject) => hasher.update(subject(req).unwrap_or_default()),
+            None => {
+                for name in [http::header::AUTHORIZATION, http::header::COOKIE] {
+                    for value in req.headers().get_all(name) {
+                        hasher.update((value.len() as u64).to_be_bytes());
+                        hasher.update(value.as_bytes());
+                    }
+                }
+            }
+        }
+        format!("{}{:x}:{}", self.prefix, hasher.finalize(), key)
+    }
+
+    /// How long a stored response is replayed for
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long a request that never finished (e.g. the process died) blocks its key
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+}
+
+/// What is stored under an idempotency key
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum IdempotentEntry {
+    InProgress { fingerprint: String },
+    Completed {
+        fingerprint: String,
+        status_code: u16,
+        headers: Vec<(String, String)>,
+        #[serde(with = "base64_body")]
+        body: Vec<u8>,
+    },
+}
+
+/// Stored response bodies as base64 strings rather than arrays of numbers
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+mod base64_body {
+    use base64::{engine::general_purpose, Engine as _};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        general_purpose::STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// SHA-256 over the parts that make two requests "the same", stable across processes
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+fn request_fingerprint(req: &Request) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    let target = req.uri().path_and_query().map_or(req.path(), |target| target.as_str());
+    for part in [req.method().as_str().as_bytes(), target.as_bytes(), req.body()] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Response headers that belong to one connection or one client and must not
+/// be replayed to another: cookies, hop-by-hop headers and per-response metadata
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+const UNREPLAYABLE_HEADERS: &[&str] = &[
+    "set-cookie",
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+    "date",
+    "x-request-id",
+];
+
+#[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+impl Middleware for IdempotencyKey {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        let unsafe_method = req.method() == http::Method::POST || req.method() == http::Method::PATCH;
+        let key = req.header(Self::HEADER).map(str::to_string);
+        let (Some(key), true) = (key, unsafe_method) else {
+            return next(req);
+        };
+        if key.is_empty() || key.len() > 255 {
+            return Box::pin(async {
+                Response::bad_request().body("Idempotency-Key must be between 1 and 255 characters")
+            });
+        }
+
+        let cache = self.cache.clone();
+        let (ttl, lock_timeout) = (self.ttl, self.lock_timeout);
+        let cache_key = self.cache_key(&req, &key);
+
+        Box::pin(async move {
+            let fingerprint = request_fingerprint(&req);
+            let in_progress = serde_json::to_string(&IdempotentEntry::InProgress { fingerprint: fingerprint.clone() })
+                .unwrap_or_default();
+
+            match cache.add(&cache_key, &in_progress, Some(lock_timeout)).await.map_err(|e| e.to_string()) {
+                Ok(true) => {}
+                Ok(false) => {
+                    let stored = cache.get(&cache_key).await.and_then(|entry| serde_json::from_str(&entry).ok());
+                    return match stored {
+                        Some(IdempotentEntry::Completed { fingerprint: stored, status_code, headers, body }) if stored == fingerprint => {
+                            let mut response = Response::with_status(
+                                http::StatusCode::from_u16(status_code).unwrap_or(http::StatusCode::OK),
+                            )
+                            .body(body);
+                            for (name, value) in headers {
+                                response = response.header(name.as_str(), value.as_str());
+                            }
+                            response.header("idempotent-replayed", "true")
+                        }
+                        Some(IdempotentEntry::InProgress { fingerprint: stored }) if stored == fingerprint => {
+                            Response::with_status(http::StatusCode::CONFLICT)
+                                .body("A request with this Idempotency-Key is still being processed")
+                        }
+                        _ => Response::unprocessable_entity()
+                            .body("This Idempotency-Key was already used for a different request"),
+                    };
+                }
+                Err(e) => {
+                    // Without the cache there is no protection, but the request still works
+                    eprintln!("Idempotency cache unavailable: {}", e);
+                    return next(req).await;
+                }
+            }
+
+            let response = next(req).await;
+            let status = response.status_code();
+
+            if status.is_server_error() || status == http::StatusCode::TOO_MANY_REQUESTS {
+                let _ = cache.delete(&cache_key).await;
+                return response;
+            }
+
+            let completed = IdempotentEntry::Completed {
+                fingerprint,
+                status_code: status.as_u16(),
+                headers: response
+                    .headers()
+                    .iter()
+                    .filter(|(name, _)| !UNREPLAYABLE_HEADERS.contains(&name.as_str()))
+                    .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                    .collect(),
+                body: response.body_data().to_vec(),
+            };
+            if let Ok(serialized) = serde_json::to_string(&completed) {
+                if let Err(e) = cache.set(&cache_key, &serialized, Some(ttl)).await {
+                    eprintln!("Failed to store idempotent response: {}", e);
+                }
+            }
+
+            response
+        })
+    }
+}
+
 /// Cache warming utility
 pub struct CacheWarmer {
     cache: Arc<dyn Cache>,
@@ -566,6 +1459,20 @@ mod tests {
         assert_eq!(cache.get("key1").await, None);
     }
 
+    #[tokio::test]
+    async fn test_cache_fake_records_operations() {
+        let cache = CacheFake::new();
+        cache.assert_nothing_written();
+
+        Cache::set(&cache, "key1", "value1", None).await.unwrap();
+        cache.assert_stored("key1", "value1");
+        cache.assert_has("key1").await;
+
+        Cache::delete(&cache, "key1").await.unwrap();
+        cache.assert_deleted("key1");
+        cache.assert_missing("key1").await;
+    }
+
     #[tokio::test]
     async fn test_cache_expiration() {
         let cache = MemoryCache::new(None);
@@ -603,4 +1510,222 @@ mod tests {
         
         assert_eq!(stats.hit_rate(), 0.8);
     }
+
+    #[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+    #[tokio::test]
+    async fn test_idempotency_key_replays_responses() {
+        use std::pin::Pin;
+        use std::future::Future;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let middleware = IdempotencyKey::new(Arc::new(MemoryCache::new(None)));
+
+        let send = |key: &str, body: &str, status: http::StatusCode| {
+            let req = Request::from_parts(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("/payments")
+                    .header("idempotency-key", key)
+                    .body(())
+                    .unwrap()
+                    .into_parts()
+                    .0,
+                body.as_bytes().to_vec(),
+            );
+            let calls = calls.clone();
+            middleware.call(
+                req,
+                Box::new(move |_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                    let count = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    Box::pin(async move {
+                        Response::with_status(status)
+                            .header("x-charge", count.to_string())
+                            .header("set-cookie", "session=first-client")
+                            .body("charged")
+                    })
+                }),
+            )
+        };
+
+        let first = send("abc", "amount=10", http::StatusCode::CREATED).await;
+        assert_eq!(first.status_code(), http::StatusCode::CREATED);
+        assert!(first.headers().get("idempotent-replayed").is_none());
+
+        let retry = send("abc", "amount=10", http::StatusCode::CREATED).await;
+        assert_eq!(retry.status_code(), http::StatusCode::CREATED);
+        assert_eq!(retry.headers().get("x-charge").unwrap(), "1");
+        assert_eq!(retry.headers().get("idempotent-replayed").unwrap(), "true");
+        assert!(retry.headers().get("set-cookie").is_none());
+        assert_eq!(retry.body_data(), b"charged");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let reused = send("abc", "amount=99", http::StatusCode::CREATED).await;
+        assert_eq!(reused.status_code(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Server errors are not stored, so the retry runs the handler again
+        send("def", "amount=10", http::StatusCode::BAD_GATEWAY).await;
+        send("def", "amount=10", http::StatusCode::BAD_GATEWAY).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Bodies are stored as base64 rather than an array of numbers
+        let entry = IdempotentEntry::Completed {
+            fingerprint: "f".to_string(),
+            status_code: 201,
+            headers: Vec::new(),
+            body: b"charged".to_vec(),
+        };
+        let stored = serde_json::to_string(&entry).unwrap();
+        assert!(stored.contains("\"body\":\"Y2hhcmdlZA==\""), "{}", stored);
+        assert!(matches!(serde_json::from_str(&stored), Ok(IdempotentEntry::Completed { body, .. }) if body == b"charged"));
+    }
+
+    #[cfg(all(feature = "json", feature = "sha2", feature = "base64"))]
+    #[tokio::test]
+    async fn test_idempotency_keys_are_scoped_by_caller_and_query() {
+        use std::pin::Pin;
+        use std::future::Future;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let send = |middleware: &IdempotencyKey, uri: &str, user: &str| {
+            let req = Request::mock(http::Method::POST, uri)
+                .with_header("idempotency-key", "retry-1")
+                .with_header("authorization", user)
+                .with_body("to=bob");
+            let calls = calls.clone();
+            middleware.call(
+                req,
+                Box::new(move |req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let body = format!("sent {} for {}", req.query_string().unwrap_or_default(), req.header("authorization").unwrap_or_default());
+                    Box::pin(async move { Response::created().body(body) })
+                }),
+            )
+        };
+
+        let middleware = IdempotencyKey::new(Arc::new(MemoryCache::new(None)));
+        let first = send(&middleware, "/transfer?amount=1", "Bearer ann").await;
+        assert_eq!(first.body_data(), b"sent amount=1 for Bearer ann");
+
+        // Another caller choosing the same key runs its own request
+        let other = send(&middleware, "/transfer?amount=1", "Bearer bob").await;
+        assert_eq!(other.body_data(), b"sent amount=1 for Bearer bob");
+        assert!(other.headers().get("idempotent-replayed").is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The same caller reusing the key with another query string is refused
+        let changed = send(&middleware, "/transfer?amount=1000", "Bearer ann").await;
+        assert_eq!(changed.status_code(), http::StatusCode::UNPROCESSABLE_ENTITY);
+        let retry = send(&middleware, "/transfer?amount=1", "Bearer ann").await;
+        assert_eq!(retry.headers().get("idempotent-replayed").unwrap(), "true");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // A subject callback names callers instead of their headers
+        let middleware = IdempotencyKey::new(Arc::new(MemoryCache::new(None))).subject(|_req| Some("team-1".to_string()));
+        send(&middleware, "/transfer?amount=1", "Bearer ann").await;
+        let teammate = send(&middleware, "/transfer?amount=1", "Bearer bob").await;
+        assert_eq!(teammate.body_data(), b"sent amount=1 for Bearer ann");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cache_fake_stands_in_for_torch_caches() {
+        let fake = <dyn Cache>::fake();
+        let cached = Cached::new(Duration::from_secs(60));
+
+        let handler = |_req: Request| async { Response::ok().body("monthly report") };
+        let response = cached.call(Request::mock(http::Method::GET, "/reports/monthly"), handler).await;
+        assert_eq!(response.body_data(), b"monthly report");
+        assert!(fake.writes().iter().any(|(key, _)| key.ends_with("GET:/reports/monthly")));
+
+        // Spawned work uses the fake too
+        let fake_from_task = tokio::spawn(async { active_fake().is_some() }).await.unwrap();
+        assert!(fake_from_task);
+
+        drop(fake);
+        assert!(active_fake().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_coalesces_concurrent_gets() {
+        use std::pin::Pin;
+        use std::future::Future;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let single_flight = SingleFlight::new();
+
+        let get = |uri: &str, user: &str| {
+            let calls = calls.clone();
+            single_flight.call(
+                Request::mock(http::Method::GET, uri).with_header("authorization", user),
+                Box::new(move |_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                    let calls = calls.clone();
+                    Box::pin(async move {
+                        let count = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Response::ok().header("x-run", count.to_string()).body("report")
+                    })
+                }),
+            )
+        };
+
+        let responses = futures::future::join_all(vec![
+            get("/report?year=2024", "ann"),
+            get("/report?year=2024", "ann"),
+            get("/report?year=2024", "ann"),
+            get("/report?year=2025", "ann"),
+            get("/report?year=2024", "bob"),
+        ])
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(responses[0].headers().get("x-run"), responses[1].headers().get("x-run"));
+        assert_eq!(responses[1].headers().get("x-run"), responses[2].headers().get("x-run"));
+        assert_eq!(responses[2].body_data(), b"report");
+        assert!(single_flight.in_flight.lock().unwrap().is_empty());
+
+        // Once a flight lands, the next request runs the handler again
+        get("/report?year=2024", "ann").await;
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[cfg(feature = "macros")]
+    #[tokio::test]
+    async fn test_cache_attribute_caches_per_vary_header() {
+        use crate::extractors::Path;
+        use crate::App;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        /// Shows a post
+        #[crate::cache(ttl = "60s", vary = ["Accept-Language"])]
+        async fn show(Path(id): Path<u32>) -> Response {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Response::ok().body(format!("post {}", id))
+        }
+
+        let store: Arc<dyn Cache> = Arc::new(MemoryCache::new(None));
+        let app = App::new().with_state(store.clone()).get("/posts/:id", show);
+        let get = |path: &str, language: &str| {
+            app.handle_request(Request::mock(http::Method::GET, path).with_header("accept-language", language))
+        };
+
+        let first = get("/posts/7", "en").await;
+        assert_eq!(first.body_data(), b"post 7");
+        assert_eq!(first.headers().get("x-cache").unwrap(), "MISS");
+        assert_eq!(first.headers().get("vary").unwrap(), "accept-language");
+
+        let again = get("/posts/7", "en").await;
+        assert_eq!(again.headers().get("x-cache").unwrap(), "HIT");
+        assert_eq!(again.body_data(), b"post 7");
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+        get("/posts/7", "fr").await;
+        get("/posts/8", "en").await;
+        assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+        assert!(store.get("torch_cache:GET:/posts/7|accept-language=fr").await.is_some());
+    }
 }
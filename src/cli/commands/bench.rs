@@ -0,0 +1,505 @@
+//! HTTP load testing command
+//!
+//! `torch bench` keeps a fixed number of keep-alive connections busy against
+//! the dev server for a set duration, then reports throughput and latency
+//! percentiles. Results can be saved under a name (the current git branch by
+//! default) and compared later, which makes it easy to check a feature
+//! branch against main before merging.
+
+use colored::*;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, Uri};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+/// Where saved results live, one JSON file per name
+const RESULTS_DIR: &str = "target/torch-bench";
+
+/// Options for `torch bench`
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub target: String,
+    pub host: String,
+    pub port: u16,
+    pub connections: usize,
+    pub duration: Duration,
+    pub method: String,
+    pub headers: Vec<String>,
+    pub body: Option<String>,
+    /// Save the results under this name; an empty name means the current git branch
+    pub save: Option<String>,
+    /// Compare against results saved under this name
+    pub compare: Option<String>,
+    /// Allowed slowdown, in percent, before a comparison fails
+    pub threshold: f64,
+}
+
+/// Parse durations such as `30s`, `2m`, `500ms` or a bare number of seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid duration unit '{}', expected ms, s, m or h", unit)),
+    };
+    if seconds <= 0.0 {
+        return Err("duration must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// A fully resolved request, shared by every connection
+struct Target {
+    uri: Uri,
+    address: String,
+    method: Method,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+}
+
+impl Target {
+    fn from_options(options: &BenchOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        let url = if options.target.starts_with("http://") || options.target.starts_with("https://") {
+            options.target.clone()
+        } else {
+            let path = options.target.trim_start_matches('/');
+            format!("http://{}:{}/{}", options.host, options.port, path)
+        };
+        let uri: Uri = url.parse()?;
+        if uri.scheme_str() != Some("http") {
+            return Err("torch bench only speaks plain HTTP; point it at the dev server".into());
+        }
+        let host = uri.host().ok_or("URL has no host")?;
+        let address = format!("{}:{}", host, uri.port_u16().unwrap_or(80));
+
+        let mut headers = Vec::new();
+        for header in &options.headers {
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid header '{}', expected 'Name: value'", header))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        Ok(Self {
+            method: options.method.to_uppercase().parse()?,
+            body: Bytes::from(options.body.clone().unwrap_or_default()),
+            uri,
+            address,
+            headers,
+        })
+    }
+
+    fn label(&self) -> String {
+        format!("{} {}", self.method, self.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/"))
+    }
+
+    fn request(&self) -> Result<Request<Full<Bytes>>, hyper::http::Error> {
+        let mut builder = Request::builder()
+            .method(self.method.clone())
+            .uri(self.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/"))
+            .header(hyper::header::HOST, self.uri.authority().map(|authority| authority.as_str()).unwrap_or("localhost"));
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder.body(Full::new(self.body.clone()))
+    }
+}
+
+/// What one connection observed
+#[derive(Default)]
+struct ConnectionStats {
+    latencies: Vec<u32>,
+    statuses: BTreeMap<u16, u64>,
+    errors: u64,
+    bytes: u64,
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Send requests over one keep-alive connection until the deadline, reconnecting after errors
+async fn run_connection(target: Arc<Target>, deadline: Instant) -> ConnectionStats {
+    let mut stats = ConnectionStats::default();
+
+    while Instant::now() < deadline {
+        let mut sender = match tokio::time::timeout_at(deadline, connect(&target)).await {
+            Ok(Ok(sender)) => sender,
+            Ok(Err(_)) => {
+                stats.errors += 1;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+            Err(_) => break,
+        };
+
+        while Instant::now() < deadline {
+            let started = Instant::now();
+            match tokio::time::timeout_at(deadline, send(&mut sender, &target)).await {
+                Ok(Ok((status, bytes))) => {
+                    let micros = started.elapsed().as_micros().min(u32::MAX as u128) as u32;
+                    stats.latencies.push(micros);
+                    *stats.statuses.entry(status).or_default() += 1;
+                    stats.bytes += bytes;
+                }
+                Ok(Err(_)) => {
+                    stats.errors += 1;
+                    break;
+                }
+                // Requests still in flight at the deadline are not counted
+                Err(_) => break,
+            }
+        }
+    }
+
+    stats
+}
+
+async fn connect(target: &Target) -> Result<hyper::client::conn::http1::SendRequest<Full<Bytes>>, BoxError> {
+    let stream = TcpStream::connect(&target.address).await?;
+    stream.set_nodelay(true)?;
+    let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(connection);
+    Ok(sender)
+}
+
+async fn send(
+    sender: &mut hyper::client::conn::http1::SendRequest<Full<Bytes>>,
+    target: &Target,
+) -> Result<(u16, u64), BoxError> {
+    sender.ready().await?;
+    let response = sender.send_request(target.request()?).await?;
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, body.len() as u64))
+}
+
+/// Summary of a run, as printed and saved
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchReport {
+    pub target: String,
+    pub connections: usize,
+    pub duration_secs: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub non_success: u64,
+    pub bytes: u64,
+    pub requests_per_sec: f64,
+    pub latency_ms: Latency,
+}
+
+/// Latency distribution in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Latency {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Latency {
+    /// Build from microsecond samples, sorting them in place
+    fn from_samples(samples: &mut [u32]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let ms = |micros: u32| micros as f64 / 1000.0;
+        let percentile = |p: f64| {
+            let rank = ((p / 100.0) * samples.len() as f64).ceil() as usize;
+            ms(samples[rank.clamp(1, samples.len()) - 1])
+        };
+        let total: u64 = samples.iter().map(|micros| *micros as u64).sum();
+
+        Self {
+            min: ms(samples[0]),
+            mean: total as f64 / samples.len() as f64 / 1000.0,
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            max: ms(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// Run a load test and print, save or compare its results
+pub fn run_bench(options: BenchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let target = Arc::new(Target::from_options(&options)?);
+    let baseline = match &options.compare {
+        Some(name) => Some((name, load_result(name, &target.label())?)),
+        None => None,
+    };
+    let connections = options.connections.max(1);
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    runtime.block_on(async {
+        let mut sender = connect(&target).await.map_err(|e| {
+            format!("Could not connect to {} ({}). Is `torch serve` running?", target.address, e)
+        })?;
+        send(&mut sender, &target).await.map_err(|e| format!("Warm-up request failed: {}", e))?;
+        Ok::<_, Box<dyn std::error::Error>>(())
+    })?;
+
+    println!(
+        "{} Running {} test @ {} {}",
+        "🔥".yellow(),
+        format_duration(options.duration),
+        target.method,
+        target.uri.to_string().cyan()
+    );
+    println!("   {} connections", connections);
+
+    let started = std::time::Instant::now();
+    let stats = runtime.block_on(async {
+        let deadline = Instant::now() + options.duration;
+        let tasks: Vec<_> = (0..connections)
+            .map(|_| tokio::spawn(run_connection(target.clone(), deadline)))
+            .collect();
+        let mut all = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            all.push(task.await.unwrap_or_default());
+        }
+        all
+    });
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let report = summarize(&target.label(), connections, elapsed, stats);
+    print_report(&report);
+
+    if let Some((name, baseline)) = baseline {
+        let regressions = compare(&baseline, &report, options.threshold);
+        print_comparison(name, &baseline, &report);
+        for regression in &regressions {
+            println!("{} {}", "❌".red(), regression);
+        }
+        if let Some(save) = &options.save {
+            save_result(save, &report)?;
+        }
+        if !regressions.is_empty() {
+            return Err(format!("Performance regressed more than {}% against '{}'", options.threshold, name).into());
+        }
+        println!("{} Within {}% of '{}'", "✅".green(), options.threshold, name);
+    } else if let Some(save) = &options.save {
+        save_result(save, &report)?;
+    }
+
+    Ok(())
+}
+
+fn summarize(target: &str, connections: usize, elapsed: f64, stats: Vec<ConnectionStats>) -> BenchReport {
+    let mut latencies = Vec::new();
+    let mut statuses: BTreeMap<u16, u64> = BTreeMap::new();
+    let mut errors = 0;
+    let mut bytes = 0;
+    for connection in stats {
+        latencies.extend(connection.latencies);
+        for (status, count) in connection.statuses {
+            *statuses.entry(status).or_default() += count;
+        }
+        errors += connection.errors;
+        bytes += connection.bytes;
+    }
+
+    let requests = latencies.len() as u64;
+    let non_success = statuses.iter().filter(|(status, _)| **status >= 400).map(|(_, count)| count).sum();
+    BenchReport {
+        target: target.to_string(),
+        connections,
+        duration_secs: elapsed,
+        requests,
+        errors,
+        non_success,
+        bytes,
+        requests_per_sec: if elapsed > 0.0 { requests as f64 / elapsed } else { 0.0 },
+        latency_ms: Latency::from_samples(&mut latencies),
+    }
+}
+
+fn print_report(report: &BenchReport) {
+    let latency = &report.latency_ms;
+    println!();
+    println!("{}", "Latency".bold());
+    println!("   min {:>9.2}ms   mean {:>9.2}ms   max {:>9.2}ms", latency.min, latency.mean, latency.max);
+    println!("   p50 {:>9.2}ms   p90  {:>9.2}ms   p99 {:>9.2}ms", latency.p50, latency.p90, latency.p99);
+    println!();
+    println!(
+        "{} {} requests in {:.2}s, {} read",
+        "📊".blue(),
+        report.requests,
+        report.duration_secs,
+        format_bytes(report.bytes)
+    );
+    println!("   {} {:.2}", "Requests/sec:".bold(), report.requests_per_sec);
+    if report.non_success > 0 {
+        println!("   {} {}", "4xx/5xx responses:".yellow(), report.non_success);
+    }
+    if report.errors > 0 {
+        println!("   {} {}", "Connection errors:".red(), report.errors);
+    }
+}
+
+/// Regressions of `current` against `baseline` beyond `threshold` percent
+fn compare(baseline: &BenchReport, current: &BenchReport, threshold: f64) -> Vec<String> {
+    let mut regressions = Vec::new();
+    let rps = percent_change(baseline.requests_per_sec, current.requests_per_sec);
+    if rps < -threshold {
+        regressions.push(format!("Requests/sec dropped {:.1}%", -rps));
+    }
+    for (name, before, after) in [
+        ("p50", baseline.latency_ms.p50, current.latency_ms.p50),
+        ("p99", baseline.latency_ms.p99, current.latency_ms.p99),
+    ] {
+        let change = percent_change(before, after);
+        if change > threshold {
+            regressions.push(format!("{} latency rose {:.1}%", name, change));
+        }
+    }
+    regressions
+}
+
+fn percent_change(before: f64, after: f64) -> f64 {
+    if before == 0.0 {
+        0.0
+    } else {
+        (after - before) / before * 100.0
+    }
+}
+
+fn print_comparison(name: &str, baseline: &BenchReport, current: &BenchReport) {
+    println!();
+    println!("{} {}", "Compared with".bold(), name.cyan());
+    let rows = [
+        ("Requests/sec", baseline.requests_per_sec, current.requests_per_sec, true),
+        ("p50 (ms)", baseline.latency_ms.p50, current.latency_ms.p50, false),
+        ("p90 (ms)", baseline.latency_ms.p90, current.latency_ms.p90, false),
+        ("p99 (ms)", baseline.latency_ms.p99, current.latency_ms.p99, false),
+    ];
+    for (label, before, after, higher_is_better) in rows {
+        let change = percent_change(before, after);
+        let better = if higher_is_better { change >= 0.0 } else { change <= 0.0 };
+        let change = format!("{:+.1}%", change);
+        let change = if better { change.green() } else { change.red() };
+        println!("   {:<14} {:>12.2} → {:>12.2}  {}", label, before, after, change);
+    }
+}
+
+/// Name used when `--save` is given without one
+fn current_branch() -> Option<String> {
+    let output = Command::new("git").args(["branch", "--show-current"]).output().ok()?;
+    let branch = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !branch.is_empty()).then_some(branch)
+}
+
+fn result_path(name: &str) -> PathBuf {
+    let file: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '-' })
+        .collect();
+    Path::new(RESULTS_DIR).join(format!("{}.json", file))
+}
+
+/// Saved results are keyed by `METHOD /path`, so one name can hold several routes
+fn save_result(name: &str, report: &BenchReport) -> Result<(), Box<dyn std::error::Error>> {
+    let name = if name.is_empty() {
+        current_branch().ok_or("Not in a git repository; pass a name to --save")?
+    } else {
+        name.to_string()
+    };
+    let path = result_path(&name);
+
+    let mut results: BTreeMap<String, BenchReport> = match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)?,
+        Err(_) => BTreeMap::new(),
+    };
+    results.insert(report.target.clone(), report.clone());
+
+    fs::create_dir_all(RESULTS_DIR)?;
+    fs::write(&path, serde_json::to_string_pretty(&results)?)?;
+    println!("{} Saved results as '{}' ({})", "💾".blue(), name, path.display());
+    Ok(())
+}
+
+fn load_result(name: &str, target: &str) -> Result<BenchReport, Box<dyn std::error::Error>> {
+    let path = if name.ends_with(".json") { PathBuf::from(name) } else { result_path(name) };
+    let contents = fs::read_to_string(&path).map_err(|_| {
+        format!("No saved results named '{}'. Run `torch bench <route> --save {}` on that branch first", name, name)
+    })?;
+    let mut results: BTreeMap<String, BenchReport> = serde_json::from_str(&contents)?;
+    results
+        .remove(target)
+        .ok_or_else(|| format!("'{}' has no results for {}", name, target).into())
+}
+
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis % 1000 != 0 {
+        format!("{}ms", millis)
+    } else {
+        format!("{}s", millis / 1000)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2}{}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("5").unwrap(), Duration::from_secs(5));
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("0s").is_err());
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut samples: Vec<u32> = (1..=100).rev().map(|ms| ms * 1000).collect();
+        let latency = Latency::from_samples(&mut samples);
+        assert_eq!(latency.min, 1.0);
+        assert_eq!(latency.p50, 50.0);
+        assert_eq!(latency.p90, 90.0);
+        assert_eq!(latency.p99, 99.0);
+        assert_eq!(latency.max, 100.0);
+        assert_eq!(latency.mean, 50.5);
+    }
+
+    #[test]
+    fn test_compare_flags_regressions_beyond_threshold() {
+        let baseline = summarize("GET /", 1, 1.0, vec![ConnectionStats { latencies: vec![1000; 100], ..Default::default() }]);
+
+        let mut slower = baseline.clone();
+        slower.requests_per_sec = 80.0;
+        slower.latency_ms.p99 = 1.05;
+        let regressions = compare(&baseline, &slower, 10.0);
+        assert_eq!(regressions, vec!["Requests/sec dropped 20.0%".to_string()]);
+
+        assert!(compare(&baseline, &baseline, 10.0).is_empty());
+    }
+}
@@ -0,0 +1,30 @@
+//! Application console commands
+
+use colored::*;
+use std::path::Path;
+use std::process::Command;
+
+/// Run an app command registered on `torch_web::console::Console` by
+/// starting the app with `args`, exiting with the command's exit code
+pub fn run_app_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if !Path::new("Cargo.toml").exists() {
+        return Err(format!(
+            "'{}' is not a torch command, and there is no Cargo.toml here to look for an app command",
+            args.first().map_or("", String::as_str)
+        )
+        .into());
+    }
+
+    println!("{} Running app command: {}", "⚙️".yellow(), args.join(" ").cyan());
+    let status = Command::new("cargo")
+        .args(["run", "--quiet", "--"])
+        .args(args)
+        .env(crate::console::FORWARDED_BY_TORCH, "1")
+        .status()?;
+
+    // The app prints its own errors; only pass the exit code on
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
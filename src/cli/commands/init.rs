@@ -564,16 +564,7 @@ fn generate_config_file(force: bool, env: &str) -> Result<(), Box<dyn std::error
 
 /// Generate application key
 fn generate_app_key(show_only: bool) -> Result<(), Box<dyn std::error::Error>> {
-    use rand::{Rng, thread_rng};
-    use rand::distributions::Alphanumeric;
-    
-    let key: String = thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect();
-    
-    let app_key = format!("base64:{}", base64::encode(&key));
+    let app_key = crate::security::encryption::generate_app_key();
     
     if show_only {
         println!("{}", app_key);
@@ -0,0 +1,459 @@
+//! Application key rotation commands
+
+use super::db::{block_on, quote_identifier, DatabaseSettings};
+use crate::cli::KeyOperation;
+use crate::orm::connection::{try_get_pool, placeholders};
+use crate::orm::DatabaseDriver;
+use crate::security::encryption::{generate_app_key, Encrypter, APP_KEY_ENV, APP_PREVIOUS_KEYS_ENV};
+use colored::*;
+use sqlx::Row;
+use std::fs;
+
+/// Rows read per query during re-encryption
+const BATCH_SIZE: i64 = 500;
+
+/// Handle key operations
+pub fn handle_operation(operation: KeyOperation) -> Result<(), Box<dyn std::error::Error>> {
+    match operation {
+        KeyOperation::Rotate { reencrypt } => rotate(&reencrypt),
+        KeyOperation::Reencrypt { columns } => {
+            let keys = Keys::load()?;
+            reencrypt_columns(&keys.encrypter()?, &columns)
+        }
+    }
+}
+
+/// Where the application key was found
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeySource {
+    Environment,
+    DotEnv,
+    TorchToml,
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Environment => write!(f, "the {} environment variable", APP_KEY_ENV),
+            KeySource::DotEnv => write!(f, ".env"),
+            KeySource::TorchToml => write!(f, "torch.toml"),
+        }
+    }
+}
+
+/// The current key and the retired keys still accepted for decryption
+#[derive(Debug, Clone, PartialEq)]
+struct Keys {
+    current: String,
+    previous: Vec<String>,
+    source: KeySource,
+}
+
+impl Keys {
+    /// Resolve keys from the environment, `.env`, then `torch.toml`
+    fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        if let Ok(current) = std::env::var(APP_KEY_ENV) {
+            let previous = std::env::var(APP_PREVIOUS_KEYS_ENV).unwrap_or_default();
+            return Ok(Self {
+                current,
+                previous: split_keys(&previous),
+                source: KeySource::Environment,
+            });
+        }
+
+        if let Ok(contents) = fs::read_to_string(".env") {
+            let env = super::tinker::parse_env(&contents);
+            if let Some(current) = env.get(APP_KEY_ENV).filter(|key| !key.is_empty()) {
+                return Ok(Self {
+                    current: current.clone(),
+                    previous: split_keys(env.get(APP_PREVIOUS_KEYS_ENV).map_or("", String::as_str)),
+                    source: KeySource::DotEnv,
+                });
+            }
+        }
+
+        if let Ok(contents) = fs::read_to_string("torch.toml") {
+            if let Some(keys) = Self::from_toml(&contents)? {
+                return Ok(keys);
+            }
+        }
+
+        Err(format!(
+            "No application key found in {}, .env or torch.toml\n{} Run 'torch init key' to generate one",
+            APP_KEY_ENV,
+            "💡".yellow()
+        )
+        .into())
+    }
+
+    fn from_toml(contents: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let config: toml::Value = contents.parse()?;
+        let Some(app) = config.get("app") else {
+            return Ok(None);
+        };
+        let Some(current) = app.get("key").and_then(|key| key.as_str()).filter(|key| !key.is_empty()) else {
+            return Ok(None);
+        };
+        let previous = app
+            .get("previous_keys")
+            .and_then(|keys| keys.as_array())
+            .map(|keys| keys.iter().filter_map(|key| key.as_str().map(str::to_string)).collect())
+            .unwrap_or_default();
+
+        Ok(Some(Self {
+            current: current.to_string(),
+            previous,
+            source: KeySource::TorchToml,
+        }))
+    }
+
+    fn encrypter(&self) -> Result<Encrypter, Box<dyn std::error::Error>> {
+        Ok(Encrypter::new(&self.current)?.with_previous_keys(&self.previous)?)
+    }
+
+    /// A new current key, with the old one first in line for decryption
+    fn rotate(&self, new_key: String) -> Self {
+        let mut previous = vec![self.current.clone()];
+        previous.extend(self.previous.iter().filter(|key| **key != self.current).cloned());
+        Self {
+            current: new_key,
+            previous,
+            source: self.source,
+        }
+    }
+
+    /// Write the keys back to where they were loaded from
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.source {
+            KeySource::Environment => Err("Keys from the environment can't be saved".into()),
+            KeySource::DotEnv => {
+                let contents = fs::read_to_string(".env")?;
+                let updated = set_env_values(
+                    &contents,
+                    &[(APP_KEY_ENV, &self.current), (APP_PREVIOUS_KEYS_ENV, &self.previous.join(","))],
+                );
+                fs::write(".env", updated)?;
+                Ok(())
+            }
+            KeySource::TorchToml => {
+                let contents = fs::read_to_string("torch.toml")?;
+                fs::write("torch.toml", set_toml_keys(&contents, &self.current, &self.previous))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn split_keys(keys: &str) -> Vec<String> {
+    keys.split(',').map(str::trim).filter(|key| !key.is_empty()).map(str::to_string).collect()
+}
+
+/// Replace `NAME=value` lines in a `.env` file, appending the ones that are missing
+fn set_env_values(contents: &str, values: &[(&str, &str)]) -> String {
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+
+    for (name, value) in values {
+        let line = format!("{}={}", name, value);
+        let existing = lines.iter().position(|existing| {
+            let existing = existing.trim_start();
+            let existing = existing.strip_prefix("export ").unwrap_or(existing);
+            existing.split_once('=').is_some_and(|(key, _)| key.trim() == *name)
+        });
+        match existing {
+            Some(index) => lines[index] = line,
+            None => lines.push(line),
+        }
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    updated
+}
+
+/// Replace `key` and `previous_keys` in the `[app]` table of torch.toml,
+/// leaving the rest of the file and its comments untouched
+fn set_toml_keys(contents: &str, key: &str, previous: &[String]) -> String {
+    let previous_line = format!(
+        "previous_keys = [{}]",
+        previous.iter().map(|key| format!("\"{}\"", key)).collect::<Vec<_>>().join(", ")
+    );
+    let key_line = format!("key = \"{}\"", key);
+
+    let setting = |line: &str, name: &str| {
+        line.split_once('=').is_some_and(|(setting, _)| setting.trim() == name)
+    };
+
+    let mut lines = Vec::new();
+    let mut in_app = false;
+    let mut key_index = None;
+    let mut wrote_previous = false;
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_app = trimmed == "[app]";
+        } else if in_app && setting(trimmed, "key") {
+            key_index = Some(lines.len());
+            lines.push(key_line.clone());
+            continue;
+        } else if in_app && setting(trimmed, "previous_keys") {
+            wrote_previous = true;
+            lines.push(previous_line.clone());
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+
+    if !wrote_previous {
+        if let Some(index) = key_index {
+            lines.insert(index + 1, previous_line);
+        }
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    updated
+}
+
+/// Generate a new key, keep the old one for decryption and optionally
+/// re-encrypt stored values
+fn rotate(columns: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let keys = Keys::load()?;
+    keys.encrypter().map_err(|e| format!("The current key in {} is invalid: {}", keys.source, e))?;
+
+    println!("{} Rotating application key from {}...", "🔑".yellow(), keys.source);
+    let rotated = keys.rotate(generate_app_key());
+
+    if rotated.source == KeySource::Environment {
+        println!("{} Keys come from the environment, so set these yourself:", "💡".yellow());
+        println!("    {}={}", APP_KEY_ENV, rotated.current);
+        println!("    {}={}", APP_PREVIOUS_KEYS_ENV, rotated.previous.join(","));
+    } else {
+        rotated.save()?;
+        println!("{} New key written to {}", "✅".green(), rotated.source);
+        println!(
+            "{} {} previous key(s) kept for decrypting existing data",
+            "✅".green(),
+            rotated.previous.len()
+        );
+    }
+
+    if !columns.is_empty() {
+        println!();
+        reencrypt_columns(&rotated.encrypter()?, columns)?;
+    }
+
+    println!();
+    println!("{} Restart running servers and workers so they pick up the new key", "💡".blue());
+    println!(
+        "{} Cookies from EncryptCookies and signed URLs made with an old key keep working while it is listed in {}",
+        "💡".blue(),
+        APP_PREVIOUS_KEYS_ENV
+    );
+    for warning in session_warnings(rotated.source) {
+        println!("{} {}", "⚠️".yellow(), warning);
+    }
+    if columns.is_empty() {
+        println!(
+            "{} Re-encrypt stored values with 'torch key reencrypt <table.column>...'",
+            "💡".blue()
+        );
+    }
+    println!(
+        "{} Drop old entries from {} once stored values are re-encrypted and old cookies have expired",
+        "💡".blue(),
+        APP_PREVIOUS_KEYS_ENV
+    );
+
+    Ok(())
+}
+
+/// What stops reading data encrypted with the old key once the app
+/// restarts, signing users out
+fn session_warnings(source: KeySource) -> Vec<String> {
+    let mut warnings = vec![format!(
+        "Sessions and cookies encrypted by an Encrypter built from {} alone, without with_previous_keys, can't be read with the new key; their users will be signed out",
+        APP_KEY_ENV
+    )];
+    if source == KeySource::TorchToml {
+        warnings.push(format!(
+            "Encrypter::from_env and EncryptCookies::from_env read {} and {} from the environment, not torch.toml; set both before restarting or every session becomes unreadable",
+            APP_KEY_ENV, APP_PREVIOUS_KEYS_ENV
+        ));
+    }
+    warnings
+}
+
+/// Split `table.column`, allowing only plain identifiers
+fn parse_column(spec: &str) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let valid = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match spec.trim().split_once('.') {
+        Some((table, column)) if valid(table) && valid(column) => Ok((table.to_string(), column.to_string())),
+        _ => Err(format!("Invalid column '{}', expected table.column (e.g. users.ssn)", spec).into()),
+    }
+}
+
+/// Outcome of re-encrypting one column
+#[derive(Debug, Default)]
+struct ColumnReport {
+    reencrypted: u64,
+    current: u64,
+    unreadable: u64,
+}
+
+/// Re-encrypt every listed column with the current key
+fn reencrypt_columns(encrypter: &Encrypter, columns: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let targets = columns.iter().map(|column| parse_column(column)).collect::<Result<Vec<_>, _>>()?;
+    let settings = DatabaseSettings::load()?;
+
+    block_on(async {
+        settings.connect_orm().await?;
+
+        for (table, column) in &targets {
+            println!("{} Re-encrypting {}.{}...", "🔄".yellow(), table.cyan(), column.cyan());
+            let report = reencrypt_column(encrypter, &settings.driver, table, column).await?;
+
+            println!(
+                "{} {} re-encrypted, {} already on the current key",
+                "✅".green(),
+                report.reencrypted,
+                report.current
+            );
+            if report.unreadable > 0 {
+                println!(
+                    "{} {} value(s) could not be decrypted with any known key and were left as they are",
+                    "⚠️".yellow(),
+                    report.unreadable
+                );
+            }
+        }
+
+        Ok::<_, Box<dyn std::error::Error>>(())
+    })
+}
+
+/// Primary key of a row being re-encrypted
+enum RowId {
+    Integer(i64),
+    Text(String),
+}
+
+/// Walk the table in primary key order, rewriting values that were encrypted
+/// with a previous key. Rows already on the current key are skipped, so an
+/// interrupted run can simply be started again.
+async fn reencrypt_column(
+    encrypter: &Encrypter,
+    driver: &DatabaseDriver,
+    table: &str,
+    column: &str,
+) -> Result<ColumnReport, Box<dyn std::error::Error>> {
+    let id_column = quote_identifier(driver, "id");
+    let table = quote_identifier(driver, table);
+    let column = quote_identifier(driver, column);
+
+    let update = placeholders(&format!("UPDATE {} SET {} = ? WHERE {} = ?", table, column, id_column));
+    let mut report = ColumnReport::default();
+    let mut last: Option<RowId> = None;
+
+    loop {
+        let after = if last.is_some() { format!(" AND {} > ?", id_column) } else { String::new() };
+        let select = placeholders(&format!(
+            "SELECT {id}, {column} FROM {table} WHERE {column} IS NOT NULL{after} ORDER BY {id} LIMIT {limit}",
+            id = id_column,
+            column = column,
+            table = table,
+            after = after,
+            limit = BATCH_SIZE,
+        ));
+        let query = match &last {
+            Some(RowId::Integer(id)) => sqlx::query(&select).bind(*id),
+            Some(RowId::Text(id)) => sqlx::query(&select).bind(id.clone()),
+            None => sqlx::query(&select),
+        };
+        let rows = query.fetch_all(&try_get_pool()?).await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in &rows {
+            let id = match row.try_get::<i64, _>(0) {
+                Ok(id) => RowId::Integer(id),
+                Err(_) => RowId::Text(row.try_get::<String, _>(0)?),
+            };
+            let value: String = row.try_get(1)?;
+
+            match encrypter.reencrypt(&value) {
+                Ok(Some(reencrypted)) => {
+                    let query = sqlx::query(&update).bind(reencrypted);
+                    let query = match &id {
+                        RowId::Integer(id) => query.bind(*id),
+                        RowId::Text(id) => query.bind(id.clone()),
+                    };
+                    query.execute(&try_get_pool()?).await?;
+                    report.reencrypted += 1;
+                }
+                Ok(None) => report.current += 1,
+                Err(_) => report.unreadable += 1,
+            }
+            last = Some(id);
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_keeps_previous_keys() {
+        let keys = Keys {
+            current: "base64:b".to_string(),
+            previous: vec!["base64:a".to_string()],
+            source: KeySource::DotEnv,
+        };
+        let rotated = keys.rotate("base64:c".to_string());
+        assert_eq!(rotated.current, "base64:c");
+        assert_eq!(rotated.previous, vec!["base64:b", "base64:a"]);
+    }
+
+    #[test]
+    fn test_session_warnings_name_what_signs_users_out() {
+        let warnings = session_warnings(KeySource::DotEnv);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("signed out"));
+
+        let warnings = session_warnings(KeySource::TorchToml);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].contains(APP_PREVIOUS_KEYS_ENV));
+    }
+
+    #[test]
+    fn test_set_env_values() {
+        let env = "APP_NAME=demo\nexport APP_KEY=base64:old\n";
+        let updated = set_env_values(env, &[(APP_KEY_ENV, "base64:new"), (APP_PREVIOUS_KEYS_ENV, "base64:old")]);
+        assert_eq!(updated, "APP_NAME=demo\nAPP_KEY=base64:new\nAPP_PREVIOUS_KEYS=base64:old\n");
+    }
+
+    #[test]
+    fn test_set_toml_keys_only_touches_app_table() {
+        let toml = "[app]\n# Application key\nkey = \"base64:old\"\ncipher = \"AES-256-GCM\"\n\n[services.ses]\nkey = \"\"\n";
+        let updated = set_toml_keys(toml, "base64:new", &["base64:old".to_string()]);
+        assert_eq!(
+            updated,
+            "[app]\n# Application key\nkey = \"base64:new\"\nprevious_keys = [\"base64:old\"]\ncipher = \"AES-256-GCM\"\n\n[services.ses]\nkey = \"\"\n"
+        );
+
+        let keys = Keys::from_toml(&updated).unwrap().unwrap();
+        assert_eq!(keys.current, "base64:new");
+        assert_eq!(keys.previous, vec!["base64:old"]);
+    }
+
+    #[test]
+    fn test_parse_column() {
+        assert_eq!(parse_column("users.ssn").unwrap(), ("users".to_string(), "ssn".to_string()));
+        assert!(parse_column("users").is_err());
+        assert!(parse_column("users.ssn; drop table users").is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! Log viewing commands
+//!
+//! `torch logs` reads the file channels configured under `[logging]` in
+//! torch.toml or config/app.toml, so nobody has to remember where each
+//! channel writes or how daily files are named.
+
+use clap::ValueEnum;
+use colored::*;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often followed files are checked for new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where logs go when nothing is configured
+const DEFAULT_LOG_PATH: &str = "storage/logs/torch.log";
+
+/// Minimum severity shown by `--level`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Recognise tracing, log and Laravel-style level names
+    fn parse(word: &str) -> Option<Self> {
+        match word.to_ascii_lowercase().as_str() {
+            "trace" => Some(Self::Trace),
+            "debug" => Some(Self::Debug),
+            "info" | "notice" => Some(Self::Info),
+            "warn" | "warning" => Some(Self::Warn),
+            "error" | "err" | "critical" | "alert" | "emergency" | "fatal" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// Options for `torch logs`
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    pub follow: bool,
+    pub lines: usize,
+    pub level: Option<LogLevel>,
+    pub request_id: Option<String>,
+    pub json: bool,
+    pub channel: Option<String>,
+}
+
+/// How a channel names its files
+#[derive(Debug, Clone, PartialEq)]
+enum Driver {
+    /// Everything goes to `path`
+    Single,
+    /// One file per day next to `path`, e.g. `torch-2024-01-15.log` or `torch.log.2024-01-15`
+    Daily,
+}
+
+/// A channel that writes to disk
+#[derive(Debug, Clone, PartialEq)]
+struct LogChannel {
+    name: String,
+    driver: Driver,
+    path: PathBuf,
+}
+
+impl LogChannel {
+    /// The file currently being written to, if there is one yet
+    fn current_file(&self) -> Option<PathBuf> {
+        match self.driver {
+            Driver::Single => self.path.exists().then(|| self.path.clone()),
+            Driver::Daily => latest_daily_file(&self.path),
+        }
+    }
+}
+
+/// Newest dated sibling of `path`. Dates sort lexically, so the greatest name wins.
+fn latest_daily_file(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path.file_name()?.to_str()?;
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (file_name, String::new()),
+    };
+    let laravel_prefix = format!("{}-", stem);
+    let appender_prefix = format!("{}.", file_name);
+
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| {
+            name.strip_prefix(&laravel_prefix)
+                .and_then(|rest| rest.strip_suffix(extension.as_str()))
+                .or_else(|| name.strip_prefix(&appender_prefix))
+                .is_some_and(|date| date.starts_with(|c: char| c.is_ascii_digit()))
+        })
+        .max()
+        .map(|name| dir.join(name))
+}
+
+/// Resolve file channels from `[logging]` in torch.toml, then config/app.toml
+fn discover_channels(only: Option<&str>) -> Result<Vec<LogChannel>, Box<dyn std::error::Error>> {
+    let mut channels = None;
+    for config in ["torch.toml", "config/app.toml"] {
+        if let Ok(contents) = fs::read_to_string(config) {
+            let logging = contents.parse::<toml::Value>()?.get("logging").cloned();
+            if let Some(logging) = logging {
+                channels = Some(channels_from_config(&logging, only)?);
+                break;
+            }
+        }
+    }
+
+    let channels = match channels {
+        Some(channels) => channels,
+        None => match only {
+            Some(name) => return Err(format!("Log channel '{}' is not configured", name).into()),
+            None => vec![LogChannel {
+                name: "single".to_string(),
+                driver: Driver::Single,
+                path: PathBuf::from(DEFAULT_LOG_PATH),
+            }],
+        },
+    };
+
+    if channels.is_empty() {
+        return Err("No file log channels are configured; console and stderr channels can't be read back".into());
+    }
+    Ok(channels)
+}
+
+/// File channels named by a `[logging]` table.
+///
+/// Understands the `default`/stack layout written by `torch init config`, a
+/// `channels = [...]` list, and the shorthand `file = "..."` setting.
+fn channels_from_config(logging: &toml::Value, only: Option<&str>) -> Result<Vec<LogChannel>, Box<dyn std::error::Error>> {
+    let definitions = logging.get("channels").and_then(|channels| channels.as_table());
+
+    if definitions.is_none() && only.is_none() {
+        if let Some(file) = logging.get("file").and_then(|file| file.as_str()) {
+            return Ok(vec![LogChannel {
+                name: "file".to_string(),
+                driver: Driver::Single,
+                path: PathBuf::from(file),
+            }]);
+        }
+    }
+
+    let Some(definitions) = definitions else {
+        return Ok(Vec::new());
+    };
+
+    let mut pending: Vec<String> = match only {
+        Some(name) => vec![name.to_string()],
+        None => match (logging.get("default").and_then(|name| name.as_str()), logging.get("channels")) {
+            (Some(default), _) => vec![default.to_string()],
+            (None, Some(toml::Value::Array(names))) => {
+                names.iter().filter_map(|name| name.as_str().map(str::to_string)).collect()
+            }
+            _ => definitions.keys().cloned().collect(),
+        },
+    };
+    pending.reverse();
+
+    let mut seen = Vec::new();
+    let mut channels = Vec::new();
+    while let Some(name) = pending.pop() {
+        if seen.contains(&name) {
+            continue;
+        }
+        seen.push(name.clone());
+
+        let definition = definitions
+            .get(&name)
+            .ok_or_else(|| format!("Log channel '{}' is not configured", name))?;
+        let driver = definition.get("driver").and_then(|driver| driver.as_str()).unwrap_or("single");
+        let path = definition.get("path").and_then(|path| path.as_str()).unwrap_or(DEFAULT_LOG_PATH);
+
+        match driver {
+            "stack" => {
+                let members = definition.get("channels").and_then(|members| members.as_array());
+                for member in members.into_iter().flatten().rev() {
+                    if let Some(member) = member.as_str() {
+                        pending.push(member.to_string());
+                    }
+                }
+            }
+            "single" | "file" => channels.push(LogChannel { name, driver: Driver::Single, path: PathBuf::from(path) }),
+            "daily" => channels.push(LogChannel { name, driver: Driver::Daily, path: PathBuf::from(path) }),
+            _ => {}
+        }
+    }
+
+    Ok(channels)
+}
+
+/// One parsed log line
+#[derive(Debug, Clone, Default, PartialEq)]
+struct LogEntry {
+    timestamp: Option<String>,
+    level: Option<LogLevel>,
+    message: String,
+    request_id: Option<String>,
+}
+
+/// Remove ANSI colour codes written by pretty console formatters
+fn strip_ansi(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\u{1b}' {
+            for code in chars.by_ref() {
+                if code.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(ch);
+        }
+    }
+    plain
+}
+
+/// Parse a JSON line from `tracing-subscriber`'s JSON formatter or a similar logger
+fn parse_json_line(line: &str) -> Option<LogEntry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let object = value.as_object()?;
+    let text = |value: &Value| match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    };
+    let field = |name: &str| {
+        object
+            .get(name)
+            .or_else(|| object.get("fields").and_then(|fields| fields.get(name)))
+            .or_else(|| object.get("context").and_then(|context| context.get(name)))
+            .or_else(|| object.get("span").and_then(|span| span.get(name)))
+            .and_then(text)
+    };
+
+    let request_id = field("request_id").or_else(|| {
+        object
+            .get("spans")?
+            .as_array()?
+            .iter()
+            .rev()
+            .find_map(|span| span.get("request_id").and_then(text))
+    });
+
+    Some(LogEntry {
+        timestamp: field("timestamp").or_else(|| field("time")).or_else(|| field("ts")),
+        level: field("level").or_else(|| field("level_name")).and_then(|level| LogLevel::parse(&level)),
+        message: field("message").or_else(|| field("msg")).unwrap_or_default(),
+        request_id,
+    })
+}
+
+/// Parse a plain text line such as `2024-01-15T10:30:00Z  WARN app: slow query request_id=abc`
+/// or `[2024-01-15 10:30:00] production.WARNING: slow query`
+fn parse_text_line(line: &str) -> LogEntry {
+    let plain = strip_ansi(line);
+    let words: Vec<&str> = plain.split_whitespace().collect();
+
+    let level_at = words.iter().take(6).position(|word| {
+        let word = word.trim_matches(|c: char| !c.is_ascii_alphabetic() && c != '.');
+        let word = word.rsplit('.').next().unwrap_or(word);
+        LogLevel::parse(word).is_some()
+    });
+    let level = level_at.and_then(|index| {
+        let word = words[index].trim_matches(|c: char| !c.is_ascii_alphabetic() && c != '.');
+        LogLevel::parse(word.rsplit('.').next().unwrap_or(word))
+    });
+
+    let timestamp = level_at
+        .filter(|index| *index > 0)
+        .map(|index| words[..index].join(" ").trim_matches(|c| c == '[' || c == ']').to_string());
+    let message = match level_at {
+        Some(index) => words[index + 1..].join(" "),
+        None => plain.trim().to_string(),
+    };
+
+    let request_id = words.iter().find_map(|word| {
+        ["request_id=", "request_id:", "x-request-id=", "\"request_id\":"]
+            .iter()
+            .find_map(|prefix| word.strip_prefix(prefix))
+            .map(|value| value.trim_matches(|c: char| c == '"' || c == '\'' || c == ',' || c == '}').to_string())
+            .filter(|value| !value.is_empty())
+    });
+
+    LogEntry { timestamp, level, message, request_id }
+}
+
+fn parse_line(line: &str) -> LogEntry {
+    parse_json_line(line).unwrap_or_else(|| parse_text_line(line))
+}
+
+/// Decides which lines are printed. Lines without a level, such as stack
+/// trace continuations, follow the decision made for the line before them.
+struct Filter {
+    level: Option<LogLevel>,
+    request_id: Option<String>,
+    showing: bool,
+}
+
+impl Filter {
+    fn new(options: &LogOptions) -> Self {
+        Self {
+            level: options.level,
+            request_id: options.request_id.clone(),
+            showing: options.level.is_none() && options.request_id.is_none(),
+        }
+    }
+
+    fn accepts(&mut self, entry: &LogEntry) -> bool {
+        if entry.level.is_none() && entry.request_id.is_none() && entry.timestamp.is_none() {
+            return self.showing;
+        }
+
+        let level_ok = match (self.level, entry.level) {
+            (Some(minimum), Some(level)) => level >= minimum,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let request_ok = self
+            .request_id
+            .as_deref()
+            .map_or(true, |wanted| entry.request_id.as_deref() == Some(wanted));
+
+        self.showing = level_ok && request_ok;
+        self.showing
+    }
+}
+
+/// Print one line as text, or as a JSON object with `--json`
+fn print_entry(channel: &str, line: &str, entry: &LogEntry, options: &LogOptions, prefix: bool) {
+    if options.json {
+        let object = serde_json::json!({
+            "channel": channel,
+            "timestamp": entry.timestamp,
+            "level": entry.level.map(LogLevel::as_str),
+            "message": entry.message,
+            "request_id": entry.request_id,
+            "line": line,
+        });
+        println!("{}", object);
+        return;
+    }
+
+    let line = match entry.level {
+        Some(LogLevel::Error) => line.red().to_string(),
+        Some(LogLevel::Warn) => line.yellow().to_string(),
+        Some(LogLevel::Trace) | Some(LogLevel::Debug) => line.dimmed().to_string(),
+        _ => line.to_string(),
+    };
+    if prefix {
+        println!("{} {}", format!("[{}]", channel).cyan(), line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// The last `count` lines of a file, reading backwards so large logs stay cheap.
+/// Returns the lines and the file length they were read up to.
+fn read_last_lines(path: &Path, count: usize) -> std::io::Result<(Vec<String>, u64)> {
+    const BLOCK: u64 = 64 * 1024;
+
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+    if count == 0 {
+        return Ok((Vec::new(), length));
+    }
+
+    let mut start = length;
+    let mut buffer = Vec::new();
+    while start > 0 && buffer.iter().filter(|byte| **byte == b'\n').count() <= count {
+        let read_from = start.saturating_sub(BLOCK);
+        let mut block = vec![0; (start - read_from) as usize];
+        file.seek(SeekFrom::Start(read_from))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&buffer);
+        buffer = block;
+        start = read_from;
+    }
+
+    let text = String::from_utf8_lossy(&buffer);
+    let lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let skip = lines.len().saturating_sub(count);
+    Ok((lines.into_iter().skip(skip).collect(), length))
+}
+
+/// A channel being followed
+struct Follower {
+    channel: LogChannel,
+    file: Option<PathBuf>,
+    position: u64,
+    partial: String,
+}
+
+impl Follower {
+    /// Lines appended since the last poll, switching to a new daily file and
+    /// starting over when the file was truncated or replaced
+    fn poll(&mut self) -> std::io::Result<Vec<String>> {
+        let current = self.channel.current_file();
+        if current != self.file {
+            self.file = current;
+            self.position = 0;
+            self.partial.clear();
+        }
+        let Some(path) = &self.file else {
+            return Ok(Vec::new());
+        };
+
+        let mut file = File::open(path)?;
+        let length = file.metadata()?.len();
+        if length < self.position {
+            self.position = 0;
+            self.partial.clear();
+        }
+        if length == self.position {
+            return Ok(Vec::new());
+        }
+
+        file.seek(SeekFrom::Start(self.position))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        self.position += appended.len() as u64;
+
+        self.partial.push_str(&String::from_utf8_lossy(&appended));
+        let Some(last_newline) = self.partial.rfind('\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: String = self.partial.drain(..=last_newline).collect();
+        Ok(complete.lines().map(str::to_string).collect())
+    }
+}
+
+/// Show recent log lines and optionally keep following them
+pub fn show_logs(options: LogOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let channels = discover_channels(options.channel.as_deref())?;
+    let prefix = channels.len() > 1;
+    let mut followers = Vec::new();
+
+    for channel in channels {
+        let file = channel.current_file();
+        let mut filter = Filter::new(&options);
+        let position = match &file {
+            Some(path) => {
+                let (lines, length) = read_last_lines(path, options.lines)?;
+                for line in &lines {
+                    let entry = parse_line(line);
+                    if filter.accepts(&entry) {
+                        print_entry(&channel.name, line, &entry, &options, prefix);
+                    }
+                }
+                length
+            }
+            None => {
+                if !options.json {
+                    println!(
+                        "{} No log file for channel '{}' yet ({})",
+                        "ℹ️".blue(),
+                        channel.name,
+                        channel.path.display()
+                    );
+                }
+                0
+            }
+        };
+        followers.push((Follower { channel, file, position, partial: String::new() }, filter));
+    }
+
+    if !options.follow {
+        return Ok(());
+    }
+
+    loop {
+        for (follower, filter) in &mut followers {
+            for line in follower.poll()? {
+                let entry = parse_line(&line);
+                if filter.accepts(&entry) {
+                    print_entry(&follower.channel.name, &line, &entry, &options, prefix);
+                }
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels_from_stack_config() {
+        let logging: toml::Value = r#"
+            default = "stack"
+
+            [channels.stack]
+            driver = "stack"
+            channels = ["single", "daily", "stderr"]
+
+            [channels.single]
+            driver = "single"
+            path = "storage/logs/torch.log"
+
+            [channels.daily]
+            driver = "daily"
+            path = "storage/logs/daily.log"
+
+            [channels.stderr]
+            driver = "stderr"
+        "#
+        .parse()
+        .unwrap();
+
+        let channels = channels_from_config(&logging, None).unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "single");
+        assert_eq!(channels[1].driver, Driver::Daily);
+
+        let only = channels_from_config(&logging, Some("daily")).unwrap();
+        assert_eq!(only[0].path, PathBuf::from("storage/logs/daily.log"));
+        assert!(channels_from_config(&logging, Some("missing")).is_err());
+
+        let shorthand: toml::Value = "level = \"info\"\nfile = \"logs/torch.log\"".parse().unwrap();
+        assert_eq!(channels_from_config(&shorthand, None).unwrap()[0].path, PathBuf::from("logs/torch.log"));
+    }
+
+    #[test]
+    fn test_latest_daily_file() {
+        let dir = std::env::temp_dir().join(format!("torch-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["torch-2024-01-14.log", "torch-2024-01-15.log", "torch-old.log", "other-2024-02-01.log"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let latest = latest_daily_file(&dir.join("torch.log"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(latest, Some(dir.join("torch-2024-01-15.log")));
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let json = parse_line(
+            r#"{"timestamp":"2024-01-15T10:30:00Z","level":"WARN","fields":{"message":"slow query","request_id":"abc"},"target":"app"}"#,
+        );
+        assert_eq!(json.level, Some(LogLevel::Warn));
+        assert_eq!(json.message, "slow query");
+        assert_eq!(json.request_id.as_deref(), Some("abc"));
+
+        let text = parse_line("2024-01-15T10:30:00.123Z \u{1b}[33m WARN\u{1b}[0m app::db: slow query request_id=abc elapsed=3s");
+        assert_eq!(text.level, Some(LogLevel::Warn));
+        assert_eq!(text.timestamp.as_deref(), Some("2024-01-15T10:30:00.123Z"));
+        assert_eq!(text.request_id.as_deref(), Some("abc"));
+
+        let laravel = parse_line("[2024-01-15 10:30:00] production.ERROR: Payment failed");
+        assert_eq!(laravel.level, Some(LogLevel::Error));
+        assert_eq!(laravel.message, "Payment failed");
+    }
+
+    #[test]
+    fn test_filter_keeps_continuation_lines_with_their_entry() {
+        let mut filter = Filter::new(&LogOptions { level: Some(LogLevel::Warn), ..Default::default() });
+
+        assert!(!filter.accepts(&parse_line("2024-01-15T10:30:00Z INFO app: started")));
+        assert!(!filter.accepts(&parse_line("    at continuation")));
+        assert!(filter.accepts(&parse_line("2024-01-15T10:30:01Z ERROR app: boom")));
+        assert!(filter.accepts(&parse_line("    at src/main.rs:10")));
+    }
+}
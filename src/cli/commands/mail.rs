@@ -0,0 +1,67 @@
+//! Mail commands
+
+use crate::cli::MailOperation;
+use crate::mail::Mail;
+use colored::*;
+use std::path::PathBuf;
+
+/// Handle mail operations
+pub fn handle_operation(operation: MailOperation) -> Result<(), Box<dyn std::error::Error>> {
+    match operation {
+        MailOperation::Failed { file } => list_failed_mail(file)?,
+        MailOperation::Clear { file } => clear_failed_mail(file)?,
+    }
+    Ok(())
+}
+
+/// List queued sends that failed for good
+fn list_failed_mail(file: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    Mail::record_failures_in(&file);
+    let failed = Mail::failed()?;
+
+    println!("{} Failed Mail", "❌".red().bold());
+    println!();
+
+    if failed.is_empty() {
+        println!("{} No failed mail in {}", "✅".green(), file.display());
+        return Ok(());
+    }
+
+    println!("{:<5} {:<30} {:<30} {:<10} {:<20} {}", "ID".bold(), "Mailable".bold(), "To".bold(), "Provider".bold(), "Failed At".bold(), "Error".bold());
+    println!("{}", "-".repeat(120));
+
+    for mail in &failed {
+        let failed_at = chrono::DateTime::from_timestamp(mail.failed_at as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let mailable = mail.mailable.rsplit("::").next().unwrap_or(&mail.mailable);
+        println!("{:<5} {:<30} {:<30} {:<10} {:<20} {} (after {} attempts)",
+                 mail.id.to_string().red(),
+                 mailable.cyan(),
+                 mail.to.join(", "),
+                 mail.provider,
+                 failed_at.yellow(),
+                 mail.error.red(),
+                 mail.attempts);
+        println!("      {}", mail.subject.dimmed());
+    }
+
+    println!();
+    println!("{} Use 'torch mail clear' once they have been dealt with", "💡".blue());
+
+    Ok(())
+}
+
+/// Delete the failed mail log
+fn clear_failed_mail(file: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    Mail::record_failures_in(&file);
+    let cleared = Mail::clear_failed()?;
+
+    if cleared > 0 {
+        println!("{} Cleared {} failed mail", "✅".green(), cleared);
+    } else {
+        println!("{} No failed mail to clear", "ℹ️".blue());
+    }
+
+    Ok(())
+}
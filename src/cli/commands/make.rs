@@ -1,5 +1,6 @@
 //! Code generation commands
 
+use crate::cli::generators::{self, MigrationTarget};
 use crate::cli::Generator;
 use colored::*;
 use std::fs;
@@ -8,12 +9,21 @@ use std::path::Path;
 /// Generate code based on the generator type
 pub fn generate(generator: Generator) -> Result<(), Box<dyn std::error::Error>> {
     match generator {
-        Generator::Controller { name, resource, api } => {
-            generate_controller(&name, resource, api)?;
+        Generator::Controller { name, resource, api, cache } => {
+            generate_controller(&name, resource, api, cache.as_deref())?;
         }
         Generator::Model { name, migration, factory, seeder, policy } => {
             generate_model(&name, migration, factory, seeder, policy)?;
         }
+        Generator::Resource { name, fields } => {
+            generate_resource(&name, &fields)?;
+        }
+        Generator::Auth { force } => {
+            generate_auth(force)?;
+        }
+        Generator::FromOpenapi { spec, prefix, force } => {
+            generate_from_openapi(&spec, prefix.as_deref(), force)?;
+        }
         Generator::Middleware { name } => {
             generate_middleware(&name)?;
         }
@@ -54,8 +64,12 @@ pub fn generate(generator: Generator) -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
-fn generate_controller(name: &str, resource: bool, api: bool) -> Result<(), Box<dyn std::error::Error>> {
+fn generate_controller(name: &str, resource: bool, api: bool, cache: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Generating controller: {}", "🎮".yellow(), name.cyan().bold());
+
+    if let Some(ttl) = cache {
+        super::bench::parse_duration(ttl)?;
+    }
     
     let controller_name = if name.ends_with("Controller") {
         name.to_string()
@@ -77,10 +91,17 @@ fn generate_controller(name: &str, resource: bool, api: bool) -> Result<(), Box<
     } else {
         generate_basic_controller(&controller_name)
     };
+    let content = match cache {
+        Some(ttl) => cache_get_actions(&content, ttl),
+        None => content,
+    };
     
     fs::write(&filename, content)?;
     
     println!("{} Controller created: {}", "✅".green(), filename);
+    if let Some(ttl) = cache {
+        println!("{} GET actions are cached for {} (needs torch-web's \"macros\" feature)", "💡".blue(), ttl);
+    }
     
     if resource {
         if api {
@@ -93,6 +114,23 @@ fn generate_controller(name: &str, resource: bool, api: bool) -> Result<(), Box<
     Ok(())
 }
 
+/// Put `#[cache(ttl = ...)]` on a generated controller's GET actions
+fn cache_get_actions(content: &str, ttl: &str) -> String {
+    let mut cached = String::with_capacity(content.len());
+    for line in content.lines() {
+        if line.starts_with("use torch_web::{") {
+            cached.push_str("use torch_web::cache;\n");
+        }
+        let action = line.trim_start().strip_prefix("pub async fn ").and_then(|rest| rest.split('(').next());
+        if matches!(action, Some("handle" | "index" | "show")) {
+            cached.push_str(&format!("    #[cache(ttl = \"{}\")]\n", ttl));
+        }
+        cached.push_str(line);
+        cached.push('\n');
+    }
+    cached
+}
+
 fn generate_basic_controller(name: &str) -> String {
     format!(r#"//! {} - Generated by Torch CLI
 
@@ -224,6 +262,231 @@ fn generate_model(name: &str, migration: bool, factory: bool, seeder: bool, poli
     Ok(())
 }
 
+fn generate_resource(name: &str, fields: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::cli::generators::resource::{self, Resource};
+
+    println!("{} Generating resource: {}", "🧱".yellow(), name.cyan().bold());
+
+    let resource = Resource::new(name, resource::parse_fields(fields)?);
+
+    for (path, content) in [
+        (resource.model_path(), resource::model_content(&resource)),
+        (resource.request_path(), resource::request_content(&resource)),
+        (resource.controller_path(), resource::controller_content(&resource)),
+    ] {
+        write_new_file(&path, &content)?;
+        println!("{} Created: {}", "✅".green(), path);
+    }
+
+    register_module("src/models", &resource.snake)?;
+    register_module("src/requests", &format!("{}_request", resource.snake))?;
+    register_module("src/controllers", &format!("{}_controller", resource.snake))?;
+
+    let migration_name = format!("create_{}_table", resource.plural);
+    let version = chrono::Utc::now().format("%Y_%m_%d_%H%M%S").to_string();
+    let migration_path = format!("migrations/{}_{}.rs", version, migration_name);
+    fs::create_dir_all("migrations")?;
+    fs::write(&migration_path, resource::migration_content(&resource, &migration_name, &version))?;
+    println!("{} Created: {}", "✅".green(), migration_path);
+
+    for (file, content) in resource::view_contents(&resource) {
+        let path = format!("{}/{}", resource.view_dir(), file);
+        write_new_file(&path, &content)?;
+        println!("{} Created: {}", "✅".green(), path);
+    }
+
+    println!();
+    println!("{} Register the routes in src/main.rs:", "💡".blue());
+    for line in resource::routes_snippet(&resource).lines() {
+        println!("    {}", line);
+    }
+    println!("{} Make sure src/main.rs declares `mod models; mod requests; mod controllers;`", "💡".blue());
+    println!("{} The model needs torch-web's `database` feature; run `torch migrate` to create the table", "💡".blue());
+
+    Ok(())
+}
+
+fn generate_auth(force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::cli::generators::auth;
+
+    println!("{} Generating authentication scaffolding", "🔐".yellow());
+
+    let views: Vec<(String, String)> = auth::view_contents()
+        .into_iter()
+        .map(|(file, content)| (format!("templates/auth/{}", file), content))
+        .collect();
+    let files: Vec<(String, String)> = auth::source_files()
+        .into_iter()
+        .map(|(path, content)| (path.to_string(), content))
+        .chain(views)
+        .collect();
+
+    // Check everything up front so a conflict doesn't leave half a scaffold behind
+    let existing: Vec<&str> = files
+        .iter()
+        .map(|(path, _)| path.as_str())
+        .filter(|path| Path::new(path).exists())
+        .collect();
+    let conflicts = auth_conflicts(Path::new("."), &existing);
+    if !conflicts.is_empty() && !force {
+        return Err(auth_conflict_message(&conflicts).into());
+    }
+
+    for (path, content) in &files {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        let verb = if conflicts.contains(&path.as_str()) {
+            "Overwrote"
+        } else if existing.contains(&path.as_str()) {
+            "Replaced the example model from `torch new`"
+        } else {
+            "Created"
+        };
+        println!("{} {}: {}", "✅".green(), verb, path);
+    }
+
+    register_module("src/models", "user")?;
+    register_module("src/requests", "auth_request")?;
+    register_module("src/controllers", "auth_controller")?;
+
+    let migration_name = "create_users_table";
+    let version = chrono::Utc::now().format("%Y_%m_%d_%H%M%S").to_string();
+    let migration_path = format!("migrations/{}_{}.rs", version, migration_name);
+    fs::create_dir_all("migrations")?;
+    fs::write(&migration_path, auth::migration_content(migration_name, &version))?;
+    println!("{} Created: {}", "✅".green(), migration_path);
+
+    println!();
+    println!("{} Register the routes in src/main.rs:", "💡".blue());
+    for line in auth::routes_snippet().lines() {
+        println!("    {}", line);
+    }
+    println!("{} Make sure src/main.rs declares `mod auth; mod models; mod requests; mod controllers;`", "💡".blue());
+
+    let manifest = fs::read_to_string("Cargo.toml").unwrap_or_default();
+    let has_features = manifest
+        .lines()
+        .filter(|line| line.trim_start().starts_with("torch-web"))
+        .any(|line| line.contains("\"full\"") || (line.contains("\"security\"") && line.contains("\"templates\"")));
+    if !has_features {
+        println!("{} Enable the torch-web \"security\" and \"templates\" features in Cargo.toml", "💡".blue());
+    }
+
+    Ok(())
+}
+
+/// The files in `existing` that hold the user's own code. The example user
+/// model `torch new` creates isn't one of them while it is left as generated,
+/// since the scaffold's model replaces it.
+fn auth_conflicts<'a>(root: &Path, existing: &[&'a str]) -> Vec<&'a str> {
+    use crate::cli::commands::new::EXAMPLE_USER_MODEL;
+
+    existing
+        .iter()
+        .copied()
+        .filter(|path| {
+            *path != "src/models/user.rs"
+                || fs::read_to_string(root.join(path)).map_or(true, |content| content.replace("\r\n", "\n") != EXAMPLE_USER_MODEL)
+        })
+        .collect()
+}
+
+fn auth_conflict_message(conflicts: &[&str]) -> String {
+    let mut message = format!("These files already exist: {}", conflicts.join(", "));
+    if conflicts.contains(&"src/models/user.rs") {
+        message.push_str(
+            "\nsrc/models/user.rs has a User model of your own. The auth scaffold needs its own User, with a password hash, \
+             and a UserRepository; move yours aside, or rerun with --force and merge your fields back in afterwards",
+        );
+    }
+    message.push_str("\nRerun with --force to overwrite them with the scaffold's versions; what they contain now is lost");
+    message
+}
+
+fn generate_from_openapi(spec_path: &str, prefix: Option<&str>, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::cli::generators::openapi;
+
+    println!("{} Generating API from {}", "📜".yellow(), spec_path.cyan().bold());
+
+    let contents = fs::read_to_string(spec_path).map_err(|e| format!("Could not read {}: {}", spec_path, e))?;
+    let spec = openapi::parse_spec(&contents)?;
+    let source = Path::new(spec_path).file_name().and_then(|name| name.to_str()).unwrap_or(spec_path);
+    let api = openapi::generate(&spec, source, prefix)?;
+
+    fs::create_dir_all("src/openapi")?;
+    for (path, content) in [("src/openapi/mod.rs", &api.module), ("src/openapi/schemas.rs", &api.schemas)] {
+        let verb = if Path::new(path).exists() { "Regenerated" } else { "Created" };
+        fs::write(path, content)?;
+        println!("{} {}: {}", "✅".green(), verb, path);
+    }
+
+    for controller in &api.controllers {
+        let path = format!("src/controllers/{}.rs", controller.module);
+        match fs::read_to_string(&path) {
+            Ok(existing) if !force => {
+                let missing: Vec<&str> = controller
+                    .handlers
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|handler| !existing.contains(&format!("fn {}(", handler)))
+                    .collect();
+                if missing.is_empty() {
+                    println!("{} Kept: {}", "⏭️".blue(), path);
+                } else {
+                    println!("{} Kept: {} (add handlers: {})", "⚠️".yellow(), path, missing.join(", "));
+                }
+            }
+            existing => {
+                fs::create_dir_all("src/controllers")?;
+                fs::write(&path, &controller.content)?;
+                let verb = if existing.is_ok() { "Overwrote" } else { "Created" };
+                println!("{} {}: {}", "✅".green(), verb, path);
+            }
+        }
+        register_module("src/controllers", &controller.module)?;
+    }
+
+    println!();
+    println!("{} {} operations across {} controllers", "📊".blue(), api.operations, api.controllers.len());
+    println!("{} Register the routes in src/main.rs:", "💡".blue());
+    println!("    let app = openapi::routes(app);");
+    println!("{} Make sure src/main.rs declares `mod openapi; mod controllers;`", "💡".blue());
+
+    Ok(())
+}
+
+/// Write a generated file, refusing to overwrite existing work
+fn write_new_file(path: &str, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if Path::new(path).exists() {
+        return Err(format!("{} already exists", path).into());
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Add `pub mod <module>;` to `<dir>/mod.rs`, creating it if needed
+fn register_module(dir: &str, module: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mod_path = Path::new(dir).join("mod.rs");
+    let declaration = format!("pub mod {};", module);
+    let existing = fs::read_to_string(&mod_path).unwrap_or_default();
+
+    if !existing.lines().any(|line| line.trim() == declaration) {
+        let mut content = existing;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&declaration);
+        content.push('\n');
+        fs::write(&mod_path, content)?;
+    }
+    Ok(())
+}
+
 fn generate_middleware(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Generating middleware: {}", "🛡️".yellow(), name.cyan().bold());
     
@@ -389,7 +652,7 @@ fn generate_model_content(name: &str) -> String {
 use serde::{{Deserialize, Serialize}};
 
 #[cfg(feature = "database")]
-use torch_web::orm::{{Model, Timestamps, HasRelationships, impl_model, impl_timestamps, impl_from_row}};
+use torch_web::orm::{{Model, Timestamps, impl_model, impl_timestamps, impl_from_row}};
 
 /// {name} model with Active Record functionality
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -460,18 +723,27 @@ impl Default for {name} {{
 fn generate_migration(name: &str, create: Option<&str>, table: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Generating migration: {}", "📝".yellow(), name.cyan().bold());
 
-    let timestamp = chrono::Utc::now().format("%Y_%m_%d_%H%M%S");
-    let filename = format!("migrations/{}_{}.rs", timestamp, name);
+    let version = chrono::Utc::now().format("%Y_%m_%d_%H%M%S").to_string();
+    let filename = format!("migrations/{}_{}.rs", version, name);
 
     // Create migrations directory if it doesn't exist
     fs::create_dir_all("migrations")?;
 
-    let content = if let Some(table_name) = create {
-        crate::cli::generators::generate_create_table_migration(name, table_name)
-    } else if let Some(table_name) = table {
-        crate::cli::generators::generate_modify_table_migration(name, table_name)
-    } else {
-        crate::cli::generators::generate_basic_migration(name)
+    // Without --create or --table, fall back to what the name suggests
+    let target = match (create, table) {
+        (Some(table_name), _) => Some(MigrationTarget::Create(table_name.to_string())),
+        (None, Some(table_name)) => Some(MigrationTarget::Alter(table_name.to_string())),
+        (None, None) => generators::guess_migration_target(name),
+    };
+
+    let content = match target {
+        Some(MigrationTarget::Create(table_name)) => {
+            generators::generate_create_table_migration(name, &version, &table_name)
+        }
+        Some(MigrationTarget::Alter(table_name)) => {
+            generators::generate_modify_table_migration(name, &version, &table_name)
+        }
+        None => generators::generate_basic_migration(name, &version),
     };
 
     fs::write(&filename, content)?;
@@ -679,7 +951,8 @@ fn generate_command(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     fs::write(&filename, content)?;
 
     println!("{} Command created: {}", "✅".green(), filename);
-    println!("{} Don't forget to register the command in your CLI", "💡".blue());
+    println!("{} Register it with Console::new().register::<{}>() in main, then run: torch {}",
+             "💡".blue(), command_name, crate::cli::generators::console_command_name(&command_name));
 
     Ok(())
 }
@@ -716,3 +989,31 @@ fn generate_template(name: &str, layout: Option<&str>) -> Result<(), Box<dyn std
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::commands::new::EXAMPLE_USER_MODEL;
+
+    #[test]
+    fn test_make_auth_replaces_only_the_untouched_example_user_model() {
+        let root = std::env::temp_dir().join(format!("torch-make-auth-{}", std::process::id()));
+        fs::create_dir_all(root.join("src/models")).unwrap();
+        let existing = ["src/models/user.rs", "src/auth.rs"];
+
+        fs::write(root.join("src/models/user.rs"), EXAMPLE_USER_MODEL.replace('\n', "\r\n")).unwrap();
+        assert_eq!(auth_conflicts(&root, &existing[..1]), Vec::<&str>::new());
+        assert_eq!(auth_conflicts(&root, &existing), ["src/auth.rs"]);
+
+        fs::write(root.join("src/models/user.rs"), EXAMPLE_USER_MODEL.replace("pub email: String,", "pub email: String,\n    pub plan: String,")).unwrap();
+        let conflicts = auth_conflicts(&root, &existing);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(conflicts, existing);
+
+        let message = auth_conflict_message(&conflicts);
+        assert!(message.starts_with("These files already exist: src/models/user.rs, src/auth.rs\n"), "{}", message);
+        assert!(message.contains("move yours aside"));
+        assert!(message.ends_with("what they contain now is lost"));
+        assert!(!auth_conflict_message(&["src/auth.rs"]).contains("User model"));
+    }
+}
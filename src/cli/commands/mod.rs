@@ -6,6 +6,7 @@ pub mod serve;
 pub mod build;
 pub mod about;
 pub mod db;
+pub mod schema_diff;
 pub mod migrate;
 pub mod route;
 pub mod cache;
@@ -13,8 +14,14 @@ pub mod config;
 pub mod init;
 pub mod view;
 pub mod queue;
+pub mod mail;
 pub mod test;
 pub mod maintenance;
 pub mod optimize;
 pub mod tinker;
 pub mod schedule;
+pub mod deploy;
+pub mod key;
+pub mod bench;
+pub mod logs;
+pub mod console;
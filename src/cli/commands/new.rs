@@ -2,24 +2,132 @@
 
 use colored::*;
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Starting points for `torch new --template`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Template {
+    /// Full application with example controllers, models and middleware
+    Full,
+    /// Single file app with no examples
+    Minimal,
+    /// Versioned JSON API
+    Api,
+    /// Server-rendered Ember app enhanced with htmx
+    Htmx,
+    /// JSON backend that serves a single-page app from `public/`
+    Spa,
+}
+
+impl Template {
+    /// torch-web features the generated project depends on
+    fn features(self) -> &'static [&'static str] {
+        match self {
+            Template::Full => &["full"],
+            Template::Minimal | Template::Api | Template::Spa => &["json"],
+            Template::Htmx => &["json", "templates"],
+        }
+    }
+}
+
+/// Where the generated project gets torch-web from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TorchSource {
+    /// The crates.io release matching this CLI
+    #[default]
+    Registry,
+    /// A local checkout, e.g. `--path ../Torch`
+    Path(PathBuf),
+    /// A git repository, optionally pinned with `--branch` or `--rev`
+    Git { url: String, branch: Option<String>, rev: Option<String> },
+}
+
+impl TorchSource {
+    /// Check a local checkout before anything is generated
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let TorchSource::Path(path) = self {
+            let manifest = fs::read_to_string(path.join("Cargo.toml"))
+                .map_err(|_| format!("No Cargo.toml found in '{}'", path.display()))?;
+            if !manifest.lines().any(|line| line.trim() == r#"name = "torch-web""#) {
+                return Err(format!("'{}' is not a torch-web checkout", path.display()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Keys of the `torch-web` dependency for a project created in `project`,
+    /// e.g. `version = "0.2.8"` or `path = "../../Torch"`
+    fn dependency(&self, project: &Path) -> String {
+        match self {
+            TorchSource::Registry => format!("version = \"{}\"", get_torch_version()),
+            TorchSource::Path(path) => {
+                let path = relative_to(project, path).unwrap_or_else(|| path.clone());
+                format!("path = \"{}\"", path.display().to_string().replace('\\', "/"))
+            }
+            TorchSource::Git { url, branch, rev } => {
+                let mut keys = format!("git = \"{}\"", url);
+                if let Some(branch) = branch {
+                    keys.push_str(&format!(", branch = \"{}\"", branch));
+                }
+                if let Some(rev) = rev {
+                    keys.push_str(&format!(", rev = \"{}\"", rev));
+                }
+                keys
+            }
+        }
+    }
+}
+
+/// `target` relative to the directory `from`, so the manifest keeps working
+/// when the workspace is moved or cloned elsewhere
+fn relative_to(from: &Path, target: &Path) -> Option<PathBuf> {
+    let from = fs::canonicalize(from).ok()?;
+    let target = fs::canonicalize(target).ok()?;
+    let from: Vec<Component> = from.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    if from.first() != target.first() {
+        return None;
+    }
+
+    let common = from.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
 
 /// Create a new Torch project
-pub fn create_project(name: &str, minimal: bool) -> Result<(), Box<dyn std::error::Error>> {
+pub fn create_project(name: &str, template: Template, check: bool, source: &TorchSource) -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Creating new Torch application: {}", "🔥".yellow(), name.cyan().bold());
-    
+
     let project_path = Path::new(name);
-    
+
     if project_path.exists() {
         return Err(format!("Directory '{}' already exists", name).into());
     }
-    
+    source.validate()?;
+
     // Create project directory
     fs::create_dir_all(project_path)?;
-    
+
     // Create project structure
-    create_project_structure(project_path, minimal)?;
-    
+    create_project_structure(project_path, template, source)?;
+    match source {
+        TorchSource::Registry => {}
+        TorchSource::Path(_) | TorchSource::Git { .. } => {
+            println!("{} Using torch-web from {}", "🔗".blue(), source.dependency(project_path).cyan());
+        }
+    }
+
+    if check {
+        verify_project(project_path)?;
+    }
+
     println!("{} Project created successfully!", "✅".green());
     println!();
     println!("{}", "Next steps:".bold());
@@ -36,21 +144,61 @@ pub fn create_project(name: &str, minimal: bool) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
-fn create_project_structure(path: &Path, minimal: bool) -> Result<(), Box<dyn std::error::Error>> {
+/// Run `cargo check` in the new project so a broken manifest or template
+/// is reported now rather than on the user's first build
+fn verify_project(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} Verifying project with cargo check...", "🔍".yellow());
+
+    let output = match Command::new("cargo").arg("check").current_dir(path).output() {
+        Ok(output) => output,
+        Err(_) => {
+            println!("{} cargo not found, skipping verification", "⚠️".yellow());
+            return Ok(());
+        }
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let errors: Vec<&str> = stderr
+            .lines()
+            .filter(|line| line.starts_with("error"))
+            .take(10)
+            .collect();
+        for line in &errors {
+            println!("  {}", line.red());
+        }
+        return Err(format!(
+            "The generated project in '{}' failed `cargo check`. Fix the errors above or rerun with --no-check",
+            path.display()
+        )
+        .into());
+    }
+
+    println!("{} cargo check passed", "✅".green());
+    Ok(())
+}
+
+fn create_project_structure(path: &Path, template: Template, source: &TorchSource) -> Result<(), Box<dyn std::error::Error>> {
+    let minimal = template != Template::Full;
+    let project_name = path.file_name().unwrap().to_str().unwrap();
+
     // Create core directories
     fs::create_dir_all(path.join("src"))?;
-    fs::create_dir_all(path.join("src/controllers"))?;
-    fs::create_dir_all(path.join("src/models"))?;
-    fs::create_dir_all(path.join("src/middleware"))?;
-    fs::create_dir_all(path.join("templates"))?;
-    fs::create_dir_all(path.join("static/css"))?;
-    fs::create_dir_all(path.join("static/js"))?;
-    fs::create_dir_all(path.join("static/images"))?;
     fs::create_dir_all(path.join("config"))?;
     fs::create_dir_all(path.join("migrations"))?;
     fs::create_dir_all(path.join("storage/logs"))?;
     fs::create_dir_all(path.join("storage/framework"))?;
 
+    if matches!(template, Template::Full | Template::Minimal) {
+        fs::create_dir_all(path.join("src/controllers"))?;
+        fs::create_dir_all(path.join("src/models"))?;
+        fs::create_dir_all(path.join("src/middleware"))?;
+        fs::create_dir_all(path.join("templates"))?;
+        fs::create_dir_all(path.join("static/css"))?;
+        fs::create_dir_all(path.join("static/js"))?;
+        fs::create_dir_all(path.join("static/images"))?;
+    }
+
     if !minimal {
         fs::create_dir_all(path.join("examples"))?;
         fs::create_dir_all(path.join("tests"))?;
@@ -64,14 +212,73 @@ fn create_project_structure(path: &Path, minimal: bool) -> Result<(), Box<dyn st
     }
     
     // Create Cargo.toml
-    let cargo_toml = if minimal {
-        create_minimal_cargo_toml(path.file_name().unwrap().to_str().unwrap())
-    } else {
-        create_full_cargo_toml(path.file_name().unwrap().to_str().unwrap())
+    let torch = source.dependency(path);
+    let cargo_toml = match template {
+        Template::Full => create_full_cargo_toml(project_name, &torch),
+        Template::Minimal => create_minimal_cargo_toml(project_name, &torch),
+        _ => create_preset_cargo_toml(project_name, template, &torch),
     };
-    
+
     fs::write(path.join("Cargo.toml"), cargo_toml)?;
-    
+
+    // Create torch.toml configuration
+    let torch_config = create_torch_config(minimal);
+    fs::write(path.join("torch.toml"), torch_config)?;
+
+    // Presets bring their own sources and templates
+    match template {
+        Template::Api => create_api_files(path)?,
+        Template::Htmx => create_htmx_files(path)?,
+        Template::Spa => create_spa_files(path)?,
+        Template::Full | Template::Minimal => create_starter_files(path, minimal)?,
+    }
+
+    // Create README
+    let readme = format!(r#"# {}
+
+A Torch web application.
+
+## Getting Started
+
+```bash
+# Run the application
+cargo run
+
+# Or use the Torch CLI
+torch serve --hot
+```
+
+## Project Structure
+
+- `src/` - Application source code
+- `templates/` - Ember templates
+- `static/` - Static assets (CSS, JS, images)
+- `config/` - Configuration files
+
+## Learn More
+
+- [Torch Documentation](https://docs.rs/torch-web)
+- [GitHub Repository](https://github.com/Enigmatikk/torch)
+"#, project_name);
+
+    fs::write(path.join("README.md"), readme)?;
+
+    // Create additional files for non-minimal projects
+    if !minimal {
+        create_additional_files(path)?;
+    }
+
+    // Create configuration files
+    create_config_files(path)?;
+
+    // Create gitignore
+    create_gitignore(path)?;
+
+    Ok(())
+}
+
+/// Create main.rs and the welcome templates for the full and minimal templates
+fn create_starter_files(path: &Path, minimal: bool) -> Result<(), Box<dyn std::error::Error>> {
     // Create main.rs
     let main_rs = if minimal {
         r#"use torch_web::{App, Request, Response};
@@ -93,8 +300,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 "#
     } else {
         r#"use torch_web::{App, Request, Response};
-use tracing::{info, Level};
-use tracing_subscriber;
+use tracing::info;
 
 mod controllers;
 mod models;
@@ -102,10 +308,8 @@ mod middleware;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
+    // Log through the channels in the [logging] section of torch.toml
+    torch_web::logging::init()?;
 
     info!("🔥 Starting Torch application");
 
@@ -141,17 +345,13 @@ async fn health_handler(_req: Request) -> Response {
     
     fs::write(path.join("src/main.rs"), main_rs)?;
 
-    // Create torch.toml configuration
-    let torch_config = create_torch_config(minimal);
-    fs::write(path.join("torch.toml"), torch_config)?;
-
     // Create basic template
     let layout_template = r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>@yield('title', 'Torch App')</title>
+    <title>@section('title')Torch App@endsection</title>
     <style>
         body { font-family: Arial, sans-serif; margin: 40px; }
         .container { max-width: 800px; margin: 0 auto; }
@@ -160,7 +360,7 @@ async fn health_handler(_req: Request) -> Response {
 </head>
 <body>
     <div class="container">
-        @yield('content')
+        @section('content')@endsection
     </div>
 </body>
 </html>
@@ -185,51 +385,382 @@ async fn health_handler(_req: Request) -> Response {
 "#;
     
     fs::write(path.join("templates/welcome.ember"), welcome_template)?;
-    
-    // Create README
-    let readme = format!(r#"# {}
 
-A Torch web application.
+    Ok(())
+}
 
-## Getting Started
+/// Create the sources for `--template api`: a JSON API versioned by path prefix
+fn create_api_files(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(path.join("src/api"))?;
 
-```bash
-# Run the application
-cargo run
+    let main_rs = r#"use torch_web::{App, Request, Response};
 
-# Or use the Torch CLI
-torch serve --hot
-```
+mod api;
 
-## Project Structure
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let app = App::new().get("/health", health).name("health");
 
-- `src/` - Application source code
-- `templates/` - Ember templates
-- `static/` - Static assets (CSS, JS, images)
-- `config/` - Configuration files
+    // Each API version registers its own routes under its prefix
+    let app = api::v1::routes(app);
 
-## Learn More
+    println!("🔥 Torch API listening on http://127.0.0.1:3000");
+    app.listen("127.0.0.1:3000").await
+}
 
-- [Torch Documentation](https://docs.rs/torch-web)
-- [GitHub Repository](https://github.com/Enigmatikk/torch)
-"#, path.file_name().unwrap().to_str().unwrap());
+async fn health(_req: Request) -> Response {
+    api::json(200, &serde_json::json!({ "status": "ok" }))
+}
+"#;
 
-    fs::write(path.join("README.md"), readme)?;
+    fs::write(path.join("src/main.rs"), main_rs)?;
 
-    // Create additional files for non-minimal projects
-    if !minimal {
-        create_additional_files(path)?;
+    let api_mod = r#"//! Versioned JSON API
+//!
+//! Add a new module (e.g. `v2`) next to `v1` when the API changes in a
+//! breaking way, and keep the old version registered until clients move.
+
+use torch_web::{Response, StatusCode};
+
+pub mod v1;
+
+/// Serialize `value` as a JSON response with the given status code
+pub fn json<T: serde::Serialize>(status: u16, value: &T) -> Response {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    match Response::with_status(status).json(value) {
+        Ok(response) => response,
+        Err(error) => Response::with_status(StatusCode::INTERNAL_SERVER_ERROR).body(error.to_string()),
     }
+}
+"#;
 
-    // Create configuration files
-    create_config_files(path)?;
+    fs::write(path.join("src/api/mod.rs"), api_mod)?;
 
-    // Create gitignore
-    create_gitignore(path)?;
+    let v1 = r#"//! Version 1 of the API
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Mutex;
+use torch_web::extractors::Path;
+use torch_web::{App, Request, Response};
+
+use super::json;
+
+/// Every route in this module lives under this prefix
+pub const PREFIX: &str = "/api/v1";
+
+#[derive(Clone, Serialize)]
+pub struct Item {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewItem {
+    pub name: String,
+}
+
+/// In-memory store standing in for a database
+static ITEMS: Mutex<Vec<Item>> = Mutex::new(Vec::new());
+
+/// Register the v1 routes on `app`
+pub fn routes(app: App) -> App {
+    app.get(&format!("{}/items", PREFIX), index)
+        .name("v1.items.index")
+        .get(&format!("{}/items/:id", PREFIX), show)
+        .name("v1.items.show")
+        .post(&format!("{}/items", PREFIX), store)
+        .name("v1.items.store")
+}
+
+async fn index() -> Response {
+    let items = ITEMS.lock().unwrap().clone();
+    json(200, &json!({ "data": items }))
+}
+
+async fn show(Path(id): Path<u64>) -> Response {
+    match ITEMS.lock().unwrap().iter().find(|item| item.id == id) {
+        Some(item) => json(200, &json!({ "data": item })),
+        None => json(404, &json!({ "error": "Item not found" })),
+    }
+}
+
+async fn store(req: Request) -> Response {
+    let input: NewItem = match serde_json::from_slice(req.body()) {
+        Ok(input) => input,
+        Err(error) => return json(422, &json!({ "error": error.to_string() })),
+    };
+
+    let mut items = ITEMS.lock().unwrap();
+    let item = Item {
+        id: items.len() as u64 + 1,
+        name: input.name,
+    };
+    items.push(item.clone());
+    json(201, &json!({ "data": item }))
+}
+"#;
+
+    fs::write(path.join("src/api/v1.rs"), v1)?;
+
+    Ok(())
+}
+
+/// Create the sources for `--template htmx`: server-rendered Ember pages
+/// that swap in fragments with htmx
+fn create_htmx_files(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(path.join("templates"))?;
+
+    let main_rs = r#"use std::sync::atomic::{AtomicU64, Ordering};
+use torch_web::ember::{ember, ember_fragment, EmberData};
+use torch_web::{App, Request, Response};
+
+static CLICKS: AtomicU64 = AtomicU64::new(0);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let app = App::new()
+        .get("/", home)
+        .name("home")
+        .post("/clicks", click)
+        .name("clicks.store");
+
+    println!("🔥 Torch server starting on http://127.0.0.1:3000");
+    app.listen("127.0.0.1:3000").await
+}
+
+/// Render the full page
+async fn home(_req: Request) -> Response {
+    let data = EmberData::new().with("clicks", CLICKS.load(Ordering::Relaxed).to_string());
+    ember("home", data).await
+}
+
+/// Render just the fragment htmx swaps into the page
+async fn click(_req: Request) -> Response {
+    let clicks = CLICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    ember_fragment("home", "clicks", EmberData::new().with("clicks", clicks.to_string())).await
+}
+"#;
+
+    fs::write(path.join("src/main.rs"), main_rs)?;
+
+    let layout_template = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Torch App</title>
+    <script src="https://unpkg.com/htmx.org@2.0.4"></script>
+    <style>
+        body { font-family: Arial, sans-serif; margin: 40px; }
+        .container { max-width: 800px; margin: 0 auto; }
+        .flame { color: #FF6B35; }
+    </style>
+</head>
+<body>
+    <div class="container">
+        @section('content')
+        @endsection
+    </div>
+</body>
+</html>
+"#;
+
+    fs::write(path.join("templates/layout.ember"), layout_template)?;
+
+    let home_template = r##"@extends('layout')
+
+@section('content')
+    <h1 class="flame">🔥 Welcome to Torch!</h1>
+    <p>This page is rendered on the server. The button below asks for a fragment and htmx swaps it in.</p>
+
+    <button hx-post="/clicks" hx-target="#clicks" hx-swap="outerHTML">Click me</button>
+    @fragment('clicks')
+    <p id="clicks">Clicked {{ $clicks }} time(s)</p>
+    @endfragment
+@endsection
+"##;
+
+    fs::write(path.join("templates/home.ember"), home_template)?;
 
     Ok(())
 }
 
+/// Create the sources for `--template spa`: a JSON API plus a static file
+/// server that falls back to `public/index.html` for client-side routes
+fn create_spa_files(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(path.join("public"))?;
+
+    let main_rs = r#"use torch_web::{App, Request, Response};
+
+mod assets;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let app = App::new()
+        .get("/api/hello", hello)
+        .name("api.hello")
+        // Anything that isn't an API route is a file from public/ or a client-side route
+        .not_found(assets::serve);
+
+    println!("🔥 Torch server starting on http://127.0.0.1:3000");
+    app.listen("127.0.0.1:3000").await
+}
+
+async fn hello(_req: Request) -> Response {
+    Response::ok()
+        .json(&serde_json::json!({ "message": "Hello from Torch!" }))
+        .unwrap()
+}
+"#;
+
+    fs::write(path.join("src/main.rs"), main_rs)?;
+
+    let assets = r#"//! Static file serving for the single-page app
+//!
+//! Files are read from `public/`. Requests for paths that don't exist get
+//! `index.html`, so the client-side router can handle them, except under
+//! `/api/` where a missing route is a real 404.
+
+use std::path::{Component, Path, PathBuf};
+use torch_web::{Method, Request, Response};
+
+const PUBLIC_DIR: &str = "public";
+
+pub async fn serve(req: Request) -> Response {
+    if req.path().starts_with("/api/") || !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return Response::not_found()
+            .json(&serde_json::json!({ "error": "Not found" }))
+            .unwrap();
+    }
+
+    if let Some(file) = resolve(req.path()) {
+        if let Ok(contents) = tokio::fs::read(&file).await {
+            return Response::ok()
+                .header("Content-Type", content_type(&file))
+                .body(contents);
+        }
+    }
+
+    match tokio::fs::read(Path::new(PUBLIC_DIR).join("index.html")).await {
+        Ok(contents) => Response::ok()
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(_) => Response::not_found().body("public/index.html is missing"),
+    }
+}
+
+/// Map a request path to a file under `public/`, refusing anything that
+/// would escape it
+fn resolve(request_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(request_path.trim_start_matches('/'));
+    if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    let file = Path::new(PUBLIC_DIR).join(relative);
+    file.is_file().then_some(file)
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+"#;
+
+    fs::write(path.join("src/assets.rs"), assets)?;
+
+    let index_html = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Torch App</title>
+    <link rel="stylesheet" href="/app.css">
+</head>
+<body>
+    <div id="app">Loading...</div>
+    <script src="/app.js"></script>
+</body>
+</html>
+"#;
+
+    fs::write(path.join("public/index.html"), index_html)?;
+
+    let app_js = r#"// Replace this with the build output of your frontend framework
+fetch("/api/hello")
+    .then((response) => response.json())
+    .then((data) => {
+        document.getElementById("app").textContent = `🔥 ${data.message} (${location.pathname})`;
+    });
+"#;
+
+    fs::write(path.join("public/app.js"), app_js)?;
+
+    let app_css = r#"body { font-family: Arial, sans-serif; margin: 40px; }
+#app { color: #FF6B35; font-size: 1.5em; }
+"#;
+
+    fs::write(path.join("public/app.css"), app_css)?;
+
+    Ok(())
+}
+
+/// The example user model of a full project, which `torch make auth`
+/// replaces while it is left as generated
+pub(crate) const EXAMPLE_USER_MODEL: &str = r#"//! User model - Example model
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: Option<u32>,
+    pub name: String,
+    pub email: String,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl User {
+    pub fn new(name: String, email: String) -> Self {
+        Self {
+            id: None,
+            name,
+            email,
+            created_at: Some(chrono::Utc::now()),
+            updated_at: Some(chrono::Utc::now()),
+        }
+    }
+
+    /// Find all users
+    pub async fn all() -> Result<Vec<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        // TODO: Implement database query
+        Ok(vec![])
+    }
+
+    /// Find user by ID
+    pub async fn find(id: u32) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        // TODO: Implement database query
+        Ok(None)
+    }
+
+    /// Save user to database
+    pub async fn save(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // TODO: Implement database save
+        Ok(())
+    }
+}
+"#;
+
 /// Create additional files for full project setup
 fn create_additional_files(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
     // Create example controller
@@ -310,51 +841,7 @@ pub use user_controller::UserController;
     fs::write(path.join("src/controllers/mod.rs"), controllers_mod)?;
 
     // Create example model
-    let user_model = r#"//! User model - Example model
-
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct User {
-    pub id: Option<u32>,
-    pub name: String,
-    pub email: String,
-    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
-    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
-}
-
-impl User {
-    pub fn new(name: String, email: String) -> Self {
-        Self {
-            id: None,
-            name,
-            email,
-            created_at: Some(chrono::Utc::now()),
-            updated_at: Some(chrono::Utc::now()),
-        }
-    }
-
-    /// Find all users
-    pub async fn all() -> Result<Vec<Self>, Box<dyn std::error::Error + Send + Sync>> {
-        // TODO: Implement database query
-        Ok(vec![])
-    }
-
-    /// Find user by ID
-    pub async fn find(id: u32) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
-        // TODO: Implement database query
-        Ok(None)
-    }
-
-    /// Save user to database
-    pub async fn save(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // TODO: Implement database save
-        Ok(())
-    }
-}
-"#;
-
-    fs::write(path.join("src/models/user.rs"), user_model)?;
+    fs::write(path.join("src/models/user.rs"), EXAMPLE_USER_MODEL)?;
 
     // Create models mod.rs
     let models_mod = r#"//! Models module
@@ -549,8 +1036,12 @@ build/
 
 /// Get the current torch-web version from the root Cargo.toml
 fn get_torch_version() -> String {
-    // Try to read the version from the current package's Cargo.toml
+    // Try to read the version from the current package's Cargo.toml, but only
+    // inside a torch-web checkout; any other project's version is meaningless here
     if let Ok(cargo_toml) = std::fs::read_to_string("Cargo.toml") {
+        if !cargo_toml.lines().any(|line| line.trim() == r#"name = "torch-web""#) {
+            return env!("CARGO_PKG_VERSION").to_string();
+        }
         for line in cargo_toml.lines() {
             if line.starts_with("version = ") {
                 if let Some(version) = line.split('"').nth(1) {
@@ -565,8 +1056,7 @@ fn get_torch_version() -> String {
 }
 
 /// Create a minimal Cargo.toml for basic applications
-fn create_minimal_cargo_toml(project_name: &str) -> String {
-    let torch_version = get_torch_version();
+fn create_minimal_cargo_toml(project_name: &str, torch: &str) -> String {
     format!(r#"[package]
 name = "{}"
 version = "0.1.0"
@@ -575,7 +1065,7 @@ authors = ["Your Name <your.email@example.com>"]
 description = "A Torch web application"
 
 [dependencies]
-torch-web = {{ version = "{}", features = ["json"] }}
+torch-web = {{ {}, features = ["json"] }}
 tokio = {{ version = "1.0", features = ["full"] }}
 serde = {{ version = "1.0", features = ["derive"] }}
 serde_json = "1.0"
@@ -583,12 +1073,11 @@ serde_json = "1.0"
 [[bin]]
 name = "server"
 path = "src/main.rs"
-"#, project_name, torch_version)
+"#, project_name, torch)
 }
 
 /// Create a full-featured Cargo.toml for production applications
-fn create_full_cargo_toml(project_name: &str) -> String {
-    let torch_version = get_torch_version();
+fn create_full_cargo_toml(project_name: &str, torch: &str) -> String {
     format!(r#"[package]
 name = "{}"
 version = "0.1.0"
@@ -598,7 +1087,7 @@ description = "A Torch web application"
 
 [dependencies]
 # Core Torch framework with all features
-torch-web = {{ version = "{}", features = ["full"] }}
+torch-web = {{ {}, features = ["full"] }}
 
 # Async runtime
 tokio = {{ version = "1.0", features = ["full"] }}
@@ -635,7 +1124,29 @@ anyhow = "1.0"
 [[bin]]
 name = "server"
 path = "src/main.rs"
-"#, project_name, torch_version)
+"#, project_name, torch)
+}
+
+/// Create a Cargo.toml for the api, htmx and spa presets
+fn create_preset_cargo_toml(project_name: &str, template: Template, torch: &str) -> String {
+    let features: Vec<String> = template.features().iter().map(|feature| format!("\"{}\"", feature)).collect();
+    format!(r#"[package]
+name = "{}"
+version = "0.1.0"
+edition = "2021"
+authors = ["Your Name <your.email@example.com>"]
+description = "A Torch web application"
+
+[dependencies]
+torch-web = {{ {}, features = [{}] }}
+tokio = {{ version = "1.0", features = ["full"] }}
+serde = {{ version = "1.0", features = ["derive"] }}
+serde_json = "1.0"
+
+[[bin]]
+name = "server"
+path = "src/main.rs"
+"#, project_name, torch, features.join(", "))
 }
 
 /// Create torch.toml configuration file
@@ -998,6 +1509,13 @@ health_check = "/health"
 # Prometheus metrics endpoint
 metrics_endpoint = "/metrics"
 
+[monitoring.otel]
+# Send traces and metrics to an OpenTelemetry collector (requires the "otel" feature)
+enabled = false
+endpoint = "http://localhost:4318"
+service_name = "torch-app"
+sample_ratio = 1.0
+
 [api]
 # API configuration
 prefix = "api"
@@ -1038,3 +1556,37 @@ detection = "header"  # header, session, query
 fallback = "en"
 "#.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_cargo_toml_features() {
+        let torch = TorchSource::Registry.dependency(Path::new("demo"));
+        let manifest: toml::Table = create_preset_cargo_toml("demo", Template::Htmx, &torch).parse().unwrap();
+        let torch = &manifest["dependencies"]["torch-web"];
+
+        assert_eq!(torch["version"].as_str(), Some(env!("CARGO_PKG_VERSION")));
+        let features: Vec<&str> = torch["features"].as_array().unwrap().iter().map(|f| f.as_str().unwrap()).collect();
+        assert_eq!(features, ["json", "templates"]);
+    }
+
+    #[test]
+    fn test_torch_source_dependency() {
+        let root = std::env::temp_dir().join(format!("torch-new-{}", std::process::id()));
+        fs::create_dir_all(root.join("work/app")).unwrap();
+        fs::create_dir_all(root.join("Torch")).unwrap();
+
+        let local = TorchSource::Path(root.join("work/../Torch"));
+        assert_eq!(local.dependency(&root.join("work/app")), r#"path = "../../Torch""#);
+        fs::remove_dir_all(&root).unwrap();
+
+        let git = TorchSource::Git {
+            url: "https://github.com/Enigmatikk/torch".to_string(),
+            branch: None,
+            rev: Some("abc123".to_string()),
+        };
+        assert_eq!(git.dependency(Path::new("app")), r#"git = "https://github.com/Enigmatikk/torch", rev = "abc123""#);
+    }
+}
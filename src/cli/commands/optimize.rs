@@ -73,7 +73,7 @@ fn clear_optimization_caches() -> Result<(), Box<dyn std::error::Error>> {
     }
     
     // Clear route cache
-    if clear_file("src/cache/routes.rs")? {
+    if clear_file(super::route::ROUTE_CACHE)? {
         cleared.push("Routes");
     }
     
@@ -118,20 +118,7 @@ pub const APP_DEBUG: bool = false;
 
 /// Cache routes
 fn cache_routes() -> Result<(), Box<dyn std::error::Error>> {
-    // TODO: Implement actual route caching
-    fs::create_dir_all("src/cache")?;
-    
-    let route_cache = r#"// Cached routes - Generated by torch optimize
-use torch_web::Router;
-
-pub fn get_cached_routes() -> Router {
-    Router::new()
-        .get("/", crate::handlers::home)
-        .get("/api/health", crate::handlers::health)
-}
-"#;
-    
-    fs::write("src/cache/routes.rs", route_cache)?;
+    super::route::write_route_cache()?;
     Ok(())
 }
 
@@ -1,7 +1,10 @@
 //! Queue operations commands
 
+use super::db::{block_on, DatabaseSettings};
 use crate::cli::QueueOperation;
+use crate::queue::{DatabaseQueue, QueueError};
 use colored::*;
+use std::future::Future;
 use std::thread;
 use std::time::Duration;
 
@@ -20,8 +23,14 @@ pub fn handle_operation(operation: QueueOperation) -> Result<(), Box<dyn std::er
         QueueOperation::Failed => {
             list_failed_jobs()?;
         }
-        QueueOperation::Retry { id } => {
-            retry_failed_jobs(id)?;
+        QueueOperation::Retry { id, all } => {
+            retry_failed_jobs(id, all)?;
+        }
+        QueueOperation::Stats => {
+            show_queue_stats()?;
+        }
+        QueueOperation::PruneFailed { hours } => {
+            prune_failed_jobs(hours)?;
         }
     }
     Ok(())
@@ -94,71 +103,144 @@ fn restart_queue_workers() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Connect to the application database and make sure the queue tables exist
+async fn connect() -> Result<DatabaseQueue, Box<dyn std::error::Error>> {
+    DatabaseSettings::load()?.connect_orm().await?;
+
+    let queue = DatabaseQueue::new();
+    queue.install().await?;
+    Ok(queue)
+}
+
+/// Run `task` against the database queue inside a fresh runtime
+fn with_queue<T, F, Fut>(task: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: FnOnce(DatabaseQueue) -> Fut,
+    Fut: Future<Output = Result<T, QueueError>>,
+{
+    block_on(async {
+        let queue = connect().await?;
+        Ok(task(queue).await?)
+    })
+}
+
+/// Show job counts per queue
+fn show_queue_stats() -> Result<(), Box<dyn std::error::Error>> {
+    let stats = with_queue(|queue| async move { queue.stats().await })?;
+
+    println!("{} Queue Stats", "📊".yellow().bold());
+    println!();
+
+    if stats.is_empty() {
+        println!("{} No jobs queued", "✅".green());
+        return Ok(());
+    }
+
+    println!("{:<20} {:>10} {:>10} {:>12} {:>10} {:>10}",
+             "Queue".bold(), "Pending".bold(), "Delayed".bold(), "Processing".bold(), "Failed".bold(), "Latency".bold());
+    println!("{}", "-".repeat(77));
+
+    for queue in &stats {
+        let failed = if queue.failed > 0 { queue.failed.to_string().red() } else { queue.failed.to_string().normal() };
+        println!("{:<20} {:>10} {:>10} {:>12} {:>10} {:>10}",
+                 queue.queue.cyan(),
+                 queue.pending.to_string().yellow(),
+                 queue.delayed.to_string().dimmed(),
+                 queue.processing.to_string().blue(),
+                 failed,
+                 format!("{}s", queue.latency));
+    }
+
+    Ok(())
+}
+
 /// Clear all failed jobs
 fn clear_failed_jobs() -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Clearing failed jobs...", "🗑️".yellow());
-    
-    // TODO: Implement actual failed job clearing
-    let failed_count = 3; // Simulated count
-    
+
+    let failed_count = with_queue(|queue| async move { queue.clear_failed().await })?;
+
     if failed_count > 0 {
         println!("{} Cleared {} failed jobs", "✅".green(), failed_count);
     } else {
         println!("{} No failed jobs to clear", "ℹ️".blue());
     }
-    
+
+    Ok(())
+}
+
+/// Delete failed jobs older than `hours`
+fn prune_failed_jobs(hours: u64) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} Pruning failed jobs older than {} hours...", "🗑️".yellow(), hours);
+
+    let age = Duration::from_secs(hours.saturating_mul(3600));
+    let pruned = with_queue(|queue| async move { queue.prune_failed(age).await })?;
+
+    if pruned > 0 {
+        println!("{} Pruned {} failed jobs", "✅".green(), pruned);
+    } else {
+        println!("{} No failed jobs older than {} hours", "ℹ️".blue(), hours);
+    }
+
     Ok(())
 }
 
 /// List failed jobs
 fn list_failed_jobs() -> Result<(), Box<dyn std::error::Error>> {
+    let failed_jobs = with_queue(|queue| async move { queue.failed().await })?;
+
     println!("{} Failed Jobs", "❌".red().bold());
     println!();
-    
-    // TODO: Implement actual failed job listing
-    let failed_jobs = vec![
-        ("1", "SendWelcomeEmail", "2024-01-15 10:30:00", "Connection timeout"),
-        ("2", "ProcessPayment", "2024-01-15 11:45:00", "Invalid payment method"),
-        ("3", "GenerateReport", "2024-01-15 12:15:00", "Database connection failed"),
-    ];
-    
+
     if failed_jobs.is_empty() {
         println!("{} No failed jobs found", "✅".green());
         return Ok(());
     }
-    
-    println!("{:<5} {:<20} {:<20} {}", "ID".bold(), "Job".bold(), "Failed At".bold(), "Error".bold());
-    println!("{}", "-".repeat(80));
-    
-    for (id, job, failed_at, error) in failed_jobs {
-        println!("{:<5} {:<20} {:<20} {}", 
-                 id.red(), 
-                 job.cyan(), 
-                 failed_at.yellow(), 
-                 error.red());
+
+    println!("{:<5} {:<30} {:<12} {:<20} {}", "ID".bold(), "Job".bold(), "Queue".bold(), "Failed At".bold(), "Error".bold());
+    println!("{}", "-".repeat(100));
+
+    for job in &failed_jobs {
+        let failed_at = chrono::DateTime::from_timestamp(job.failed_at as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        println!("{:<5} {:<30} {:<12} {:<20} {}",
+                 job.id.to_string().red(),
+                 job.job.cyan(),
+                 job.queue,
+                 failed_at.yellow(),
+                 job.error.red());
     }
-    
+
     println!();
     println!("{} Use 'torch queue retry <id>' to retry a specific job", "💡".blue());
-    println!("{} Use 'torch queue retry' to retry all failed jobs", "💡".blue());
-    
+    println!("{} Use 'torch queue retry --all' to retry all failed jobs", "💡".blue());
+
     Ok(())
 }
 
 /// Retry failed jobs
-fn retry_failed_jobs(id: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(job_id) = id {
-        println!("{} Retrying failed job: {}", "🔄".yellow(), job_id.cyan());
-        
-        // TODO: Implement specific job retry
-        println!("{} Job {} queued for retry", "✅".green(), job_id);
-    } else {
-        println!("{} Retrying all failed jobs...", "🔄".yellow());
-        
-        // TODO: Implement all failed jobs retry
-        let retry_count = 3; // Simulated count
-        println!("{} {} failed jobs queued for retry", "✅".green(), retry_count);
+fn retry_failed_jobs(id: Option<u64>, all: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match (id, all) {
+        (Some(job_id), _) => {
+            println!("{} Retrying failed job: {}", "🔄".yellow(), job_id.to_string().cyan());
+
+            let found = with_queue(|queue| async move { queue.retry(job_id).await })?;
+            if !found {
+                return Err(format!("No failed job with id {}", job_id).into());
+            }
+            println!("{} Job {} queued for retry", "✅".green(), job_id);
+        }
+        (None, true) => {
+            println!("{} Retrying all failed jobs...", "🔄".yellow());
+
+            let retry_count = with_queue(|queue| async move { queue.retry_all().await })?;
+            println!("{} {} failed jobs queued for retry", "✅".green(), retry_count);
+        }
+        (None, false) => {
+            return Err("Pass a job id or --all, e.g. 'torch queue retry 42' or 'torch queue retry --all'".into());
+        }
     }
-    
+
     Ok(())
 }
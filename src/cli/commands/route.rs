@@ -1,14 +1,39 @@
 //! Route operations commands
+//!
+//! Routes are registered at runtime, so the CLI can't discover them by
+//! reading source. Instead the app is built and started with
+//! [`ROUTE_DUMP_ENV`] set, which makes `App::listen` write its route table
+//! to the route cache and exit. `torch route list` reads that cache, rebuilt
+//! first when it is missing or older than the sources, or with `--server`
+//! asks a running app for its table at `/torch/routes.json`.
+//! `torch route check` rebuilds the table and reports routes that can never
+//! match and names used twice, the same checks `App::listen` runs at startup.
 
+use crate::app::ROUTE_DUMP_ENV;
 use crate::cli::RouteOperation;
+use crate::router::RouteInfo;
 use colored::*;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::Uri;
+use hyper_util::rt::TokioIo;
 use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tokio::net::TcpStream;
+use walkdir::WalkDir;
+
+/// Where the route table dumped by the app is kept
+pub const ROUTE_CACHE: &str = "storage/framework/routes.json";
+
+/// Files and directories whose changes can change the route table
+const ROUTE_SOURCES: &[&str] = &["src", "build.rs", "Cargo.toml", "Cargo.lock", "torch.toml"];
 
 /// Handle route operations
 pub fn handle_operation(operation: RouteOperation) -> Result<(), Box<dyn std::error::Error>> {
     match operation {
-        RouteOperation::List { method, name } => {
-            list_routes(method, name)?;
+        RouteOperation::List { method, name, json, server } => {
+            list_routes(method, name, json, server)?;
         }
         RouteOperation::Cache => {
             cache_routes()?;
@@ -16,101 +41,315 @@ pub fn handle_operation(operation: RouteOperation) -> Result<(), Box<dyn std::er
         RouteOperation::Clear => {
             clear_route_cache()?;
         }
+        RouteOperation::Check => {
+            check_routes()?;
+        }
     }
     Ok(())
 }
 
 /// List all registered routes
-fn list_routes(method_filter: Option<String>, name_filter: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{} Application Routes", "🛣️".yellow().bold());
-    println!();
-    
-    // TODO: Implement actual route discovery and listing
-    let routes = vec![
-        ("GET", "/", "welcome", "App::welcome"),
-        ("GET", "/hello/:name", "hello", "App::hello"),
-        ("GET", "/users", "users.index", "UserController::index"),
-        ("POST", "/users", "users.store", "UserController::store"),
-        ("GET", "/users/:id", "users.show", "UserController::show"),
-        ("PUT", "/users/:id", "users.update", "UserController::update"),
-        ("DELETE", "/users/:id", "users.destroy", "UserController::destroy"),
-    ];
-    
-    println!("{:<8} {:<25} {:<20} {}", "Method".bold(), "URI".bold(), "Name".bold(), "Action".bold());
-    println!("{}", "-".repeat(80));
-    
-    for (method, uri, route_name, action) in routes {
-        // Apply filters
-        if let Some(ref filter_method) = method_filter {
-            if !method.eq_ignore_ascii_case(filter_method) {
-                continue;
+fn list_routes(
+    method_filter: Option<String>,
+    name_filter: Option<String>,
+    json: bool,
+    server: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = match &server {
+        Some(server) => format!("{}/torch/routes.json", server.trim_end_matches('/')),
+        None => ROUTE_CACHE.to_string(),
+    };
+    let routes = match server {
+        Some(_) => fetch_routes(&source)?,
+        None => {
+            if cache_is_stale(Path::new(".")) {
+                if !json {
+                    println!("{} Route cache missing or out of date, building the application...", "ℹ️".blue());
+                }
+                write_route_cache()?;
             }
+            read_route_cache()?
         }
-        
-        if let Some(ref filter_name) = name_filter {
-            if !route_name.contains(filter_name) {
-                continue;
-            }
-        }
-        
+    };
+    let routes = filter_routes(routes, method_filter.as_deref(), name_filter.as_deref());
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&routes)?);
+        return Ok(());
+    }
+
+    println!("{} Application Routes", "🛣️".yellow().bold());
+    println!();
+
+    if routes.is_empty() {
+        println!("{} No routes matched", "ℹ️".blue());
+        return Ok(());
+    }
+
+    let path_width = column_width(routes.iter().map(|route| route.path.len()), "URI");
+    let name_width = column_width(
+        routes.iter().map(|route| route.name.as_deref().unwrap_or("").len()),
+        "Name",
+    );
+    let handler_width = column_width(
+        routes.iter().map(|route| short_type_name(&route.handler).len()),
+        "Handler",
+    );
+
+    println!(
+        "{:<8} {:<path_width$} {:<name_width$} {:<handler_width$} {}",
+        "Method".bold(),
+        "URI".bold(),
+        "Name".bold(),
+        "Handler".bold(),
+        "Middleware".bold(),
+    );
+    println!("{}", "-".repeat(8 + path_width + name_width + handler_width + 14));
+
+    for route in &routes {
+        let method = route.method.as_str();
         let method_colored = match method {
             "GET" => method.blue(),
             "POST" => method.green(),
-            "PUT" => method.yellow(),
+            "PUT" | "PATCH" => method.yellow(),
             "DELETE" => method.red(),
             _ => method.white(),
         };
-        
-        println!("{:<8} {:<25} {:<20} {}", method_colored, uri.cyan(), route_name.magenta(), action);
+        let middleware: Vec<&str> = route.middleware.iter().map(|m| short_type_name(m)).collect();
+
+        println!(
+            "{:<8} {:<path_width$} {:<name_width$} {:<handler_width$} {}",
+            method_colored,
+            route.path.cyan(),
+            route.name.as_deref().unwrap_or("").magenta(),
+            short_type_name(&route.handler),
+            middleware.join(", ").dimmed(),
+        );
     }
-    
+
+    println!();
+    println!("{} {} route(s), read from {}", "📋".blue(), routes.len(), source);
+
     Ok(())
 }
 
-/// Cache routes for faster registration
+/// Build the app and refresh the route cache
 fn cache_routes() -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Caching routes...", "💾".yellow());
-    
-    // TODO: Implement actual route caching
-    // This would involve scanning the codebase for route definitions
-    // and creating a cached representation for faster startup
-    
-    let cache_content = r#"// Generated route cache
-// This file is automatically generated. Do not edit manually.
-
-use torch_web::Router;
-
-pub fn cached_routes() -> Router {
-    Router::new()
-        .get("/", crate::handlers::welcome)
-        .get("/hello/:name", crate::handlers::hello)
-        .get("/users", crate::controllers::UserController::index)
-        .post("/users", crate::controllers::UserController::store)
-        .get("/users/:id", crate::controllers::UserController::show)
-        .put("/users/:id", crate::controllers::UserController::update)
-        .delete("/users/:id", crate::controllers::UserController::destroy)
-}
-"#;
-    
-    fs::create_dir_all("src/cache")?;
-    fs::write("src/cache/routes.rs", cache_content)?;
-    
-    println!("{} Routes cached successfully", "✅".green());
-    println!("{} Cache file: src/cache/routes.rs", "📁".blue());
-    
+
+    let count = write_route_cache()?;
+
+    println!("{} Cached {} route(s)", "✅".green(), count);
+    println!("{} Cache file: {}", "📁".blue(), ROUTE_CACHE);
+
     Ok(())
 }
 
+/// Rebuild the route table and report routes the router would never pick
+fn check_routes() -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} Checking routes...", "🔍".yellow());
+
+    write_route_cache()?;
+    let routes = read_route_cache()?;
+    let conflicts = crate::router::check_routes(&routes);
+    if conflicts.is_empty() {
+        println!("{} {} route(s), no conflicts", "✅".green(), routes.len());
+        return Ok(());
+    }
+
+    for conflict in &conflicts {
+        println!("  {} {}", "✗".red(), conflict);
+    }
+    Err(format!("{} conflicting route definition(s)", conflicts.len()).into())
+}
+
+/// Build the app, run it in route dump mode and return how many routes it has
+pub(crate) fn write_route_cache() -> Result<usize, Box<dyn std::error::Error>> {
+    let executable = super::serve::rebuild_application()?;
+
+    if let Some(dir) = Path::new(ROUTE_CACHE).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _ = fs::remove_file(ROUTE_CACHE);
+
+    let status = Command::new(&executable)
+        .env(ROUTE_DUMP_ENV, ROUTE_CACHE)
+        .status()?;
+
+    if !status.success() || !Path::new(ROUTE_CACHE).exists() {
+        return Err(format!(
+            "{} did not write a route table; make sure it starts the app with App::listen",
+            executable.display()
+        )
+        .into());
+    }
+
+    Ok(read_route_cache()?.len())
+}
+
+/// Whether the route cache under `root` is missing or older than any of the
+/// [`ROUTE_SOURCES`]
+fn cache_is_stale(root: &Path) -> bool {
+    let Ok(cached) = fs::metadata(root.join(ROUTE_CACHE)).and_then(|metadata| metadata.modified()) else {
+        return true;
+    };
+
+    ROUTE_SOURCES
+        .iter()
+        .flat_map(|source| WalkDir::new(root.join(source)).into_iter().flatten())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .any(|modified| modified > cached)
+}
+
+fn read_route_cache() -> Result<Vec<RouteInfo>, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(ROUTE_CACHE)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Ask a running app for its routing table, served by `App::route_list`
+fn fetch_routes(url: &str) -> Result<Vec<RouteInfo>, Box<dyn std::error::Error>> {
+    let uri: Uri = url.parse()?;
+    if uri.scheme_str() != Some("http") {
+        return Err(format!("{} is not an http:// URL", url).into());
+    }
+    let host = uri.host().ok_or_else(|| format!("{} has no host", url))?.to_string();
+    let address = format!("{}:{}", host, uri.port_u16().unwrap_or(80));
+
+    let body = tokio::runtime::Runtime::new()?.block_on(async move {
+        let stream = TcpStream::connect(&address).await?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+        let request = hyper::Request::get(uri).header("host", host).body(Empty::<Bytes>::new())?;
+        let response = sender.send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "{} answered {}; is the app running in development with App::route_list?",
+                url,
+                response.status()
+            )
+            .into());
+        }
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(response.into_body().collect().await?.to_bytes())
+    }).map_err(|e| e.to_string())?;
+
+    #[derive(serde::Deserialize)]
+    struct Listing {
+        routes: Vec<RouteInfo>,
+    }
+    Ok(serde_json::from_slice::<Listing>(&body)?.routes)
+}
+
 /// Clear the route cache
 fn clear_route_cache() -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Clearing route cache...", "🗑️".yellow());
-    
-    if std::path::Path::new("src/cache/routes.rs").exists() {
-        fs::remove_file("src/cache/routes.rs")?;
+
+    if Path::new(ROUTE_CACHE).exists() {
+        fs::remove_file(ROUTE_CACHE)?;
         println!("{} Route cache cleared successfully", "✅".green());
     } else {
         println!("{} No route cache found", "ℹ️".blue());
     }
-    
+
     Ok(())
 }
+
+fn filter_routes(routes: Vec<RouteInfo>, method: Option<&str>, name: Option<&str>) -> Vec<RouteInfo> {
+    routes
+        .into_iter()
+        .filter(|route| method.map_or(true, |method| route.method.eq_ignore_ascii_case(method)))
+        .filter(|route| {
+            name.map_or(true, |name| route.name.as_deref().is_some_and(|route_name| route_name.contains(name)))
+        })
+        .collect()
+}
+
+/// Strip module paths from a type name for display
+///
+/// `my_app::controllers::PostController::index` becomes `PostController::index`
+/// and closures keep the function that defined them, e.g. `main::{{closure}}`.
+fn short_type_name(name: &str) -> &str {
+    if name.is_empty() {
+        return "-";
+    }
+    if name.contains('<') {
+        return name;
+    }
+    let segments: Vec<&str> = name.split("::").collect();
+    // Closures are shown with the function that defines them, methods with their type
+    let closures = segments.iter().rev().take_while(|segment| segment.starts_with('{')).count();
+    let item = segments.len().saturating_sub(closures + 1);
+    let owner = segments.get(item).is_some_and(|segment| segment.starts_with(char::is_lowercase));
+    let keep = closures + 1 + usize::from(owner && closures == 0);
+    if segments.len() <= keep {
+        return name;
+    }
+    let skipped: usize = segments[..segments.len() - keep].iter().map(|segment| segment.len() + 2).sum();
+    &name[skipped..]
+}
+
+fn column_width(lengths: impl Iterator<Item = usize>, header: &str) -> usize {
+    lengths.max().unwrap_or(0).max(header.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(method: &str, path: &str, name: Option<&str>) -> RouteInfo {
+        RouteInfo {
+            method: method.to_string(),
+            path: path.to_string(),
+            name: name.map(str::to_string),
+            handler: String::new(),
+            middleware: Vec::new(),
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_is_stale_after_source_changes() {
+        let root = std::env::temp_dir().join(format!("torch-route-{}", std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("storage/framework")).unwrap();
+        assert!(cache_is_stale(&root));
+
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        let main = fs::File::create(root.join("src/main.rs")).unwrap();
+        main.set_modified(old).unwrap();
+        fs::write(root.join(ROUTE_CACHE), "[]").unwrap();
+        assert!(!cache_is_stale(&root));
+
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        assert!(cache_is_stale(&root));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_filter_routes() {
+        let routes = vec![
+            route("GET", "/posts", Some("posts.index")),
+            route("POST", "/posts", Some("posts.store")),
+            route("GET", "/health", None),
+        ];
+
+        let gets = filter_routes(routes.clone(), Some("get"), None);
+        assert_eq!(gets.len(), 2);
+
+        let named = filter_routes(routes.clone(), None, Some("posts"));
+        assert_eq!(named.len(), 2);
+
+        let both = filter_routes(routes, Some("POST"), Some("posts"));
+        assert_eq!(both, vec![route("POST", "/posts", Some("posts.store"))]);
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name("my_app::controllers::PostController::index"), "PostController::index");
+        assert_eq!(short_type_name("my_app::main::{{closure}}"), "main::{{closure}}");
+        assert_eq!(short_type_name("my_app::main::{{closure}}::{{closure}}"), "main::{{closure}}::{{closure}}");
+        assert_eq!(short_type_name("torch_web::middleware::Logger"), "Logger");
+        assert_eq!(short_type_name(""), "-");
+    }
+}
@@ -1,6 +1,8 @@
 //! Schedule operations commands
 
+use super::db::{block_on, DatabaseSettings};
 use crate::cli::ScheduleOperation;
+use crate::schedule::Schedule;
 use colored::*;
 use std::fs;
 use std::path::Path;
@@ -15,7 +17,10 @@ pub fn handle_operation(operation: ScheduleOperation) -> Result<(), Box<dyn std:
         ScheduleOperation::ClearCache => {
             clear_schedule_cache()?;
         }
-        ScheduleOperation::List => {
+        ScheduleOperation::List { history: true, limit } => {
+            list_task_runs(limit)?;
+        }
+        ScheduleOperation::List { history: false, .. } => {
             list_scheduled_tasks()?;
         }
     }
@@ -143,6 +148,51 @@ fn list_scheduled_tasks() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Show the latest runs recorded by apps that call `Schedule::persist_history`
+fn list_task_runs(limit: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let runs = block_on(async {
+        DatabaseSettings::load()?.connect_orm().await?;
+        Ok::<_, Box<dyn std::error::Error>>(Schedule::stored_history(limit).await?)
+    })?;
+
+    println!("{} Task Runs", "📜".yellow().bold());
+    println!();
+
+    if runs.is_empty() {
+        println!("{} No task runs recorded", "ℹ️".blue());
+        return Ok(());
+    }
+
+    println!("{:<25} {:<20} {:>10} {:<8} {:>5} {}",
+             "Task".bold(), "Started".bold(), "Duration".bold(), "Status".bold(), "Exit".bold(), "Output".bold());
+    println!("{}", "-".repeat(85));
+
+    for run in &runs {
+        let started = chrono::DateTime::from_timestamp(run.started_at as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let status = match (run.success, run.slow) {
+            (false, _) => "failed".red(),
+            (true, true) => "slow".yellow(),
+            (true, false) => "ok".green(),
+        };
+        let exit = run.exit_code.map_or_else(|| "-".to_string(), |code| code.to_string());
+        // The last line is usually the error, or the summary of a command
+        let output = if run.stderr.trim().is_empty() { &run.stdout } else { &run.stderr };
+        let last_line = output.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("");
+
+        println!("{:<25} {:<20} {:>10} {:<8} {:>5} {}",
+                 run.task.cyan(),
+                 started,
+                 format!("{}ms", run.duration_ms),
+                 status,
+                 exit,
+                 last_line.dimmed());
+    }
+
+    Ok(())
+}
+
 /// Generate a new scheduled task
 pub fn generate_scheduled_task(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Generating scheduled task: {}", "⏰".yellow(), name.cyan().bold());
@@ -0,0 +1,442 @@
+//! `torch db diff`: compare the live database with the schema the
+//! migrations describe
+//!
+//! Migrations are Rust, so the comparison runs inside the app, as its
+//! [`migrate --diff`](crate::console::Migrate) command: each migration's
+//! `up_sql` is called and the tables it builds with
+//! [`Schema`](crate::orm::migration::Schema) are recorded, for the
+//! configured database's driver. Schema changed any other way, such as raw
+//! SQL, isn't seen.
+
+use crate::cli::generators::{self, TableChanges};
+use crate::console::CommandError;
+use crate::orm::migration::{MigrationRunner, SchemaChange, TableBuilder};
+use crate::orm::schema::{Schema, TableInfo};
+use colored::*;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Name of the migration `torch db diff --write` generates
+const FIX_MIGRATION: &str = "fix_schema_drift";
+
+/// A column the migrations expect
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExpectedColumn {
+    pub name: String,
+    /// Builder call adding the column
+    pub definition: String,
+    /// Migration adding it
+    pub source: String,
+}
+
+/// An index the migrations expect
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExpectedIndex {
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub definition: String,
+    pub source: String,
+}
+
+/// A table as the migrations describe it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ExpectedTable {
+    pub name: String,
+    pub columns: Vec<ExpectedColumn>,
+    pub indexes: Vec<ExpectedIndex>,
+    pub source: String,
+}
+
+impl ExpectedTable {
+    fn new(name: &str, source: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            source: source.to_string(),
+            ..Self::default()
+        }
+    }
+
+    fn add_column(&mut self, column: ExpectedColumn) {
+        self.columns.retain(|existing| !existing.name.eq_ignore_ascii_case(&column.name));
+        self.columns.push(column);
+    }
+
+    fn drop_column(&mut self, name: &str) {
+        self.columns.retain(|column| !column.name.eq_ignore_ascii_case(name));
+        self.indexes
+            .retain(|index| !index.columns.iter().any(|column| column.eq_ignore_ascii_case(name)));
+    }
+}
+
+/// Expected tables, keyed by lowercased name
+pub(crate) type ExpectedSchema = BTreeMap<String, ExpectedTable>;
+
+/// A table, column or index the database lacks
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Drift {
+    Table(ExpectedTable),
+    Column { table: String, column: ExpectedColumn },
+    Index { table: String, index: ExpectedIndex },
+}
+
+/// Run the app's `migrate --diff` command, which compares the database with
+/// the migrations and with `write` generates a migration adding what's
+/// missing
+pub fn diff_database(write: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = vec!["migrate".to_string(), "--diff".to_string()];
+    if write {
+        args.push("--write".to_string());
+    }
+    super::console::run_app_command(&args)
+}
+
+/// Compare the connected database with `runner`'s migrations, and with
+/// `write` generate a migration adding what's missing
+pub(crate) async fn diff_migrations(runner: &MigrationRunner, write: bool) -> Result<(), CommandError> {
+    let expected = expected_schema(runner);
+    if expected.is_empty() {
+        println!("{} No migrations describe any tables", "ℹ️".blue());
+        return Ok(());
+    }
+
+    println!("{} Comparing the database with the migrations...", "🔍".yellow());
+    let actual = introspect(&expected).await?;
+    let drift = diff(&expected, &actual);
+    if drift.is_empty() {
+        println!("{} The database matches the migrations", "✅".green());
+        return Ok(());
+    }
+
+    print_drift(&drift);
+    println!();
+
+    if !write {
+        println!("{} Run {} to generate a migration adding them", "💡".yellow(), "torch db diff --write".cyan());
+        return Err(format!("Found {} difference(s) between the database and the migrations", drift.len()).into());
+    }
+
+    let version = chrono::Utc::now().format("%Y_%m_%d_%H%M%S").to_string();
+    let path = format!("migrations/{}_{}.rs", version, FIX_MIGRATION);
+    fs::create_dir_all("migrations")?;
+    fs::write(&path, generators::generate_schema_changes_migration(FIX_MIGRATION, &version, &changes(&drift)))?;
+    println!("{} Migration created: {}", "✅".green(), path);
+    println!("{} Review the column types and defaults, then run {}", "💡".yellow(), "torch migrate".cyan());
+
+    Ok(())
+}
+
+/// Read the live definition of every expected table that exists
+async fn introspect(expected: &ExpectedSchema) -> Result<BTreeMap<String, TableInfo>, CommandError> {
+    let schema = Schema::new().await?;
+    let mut actual = BTreeMap::new();
+    for table in schema.table_names().await? {
+        if expected.contains_key(&table.to_lowercase()) {
+            actual.insert(table.to_lowercase(), schema.table_info(&table).await?);
+        }
+    }
+    Ok(actual)
+}
+
+fn print_drift(drift: &[Drift]) {
+    for item in drift {
+        match item {
+            Drift::Table(table) => {
+                println!("{} Missing table {} ({})", "❌".red(), table.name.cyan(), table.source);
+            }
+            Drift::Column { table, column } => {
+                println!("{} Missing column {}.{} ({})", "❌".red(), table.cyan(), column.name.cyan(), column.source);
+            }
+            Drift::Index { table, index } => {
+                println!(
+                    "{} Missing {}index on {} ({}) ({})",
+                    "❌".red(),
+                    if index.unique { "unique " } else { "" },
+                    table.cyan(),
+                    index.columns.join(", ").cyan(),
+                    index.source
+                );
+            }
+        }
+    }
+}
+
+/// The schema `runner`'s migrations build, applied in the order they were
+/// added
+pub(crate) fn expected_schema(runner: &MigrationRunner) -> ExpectedSchema {
+    let mut schema = ExpectedSchema::new();
+    for (migration, changes) in runner.schema_changes() {
+        for change in changes {
+            match change {
+                SchemaChange::Create(table) => {
+                    let mut created = ExpectedTable::new(&table.table_name, &migration);
+                    apply_table(&mut created, &migration, &table);
+                    schema.insert(table.table_name.to_lowercase(), created);
+                }
+                SchemaChange::Alter(table) => {
+                    let altered = schema
+                        .entry(table.table_name.to_lowercase())
+                        .or_insert_with(|| ExpectedTable::new(&table.table_name, &migration));
+                    apply_table(altered, &migration, &table);
+                }
+                SchemaChange::Drop(table) => {
+                    schema.remove(&table.to_lowercase());
+                }
+            }
+        }
+    }
+    schema
+}
+
+/// Add the columns and indexes of `table` to `expected`, and drop the
+/// columns it drops
+fn apply_table(expected: &mut ExpectedTable, migration: &str, table: &TableBuilder) {
+    for column in &table.columns {
+        if column.unique {
+            expected.indexes.push(ExpectedIndex {
+                columns: vec![column.name.clone()],
+                unique: true,
+                definition: format!("table.unique_index(&[{:?}], None)", column.name),
+                source: migration.to_string(),
+            });
+        }
+        expected.add_column(ExpectedColumn {
+            name: column.name.clone(),
+            definition: column.to_builder_call(),
+            source: migration.to_string(),
+        });
+    }
+    for column in &table.dropped_columns {
+        expected.drop_column(column);
+    }
+    for index in &table.indexes {
+        expected.indexes.push(ExpectedIndex {
+            columns: index.columns.clone(),
+            unique: index.unique,
+            definition: index.to_builder_call(&table.table_name),
+            source: migration.to_string(),
+        });
+    }
+}
+
+/// What `actual` lacks of `expected`
+pub(crate) fn diff(expected: &ExpectedSchema, actual: &BTreeMap<String, TableInfo>) -> Vec<Drift> {
+    let mut drift = Vec::new();
+    for (key, table) in expected {
+        let Some(live) = actual.get(key) else {
+            drift.push(Drift::Table(table.clone()));
+            continue;
+        };
+        for column in &table.columns {
+            if !live.columns.iter().any(|live| live.name.eq_ignore_ascii_case(&column.name)) {
+                drift.push(Drift::Column {
+                    table: table.name.clone(),
+                    column: column.clone(),
+                });
+            }
+        }
+        for index in &table.indexes {
+            let covered = live.indexes.iter().any(|live| {
+                (live.is_unique || !index.unique)
+                    && live.columns.len() == index.columns.len()
+                    && live.columns.iter().zip(&index.columns).all(|(a, b)| a.eq_ignore_ascii_case(b))
+            });
+            // An index on a missing column comes back with the column
+            let columns_exist = index
+                .columns
+                .iter()
+                .all(|column| live.columns.iter().any(|live| live.name.eq_ignore_ascii_case(column)));
+            if !covered || !columns_exist {
+                drift.push(Drift::Index {
+                    table: table.name.clone(),
+                    index: index.clone(),
+                });
+            }
+        }
+    }
+    drift
+}
+
+/// Builder calls adding what `drift` lists, one entry per table
+pub(crate) fn changes(drift: &[Drift]) -> Vec<TableChanges> {
+    let mut changes: Vec<TableChanges> = Vec::new();
+    let mut entry = |table: &str, create: bool| -> usize {
+        match changes.iter().position(|change| change.table == table) {
+            Some(position) => position,
+            None => {
+                changes.push(TableChanges {
+                    table: table.to_string(),
+                    create,
+                    ..TableChanges::default()
+                });
+                changes.len() - 1
+            }
+        }
+    };
+
+    let mut lines: Vec<(usize, Vec<String>, Option<String>)> = Vec::new();
+    for item in drift {
+        match item {
+            Drift::Table(table) => {
+                let position = entry(&table.name, true);
+                let mut up: Vec<String> = table.columns.iter().map(|column| column.definition.clone()).collect();
+                up.extend(table.indexes.iter().map(|index| index.definition.clone()));
+                lines.push((position, up, None));
+            }
+            Drift::Column { table, column } => {
+                let position = entry(table, false);
+                let down = format!("table.drop_column(\"{}\")", column.name);
+                lines.push((position, vec![column.definition.clone()], Some(down)));
+            }
+            Drift::Index { table, index } => {
+                let position = entry(table, false);
+                lines.push((position, vec![index.definition.clone()], None));
+            }
+        }
+    }
+    for (position, up, down) in lines {
+        changes[position].up.extend(up);
+        changes[position].down.extend(down);
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::migration::{self, Migration};
+    use crate::orm::schema::{ColumnInfo, IndexInfo};
+
+    struct CreatePosts;
+
+    impl Migration for CreatePosts {
+        fn name(&self) -> &str {
+            "2024_01_01_000000_create_posts_table"
+        }
+
+        fn version(&self) -> &str {
+            "2024_01_01_000000"
+        }
+
+        fn up_sql(&self) -> String {
+            migration::Schema::create_table("posts", |table| {
+                table.id("id");
+                table.string("slug", None).unique();
+                table.string("title", Some(120));
+                table.text("draft").nullable();
+                table.index(&["title"], None);
+                table.timestamps();
+            })
+            .to_sql()
+        }
+
+        fn down_sql(&self) -> String {
+            migration::Schema::drop_table("posts").to_sql()
+        }
+    }
+
+    struct UpdatePosts;
+
+    impl Migration for UpdatePosts {
+        fn name(&self) -> &str {
+            "2024_01_02_000000_update_posts_table"
+        }
+
+        fn version(&self) -> &str {
+            "2024_01_02_000000"
+        }
+
+        fn up_sql(&self) -> String {
+            [
+                migration::Schema::alter_table("posts", |table| {
+                    table.drop_column("draft");
+                    table.boolean("published").default("false");
+                })
+                .to_sql(),
+                migration::Schema::create_table("tags", |table| {
+                    table.id("id");
+                })
+                .to_sql(),
+                migration::Schema::create_table("scratch", |table| {
+                    table.id("id");
+                })
+                .to_sql(),
+                migration::Schema::drop_table("scratch").to_sql(),
+                // Raw SQL isn't seen
+                "CREATE TABLE legacy (id INTEGER)".to_string(),
+            ]
+            .join(";\n")
+        }
+
+        fn down_sql(&self) -> String {
+            String::new()
+        }
+    }
+
+    #[test]
+    fn test_diff_against_migrations() {
+        let mut runner = MigrationRunner::new();
+        runner.add_migration(Box::new(CreatePosts));
+        runner.add_migration(Box::new(UpdatePosts));
+        let schema = expected_schema(&runner);
+        assert_eq!(schema.keys().collect::<Vec<_>>(), vec!["posts", "tags"]);
+
+        let posts = &schema["posts"];
+        let columns: Vec<&str> = posts.columns.iter().map(|column| column.name.as_str()).collect();
+        assert_eq!(columns, vec!["id", "slug", "title", "created_at", "updated_at", "published"]);
+        let definitions: Vec<&str> = posts.columns.iter().map(|column| column.definition.as_str()).collect();
+        assert_eq!(
+            definitions,
+            vec![
+                "table.id(\"id\")",
+                "table.string(\"slug\", None).unique()",
+                "table.string(\"title\", Some(120))",
+                "table.timestamp(\"created_at\").nullable().default(\"CURRENT_TIMESTAMP\")",
+                "table.timestamp(\"updated_at\").nullable().default(\"CURRENT_TIMESTAMP\")",
+                "table.boolean(\"published\").default(\"false\")",
+            ]
+        );
+        assert_eq!(posts.columns[5].source, "2024_01_02_000000_update_posts_table");
+        assert_eq!(posts.indexes.len(), 2);
+
+        let column = |name: &str| ColumnInfo {
+            name: name.to_string(),
+            data_type: "text".to_string(),
+            is_nullable: true,
+            default_value: None,
+            max_length: None,
+            precision: None,
+            scale: None,
+            position: 0,
+        };
+        let live = TableInfo {
+            name: "posts".to_string(),
+            columns: ["id", "slug", "title", "created_at", "updated_at"].into_iter().map(column).collect(),
+            indexes: vec![IndexInfo {
+                name: "posts_slug_key".to_string(),
+                is_unique: true,
+                is_primary: false,
+                columns: vec!["slug".to_string()],
+            }],
+            foreign_keys: vec![],
+        };
+        let drift = diff(&schema, &BTreeMap::from([("posts".to_string(), live)]));
+        let summary: Vec<String> = drift
+            .iter()
+            .map(|item| match item {
+                Drift::Table(table) => format!("table {}", table.name),
+                Drift::Column { column, .. } => format!("column {}", column.name),
+                Drift::Index { index, .. } => format!("index {}", index.columns.join(",")),
+            })
+            .collect();
+        assert_eq!(summary, vec!["column published", "index title", "table tags"]);
+
+        let changes = changes(&drift);
+        assert_eq!(
+            changes[0].up,
+            vec!["table.boolean(\"published\").default(\"false\")", "table.index(&[\"title\"], None)"]
+        );
+        assert_eq!(changes[0].down, vec!["table.drop_column(\"published\")"]);
+        assert_eq!((changes[1].table.as_str(), changes[1].create), ("tags", true));
+        assert_eq!(changes[1].up, vec!["table.id(\"id\")"]);
+    }
+}
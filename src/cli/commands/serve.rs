@@ -1,13 +1,40 @@
 //! Development server command
 
 use colored::*;
-use std::process::{Command, Child, Stdio};
-use std::path::Path;
-use std::time::Duration;
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use http_body_util::{combinators::BoxBody, BodyExt, Full, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, UPGRADE};
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::{TcpListener, TcpStream};
 use walkdir::WalkDir;
 
+use crate::app::{DEV_SERVER_ENV, SERVE_ADDR_ENV};
+
+/// Endpoint the injected script subscribes to for reload events
+const LIVE_RELOAD_PATH: &str = "/__torch/livereload";
+
+/// Script injected into HTML pages served through the dev proxy
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>(function(){var s=new EventSource("/__torch/livereload");s.addEventListener("reload",function(){location.reload()});})();</script>"#;
+
+/// How long the watcher must be quiet before a batch of changes is handled
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How long a freshly built app gets to start accepting connections
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type ProxyBody = BoxBody<Bytes, BoxError>;
+
 /// Start development server
 pub fn start_server(host: &str, port: u16, hot: bool) -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Starting Torch development server...", "🔥".yellow());
@@ -24,86 +51,211 @@ pub fn start_server(host: &str, port: u16, hot: bool) -> Result<(), Box<dyn std:
     Ok(())
 }
 
+/// What a changed file requires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Change {
+    /// Templates and static assets are re-read by the running app
+    Reload,
+    /// Rust sources and manifests need a rebuild and restart
+    Rebuild,
+}
+
+fn classify(path: &str) -> Change {
+    let path = Path::new(path);
+    let is_manifest = matches!(
+        path.file_name().and_then(|name| name.to_str()),
+        Some("Cargo.toml" | "Cargo.lock" | "torch.toml" | "build.rs")
+    );
+    let is_rust = path.extension().is_some_and(|ext| ext == "rs");
+
+    if is_manifest || is_rust {
+        Change::Rebuild
+    } else {
+        Change::Reload
+    }
+}
+
+/// The app binary currently serving requests behind the proxy
+struct RunningApp {
+    child: Child,
+    port: u16,
+    /// Copy of the build the app runs from, removed once it stops
+    executable: PathBuf,
+}
+
+impl RunningApp {
+    fn stop(mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.executable);
+    }
+}
+
 /// Start server with hot reload functionality
-fn start_hot_reload_server(_host: &str, _port: u16) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{} Press Ctrl+C to stop", "💡".yellow());
-    println!();
+///
+/// The CLI owns the public port and proxies to the app, which runs on an
+/// internal port chosen per restart. A rebuilt binary is started next to the
+/// old one and only receives traffic once it accepts connections, so a broken
+/// build never takes the site down. Each build runs from its own copy, leaving
+/// cargo free to relink while the previous one still runs (Windows won't let
+/// it overwrite a running executable). HTML pages get a small script that
+/// listens on an SSE endpoint and refreshes the page after every reload.
+fn start_hot_reload_server(host: &str, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let listener = runtime.block_on(TcpListener::bind(addr))?;
+    let proxy = DevProxy::default();
+    runtime.spawn(proxy.clone().run(listener));
 
     let (tx, rx) = mpsc::channel();
-
-    // Start file watcher in a separate thread
-    let watcher_tx = tx.clone();
     thread::spawn(move || {
-        if let Err(e) = watch_files(watcher_tx) {
+        if let Err(e) = watch_files(tx) {
             eprintln!("{} File watcher error: {}", "❌".red(), e);
         }
     });
 
-    let mut server_process: Option<Child> = None;
+    println!("{} Press Ctrl+C to stop", "💡".yellow());
+    println!();
 
-    // Start initial server
-    server_process = Some(start_server_process()?);
-    println!("{} Server started successfully", "✅".green());
+    let mut app = start_app(&proxy);
 
-    // Listen for file changes
     loop {
-        match rx.recv_timeout(Duration::from_millis(100)) {
-            Ok(file_path) => {
-                println!("{} File changed: {}", "🔄".yellow(), file_path.cyan());
-
-                // Kill existing server
-                if let Some(mut process) = server_process.take() {
-                    println!("{} Stopping server...", "🛑".yellow());
-                    let _ = process.kill();
-                    let _ = process.wait();
-                }
-
-                // Wait a bit for file operations to complete
-                thread::sleep(Duration::from_millis(500));
-
-                // Rebuild and restart server
-                println!("{} Rebuilding application...", "🔨".blue());
-                if rebuild_application().is_ok() {
-                    server_process = Some(start_server_process()?);
-                    println!("{} Server restarted successfully", "✅".green());
-                } else {
-                    println!("{} Build failed - waiting for next change", "❌".red());
-                }
-            }
+        let first = match rx.recv_timeout(Duration::from_millis(250)) {
+            Ok(path) => path,
             Err(mpsc::RecvTimeoutError::Timeout) => {
-                // Check if server process is still running
-                if let Some(ref mut process) = server_process {
-                    match process.try_wait() {
-                        Ok(Some(status)) => {
-                            println!("{} Server process exited with status: {}", "⚠️".yellow(), status);
-                            break;
-                        }
-                        Ok(None) => {
-                            // Process is still running
-                        }
-                        Err(e) => {
-                            println!("{} Error checking server process: {}", "❌".red(), e);
-                            break;
-                        }
+                if let Some(status) = app.as_mut().and_then(|running| running.child.try_wait().ok().flatten()) {
+                    println!("{} App exited with {} - waiting for changes", "⚠️".yellow(), status);
+                    proxy.set_target(None);
+                    if let Some(exited) = app.take() {
+                        exited.stop();
                     }
                 }
+                continue;
             }
             Err(mpsc::RecvTimeoutError::Disconnected) => {
                 println!("{} File watcher disconnected", "⚠️".yellow());
                 break;
             }
+        };
+
+        let mut changed = vec![first];
+        while let Ok(path) = rx.recv_timeout(DEBOUNCE) {
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        for path in &changed {
+            println!("{} File changed: {}", "🔄".yellow(), path.cyan());
+        }
+
+        match changed.iter().map(|path| classify(path)).max() {
+            Some(Change::Rebuild) => {
+                if let Some(started) = start_app(&proxy) {
+                    if let Some(previous) = app.replace(started) {
+                        previous.stop();
+                    }
+                    proxy.reload();
+                }
+            }
+            Some(Change::Reload) => {
+                println!("{} Reloading browsers", "🌐".blue());
+                proxy.reload();
+            }
+            None => {}
         }
     }
 
-    // Clean up server process
-    if let Some(mut process) = server_process {
-        let _ = process.kill();
-        let _ = process.wait();
+    if let Some(running) = app {
+        running.stop();
     }
+    runtime.shutdown_background();
 
     Ok(())
 }
 
+/// Build the app and start it on a fresh internal port, switching the proxy
+/// over once it is listening. Failures are reported and leave the proxy as is.
+fn start_app(proxy: &DevProxy) -> Option<RunningApp> {
+    println!("{} Building application...", "🔨".blue());
+    let started = rebuild_application().and_then(|built| {
+        let port = free_port()?;
+        let executable = run_copy(&built, port)?;
+        let child = Command::new(&executable)
+            .env(SERVE_ADDR_ENV, format!("127.0.0.1:{}", port))
+            .env(DEV_SERVER_ENV, "1")
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", executable.display(), e));
+        let mut app = match child {
+            Ok(child) => RunningApp { child, port, executable },
+            Err(e) => {
+                let _ = std::fs::remove_file(&executable);
+                return Err(e.into());
+            }
+        };
+
+        if let Err(e) = wait_until_listening(&mut app.child, port) {
+            app.stop();
+            return Err(e);
+        }
+        Ok(app)
+    });
+
+    match started {
+        Ok(running) => {
+            proxy.set_target(Some(running.port));
+            println!("{} App running (internal port {})", "✅".green(), running.port);
+            Some(running)
+        }
+        Err(e) => {
+            println!("{} {} - waiting for next change", "❌".red(), e);
+            None
+        }
+    }
+}
+
+/// Copy a freshly built binary to `torch-serve/` next to it, named after the
+/// port it will listen on, so the next build can replace the original.
+/// Copies left by a session that was interrupted are cleared out first; one
+/// still running can't be removed on Windows and is unaffected elsewhere.
+fn run_copy(built: &Path, port: u16) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let directory = built.parent().unwrap_or(Path::new(".")).join("torch-serve");
+    std::fs::create_dir_all(&directory)?;
+    for entry in std::fs::read_dir(&directory)?.flatten() {
+        let _ = std::fs::remove_file(entry.path());
+    }
+
+    let stem = built.file_stem().and_then(|stem| stem.to_str()).unwrap_or("app");
+    let mut name = format!("{}-{}", stem, port);
+    if let Some(extension) = built.extension().and_then(|extension| extension.to_str()) {
+        name.push('.');
+        name.push_str(extension);
+    }
+    let copy = directory.join(name);
+    std::fs::copy(built, &copy).map_err(|e| format!("Failed to copy {}: {}", built.display(), e))?;
+    Ok(copy)
+}
+
+/// Ask the OS for a port that is free right now
+fn free_port() -> Result<u16, Box<dyn std::error::Error>> {
+    Ok(std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+fn wait_until_listening(child: &mut Child, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Err(format!("App exited during startup with {}", status).into());
+        }
+        if StdTcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Err(format!("App did not start listening within {:?}", STARTUP_TIMEOUT).into())
+}
+
 /// Start normal server without hot reload
 fn start_normal_server() -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Press Ctrl+C to stop", "💡".yellow());
@@ -134,59 +286,203 @@ fn start_normal_server() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Start server process for hot reload
-fn start_server_process() -> Result<Child, Box<dyn std::error::Error>> {
-    let mut cmd = Command::new("cargo");
+/// Rebuild the application and return the path of the binary to run
+pub(crate) fn rebuild_application() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !Path::new("Cargo.toml").exists() {
+        return Err("No Cargo.toml found. Make sure you're in a Rust project directory.".into());
+    }
 
-    // Check if we're in a Torch project directory
-    if Path::new("Cargo.toml").exists() {
-        // Try to run the server binary first
-        cmd.arg("run")
-           .arg("--bin")
-           .arg("server")
-           .stdout(Stdio::piped())
-           .stderr(Stdio::piped());
-
-        // Try to spawn the process
-        match cmd.spawn() {
-            Ok(child) => Ok(child),
-            Err(_) => {
-                // Fallback to default cargo run
-                let mut fallback_cmd = Command::new("cargo");
-                fallback_cmd.arg("run")
-                           .stdout(Stdio::piped())
-                           .stderr(Stdio::piped());
-
-                fallback_cmd.spawn().map_err(|e| {
-                    format!("Failed to start server: {}. Make sure you're in a Torch project directory.", e).into()
-                })
-            }
+    // Diagnostics are rendered to stderr as usual; stdout carries the JSON
+    // messages that name the executables that were built.
+    let output = Command::new("cargo")
+        .args(["build", "--message-format=json-render-diagnostics"])
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        return Err("Build failed".into());
+    }
+
+    find_executable(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "Build produced no binary to run".into())
+}
+
+/// Pick the binary from cargo's JSON messages, preferring one named `server`
+fn find_executable(messages: &str) -> Option<PathBuf> {
+    let executables: Vec<(String, PathBuf)> = messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter_map(|message| {
+            let path = message["executable"].as_str()?;
+            let name = message["target"]["name"].as_str()?;
+            Some((name.to_string(), PathBuf::from(path)))
+        })
+        .collect();
+
+    executables
+        .iter()
+        .find(|(name, _)| name == "server")
+        .or_else(|| executables.first())
+        .map(|(_, path)| path.clone())
+}
+
+/// Reverse proxy in front of the app that adds live reload
+#[derive(Clone, Default)]
+struct DevProxy {
+    /// Internal port of the running app, 0 while none is available
+    target: Arc<AtomicU16>,
+    /// Open live reload streams
+    clients: Arc<Mutex<Vec<tokio::sync::mpsc::Sender<Bytes>>>>,
+}
+
+impl DevProxy {
+    fn set_target(&self, port: Option<u16>) {
+        self.target.store(port.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    /// Tell every connected browser to refresh
+    fn reload(&self) {
+        self.clients.lock().unwrap().retain(|client| {
+            !matches!(
+                client.try_send(Bytes::from_static(b"event: reload\ndata: {}\n\n")),
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_))
+            )
+        });
+    }
+
+    async fn run(self, listener: TcpListener) {
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    eprintln!("{} Proxy accept error: {}", "❌".red(), e);
+                    continue;
+                }
+            };
+
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                let service = hyper::service::service_fn(move |req| proxy.clone().handle(req));
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .with_upgrades()
+                    .await;
+            });
+        }
+    }
+
+    async fn handle(self, req: Request<Incoming>) -> Result<Response<ProxyBody>, Infallible> {
+        if req.uri().path() == LIVE_RELOAD_PATH {
+            return Ok(self.subscribe());
+        }
+
+        let port = self.target.load(Ordering::SeqCst);
+        if port == 0 {
+            return Ok(unavailable("The application is building or failed to start."));
+        }
+
+        match forward(req, port).await {
+            Ok(response) => Ok(response),
+            Err(e) => Ok(unavailable(&format!("Could not reach the application: {}", e))),
         }
-    } else {
-        Err("No Cargo.toml found. Make sure you're in a Rust project directory.".into())
+    }
+
+    fn subscribe(&self) -> Response<ProxyBody> {
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
+        let _ = tx.try_send(Bytes::from_static(b": connected\n\n"));
+        self.clients.lock().unwrap().push(tx);
+
+        let events = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (Ok::<_, BoxError>(Frame::data(chunk)), rx))
+        });
+
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(BodyExt::boxed(StreamBody::new(events)))
+            .unwrap()
     }
 }
 
-/// Rebuild the application
-fn rebuild_application() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::new("cargo");
-    cmd.arg("build");
+/// Send a request to the app, injecting the reload script into HTML and
+/// tunnelling protocol upgrades (WebSockets) through untouched
+async fn forward(mut req: Request<Incoming>, port: u16) -> Result<Response<ProxyBody>, BoxError> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        let _ = connection.with_upgrades().await;
+    });
 
-    let output = cmd.output()?;
+    let client_upgrade = req.headers().contains_key(UPGRADE).then(|| hyper::upgrade::on(&mut req));
+    let mut response = sender.send_request(req).await?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        println!("{} Build errors:", "❌".red());
-        println!("{}", stderr);
-        return Err("Build failed".into());
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(client_upgrade) = client_upgrade {
+            let app_upgrade = hyper::upgrade::on(&mut response);
+            tokio::spawn(async move {
+                if let (Ok(client), Ok(app)) = (client_upgrade.await, app_upgrade.await) {
+                    let _ = tokio::io::copy_bidirectional(&mut TokioIo::new(client), &mut TokioIo::new(app)).await;
+                }
+            });
+        }
     }
 
-    Ok(())
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if !is_html || response.headers().contains_key(CONTENT_ENCODING) {
+        return Ok(response.map(|body| body.map_err(BoxError::from).boxed()));
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let html = body.collect().await?.to_bytes();
+    let body = match inject_script(&html) {
+        Some(page) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            full(page)
+        }
+        None => full(html),
+    };
+    Ok(Response::from_parts(parts, body))
+}
+
+/// Insert the live reload script before `</body>`. Fragments without one,
+/// such as htmx partials, are left alone.
+fn inject_script(html: &[u8]) -> Option<Vec<u8>> {
+    let position = html
+        .windows(7)
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))?;
+
+    let mut output = Vec::with_capacity(html.len() + LIVE_RELOAD_SCRIPT.len());
+    output.extend_from_slice(&html[..position]);
+    output.extend_from_slice(LIVE_RELOAD_SCRIPT.as_bytes());
+    output.extend_from_slice(&html[position..]);
+    Some(output)
+}
+
+fn unavailable(message: &str) -> Response<ProxyBody> {
+    let page = format!(
+        "<!DOCTYPE html><html><head><title>Torch dev server</title></head>\
+         <body><h1>🔥 Waiting for the app</h1><p>{}</p><p>This page reloads when it is ready.</p>{}</body></html>",
+        message, LIVE_RELOAD_SCRIPT
+    );
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(full(page))
+        .unwrap()
+}
+
+fn full(body: impl Into<Bytes>) -> ProxyBody {
+    Full::new(body.into()).map_err(|never| match never {}).boxed()
 }
 
 /// Watch files for changes
 fn watch_files(tx: mpsc::Sender<String>) -> Result<(), Box<dyn std::error::Error>> {
-    let watch_paths = vec!["src", "templates", "static", "Cargo.toml"];
+    let watch_paths = vec!["src", "templates", "static", "Cargo.toml", "torch.toml"];
     let mut last_modified = std::collections::HashMap::new();
 
     loop {
@@ -234,3 +530,55 @@ fn watch_files(tx: mpsc::Sender<String>) -> Result<(), Box<dyn std::error::Error
         thread::sleep(Duration::from_millis(500));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_changes() {
+        assert_eq!(classify("src/main.rs"), Change::Rebuild);
+        assert_eq!(classify("Cargo.toml"), Change::Rebuild);
+        assert_eq!(classify("torch.toml"), Change::Rebuild);
+        assert_eq!(classify("templates/posts/index.ember"), Change::Reload);
+        assert_eq!(classify("static/app.css"), Change::Reload);
+    }
+
+    #[test]
+    fn test_inject_script() {
+        let html = inject_script(b"<html><BODY>hi</BODY></html>").unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<html><BODY>hi<script>"));
+        assert!(html.ends_with("</script></BODY></html>"));
+
+        assert_eq!(inject_script(b"<p>hi</p>"), None);
+    }
+
+    #[test]
+    fn test_run_copy_leaves_the_build_free() {
+        let dir = std::env::temp_dir().join(format!("torch-serve-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("torch-serve")).unwrap();
+        std::fs::write(dir.join("torch-serve/server-4000"), "stale").unwrap();
+        let built = dir.join("server");
+        std::fs::write(&built, "binary").unwrap();
+
+        let copy = run_copy(&built, 4100).unwrap();
+        assert_eq!(copy, dir.join("torch-serve/server-4100"));
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), "binary");
+        assert!(!dir.join("torch-serve/server-4000").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_executable_prefers_server_binary() {
+        let messages = r#"{"reason":"compiler-artifact","target":{"name":"helper"},"executable":"/t/helper"}
+{"reason":"compiler-artifact","target":{"name":"mylib"},"executable":null}
+{"reason":"compiler-artifact","target":{"name":"server"},"executable":"/t/server"}
+{"reason":"build-finished","success":true}"#;
+
+        assert_eq!(find_executable(messages), Some(PathBuf::from("/t/server")));
+        assert_eq!(find_executable(&messages.replace("\"server\"", "\"app\"")), Some(PathBuf::from("/t/helper")));
+        assert_eq!(find_executable(""), None);
+    }
+}
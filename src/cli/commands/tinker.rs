@@ -1,7 +1,25 @@
 //! Interactive REPL for Torch (Tinker equivalent)
+//!
+//! Every line is compiled into a small program under `target/tinker` that
+//! includes the app's own modules, so models, repositories, jobs and
+//! templates can be used directly, e.g. `Post::find(1).await`.
+//!
+//! Items such as `use` or `fn` are kept and compiled into every later line.
+//! The program is a fresh process each time, so `let` bindings are saved as
+//! JSON after their line runs and loaded on later lines rather than run again.
+//! A value that isn't `Serialize + Deserialize`, or a pattern other than
+//! `let name`, can't be saved: it is re-run on every line, with a warning when
+//! it's defined. Changes a later line makes to a binding don't carry over.
 
 use colored::*;
+use std::collections::HashMap;
+use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory, relative to the project root, that holds the generated program
+const SESSION_DIR: &str = "target/tinker";
 
 /// Start interactive REPL
 pub fn start_repl() -> Result<(), Box<dyn std::error::Error>> {
@@ -9,82 +27,63 @@ pub fn start_repl() -> Result<(), Box<dyn std::error::Error>> {
     println!("{} Type 'help' for available commands, 'exit' to quit", "💡".blue());
     println!();
 
-    // Initialize Torch application context
-    println!("{} Initializing Torch application...", "⚙️".blue());
-    let context = initialize_application_context()?;
+    println!("{} Compiling the application...", "⚙️".blue());
+    let project = Project::discover(Path::new("."))?;
+    let mut session = Session::new(project);
+    session.prepare()?;
+    if let Err(errors) = session.run(None) {
+        println!("{}", errors);
+        return Err("The application does not compile".into());
+    }
     println!("{} Application loaded successfully", "✅".green());
+    if session.project.has_database() {
+        println!("{} Database connection opened from DATABASE_URL when set", "🗄️".blue());
+    }
     println!();
 
     let mut line_number = 1;
     let mut command_history = Vec::new();
 
     loop {
-        // Print prompt with context info
-        let prompt = format!("torch[{}]>", line_number);
-        print!("{} ", prompt.cyan().bold());
-        io::stdout().flush()?;
-
-        // Read input
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+        let Some(input) = read_input(line_number)? else {
+            break;
+        };
         let input = input.trim();
 
-        // Handle empty input
         if input.is_empty() {
             continue;
         }
 
-        // Add to history
         command_history.push(input.to_string());
 
-        // Handle special commands
         match input {
             "exit" | "quit" => {
                 println!("{} Goodbye!", "👋".yellow());
                 break;
             }
             "help" => {
-                show_help();
+                show_help(&session.project);
             }
             "clear" => {
                 print!("\x1B[2J\x1B[1;1H"); // Clear screen
                 continue;
             }
-            "app" => {
-                show_app_info(&context);
-            }
-            "routes" => {
-                show_routes(&context);
-            }
-            "config" => {
-                show_config(&context);
-            }
             "models" => {
-                show_models(&context);
-            }
-            "db" => {
-                show_database_info(&context);
-            }
-            "cache" => {
-                show_cache_info(&context);
+                show_models(&session.project);
             }
             "history" => {
                 show_command_history(&command_history);
             }
             "vars" => {
-                show_variables(&context);
+                show_session(&session);
+            }
+            "reset" => {
+                session.reset();
+                println!("{} Bindings and items cleared", "✅".green());
             }
             _ => {
-                // Evaluate expression or command
-                match evaluate_expression(input, &context) {
-                    Ok(result) => {
-                        if !result.is_empty() {
-                            println!("{}", result);
-                        }
-                    }
-                    Err(e) => {
-                        println!("{} {}", "❌".red(), e);
-                    }
+                if let Err(errors) = session.eval(input) {
+                    println!("{} {}", "❌".red(), errors);
                 }
             }
         }
@@ -95,177 +94,663 @@ pub fn start_repl() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Application context for REPL
+/// Read one line, or several while brackets are left open; `None` on EOF
+fn read_input(line_number: usize) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut input = String::new();
+
+    loop {
+        let prompt = if input.is_empty() {
+            format!("torch[{}]>", line_number)
+        } else {
+            "...>".to_string()
+        };
+        print!("{} ", prompt.cyan().bold());
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(if input.is_empty() { None } else { Some(input) });
+        }
+        input.push_str(&line);
+
+        if is_complete(&input) {
+            return Ok(Some(input));
+        }
+    }
+}
+
+/// The Torch project tinker runs against
 #[derive(Debug)]
-struct ApplicationContext {
-    app_name: String,
-    version: String,
-    environment: String,
-    debug: bool,
-    database_connected: bool,
-    cache_driver: String,
-    routes_count: usize,
+struct Project {
+    root: PathBuf,
+    edition: String,
+    dependencies: toml::Table,
+    /// Enabled `torch-web` features, with `default` and `full` expanded
+    torch_features: Vec<String>,
+    /// Top-level modules of the crate and the files they live in
+    modules: Vec<(String, PathBuf)>,
+    /// Public submodules of `models`
     models: Vec<String>,
-    variables: std::collections::HashMap<String, String>,
+    env: HashMap<String, String>,
 }
 
-/// Initialize application context
-fn initialize_application_context() -> Result<ApplicationContext, Box<dyn std::error::Error>> {
-    // Simulate loading application configuration
-    std::thread::sleep(std::time::Duration::from_millis(300));
-
-    let mut variables = std::collections::HashMap::new();
-    variables.insert("app_start_time".to_string(), chrono::Utc::now().to_rfc3339());
-
-    Ok(ApplicationContext {
-        app_name: "Torch App".to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
-        environment: "development".to_string(),
-        debug: true,
-        database_connected: true, // Simulate connection
-        cache_driver: "memory".to_string(),
-        routes_count: 5,
-        models: vec!["User".to_string(), "Post".to_string()],
-        variables,
-    })
+impl Project {
+    fn discover(root: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let manifest_path = root.join("Cargo.toml");
+        if !manifest_path.exists() {
+            return Err("No Cargo.toml found. Make sure you're in a Rust project directory.".into());
+        }
+        let root = root.canonicalize()?;
+        let manifest: toml::Table = fs::read_to_string(&manifest_path)?.parse()?;
+
+        let edition = manifest
+            .get("package")
+            .and_then(|package| package.get("edition"))
+            .and_then(|edition| edition.as_str())
+            .unwrap_or("2021")
+            .to_string();
+
+        let mut dependencies = manifest
+            .get("dependencies")
+            .and_then(|dependencies| dependencies.as_table())
+            .cloned()
+            .unwrap_or_default();
+        absolutize_paths(&mut dependencies, &root);
+
+        let torch = dependencies
+            .get("torch-web")
+            .ok_or("torch-web is not a dependency of this project")?;
+        let torch_features = torch_features(torch);
+
+        // Modules are mirrored from the library when there is one, since a
+        // binary that uses it won't declare them itself
+        let entry = ["src/lib.rs", "src/main.rs"]
+            .iter()
+            .map(|entry| root.join(entry))
+            .find(|entry| entry.exists())
+            .ok_or("No src/lib.rs or src/main.rs found")?;
+        let modules = declared_modules(&fs::read_to_string(&entry)?, false)
+            .into_iter()
+            .filter_map(|module| {
+                let file = module_file(&root.join("src"), &module)?;
+                Some((module, file))
+            })
+            .collect::<Vec<_>>();
+
+        let models = modules
+            .iter()
+            .find(|(module, _)| module == "models")
+            .and_then(|(_, file)| fs::read_to_string(file).ok())
+            .map(|source| declared_modules(&source, true))
+            .unwrap_or_default();
+
+        let env = fs::read_to_string(root.join(".env"))
+            .map(|contents| parse_env(&contents))
+            .unwrap_or_default();
+
+        Ok(Self {
+            root,
+            edition,
+            dependencies,
+            torch_features,
+            modules,
+            models,
+            env,
+        })
+    }
+
+    fn has_feature(&self, feature: &str) -> bool {
+        self.torch_features.iter().any(|enabled| enabled == feature)
+    }
+
+    fn has_database(&self) -> bool {
+        self.has_feature("database")
+    }
 }
 
-/// Show help information
-fn show_help() {
-    println!("{}", "Available Commands:".bold());
-    println!("  {}     - Show this help message", "help".cyan());
-    println!("  {}    - Clear the screen", "clear".cyan());
-    println!("  {}      - Show application information", "app".cyan());
-    println!("  {}   - Show registered routes", "routes".cyan());
-    println!("  {}   - Show configuration", "config".cyan());
-    println!("  {}   - Show available models", "models".cyan());
-    println!("  {}       - Show database information", "db".cyan());
-    println!("  {}    - Show cache information", "cache".cyan());
-    println!("  {}  - Show command history", "history".cyan());
-    println!("  {}     - Show variables", "vars".cyan());
-    println!("  {}     - Exit the REPL", "exit".cyan());
-    println!();
-    println!("{}", "Rust Expressions:".bold());
-    println!("  You can evaluate Rust expressions in the Torch context");
-    println!("  Example: {}", "2 + 2".yellow());
-    println!("  Example: {}", r#"println!("Hello, Torch!")"#.yellow());
-    println!("  Example: {}", "chrono::Utc::now()".yellow());
-    println!();
-    println!("{}", "Variables:".bold());
-    println!("  Access application context with built-in variables");
-    println!("  Example: {}", "$app_name".yellow());
-    println!("  Example: {}", "$environment".yellow());
-    println!();
+/// Make `path = "..."` dependencies absolute so they resolve from the session directory
+fn absolutize_paths(dependencies: &mut toml::Table, root: &Path) {
+    for (_, dependency) in dependencies.iter_mut() {
+        if let Some(path) = dependency.get_mut("path") {
+            if let Some(relative) = path.as_str() {
+                let absolute = root.join(relative);
+                *path = toml::Value::String(absolute.to_string_lossy().into_owned());
+            }
+        }
+    }
 }
 
-/// Show application information
-fn show_app_info(context: &ApplicationContext) {
-    println!("{}", "Application Information:".bold());
-    println!("  Name: {}", context.app_name.cyan());
-    println!("  Version: {}", context.version.cyan());
-    println!("  Environment: {}", context.environment.cyan());
-    println!("  Debug: {}", if context.debug { "true".green() } else { "false".red() });
-    println!("  Database: {}", if context.database_connected { "Connected".green() } else { "Disconnected".red() });
-    println!("  Cache Driver: {}", context.cache_driver.cyan());
-    println!("  Routes: {}", context.routes_count.to_string().cyan());
-    println!("  Models: {}", context.models.len().to_string().cyan());
-    println!();
+/// Features enabled on the torch-web dependency
+fn torch_features(dependency: &toml::Value) -> Vec<String> {
+    let mut features: Vec<String> = dependency
+        .get("features")
+        .and_then(|features| features.as_array())
+        .map(|features| features.iter().filter_map(|f| f.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+
+    let default_features = dependency
+        .get("default-features")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(true);
+    if default_features {
+        features.push("json".to_string());
+    }
+    if features.iter().any(|feature| feature == "full") {
+        features.extend(["database", "templates", "json"].map(str::to_string));
+    }
+
+    features
 }
 
-/// Show registered routes
-fn show_routes(context: &ApplicationContext) {
-    println!("{}", "Registered Routes:".bold());
-    println!("  Total routes: {}", context.routes_count.to_string().cyan());
-    println!();
+/// Modules declared as `mod name;` in a source file
+fn declared_modules(source: &str, public_only: bool) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (public, rest) = match line.strip_prefix("pub(crate) ").or_else(|| line.strip_prefix("pub ")) {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let name = rest.strip_prefix("mod ")?.strip_suffix(';')?.trim();
+            let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+            (valid && (public || !public_only)).then(|| name.to_string())
+        })
+        .collect()
+}
 
-    let routes = vec![
-        ("GET", "/", "home", "App::home"),
-        ("GET", "/hello/:name", "hello", "App::hello"),
-        ("GET", "/api/health", "health", "App::health"),
-        ("GET", "/api/users", "users.index", "UserController::index"),
-        ("POST", "/api/users", "users.store", "UserController::create"),
-        ("GET", "/api/users/:id", "users.show", "UserController::show"),
-    ];
-
-    println!("{:<8} {:<20} {:<15} {}", "Method".bold(), "URI".bold(), "Name".bold(), "Action".bold());
-    println!("{}", "-".repeat(65));
-
-    for (method, path, name, action) in routes {
-        let method_colored = match method {
-            "GET" => method.blue(),
-            "POST" => method.green(),
-            "PUT" => method.yellow(),
-            "DELETE" => method.red(),
-            _ => method.white(),
+fn module_file(src: &Path, module: &str) -> Option<PathBuf> {
+    [src.join(format!("{}.rs", module)), src.join(module).join("mod.rs")]
+        .into_iter()
+        .find(|file| file.exists())
+}
+
+/// Parse `KEY=value` lines from a `.env` file
+pub(crate) fn parse_env(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim().trim_matches('"').trim_matches('\'');
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// What a line of input is, which decides where it goes in the program
+#[derive(Debug, Clone, PartialEq)]
+enum Input {
+    /// `use`, `fn`, `struct` and other items, placed at the crate root
+    Item(String),
+    /// A `let` statement, kept for every following line
+    Binding(Binding),
+    /// Code ending in `;`, run once
+    Statement(String),
+    /// Anything else, run once and printed with `{:#?}`
+    Expression(String),
+}
+
+const ITEM_PREFIXES: &[&str] = &[
+    "use ", "fn ", "async fn ", "struct ", "enum ", "impl ", "impl<", "trait ", "mod ", "const ",
+    "static ", "type ", "pub ", "#[",
+];
+
+fn classify(input: &str) -> Input {
+    let input = input.trim().to_string();
+
+    if ITEM_PREFIXES.iter().any(|prefix| input.starts_with(prefix)) {
+        Input::Item(input)
+    } else if input.starts_with("let ") {
+        if input.ends_with(';') {
+            Input::Binding(Binding::Replayed(input))
+        } else {
+            Input::Binding(Binding::Replayed(format!("{};", input)))
+        }
+    } else if input.ends_with(';') {
+        Input::Statement(input)
+    } else {
+        Input::Expression(input)
+    }
+}
+
+/// A `let` statement kept in the session
+#[derive(Debug, Clone, PartialEq)]
+enum Binding {
+    /// Run once, then loaded from the value it saved
+    Saved(SavedBinding),
+    /// Run again on every line, because its value can't be saved
+    Replayed(String),
+}
+
+impl Binding {
+    fn code(&self) -> &str {
+        match self {
+            Binding::Saved(binding) => &binding.code,
+            Binding::Replayed(code) => code,
+        }
+    }
+}
+
+/// `let [mut] name[: Type] = value;`, the only shape whose value can be saved
+#[derive(Debug, Clone, PartialEq)]
+struct SavedBinding {
+    code: String,
+    mutable: bool,
+    name: String,
+    ty: Option<String>,
+    value: String,
+}
+
+impl SavedBinding {
+    fn parse(code: &str) -> Option<Self> {
+        let rest = code.strip_prefix("let ")?.trim().strip_suffix(';')?;
+
+        // The first `=` outside a type's angle brackets ends the pattern
+        let mut depth = 0;
+        let mut previous = ' ';
+        let split = rest.char_indices().find_map(|(index, c)| {
+            match c {
+                '<' => depth += 1,
+                '>' if previous != '-' => depth -= 1,
+                '=' if depth == 0 => return Some(index),
+                _ => {}
+            }
+            previous = c;
+            None
+        })?;
+        let (target, value) = (rest[..split].trim(), rest[split + 1..].trim());
+
+        let (target, ty) = match target.split_once(':') {
+            Some((target, ty)) => (target.trim(), Some(ty.trim().to_string())),
+            None => (target, None),
+        };
+        let (mutable, name) = match target.strip_prefix("mut ") {
+            Some(name) => (true, name.trim()),
+            None => (false, target),
         };
+        let is_ident = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !is_ident || value.is_empty() || value.starts_with('=') {
+            return None;
+        }
 
-        println!("{:<8} {:<20} {:<15} {}", method_colored, path.cyan(), name.magenta(), action);
+        Some(Self {
+            code: code.to_string(),
+            mutable,
+            name: name.to_string(),
+            ty,
+            value: value.to_string(),
+        })
+    }
+
+    /// `mut name: Type`, as written
+    fn target(&self) -> String {
+        let mut target = if self.mutable { format!("mut {}", self.name) } else { self.name.clone() };
+        if let Some(ty) = &self.ty {
+            target.push_str(&format!(": {}", ty));
+        }
+        target
+    }
+
+    /// Load the saved value on a later line. The closure is never called; it
+    /// only tells the compiler the value's type.
+    fn load(&self, path: &Path) -> String {
+        format!(
+            "let {} = __tinker_load({:?}, || async {{ Ok::<{}, __TinkerError>({{ {} }}) }})?;",
+            self.target(),
+            path.to_string_lossy(),
+            self.ty.as_deref().unwrap_or("_"),
+            self.value,
+        )
+    }
+
+    /// Run the binding and save its value
+    fn save(&self, path: &Path) -> String {
+        format!(
+            "let {} = {};
+    __tinker_save({:?}, &{})?;",
+            self.target(),
+            self.value,
+            path.to_string_lossy(),
+            self.name,
+        )
     }
-    println!();
 }
 
-/// Show configuration
-fn show_config(context: &ApplicationContext) {
-    println!("{}", "Configuration:".bold());
-    println!("  app.name: {}", context.app_name.cyan());
-    println!("  app.env: {}", context.environment.cyan());
-    println!("  app.debug: {}", if context.debug { "true".green() } else { "false".red() });
-    println!("  app.version: {}", context.version.cyan());
-    println!("  app.url: {}", "http://localhost:3000".cyan());
-    println!("  database.connected: {}", if context.database_connected { "true".green() } else { "false".red() });
-    println!("  cache.driver: {}", context.cache_driver.cyan());
-    println!("  server.host: {}", "127.0.0.1".cyan());
-    println!("  server.port: {}", "3000".cyan());
-    println!();
+/// Whether compiling failed because a value to save isn't serializable
+fn is_unsaveable(errors: &str) -> bool {
+    errors.contains("error[E0277]") && (errors.contains("Serialize") || errors.contains("Deserialize"))
 }
 
-/// Show available models
-fn show_models(context: &ApplicationContext) {
-    println!("{}", "Available Models:".bold());
+/// Whether every bracket opened in `input` has been closed
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut chars = input.chars();
+    let mut in_string = false;
 
-    if context.models.is_empty() {
-        println!("  No models found");
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth <= 0 && !in_string
+}
+
+/// Keep only the error diagnostics from cargo's output
+///
+/// When nothing looks like a compile error the program built and failed at
+/// runtime, so everything it wrote is returned.
+fn compiler_errors(stderr: &str) -> String {
+    let errors: Vec<&str> = stderr
+        .split("\n\n")
+        .filter(|block| block.trim_start().starts_with("error"))
+        .filter(|block| !block.trim_start().starts_with("error: could not compile"))
+        .collect();
+
+    if errors.is_empty() {
+        stderr.trim_end().to_string()
     } else {
-        for model in &context.models {
-            println!("  • {}", model.cyan());
+        errors.join("\n\n")
+    }
+}
+
+/// Accepted items and bindings, plus the generated program they compile into
+struct Session {
+    project: Project,
+    items: Vec<String>,
+    bindings: Vec<Binding>,
+}
+
+impl Session {
+    fn new(project: Project) -> Self {
+        Self {
+            project,
+            items: Vec::new(),
+            bindings: Vec::new(),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.project.root.join(SESSION_DIR)
+    }
+
+    /// Where the binding at `index` saves its value
+    fn value_path(&self, index: usize) -> PathBuf {
+        self.dir().join("values").join(format!("{}.json", index))
+    }
+
+    /// Binding values can be saved when the app depends on serde and serde_json
+    fn can_save(&self) -> bool {
+        ["serde", "serde_json"].iter().all(|dependency| self.project.dependencies.contains_key(*dependency))
+    }
+
+    fn reset(&mut self) {
+        self.items.clear();
+        self.bindings.clear();
+        let _ = fs::remove_dir_all(self.dir().join("values"));
+    }
+
+    /// Write the session's manifest, reusing the app's lockfile so the same
+    /// dependency versions (and build artifacts) are used
+    fn prepare(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(self.dir().join("src"))?;
+        let _ = fs::remove_dir_all(self.dir().join("values"));
+        fs::create_dir_all(self.dir().join("values"))?;
+
+        let mut package = toml::Table::new();
+        package.insert("name".into(), "torch-tinker".into());
+        package.insert("version".into(), "0.0.0".into());
+        package.insert("edition".into(), self.project.edition.clone().into());
+        package.insert("publish".into(), false.into());
+
+        let mut manifest = toml::Table::new();
+        manifest.insert("package".into(), package.into());
+        // An empty workspace keeps cargo from treating the app as our workspace root
+        manifest.insert("workspace".into(), toml::Table::new().into());
+        manifest.insert("dependencies".into(), self.project.dependencies.clone().into());
+        fs::write(self.dir().join("Cargo.toml"), toml::to_string(&manifest)?)?;
+
+        let lockfile = self.project.root.join("Cargo.lock");
+        if lockfile.exists() {
+            fs::copy(lockfile, self.dir().join("Cargo.lock"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate a line, keeping it in the session if it's a binding or item
+    fn eval(&mut self, input: &str) -> Result<(), String> {
+        let input = classify(input);
+        if let Input::Binding(binding) = input {
+            return self.bind(binding.code());
+        }
+        self.run(Some(&input))?;
+
+        if let Input::Item(item) = input {
+            self.items.push(item);
+        }
+        Ok(())
+    }
+
+    /// Run a `let` statement once and save its value, falling back to
+    /// re-running it on every line when the value can't be saved
+    fn bind(&mut self, code: &str) -> Result<(), String> {
+        let saved = SavedBinding::parse(code).filter(|_| self.can_save());
+        let reason = match saved {
+            Some(binding) => {
+                let name = binding.name.clone();
+                let binding = Binding::Saved(binding);
+                match self.run(Some(&Input::Binding(binding.clone()))) {
+                    Ok(()) => {
+                        self.bindings.push(binding);
+                        return Ok(());
+                    }
+                    Err(errors) if is_unsaveable(&errors) => {
+                        format!("`{}` isn't Serialize + Deserialize, so it can't be saved", name)
+                    }
+                    Err(errors) => return Err(errors),
+                }
+            }
+            None if self.can_save() => "Only `let name = ...` bindings can be saved".to_string(),
+            None => "Add serde and serde_json to the app's dependencies to save bindings".to_string(),
+        };
+
+        let binding = Binding::Replayed(code.to_string());
+        self.run(Some(&Input::Binding(binding.clone())))?;
+        println!(
+            "{} {}; it runs again on every line, so keep side effects out of it",
+            "⚠️".yellow(),
+            reason
+        );
+        self.bindings.push(binding);
+        Ok(())
+    }
+
+    /// Compile and run the program, streaming its stdout
+    fn run(&self, input: Option<&Input>) -> Result<(), String> {
+        fs::write(self.dir().join("src/main.rs"), self.program(input)).map_err(|e| e.to_string())?;
+
+        let output = Command::new("cargo")
+            .args(["run", "--quiet", "--manifest-path"])
+            .arg(self.dir().join("Cargo.toml"))
+            .env("CARGO_TARGET_DIR", self.project.root.join("target"))
+            .envs(self.project.env.iter().filter(|(key, _)| std::env::var_os(key).is_none()))
+            .current_dir(&self.project.root)
+            .output()
+            .map_err(|e| format!("Failed to run cargo: {}", e))?;
+
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(compiler_errors(&String::from_utf8_lossy(&output.stderr)))
         }
     }
 
+    /// Render the program for the current session plus `input`
+    fn program(&self, input: Option<&Input>) -> String {
+        let project = &self.project;
+        let mut program = String::new();
+
+        program.push_str("// Generated by `torch tinker`. Do not edit.\n");
+        program.push_str("#![allow(warnings)]\n\n");
+
+        for (module, file) in &project.modules {
+            program.push_str(&format!("#[path = {:?}]\nmod {};\n", file.to_string_lossy(), module));
+        }
+        if !project.models.is_empty() {
+            program.push_str("\nuse crate::models::*;\n");
+            for model in &project.models {
+                program.push_str(&format!("use crate::models::{}::*;\n", model));
+            }
+        }
+        program.push_str("\nuse torch_web::{App, Request, Response};\n");
+        if project.has_feature("json") {
+            program.push_str("use torch_web::json;\n");
+        }
+
+        program.push_str(&self.helpers());
+
+        let mut items = self.items.iter().map(String::as_str).collect::<Vec<_>>();
+        if let Some(Input::Item(item)) = input {
+            items.push(item);
+        }
+        for item in items {
+            program.push('\n');
+            program.push_str(item);
+            program.push('\n');
+        }
+
+        program.push_str("\n#[tokio::main]\n");
+        program.push_str("async fn main() -> Result<(), __TinkerError> {\n");
+        if project.has_database() {
+            program.push_str("    if let Ok(database_url) = std::env::var(\"DATABASE_URL\") {\n");
+            program.push_str("        torch_web::orm::initialize(torch_web::orm::OrmConfig {\n");
+            program.push_str("            database_url,\n");
+            program.push_str("            ..Default::default()\n");
+            program.push_str("        }).await?;\n");
+            program.push_str("    }\n");
+        }
+        for (index, binding) in self.bindings.iter().enumerate() {
+            let line = match binding {
+                Binding::Saved(binding) => binding.load(&self.value_path(index)),
+                Binding::Replayed(code) => code.clone(),
+            };
+            program.push_str(&format!("    {}\n", line));
+        }
+        match input {
+            Some(Input::Binding(Binding::Saved(binding))) => {
+                program.push_str(&format!("    {}\n", binding.save(&self.value_path(self.bindings.len()))));
+            }
+            Some(Input::Binding(Binding::Replayed(code))) | Some(Input::Statement(code)) => {
+                program.push_str(&format!("    {}\n", code));
+            }
+            Some(Input::Expression(code)) => {
+                program.push_str(&format!("    let __tinker = {{ {} }};\n", code));
+                program.push_str("    println!(\"{:#?}\", __tinker);\n");
+            }
+            Some(Input::Item(_)) | None => {}
+        }
+        program.push_str("    Ok(())\n");
+        program.push_str("}\n");
+
+        program
+    }
+
+    /// Helpers for saving bindings, dispatching jobs and rendering templates
+    fn helpers(&self) -> String {
+        let mut helpers = String::new();
+
+        helpers.push_str("\ntype __TinkerError = Box<dyn std::error::Error + Send + Sync>;\n");
+        if self.can_save() {
+            helpers.push_str("\n/// Load a binding saved by an earlier line; `_type` is never called\n");
+            helpers.push_str("fn __tinker_load<T, F>(path: &str, _type: impl FnOnce() -> F) -> Result<T, __TinkerError>\n");
+            helpers.push_str("where\n");
+            helpers.push_str("    F: std::future::Future<Output = Result<T, __TinkerError>>,\n");
+            helpers.push_str("    T: ::serde::de::DeserializeOwned,\n");
+            helpers.push_str("{\n");
+            helpers.push_str("    Ok(::serde_json::from_str(&std::fs::read_to_string(path)?)?)\n");
+            helpers.push_str("}\n");
+            helpers.push_str("\nfn __tinker_save<T: ::serde::Serialize>(path: &str, value: &T) -> Result<(), __TinkerError> {\n");
+            helpers.push_str("    Ok(std::fs::write(path, ::serde_json::to_string(value)?)?)\n");
+            helpers.push_str("}\n");
+        }
+
+        helpers.push_str("\n/// Push a job through the configured queue driver\n");
+        helpers.push_str("async fn dispatch<J: torch_web::queue::Job>(job: J) -> Result<(), torch_web::queue::QueueError> {\n");
+        helpers.push_str("    torch_web::queue::Queue::dispatch(job).await\n");
+        helpers.push_str("}\n");
+
+        if self.project.has_feature("templates") && self.project.has_feature("json") {
+            helpers.push_str("\n/// Render an Ember template with `json!` data\n");
+            helpers.push_str("async fn render(template: &str, data: torch_web::JsonValue) -> Result<String, torch_web::ember::EmberError> {\n");
+            helpers.push_str("    let mut context = torch_web::ember::EmberData::new();\n");
+            helpers.push_str("    if let torch_web::JsonValue::Object(fields) = data {\n");
+            helpers.push_str("        for (key, value) in fields {\n");
+            helpers.push_str("            context.insert(key, value);\n");
+            helpers.push_str("        }\n");
+            helpers.push_str("    }\n");
+            helpers.push_str("    torch_web::ember::EmberEngine::new().render(template, context).await\n");
+            helpers.push_str("}\n");
+        }
+
+        helpers
+    }
+}
+
+/// Show help information
+fn show_help(project: &Project) {
+    println!("{}", "Available Commands:".bold());
+    println!("  {}     - Show this help message", "help".cyan());
+    println!("  {}    - Clear the screen", "clear".cyan());
+    println!("  {}   - Show the models in scope", "models".cyan());
+    println!("  {}  - Show command history", "history".cyan());
+    println!("  {}     - Show the bindings and items kept so far", "vars".cyan());
+    println!("  {}    - Forget all bindings and items", "reset".cyan());
+    println!("  {}     - Exit the REPL", "exit".cyan());
     println!();
-    println!("  Generate new model: {}", "torch make model <name>".yellow());
+    println!("{}", "Rust Code:".bold());
+    println!("  Anything else is compiled and run inside an async main with the app's modules in scope.");
+    println!("  Expressions are printed with {{:#?}}; `.await` works everywhere.");
+    println!("  Example: {}", "Post::find(1).await".yellow());
+    println!("  Example: {}", "let repo = PostRepository::new()".yellow());
+    println!("  Example: {}", "use std::collections::HashMap;".yellow());
+    println!();
+    println!("{}", "Helpers:".bold());
+    println!("  {} - push a job through the queue", "dispatch(job).await".yellow());
+    if project.has_feature("templates") && project.has_feature("json") {
+        println!("  {} - render a template", r#"render("posts/index", json!({ "posts": [] })).await"#.yellow());
+    }
+    println!();
+    println!("  Each line runs in a fresh process. `let` bindings run once and their values");
+    println!("  are saved for later lines; ones that can't be saved run again on every line.");
     println!();
 }
 
-/// Show database information
-fn show_database_info(context: &ApplicationContext) {
-    println!("{}", "Database Information:".bold());
-    println!("  Status: {}", if context.database_connected { "Connected".green() } else { "Disconnected".red() });
-    println!("  Driver: {}", "PostgreSQL".cyan());
-    println!("  Host: {}", "localhost:5432".cyan());
-    println!("  Database: {}", "torch_app".cyan());
-    println!();
+/// Show the models brought into scope
+fn show_models(project: &Project) {
+    println!("{}", "Available Models:".bold());
 
-    if context.database_connected {
-        println!("{}", "Available Tables:".bold());
-        let tables = vec!["users", "migrations", "sessions"];
-        for table in tables {
-            println!("  • {}", table.cyan());
+    if project.models.is_empty() {
+        println!("  No models found");
+    } else {
+        for model in &project.models {
+            println!("  • {}", format!("models::{}", model).cyan());
         }
-        println!();
     }
-}
 
-/// Show cache information
-fn show_cache_info(context: &ApplicationContext) {
-    println!("{}", "Cache Information:".bold());
-    println!("  Driver: {}", context.cache_driver.cyan());
-    println!("  Status: {}", "Active".green());
     println!();
-
-    println!("{}", "Cache Statistics:".bold());
-    println!("  • Keys: {}", "0".cyan());
-    println!("  • Memory Usage: {}", "0 MB".cyan());
-    println!("  • Hit Rate: {}", "0%".cyan());
+    println!("  Generate new model: {}", "torch make model <name>".yellow());
     println!();
 }
 
@@ -276,7 +761,7 @@ fn show_command_history(history: &[String]) {
     if history.is_empty() {
         println!("  No commands in history");
     } else {
-        let start = if history.len() > 10 { history.len() - 10 } else { 0 };
+        let start = history.len().saturating_sub(10);
         for (i, command) in history[start..].iter().enumerate() {
             println!("  {}: {}", (start + i + 1).to_string().yellow(), command.cyan());
         }
@@ -285,167 +770,125 @@ fn show_command_history(history: &[String]) {
     println!();
 }
 
-/// Show variables
-fn show_variables(context: &ApplicationContext) {
-    println!("{}", "Available Variables:".bold());
-
-    // Built-in variables
-    println!("  {}: {}", "$app_name".yellow(), context.app_name.cyan());
-    println!("  {}: {}", "$version".yellow(), context.version.cyan());
-    println!("  {}: {}", "$environment".yellow(), context.environment.cyan());
-    println!("  {}: {}", "$debug".yellow(), context.debug.to_string().cyan());
-
-    // Custom variables
-    if !context.variables.is_empty() {
-        println!();
-        println!("{}", "Custom Variables:".bold());
-        for (key, value) in &context.variables {
-            println!("  {}: {}", format!("${}", key).yellow(), value.cyan());
+/// Show the bindings and items the session keeps
+fn show_session(session: &Session) {
+    println!("{}", "Session:".bold());
+
+    if session.items.is_empty() && session.bindings.is_empty() {
+        println!("  Nothing defined yet");
+    }
+    for item in &session.items {
+        println!("  {}", item.cyan());
+    }
+    for binding in &session.bindings {
+        match binding {
+            Binding::Saved(_) => println!("  {}", binding.code().cyan()),
+            Binding::Replayed(code) => println!("  {} {}", code.cyan(), "(runs on every line)".dimmed()),
         }
     }
 
     println!();
 }
 
-/// Evaluate a Rust expression
-fn evaluate_expression(expr: &str, context: &ApplicationContext) -> Result<String, Box<dyn std::error::Error>> {
-    // Handle variable substitution
-    let expr = substitute_variables(expr, context);
-
-    // Handle special expressions
-    match expr.as_str() {
-        expr if expr.starts_with("println!") => {
-            // Extract the string from println!
-            if let Some(start) = expr.find('"') {
-                if let Some(end) = expr.rfind('"') {
-                    let message = &expr[start + 1..end];
-                    return Ok(message.to_string());
-                }
-            }
-            return Err("Invalid println! syntax".into());
-        }
-        expr if expr.contains('+') || expr.contains('-') || expr.contains('*') || expr.contains('/') => {
-            // Try to evaluate simple math expressions
-            match evaluate_math(&expr) {
-                Ok(result) => {
-                    return Ok(format!("{} = {}", expr.yellow(), result.to_string().green()));
-                }
-                Err(_) => {
-                    return Err("Invalid mathematical expression".into());
-                }
-            }
-        }
-        "env!('CARGO_PKG_VERSION')" => {
-            return Ok(env!("CARGO_PKG_VERSION").to_string());
-        }
-        "chrono::Utc::now()" => {
-            return Ok(chrono::Utc::now().to_rfc3339());
-        }
-        expr if expr.starts_with("User::") => {
-            return evaluate_model_expression(&expr);
-        }
-        expr if expr.starts_with("$") => {
-            return evaluate_variable(&expr, context);
-        }
-        _ => {
-            return Err("Expression not supported. Try 'help' for available commands.".into());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> Project {
+        Project {
+            root: PathBuf::from("/app"),
+            edition: "2021".to_string(),
+            dependencies: toml::Table::new(),
+            torch_features: vec!["json".to_string(), "database".to_string()],
+            modules: vec![("models".to_string(), PathBuf::from("/app/src/models/mod.rs"))],
+            models: vec!["post".to_string()],
+            env: HashMap::new(),
         }
     }
-}
 
-/// Substitute variables in expression
-fn substitute_variables(expr: &str, context: &ApplicationContext) -> String {
-    let mut result = expr.to_string();
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("use std::fmt;"), Input::Item("use std::fmt;".to_string()));
+        assert_eq!(classify("let x = 1"), Input::Binding(Binding::Replayed("let x = 1;".to_string())));
+        assert_eq!(classify("println!(\"hi\");"), Input::Statement("println!(\"hi\");".to_string()));
+        assert_eq!(classify("Post::find(1).await"), Input::Expression("Post::find(1).await".to_string()));
+    }
 
-    // Replace built-in variables
-    result = result.replace("$app_name", &format!("\"{}\"", context.app_name));
-    result = result.replace("$version", &format!("\"{}\"", context.version));
-    result = result.replace("$environment", &format!("\"{}\"", context.environment));
-    result = result.replace("$debug", &context.debug.to_string());
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("1 + 1"));
+        assert!(!is_complete("fn double(x: i32) -> i32 {"));
+        assert!(is_complete("fn double(x: i32) -> i32 {\n    x * 2\n}"));
+        assert!(is_complete("\"{ not a brace\""));
+    }
 
-    // Replace custom variables
-    for (key, value) in &context.variables {
-        result = result.replace(&format!("${}", key), &format!("\"{}\"", value));
+    #[test]
+    fn test_declared_modules() {
+        let source = "mod models;\npub mod controllers;\nmod tests {\n}\nuse foo;\n";
+        assert_eq!(declared_modules(source, false), vec!["models", "controllers"]);
+        assert_eq!(declared_modules(source, true), vec!["controllers"]);
     }
 
-    result
-}
+    #[test]
+    fn test_torch_features() {
+        let dependency: toml::Value = toml::from_str("features = [\"full\"]\ndefault-features = false").unwrap();
+        let features = torch_features(&dependency);
+        assert!(features.contains(&"database".to_string()));
 
-/// Evaluate model expression
-fn evaluate_model_expression(expr: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // TODO: Implement actual model method calls
-    // For now, just simulate some common operations
+        let dependency: toml::Value = toml::from_str("version = \"0.2\"").unwrap();
+        assert_eq!(torch_features(&dependency), vec!["json"]);
+    }
 
-    match expr {
-        "User::all()" => {
-            Ok("Vec<User> with 0 items".to_string())
-        }
-        "User::count()" => {
-            Ok("0".to_string())
-        }
-        expr if expr.starts_with("User::find(") => {
-            Ok("None".to_string())
-        }
-        _ => {
-            Err(format!("Model method '{}' not implemented", expr).into())
-        }
+    #[test]
+    fn test_program_replays_bindings() {
+        let mut session = Session::new(project());
+        session.bindings.push(Binding::Replayed("let repo = PostRepository::new();".to_string()));
+
+        let program = session.program(Some(&classify("repo.all()")));
+        assert!(program.contains("#[path = \"/app/src/models/mod.rs\"]\nmod models;"));
+        assert!(program.contains("use crate::models::post::*;"));
+        assert!(program.contains("torch_web::orm::initialize"));
+        assert!(program.contains("    let repo = PostRepository::new();\n    let __tinker = { repo.all() };"));
     }
-}
 
-/// Evaluate variable
-fn evaluate_variable(expr: &str, context: &ApplicationContext) -> Result<String, Box<dyn std::error::Error>> {
-    let var_name = &expr[1..]; // Remove $
-
-    match var_name {
-        "app_name" => Ok(context.app_name.clone()),
-        "version" => Ok(context.version.clone()),
-        "environment" => Ok(context.environment.clone()),
-        "debug" => Ok(context.debug.to_string()),
-        _ => {
-            if let Some(value) = context.variables.get(var_name) {
-                Ok(value.clone())
-            } else {
-                Err(format!("Variable '{}' not found", expr).into())
-            }
-        }
+    #[test]
+    fn test_program_loads_saved_bindings() {
+        let mut session = Session::new(project());
+        let post = SavedBinding::parse("let mut post = Post::find(1).await?;").unwrap();
+        assert_eq!((post.mutable, post.name.as_str(), post.value.as_str()), (true, "post", "Post::find(1).await?"));
+
+        let program = session.program(Some(&Input::Binding(Binding::Saved(post.clone()))));
+        assert!(program.contains("    let mut post = Post::find(1).await?;\n    __tinker_save(\"/app/target/tinker/values/0.json\", &post)?;"));
+
+        session.bindings.push(Binding::Saved(post));
+        let program = session.program(Some(&classify("post.title")));
+        assert!(program.contains(
+            "    let mut post = __tinker_load(\"/app/target/tinker/values/0.json\", || async { Ok::<_, __TinkerError>({ Post::find(1).await? }) })?;"
+        ));
+        assert!(!program.contains("    let mut post = Post::find"));
     }
-}
 
-/// Evaluate simple math expressions
-fn evaluate_math(expr: &str) -> Result<f64, Box<dyn std::error::Error>> {
-    // Very basic math evaluation - in a real implementation,
-    // you'd want to use a proper expression parser
-    
-    let expr = expr.replace(' ', "");
-    
-    // Handle simple addition
-    if let Some(pos) = expr.find('+') {
-        let left: f64 = expr[..pos].parse()?;
-        let right: f64 = expr[pos + 1..].parse()?;
-        return Ok(left + right);
-    }
-    
-    // Handle simple subtraction
-    if let Some(pos) = expr.find('-') {
-        let left: f64 = expr[..pos].parse()?;
-        let right: f64 = expr[pos + 1..].parse()?;
-        return Ok(left - right);
-    }
-    
-    // Handle simple multiplication
-    if let Some(pos) = expr.find('*') {
-        let left: f64 = expr[..pos].parse()?;
-        let right: f64 = expr[pos + 1..].parse()?;
-        return Ok(left * right);
-    }
-    
-    // Handle simple division
-    if let Some(pos) = expr.find('/') {
-        let left: f64 = expr[..pos].parse()?;
-        let right: f64 = expr[pos + 1..].parse()?;
-        return Ok(left / right);
-    }
-    
-    // Try to parse as a single number
-    Ok(expr.parse()?)
+    #[test]
+    fn test_saved_binding_patterns() {
+        let typed = SavedBinding::parse("let ids: Vec<Box<dyn Fn(u8) -> u8>> = Vec::new();").unwrap();
+        assert_eq!(typed.ty.as_deref(), Some("Vec<Box<dyn Fn(u8) -> u8>>"));
+        assert_eq!(typed.value, "Vec::new()");
+
+        assert!(SavedBinding::parse("let (a, b) = (1, 2);").is_none());
+        assert!(SavedBinding::parse("let Some(x) = maybe else { return Ok(()) };").is_none());
+    }
+
+    #[test]
+    fn test_compiler_errors() {
+        let stderr = "warning: unused import\n  --> src/main.rs:1:5\n\nerror[E0425]: cannot find value `x`\n  --> src/main.rs:9:5\n\nerror: could not compile `torch-tinker`";
+        assert_eq!(compiler_errors(stderr), "error[E0425]: cannot find value `x`\n  --> src/main.rs:9:5");
+        assert_eq!(compiler_errors("thread 'main' panicked\n"), "thread 'main' panicked");
+    }
+
+    #[test]
+    fn test_parse_env() {
+        let env = parse_env("# comment\nDATABASE_URL=\"sqlite://app.db\"\nAPP_ENV = local\n");
+        assert_eq!(env.get("DATABASE_URL").map(String::as_str), Some("sqlite://app.db"));
+        assert_eq!(env.get("APP_ENV").map(String::as_str), Some("local"));
+    }
 }
@@ -1,100 +1,202 @@
 //! Code generators for Torch CLI
 
-/// Generate create table migration content
-pub fn generate_create_table_migration(name: &str, table_name: &str) -> String {
+use crate::cli::templates::pascal_case;
+
+pub mod auth;
+pub mod openapi;
+pub mod resource;
+
+/// Table a migration name refers to, e.g. `create_posts_table` creates `posts`
+#[derive(Debug, PartialEq)]
+pub enum MigrationTarget {
+    Create(String),
+    Alter(String),
+}
+
+/// Guess the table from names like `create_posts_table` or `add_status_to_posts_table`
+pub fn guess_migration_target(name: &str) -> Option<MigrationTarget> {
+    if let Some(table) = name.strip_prefix("create_").and_then(|rest| rest.strip_suffix("_table")) {
+        return Some(MigrationTarget::Create(table.to_string()));
+    }
+
+    ["_to_", "_from_", "_in_"].iter().find_map(|separator| {
+        let (_, table) = name.rsplit_once(separator)?;
+        let table = table.strip_suffix("_table").unwrap_or(table);
+        (!table.is_empty()).then(|| MigrationTarget::Alter(table.to_string()))
+    })
+}
+
+/// Column added by names like `add_status_to_posts_table`
+fn guess_added_column(name: &str) -> Option<&str> {
+    let (column, _) = name.strip_prefix("add_")?.split_once("_to_")?;
+    (!column.is_empty()).then_some(column)
+}
+
+/// Wrap `up` and `down` schema code in a `Migration` implementation
+fn migration_file(name: &str, version: &str, up: &str, down: &str) -> String {
     let mut content = String::new();
     content.push_str(&format!("//! {} - Generated by Torch CLI\n\n", name));
-    content.push_str("use sqlx::{Pool, Postgres};\n\n");
-    content.push_str(&format!("pub struct {} {{}}\n\n", name));
-    content.push_str(&format!("impl {} {{\n", name));
-    content.push_str("    pub async fn up(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {\n");
-    content.push_str(&format!("        sqlx::query!(r#\"\n"));
-    content.push_str(&format!("            CREATE TABLE {} (\n", table_name));
-    content.push_str("                id SERIAL PRIMARY KEY,\n");
-    content.push_str("                -- TODO: Add your columns here\n");
-    content.push_str("                -- name VARCHAR NOT NULL,\n");
-    content.push_str("                -- email VARCHAR UNIQUE NOT NULL,\n");
-    content.push_str("                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),\n");
-    content.push_str("                updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()\n");
-    content.push_str("            )\n");
-    content.push_str("        \"#)\n");
-    content.push_str("        .execute(pool)\n");
-    content.push_str("        .await?;\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
+    let uses_schema = [up, down]
+        .iter()
+        .any(|code| code.lines().any(|line| line.trim_start().starts_with("Schema::")));
+    if uses_schema {
+        content.push_str("use torch_web::orm::migration::{Migration, Schema};\n\n");
+    } else {
+        content.push_str("use torch_web::orm::migration::Migration;\n\n");
+    }
+    content.push_str(&format!("pub struct {};\n\n", pascal_case(name)));
+    content.push_str(&format!("impl Migration for {} {{\n", pascal_case(name)));
+    content.push_str("    fn name(&self) -> &str {\n");
+    content.push_str(&format!("        \"{}_{}\"\n", version, name));
     content.push_str("    }\n\n");
-    content.push_str("    pub async fn down(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {\n");
-    content.push_str(&format!("        sqlx::query!(r#\"DROP TABLE IF EXISTS {}\"#)\n", table_name));
-    content.push_str("            .execute(pool)\n");
-    content.push_str("            .await?;\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
+    content.push_str("    fn version(&self) -> &str {\n");
+    content.push_str(&format!("        \"{}\"\n", version));
+    content.push_str("    }\n\n");
+    content.push_str("    fn up_sql(&self) -> String {\n");
+    content.push_str(up);
+    content.push_str("    }\n\n");
+    content.push_str("    fn down_sql(&self) -> String {\n");
+    content.push_str(down);
     content.push_str("    }\n");
     content.push_str("}\n");
-    
     content
 }
 
+/// Generate create table migration content
+pub fn generate_create_table_migration(name: &str, version: &str, table_name: &str) -> String {
+    generate_create_table_migration_with_columns(name, version, table_name, &[])
+}
+
+/// Generate create table migration content with schema builder calls such as
+/// `table.string("title", None);` for the columns
+pub fn generate_create_table_migration_with_columns(name: &str, version: &str, table_name: &str, columns: &[String]) -> String {
+    let mut up = String::new();
+    up.push_str(&format!("        Schema::create_table(\"{}\", |table| {{\n", table_name));
+    up.push_str("            table.id(\"id\");\n");
+    if columns.is_empty() {
+        up.push_str("            // table.string(\"name\", None);\n");
+        up.push_str("            // table.string(\"email\", None).unique();\n");
+    }
+    for column in columns {
+        up.push_str(&format!("            {}\n", column));
+    }
+    up.push_str("            table.timestamps();\n");
+    up.push_str("        })\n");
+    up.push_str("        .to_sql()\n");
+
+    let down = format!("        Schema::drop_table(\"{}\").to_sql()\n", table_name);
+
+    migration_file(name, version, &up, &down)
+}
+
 /// Generate modify table migration content
-pub fn generate_modify_table_migration(name: &str, table_name: &str) -> String {
-    let mut content = String::new();
-    content.push_str(&format!("//! {} - Generated by Torch CLI\n\n", name));
-    content.push_str("use sqlx::{Pool, Postgres};\n\n");
-    content.push_str(&format!("pub struct {} {{}}\n\n", name));
-    content.push_str(&format!("impl {} {{\n", name));
-    content.push_str("    pub async fn up(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {\n");
-    content.push_str("        // TODO: Add your table modifications here\n");
-    content.push_str("        // Examples:\n");
-    content.push_str(&format!("        // sqlx::query!(r#\"ALTER TABLE {} ADD COLUMN new_column VARCHAR\"#)\n", table_name));
-    content.push_str("        //     .execute(pool)\n");
-    content.push_str("        //     .await?;\n");
-    content.push_str("        \n");
-    content.push_str(&format!("        // sqlx::query!(r#\"ALTER TABLE {} DROP COLUMN old_column\"#)\n", table_name));
-    content.push_str("        //     .execute(pool)\n");
-    content.push_str("        //     .await?;\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
-    content.push_str("    }\n");
-    content.push_str("    \n");
-    content.push_str("    pub async fn down(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {\n");
-    content.push_str("        // TODO: Add rollback logic here\n");
-    content.push_str("        // Examples:\n");
-    content.push_str(&format!("        // sqlx::query!(r#\"ALTER TABLE {} DROP COLUMN new_column\"#)\n", table_name));
-    content.push_str("        //     .execute(pool)\n");
-    content.push_str("        //     .await?;\n");
-    content.push_str("        \n");
-    content.push_str(&format!("        // sqlx::query!(r#\"ALTER TABLE {} ADD COLUMN old_column VARCHAR\"#)\n", table_name));
-    content.push_str("        //     .execute(pool)\n");
-    content.push_str("        //     .await?;\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
-    content.push_str("    }\n");
-    content.push_str("}\n");
-    
-    content
+pub fn generate_modify_table_migration(name: &str, version: &str, table_name: &str) -> String {
+    let (add, drop) = match guess_added_column(name) {
+        Some(column) => (
+            format!("            table.string(\"{}\", None).nullable();\n", column),
+            format!("            table.drop_column(\"{}\");\n", column),
+        ),
+        None => (
+            "            // table.string(\"new_column\", None).nullable();\n".to_string(),
+            "            // table.drop_column(\"new_column\");\n".to_string(),
+        ),
+    };
+
+    let alter = |body: &str| {
+        format!(
+            "        Schema::alter_table(\"{}\", |table| {{\n{}        }})\n        .to_sql()\n",
+            table_name, body
+        )
+    };
+
+    migration_file(name, version, &alter(&add), &alter(&drop))
+}
+
+/// Schema builder calls for one table of a generated migration
+#[derive(Debug, Default, PartialEq)]
+pub struct TableChanges {
+    pub table: String,
+    /// Create the table rather than alter it
+    pub create: bool,
+    /// Calls such as `table.string("title", None)`, without the `;`
+    pub up: Vec<String>,
+    /// Calls undoing `up` on an altered table, e.g. `table.drop_column("title")`
+    pub down: Vec<String>,
+}
+
+/// Generate a migration making `changes`, one builder per table
+pub fn generate_schema_changes_migration(name: &str, version: &str, changes: &[TableChanges]) -> String {
+    let builder = |kind: &str, table: &str, calls: &[String]| {
+        let mut code = format!("Schema::{}(\"{}\", |table| {{\n", kind, table);
+        for call in calls {
+            if call.starts_with("//") {
+                code.push_str(&format!("    {}\n", call));
+            } else {
+                code.push_str(&format!("    {};\n", call));
+            }
+        }
+        code.push_str("})\n.to_sql()");
+        code
+    };
+
+    let up: Vec<String> = changes
+        .iter()
+        .map(|change| builder(if change.create { "create_table" } else { "alter_table" }, &change.table, &change.up))
+        .collect();
+    let down: Vec<String> = changes
+        .iter()
+        .rev()
+        .filter(|change| change.create || !change.down.is_empty())
+        .map(|change| {
+            if change.create {
+                format!("Schema::drop_table(\"{}\").to_sql()", change.table)
+            } else {
+                builder("alter_table", &change.table, &change.down)
+            }
+        })
+        .collect();
+
+    migration_file(name, version, &statements_body(&up), &statements_body(&down))
+}
+
+/// Body of `up_sql` or `down_sql` returning the SQL of `builders`
+fn statements_body(builders: &[String]) -> String {
+    let indent = |code: &str, spaces: usize| {
+        code.lines()
+            .map(|line| format!("{}{}\n", " ".repeat(spaces), line))
+            .collect::<String>()
+    };
+    match builders {
+        [] => "        String::new()\n".to_string(),
+        [builder] => indent(builder, 8),
+        builders => {
+            let mut body = "        [\n".to_string();
+            for builder in builders {
+                let code = indent(builder, 12);
+                body.push_str(code.trim_end());
+                body.push_str(",\n");
+            }
+            body.push_str("        ]\n        .join(\";\\n\")\n");
+            body
+        }
+    }
 }
 
 /// Generate basic migration content
-pub fn generate_basic_migration(name: &str) -> String {
-    let mut content = String::new();
-    content.push_str(&format!("//! {} - Generated by Torch CLI\n\n", name));
-    content.push_str("use sqlx::{Pool, Postgres};\n\n");
-    content.push_str(&format!("pub struct {} {{}}\n\n", name));
-    content.push_str(&format!("impl {} {{\n", name));
-    content.push_str("    pub async fn up(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {\n");
-    content.push_str("        // TODO: Add your migration logic here\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
-    content.push_str("    }\n");
-    content.push_str("    \n");
-    content.push_str("    pub async fn down(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {\n");
-    content.push_str("        // TODO: Add rollback logic here\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
-    content.push_str("    }\n");
-    content.push_str("}\n");
-    
-    content
+pub fn generate_basic_migration(name: &str, version: &str) -> String {
+    let mut up = String::new();
+    up.push_str("        // Build the change with the schema builder, e.g.\n");
+    up.push_str("        // Schema::alter_table(\"users\", |table| {\n");
+    up.push_str("        //     table.boolean(\"active\").default(\"true\");\n");
+    up.push_str("        // })\n");
+    up.push_str("        // .to_sql()\n");
+    up.push_str("        String::new()\n");
+
+    let mut down = String::new();
+    down.push_str("        // Reverse whatever `up_sql` does\n");
+    down.push_str("        String::new()\n");
+
+    migration_file(name, version, &up, &down)
 }
 
 /// Generate seeder content
@@ -109,7 +211,7 @@ pub fn generate_seeder_content(name: &str) -> String {
     content.push_str("        \n");
     content.push_str("        // TODO: Add your seeding logic here\n");
     content.push_str("        // Example:\n");
-    content.push_str("        // sqlx::query!(r#\"\n");
+    content.push_str("        // sqlx::query(r#\"\n");
     content.push_str("        //     INSERT INTO users (name, email) VALUES \n");
     content.push_str("        //     ('John Doe', 'john@example.com'),\n");
     content.push_str("        //     ('Jane Smith', 'jane@example.com')\n");
@@ -426,12 +528,18 @@ pub fn generate_notification_content(name: &str) -> String {
 
 /// Generate command content
 pub fn generate_command_content(name: &str) -> String {
+    let command_name = console_command_name(name);
+
     let mut content = String::new();
-    content.push_str(&format!("//! {} - Generated by Torch CLI\n\n", name));
-    content.push_str("use clap::{Args, Parser};\n");
-    content.push_str("use colored::*;\n\n");
+    content.push_str(&format!("//! {} - Generated by Torch CLI\n", name));
+    content.push_str("//!\n");
+    content.push_str("//! Register it in main: `Console::new().register::<");
+    content.push_str(&format!("{}>()`, then run `torch {}`\n\n", name, command_name));
+    content.push_str("use clap::Parser;\n");
+    content.push_str("use colored::*;\n");
+    content.push_str("use torch_web::console::{CommandContext, CommandFuture, ConsoleCommand};\n\n");
     content.push_str("#[derive(Debug, Parser)]\n");
-    content.push_str(&format!("#[command(name = \"{}\")]\n", name.to_lowercase()));
+    content.push_str(&format!("#[command(name = \"{}\")]\n", command_name));
     content.push_str("#[command(about = \"TODO: Add command description\")]\n");
     content.push_str(&format!("pub struct {} {{\n", name));
     content.push_str("    /// TODO: Add command arguments\n");
@@ -445,25 +553,100 @@ pub fn generate_command_content(name: &str) -> String {
     content.push_str("    // #[arg(short, long, default_value = \"output.txt\")]\n");
     content.push_str("    // pub output: String,\n");
     content.push_str("}\n\n");
-    content.push_str(&format!("impl {} {{\n", name));
+    content.push_str(&format!("impl ConsoleCommand for {} {{\n", name));
     content.push_str("    /// Execute the command\n");
-    content.push_str("    pub async fn run(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {\n");
-    content.push_str("        if self.verbose {\n");
-    content.push_str(&format!("            println!(\"{{}} Running {} command...\", \"🔧\".yellow());\n", name));
-    content.push_str("        }\n");
-    content.push_str("        \n");
-    content.push_str("        // TODO: Implement your command logic\n");
-    content.push_str("        // Examples:\n");
-    content.push_str("        // - Process files\n");
-    content.push_str("        // - Interact with databases\n");
-    content.push_str("        // - Call external APIs\n");
-    content.push_str("        // - Generate reports\n");
-    content.push_str("        \n");
-    content.push_str("        println!(\"{} Command executed successfully!\", \"✅\".green());\n");
-    content.push_str("        \n");
-    content.push_str("        Ok(())\n");
+    content.push_str("    fn handle<'a>(&'a self, _context: &'a CommandContext) -> CommandFuture<'a> {\n");
+    content.push_str("        Box::pin(async move {\n");
+    content.push_str("            if self.verbose {\n");
+    content.push_str(&format!("                println!(\"{{}} Running {} command...\", \"🔧\".yellow());\n", command_name));
+    content.push_str("            }\n");
+    content.push_str("            \n");
+    content.push_str("            // TODO: Implement your command logic\n");
+    content.push_str("            // Examples:\n");
+    content.push_str("            // - Process files\n");
+    content.push_str("            // - Query models (the database is already connected)\n");
+    content.push_str("            // - Read shared state: _context.state::<AppState>()\n");
+    content.push_str("            // - Generate reports\n");
+    content.push_str("            \n");
+    content.push_str("            println!(\"{} Command executed successfully!\", \"✅\".green());\n");
+    content.push_str("            \n");
+    content.push_str("            Ok(())\n");
+    content.push_str("        })\n");
     content.push_str("    }\n");
     content.push_str("}\n");
 
     content
 }
+
+/// Name a command is run by: `SendEmailsCommand` becomes `send:emails`
+pub fn console_command_name(name: &str) -> String {
+    let name = name.strip_suffix("Command").filter(|rest| !rest.is_empty()).unwrap_or(name);
+    let mut words: Vec<String> = Vec::new();
+    for c in name.chars() {
+        if c.is_uppercase() || words.is_empty() {
+            words.push(String::new());
+        }
+        words.last_mut().unwrap().extend(c.to_lowercase());
+    }
+    words.join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_migration_target() {
+        assert_eq!(guess_migration_target("create_posts_table"), Some(MigrationTarget::Create("posts".to_string())));
+        assert_eq!(guess_migration_target("add_status_to_posts_table"), Some(MigrationTarget::Alter("posts".to_string())));
+        assert_eq!(guess_migration_target("remove_bio_from_users"), Some(MigrationTarget::Alter("users".to_string())));
+        assert_eq!(guess_migration_target("backfill_slugs"), None);
+    }
+
+    #[test]
+    fn test_migration_content() {
+        let create = generate_create_table_migration("create_posts_table", "2024_01_01_000000", "posts");
+        assert!(create.contains("impl Migration for CreatePostsTable {"));
+        assert!(create.contains("\"2024_01_01_000000_create_posts_table\""));
+        assert!(create.contains("Schema::drop_table(\"posts\").to_sql()"));
+
+        let alter = generate_modify_table_migration("add_status_to_posts_table", "2024_01_01_000000", "posts");
+        assert!(alter.contains("table.string(\"status\", None).nullable();"));
+        assert!(alter.contains("table.drop_column(\"status\");"));
+
+        let basic = generate_basic_migration("backfill_slugs", "2024_01_01_000000");
+        assert!(basic.contains("use torch_web::orm::migration::Migration;\n"));
+
+        let changes = generate_schema_changes_migration(
+            "fix_schema_drift",
+            "2024_01_01_000000",
+            &[
+                TableChanges {
+                    table: "tags".to_string(),
+                    create: true,
+                    up: vec!["table.id(\"id\")".to_string()],
+                    down: vec![],
+                },
+                TableChanges {
+                    table: "posts".to_string(),
+                    create: false,
+                    up: vec!["table.text(\"body\")".to_string()],
+                    down: vec!["table.drop_column(\"body\")".to_string()],
+                },
+            ],
+        );
+        assert!(changes.contains("        [\n            Schema::create_table(\"tags\", |table| {\n                table.id(\"id\");\n"));
+        assert!(changes.contains("            .to_sql(),\n        ]\n        .join(\";\\n\")\n"));
+        assert!(changes.contains("            Schema::drop_table(\"tags\").to_sql(),\n"));
+    }
+
+    #[test]
+    fn test_command_content() {
+        assert_eq!(console_command_name("SendEmailsCommand"), "send:emails");
+        assert_eq!(console_command_name("Command"), "command");
+
+        let command = generate_command_content("ReportDailyCommand");
+        assert!(command.contains("#[command(name = \"report:daily\")]"));
+        assert!(command.contains("impl ConsoleCommand for ReportDailyCommand {"));
+    }
+}
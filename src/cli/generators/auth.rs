@@ -0,0 +1,824 @@
+//! Authentication scaffolding for `torch make auth`
+//!
+//! Like `torch make resource`, the generated users and sessions are kept in
+//! memory so a fresh project runs without a database; the `users` migration
+//! is generated alongside for when one is wired up. Passwords are hashed with
+//! `torch_web::security::auth`, so the project needs the `security` and
+//! `templates` features (both part of `full`).
+
+use super::resource::VALIDATION_ERRORS;
+
+/// Files written by `torch make auth`, as `(path, content)` pairs
+pub fn source_files() -> Vec<(&'static str, String)> {
+    vec![
+        ("src/auth/mod.rs", session_content()),
+        ("src/models/user.rs", model_content()),
+        ("src/requests/auth_request.rs", request_content()),
+        ("src/controllers/auth_controller.rs", controller_content()),
+    ]
+}
+
+/// Generate the session store and the `Authenticate` middleware
+pub fn session_content() -> String {
+    r##"//! Sessions and route protection - Generated by Torch CLI
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use torch_web::middleware::Middleware;
+use torch_web::security::encryption::generate_random_token;
+use torch_web::{Request, Response};
+
+/// Cookie holding the session token
+pub const SESSION_COOKIE: &str = "torch_session";
+
+/// Where guests are sent when they open a protected page
+pub const LOGIN_PATH: &str = "/login";
+
+/// Where users land after logging in or registering
+pub const HOME_PATH: &str = "/dashboard";
+
+/// Id of the logged-in user, added to the request by [`Authenticate`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AuthUser(pub i64);
+
+/// Active sessions, keyed by the token stored in the session cookie.
+///
+/// Kept in memory, so everyone is logged out when the server restarts.
+#[derive(Clone, Default)]
+pub struct Sessions {
+    tokens: Arc<RwLock<HashMap<String, i64>>>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a session for `user_id`, returning its token
+    pub fn start(&self, user_id: i64) -> String {
+        let token = generate_random_token(40);
+        self.tokens.write().unwrap().insert(token.clone(), user_id);
+        token
+    }
+
+    /// User the session token belongs to
+    pub fn user_id(&self, token: &str) -> Option<i64> {
+        self.tokens.read().unwrap().get(token).copied()
+    }
+
+    pub fn end(&self, token: &str) {
+        self.tokens.write().unwrap().remove(token);
+    }
+
+    /// Move a session to a fresh token, returning the new token.
+    ///
+    /// Call this whenever the user's privileges change (confirming a password,
+    /// passing two-factor authentication, becoming an admin) and send the new
+    /// token with [`session_cookie`], so a token an attacker planted or
+    /// captured earlier stops working.
+    pub fn regenerate(&self, token: &str) -> Option<String> {
+        let mut tokens = self.tokens.write().unwrap();
+        let user_id = tokens.remove(token)?;
+        let fresh = generate_random_token(40);
+        tokens.insert(fresh.clone(), user_id);
+        Some(fresh)
+    }
+
+    /// User logged in on this request, if any
+    pub fn current_user(&self, req: &Request) -> Option<i64> {
+        session_token(req).and_then(|token| self.user_id(&token))
+    }
+}
+
+/// Session token from the request's cookies
+pub fn session_token(req: &Request) -> Option<String> {
+    req.header("cookie")?.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        (name == SESSION_COOKIE && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// `Set-Cookie` value that stores the session token.
+///
+/// Add `; Secure` once the app is served over HTTPS.
+pub fn session_cookie(token: &str) -> String {
+    format!("{}={}; Path=/; HttpOnly; SameSite=Lax", SESSION_COOKIE, token)
+}
+
+/// `Set-Cookie` value that removes the session cookie
+pub fn expired_session_cookie() -> String {
+    format!("{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0", SESSION_COOKIE)
+}
+
+/// Redirects guests to the login page for protected paths and adds
+/// [`AuthUser`] to the request for logged-in users
+pub struct Authenticate {
+    sessions: Sessions,
+    protected: Vec<String>,
+}
+
+impl Authenticate {
+    pub fn new(sessions: Sessions) -> Self {
+        Self {
+            sessions,
+            protected: Vec::new(),
+        }
+    }
+
+    /// Require a login for `prefix` and everything below it
+    pub fn protect(mut self, prefix: &str) -> Self {
+        self.protected.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    fn is_protected(&self, path: &str) -> bool {
+        self.protected.iter().any(|prefix| {
+            path == prefix || path.strip_prefix(prefix.as_str()).map_or(false, |rest| rest.starts_with('/'))
+        })
+    }
+}
+
+impl Middleware for Authenticate {
+    fn call(
+        &self,
+        mut req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let user = self.sessions.current_user(&req);
+        let protected = self.is_protected(req.path());
+
+        Box::pin(async move {
+            match user {
+                Some(id) => {
+                    req.insert_extension(AuthUser(id));
+                    next(req).await
+                }
+                None if protected => Response::redirect_found(LOGIN_PATH),
+                None => next(req).await,
+            }
+        })
+    }
+}
+"##
+    .to_string()
+}
+
+/// Generate the user model and its in-memory repository
+pub fn model_content() -> String {
+    r##"//! User - Generated by Torch CLI
+
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use torch_web::security::auth::{hash_password, verify_password};
+
+use crate::requests::auth_request::RegisterRequest;
+
+/// A registered user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+    /// Salted password hash, never sent to views
+    #[serde(skip_serializing)]
+    pub password: String,
+}
+
+/// Storage for users, shared with handlers as application state.
+///
+/// Kept in memory so the scaffold runs without a database; replace the
+/// method bodies with queries against the `users` table when you wire one up.
+#[derive(Clone, Default)]
+pub struct UserRepository {
+    rows: Arc<RwLock<(i64, Vec<User>)>>,
+}
+
+impl UserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn find(&self, id: i64) -> Option<User> {
+        self.rows.read().unwrap().1.iter().find(|user| user.id == id).cloned()
+    }
+
+    pub fn find_by_email(&self, email: &str) -> Option<User> {
+        self.rows
+            .read()
+            .unwrap()
+            .1
+            .iter()
+            .find(|user| user.email.eq_ignore_ascii_case(email))
+            .cloned()
+    }
+
+    /// Create a user, hashing the password. Fails if the email is taken.
+    pub fn create(&self, input: RegisterRequest) -> Result<User, String> {
+        let password = hash_password(&input.password).map_err(|e| e.to_string())?;
+        let mut rows = self.rows.write().unwrap();
+        if rows.1.iter().any(|user| user.email.eq_ignore_ascii_case(&input.email)) {
+            return Err("The email has already been taken.".to_string());
+        }
+
+        rows.0 += 1;
+        let user = User {
+            id: rows.0,
+            name: input.name,
+            email: input.email,
+            password,
+        };
+        rows.1.push(user.clone());
+        Ok(user)
+    }
+
+    /// The user with these credentials, if the password matches
+    pub fn attempt(&self, email: &str, password: &str) -> Option<User> {
+        let user = self.find_by_email(email)?;
+        verify_password(password, &user.password).unwrap_or(false).then_some(user)
+    }
+}
+"##
+    .to_string()
+}
+
+/// Generate the validated login and registration input
+pub fn request_content() -> String {
+    let mut content = String::from(
+        r##"//! Login and registration input - Generated by Torch CLI
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use torch_web::extractors::{Form, FromRequest};
+use torch_web::Request;
+
+/// Shortest password accepted on registration
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Validated registration form
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+impl RegisterRequest {
+    pub fn validate(input: &HashMap<String, String>) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        let value = |name: &str| input.get(name).map(|v| v.trim()).unwrap_or("");
+
+        let name = value("name").to_string();
+        if name.is_empty() {
+            errors.add("name", "The name field is required.");
+        }
+        if name.chars().count() > 255 {
+            errors.add("name", "The name may not be longer than 255 characters.");
+        }
+
+        let email = value("email").to_lowercase();
+        validate_email(&email, &mut errors);
+
+        // Passwords are not trimmed
+        let password = input.get("password").cloned().unwrap_or_default();
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            errors.add("password", "The password must be at least 8 characters.");
+        }
+        if input.get("password_confirmation") != Some(&password) {
+            errors.add("password", "The password confirmation does not match.");
+        }
+
+        if errors.is_empty() {
+            Ok(Self { name, email, password })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Validated login form
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+impl LoginRequest {
+    pub fn validate(input: &HashMap<String, String>) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        let email = input.get("email").map(|v| v.trim().to_lowercase()).unwrap_or_default();
+        validate_email(&email, &mut errors);
+
+        let password = input.get("password").cloned().unwrap_or_default();
+        if password.is_empty() {
+            errors.add("password", "The password field is required.");
+        }
+
+        if errors.is_empty() {
+            Ok(Self { email, password })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn validate_email(email: &str, errors: &mut ValidationErrors) {
+    if email.is_empty() {
+        errors.add("email", "The email field is required.");
+    } else if !email.split_once('@').map_or(false, |(user, domain)| !user.is_empty() && domain.contains('.')) {
+        errors.add("email", "The email must be a valid email address.");
+    }
+}
+
+"##,
+    );
+    content.push_str(&VALIDATION_ERRORS.replacen("async fn read_input", "pub async fn read_input", 1));
+    content
+}
+
+/// Generate the controller with register, login, logout and dashboard handlers
+pub fn controller_content() -> String {
+    r##"//! AuthController - Generated by Torch CLI
+
+use std::collections::HashMap;
+
+use torch_web::ember::{ember, EmberData};
+use torch_web::extractors::{FromRequestParts, IntoResponse, State};
+use torch_web::security::auth::LoginThrottle;
+use torch_web::{App, Request, Response, StatusCode};
+
+use crate::auth::{expired_session_cookie, session_cookie, session_token, AuthUser, Authenticate, Sessions, HOME_PATH};
+use crate::models::user::UserRepository;
+use crate::requests::auth_request::{read_input, LoginRequest, RegisterRequest, ValidationErrors};
+
+pub struct AuthController;
+
+impl AuthController {
+    /// Register the auth routes, the stores they share and the middleware
+    /// that keeps guests out of the dashboard.
+    ///
+    /// Failed logins are throttled per IP address and per account; tune the
+    /// limits and hook into lockouts on the [`LoginThrottle`] here.
+    pub fn routes(app: App) -> App {
+        let sessions = Sessions::new();
+
+        app.with_state(UserRepository::new())
+            .with_state(sessions.clone())
+            .with_state(LoginThrottle::new())
+            .middleware(Authenticate::new(sessions).protect(HOME_PATH))
+            .get("/register", Self::create_user)
+            .name("register")
+            .post("/register", Self::store_user)
+            .get("/login", Self::create_session)
+            .name("login")
+            .post("/login", Self::store_session)
+            .post("/logout", Self::destroy_session)
+            .name("logout")
+            .get(HOME_PATH, Self::dashboard)
+            .name("dashboard")
+    }
+
+    /// GET /register
+    pub async fn create_user(req: Request) -> Response {
+        if req.get_extension::<AuthUser>().is_some() {
+            return Response::redirect_found(HOME_PATH);
+        }
+        form("auth/register", &HashMap::new(), &ValidationErrors::default()).await
+    }
+
+    /// POST /register
+    pub async fn store_user(mut req: Request) -> Response {
+        let (users, sessions) = match stores(&mut req).await {
+            Ok(stores) => stores,
+            Err(response) => return response,
+        };
+
+        let previous = session_token(&req);
+        let input = read_input(req).await;
+        let user = match RegisterRequest::validate(&input).map(|input| users.create(input)) {
+            Ok(Ok(user)) => user,
+            Ok(Err(message)) => {
+                let mut errors = ValidationErrors::default();
+                errors.add("email", &message);
+                return form("auth/register", &input, &errors).await;
+            }
+            Err(errors) => return form("auth/register", &input, &errors).await,
+        };
+
+        login(&sessions, previous.as_deref(), user.id)
+    }
+
+    /// GET /login
+    pub async fn create_session(req: Request) -> Response {
+        if req.get_extension::<AuthUser>().is_some() {
+            return Response::redirect_found(HOME_PATH);
+        }
+        form("auth/login", &HashMap::new(), &ValidationErrors::default()).await
+    }
+
+    /// POST /login
+    pub async fn store_session(mut req: Request) -> Response {
+        let (users, sessions) = match stores(&mut req).await {
+            Ok(stores) => stores,
+            Err(response) => return response,
+        };
+
+        let State(throttle) = match State::<LoginThrottle>::from_request_parts(&mut req).await {
+            Ok(throttle) => throttle,
+            Err(err) => return err.into_response(),
+        };
+
+        let ip = client_ip(&req);
+        let previous = session_token(&req);
+        let input = read_input(req).await;
+        let credentials = match LoginRequest::validate(&input) {
+            Ok(credentials) => credentials,
+            Err(errors) => return form("auth/login", &input, &errors).await,
+        };
+
+        if let Err(retry_after) = throttle.check(ip.as_deref(), &credentials.email) {
+            let seconds = retry_after.as_secs().max(1);
+            let mut errors = ValidationErrors::default();
+            errors.add("email", &format!("Too many login attempts. Please try again in {} seconds.", seconds));
+            let mut response = form("auth/login", &input, &errors).await;
+            *response.status_code_mut() = StatusCode::TOO_MANY_REQUESTS;
+            return response.header("retry-after", &seconds.to_string());
+        }
+
+        match users.attempt(&credentials.email, &credentials.password) {
+            Some(user) => {
+                throttle.succeeded(&credentials.email);
+                login(&sessions, previous.as_deref(), user.id)
+            }
+            None => {
+                throttle.failed(ip.as_deref(), &credentials.email);
+                let mut errors = ValidationErrors::default();
+                errors.add("email", "These credentials do not match our records.");
+                form("auth/login", &input, &errors).await
+            }
+        }
+    }
+
+    /// POST /logout
+    pub async fn destroy_session(mut req: Request) -> Response {
+        let (_, sessions) = match stores(&mut req).await {
+            Ok(stores) => stores,
+            Err(response) => return response,
+        };
+
+        if let Some(token) = session_token(&req) {
+            sessions.end(&token);
+        }
+        Response::redirect_found("/").header("set-cookie", &expired_session_cookie())
+    }
+
+    /// GET /dashboard, only reachable when logged in
+    pub async fn dashboard(mut req: Request) -> Response {
+        let (users, _) = match stores(&mut req).await {
+            Ok(stores) => stores,
+            Err(response) => return response,
+        };
+
+        match req.get_extension::<AuthUser>().and_then(|AuthUser(id)| users.find(*id)) {
+            Some(user) => ember("auth/dashboard", EmberData::new().with("user", to_value(&user))).await,
+            None => Response::redirect_found("/login"),
+        }
+    }
+}
+
+async fn stores(req: &mut Request) -> Result<(UserRepository, Sessions), Response> {
+    let State(users) = State::<UserRepository>::from_request_parts(req)
+        .await
+        .map_err(|err| err.into_response())?;
+    let State(sessions) = State::<Sessions>::from_request_parts(req)
+        .await
+        .map_err(|err| err.into_response())?;
+    Ok((users, sessions))
+}
+
+/// Reverse proxies and load balancers in front of the app, whose
+/// `X-Forwarded-For` header names the client. Add yours, or every client
+/// behind it shares the proxy's login throttle.
+const TRUSTED_PROXIES: &[&str] = &[];
+
+/// Address of the client logging in: the connection's, or the one a trusted
+/// proxy forwarded. `None` when it isn't known, so only the account is
+/// throttled.
+fn client_ip(req: &Request) -> Option<String> {
+    let peer = req.remote_addr()?.ip().to_string();
+    if !TRUSTED_PROXIES.contains(&peer.as_str()) {
+        return Some(peer);
+    }
+    // The last address is the one the trusted proxy added
+    let forwarded = req.header("x-forwarded-for").and_then(|header| header.rsplit(',').next());
+    match forwarded.and_then(|ip| ip.trim().parse::<std::net::IpAddr>().ok()) {
+        Some(ip) => Some(ip.to_string()),
+        None => Some(peer),
+    }
+}
+
+/// Start a fresh session, replacing any previous one so a token set before
+/// login can't be reused afterwards
+fn login(sessions: &Sessions, previous: Option<&str>, user_id: i64) -> Response {
+    if let Some(token) = previous {
+        sessions.end(token);
+    }
+    let token = sessions.start(user_id);
+    Response::redirect_found(HOME_PATH).header("set-cookie", &session_cookie(&token))
+}
+
+/// Render a login/register form, answering 422 when validation failed.
+/// Passwords are never echoed back.
+async fn form(view: &str, input: &HashMap<String, String>, errors: &ValidationErrors) -> Response {
+    let old = |name: &str| input.get(name).cloned().unwrap_or_default();
+    let data = EmberData::new()
+        .with("name", old("name"))
+        .with("email", old("email"))
+        .with("errors", errors.messages());
+    let mut response = ember(view, data).await;
+    if !errors.is_empty() {
+        *response.status_code_mut() = StatusCode::UNPROCESSABLE_ENTITY;
+    }
+    response
+}
+
+fn to_value<T: serde::Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use torch_web::Method;
+
+    fn form_request(uri: &str, body: &str) -> Request {
+        Request::mock(Method::POST, uri)
+            .with_header("content-type", "application/x-www-form-urlencoded")
+            .with_body(body)
+    }
+
+    fn header<'a>(response: &'a Response, name: &str) -> &'a str {
+        response.headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or("")
+    }
+
+    /// `name=value` part of the session cookie a response set
+    fn session(response: &Response) -> String {
+        header(response, "set-cookie").split(';').next().unwrap_or("").to_string()
+    }
+
+    const REGISTRATION: &str = "name=Ada&email=ada%40example.com&password=correct-horse&password_confirmation=correct-horse";
+
+    #[tokio::test]
+    async fn test_guests_are_redirected_to_login() {
+        let app = AuthController::routes(App::new());
+
+        let response = app.handle_request(Request::mock(Method::GET, "/dashboard")).await;
+        assert_eq!(response.status_code(), StatusCode::FOUND);
+        assert_eq!(header(&response, "location"), "/login");
+    }
+
+    #[tokio::test]
+    async fn test_register_logs_the_user_in() {
+        let app = AuthController::routes(App::new());
+
+        let response = app.handle_request(form_request("/register", REGISTRATION)).await;
+        assert_eq!(header(&response, "location"), "/dashboard");
+
+        let cookie = session(&response);
+        let response = app
+            .handle_request(Request::mock(Method::GET, "/dashboard").with_header("cookie", &cookie))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_login_checks_the_password() {
+        let app = AuthController::routes(App::new());
+        app.handle_request(form_request("/register", REGISTRATION)).await;
+
+        let response = app
+            .handle_request(form_request("/login", "email=ada%40example.com&password=wrong-password"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = app
+            .handle_request(form_request("/login", "email=ada%40example.com&password=correct-horse"))
+            .await;
+        assert_eq!(header(&response, "location"), "/dashboard");
+    }
+
+    #[tokio::test]
+    async fn test_login_starts_a_new_session() {
+        let app = AuthController::routes(App::new());
+        let response = app.handle_request(form_request("/register", REGISTRATION)).await;
+        let before = session(&response);
+
+        let response = app
+            .handle_request(
+                form_request("/login", "email=ada%40example.com&password=correct-horse").with_header("cookie", &before),
+            )
+            .await;
+        let after = session(&response);
+        assert_ne!(before, after);
+
+        let response = app
+            .handle_request(Request::mock(Method::GET, "/dashboard").with_header("cookie", &before))
+            .await;
+        assert_eq!(header(&response, "location"), "/login");
+    }
+
+    #[tokio::test]
+    async fn test_repeated_failed_logins_are_throttled() {
+        let app = AuthController::routes(App::new());
+        app.handle_request(form_request("/register", REGISTRATION)).await;
+
+        for _ in 0..5 {
+            let response = app
+                .handle_request(form_request("/login", "email=ada%40example.com&password=wrong-password"))
+                .await;
+            assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        // Even the right password is refused while the account is locked
+        let response = app
+            .handle_request(form_request("/login", "email=ada%40example.com&password=correct-horse"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(!header(&response, "retry-after").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logout_ends_the_session() {
+        let app = AuthController::routes(App::new());
+        let response = app.handle_request(form_request("/register", REGISTRATION)).await;
+        let cookie = session(&response);
+
+        let response = app.handle_request(form_request("/logout", "").with_header("cookie", &cookie)).await;
+        assert!(header(&response, "set-cookie").contains("Max-Age=0"));
+
+        let response = app
+            .handle_request(Request::mock(Method::GET, "/dashboard").with_header("cookie", &cookie))
+            .await;
+        assert_eq!(header(&response, "location"), "/login");
+    }
+
+    #[test]
+    fn test_registration_validation() {
+        let input = |password: &str, confirmation: &str| {
+            HashMap::from([
+                ("name".to_string(), "Ada".to_string()),
+                ("email".to_string(), "ada@example.com".to_string()),
+                ("password".to_string(), password.to_string()),
+                ("password_confirmation".to_string(), confirmation.to_string()),
+            ])
+        };
+
+        assert!(RegisterRequest::validate(&input("correct-horse", "correct-horse")).is_ok());
+        assert!(RegisterRequest::validate(&input("short", "short")).is_err());
+        assert!(RegisterRequest::validate(&input("correct-horse", "other-horse")).is_err());
+    }
+}
+"##
+    .to_string()
+}
+
+/// Generate the `users` table migration
+pub fn migration_content(name: &str, version: &str) -> String {
+    let columns = [
+        "table.string(\"name\", None);".to_string(),
+        "table.string(\"email\", None).unique();".to_string(),
+        "table.string(\"password\", None);".to_string(),
+    ];
+    super::generate_create_table_migration_with_columns(name, version, "users", &columns)
+}
+
+/// Routes snippet printed after generation
+pub fn routes_snippet() -> String {
+    "use crate::controllers::auth_controller::AuthController;\n\n\
+     let app = AuthController::routes(app);"
+        .to_string()
+}
+
+/// Generate the Ember views as `(file name, content)` pairs
+pub fn view_contents() -> Vec<(&'static str, String)> {
+    vec![
+        ("login.ember", LOGIN_VIEW.to_string()),
+        ("register.ember", REGISTER_VIEW.to_string()),
+        ("dashboard.ember", DASHBOARD_VIEW.to_string()),
+        ("_errors.ember", ERRORS_PARTIAL.to_string()),
+    ]
+}
+
+const ERRORS_PARTIAL: &str = r#"@if($errors)
+    <ul class="errors">
+    @foreach($errors as $error)
+        <li>{{ $error }}</li>
+    @endforeach
+    </ul>
+@endif
+"#;
+
+const LOGIN_VIEW: &str = r#"@extends('layout')
+
+@section('title', 'Log in')
+
+@section('content')
+    <h1>Log in</h1>
+
+    @include('auth/_errors')
+
+    <form method="POST" action="/login">
+        <div class="field">
+            <label for="email">Email</label>
+            <input type="email" id="email" name="email" value="{{ $email }}" autocomplete="username" required autofocus>
+        </div>
+        <div class="field">
+            <label for="password">Password</label>
+            <input type="password" id="password" name="password" autocomplete="current-password" required>
+        </div>
+        <button type="submit">Log in</button>
+    </form>
+
+    <p>No account yet? <a href="/register">Register</a></p>
+@endsection
+"#;
+
+const REGISTER_VIEW: &str = r#"@extends('layout')
+
+@section('title', 'Register')
+
+@section('content')
+    <h1>Register</h1>
+
+    @include('auth/_errors')
+
+    <form method="POST" action="/register">
+        <div class="field">
+            <label for="name">Name</label>
+            <input type="text" id="name" name="name" value="{{ $name }}" maxlength="255" autocomplete="name" required autofocus>
+        </div>
+        <div class="field">
+            <label for="email">Email</label>
+            <input type="email" id="email" name="email" value="{{ $email }}" autocomplete="username" required>
+        </div>
+        <div class="field">
+            <label for="password">Password</label>
+            <input type="password" id="password" name="password" minlength="8" autocomplete="new-password" required>
+        </div>
+        <div class="field">
+            <label for="password_confirmation">Confirm password</label>
+            <input type="password" id="password_confirmation" name="password_confirmation" minlength="8" autocomplete="new-password" required>
+        </div>
+        <button type="submit">Register</button>
+    </form>
+
+    <p>Already registered? <a href="/login">Log in</a></p>
+@endsection
+"#;
+
+const DASHBOARD_VIEW: &str = r#"@extends('layout')
+
+@section('title', 'Dashboard')
+
+@section('content')
+    <h1>Dashboard</h1>
+    <p>You're logged in as {{ $user.name }} ({{ $user.email }}).</p>
+
+    <form method="POST" action="/logout">
+        <button type="submit">Log out</button>
+    </form>
+@endsection
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_sources_fit_together() {
+        let controller = controller_content();
+        assert!(controller.contains(".middleware(Authenticate::new(sessions).protect(HOME_PATH))"));
+        assert!(controller.contains("use crate::requests::auth_request::{read_input,"));
+        assert!(controller.contains(".with_state(LoginThrottle::new())"));
+        assert!(controller.contains("throttle.check(ip.as_deref(), &credentials.email)"));
+        assert!(!controller.contains("\"unknown\""));
+        assert!(session_content().contains("pub fn regenerate(&self, token: &str) -> Option<String>"));
+
+        let request = request_content();
+        assert!(request.contains("pub async fn read_input(req: Request)"));
+        assert!(request.contains("pub struct ValidationErrors"));
+
+        let model = model_content();
+        assert!(model.contains("hash_password(&input.password)"));
+        assert!(model.contains("#[serde(skip_serializing)]"));
+
+        let migration = migration_content("create_users_table", "2024_01_01_000000");
+        assert!(migration.contains("Schema::create_table(\"users\", |table| {"));
+        assert!(migration.contains("table.string(\"email\", None).unique();"));
+    }
+}
@@ -0,0 +1,1194 @@
+//! Spec-first scaffolding for `torch make from-openapi`
+//!
+//! Reads an OpenAPI 3 document and generates typed schemas with validation,
+//! the route table and controller skeletons. Everything under `src/openapi/`
+//! is regenerated from the spec on every run; controllers are only created
+//! when missing, so handler code written by hand is never lost.
+
+use crate::cli::templates::{pascal_case, snake_case};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// HTTP methods in the order they are registered
+const METHODS: [&str; 7] = ["get", "post", "put", "patch", "delete", "head", "options"];
+
+/// Parse a YAML or JSON OpenAPI document
+pub fn parse_spec(contents: &str) -> Result<Value, String> {
+    let spec: Value = if contents.trim_start().starts_with('{') {
+        serde_json::from_str(contents).map_err(|e| format!("Invalid JSON: {}", e))?
+    } else {
+        serde_yaml::from_str(contents).map_err(|e| format!("Invalid YAML: {}", e))?
+    };
+
+    match spec.get("openapi").and_then(Value::as_str) {
+        Some(version) if version.starts_with('3') => Ok(spec),
+        Some(version) => Err(format!("OpenAPI {} is not supported; convert the document to OpenAPI 3", version)),
+        None if spec.get("swagger").is_some() => {
+            Err("Swagger 2.0 documents are not supported; convert the document to OpenAPI 3".to_string())
+        }
+        None => Err("Not an OpenAPI document: the `openapi` version field is missing".to_string()),
+    }
+}
+
+/// A controller skeleton for one tag
+#[derive(Debug, Clone)]
+pub struct ControllerFile {
+    /// `PetsController`
+    pub name: String,
+    /// `pets_controller`
+    pub module: String,
+    /// Handler functions the controller must define
+    pub handlers: Vec<String>,
+    pub content: String,
+}
+
+/// Everything generated from a spec
+#[derive(Debug, Clone)]
+pub struct GeneratedApi {
+    /// `src/openapi/mod.rs`: helpers and the route table
+    pub module: String,
+    /// `src/openapi/schemas.rs`: typed schemas with validation
+    pub schemas: String,
+    pub controllers: Vec<ControllerFile>,
+    pub operations: usize,
+}
+
+/// Generate sources from a parsed spec. `source` names the spec file in
+/// headers, and `prefix` overrides the base path taken from `servers`.
+pub fn generate(spec: &Value, source: &str, prefix: Option<&str>) -> Result<GeneratedApi, String> {
+    let mut types = TypeRegistry::new(spec);
+    types.define_components()?;
+
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim_end_matches('/').to_string(),
+        None => server_base_path(spec),
+    };
+
+    let mut controllers: BTreeMap<String, Vec<Operation>> = BTreeMap::new();
+    let empty = Map::new();
+    let paths = spec.get("paths").and_then(Value::as_object).unwrap_or(&empty);
+    for (path, item) in paths {
+        let item = types.resolve(item);
+        let shared = item.get("parameters").and_then(Value::as_array).cloned().unwrap_or_default();
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+            let operation = Operation::parse(&mut types, method, path, operation, &shared)?;
+            controllers.entry(operation.tag.clone()).or_default().push(operation);
+        }
+    }
+
+    for operations in controllers.values_mut() {
+        let mut seen = BTreeSet::new();
+        for operation in operations.iter_mut() {
+            let base = operation.handler.clone();
+            let mut suffix = 2;
+            while !seen.insert(operation.handler.clone()) {
+                operation.handler = format!("{}_{}", base, suffix);
+                suffix += 1;
+            }
+        }
+    }
+
+    let operations = controllers.values().map(Vec::len).sum();
+    Ok(GeneratedApi {
+        module: module_content(&controllers, source, &prefix),
+        schemas: types.render(source),
+        controllers: controllers
+            .iter()
+            .map(|(tag, operations)| controller_content(tag, operations, source))
+            .collect(),
+        operations,
+    })
+}
+
+/// Path portion of the first server URL, e.g. `/v1` for `https://api.example.com/v1`
+fn server_base_path(spec: &Value) -> String {
+    let url = spec
+        .get("servers")
+        .and_then(Value::as_array)
+        .and_then(|servers| servers.first())
+        .and_then(|server| server.get("url"))
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|index| &rest[index..]).unwrap_or(""),
+        None => url,
+    };
+    path.trim_end_matches('/').to_string()
+}
+
+/// Rust keywords that need a raw identifier when used as field names
+const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "trait",
+    "true", "type", "unsafe", "use", "where", "while", "abstract", "final", "override", "yield",
+];
+
+/// A valid snake_case Rust identifier for a JSON name
+fn field_ident(name: &str) -> String {
+    let mut ident: String = snake_case(name)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    match ident.as_str() {
+        "self" | "super" | "crate" => format!("{}_", ident),
+        keyword if KEYWORDS.contains(&keyword) => format!("r#{}", ident),
+        _ => ident,
+    }
+}
+
+/// A valid PascalCase Rust type or variant name for a JSON name
+fn type_ident(name: &str) -> String {
+    let words: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let ident = pascal_case(&words);
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("V{}", ident)
+    } else {
+        ident
+    }
+}
+
+/// Doc comment lines for an optional description
+fn doc_lines(text: Option<&str>, indent: &str) -> String {
+    text.map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(|text| text.lines().map(|line| format!("{}/// {}\n", indent, line.trim_end()).replace("/// \n", "///\n")).collect())
+        .unwrap_or_default()
+}
+
+/// A Rust type for a schema, and whether it has nested validation to run
+#[derive(Debug, Clone)]
+struct RustType {
+    name: String,
+    nested: bool,
+}
+
+impl RustType {
+    fn plain(name: &str) -> Self {
+        Self { name: name.to_string(), nested: false }
+    }
+}
+
+/// A constraint checked by the generated `validate_into`
+#[derive(Debug, Clone, PartialEq)]
+enum Check {
+    MinLength(u64),
+    MaxLength(u64),
+    Minimum { value: f64, exclusive: bool },
+    Maximum { value: f64, exclusive: bool },
+    MinItems(u64),
+    MaxItems(u64),
+    Email,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    ident: String,
+    json_name: String,
+    ty: RustType,
+    optional: bool,
+    float: bool,
+    checks: Vec<Check>,
+    doc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum TypeDef {
+    Struct { name: String, doc: Option<String>, fields: Vec<Field> },
+    Enum { name: String, doc: Option<String>, variants: Vec<(String, String)> },
+    Alias { name: String, doc: Option<String>, target: String },
+}
+
+/// Collects the types a spec needs, naming inline schemas after where they appear
+struct TypeRegistry<'a> {
+    spec: &'a Value,
+    defs: Vec<TypeDef>,
+    names: BTreeSet<String>,
+    /// Component names mapped to their Rust type names
+    components: BTreeMap<String, String>,
+}
+
+impl<'a> TypeRegistry<'a> {
+    fn new(spec: &'a Value) -> Self {
+        Self { spec, defs: Vec::new(), names: BTreeSet::new(), components: BTreeMap::new() }
+    }
+
+    /// Follow a local `$ref` such as `#/components/schemas/Pet`
+    fn resolve<'v>(&self, value: &'v Value) -> &'v Value
+    where
+        'a: 'v,
+    {
+        let mut value = value;
+        for _ in 0..16 {
+            let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+                break;
+            };
+            match reference.strip_prefix('#').and_then(|pointer| self.spec.pointer(pointer)) {
+                Some(target) => value = target,
+                None => break,
+            }
+        }
+        value
+    }
+
+    /// Reserve a unique type name
+    fn claim(&mut self, name: &str) -> String {
+        let base = type_ident(name);
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while !self.names.insert(name.clone()) {
+            name = format!("{}{}", base, suffix);
+            suffix += 1;
+        }
+        name
+    }
+
+    /// Name every component first so references resolve regardless of order
+    fn define_components(&mut self) -> Result<(), String> {
+        let Some(schemas) = self.spec.pointer("/components/schemas").and_then(Value::as_object) else {
+            return Ok(());
+        };
+        for name in schemas.keys() {
+            let rust_name = self.claim(name);
+            self.components.insert(name.clone(), rust_name);
+        }
+        for (name, schema) in schemas {
+            let rust_name = self.components[name].clone();
+            self.define_named(&rust_name, schema)?;
+        }
+        Ok(())
+    }
+
+    /// Define a component under its reserved name
+    fn define_named(&mut self, name: &str, schema: &Value) -> Result<(), String> {
+        let doc = schema.get("description").and_then(Value::as_str).map(str::to_string);
+        if is_object_like(schema) {
+            let fields = self.fields(name, schema)?;
+            self.defs.push(TypeDef::Struct { name: name.to_string(), doc, fields });
+        } else if let Some(values) = string_enum(schema) {
+            self.defs.push(TypeDef::Enum { name: name.to_string(), doc, variants: variants(&values) });
+        } else {
+            let target = self.rust_type(schema, &format!("{}Item", name))?;
+            self.defs.push(TypeDef::Alias { name: name.to_string(), doc, target: target.name });
+        }
+        Ok(())
+    }
+
+    /// The Rust type for `schema`, defining inline structs and enums as `hint`
+    fn rust_type(&mut self, schema: &Value, hint: &str) -> Result<RustType, String> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference
+                .strip_prefix("#/components/schemas/")
+                .and_then(|name| self.components.get(name))
+                .ok_or_else(|| format!("Unsupported reference '{}'; only #/components/schemas refs are followed", reference))?;
+            return Ok(RustType { name: name.clone(), nested: true });
+        }
+
+        let ty = if is_object_like(schema) {
+            let name = self.claim(hint);
+            let doc = schema.get("description").and_then(Value::as_str).map(str::to_string);
+            let fields = self.fields(&name, schema)?;
+            self.defs.push(TypeDef::Struct { name: name.clone(), doc, fields });
+            RustType { name, nested: true }
+        } else if let Some(values) = string_enum(schema) {
+            let name = self.claim(hint);
+            let doc = schema.get("description").and_then(Value::as_str).map(str::to_string);
+            self.defs.push(TypeDef::Enum { name: name.clone(), doc, variants: variants(&values) });
+            RustType { name, nested: true }
+        } else {
+            match schema_type(schema) {
+                Some("string") => RustType::plain("String"),
+                Some("integer") if schema.get("format").and_then(Value::as_str) == Some("int32") => RustType::plain("i32"),
+                Some("integer") => RustType::plain("i64"),
+                Some("number") if schema.get("format").and_then(Value::as_str) == Some("float") => RustType::plain("f32"),
+                Some("number") => RustType::plain("f64"),
+                Some("boolean") => RustType::plain("bool"),
+                Some("array") => {
+                    let items = schema.get("items").cloned().unwrap_or(Value::Null);
+                    let item = self.rust_type(&items, &format!("{}Item", hint))?;
+                    RustType { name: format!("Vec<{}>", item.name), nested: item.nested }
+                }
+                Some("object") => match schema.get("additionalProperties") {
+                    Some(values) if values.is_object() => {
+                        let value = self.rust_type(values, &format!("{}Value", hint))?;
+                        RustType { name: format!("HashMap<String, {}>", value.name), nested: value.nested }
+                    }
+                    _ => RustType::plain("serde_json::Value"),
+                },
+                _ => RustType::plain("serde_json::Value"),
+            }
+        };
+
+        if is_nullable(schema) {
+            Ok(RustType { name: format!("Option<{}>", ty.name), nested: ty.nested })
+        } else {
+            Ok(ty)
+        }
+    }
+
+    /// Fields of an object schema, merging `allOf` parts
+    fn fields(&mut self, owner: &str, schema: &Value) -> Result<Vec<Field>, String> {
+        let mut properties: Vec<(String, Value)> = Vec::new();
+        let mut required: BTreeSet<String> = BTreeSet::new();
+        self.collect_properties(schema, &mut properties, &mut required, 0);
+
+        let mut fields = Vec::new();
+        let mut idents = BTreeSet::new();
+        for (json_name, property) in properties {
+            let mut ident = field_ident(&json_name);
+            while !idents.insert(ident.clone()) {
+                ident.push('_');
+            }
+            let resolved = self.resolve(&property).clone();
+            let ty = self.rust_type(&property, &format!("{}{}", owner, type_ident(&json_name)))?;
+            let optional = !required.contains(&json_name) && !ty.name.starts_with("Option<");
+            let checks = if property.get("$ref").is_some() { Vec::new() } else { checks(&resolved) };
+            let base = ty.name.trim_start_matches("Option<");
+            fields.push(Field {
+                float: base.starts_with("f32") || base.starts_with("f64"),
+                doc: field_doc(&resolved),
+                ident,
+                json_name,
+                ty,
+                optional,
+                checks,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn collect_properties(&self, schema: &Value, properties: &mut Vec<(String, Value)>, required: &mut BTreeSet<String>, depth: usize) {
+        if depth > 16 {
+            return;
+        }
+        let schema = self.resolve(schema);
+        for part in schema.get("allOf").and_then(Value::as_array).into_iter().flatten() {
+            self.collect_properties(part, properties, required, depth + 1);
+        }
+        if let Some(own) = schema.get("properties").and_then(Value::as_object) {
+            for (name, property) in own {
+                match properties.iter_mut().find(|(existing, _)| existing == name) {
+                    Some(entry) => entry.1 = property.clone(),
+                    None => properties.push((name.clone(), property.clone())),
+                }
+            }
+        }
+        for name in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(name) = name.as_str() {
+                required.insert(name.to_string());
+            }
+        }
+    }
+
+    /// Render `schemas.rs`
+    fn render(&self, source: &str) -> String {
+        let mut content = format!(
+            "//! Schemas from {source} - Generated by Torch CLI\n\
+             //!\n\
+             //! Regenerated by `torch make from-openapi`; change the spec instead of\n\
+             //! editing this file.\n\n\
+             #![allow(dead_code)]\n\n",
+        );
+        if self.uses("HashMap<") {
+            content.push_str("use std::collections::HashMap;\n\n");
+        }
+        content.push_str("use serde::{Deserialize, Serialize};\n\n");
+        content.push_str("use super::{field_path, Validate, ValidationErrors};\n");
+
+        for def in &self.defs {
+            content.push('\n');
+            match def {
+                TypeDef::Struct { name, doc, fields } => render_struct(&mut content, name, doc.as_deref(), fields),
+                TypeDef::Enum { name, doc, variants } => render_enum(&mut content, name, doc.as_deref(), variants),
+                TypeDef::Alias { name, doc, target } => {
+                    content.push_str(&doc_lines(doc.as_deref(), ""));
+                    content.push_str(&format!("pub type {} = {};\n", name, target));
+                }
+            }
+        }
+        content
+    }
+
+    fn uses(&self, needle: &str) -> bool {
+        self.defs.iter().any(|def| match def {
+            TypeDef::Struct { fields, .. } => fields.iter().any(|field| field.ty.name.contains(needle)),
+            TypeDef::Alias { target, .. } => target.contains(needle),
+            TypeDef::Enum { .. } => false,
+        })
+    }
+}
+
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type") {
+        Some(Value::String(ty)) => Some(ty),
+        // OpenAPI 3.1 writes nullable types as `type: [string, "null"]`
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).find(|ty| *ty != "null"),
+        _ => None,
+    }
+}
+
+fn is_nullable(schema: &Value) -> bool {
+    schema.get("nullable").and_then(Value::as_bool).unwrap_or(false)
+        || matches!(schema.get("type"), Some(Value::Array(types)) if types.iter().any(|ty| ty == "null"))
+}
+
+fn is_object_like(schema: &Value) -> bool {
+    schema.get("properties").is_some() || schema.get("allOf").is_some()
+}
+
+fn string_enum(schema: &Value) -> Option<Vec<String>> {
+    if schema_type(schema).is_some_and(|ty| ty != "string") {
+        return None;
+    }
+    let values = schema.get("enum")?.as_array()?;
+    values.iter().map(|value| value.as_str().map(str::to_string)).collect()
+}
+
+/// Variant names paired with the JSON values they stand for
+fn variants(values: &[String]) -> Vec<(String, String)> {
+    let mut seen = BTreeSet::new();
+    values
+        .iter()
+        .map(|value| {
+            let mut variant = type_ident(value);
+            while !seen.insert(variant.clone()) {
+                variant.push('_');
+            }
+            (variant, value.clone())
+        })
+        .collect()
+}
+
+fn checks(schema: &Value) -> Vec<Check> {
+    let number = |key: &str| schema.get(key).and_then(Value::as_f64);
+    let count = |key: &str| schema.get(key).and_then(Value::as_u64);
+    let mut checks = Vec::new();
+
+    if let Some(length) = count("minLength").filter(|length| *length > 0) {
+        checks.push(Check::MinLength(length));
+    }
+    if let Some(length) = count("maxLength") {
+        checks.push(Check::MaxLength(length));
+    }
+    // OpenAPI 3.0 flags the bound as exclusive; 3.1 gives the bound itself
+    match (number("minimum"), schema.get("exclusiveMinimum")) {
+        (_, Some(Value::Number(bound))) => checks.push(Check::Minimum { value: bound.as_f64().unwrap_or_default(), exclusive: true }),
+        (Some(value), exclusive) => checks.push(Check::Minimum { value, exclusive: exclusive == Some(&Value::Bool(true)) }),
+        _ => {}
+    }
+    match (number("maximum"), schema.get("exclusiveMaximum")) {
+        (_, Some(Value::Number(bound))) => checks.push(Check::Maximum { value: bound.as_f64().unwrap_or_default(), exclusive: true }),
+        (Some(value), exclusive) => checks.push(Check::Maximum { value, exclusive: exclusive == Some(&Value::Bool(true)) }),
+        _ => {}
+    }
+    if let Some(items) = count("minItems").filter(|items| *items > 0) {
+        checks.push(Check::MinItems(items));
+    }
+    if let Some(items) = count("maxItems") {
+        checks.push(Check::MaxItems(items));
+    }
+    if schema.get("format").and_then(Value::as_str) == Some("email") {
+        checks.push(Check::Email);
+    }
+    checks
+}
+
+/// Description plus anything the generated code doesn't enforce
+fn field_doc(schema: &Value) -> Option<String> {
+    let mut lines: Vec<String> = schema
+        .get("description")
+        .and_then(Value::as_str)
+        .map(|text| text.trim().lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    if let Some(format) = schema.get("format").and_then(Value::as_str) {
+        if !matches!(format, "email" | "int32" | "int64" | "float" | "double") {
+            lines.push(format!("Format: `{}`", format));
+        }
+    }
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        lines.push(format!("Pattern (not checked): `{}`", pattern));
+    }
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+fn render_struct(content: &mut String, name: &str, doc: Option<&str>, fields: &[Field]) {
+    content.push_str(&doc_lines(doc, ""));
+    content.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+    content.push_str(&format!("pub struct {} {{\n", name));
+    for field in fields {
+        content.push_str(&doc_lines(field.doc.as_deref(), "    "));
+        if field.ident.trim_start_matches("r#") != field.json_name {
+            content.push_str(&format!("    #[serde(rename = \"{}\")]\n", field.json_name.escape_default()));
+        }
+        if field.optional {
+            content.push_str("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n");
+            content.push_str(&format!("    pub {}: Option<{}>,\n", field.ident, field.ty.name));
+        } else {
+            content.push_str(&format!("    pub {}: {},\n", field.ident, field.ty.name));
+        }
+    }
+    content.push_str("}\n\n");
+
+    let validated: Vec<&Field> = fields.iter().filter(|field| field.ty.nested || !field.checks.is_empty()).collect();
+    let (path, errors) = if validated.is_empty() { ("_path", "_errors") } else { ("path", "errors") };
+    content.push_str(&format!("impl Validate for {} {{\n", name));
+    content.push_str(&format!("    fn validate_into(&self, {}: &str, {}: &mut ValidationErrors) {{\n", path, errors));
+    for (index, field) in validated.iter().enumerate() {
+        if index > 0 {
+            content.push('\n');
+        }
+        render_field_validation(content, field);
+    }
+    content.push_str("    }\n");
+    content.push_str("}\n");
+}
+
+fn render_field_validation(content: &mut String, field: &Field) {
+    let ident = &field.ident;
+    let json_name = field.json_name.escape_default();
+    if field.checks.is_empty() {
+        content.push_str(&format!("        self.{}.validate_into(&field_path(path, \"{}\"), errors);\n", ident, json_name));
+        return;
+    }
+
+    content.push_str(&format!("        let field = field_path(path, \"{}\");\n", json_name));
+    let nullable = field.ty.name.starts_with("Option<");
+    let (open, indent) = if field.optional || nullable {
+        (format!("        if let Some(value) = &self.{} {{\n", ident), "            ")
+    } else {
+        (format!("        let value = &self.{};\n", ident), "        ")
+    };
+    content.push_str(&open);
+
+    for check in &field.checks {
+        let (condition, message) = match check {
+            Check::MinLength(1) => ("value.is_empty()".to_string(), "is required".to_string()),
+            Check::MinLength(length) => (
+                format!("value.chars().count() < {}", length),
+                format!("must be at least {} characters", length),
+            ),
+            Check::MaxLength(length) => (
+                format!("value.chars().count() > {}", length),
+                format!("may not be longer than {} characters", length),
+            ),
+            Check::Minimum { value, exclusive } => (
+                format!("*value {} {}", if *exclusive { "<=" } else { "<" }, number_literal(*value, field.float)),
+                format!("must be {} {}", if *exclusive { "greater than" } else { "at least" }, value),
+            ),
+            Check::Maximum { value, exclusive } => (
+                format!("*value {} {}", if *exclusive { ">=" } else { ">" }, number_literal(*value, field.float)),
+                format!("must be {} {}", if *exclusive { "less than" } else { "at most" }, value),
+            ),
+            Check::MinItems(1) => ("value.is_empty()".to_string(), "must have at least 1 item".to_string()),
+            Check::MinItems(items) => (format!("value.len() < {}", items), format!("must have at least {} items", items)),
+            Check::MaxItems(items) => (format!("value.len() > {}", items), format!("may not have more than {} items", items)),
+            Check::Email => ("!value.contains('@')".to_string(), "must be a valid email address".to_string()),
+        };
+        content.push_str(&format!("{}if {} {{\n", indent, condition));
+        content.push_str(&format!("{}    errors.add(&field, \"{}\");\n", indent, message));
+        content.push_str(&format!("{}}}\n", indent));
+    }
+    if field.ty.nested {
+        content.push_str(&format!("{}value.validate_into(&field, errors);\n", indent));
+    }
+    if field.optional || nullable {
+        content.push_str("        }\n");
+    }
+}
+
+fn number_literal(value: f64, float: bool) -> String {
+    if float {
+        format!("{:?}", value)
+    } else {
+        format!("{}", value.trunc() as i64)
+    }
+}
+
+fn render_enum(content: &mut String, name: &str, doc: Option<&str>, variants: &[(String, String)]) {
+    content.push_str(&doc_lines(doc, ""));
+    content.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]\n");
+    content.push_str(&format!("pub enum {} {{\n", name));
+    for (variant, value) in variants {
+        content.push_str(&format!("    #[serde(rename = \"{}\")]\n", value.escape_default()));
+        content.push_str(&format!("    {},\n", variant));
+    }
+    content.push_str("}\n\n");
+    content.push_str(&format!("impl Validate for {} {{\n", name));
+    content.push_str("    fn validate_into(&self, _path: &str, _errors: &mut ValidationErrors) {}\n");
+    content.push_str("}\n");
+}
+
+/// A path or query parameter read by a handler
+#[derive(Debug, Clone)]
+struct Parameter {
+    name: String,
+    ident: String,
+    location: String,
+    ty: String,
+    required: bool,
+}
+
+/// One operation, handled by a controller method
+#[derive(Debug, Clone)]
+struct Operation {
+    method: String,
+    path: String,
+    tag: String,
+    handler: String,
+    route_name: String,
+    summary: Option<String>,
+    parameters: Vec<Parameter>,
+    body: Option<(RustType, bool)>,
+    response: Option<(u16, Option<String>)>,
+}
+
+impl Operation {
+    fn parse(types: &mut TypeRegistry, method: &str, path: &str, operation: &Value, shared: &[Value]) -> Result<Self, String> {
+        let operation_id = operation.get("operationId").and_then(Value::as_str);
+        let handler = match operation_id {
+            Some(id) => field_ident(id).trim_start_matches("r#").to_string(),
+            None => format!("{} {}", method, path)
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(snake_case)
+                .collect::<Vec<_>>()
+                .join("_"),
+        };
+        let type_hint = type_ident(operation_id.unwrap_or(&handler));
+
+        let tag = operation
+            .get("tags")
+            .and_then(Value::as_array)
+            .and_then(|tags| tags.first())
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or_else(|| path.split('/').find(|segment| !segment.is_empty() && !segment.starts_with('{')).map(str::to_string))
+            .unwrap_or_else(|| "api".to_string());
+
+        let own = operation.get("parameters").and_then(Value::as_array).cloned().unwrap_or_default();
+        let mut parameters: Vec<Parameter> = Vec::new();
+        for parameter in shared.iter().chain(own.iter()) {
+            let parameter = types.resolve(parameter);
+            let (Some(name), Some(location)) = (
+                parameter.get("name").and_then(Value::as_str),
+                parameter.get("in").and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            if location != "path" && location != "query" {
+                continue;
+            }
+            let schema = types.resolve(parameter.get("schema").unwrap_or(&Value::Null));
+            let ty = match schema_type(schema) {
+                Some("integer") if schema.get("format").and_then(Value::as_str) == Some("int32") => "i32",
+                Some("integer") => "i64",
+                Some("number") => "f64",
+                Some("boolean") => "bool",
+                _ => "String",
+            };
+            let parsed = Parameter {
+                name: name.to_string(),
+                ident: field_ident(name),
+                location: location.to_string(),
+                ty: ty.to_string(),
+                required: location == "path" || parameter.get("required").and_then(Value::as_bool).unwrap_or(false),
+            };
+            // Operation parameters override path-level ones with the same name and location
+            parameters.retain(|existing| existing.name != parsed.name || existing.location != parsed.location);
+            parameters.push(parsed);
+        }
+
+        let body = match operation.get("requestBody") {
+            Some(body) => {
+                let body = types.resolve(body).clone();
+                match json_schema(&body) {
+                    Some(schema) => {
+                        let ty = types.rust_type(&schema, &format!("{}Request", type_hint))?;
+                        Some((ty, body.get("required").and_then(Value::as_bool).unwrap_or(false)))
+                    }
+                    None => None,
+                }
+            }
+            None => None,
+        };
+
+        let response = operation.get("responses").and_then(Value::as_object).and_then(|responses| {
+            let (code, response) = responses
+                .iter()
+                .filter_map(|(code, response)| Some((code.parse::<u16>().ok()?, response)))
+                .filter(|(code, _)| (200..300).contains(code))
+                .min_by_key(|(code, _)| *code)?;
+            Some((code, types.resolve(response).clone()))
+        });
+        let response = match response {
+            Some((code, response)) => match json_schema(&response) {
+                Some(schema) => Some((code, Some(types.rust_type(&schema, &format!("{}Response", type_hint))?.name))),
+                None => Some((code, None)),
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            tag,
+            route_name: operation_id.map(str::to_string).unwrap_or_else(|| handler.clone()),
+            handler,
+            summary: operation
+                .get("summary")
+                .or_else(|| operation.get("description"))
+                .and_then(Value::as_str)
+                .map(|text| text.lines().next().unwrap_or_default().trim().to_string()),
+            parameters,
+            body,
+            response,
+        })
+    }
+
+    /// Route pattern in Torch syntax, e.g. `/pets/:petId`
+    fn route(&self, prefix: &str) -> String {
+        let path = self.path.replace('{', ":").replace('}', "");
+        format!("{}{}", prefix, path)
+    }
+}
+
+/// Schema of the JSON content in a request body or response
+fn json_schema(body: &Value) -> Option<Value> {
+    let content = body.get("content")?.as_object()?;
+    content
+        .iter()
+        .find(|(media_type, _)| media_type.contains("json"))
+        .and_then(|(_, media)| media.get("schema").cloned())
+}
+
+fn controller_name(tag: &str) -> String {
+    format!("{}Controller", type_ident(tag))
+}
+
+fn controller_module(tag: &str) -> String {
+    format!("{}_controller", field_ident(tag).trim_start_matches("r#"))
+}
+
+/// Render `src/openapi/mod.rs`
+fn module_content(controllers: &BTreeMap<String, Vec<Operation>>, source: &str, prefix: &str) -> String {
+    let mut imports: Vec<String> = controllers
+        .keys()
+        .map(|tag| format!("use crate::controllers::{}::{};\n", controller_module(tag), controller_name(tag)))
+        .collect();
+    imports.sort();
+
+    let mut routes = String::from("app");
+    for (tag, operations) in controllers {
+        for operation in operations {
+            routes.push_str(&format!(
+                "\n        .{}(\"{}\", {}::{})\n        .name(\"{}\")",
+                operation.method,
+                operation.route(prefix),
+                controller_name(tag),
+                operation.handler,
+                operation.route_name.escape_default()
+            ));
+        }
+    }
+
+    format!(
+        r#"//! API generated from {source} by Torch CLI
+//!
+//! Regenerated by `torch make from-openapi`; change the spec instead of
+//! editing this file. Handlers live in `src/controllers/`.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use torch_web::{{App, Request, Response, StatusCode}};
+
+{imports}
+pub mod schemas;
+
+/// Register every operation in the spec
+pub fn routes(app: App) -> App {{
+    {routes}
+}}
+
+/// Validation messages keyed by field path, e.g. `owner.name` or `tags[0]`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationErrors {{
+    pub fields: HashMap<String, Vec<String>>,
+}}
+
+impl ValidationErrors {{
+    pub fn add(&mut self, field: &str, message: &str) {{
+        self.fields.entry(field.to_string()).or_default().push(message.to_string());
+    }}
+
+    pub fn is_empty(&self) -> bool {{
+        self.fields.is_empty()
+    }}
+}}
+
+/// Constraints from the spec that serde can't enforce on its own
+pub trait Validate {{
+    /// Add problems to `errors`, naming fields relative to `path`
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors);
+
+    fn validate(&self) -> Result<(), ValidationErrors> {{
+        let mut errors = ValidationErrors::default();
+        self.validate_into("", &mut errors);
+        if errors.is_empty() {{
+            Ok(())
+        }} else {{
+            Err(errors)
+        }}
+    }}
+}}
+
+impl<T: Validate> Validate for Option<T> {{
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {{
+        if let Some(value) = self {{
+            value.validate_into(path, errors);
+        }}
+    }}
+}}
+
+impl<T: Validate> Validate for Vec<T> {{
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {{
+        for (index, item) in self.iter().enumerate() {{
+            item.validate_into(&format!("{{}}[{{}}]", path, index), errors);
+        }}
+    }}
+}}
+
+impl<T: Validate> Validate for HashMap<String, T> {{
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {{
+        for (key, value) in self {{
+            value.validate_into(&field_path(path, key), errors);
+        }}
+    }}
+}}
+
+macro_rules! always_valid {{
+    ($($ty:ty),*) => {{
+        $(impl Validate for $ty {{
+            fn validate_into(&self, _path: &str, _errors: &mut ValidationErrors) {{}}
+        }})*
+    }};
+}}
+
+always_valid!(String, bool, i32, i64, f32, f64, serde_json::Value);
+
+/// `owner` + `name` → `owner.name`
+pub fn field_path(path: &str, field: &str) -> String {{
+    if path.is_empty() {{
+        field.to_string()
+    }} else {{
+        format!("{{}}.{{}}", path, field)
+    }}
+}}
+
+/// Serialize `value` as a JSON response with the given status code
+pub fn json<T: Serialize>(status: u16, value: &T) -> Response {{
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    match Response::with_status(status).json(value) {{
+        Ok(response) => response,
+        Err(error) => Response::with_status(StatusCode::INTERNAL_SERVER_ERROR).body(error.to_string()),
+    }}
+}}
+
+/// Answer 501 for operations that have no implementation yet
+pub fn not_implemented(operation: &str) -> Response {{
+    json(501, &json!({{ "message": format!("{{}} is not implemented yet", operation) }}))
+}}
+
+fn invalid(field: &str, message: &str) -> Response {{
+    let mut errors = ValidationErrors::default();
+    errors.add(field, message);
+    json(422, &json!({{ "message": "The given data was invalid.", "errors": errors.fields }}))
+}}
+
+/// Deserialize and validate a JSON body: 400 for malformed JSON, 422 for invalid data
+pub async fn json_body<T: DeserializeOwned + Validate>(req: &Request) -> Result<T, Response> {{
+    let body: T = serde_json::from_slice(req.body()).map_err(|error| {{
+        if error.is_data() {{
+            invalid("body", &error.to_string())
+        }} else {{
+            json(400, &json!({{ "message": format!("Malformed JSON body: {{}}", error) }}))
+        }}
+    }})?;
+    body.validate()
+        .map_err(|errors| json(422, &json!({{ "message": "The given data was invalid.", "errors": errors.fields }})))?;
+    Ok(body)
+}}
+
+/// Like [`json_body`], but an empty body is `None`
+pub async fn optional_json_body<T: DeserializeOwned + Validate>(req: &Request) -> Result<Option<T>, Response> {{
+    if req.body().iter().all(u8::is_ascii_whitespace) {{
+        return Ok(None);
+    }}
+    json_body(req).await.map(Some)
+}}
+
+/// Parse a path parameter, answering 422 when it has the wrong type
+pub fn path_param<T: FromStr>(req: &Request, name: &str) -> Result<T, Response> {{
+    req.param(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid(name, "is not valid"))
+}}
+
+/// Parse an optional query parameter, answering 422 when it has the wrong type
+pub fn query_param<T: FromStr>(req: &Request, name: &str) -> Result<Option<T>, Response> {{
+    match req.query(name) {{
+        Some(value) => value.parse().map(Some).map_err(|_| invalid(name, "is not valid")),
+        None => Ok(None),
+    }}
+}}
+
+/// Parse a required query parameter, answering 422 when it is missing or has the wrong type
+pub fn required_query_param<T: FromStr>(req: &Request, name: &str) -> Result<T, Response> {{
+    query_param(req, name)?.ok_or_else(|| invalid(name, "is required"))
+}}
+"#,
+        source = source,
+        imports = imports.concat(),
+        routes = routes,
+    )
+}
+
+/// Render a controller skeleton for one tag
+fn controller_content(tag: &str, operations: &[Operation], source: &str) -> ControllerFile {
+    let name = controller_name(tag);
+    let mut schema_types: BTreeSet<String> = BTreeSet::new();
+    let mut handlers = String::new();
+
+    for (index, operation) in operations.iter().enumerate() {
+        if index > 0 {
+            handlers.push('\n');
+        }
+        let uses_request = !operation.parameters.is_empty() || operation.body.is_some();
+        handlers.push_str(&format!("    /// {} {}\n", operation.method.to_uppercase(), operation.path));
+        if let Some(summary) = operation.summary.as_deref().filter(|summary| !summary.is_empty()) {
+            handlers.push_str("    ///\n");
+            handlers.push_str(&format!("    /// {}\n", summary));
+        }
+        handlers.push_str(&format!(
+            "    pub async fn {}({}: Request) -> Response {{\n",
+            operation.handler,
+            if uses_request { "req" } else { "_req" }
+        ));
+
+        let mut inputs = Vec::new();
+        for parameter in &operation.parameters {
+            let (helper, ty) = match (parameter.location.as_str(), parameter.required) {
+                ("path", _) => ("path_param", parameter.ty.clone()),
+                (_, true) => ("required_query_param", parameter.ty.clone()),
+                _ => ("query_param", format!("Option<{}>", parameter.ty)),
+            };
+            handlers.push_str(&format!(
+                "        let {}: {} = match openapi::{}(&req, \"{}\") {{\n",
+                parameter.ident, ty, helper, parameter.name
+            ));
+            handlers.push_str("            Ok(value) => value,\n");
+            handlers.push_str("            Err(response) => return response,\n");
+            handlers.push_str("        };\n");
+            inputs.push(parameter.ident.clone());
+        }
+        if let Some((ty, required)) = &operation.body {
+            collect_schema_types(&ty.name, &mut schema_types);
+            let (helper, ty) = if *required {
+                ("json_body", ty.name.clone())
+            } else {
+                ("optional_json_body", format!("Option<{}>", ty.name))
+            };
+            handlers.push_str(&format!("        let body: {} = match openapi::{}(&req).await {{\n", ty, helper));
+            handlers.push_str("            Ok(body) => body,\n");
+            handlers.push_str("            Err(response) => return response,\n");
+            handlers.push_str("        };\n");
+            inputs.push("body".to_string());
+        }
+        if uses_request {
+            handlers.push('\n');
+        }
+
+        let todo = match &operation.response {
+            Some((code, Some(ty))) => format!("respond with openapi::json({}, &{})", code, ty),
+            Some((code, None)) => format!("respond with {}", code),
+            None => "respond".to_string(),
+        };
+        handlers.push_str(&format!("        // TODO: {}\n", todo));
+        match inputs.len() {
+            0 => {}
+            1 => handlers.push_str(&format!("        let _ = {};\n", inputs[0])),
+            _ => handlers.push_str(&format!("        let _ = ({});\n", inputs.join(", "))),
+        }
+        handlers.push_str(&format!("        openapi::not_implemented(\"{}\")\n", operation.route_name.escape_default()));
+        handlers.push_str("    }\n");
+    }
+
+    let schema_import = match schema_types.len() {
+        0 => String::new(),
+        1 => format!("use crate::openapi::schemas::{};\n", schema_types.iter().next().unwrap()),
+        _ => format!("use crate::openapi::schemas::{{{}}};\n", schema_types.into_iter().collect::<Vec<_>>().join(", ")),
+    };
+
+    let content = format!(
+        r#"//! {name} - Generated from {source} by Torch CLI
+//!
+//! Routes are registered by `crate::openapi::routes`, which is regenerated
+//! with the spec. This file is only created when missing.
+
+use torch_web::{{Request, Response}};
+
+use crate::openapi;
+{schema_import}
+pub struct {name};
+
+impl {name} {{
+{handlers}}}
+"#,
+    );
+
+    ControllerFile {
+        module: controller_module(tag),
+        handlers: operations.iter().map(|operation| operation.handler.clone()).collect(),
+        name,
+        content,
+    }
+}
+
+/// Generated type names inside a type such as `Vec<Pet>`
+fn collect_schema_types(ty: &str, names: &mut BTreeSet<String>) {
+    for word in ty.split(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != ':') {
+        if !word.is_empty()
+            && !word.contains("::")
+            && !matches!(word, "Vec" | "Option" | "HashMap" | "String" | "bool" | "i32" | "i64" | "f32" | "f64")
+        {
+            names.insert(word.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PETSTORE: &str = r##"
+openapi: 3.0.3
+info:
+  title: Petstore
+  version: 1.0.0
+servers:
+  - url: https://petstore.example.com/v1
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      summary: List all pets
+      tags: [pets]
+      parameters:
+        - name: limit
+          in: query
+          schema: { type: integer, format: int32, maximum: 100 }
+      responses:
+        "200":
+          description: A page of pets
+          content:
+            application/json:
+              schema: { type: array, items: { $ref: "#/components/schemas/Pet" } }
+    post:
+      operationId: createPet
+      tags: [pets]
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema: { $ref: "#/components/schemas/NewPet" }
+      responses:
+        "201":
+          description: Created
+          content:
+            application/json:
+              schema: { $ref: "#/components/schemas/Pet" }
+  /pets/{petId}:
+    parameters:
+      - { name: petId, in: path, required: true, schema: { type: integer } }
+    delete:
+      operationId: deletePet
+      tags: [pets]
+      responses:
+        "204": { description: Deleted }
+components:
+  schemas:
+    NewPet:
+      type: object
+      required: [name]
+      properties:
+        name: { type: string, minLength: 1, maxLength: 50 }
+        type: { type: string, enum: [cat, dog] }
+        ownerEmail: { type: string, format: email }
+    Pet:
+      allOf:
+        - $ref: "#/components/schemas/NewPet"
+        - type: object
+          required: [id]
+          properties:
+            id: { type: integer }
+"##;
+
+    #[test]
+    fn test_generates_typed_schemas_with_validation() {
+        let spec = parse_spec(PETSTORE).unwrap();
+        let api = generate(&spec, "petstore.yaml", None).unwrap();
+
+        assert!(api.schemas.contains("pub struct NewPet {"));
+        assert!(api.schemas.contains("    pub name: String,"));
+        assert!(api.schemas.contains("    pub r#type: Option<NewPetType>,"));
+        assert!(api.schemas.contains("    #[serde(rename = \"ownerEmail\")]\n"));
+        assert!(api.schemas.contains("pub enum NewPetType {"));
+        assert!(api.schemas.contains("if value.chars().count() > 50 {"));
+        assert!(api.schemas.contains("if !value.contains('@') {"));
+        // allOf merges the referenced properties with its own
+        assert!(api.schemas.contains("pub struct Pet {"));
+        assert!(api.schemas.contains("    pub id: i64,"));
+    }
+
+    #[test]
+    fn test_generates_routes_and_controller_skeletons() {
+        let spec = parse_spec(PETSTORE).unwrap();
+        let api = generate(&spec, "petstore.yaml", None).unwrap();
+
+        assert_eq!(api.operations, 3);
+        assert!(api.module.contains(".get(\"/v1/pets\", PetsController::list_pets)"));
+        assert!(api.module.contains(".delete(\"/v1/pets/:petId\", PetsController::delete_pet)"));
+        assert!(api.module.contains(".name(\"createPet\")"));
+
+        let controller = &api.controllers[0];
+        assert_eq!(controller.module, "pets_controller");
+        assert_eq!(controller.handlers, vec!["list_pets", "create_pet", "delete_pet"]);
+        assert!(controller.content.contains("let limit: Option<i32> = match openapi::query_param(&req, \"limit\")"));
+        assert!(controller.content.contains("let pet_id: i64 = match openapi::path_param(&req, \"petId\")"));
+        assert!(controller.content.contains("let body: NewPet = match openapi::json_body(&req).await"));
+        assert!(controller.content.contains("// TODO: respond with openapi::json(201, &Pet)"));
+        assert!(controller.content.contains("use crate::openapi::schemas::NewPet;"));
+
+        let prefixed = generate(&spec, "petstore.yaml", Some("/api/")).unwrap();
+        assert!(prefixed.module.contains("\"/api/pets\""));
+    }
+
+    #[test]
+    fn test_rejects_swagger_2() {
+        assert!(parse_spec("swagger: \"2.0\"\npaths: {}").unwrap_err().contains("Swagger 2.0"));
+        assert!(parse_spec("{\"openapi\": \"3.1.0\", \"paths\": {}}").is_ok());
+    }
+}
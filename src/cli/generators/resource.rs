@@ -0,0 +1,807 @@
+//! CRUD resource scaffolding for `torch make resource`
+//!
+//! Every file generated here only uses the public `torch_web` API plus the
+//! `serde`, `serde_json` and `tokio` dependencies that `torch new` adds, so a
+//! fresh project compiles as soon as the modules are registered.
+
+use crate::cli::templates::{pascal_case, plural, snake_case};
+
+/// Column type accepted by `--fields name:type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    String,
+    Text,
+    Integer,
+    Float,
+    Boolean,
+}
+
+impl FieldKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "string" | "str" => Some(Self::String),
+            "text" => Some(Self::Text),
+            "int" | "integer" | "i64" => Some(Self::Integer),
+            "float" | "f64" | "decimal" => Some(Self::Float),
+            "bool" | "boolean" => Some(Self::Boolean),
+            _ => None,
+        }
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            Self::String | Self::Text => "String",
+            Self::Integer => "i64",
+            Self::Float => "f64",
+            Self::Boolean => "bool",
+        }
+    }
+
+    /// Schema builder call that adds a column of this kind
+    fn schema_column(self, name: &str) -> String {
+        match self {
+            Self::String => format!("table.string(\"{}\", None);", name),
+            Self::Text => format!("table.text(\"{}\");", name),
+            Self::Integer => format!("table.big_integer(\"{}\");", name),
+            Self::Float => format!("table.float(\"{}\");", name),
+            Self::Boolean => format!("table.boolean(\"{}\").default(\"false\");", name),
+        }
+    }
+
+    /// Form-encoded value used by the generated tests
+    fn sample(self) -> &'static str {
+        match self {
+            Self::String => "Example",
+            Self::Text => "Example+text",
+            Self::Integer => "42",
+            Self::Float => "1.5",
+            Self::Boolean => "on",
+        }
+    }
+}
+
+/// A single model field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceField {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+/// Names derived from the resource name, e.g. `BlogPost`
+#[derive(Debug, Clone)]
+pub struct Resource {
+    /// `BlogPost`
+    pub model: String,
+    /// `blog_post`
+    pub snake: String,
+    /// `blog_posts` (table, route prefix and view directory)
+    pub plural: String,
+    pub fields: Vec<ResourceField>,
+}
+
+impl Resource {
+    pub fn new(name: &str, fields: Vec<ResourceField>) -> Self {
+        let model = pascal_case(&snake_case(name));
+        let snake = snake_case(&model);
+        let plural = plural(&snake);
+        Self { model, snake, plural, fields }
+    }
+
+    pub fn model_path(&self) -> String {
+        format!("src/models/{}.rs", self.snake)
+    }
+
+    pub fn request_path(&self) -> String {
+        format!("src/requests/{}_request.rs", self.snake)
+    }
+
+    pub fn controller_path(&self) -> String {
+        format!("src/controllers/{}_controller.rs", self.snake)
+    }
+
+    pub fn view_dir(&self) -> String {
+        format!("templates/{}", self.plural)
+    }
+}
+
+/// Parse `title:string,body:text,published:bool`
+pub fn parse_fields(spec: &str) -> Result<Vec<ResourceField>, String> {
+    let mut fields = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (name, kind) = entry.split_once(':').unwrap_or((entry, "string"));
+        let name = snake_case(name.trim());
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("Invalid field name '{}'", entry));
+        }
+        if name == "id" {
+            return Err("The id field is generated automatically".to_string());
+        }
+        let kind = FieldKind::parse(kind.trim())
+            .ok_or_else(|| format!("Unknown field type '{}' (expected string, text, int, float or bool)", kind))?;
+        fields.push(ResourceField { name, kind });
+    }
+
+    if fields.is_empty() {
+        return Err("A resource needs at least one field".to_string());
+    }
+    Ok(fields)
+}
+
+/// Generate the ORM model backed by the resource's table
+pub fn model_content(resource: &Resource) -> String {
+    let Resource { model, snake, plural, fields } = resource;
+    let columns = std::iter::once("id")
+        .chain(fields.iter().map(|field| field.name.as_str()))
+        .collect::<Vec<_>>();
+
+    let mut content = String::new();
+    content.push_str(&format!("//! {} - Generated by Torch CLI\n\n", model));
+    content.push_str("use serde::{Deserialize, Serialize};\n");
+    content.push_str("use torch_web::orm::{impl_from_row, impl_model};\n\n");
+    content.push_str(&format!("use crate::requests::{}_request::{}Request;\n\n", snake, model));
+
+    content.push_str(&format!("/// A single {}, stored in the `{}` table\n", snake.replace('_', " "), plural));
+    content.push_str("#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]\n");
+    content.push_str(&format!("pub struct {} {{\n", model));
+    content.push_str("    pub id: Option<i64>,\n");
+    for field in fields {
+        content.push_str(&format!("    pub {}: {},\n", field.name, field.kind.rust_type()));
+    }
+    content.push_str("}\n\n");
+
+    // The timestamps the migration adds are left to the database
+    content.push_str(&format!(
+        "impl_model!(\n    {},\n    table = \"{}\",\n    primary_key = \"id\",\n    primary_key_type = i64,\n    columns = [{}],\n);\n",
+        model,
+        plural,
+        columns.iter().map(|column| format!("\"{}\"", column)).collect::<Vec<_>>().join(", "),
+    ));
+    content.push_str(&format!("impl_from_row!({}, {{ {} }});\n\n", model, columns.join(", ")));
+
+    content.push_str(&format!("impl {} {{\n", model));
+    content.push_str("    /// Copy validated input onto the model\n");
+    content.push_str(&format!("    pub fn fill(&mut self, input: {}Request) {{\n", model));
+    for field in fields {
+        content.push_str(&format!("        self.{0} = input.{0};\n", field.name));
+    }
+    content.push_str("    }\n");
+    content.push_str("}\n\n");
+
+    content.push_str(&format!("impl From<{}Request> for {} {{\n", model, model));
+    content.push_str(&format!("    fn from(input: {}Request) -> Self {{\n", model));
+    content.push_str(&format!("        let mut {} = Self::default();\n", snake));
+    content.push_str(&format!("        {}.fill(input);\n", snake));
+    content.push_str(&format!("        {}\n", snake));
+    content.push_str("    }\n");
+    content.push_str("}\n");
+
+    content
+}
+
+/// Generate the validated input struct
+pub fn request_content(resource: &Resource) -> String {
+    let Resource { model, fields, .. } = resource;
+    let mut content = String::new();
+    content.push_str(&format!("//! {}Request - Generated by Torch CLI\n\n", model));
+    content.push_str("use std::collections::HashMap;\n\n");
+    content.push_str("use serde::{Deserialize, Serialize};\n");
+    content.push_str("use torch_web::extractors::{Form, FromRequest};\n");
+    content.push_str("use torch_web::Request;\n\n");
+
+    content.push_str(&format!("/// Validated input for creating or updating a {}\n", resource.snake.replace('_', " ")));
+    content.push_str("#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]\n");
+    content.push_str(&format!("pub struct {}Request {{\n", model));
+    for field in fields {
+        content.push_str(&format!("    pub {}: {},\n", field.name, field.kind.rust_type()));
+    }
+    content.push_str("}\n\n");
+
+    content.push_str(&format!("impl {}Request {{\n", model));
+    content.push_str("    /// Read a form-encoded or JSON body and validate it\n");
+    content.push_str("    pub async fn from_request(req: Request) -> Result<Self, ValidationErrors> {\n");
+    content.push_str("        Self::validate(&read_input(req).await)\n");
+    content.push_str("    }\n\n");
+    content.push_str("    /// Validate raw input fields\n");
+    content.push_str("    pub fn validate(input: &HashMap<String, String>) -> Result<Self, ValidationErrors> {\n");
+    content.push_str("        let mut errors = ValidationErrors::default();\n");
+    content.push_str("        let value = |name: &str| input.get(name).map(|v| v.trim()).unwrap_or(\"\");\n\n");
+    for field in fields {
+        let name = &field.name;
+        let label = name.replace('_', " ");
+        match field.kind {
+            FieldKind::String | FieldKind::Text => {
+                content.push_str(&format!("        let {} = value(\"{}\").to_string();\n", name, name));
+                content.push_str(&format!("        if {}.is_empty() {{\n", name));
+                content.push_str(&format!("            errors.add(\"{}\", \"The {} field is required.\");\n", name, label));
+                content.push_str("        }\n");
+                if field.kind == FieldKind::String {
+                    content.push_str(&format!("        if {}.chars().count() > 255 {{\n", name));
+                    content.push_str(&format!("            errors.add(\"{}\", \"The {} may not be longer than 255 characters.\");\n", name, label));
+                    content.push_str("        }\n");
+                }
+            }
+            FieldKind::Integer | FieldKind::Float => {
+                let ty = field.kind.rust_type();
+                let noun = if field.kind == FieldKind::Integer { "an integer" } else { "a number" };
+                content.push_str(&format!("        let {} = value(\"{}\").parse::<{}>().unwrap_or_else(|_| {{\n", name, name, ty));
+                content.push_str(&format!("            errors.add(\"{}\", \"The {} must be {}.\");\n", name, label, noun));
+                content.push_str(&format!("            {}::default()\n", ty));
+                content.push_str("        });\n");
+            }
+            FieldKind::Boolean => {
+                content.push_str(&format!("        let {} = matches!(value(\"{}\"), \"1\" | \"on\" | \"true\" | \"yes\");\n", name, name));
+            }
+        }
+        content.push('\n');
+    }
+    content.push_str("        if errors.is_empty() {\n");
+    content.push_str("            Ok(Self {\n");
+    for field in fields {
+        content.push_str(&format!("                {},\n", field.name));
+    }
+    content.push_str("            })\n");
+    content.push_str("        } else {\n");
+    content.push_str("            Err(errors)\n");
+    content.push_str("        }\n");
+    content.push_str("    }\n");
+    content.push_str("}\n\n");
+
+    content.push_str(VALIDATION_ERRORS);
+    content
+}
+
+pub(super) const VALIDATION_ERRORS: &str = r#"/// Validation messages keyed by field name
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationErrors {
+    pub fields: HashMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.fields.entry(field.to_string()).or_default().push(message.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// All messages, sorted by field name
+    pub fn messages(&self) -> Vec<String> {
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        fields.into_iter().flat_map(|(_, messages)| messages.clone()).collect()
+    }
+}
+
+/// Collect body fields from a JSON or form-encoded request
+async fn read_input(req: Request) -> HashMap<String, String> {
+    let is_json = req
+        .header("content-type")
+        .map_or(false, |content_type| content_type.starts_with("application/json"));
+
+    if is_json {
+        req.json::<HashMap<String, serde_json::Value>>()
+            .await
+            .map(|values| {
+                values
+                    .into_iter()
+                    .map(|(key, value)| match value {
+                        serde_json::Value::String(text) => (key, text),
+                        other => (key, other.to_string()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    } else {
+        Form::<HashMap<String, String>>::from_request(req)
+            .await
+            .map(|(Form(fields), _)| fields)
+            .unwrap_or_default()
+    }
+}
+"#;
+
+/// Tests for rejected input, skipped when every field is a checkbox
+fn validation_tests(resource: &Resource) -> String {
+    let Some(required) = resource.fields.iter().find(|field| field.kind != FieldKind::Boolean) else {
+        return String::new();
+    };
+
+    format!(
+        r#"
+    #[tokio::test]
+    async fn test_store_rejects_invalid_input() {{
+        let app = {model}Controller::routes(App::new());
+
+        let response = app.handle_request(form_request(Method::POST, "/{plural}", "{field}=")).await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }}
+
+    #[test]
+    fn test_validation_reports_missing_fields() {{
+        let errors = {model}Request::validate(&Default::default()).unwrap_err();
+        assert!(errors.fields.contains_key("{field}"));
+    }}
+"#,
+        model = resource.model,
+        plural = resource.plural,
+        field = required.name,
+    )
+}
+
+/// Generate the controller with all CRUD handlers, its routes and tests
+pub fn controller_content(resource: &Resource) -> String {
+    let Resource { model, snake, plural, fields } = resource;
+    let valid_body = fields
+        .iter()
+        .map(|field| format!("{}={}", field.name, field.kind.sample()))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut content = format!(
+        r#"//! {model}Controller - Generated by Torch CLI
+
+use torch_web::ember::{{ember, EmberData}};
+use torch_web::orm::{{Model, OrmError}};
+use torch_web::{{App, Request, Response, StatusCode}};
+
+use crate::models::{snake}::{model};
+use crate::requests::{snake}_request::{{{model}Request, ValidationErrors}};
+
+pub struct {model}Controller;
+
+impl {model}Controller {{
+    /// Register the `/{plural}` routes
+    pub fn routes(app: App) -> App {{
+        app.get("/{plural}", Self::index)
+            .name("{plural}.index")
+            .get("/{plural}/create", Self::create)
+            .name("{plural}.create")
+            .post("/{plural}", Self::store)
+            .name("{plural}.store")
+            .get("/{plural}/:id", Self::show)
+            .name("{plural}.show")
+            .get("/{plural}/:id/edit", Self::edit)
+            .name("{plural}.edit")
+            .post("/{plural}/:id", Self::update)
+            .name("{plural}.update")
+            .put("/{plural}/:id", Self::update)
+            .post("/{plural}/:id/delete", Self::destroy)
+            .name("{plural}.destroy")
+            .delete("/{plural}/:id", Self::destroy)
+    }}
+
+    /// GET /{plural}
+    pub async fn index(_req: Request) -> Response {{
+        let {plural} = match {model}::all().await {{
+            Ok({plural}) => {plural},
+            Err(err) => return database_error(err),
+        }};
+
+        ember("{plural}/index", EmberData::new().with("{plural}", to_value(&{plural}))).await
+    }}
+
+    /// GET /{plural}/create
+    pub async fn create(_req: Request) -> Response {{
+        form("{plural}/create", &{model}Request::default(), &ValidationErrors::default()).await
+    }}
+
+    /// POST /{plural}
+    pub async fn store(req: Request) -> Response {{
+        let mut {snake} = match {model}Request::from_request(req).await {{
+            Ok(input) => {model}::from(input),
+            Err(errors) => return form("{plural}/create", &{model}Request::default(), &errors).await,
+        }};
+
+        match {snake}.save().await {{
+            Ok(()) => Response::redirect_found(&format!("/{plural}/{{}}", {snake}.id.unwrap_or_default())),
+            Err(err) => database_error(err),
+        }}
+    }}
+
+    /// GET /{plural}/:id
+    pub async fn show(req: Request) -> Response {{
+        let {snake} = match find(&req).await {{
+            Ok({snake}) => {snake},
+            Err(response) => return response,
+        }};
+
+        ember("{plural}/show", EmberData::new().with("{snake}", to_value(&{snake}))).await
+    }}
+
+    /// GET /{plural}/:id/edit
+    pub async fn edit(req: Request) -> Response {{
+        let {snake} = match find(&req).await {{
+            Ok({snake}) => {snake},
+            Err(response) => return response,
+        }};
+
+        form("{plural}/edit", &{snake}, &ValidationErrors::default()).await
+    }}
+
+    /// POST|PUT /{plural}/:id
+    pub async fn update(req: Request) -> Response {{
+        let mut {snake} = match find(&req).await {{
+            Ok({snake}) => {snake},
+            Err(response) => return response,
+        }};
+
+        match {model}Request::from_request(req).await {{
+            Ok(input) => {{
+                {snake}.fill(input);
+                match {snake}.save().await {{
+                    Ok(()) => Response::redirect_found(&format!("/{plural}/{{}}", {snake}.id.unwrap_or_default())),
+                    Err(err) => database_error(err),
+                }}
+            }}
+            Err(errors) => form("{plural}/edit", &{snake}, &errors).await,
+        }}
+    }}
+
+    /// POST /{plural}/:id/delete, DELETE /{plural}/:id
+    pub async fn destroy(req: Request) -> Response {{
+        let mut {snake} = match find(&req).await {{
+            Ok({snake}) => {snake},
+            Err(response) => return response,
+        }};
+
+        match {snake}.delete().await {{
+            Ok(()) => Response::redirect_found("/{plural}"),
+            Err(err) => database_error(err),
+        }}
+    }}
+}}
+
+/// The {singular} named by the `:id` route parameter, or the response to send
+async fn find(req: &Request) -> Result<{model}, Response> {{
+    let Some(id) = req.param("id").and_then(|id| id.parse().ok()) else {{
+        return Err(Response::not_found());
+    }};
+
+    match {model}::find(id).await {{
+        Ok(Some({snake})) => Ok({snake}),
+        Ok(None) => Err(Response::not_found()),
+        Err(err) => Err(database_error(err)),
+    }}
+}}
+
+fn database_error(err: OrmError) -> Response {{
+    eprintln!("{plural}: {{}}", err);
+    Response::internal_error()
+}}
+
+/// Render a create/edit form, answering 422 when validation failed
+async fn form<T: serde::Serialize>(view: &str, {snake}: &T, errors: &ValidationErrors) -> Response {{
+    let data = EmberData::new()
+        .with("{snake}", to_value({snake}))
+        .with("errors", errors.messages());
+    let mut response = ember(view, data).await;
+    if !errors.is_empty() {{
+        *response.status_code_mut() = StatusCode::UNPROCESSABLE_ENTITY;
+    }}
+    response
+}}
+
+fn to_value<T: serde::Serialize>(value: &T) -> serde_json::Value {{
+    serde_json::to_value(value).unwrap_or_default()
+}}
+"#,
+        model = model,
+        snake = snake,
+        plural = plural,
+        singular = snake.replace('_', " "),
+    );
+
+    content.push_str(&format!(
+        r#"
+#[cfg(test)]
+mod tests {{
+    use super::*;
+    use torch_web::orm::connection::initialize_pool;
+    use torch_web::orm::OrmConfig;
+    use torch_web::Method;
+
+    fn form_request(method: Method, uri: &str, body: &str) -> Request {{
+        Request::mock(method, uri)
+            .with_header("content-type", "application/x-www-form-urlencoded")
+            .with_body(body)
+    }}
+
+    fn location(response: &Response) -> &str {{
+        response.headers().get("location").and_then(|value| value.to_str().ok()).unwrap_or("")
+    }}
+
+    #[tokio::test]
+    async fn test_show_answers_404_for_a_malformed_id() {{
+        let app = {model}Controller::routes(App::new());
+
+        let response = app.handle_request(Request::mock(Method::GET, "/{plural}/latest")).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }}
+
+    /// Runs against the database in `DATABASE_URL` once `torch migrate` has
+    /// created the `{plural}` table: `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_store_update_and_destroy() {{
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        initialize_pool(OrmConfig {{ database_url, ..Default::default() }}).await.unwrap();
+        let app = {model}Controller::routes(App::new());
+
+        let response = app.handle_request(form_request(Method::POST, "/{plural}", "{valid_body}")).await;
+        assert_eq!(response.status_code(), StatusCode::FOUND);
+        let path = location(&response).to_string();
+
+        let response = app.handle_request(form_request(Method::PUT, &path, "{valid_body}")).await;
+        assert_eq!(location(&response), path);
+
+        let response = app.handle_request(Request::mock(Method::DELETE, &path)).await;
+        assert_eq!(location(&response), "/{plural}");
+
+        let response = app.handle_request(Request::mock(Method::GET, &path)).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }}
+{validation_tests}}}
+"#,
+        model = model,
+        plural = plural,
+        valid_body = valid_body,
+        validation_tests = validation_tests(resource),
+    ));
+
+    content
+}
+
+/// Generate the create-table migration
+pub fn migration_content(resource: &Resource, name: &str, version: &str) -> String {
+    let columns: Vec<String> = resource
+        .fields
+        .iter()
+        .map(|field| field.kind.schema_column(&field.name))
+        .collect();
+    super::generate_create_table_migration_with_columns(name, version, &resource.plural, &columns)
+}
+
+/// Routes snippet printed after generation
+pub fn routes_snippet(resource: &Resource) -> String {
+    format!(
+        "use crate::controllers::{snake}_controller::{model}Controller;\n\n\
+         let app = {model}Controller::routes(app);",
+        snake = resource.snake,
+        model = resource.model,
+    )
+}
+
+/// Generate the Ember views as `(file name, content)` pairs
+pub fn view_contents(resource: &Resource) -> Vec<(&'static str, String)> {
+    vec![
+        ("index.ember", index_view(resource)),
+        ("show.ember", show_view(resource)),
+        ("create.ember", form_view(resource, false)),
+        ("edit.ember", form_view(resource, true)),
+        ("_form.ember", form_fields(resource)),
+    ]
+}
+
+fn title(name: &str) -> String {
+    pascal_case(name).chars().fold(String::new(), |mut title, ch| {
+        if ch.is_uppercase() && !title.is_empty() {
+            title.push(' ');
+        }
+        title.push(ch);
+        title
+    })
+}
+
+fn index_view(resource: &Resource) -> String {
+    let Resource { snake, plural, fields, .. } = resource;
+    let mut headers = String::new();
+    let mut cells = String::new();
+    for field in fields {
+        headers.push_str(&format!("                <th>{}</th>\n", field.name.replace('_', " ")));
+        cells.push_str(&format!("                <td>{{{{ ${}.{} }}}}</td>\n", snake, field.name));
+    }
+
+    format!(
+        r#"@extends('layout')
+
+@section('title', '{title}')
+
+@section('content')
+    <h1>{title}</h1>
+    <p><a href="/{plural}/create">New {singular}</a></p>
+
+    @if(${plural})
+        <table>
+            <tr>
+                <th>id</th>
+{headers}                <th></th>
+            </tr>
+        @foreach(${plural} as ${snake})
+            <tr>
+                <td>{{{{ ${snake}.id }}}}</td>
+{cells}                <td><a href="/{plural}/{{{{ ${snake}.id }}}}">Show</a> <a href="/{plural}/{{{{ ${snake}.id }}}}/edit">Edit</a></td>
+            </tr>
+        @endforeach
+        </table>
+    @else
+        <p>No {plural_words} yet.</p>
+    @endif
+@endsection
+"#,
+        title = title(&resource.plural),
+        singular = snake.replace('_', " "),
+        plural_words = plural.replace('_', " "),
+        plural = plural,
+        snake = snake,
+        headers = headers,
+        cells = cells,
+    )
+}
+
+fn show_view(resource: &Resource) -> String {
+    let Resource { snake, plural, fields, .. } = resource;
+    let mut rows = String::new();
+    for field in fields {
+        rows.push_str(&format!(
+            "        <dt>{}</dt>\n        <dd>{{{{ ${}.{} }}}}</dd>\n",
+            field.name.replace('_', " "),
+            snake,
+            field.name
+        ));
+    }
+
+    format!(
+        r#"@extends('layout')
+
+@section('title', '{title}')
+
+@section('content')
+    <h1>{title} #{{{{ ${snake}.id }}}}</h1>
+
+    <dl>
+{rows}    </dl>
+
+    <p>
+        <a href="/{plural}/{{{{ ${snake}.id }}}}/edit">Edit</a>
+        <a href="/{plural}">Back</a>
+    </p>
+    <form method="POST" action="/{plural}/{{{{ ${snake}.id }}}}/delete">
+        <button type="submit">Delete</button>
+    </form>
+@endsection
+"#,
+        title = title(snake),
+        snake = snake,
+        plural = plural,
+        rows = rows,
+    )
+}
+
+fn form_view(resource: &Resource, editing: bool) -> String {
+    let Resource { snake, plural, .. } = resource;
+    let (heading, action) = if editing {
+        (format!("Edit {}", snake.replace('_', " ")), format!("/{}/{{{{ ${}.id }}}}", plural, snake))
+    } else {
+        (format!("New {}", snake.replace('_', " ")), format!("/{}", plural))
+    };
+
+    format!(
+        r#"@extends('layout')
+
+@section('title', '{heading}')
+
+@section('content')
+    <h1>{heading}</h1>
+
+    @if($errors)
+        <ul class="errors">
+        @foreach($errors as $error)
+            <li>{{{{ $error }}}}</li>
+        @endforeach
+        </ul>
+    @endif
+
+    <form method="POST" action="{action}">
+        @include('{plural}/_form')
+        <button type="submit">Save</button>
+    </form>
+
+    <p><a href="/{plural}">Back</a></p>
+@endsection
+"#,
+        heading = heading,
+        action = action,
+        plural = plural,
+    )
+}
+
+fn form_fields(resource: &Resource) -> String {
+    let Resource { snake, fields, .. } = resource;
+    let mut content = String::new();
+    for field in fields {
+        let name = &field.name;
+        let label = name.replace('_', " ");
+        let value = format!("{{{{ ${}.{} }}}}", snake, name);
+        let input = match field.kind {
+            FieldKind::String => format!(r#"<input type="text" id="{0}" name="{0}" value="{1}" maxlength="255" required>"#, name, value),
+            FieldKind::Text => format!(r#"<textarea id="{0}" name="{0}" required>{1}</textarea>"#, name, value),
+            FieldKind::Integer => format!(r#"<input type="number" id="{0}" name="{0}" value="{1}" step="1" required>"#, name, value),
+            FieldKind::Float => format!(r#"<input type="number" id="{0}" name="{0}" value="{1}" step="any" required>"#, name, value),
+            FieldKind::Boolean => format!(
+                r#"<input type="checkbox" id="{0}" name="{0}" value="1" @if(${1}.{0}) checked @endif>"#,
+                name, snake
+            ),
+        };
+        content.push_str(&format!(
+            "<div class=\"field\">\n    <label for=\"{}\">{}</label>\n    {}\n</div>\n",
+            name, label, input
+        ));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> Resource {
+        Resource::new("BlogPost", parse_fields("title:string, body:text, views:int, published:bool").unwrap())
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let fields = parse_fields("title,rating:float").unwrap();
+        assert_eq!(fields[0], ResourceField { name: "title".into(), kind: FieldKind::String });
+        assert_eq!(fields[1].kind, FieldKind::Float);
+
+        assert!(parse_fields("").is_err());
+        assert!(parse_fields("id:int").is_err());
+        assert!(parse_fields("title:uuid").is_err());
+    }
+
+    #[test]
+    fn test_resource_names() {
+        let resource = post();
+        assert_eq!(resource.model, "BlogPost");
+        assert_eq!(resource.plural, "blog_posts");
+        assert_eq!(resource.controller_path(), "src/controllers/blog_post_controller.rs");
+        assert_eq!(resource.view_dir(), "templates/blog_posts");
+    }
+
+    #[test]
+    fn test_generated_sources_use_typed_fields() {
+        let resource = post();
+
+        let model = model_content(&resource);
+        assert!(model.contains("pub views: i64,"));
+        assert!(model.contains("table = \"blog_posts\","));
+        assert!(model.contains("columns = [\"id\", \"title\", \"body\", \"views\", \"published\"],"));
+        assert!(model.contains("impl_from_row!(BlogPost, { id, title, body, views, published });"));
+
+        let request = request_content(&resource);
+        assert!(request.contains("value(\"views\").parse::<i64>()"));
+        assert!(request.contains("errors.add(\"title\", \"The title field is required.\")"));
+
+        let controller = controller_content(&resource);
+        assert!(controller.contains(".get(\"/blog_posts/create\", Self::create)"));
+        assert!(controller.contains("\"title=Example&body=Example+text&views=42&published=on\""));
+        assert!(controller.contains("fn test_store_rejects_invalid_input"));
+        assert!(controller.contains("match BlogPost::find(id).await {"));
+
+        let migration = migration_content(&resource, "create_blog_posts_table", "2024_01_01_000000");
+        assert!(migration.contains("Schema::create_table(\"blog_posts\", |table| {"));
+        assert!(migration.contains("table.boolean(\"published\").default(\"false\");"));
+    }
+
+    #[test]
+    fn test_views_reference_model_fields() {
+        let views = view_contents(&post());
+        let index = &views.iter().find(|(name, _)| *name == "index.ember").unwrap().1;
+        assert!(index.contains("@foreach($blog_posts as $blog_post)"));
+        assert!(index.contains("{{ $blog_post.title }}"));
+
+        let form = &views.iter().find(|(name, _)| *name == "_form.ember").unwrap().1;
+        assert!(form.contains("@if($blog_post.published) checked @endif"));
+    }
+}
@@ -34,9 +34,27 @@ pub enum Commands {
     New {
         /// Name of the application
         name: String,
-        /// Use minimal template (no examples)
+        /// Starting point to generate
+        #[arg(long, value_enum, default_value_t = commands::new::Template::Full)]
+        template: commands::new::Template,
+        /// Use minimal template (no examples), same as --template minimal
         #[arg(long)]
         minimal: bool,
+        /// Skip the `cargo check` run that verifies the generated project
+        #[arg(long)]
+        no_check: bool,
+        /// Depend on a local torch-web checkout instead of crates.io
+        #[arg(long, conflicts_with = "git")]
+        path: Option<std::path::PathBuf>,
+        /// Depend on torch-web from a git repository
+        #[arg(long)]
+        git: Option<String>,
+        /// Git branch to use with --git
+        #[arg(long, requires = "git", conflicts_with = "rev")]
+        branch: Option<String>,
+        /// Git revision to use with --git
+        #[arg(long, requires = "git")]
+        rev: Option<String>,
     },
     /// Initialize project files and configuration
     Init {
@@ -77,6 +95,12 @@ pub enum Commands {
     Migrate {
         #[command(subcommand)]
         operation: Option<MigrateOperation>,
+        /// Print the SQL pending migrations would run, through the app's `migrate` command
+        #[arg(long)]
+        pretend: bool,
+        /// Hold a database lock while migrating, through the app's `migrate` command
+        #[arg(long, conflicts_with = "pretend")]
+        isolated: bool,
     },
     /// Route operations
     Route {
@@ -103,6 +127,11 @@ pub enum Commands {
         #[command(subcommand)]
         operation: QueueOperation,
     },
+    /// Mail operations
+    Mail {
+        #[command(subcommand)]
+        operation: MailOperation,
+    },
     /// Testing operations
     Test {
         /// Run specific test
@@ -139,6 +168,75 @@ pub enum Commands {
         #[command(subcommand)]
         operation: ScheduleOperation,
     },
+    /// Deployment operations
+    Deploy {
+        #[command(subcommand)]
+        operation: DeployOperation,
+    },
+    /// Application key operations
+    Key {
+        #[command(subcommand)]
+        operation: KeyOperation,
+    },
+    /// Load test a route on the running dev server
+    Bench {
+        /// Route to request (e.g., /api/users) or a full http:// URL
+        target: String,
+        /// Number of concurrent keep-alive connections
+        #[arg(short, long, default_value = "50")]
+        connections: usize,
+        /// How long to run (e.g., 30s, 2m)
+        #[arg(short, long, default_value = "10s", value_parser = commands::bench::parse_duration)]
+        duration: std::time::Duration,
+        /// Port of the server under test
+        #[arg(short, long, default_value = "3000")]
+        port: u16,
+        /// Host of the server under test
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// HTTP method
+        #[arg(short = 'X', long, default_value = "GET")]
+        method: String,
+        /// Extra request header (e.g., "Authorization: Bearer token")
+        #[arg(short = 'H', long = "header")]
+        headers: Vec<String>,
+        /// Request body
+        #[arg(long)]
+        body: Option<String>,
+        /// Save results under a name (defaults to the current git branch)
+        #[arg(long, num_args = 0..=1, default_missing_value = "")]
+        save: Option<String>,
+        /// Compare with results saved under this name and fail on regressions
+        #[arg(long)]
+        compare: Option<String>,
+        /// Allowed slowdown in percent when comparing
+        #[arg(long, default_value = "10")]
+        threshold: f64,
+    },
+    /// Show and follow the configured log channels
+    Logs {
+        /// Keep following new lines as they are written
+        #[arg(short = 'f', long)]
+        tail: bool,
+        /// Number of existing lines to show from each channel
+        #[arg(short = 'n', long, default_value = "50")]
+        lines: usize,
+        /// Only show entries at or above this level
+        #[arg(long, value_enum)]
+        level: Option<commands::logs::LogLevel>,
+        /// Only show entries for this request ID
+        #[arg(long)]
+        request_id: Option<String>,
+        /// Print entries as JSON lines
+        #[arg(long)]
+        json: bool,
+        /// Read a single channel instead of the default stack
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Run one of the application's own console commands (e.g., torch report:daily)
+    #[command(external_subcommand)]
+    App(Vec<String>),
 }
 
 #[cfg(feature = "cli")]
@@ -154,6 +252,9 @@ pub enum Generator {
         /// Generate API controller
         #[arg(long)]
         api: bool,
+        /// Cache the GET actions' responses for this long (e.g., 60s, 5m) with #[cache]
+        #[arg(long, value_name = "TTL")]
+        cache: Option<String>,
     },
     /// Generate a new model
     Model {
@@ -172,6 +273,31 @@ pub enum Generator {
         #[arg(short, long)]
         policy: bool,
     },
+    /// Generate a model, migration, controller, request, views and tests
+    Resource {
+        /// Resource name (e.g., Post)
+        name: String,
+        /// Model fields as name:type pairs (string, text, int, float, bool)
+        #[arg(long, default_value = "name:string")]
+        fields: String,
+    },
+    /// Generate registration, login and logout with a users migration and views
+    Auth {
+        /// Overwrite existing files such as the starter User model
+        #[arg(long)]
+        force: bool,
+    },
+    /// Generate schemas, routes, validation and controllers from an OpenAPI 3 spec
+    FromOpenapi {
+        /// Path to the spec (YAML or JSON)
+        spec: String,
+        /// Route prefix, overriding the path of the first server URL
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Overwrite existing controllers
+        #[arg(long)]
+        force: bool,
+    },
     /// Generate a new middleware
     Middleware {
         /// Middleware name (e.g., AuthMiddleware)
@@ -275,6 +401,22 @@ pub enum DbOperation {
     },
     /// Show database connection status
     Status,
+    /// Create the configured database
+    Create,
+    /// Drop the configured database
+    Drop {
+        /// Force the operation without confirmation
+        #[arg(long)]
+        force: bool,
+    },
+    /// Test the database connection and explain failures
+    Doctor,
+    /// Compare the database with the migrations
+    Diff {
+        /// Generate a migration adding the missing tables, columns and indexes
+        #[arg(long)]
+        write: bool,
+    },
 }
 
 #[cfg(feature = "cli")]
@@ -302,6 +444,15 @@ pub enum MigrateOperation {
     Status,
     /// Install migration repository
     Install,
+    /// Dump the schema of the applied migrations to one SQL file and delete those migrations
+    Squash {
+        /// Keep the squashed migration files
+        #[arg(long)]
+        keep: bool,
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[cfg(feature = "cli")]
@@ -315,11 +466,20 @@ pub enum RouteOperation {
         /// Filter by name
         #[arg(long)]
         name: Option<String>,
+        /// Print the routes as JSON
+        #[arg(long)]
+        json: bool,
+        /// Read the routes from a running server (e.g., http://127.0.0.1:3000)
+        /// instead of the route cache
+        #[arg(long)]
+        server: Option<String>,
     },
-    /// Cache routes for faster registration
+    /// Build the app and cache its route table
     Cache,
     /// Clear the route cache
     Clear,
+    /// Build the app and report duplicate or unreachable routes and reused names
+    Check,
 }
 
 #[cfg(feature = "cli")]
@@ -393,7 +553,35 @@ pub enum QueueOperation {
     /// Retry failed jobs
     Retry {
         /// Specific job ID to retry
-        id: Option<String>,
+        id: Option<u64>,
+        /// Retry every failed job
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+    /// Show pending, processing and failed jobs per queue
+    Stats,
+    /// Delete failed jobs older than the given age
+    PruneFailed {
+        /// Age in hours
+        #[arg(long, default_value = "48")]
+        hours: u64,
+    },
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand)]
+pub enum MailOperation {
+    /// List queued mail that could not be sent
+    Failed {
+        /// Failed mail log to read
+        #[arg(long, default_value = crate::mail::FAILED_MAIL_FILE)]
+        file: std::path::PathBuf,
+    },
+    /// Delete the failed mail log
+    Clear {
+        /// Failed mail log to delete
+        #[arg(long, default_value = crate::mail::FAILED_MAIL_FILE)]
+        file: std::path::PathBuf,
     },
 }
 
@@ -405,7 +593,45 @@ pub enum ScheduleOperation {
     /// Clear schedule cache
     ClearCache,
     /// List scheduled tasks
-    List,
+    List {
+        /// Show recent runs stored in the `schedule_runs` table instead
+        #[arg(long)]
+        history: bool,
+        /// How many runs to show with --history
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand)]
+pub enum KeyOperation {
+    /// Generate a new APP_KEY, keeping the current one for decryption
+    Rotate {
+        /// Columns to re-encrypt with the new key, e.g. users.ssn,sessions.payload
+        #[arg(long, value_delimiter = ',')]
+        reencrypt: Vec<String>,
+    },
+    /// Re-encrypt values written with a previous key
+    Reencrypt {
+        /// Columns as table.column, e.g. users.ssn sessions.payload
+        #[arg(required = true, value_delimiter = ',')]
+        columns: Vec<String>,
+    },
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand)]
+pub enum DeployOperation {
+    /// Generate deployment files for the application
+    Init {
+        /// What to generate files for
+        #[arg(long, value_enum, default_value_t = commands::deploy::DeployTarget::Docker)]
+        target: commands::deploy::DeployTarget,
+        /// Overwrite existing files
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 /// Main CLI entry point
@@ -425,8 +651,14 @@ pub fn run() {
 #[cfg(feature = "cli")]
 fn run_command(command: Commands) -> Result<(), Box<dyn std::error::Error>> {
     match command {
-        Commands::New { name, minimal } => {
-            commands::new::create_project(&name, minimal)?;
+        Commands::New { name, template, minimal, no_check, path, git, branch, rev } => {
+            let template = if minimal { commands::new::Template::Minimal } else { template };
+            let source = match (path, git) {
+                (Some(path), _) => commands::new::TorchSource::Path(path),
+                (None, Some(url)) => commands::new::TorchSource::Git { url, branch, rev },
+                (None, None) => commands::new::TorchSource::Registry,
+            };
+            commands::new::create_project(&name, template, !no_check, &source)?;
         }
         Commands::Init { init_command } => {
             commands::init::handle_init_command(init_command)?;
@@ -446,8 +678,8 @@ fn run_command(command: Commands) -> Result<(), Box<dyn std::error::Error>> {
         Commands::Db { operation } => {
             commands::db::handle_operation(operation)?;
         }
-        Commands::Migrate { operation } => {
-            commands::migrate::handle_operation(operation)?;
+        Commands::Migrate { operation, pretend, isolated } => {
+            commands::migrate::handle_operation(operation, pretend, isolated)?;
         }
         Commands::Route { operation } => {
             commands::route::handle_operation(operation)?;
@@ -464,6 +696,9 @@ fn run_command(command: Commands) -> Result<(), Box<dyn std::error::Error>> {
         Commands::Queue { operation } => {
             commands::queue::handle_operation(operation)?;
         }
+        Commands::Mail { operation } => {
+            commands::mail::handle_operation(operation)?;
+        }
         Commands::Test { filter, unit, integration } => {
             commands::test::run_tests(filter, unit, integration)?;
         }
@@ -482,6 +717,40 @@ fn run_command(command: Commands) -> Result<(), Box<dyn std::error::Error>> {
         Commands::Schedule { operation } => {
             commands::schedule::handle_operation(operation)?;
         }
+        Commands::Deploy { operation } => {
+            commands::deploy::handle_operation(operation)?;
+        }
+        Commands::Key { operation } => {
+            commands::key::handle_operation(operation)?;
+        }
+        Commands::Bench { target, connections, duration, port, host, method, headers, body, save, compare, threshold } => {
+            commands::bench::run_bench(commands::bench::BenchOptions {
+                target,
+                host,
+                port,
+                connections,
+                duration,
+                method,
+                headers,
+                body,
+                save,
+                compare,
+                threshold,
+            })?;
+        }
+        Commands::Logs { tail, lines, level, request_id, json, channel } => {
+            commands::logs::show_logs(commands::logs::LogOptions {
+                follow: tail,
+                lines,
+                level,
+                request_id,
+                json,
+                channel,
+            })?;
+        }
+        Commands::App(args) => {
+            commands::console::run_app_command(&args)?;
+        }
     }
     Ok(())
 }
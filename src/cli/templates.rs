@@ -1,3 +1,70 @@
 //! Template utilities for Torch CLI
 
-// Placeholder for future template utilities
+/// Convert a type name like `BlogPost` to `blog_post`
+pub fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut prev_lower = false;
+    for ch in name.chars() {
+        if ch.is_uppercase() {
+            if prev_lower {
+                result.push('_');
+            }
+            result.extend(ch.to_lowercase());
+            prev_lower = false;
+        } else if ch == '-' || ch == ' ' {
+            result.push('_');
+            prev_lower = false;
+        } else {
+            result.push(ch);
+            prev_lower = ch.is_lowercase() || ch.is_ascii_digit();
+        }
+    }
+    result
+}
+
+/// Convert a name like `blog_post` or `blog-post` to `BlogPost`
+pub fn pascal_case(name: &str) -> String {
+    name.split(['_', '-', ' '])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Naive English plural used for table and route names
+pub fn plural(word: &str) -> String {
+    if word.ends_with('y') && !word.ends_with("ay") && !word.ends_with("ey") && !word.ends_with("oy") {
+        format!("{}ies", &word[..word.len() - 1])
+    } else if word.ends_with('s') || word.ends_with('x') || word.ends_with("ch") || word.ends_with("sh") {
+        format!("{}es", word)
+    } else {
+        format!("{}s", word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_conversions() {
+        assert_eq!(snake_case("BlogPost"), "blog_post");
+        assert_eq!(snake_case("post"), "post");
+        assert_eq!(snake_case("HTTPLog"), "httplog");
+        assert_eq!(pascal_case("blog_post"), "BlogPost");
+        assert_eq!(pascal_case("Post"), "Post");
+    }
+
+    #[test]
+    fn test_plural() {
+        assert_eq!(plural("post"), "posts");
+        assert_eq!(plural("category"), "categories");
+        assert_eq!(plural("box"), "boxes");
+        assert_eq!(plural("day"), "days");
+    }
+}
@@ -7,6 +7,9 @@ use std::time::Duration;
 #[cfg(feature = "config")]
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "config")]
+pub mod secrets;
+
 /// Main configuration structure for Torch applications
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
@@ -23,6 +26,12 @@ pub struct TorchConfig {
     pub rate_limiting: RateLimitingConfig,
     /// Database configuration
     pub database: Option<DatabaseConfig>,
+    /// Search engine configuration
+    #[cfg_attr(feature = "config", serde(default))]
+    pub search: Option<SearchConfig>,
+    /// Settings applied when running in production
+    #[cfg_attr(feature = "config", serde(default))]
+    pub production: ProductionSettings,
     /// Custom application settings
     #[cfg_attr(feature = "config", serde(default))]
     pub custom: std::collections::HashMap<String, String>,
@@ -74,6 +83,10 @@ pub struct SecurityConfig {
     pub enable_security_headers: bool,
     /// Content Security Policy
     pub content_security_policy: Option<String>,
+    /// Content Security Policy reported on but not enforced
+    pub content_security_policy_report_only: Option<String>,
+    /// Where browsers send CSP violation reports
+    pub csp_report_uri: Option<String>,
     /// Enable request signing verification
     pub enable_request_signing: bool,
     /// Secret key for request signing
@@ -92,6 +105,99 @@ pub struct SecurityConfig {
     pub enable_sql_injection_protection: bool,
     /// XSS protection
     pub enable_xss_protection: bool,
+    /// Bearer tokens from an OpenID Connect provider, see
+    /// [`JwtAuth`](crate::auth::JwtAuth) (`oidc` feature)
+    #[cfg_attr(feature = "config", serde(default))]
+    pub oidc: OidcConfig,
+    /// Sign-in against LDAP or Active Directory, see
+    /// [`LdapAuth`](crate::auth::ldap::LdapAuth) (`ldap` feature)
+    #[cfg_attr(feature = "config", serde(default))]
+    pub ldap: LdapConfig,
+}
+
+/// The `[security.oidc]` section: which provider's tokens
+/// [`JwtAuth::from_config`](crate::auth::JwtAuth::from_config) accepts
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "config", serde(default))]
+pub struct OidcConfig {
+    /// Issuer URL; keys are discovered from
+    /// `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    /// Accepted `aud` values; at least one is required
+    pub audience: Vec<String>,
+    /// Clock skew allowed on `exp` and `nbf`, in seconds
+    pub leeway_secs: u64,
+    /// Claim holding the user's roles, dotted for nested claims such as
+    /// Keycloak's `realm_access.roles`
+    pub roles_claim: String,
+    /// Let requests without a token through, without a user
+    pub optional: bool,
+}
+
+/// The `[security.ldap]` section: the directory
+/// [`LdapAuth::from_config`](crate::auth::ldap::LdapAuth::from_config)
+/// checks credentials against
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "config", serde(default))]
+pub struct LdapConfig {
+    /// `ldap://` or `ldaps://` server URL
+    pub url: String,
+    /// Where users are looked up
+    pub base_dn: String,
+    /// Service account used for lookups; anonymous when unset
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    /// Use Active Directory's attributes and nested groups
+    pub active_directory: bool,
+    /// Filter finding a user, with `{username}` for the escaped username
+    pub user_filter: Option<String>,
+    /// Where groups are searched, when `group_filter` is set; defaults to
+    /// `base_dn`
+    pub group_base_dn: Option<String>,
+    /// Filter finding a user's groups, with `{dn}` and `{username}`;
+    /// without one groups are read from `memberOf`
+    pub group_filter: Option<String>,
+    /// App role for each group, by DN or common name
+    pub group_roles: std::collections::BTreeMap<String, String>,
+    /// Most open connections
+    pub pool_size: usize,
+    /// Connect and operation timeout in seconds
+    pub timeout_secs: u64,
+    /// Upgrade `ldap://` connections with StartTLS
+    pub start_tls: bool,
+}
+
+impl Default for LdapConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            base_dn: String::new(),
+            bind_dn: None,
+            bind_password: None,
+            active_directory: false,
+            user_filter: None,
+            group_base_dn: None,
+            group_filter: None,
+            group_roles: std::collections::BTreeMap::new(),
+            pool_size: 5,
+            timeout_secs: 5,
+            start_tls: false,
+        }
+    }
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            issuer: String::new(),
+            audience: Vec::new(),
+            leeway_secs: 60,
+            roles_claim: "roles".to_string(),
+            optional: false,
+        }
+    }
 }
 
 /// Monitoring and logging configuration
@@ -126,6 +232,52 @@ pub struct MonitoringConfig {
     pub enable_distributed_tracing: bool,
     /// Tracing service endpoint
     pub tracing_endpoint: Option<String>,
+    /// OpenTelemetry export, see [`otel`](crate::otel) (`otel` feature)
+    #[cfg_attr(feature = "config", serde(default))]
+    pub otel: OtelConfig,
+}
+
+/// The `[monitoring.otel]` section: where to send traces and metrics
+/// over OTLP/HTTP
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "config", serde(default))]
+pub struct OtelConfig {
+    /// Export anything at all
+    pub enabled: bool,
+    /// Collector base URL; `/v1/traces` and `/v1/metrics` are appended
+    pub endpoint: String,
+    /// The `service.name` resource attribute
+    pub service_name: String,
+    /// Extra headers for the collector, e.g. an API key
+    pub headers: std::collections::HashMap<String, String>,
+    /// Share of new traces to keep, from 0.0 to 1.0; traces started
+    /// upstream follow the caller's decision
+    pub sample_ratio: f64,
+    /// Export request, query, cache and job spans
+    pub traces: bool,
+    /// Export metrics
+    pub metrics: bool,
+    /// Seconds between metric exports
+    pub metrics_interval_secs: u64,
+    /// Export timeout in seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4318".to_string(),
+            service_name: "torch-app".to_string(),
+            headers: std::collections::HashMap::new(),
+            sample_ratio: 1.0,
+            traces: true,
+            metrics: true,
+            metrics_interval_secs: 60,
+            timeout_secs: 10,
+        }
+    }
 }
 
 /// Performance configuration
@@ -200,6 +352,78 @@ pub struct DatabaseConfig {
     pub enable_migrations: bool,
     /// Migrations directory
     pub migrations_dir: Option<String>,
+    /// ORM settings
+    #[cfg_attr(feature = "config", serde(default))]
+    pub orm: DatabaseOrmConfig,
+}
+
+/// The `[database.orm]` section. Only the query cache settings, read by
+/// [`QueryCache::from_config`](crate::orm::cache::QueryCache::from_config),
+/// are used; the other keys `torch new` writes there are ignored
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "config", serde(default))]
+pub struct DatabaseOrmConfig {
+    /// Cache the results of model queries until the table is written to
+    pub query_cache: bool,
+    /// How long cached results are kept, in seconds
+    pub query_cache_ttl: u64,
+}
+
+impl Default for DatabaseOrmConfig {
+    fn default() -> Self {
+        Self {
+            query_cache: false,
+            query_cache_ttl: 300,
+        }
+    }
+}
+
+/// The `[search]` section: the engine
+/// [`Search::from_config`](crate::search::Search::from_config) indexes
+/// models in
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "config", serde(default))]
+pub struct SearchConfig {
+    /// `meilisearch`, `elasticsearch` or `memory`
+    pub driver: String,
+    /// Engine URL, e.g. `http://localhost:7700`
+    pub url: String,
+    /// Meilisearch API key, or Elasticsearch API key sent as `ApiKey`
+    pub api_key: Option<String>,
+    /// Elasticsearch basic auth, when there's no API key
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Put before every index name, to share an engine between apps or
+    /// environments
+    pub index_prefix: String,
+    /// Request timeout in seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            driver: "memory".to_string(),
+            url: String::new(),
+            api_key: None,
+            username: None,
+            password: None,
+            index_prefix: String::new(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// The `[production]` section, read by
+/// [`ProductionConfig::from_torch_config`](crate::production::ProductionConfig::from_torch_config)
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "config", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "config", serde(default))]
+pub struct ProductionSettings {
+    /// Minify server-rendered HTML responses
+    pub minify_assets: bool,
 }
 
 impl Default for TorchConfig {
@@ -211,6 +435,8 @@ impl Default for TorchConfig {
             performance: PerformanceConfig::default(),
             rate_limiting: RateLimitingConfig::default(),
             database: None,
+            search: None,
+            production: ProductionSettings::default(),
             custom: std::collections::HashMap::new(),
         }
     }
@@ -257,6 +483,8 @@ impl Default for SecurityConfig {
                 "default-src 'self'; script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'"
                     .to_string(),
             ),
+            content_security_policy_report_only: None,
+            csp_report_uri: None,
             enable_request_signing: false,
             signing_secret: None,
             enable_ip_whitelist: false,
@@ -266,6 +494,8 @@ impl Default for SecurityConfig {
             enable_input_validation: true,
             enable_sql_injection_protection: true,
             enable_xss_protection: true,
+            oidc: OidcConfig::default(),
+            ldap: LdapConfig::default(),
         }
     }
 }
@@ -287,6 +517,7 @@ impl Default for MonitoringConfig {
             error_tracking_url: None,
             enable_distributed_tracing: false,
             tracing_endpoint: None,
+            otel: OtelConfig::default(),
         }
     }
 }
@@ -325,12 +556,24 @@ impl Default for RateLimitingConfig {
 }
 
 impl TorchConfig {
-    /// Load configuration from a TOML file
+    /// Load configuration from a TOML file, resolving secret references
+    /// such as `"vault:secret/db#password"` with the built-in providers
     #[cfg(feature = "config")]
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_file_with_secrets(path, &secrets::Secrets::default())
+    }
+
+    /// Load configuration from a TOML file, resolving secret references with
+    /// `secrets`
+    #[cfg(feature = "config")]
+    pub fn from_file_with_secrets<P: AsRef<Path>>(
+        path: P,
+        secrets: &secrets::Secrets,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let content = std::fs::read_to_string(path)?;
-        let config: TorchConfig = toml::from_str(&content)?;
-        Ok(config)
+        let mut value: toml::Value = toml::from_str(&content)?;
+        secrets.resolve_toml(&mut value)?;
+        Ok(value.try_into()?)
     }
 
     /// Save configuration to a TOML file
@@ -368,6 +611,17 @@ impl TorchConfig {
             config.security.signing_secret = Some(secret);
             config.security.enable_request_signing = true;
         }
+        if let Ok(policy) = std::env::var("TORCH_CSP_REPORT_ONLY") {
+            config.security.content_security_policy_report_only = Some(policy);
+        }
+        if let Ok(uri) = std::env::var("TORCH_CSP_REPORT_URI") {
+            config.security.csp_report_uri = Some(uri);
+        }
+
+        // Production configuration
+        if let Ok(minify) = std::env::var("TORCH_MINIFY_ASSETS") {
+            config.production.minify_assets = minify.parse().unwrap_or(false);
+        }
         
         // Add more environment variable mappings as needed
         
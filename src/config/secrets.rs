@@ -0,0 +1,327 @@
+//! Secret references in configuration values
+//!
+//! A string value such as `"vault:secret/db#password"` is a reference to a
+//! secret kept outside the config file. References are resolved when the
+//! configuration is loaded, by the provider registered for the part before the
+//! first `:`. Values whose prefix isn't a registered provider, like
+//! `postgres://...`, are left alone.
+//!
+//! Built-in providers:
+//!
+//! | Reference | Resolves to |
+//! |-----------|-------------|
+//! | `env:DB_PASSWORD` | the `DB_PASSWORD` environment variable |
+//! | `file:/run/secrets/db_password` | the file's contents, without the trailing newline |
+//! | `vault:secret/db#password` | the `password` field of `secret/db`, read with `vault kv get` |
+//! | `aws-sm:prod/db#password` | the `password` key of the `prod/db` secret, read with `aws secretsmanager get-secret-value` |
+//!
+//! Vault and AWS secrets are read with their official command line tools, so
+//! they authenticate the way those tools are set up (`VAULT_ADDR` and
+//! `VAULT_TOKEN`, AWS profiles or instance roles). Register other sources
+//! with [`Secrets::provider`]:
+//!
+//! ```rust
+//! use torch_web::config::secrets::{SecretError, Secrets};
+//!
+//! let secrets = Secrets::default().provider("static", |name: &str| match name {
+//!     "db" => Ok("hunter2".to_string()),
+//!     other => Err(SecretError::new(other, "unknown secret")),
+//! });
+//!
+//! let mut config: toml::Value = toml::from_str(r#"
+//!     [database]
+//!     url = "postgres://app@localhost/app"
+//!     password = "static:db"
+//! "#).unwrap();
+//! secrets.resolve_toml(&mut config).unwrap();
+//!
+//! assert_eq!(config["database"]["password"].as_str(), Some("hunter2"));
+//! assert_eq!(config["database"]["url"].as_str(), Some("postgres://app@localhost/app"));
+//! ```
+
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// A secret that couldn't be resolved. Never includes the secret's value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecretError {
+    pub reference: String,
+    pub message: String,
+}
+
+impl SecretError {
+    pub fn new(reference: &str, message: impl ToString) -> Self {
+        Self {
+            reference: reference.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for SecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not resolve secret {}: {}", self.reference, self.message)
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Source of secrets for one reference prefix
+pub trait SecretProvider: Send + Sync + 'static {
+    /// Look up the part of the reference after the prefix
+    fn resolve(&self, reference: &str) -> Result<String, SecretError>;
+}
+
+impl<F> SecretProvider for F
+where
+    F: Fn(&str) -> Result<String, SecretError> + Send + Sync + 'static,
+{
+    fn resolve(&self, reference: &str) -> Result<String, SecretError> {
+        self(reference)
+    }
+}
+
+/// `env:NAME`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnvProvider;
+
+impl SecretProvider for EnvProvider {
+    fn resolve(&self, name: &str) -> Result<String, SecretError> {
+        std::env::var(name).map_err(|_| SecretError::new(name, "environment variable is not set"))
+    }
+}
+
+/// `file:/path/to/secret`, as mounted by Docker and Kubernetes secrets
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileProvider;
+
+impl SecretProvider for FileProvider {
+    fn resolve(&self, path: &str) -> Result<String, SecretError> {
+        let contents = std::fs::read_to_string(path).map_err(|error| SecretError::new(path, error))?;
+        Ok(contents.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// `vault:<path>#<field>`, read with `vault kv get -field=<field> <path>`
+#[derive(Debug, Clone)]
+pub struct VaultProvider {
+    program: String,
+}
+
+impl VaultProvider {
+    pub fn new() -> Self {
+        Self {
+            program: "vault".to_string(),
+        }
+    }
+
+    /// Run a different `vault` binary
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = program.to_string();
+        self
+    }
+}
+
+impl Default for VaultProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecretProvider for VaultProvider {
+    fn resolve(&self, reference: &str) -> Result<String, SecretError> {
+        let (path, field) = reference
+            .split_once('#')
+            .filter(|(path, field)| !path.is_empty() && !field.is_empty())
+            .ok_or_else(|| SecretError::new(reference, "expected vault:<path>#<field>"))?;
+        run(reference, Command::new(&self.program).args(["kv", "get", &format!("-field={}", field), path]))
+    }
+}
+
+/// `aws-sm:<secret id>` or `aws-sm:<secret id>#<key>` for one key of a JSON
+/// secret, read with `aws secretsmanager get-secret-value`
+#[derive(Debug, Clone)]
+pub struct AwsSecretsManagerProvider {
+    program: String,
+}
+
+impl AwsSecretsManagerProvider {
+    pub fn new() -> Self {
+        Self {
+            program: "aws".to_string(),
+        }
+    }
+
+    /// Run a different `aws` binary
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = program.to_string();
+        self
+    }
+}
+
+impl Default for AwsSecretsManagerProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecretProvider for AwsSecretsManagerProvider {
+    fn resolve(&self, reference: &str) -> Result<String, SecretError> {
+        let (id, key) = match reference.split_once('#') {
+            Some((id, key)) => (id, Some(key)),
+            None => (reference, None),
+        };
+        let secret = run(
+            reference,
+            Command::new(&self.program).args([
+                "secretsmanager",
+                "get-secret-value",
+                "--secret-id",
+                id,
+                "--query",
+                "SecretString",
+                "--output",
+                "text",
+            ]),
+        )?;
+
+        let Some(key) = key else {
+            return Ok(secret);
+        };
+        let fields: serde_json::Value =
+            serde_json::from_str(&secret).map_err(|_| SecretError::new(reference, "secret is not a JSON object"))?;
+        match fields.get(key) {
+            Some(serde_json::Value::String(value)) => Ok(value.clone()),
+            Some(serde_json::Value::Number(number)) => Ok(number.to_string()),
+            _ => Err(SecretError::new(reference, format!("secret has no {} key", key))),
+        }
+    }
+}
+
+/// Run a provider's command line tool, returning its trimmed output
+fn run(reference: &str, command: &mut Command) -> Result<String, SecretError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|error| SecretError::new(reference, format!("could not run {}: {}", program, error)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SecretError::new(reference, format!("{} failed: {}", program, stderr.trim())));
+    }
+    String::from_utf8(output.stdout)
+        .map(|value| value.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|_| SecretError::new(reference, "secret is not valid UTF-8"))
+}
+
+/// Providers by reference prefix, with resolved secrets cached so each one
+/// is fetched once
+#[derive(Clone)]
+pub struct Secrets {
+    providers: HashMap<String, Arc<dyn SecretProvider>>,
+    resolved: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Secrets {
+    /// No providers; see [`Secrets::default`] for the built-in ones
+    pub fn new() -> Self {
+        Self {
+            providers: HashMap::new(),
+            resolved: Arc::default(),
+        }
+    }
+
+    /// Resolve references starting with `<prefix>:` with `provider`
+    pub fn provider<P: SecretProvider>(mut self, prefix: &str, provider: P) -> Self {
+        self.providers.insert(prefix.to_string(), Arc::new(provider));
+        self
+    }
+
+    /// The secret `value` refers to, or `None` when it isn't a reference
+    pub fn resolve(&self, value: &str) -> Result<Option<String>, SecretError> {
+        let Some((prefix, reference)) = value.split_once(':') else {
+            return Ok(None);
+        };
+        let Some(provider) = self.providers.get(prefix) else {
+            return Ok(None);
+        };
+
+        if let Some(secret) = self.resolved.lock().unwrap().get(value) {
+            return Ok(Some(secret.clone()));
+        }
+        let secret = provider.resolve(reference).map_err(|error| SecretError { reference: value.to_string(), ..error })?;
+        self.resolved.lock().unwrap().insert(value.to_string(), secret.clone());
+        Ok(Some(secret))
+    }
+
+    /// Replace every reference in `value`, including inside tables and
+    /// arrays, with its secret
+    pub fn resolve_toml(&self, value: &mut toml::Value) -> Result<(), SecretError> {
+        match value {
+            toml::Value::String(text) => {
+                if let Some(secret) = self.resolve(text)? {
+                    *text = secret;
+                }
+            }
+            toml::Value::Array(values) => {
+                for value in values {
+                    self.resolve_toml(value)?;
+                }
+            }
+            toml::Value::Table(table) => {
+                for (_, value) in table.iter_mut() {
+                    self.resolve_toml(value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Default for Secrets {
+    /// The `env`, `file`, `vault` and `aws-sm` providers
+    fn default() -> Self {
+        Self::new()
+            .provider("env", EnvProvider)
+            .provider("file", FileProvider)
+            .provider("vault", VaultProvider::new())
+            .provider("aws-sm", AwsSecretsManagerProvider::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references_resolve_through_providers() {
+        let path = std::env::temp_dir().join(format!("torch-secret-{}", std::process::id()));
+        std::fs::write(&path, "s3cret\n").unwrap();
+
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let secrets = Secrets::default().provider("counted", move |name: &str| {
+            *counted.lock().unwrap() += 1;
+            Ok(format!("value-of-{}", name))
+        });
+
+        let mut config: toml::Value = toml::from_str(&format!(
+            "url = \"postgres://app@localhost/app\"\npassword = \"file:{}\"\nkeys = [\"counted:a\", \"counted:a\", \"plain\"]",
+            path.display()
+        ))
+        .unwrap();
+        secrets.resolve_toml(&mut config).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(config["url"].as_str(), Some("postgres://app@localhost/app"));
+        assert_eq!(config["password"].as_str(), Some("s3cret"));
+        assert_eq!(config["keys"][0].as_str(), Some("value-of-a"));
+        assert_eq!(config["keys"][2].as_str(), Some("plain"));
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        let error = secrets.resolve("vault:secret/db").unwrap_err();
+        assert_eq!(error.reference, "vault:secret/db");
+        assert!(secrets.resolve("env:TORCH_TEST_SECRET_THAT_IS_NOT_SET").is_err());
+    }
+}
@@ -0,0 +1,325 @@
+//! # Console Commands
+//!
+//! Apps define their own commands as clap parsers, register them on a
+//! [`Console`], and hand the process arguments to it at the top of `main`.
+//! When the first argument names a registered command it runs instead of
+//! the server:
+//!
+//! ```rust,no_run
+//! use clap::Parser;
+//! use torch_web::console::{CommandContext, CommandFuture, Console, ConsoleCommand};
+//!
+//! #[derive(Parser)]
+//! #[command(name = "report:daily", about = "Email yesterday's signups")]
+//! struct DailyReport {
+//!     #[arg(long)]
+//!     email: bool,
+//! }
+//!
+//! impl ConsoleCommand for DailyReport {
+//!     fn handle<'a>(&'a self, context: &'a CommandContext) -> CommandFuture<'a> {
+//!         Box::pin(async move {
+//!             let team = context.state::<String>().ok_or("no team configured")?;
+//!             println!("Reporting to {} (email: {})", team, self.email);
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let console = Console::new()
+//!         .with_state("growth".to_string())
+//!         .register::<DailyReport>();
+//!     if let Some(code) = console.dispatch().await {
+//!         std::process::exit(code);
+//!     }
+//!
+//!     // No command given: start the server as usual
+//! }
+//! ```
+//!
+//! `torch report:daily --email` runs the command through `cargo run` in the
+//! project directory, `<app> list` prints the registered commands, and
+//! [`Schedule::command`](crate::schedule::Schedule::command) runs them on a
+//! timetable. Commands get the ORM connected before they run, from the
+//! config given to [`Console::database`] or else `DATABASE_URL`.
+//!
+//! Registering [`Migrate`] alongside a `MigrationRunner` in state gives the
+//! app a `migrate` command, which `torch migrate --pretend`,
+//! `torch migrate --isolated` and `torch db diff` run.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use clap::Parser;
+
+use crate::extractors::state::StateMap;
+use crate::orm::OrmConfig;
+
+/// Set by `torch <command>` when it starts the app to run a command
+pub const FORWARDED_BY_TORCH: &str = "TORCH_CONSOLE";
+
+/// Error returned by a command; it is printed and the process exits with 1
+pub type CommandError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Future returned by [`ConsoleCommand::handle`]
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<(), CommandError>> + Send + 'a>>;
+
+/// An app command, parsed from its arguments by clap. The command's name is
+/// clap's `#[command(name = "...")]`.
+pub trait ConsoleCommand: Parser + Send + 'static {
+    fn handle<'a>(&'a self, context: &'a CommandContext) -> CommandFuture<'a>;
+}
+
+/// What a running command can reach besides its arguments
+pub struct CommandContext {
+    state: StateMap,
+}
+
+impl CommandContext {
+    /// State registered with [`Console::with_state`]
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.state.get::<T>()
+    }
+}
+
+/// A parsed command, with its concrete type erased
+trait Runnable: Send {
+    fn run<'a>(&'a self, context: &'a CommandContext) -> CommandFuture<'a>;
+}
+
+impl<C: ConsoleCommand> Runnable for C {
+    fn run<'a>(&'a self, context: &'a CommandContext) -> CommandFuture<'a> {
+        self.handle(context)
+    }
+}
+
+type Parsed = Result<Box<dyn Runnable>, clap::Error>;
+
+fn parse<C: ConsoleCommand>(args: Vec<String>) -> Parsed {
+    Ok(Box::new(C::try_parse_from(args)?))
+}
+
+struct Registered {
+    about: String,
+    parse: fn(Vec<String>) -> Parsed,
+}
+
+/// The commands an app provides
+pub struct Console {
+    commands: BTreeMap<String, Registered>,
+    context: CommandContext,
+    database: Option<OrmConfig>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            context: CommandContext { state: StateMap::new() },
+            database: None,
+        }
+    }
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a command, replacing any registered under the same name
+    pub fn register<C: ConsoleCommand>(mut self) -> Self {
+        let command = C::command();
+        let about = command.get_about().map(|about| about.to_string()).unwrap_or_default();
+        self.commands.insert(command.get_name().to_string(), Registered { about, parse: parse::<C> });
+        self
+    }
+
+    /// Share a value with every command, read back with
+    /// [`CommandContext::state`]
+    pub fn with_state<T: Send + Sync + 'static>(mut self, state: T) -> Self {
+        self.context.state.insert(state);
+        self
+    }
+
+    /// Connect the ORM with `config` before a command runs, instead of from
+    /// `DATABASE_URL`
+    pub fn database(mut self, config: OrmConfig) -> Self {
+        self.database = Some(config);
+        self
+    }
+
+    /// Names of the registered commands, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.commands.keys().map(String::as_str).collect()
+    }
+
+    /// Run the command named by the process arguments, if any
+    pub async fn dispatch(&self) -> Option<i32> {
+        let code = self.call(std::env::args().skip(1)).await;
+
+        // Started by `torch <command>`, so an unknown command must not fall
+        // through to starting the server
+        if code.is_none() && std::env::var_os(FORWARDED_BY_TORCH).is_some() {
+            eprintln!("❌ Unknown command: {}", std::env::args().nth(1).unwrap_or_default());
+            self.list();
+            return Some(1);
+        }
+        code
+    }
+
+    /// Run the command named by the first of `args` and return the exit code
+    /// the process should end with, or `None` when `args` don't name a
+    /// command
+    pub async fn call<I, S>(&self, args: I) -> Option<i32>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let args: Vec<String> = args.into_iter().map(Into::into).collect();
+        let name = args.first()?.clone();
+        if name == "list" && !self.commands.contains_key("list") {
+            self.list();
+            return Some(0);
+        }
+        let registered = self.commands.get(&name)?;
+
+        // `--help` and `--version` come back as errors with exit code 0
+        let command = match (registered.parse)(args) {
+            Ok(command) => command,
+            Err(error) => {
+                let _ = error.print();
+                return Some(error.exit_code());
+            }
+        };
+        if let Err(error) = self.connect().await {
+            eprintln!("❌ Could not connect to the database: {}", error);
+            return Some(1);
+        }
+        match command.run(&self.context).await {
+            Ok(()) => Some(0),
+            Err(error) => {
+                eprintln!("❌ {} failed: {}", name, error);
+                Some(1)
+            }
+        }
+    }
+
+    fn list(&self) {
+        println!("Available commands:");
+        let width = self.commands.keys().map(String::len).max().unwrap_or(0);
+        for (name, registered) in &self.commands {
+            println!("  {:<width$}  {}", name, registered.about, width = width);
+        }
+    }
+
+    async fn connect(&self) -> Result<(), crate::orm::OrmError> {
+        if crate::orm::connection::is_initialized() {
+            return Ok(());
+        }
+        let config = match (&self.database, std::env::var("DATABASE_URL")) {
+            (Some(config), _) => config.clone(),
+            (None, Ok(database_url)) => OrmConfig { database_url, ..OrmConfig::default() },
+            (None, Err(_)) => return Ok(()),
+        };
+        crate::orm::initialize(config).await
+    }
+}
+
+/// Built-in `migrate` command, running the [`MigrationRunner`] shared with
+/// [`Console::with_state`]. `torch migrate --pretend`,
+/// `torch migrate --isolated` and `torch db diff` forward to it.
+///
+/// [`MigrationRunner`]: crate::orm::migration::MigrationRunner
+#[derive(Parser)]
+#[command(name = "migrate", about = "Run pending database migrations")]
+pub struct Migrate {
+    /// Print the SQL pending migrations would run, without running it
+    #[arg(long)]
+    pretend: bool,
+
+    /// Hold a database lock while migrating, for deploys on several servers
+    #[arg(long, conflicts_with = "pretend")]
+    isolated: bool,
+
+    /// Report tables, columns and indexes the migrations build that the
+    /// database lacks, without migrating
+    #[arg(long, conflicts_with_all = ["pretend", "isolated"])]
+    diff: bool,
+
+    /// With --diff, generate a migration adding what's missing
+    #[arg(long, requires = "diff")]
+    write: bool,
+}
+
+impl ConsoleCommand for Migrate {
+    fn handle<'a>(&'a self, context: &'a CommandContext) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let runner = context
+                .state::<crate::orm::migration::MigrationRunner>()
+                .ok_or("register a MigrationRunner with Console::with_state")?;
+            if self.pretend {
+                let pending = runner.pretend().await?;
+                if pending.is_empty() {
+                    println!("Nothing to migrate");
+                }
+                for migration in pending {
+                    println!("-- {}", migration.name);
+                    for statement in migration.statements {
+                        println!("{};", statement);
+                    }
+                }
+            } else if self.diff {
+                crate::cli::commands::schema_diff::diff_migrations(runner, self.write).await?;
+            } else if self.isolated {
+                runner.migrate_isolated().await?;
+            } else {
+                runner.migrate().await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[derive(Parser)]
+    #[command(name = "invoices:remind", about = "Remind customers of unpaid invoices")]
+    struct RemindInvoices {
+        #[arg(long, default_value_t = 30)]
+        days: u32,
+    }
+
+    impl ConsoleCommand for RemindInvoices {
+        fn handle<'a>(&'a self, context: &'a CommandContext) -> CommandFuture<'a> {
+            Box::pin(async move {
+                if self.days == 0 {
+                    return Err("days must be positive".into());
+                }
+                context.state::<AtomicU32>().ok_or("missing counter")?.fetch_add(self.days, Ordering::SeqCst);
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_console_runs_registered_commands() {
+        let console = Console::new().with_state(AtomicU32::new(0)).register::<RemindInvoices>();
+        assert_eq!(console.names(), vec!["invoices:remind"]);
+
+        assert_eq!(console.call(["invoices:remind"]).await, Some(0));
+        assert_eq!(console.call(["invoices:remind", "--days", "7"]).await, Some(0));
+        assert_eq!(console.context.state::<AtomicU32>().unwrap().load(Ordering::SeqCst), 37);
+
+        assert_eq!(console.call(["invoices:remind", "--days", "0"]).await, Some(1));
+        assert_eq!(console.call(["invoices:remind", "--days", "soon"]).await, Some(2));
+        assert_eq!(console.call(["list"]).await, Some(0));
+        assert_eq!(console.call(["serve"]).await, None);
+        assert_eq!(console.call(Vec::<String>::new()).await, None);
+    }
+}
@@ -0,0 +1,8 @@
+//! # Development Tools
+//!
+//! Helpers for working on an app locally. They stay silent in release builds
+//! unless the app runs under `torch serve`.
+
+pub mod inspector;
+pub mod profiler;
+pub mod routes;
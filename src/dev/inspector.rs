@@ -0,0 +1,438 @@
+//! # Request Inspector
+//!
+//! Records what the app did recently (requests, database queries, cache
+//! reads and writes, queued jobs and errors) and shows it on a dashboard at
+//! `/torch`:
+//!
+//! ```rust,no_run
+//! use torch_web::App;
+//! use torch_web::dev::inspector::Inspector;
+//!
+//! let app = App::new()
+//!     .inspector(Inspector::new().capacity(1_000))
+//!     .get("/", |_req: torch_web::Request| async { "Hello" });
+//! ```
+//!
+//! Each query, cache operation, job and error is linked to the request that
+//! caused it, so clicking a request shows everything it did. Only the
+//! newest [`capacity`](Inspector::capacity) entries are kept.
+//!
+//! Like the mail previews, the inspector only records and answers in debug
+//! builds or under `torch serve`, so the call can stay in production code.
+//! The dashboard also only answers requests from the local machine unless
+//! [`Inspector::authorize`] says otherwise.
+//!
+//! The ORM query builder, the built-in caches and [`Queue`](crate::queue::Queue)
+//! report to the inspector themselves. Queries run through sqlx directly can
+//! be added with [`record_query`], and errors handled without a 500 with
+//! [`record_exception`].
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::clock::unix_now_ms;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+/// Entries kept when [`Inspector::capacity`] isn't set
+pub const DEFAULT_CAPACITY: usize = 500;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static ENTRIES: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+
+tokio::task_local! {
+    /// Id of the request entry being handled
+    static CURRENT_REQUEST: u64;
+}
+
+/// What an [`Entry`] records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Request,
+    Query,
+    Cache,
+    Job,
+    Exception,
+}
+
+impl EntryKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "request" => Some(EntryKind::Request),
+            "query" => Some(EntryKind::Query),
+            "cache" => Some(EntryKind::Cache),
+            "job" => Some(EntryKind::Job),
+            "exception" => Some(EntryKind::Exception),
+            _ => None,
+        }
+    }
+}
+
+/// One recorded event
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub id: u64,
+    pub kind: EntryKind,
+    /// Unix time in milliseconds
+    pub at: u64,
+    /// The request being handled when this happened
+    pub request: Option<u64>,
+    /// One line describing the entry, e.g. the SQL or `GET /users -> 200`
+    pub summary: String,
+    pub duration_ms: Option<f64>,
+    pub details: Value,
+}
+
+/// Whether entries are being recorded
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn dev_mode() -> bool {
+    cfg!(debug_assertions) || std::env::var_os(crate::app::DEV_SERVER_ENV).is_some()
+}
+
+fn push(id: u64, kind: EntryKind, summary: String, duration: Option<Duration>, details: Value) {
+    let request = CURRENT_REQUEST.try_with(|request| *request).ok().filter(|request| *request != id);
+    let entry = Entry {
+        id,
+        kind,
+        at: unix_now_ms(),
+        request,
+        summary,
+        duration_ms: duration.map(|duration| duration.as_secs_f64() * 1000.0),
+        details,
+    };
+    let mut entries = ENTRIES.lock().unwrap();
+    entries.push_back(entry);
+    while entries.len() > CAPACITY.load(Ordering::Relaxed) {
+        entries.pop_front();
+    }
+}
+
+fn record(kind: EntryKind, summary: String, duration: Option<Duration>, details: Value) {
+    if enabled() {
+        push(NEXT_ID.fetch_add(1, Ordering::Relaxed), kind, summary, duration, details);
+    }
+}
+
+/// Record a database query and how long it took
+pub fn record_query(sql: &str, bindings: usize, duration: Duration) {
+    super::profiler::record_query(duration);
+    record(EntryKind::Query, sql.to_string(), Some(duration), json!({ "bindings": bindings }));
+}
+
+/// Record a cache operation; `hit` is whether a read found the key
+pub(crate) fn record_cache(operation: &str, key: &str, hit: Option<bool>) {
+    if !enabled() {
+        return;
+    }
+    let summary = match hit {
+        Some(true) => format!("{} {} (hit)", operation, key),
+        Some(false) => format!("{} {} (miss)", operation, key),
+        None => format!("{} {}", operation, key),
+    };
+    record(EntryKind::Cache, summary, None, json!({ "operation": operation, "key": key, "hit": hit }));
+}
+
+/// Record a job being dispatched, run or failing
+pub(crate) fn record_job(job: &str, queue: &str, event: &str, duration: Option<Duration>, error: Option<&str>) {
+    if !enabled() {
+        return;
+    }
+    record(
+        EntryKind::Job,
+        format!("{} {} on {}", event, job, queue),
+        duration,
+        json!({ "job": job, "queue": queue, "event": event, "error": error }),
+    );
+}
+
+/// Record an error, e.g. one a handler caught and turned into a friendly
+/// response
+pub fn record_exception(error: &dyn std::fmt::Display) {
+    record(EntryKind::Exception, error.to_string(), None, Value::Null);
+}
+
+/// Recorded entries, newest first
+pub fn entries() -> Vec<Entry> {
+    ENTRIES.lock().unwrap().iter().rev().cloned().collect()
+}
+
+/// Forget every recorded entry
+pub fn clear() {
+    ENTRIES.lock().unwrap().clear();
+}
+
+type Authorize = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+
+/// Settings for the inspector, installed with [`App::inspector`](crate::App::inspector)
+#[derive(Clone)]
+pub struct Inspector {
+    capacity: usize,
+    authorize: Authorize,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            authorize: Arc::new(|req: &Request| req.remote_addr().is_some_and(|addr| addr.ip().is_loopback())),
+        }
+    }
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the newest `capacity` entries
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Decide who may see the dashboard, instead of only local clients
+    pub fn authorize<F>(mut self, authorize: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.authorize = Arc::new(authorize);
+        self
+    }
+
+    /// Start recording, returning whether this is a development run
+    pub(crate) fn install(&self) -> bool {
+        if !dev_mode() {
+            return false;
+        }
+        CAPACITY.store(self.capacity, Ordering::Relaxed);
+        ENABLED.store(true, Ordering::Relaxed);
+        true
+    }
+
+    fn allowed(&self, req: &Request) -> bool {
+        enabled() && (self.authorize)(req)
+    }
+
+    /// `GET /torch`
+    pub(crate) async fn dashboard(self, req: Request) -> Response {
+        if !self.allowed(&req) {
+            return Response::not_found();
+        }
+        Response::ok().html(DASHBOARD)
+    }
+
+    /// `GET /torch/entries`, optionally filtered with `?kind=` and
+    /// `?request=`
+    pub(crate) async fn serve_entries(self, req: Request) -> Response {
+        if !self.allowed(&req) {
+            return Response::not_found();
+        }
+        let kind = req.query("kind").and_then(EntryKind::parse);
+        let request: Option<u64> = req.query("request").and_then(|id| id.parse().ok());
+        let entries: Vec<Entry> = entries()
+            .into_iter()
+            .filter(|entry| kind.map_or(true, |kind| entry.kind == kind))
+            .filter(|entry| request.map_or(true, |request| entry.request == Some(request) || entry.id == request))
+            .collect();
+        Response::ok().json(&json!({ "entries": entries })).unwrap_or_else(|_| Response::internal_error())
+    }
+
+    /// `DELETE /torch/entries`
+    pub(crate) async fn serve_clear(self, req: Request) -> Response {
+        if !self.allowed(&req) {
+            return Response::not_found();
+        }
+        clear();
+        Response::no_content()
+    }
+}
+
+/// Records each request, with everything done while handling it
+pub(crate) struct InspectorMiddleware;
+
+impl Middleware for InspectorMiddleware {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            // Don't fill the buffer with the dashboard's own polling
+            if !enabled() || req.path() == "/torch" || req.path().starts_with("/torch/") {
+                return next(req).await;
+            }
+
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let summary = format!("{} {}", req.method(), req.uri());
+            let mut details = json!({
+                "method": req.method().as_str(),
+                "uri": req.uri().to_string(),
+                "ip": req.remote_addr().map(|addr| addr.ip().to_string()),
+                "user_agent": req.header("user-agent"),
+                "body_bytes": req.body().len(),
+            });
+
+            let started = Instant::now();
+            let response = std::panic::AssertUnwindSafe(CURRENT_REQUEST.scope(id, next(req))).catch_unwind().await;
+            let duration = started.elapsed();
+            let response = match response {
+                Ok(response) => response,
+                Err(panic) => {
+                    let message = match panic.downcast_ref::<String>() {
+                        Some(message) => message.clone(),
+                        None => panic.downcast_ref::<&str>().map_or("unknown panic", |message| message).to_string(),
+                    };
+                    CURRENT_REQUEST.sync_scope(id, || record(EntryKind::Exception, format!("panicked: {}", message), None, Value::Null));
+                    push(id, EntryKind::Request, format!("{} -> panicked", summary), Some(duration), details);
+                    std::panic::resume_unwind(panic);
+                }
+            };
+
+            let status = response.status_code().as_u16();
+            details["status"] = json!(status);
+            details["content_type"] = json!(response.headers().get("content-type").and_then(|value| value.to_str().ok()));
+            push(id, EntryKind::Request, format!("{} -> {}", summary, status), Some(duration), details);
+            response
+        })
+    }
+}
+
+/// The dashboard: polls `/torch/entries` and lists entries by kind
+const DASHBOARD: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Torch Inspector</title>
+<style>
+body { font: 14px system-ui, sans-serif; margin: 0; color: #222; }
+header { background: #1f2937; color: #fff; padding: 12px 20px; display: flex; gap: 16px; align-items: center; }
+header h1 { font-size: 18px; margin: 0 16px 0 0; }
+nav button { background: none; border: 0; color: #cbd5e1; font-size: 14px; cursor: pointer; padding: 4px 8px; }
+nav button.active { color: #fff; border-bottom: 2px solid #f97316; }
+#clear { margin-left: auto; }
+main { padding: 16px 20px; }
+table { border-collapse: collapse; width: 100%; }
+td, th { text-align: left; padding: 6px 8px; border-bottom: 1px solid #e5e7eb; vertical-align: top; }
+td.summary { font-family: ui-monospace, monospace; word-break: break-all; }
+tr.entry { cursor: pointer; }
+tr.entry:hover { background: #f9fafb; }
+.exception td.summary { color: #b91c1c; }
+pre { background: #f3f4f6; padding: 8px; margin: 0; white-space: pre-wrap; }
+#filter { color: #64748b; margin-bottom: 8px; }
+</style>
+</head>
+<body>
+<header>
+<h1>🔥 Inspector</h1>
+<nav>
+<button data-kind="" class="active">All</button>
+<button data-kind="request">Requests</button>
+<button data-kind="query">Queries</button>
+<button data-kind="cache">Cache</button>
+<button data-kind="job">Jobs</button>
+<button data-kind="exception">Exceptions</button>
+</nav>
+<button id="clear">Clear</button>
+</header>
+<main>
+<div id="filter"></div>
+<table>
+<thead><tr><th>Time</th><th>Kind</th><th>Summary</th><th>Duration</th></tr></thead>
+<tbody id="entries"></tbody>
+</table>
+</main>
+<script>
+let kind = "", request = null, open = null;
+const escape = (text) => String(text).replace(/[&<>"]/g, (c) => ({"&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;"}[c]));
+async function load() {
+  const params = new URLSearchParams();
+  if (kind) params.set("kind", kind);
+  if (request) params.set("request", request);
+  const response = await fetch("/torch/entries?" + params);
+  const { entries } = await response.json();
+  document.getElementById("filter").innerHTML = request
+    ? `Showing request #${request} <a href="#" onclick="request = null; load(); return false">show all</a>` : "";
+  document.getElementById("entries").innerHTML = entries.map((entry) => `
+    <tr class="entry ${entry.kind}" data-id="${entry.id}" data-request="${entry.kind === "request" ? entry.id : entry.request ?? ""}">
+      <td>${new Date(entry.at).toLocaleTimeString()}</td><td>${entry.kind}</td>
+      <td class="summary">${escape(entry.summary)}</td>
+      <td>${entry.duration_ms == null ? "" : entry.duration_ms.toFixed(1) + " ms"}</td>
+    </tr>
+    ${open === entry.id ? `<tr><td colspan="4"><pre>${escape(JSON.stringify(entry.details, null, 2))}</pre></td></tr>` : ""}`).join("");
+}
+document.querySelectorAll("nav button").forEach((button) => button.onclick = () => {
+  document.querySelectorAll("nav button").forEach((other) => other.classList.remove("active"));
+  button.classList.add("active");
+  kind = button.dataset.kind;
+  load();
+});
+document.getElementById("entries").onclick = (event) => {
+  const row = event.target.closest("tr.entry");
+  if (!row) return;
+  const id = Number(row.dataset.id);
+  // A request shows what it did; anything else shows its details
+  if (row.classList.contains("request") && request !== row.dataset.id) { request = row.dataset.id; }
+  else { open = open === id ? null : id; }
+  load();
+};
+document.getElementById("clear").onclick = async () => { await fetch("/torch/entries", { method: "DELETE" }); load(); };
+load();
+setInterval(load, 2000);
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+
+    #[tokio::test]
+    async fn test_inspector_records_requests_and_what_they_did() {
+        let app = App::new()
+            .inspector(Inspector::new().authorize(|req| req.header("x-inspect").is_some()))
+            .get("/users", |_req: Request| async {
+                record_query("SELECT * FROM users", 0, Duration::from_millis(3));
+                crate::cache::MemoryCache::new(None).get("inspector-test").await;
+                "users"
+            });
+        clear();
+
+        let response = app.handle_request(Request::mock(http::Method::GET, "/users")).await;
+        assert_eq!(response.status_code().as_u16(), 200);
+
+        let recorded = entries();
+        let request = recorded.iter().find(|entry| entry.summary == "GET /users -> 200").expect("request entry");
+        assert_eq!(request.details["status"], 200);
+        let query = recorded.iter().find(|entry| entry.kind == EntryKind::Query).unwrap();
+        assert_eq!((query.summary.as_str(), query.request), ("SELECT * FROM users", Some(request.id)));
+        let cache = recorded.iter().find(|entry| entry.kind == EntryKind::Cache && entry.summary.contains("inspector-test")).unwrap();
+        assert_eq!((cache.summary.as_str(), cache.request), ("get inspector-test (miss)", Some(request.id)));
+
+        // Protected, and filterable by kind
+        let hidden = app.handle_request(Request::mock(http::Method::GET, "/torch/entries")).await;
+        assert_eq!(hidden.status_code().as_u16(), 404);
+        let listed = app
+            .handle_request(Request::mock(http::Method::GET, "/torch/entries?kind=query").with_header("x-inspect", "1"))
+            .await;
+        let listed: Value = serde_json::from_slice(listed.body_data()).unwrap();
+        assert!(listed["entries"].as_array().unwrap().iter().all(|entry| entry["kind"] == "query"));
+        let dashboard = app.handle_request(Request::mock(http::Method::GET, "/torch").with_header("x-inspect", "1")).await;
+        assert!(String::from_utf8_lossy(dashboard.body_data()).contains("Inspector"));
+    }
+}
@@ -0,0 +1,334 @@
+//! # Request Profiler
+//!
+//! Breaks each request's time down into database queries, template
+//! rendering and everything else, and counts the allocations made while
+//! handling it:
+//!
+//! ```rust,no_run
+//! use torch_web::App;
+//! use torch_web::dev::profiler::{CountingAllocator, Profiler};
+//!
+//! // Optional: lets the profiler count allocations
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator::system();
+//!
+//! let app = App::new()
+//!     .middleware(Profiler::new())
+//!     .get("/", |_req: torch_web::Request| async { "Hello" });
+//! ```
+//!
+//! Every request then logs a line like
+//!
+//! ```text
+//! ⏱️  GET /posts 200 in 18.40ms: db 11.02ms (4 queries), render 3.10ms (1), other 4.28ms, 2841 allocations (212.6 KiB)
+//! ```
+//!
+//! and gets matching `Server-Timing` entries, which browser devtools show
+//! under the request's timing tab. Like the inspector, the profiler only
+//! measures in debug builds or under `torch serve`, so it can stay in
+//! production code.
+//!
+//! The ORM query builder and Ember report their time themselves. Queries
+//! and renders are counted when they run inside the request's task;
+//! work handed to `tokio::spawn` isn't.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+use super::inspector::dev_mode;
+
+tokio::task_local! {
+    /// Timings of the request being handled
+    static SAMPLE: Arc<Sample>;
+}
+
+#[derive(Debug, Default)]
+struct Sample {
+    db_nanos: AtomicU64,
+    queries: AtomicU64,
+    render_nanos: AtomicU64,
+    renders: AtomicU64,
+}
+
+/// Add a query that took `duration` to the current request's profile
+pub(crate) fn record_query(duration: Duration) {
+    let _ = SAMPLE.try_with(|sample| {
+        sample.db_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+        sample.queries.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// Add a template render that took `duration` to the current request's profile
+#[cfg(any(feature = "templates", test))]
+pub(crate) fn record_render(duration: Duration) {
+    let _ = SAMPLE.try_with(|sample| {
+        sample.render_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+        sample.renders.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+thread_local! {
+    /// Allocations and bytes allocated on this thread so far
+    static ALLOCATED: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+/// Set once a [`CountingAllocator`] has allocated anything
+static COUNTING: AtomicBool = AtomicBool::new(false);
+
+/// A global allocator that counts allocations per thread, so the
+/// [`Profiler`] can attribute them to requests. It adds a thread-local
+/// increment to every allocation, which is why it's opt-in.
+pub struct CountingAllocator<A = System> {
+    inner: A,
+}
+
+impl CountingAllocator<System> {
+    /// Count allocations made through the system allocator
+    pub const fn system() -> Self {
+        Self { inner: System }
+    }
+}
+
+impl<A> CountingAllocator<A> {
+    /// Count allocations made through `inner`
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+fn count(bytes: usize) {
+    // `try_with` because allocations can happen while the thread is exiting
+    let _ = ALLOCATED.try_with(|allocated| {
+        let (count, total) = allocated.get();
+        allocated.set((count + 1, total + bytes as u64));
+    });
+    if !COUNTING.load(Ordering::Relaxed) {
+        COUNTING.store(true, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        self.inner.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+}
+
+fn allocated() -> (u64, u64) {
+    ALLOCATED.try_with(Cell::get).unwrap_or_default()
+}
+
+/// Adds up what a future allocates while it is polled, whichever thread
+/// each poll runs on
+struct CountAllocations<F> {
+    inner: Pin<Box<F>>,
+    allocations: u64,
+    bytes: u64,
+}
+
+impl<F: Future> Future for CountAllocations<F> {
+    type Output = (F::Output, u64, u64);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (count_before, bytes_before) = allocated();
+        let poll = self.inner.as_mut().poll(cx);
+        let (count_after, bytes_after) = allocated();
+        self.allocations += count_after.saturating_sub(count_before);
+        self.bytes += bytes_after.saturating_sub(bytes_before);
+        match poll {
+            Poll::Ready(output) => Poll::Ready((output, self.allocations, self.bytes)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// One request's measurements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Profile {
+    pub total: Duration,
+    pub db: Duration,
+    pub queries: u64,
+    pub render: Duration,
+    pub renders: u64,
+    /// `None` without a [`CountingAllocator`]
+    pub allocations: Option<(u64, u64)>,
+}
+
+impl Profile {
+    /// Time not spent in queries or rendering
+    pub fn other(&self) -> Duration {
+        self.total.saturating_sub(self.db + self.render)
+    }
+
+    /// `Server-Timing` entries for the profile
+    pub fn server_timing(&self) -> String {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mut entries = vec![
+            format!("db;dur={:.2};desc=\"{} queries\"", ms(self.db), self.queries),
+            format!("render;dur={:.2};desc=\"{} renders\"", ms(self.render), self.renders),
+            format!("other;dur={:.2}", ms(self.other())),
+            format!("total;dur={:.2}", ms(self.total)),
+        ];
+        if let Some((count, bytes)) = self.allocations {
+            entries.push(format!("alloc;desc=\"{} allocations, {}\"", count, kib(bytes)));
+        }
+        entries.join(", ")
+    }
+}
+
+fn kib(bytes: u64) -> String {
+    format!("{:.1} KiB", bytes as f64 / 1024.0)
+}
+
+/// Middleware measuring every request, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    log: bool,
+    header: bool,
+    slower_than: Duration,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            log: true,
+            header: true,
+            slower_than: Duration::ZERO,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to log a line per request (default `true`)
+    pub fn log(mut self, log: bool) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Whether to add `Server-Timing` entries (default `true`)
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Only report requests that take longer than `threshold`
+    pub fn slower_than(mut self, threshold: Duration) -> Self {
+        self.slower_than = threshold;
+        self
+    }
+}
+
+impl Middleware for Profiler {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if !dev_mode() {
+            return next(req);
+        }
+        let settings = self.clone();
+        let summary = format!("{} {}", req.method(), req.uri());
+        Box::pin(async move {
+            let sample = Arc::new(Sample::default());
+            let started = Instant::now();
+            let counted = CountAllocations { inner: Box::pin(SAMPLE.scope(sample.clone(), next(req))), allocations: 0, bytes: 0 };
+            let (response, allocations, bytes) = counted.await;
+
+            let profile = Profile {
+                total: started.elapsed(),
+                db: Duration::from_nanos(sample.db_nanos.load(Ordering::Relaxed)),
+                queries: sample.queries.load(Ordering::Relaxed),
+                render: Duration::from_nanos(sample.render_nanos.load(Ordering::Relaxed)),
+                renders: sample.renders.load(Ordering::Relaxed),
+                allocations: COUNTING.load(Ordering::Relaxed).then_some((allocations, bytes)),
+            };
+            if profile.total < settings.slower_than {
+                return response;
+            }
+
+            if settings.log {
+                let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+                let allocations = profile
+                    .allocations
+                    .map(|(count, bytes)| format!(", {} allocations ({})", count, kib(bytes)))
+                    .unwrap_or_default();
+                eprintln!(
+                    "⏱️  {} {} in {:.2}ms: db {:.2}ms ({} queries), render {:.2}ms ({}), other {:.2}ms{}",
+                    summary,
+                    response.status_code().as_u16(),
+                    ms(profile.total),
+                    ms(profile.db),
+                    profile.queries,
+                    ms(profile.render),
+                    profile.renders,
+                    ms(profile.other()),
+                    allocations
+                );
+            }
+            if !settings.header {
+                return response;
+            }
+            let server_timing = match response.headers().get("server-timing").and_then(|value| value.to_str().ok()) {
+                Some(existing) => format!("{}, {}", existing, profile.server_timing()),
+                None => profile.server_timing(),
+            };
+            response.header("server-timing", server_timing)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+
+    // Allocation counting needs the CountingAllocator installed as the
+    // global allocator, so it is tested in tests/profiler.rs
+
+    #[tokio::test]
+    async fn test_profiler_breaks_requests_down() {
+        let app = App::new().middleware(Profiler::new().log(false)).get("/posts", |_req: Request| async {
+            record_query(Duration::from_millis(4));
+            record_query(Duration::from_millis(2));
+            record_render(Duration::from_millis(3));
+            tokio::task::yield_now().await;
+            "posts"
+        });
+
+        let response = app.handle_request(Request::mock(http::Method::GET, "/posts")).await;
+        let timing = response.headers().get("server-timing").unwrap().to_str().unwrap();
+        assert!(timing.starts_with("db;dur=6.00;desc=\"2 queries\", render;dur=3.00;desc=\"1 renders\", other;dur="), "{}", timing);
+        assert!(!timing.contains("alloc;"), "{}", timing);
+
+        // Outside a profiled request nothing is recorded
+        record_query(Duration::from_millis(1));
+    }
+}
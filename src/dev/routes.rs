@@ -0,0 +1,94 @@
+//! # Route Listing
+//!
+//! Serves the app's routing table while it runs: a page at `/torch/routes`
+//! and JSON at `/torch/routes.json`, listing each route's method, path,
+//! name, handler, middleware and the `file:line` it was registered at.
+//!
+//! ```rust,no_run
+//! use torch_web::App;
+//!
+//! let app = App::new()
+//!     .route_list()
+//!     .get("/users/:id", |_req: torch_web::Request| async { "user" })
+//!     .name("users.show");
+//! ```
+//!
+//! `torch route list --server http://127.0.0.1:3000` reads the JSON instead
+//! of building the app, so it shows exactly what the running server routes.
+//!
+//! Like the inspector, the routes only answer in debug builds or under
+//! `torch serve`, and only to clients on the local machine.
+
+use std::sync::Arc;
+
+use serde_json::json;
+
+use super::inspector::dev_mode;
+use crate::router::RouteInfo;
+use crate::{Request, Response};
+
+/// The routing table, handed to the listing by [`App::handle_request`](crate::App::handle_request)
+#[derive(Clone)]
+pub(crate) struct RouteTable(pub(crate) Arc<Vec<RouteInfo>>);
+
+/// `GET /torch/routes` and `GET /torch/routes.json`
+pub(crate) async fn serve(req: Request) -> Response {
+    let local = req.remote_addr().is_some_and(|addr| addr.ip().is_loopback());
+    let Some(table) = req.get_extension::<RouteTable>().filter(|_| local && dev_mode()) else {
+        return Response::not_found();
+    };
+    if req.path().ends_with(".json") {
+        return Response::ok().json(&json!({ "routes": *table.0 })).unwrap_or_else(|_| Response::internal_error());
+    }
+
+    let mut rows = String::new();
+    for route in table.0.iter() {
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code><br><small>{}</small></td><td>{}</td></tr>\n",
+            route.method,
+            escape(&route.path),
+            escape(route.name.as_deref().unwrap_or("")),
+            escape(&route.handler),
+            escape(route.location.as_deref().unwrap_or("")),
+            escape(&route.middleware.join(", ")),
+        ));
+    }
+    Response::ok().html(format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Routes</title></head>\n<body>\n<h1>Routes</h1>\n<table>\n<tr><th>Method</th><th>URI</th><th>Name</th><th>Handler</th><th>Middleware</th></tr>\n{}</table>\n</body>\n</html>\n",
+        rows
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{App, Request};
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_route_list_serves_the_routing_table() {
+        let app = App::new()
+            .route_list()
+            .get("/users/:id", |_req: Request| async { "user" })
+            .name("users.show");
+        let local = |path: &str| Request::mock(http::Method::GET, path).with_remote_addr(([127, 0, 0, 1], 4000).into());
+
+        let listed = app.handle_request(local("/torch/routes.json")).await;
+        let listed: Value = serde_json::from_slice(listed.body_data()).unwrap();
+        let route = listed["routes"].as_array().unwrap().iter().find(|route| route["path"] == "/users/:id").unwrap();
+        assert_eq!((route["method"].as_str(), route["name"].as_str()), (Some("GET"), Some("users.show")));
+        assert!(route["location"].as_str().unwrap().starts_with(&format!("{}:", file!())));
+
+        let page = app.handle_request(local("/torch/routes")).await;
+        assert!(String::from_utf8_lossy(page.body_data()).contains("<td>/users/:id</td>"));
+
+        let remote = Request::mock(http::Method::GET, "/torch/routes.json").with_remote_addr(([203, 0, 113, 9], 4000).into());
+        assert_eq!(app.handle_request(remote).await.status_code().as_u16(), 404);
+    }
+}
@@ -44,10 +44,139 @@
 //!     @endif
 //! @endsection
 //! ```
+//!
+//! ## Fragments
+//!
+//! Wrap part of a page in `@fragment('name')` ... `@endfragment` to render
+//! just that part for htmx or Turbo requests, without a separate partial:
+//!
+//! ```html
+//! @fragment('table')
+//!     <table id="users">...</table>
+//! @endfragment
+//! ```
+//!
+//! ```rust,no_run
+//! use torch_web::{Request, Response, ember::*};
+//!
+//! async fn users(req: Request) -> Response {
+//!     let data = EmberData::new().with("users", vec!["Alice", "Bob"]);
+//!     // The whole page normally, only the table when htmx asks for it
+//!     ember_for(&req, "users/index", "table", data).await
+//! }
+//! ```
+//!
+//! A `@section` of the template can be rendered the same way.
+//!
+//! ## Fragment caching
+//!
+//! Wrap an expensive part of a page in `@cache('name', seconds)` ...
+//! `@endcache` to render it once and reuse the HTML until it expires:
+//!
+//! ```html
+//! @cache('sidebar', 300)
+//!     @foreach($categories as $category)
+//!         <a href="/c/{{ $category.slug }}">{{ $category.name }}</a>
+//!     @endforeach
+//! @endcache
+//! ```
+//!
+//! Each combination of the variables the block uses gets its own entry, so
+//! `{{ $user.name }}` inside the block caches once per user, and the
+//! locale is part of the key too. Leave out the seconds to keep the
+//! fragment until it is forgotten with [`forget_fragment`] (one fragment)
+//! or [`flush_fragments`] (all of them). Fragments are kept in memory,
+//! or in the store set with [`use_fragment_cache`]. A cached block is
+//! rendered with the page's variables, so it can't sit inside `@foreach`.
+//!
+//! ## Streaming
+//!
+//! [`ember_stream`] sends the page before its slow data is ready. Values
+//! passed with [`StreamData::defer`] are awaited after the rest of the page
+//! went out, and the `@await` blocks using them follow as each completes,
+//! swapped in for their `@placeholder` by a small inline script:
+//!
+//! ```rust,no_run
+//! use torch_web::{Response, ember::*};
+//!
+//! async fn post() -> Response {
+//!     let data = EmberData::new().with("title", "Streaming");
+//!     let data = StreamData::new(data).defer("comments", async {
+//!         // e.g. a slow query
+//!         vec!["First!", "Nice post"]
+//!     });
+//!     ember_stream("post", data).await
+//! }
+//! ```
+//!
+//! ```html
+//! <h1>{{ $title }}</h1>
+//! @await('comments')
+//!     @foreach($comments as $comment)<p>{{ $comment }}</p>@endforeach
+//! @placeholder
+//!     <p>Loading comments...</p>
+//! @endawait
+//! ```
+//!
+//! Everything before `</body>` is sent as soon as it is rendered, the
+//! closing tags once every block is in. `ember` renders `@await` blocks in
+//! place, with the data it was given.
+//!
+//! ## Assets
+//!
+//! `@asset('resources/css/app.css')` prints the URL of the built file and
+//! `@vite('resources/js/app.js')` (or `@vite(['resources/css/app.css',
+//! 'resources/js/app.js'])`) the tags that load Vite entry points, both
+//! resolved through the bundler manifest, see [`crate::assets`].
+//!
+//! ## Forms
+//!
+//! When validation fails, redirect back with [`back_with_errors`]. The
+//! [`form_state`] middleware shares the errors and the submitted input with
+//! the templates of the next request:
+//!
+//! ```html
+//! <input name="email" value="{{ old('email') }}">
+//! @error('email')
+//!     <p class="error">{{ $message }}</p>
+//! @enderror
+//! ```
+//!
+//! `old('field', 'default')` and `old('field', $user.email)` fall back to a
+//! default when nothing was submitted. Both helpers escape their output.
+//! Fields with `password` in their name are never kept as old input.
+//!
+//! ## Filters
+//!
+//! Pipe a variable through filters to format it for the current locale
+//! (see [`crate::i18n`]):
+//!
+//! ```html
+//! <time>{{ $post.created_at | date('j F Y') }}</time> ({{ $post.created_at | since }})
+//! {{ $downloads | number }} downloads, {{ $price | currency('EUR') }}
+//! ```
+//!
+//! `date` takes a PHP `date()` style format and defaults to the locale's
+//! short date; it reads RFC 3339 timestamps, `YYYY-MM-DD` dates and Unix
+//! seconds and prints them in UTC. `number` takes the number of decimals
+//! (`| number(2)`). `upper` and `lower` change case.
+//!
+//! ## Pagination
+//!
+//! `@pagination($posts)` renders previous/next and page number links for an
+//! ORM `Paginated` result passed as JSON. Pages within two of the current
+//! one are linked, along with the first and last; pass a different window
+//! as `@pagination($posts, 3)`. Nothing is rendered when there is only one
+//! page.
+//!
+//! ```rust,ignore
+//! let posts = Post::query().paginate(page, 20).await?.with_request(&req);
+//! ember("posts/index", EmberData::new().with("posts", serde_json::to_value(&posts)?)).await
+//! ```
 
 use std::collections::HashMap;
 use std::path::PathBuf;
-use crate::Response;
+use crate::{Request, Response};
 
 #[cfg(feature = "templates")]
 use {
@@ -100,6 +229,7 @@ struct CompiledTemplate {
 }
 
 /// Template engine instance
+#[derive(Clone)]
 pub struct EmberEngine {
     #[allow(dead_code)] // Used in future configuration implementation
     config: EmberConfig,
@@ -113,14 +243,21 @@ pub struct EmberError {
     pub message: String,
     pub template: Option<String>,
     pub line: Option<usize>,
+    /// The directive at fault, e.g. `@endif`
+    pub directive: Option<String>,
 }
 
 impl std::fmt::Display for EmberError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match (&self.template, self.line) {
-            (Some(template), Some(line)) => {
-                write!(f, "Ember error in template '{}' at line {}: {}", template, line, self.message)
-            }
+            (Some(template), Some(line)) => match &self.directive {
+                Some(directive) => write!(
+                    f,
+                    "Ember error in template '{}' at line {} ({}): {}",
+                    template, line, directive, self.message
+                ),
+                None => write!(f, "Ember error in template '{}' at line {}: {}", template, line, self.message),
+            },
             (Some(template), None) => {
                 write!(f, "Ember error in template '{}': {}", template, self.message)
             }
@@ -167,6 +304,19 @@ impl EmberData {
         self.data.get(key)
     }
 
+    /// Get a value by dotted path, descending into objects (`post.title`)
+    pub fn lookup(&self, path: &str) -> Option<&EmberValue> {
+        let mut segments = path.split('.');
+        let mut value = self.data.get(segments.next()?)?;
+        for segment in segments {
+            match value {
+                EmberValue::Object(fields) => value = fields.get(segment)?,
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
     /// Get all data as a reference to the internal HashMap
     pub fn as_map(&self) -> &HashMap<String, EmberValue> {
         &self.data
@@ -263,7 +413,10 @@ impl EmberEngine {
     pub async fn render(&self, template_name: &str, data: EmberData) -> Result<String, EmberError> {
         #[cfg(feature = "templates")]
         {
-            self.render_template(template_name, data).await
+            let started = std::time::Instant::now();
+            let rendered = self.render_template(template_name, with_form_state(data)).await;
+            crate::dev::profiler::record_render(started.elapsed());
+            rendered
         }
 
         #[cfg(not(feature = "templates"))]
@@ -273,11 +426,316 @@ impl EmberEngine {
                 message: "Template feature not enabled. Add 'templates' feature to use Ember.".to_string(),
                 template: Some(template_name.to_string()),
                 line: None,
+                directive: None,
+            })
+        }
+    }
+
+    /// Render only the `@fragment` (or `@section`) called `fragment` of a
+    /// template, without its layout
+    pub async fn render_fragment(&self, template_name: &str, fragment: &str, data: EmberData) -> Result<String, EmberError> {
+        #[cfg(feature = "templates")]
+        {
+            let compiled = self.load_template(template_name).await?;
+            let Some(body) = find_fragment(&compiled.content, fragment) else {
+                return Err(EmberError {
+                    message: format!("Template has no @fragment or @section named '{}'", fragment),
+                    template: Some(template_name.to_string()),
+                    line: None,
+                    directive: Some("@fragment".to_string()),
+                });
+            };
+            let started = std::time::Instant::now();
+            let rendered = self.execute_with_cache(template_name, body, &with_form_state(data)).await;
+            crate::dev::profiler::record_render(started.elapsed());
+            rendered
+        }
+
+        #[cfg(not(feature = "templates"))]
+        {
+            let _ = (fragment, data); // Suppress unused variable warnings
+            Err(EmberError {
+                message: "Template feature not enabled. Add 'templates' feature to use Ember.".to_string(),
+                template: Some(template_name.to_string()),
+                line: None,
+                directive: None,
             })
         }
     }
 }
 
+/// Validation messages by field name, shared with templates for
+/// `@error('field')`
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorBag {
+    messages: HashMap<String, Vec<String>>,
+}
+
+impl ErrorBag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.messages.entry(field.to_string()).or_default().push(message.into());
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.messages.get(field).is_some_and(|messages| !messages.is_empty())
+    }
+
+    /// The first message for `field`
+    pub fn first(&self, field: &str) -> Option<&str> {
+        self.messages.get(field)?.first().map(String::as_str)
+    }
+
+    pub fn get(&self, field: &str) -> &[String] {
+        self.messages.get(field).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.values().all(Vec::is_empty)
+    }
+
+    /// Every message, for a summary at the top of a form
+    pub fn all(&self) -> Vec<String> {
+        let mut fields: Vec<&String> = self.messages.keys().collect();
+        fields.sort();
+        fields.into_iter().flat_map(|field| self.messages[field].clone()).collect()
+    }
+}
+
+impl From<&ErrorBag> for EmberValue {
+    fn from(errors: &ErrorBag) -> Self {
+        EmberValue::Object(
+            errors
+                .messages
+                .iter()
+                .map(|(field, messages)| (field.clone(), EmberValue::from(messages.clone())))
+                .collect(),
+        )
+    }
+}
+
+impl EmberData {
+    /// Share validation errors with `@error('field')`
+    pub fn with_errors(self, errors: &ErrorBag) -> Self {
+        self.with(ERRORS_KEY, errors)
+    }
+
+    /// Share submitted input with `{{ old('field') }}`
+    pub fn with_old(self, input: &HashMap<String, String>) -> Self {
+        let old: HashMap<String, EmberValue> = input
+            .iter()
+            .map(|(field, value)| (field.clone(), EmberValue::from(value.as_str())))
+            .collect();
+        self.with(OLD_KEY, old)
+    }
+}
+
+/// Template variable holding the [`ErrorBag`]
+const ERRORS_KEY: &str = "errors";
+
+/// Template variable holding the previous input
+const OLD_KEY: &str = "old";
+
+/// Cookie carrying errors and input to the request after a redirect
+#[cfg(feature = "templates")]
+pub const FORM_STATE_COOKIE: &str = "torch_form";
+
+/// Browsers drop cookies larger than about 4 KB
+#[cfg(feature = "templates")]
+const MAX_FORM_STATE_COOKIE: usize = 3800;
+
+/// Errors and input flashed by [`back_with_errors`]
+#[cfg(feature = "templates")]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+struct FormState {
+    errors: ErrorBag,
+    old: HashMap<String, String>,
+}
+
+#[cfg(feature = "templates")]
+tokio::task_local! {
+    static FORM_STATE: FormState;
+}
+
+/// Redirect to the page the form was submitted from, taking `errors` and
+/// `input` along for the next request's templates (see [`form_state`]).
+/// Password fields are left out of the input.
+#[cfg(feature = "templates")]
+pub fn back_with_errors(req: &Request, errors: &ErrorBag, input: &HashMap<String, String>) -> Response {
+    let old: HashMap<String, String> = input
+        .iter()
+        .filter(|(field, _)| !field.to_ascii_lowercase().contains("password"))
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect();
+    let mut state = FormState { errors: errors.clone(), old };
+    let mut value = encode_form_state(&state);
+    if value.len() > MAX_FORM_STATE_COOKIE {
+        // Long input (an article body, say) has to be typed again, but the errors survive
+        state.old.clear();
+        value = encode_form_state(&state);
+    }
+
+    Response::redirect(http::StatusCode::SEE_OTHER, &back_path(req)).header(
+        "set-cookie",
+        format!("{}={}; Path=/; HttpOnly; SameSite=Lax", FORM_STATE_COOKIE, value),
+    )
+}
+
+/// Middleware sharing what [`back_with_errors`] flashed with every template
+/// rendered for the next request, then forgetting it
+#[cfg(feature = "templates")]
+pub fn form_state() -> FormStateMiddleware {
+    FormStateMiddleware
+}
+
+/// See [`form_state`]
+#[cfg(feature = "templates")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormStateMiddleware;
+
+#[cfg(feature = "templates")]
+impl crate::middleware::Middleware for FormStateMiddleware {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            let Some(state) = req.header("cookie").and_then(decode_form_state) else {
+                return next(req).await;
+            };
+
+            let mut response = FORM_STATE.scope(state, next(req)).await;
+            let flashed_again = response
+                .headers()
+                .get_all(http::header::SET_COOKIE)
+                .iter()
+                .any(|cookie| cookie.to_str().is_ok_and(|cookie| cookie.starts_with(FORM_STATE_COOKIE)));
+            if !flashed_again {
+                let expired = format!("{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0", FORM_STATE_COOKIE);
+                if let Ok(expired) = http::HeaderValue::from_str(&expired) {
+                    response.headers_mut().append(http::header::SET_COOKIE, expired);
+                }
+            }
+            response
+        })
+    }
+}
+
+#[cfg(feature = "templates")]
+fn encode_form_state(state: &FormState) -> String {
+    urlencoding::encode(&serde_json::to_string(state).unwrap_or_default()).into_owned()
+}
+
+/// The flashed state in a `Cookie` header, if any
+#[cfg(feature = "templates")]
+fn decode_form_state(cookies: &str) -> Option<FormState> {
+    let value = cookies.split(';').find_map(|cookie| {
+        let (name, value) = cookie.trim().split_once('=')?;
+        (name == FORM_STATE_COOKIE).then_some(value)
+    })?;
+    serde_json::from_str(&urlencoding::decode(value).ok()?).ok()
+}
+
+/// Path of the `Referer`, which stays on this site, or `/`
+#[cfg(feature = "templates")]
+fn back_path(req: &Request) -> String {
+    let Some(referer) = req.header("referer") else {
+        return "/".to_string();
+    };
+    let path = match referer.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |start| &rest[start..]),
+        None => referer,
+    };
+    if path.starts_with('/') && !path.starts_with("//") {
+        path.to_string()
+    } else {
+        "/".to_string()
+    }
+}
+
+/// Add the flashed errors and input to `data`, unless the handler passed
+/// its own
+#[cfg(feature = "templates")]
+fn with_form_state(mut data: EmberData) -> EmberData {
+    let _ = FORM_STATE.try_with(|state| {
+        if data.get(ERRORS_KEY).is_none() {
+            data.insert(ERRORS_KEY, &state.errors);
+        }
+        if data.get(OLD_KEY).is_none() {
+            data = std::mem::take(&mut data).with_old(&state.old);
+        }
+    });
+    data
+}
+
+/// Store holding `@cache` fragments
+#[cfg(feature = "templates")]
+static FRAGMENT_CACHE: std::sync::RwLock<Option<Arc<dyn crate::cache::Cache>>> = std::sync::RwLock::new(None);
+
+#[cfg(feature = "templates")]
+fn fragment_cache() -> Arc<dyn crate::cache::Cache> {
+    if let Some(fake) = crate::cache::active_fake() {
+        return fake;
+    }
+    let mut cache = FRAGMENT_CACHE.write().unwrap();
+    cache.get_or_insert_with(|| Arc::new(crate::cache::MemoryCache::new(None))).clone()
+}
+
+/// Keep `@cache` fragments in `cache`, such as a `RedisCache` shared by
+/// every server, instead of in process memory
+#[cfg(feature = "templates")]
+pub fn use_fragment_cache(cache: Arc<dyn crate::cache::Cache>) {
+    *FRAGMENT_CACHE.write().unwrap() = Some(cache);
+}
+
+/// Cache key holding the generation of fragment `name`, or of every
+/// fragment for `None`. Forgetting moves to a new generation, so old
+/// entries are never read again and simply expire.
+#[cfg(feature = "templates")]
+fn fragment_generation_key(name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("torch:ember:{}:generation", name),
+        None => "torch:ember:generation".to_string(),
+    }
+}
+
+#[cfg(feature = "templates")]
+async fn new_fragment_generation(name: Option<&str>) -> Result<(), EmberError> {
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let generation = format!("{:x}{:x}", nanos, COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst));
+    fragment_cache().set(&fragment_generation_key(name), &generation, None).await.map_err(|error| EmberError {
+        message: format!("Could not forget cached fragments: {}", error),
+        template: None,
+        line: None,
+        directive: Some("@cache".to_string()),
+    })
+}
+
+/// Drop every cached copy of `@cache('name')`, e.g. after the data it
+/// shows changed
+#[cfg(feature = "templates")]
+pub async fn forget_fragment(name: &str) -> Result<(), EmberError> {
+    new_fragment_generation(Some(name)).await
+}
+
+/// Drop every cached `@cache` fragment
+#[cfg(feature = "templates")]
+pub async fn flush_fragments() -> Result<(), EmberError> {
+    new_fragment_generation(None).await
+}
+
+/// An `@await('value')` block, with its body and optional `@placeholder`
+#[cfg(feature = "templates")]
+static AWAIT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?s)@await\s*\(\s*['"]([^'"]+)['"]\s*\)(.*?)(?:@placeholder(.*?))?@endawait"#).unwrap()
+});
+
 /// Global Ember engine instance
 #[cfg(feature = "templates")]
 static EMBER_ENGINE: Lazy<EmberEngine> = Lazy::new(|| EmberEngine::new());
@@ -286,14 +744,7 @@ static EMBER_ENGINE: Lazy<EmberEngine> = Lazy::new(|| EmberEngine::new());
 pub async fn ember(template_name: &str, data: EmberData) -> Response {
     #[cfg(feature = "templates")]
     {
-        match EMBER_ENGINE.render(template_name, data).await {
-            Ok(html) => Response::ok().html(html),
-            Err(err) => {
-                eprintln!("Ember template error: {}", err);
-                Response::internal_error()
-                    .html(format!("<h1>Template Error</h1><p>{}</p>", err))
-            }
-        }
+        html_response(EMBER_ENGINE.render(template_name, data).await)
     }
 
     #[cfg(not(feature = "templates"))]
@@ -304,6 +755,122 @@ pub async fn ember(template_name: &str, data: EmberData) -> Response {
     }
 }
 
+/// Render one `@fragment` of a template using the global Ember engine, e.g.
+/// the rows of a table after an htmx request
+pub async fn ember_fragment(template_name: &str, fragment: &str, data: EmberData) -> Response {
+    #[cfg(feature = "templates")]
+    {
+        html_response(EMBER_ENGINE.render_fragment(template_name, fragment, data).await)
+    }
+
+    #[cfg(not(feature = "templates"))]
+    {
+        let _ = (template_name, fragment, data); // Suppress unused variable warnings
+        Response::internal_error()
+            .html("<h1>Template Error</h1><p>Template feature not enabled. Add 'templates' feature to use Ember.</p>")
+    }
+}
+
+/// Render the whole template, or just `fragment` when the request comes
+/// from htmx or a Turbo frame, see [`wants_fragment`]
+pub async fn ember_for(req: &Request, template_name: &str, fragment: &str, data: EmberData) -> Response {
+    let response = if wants_fragment(req) {
+        ember_fragment(template_name, fragment, data).await
+    } else {
+        ember(template_name, data).await
+    };
+    // Full pages and fragments share a URL, so caches must tell them apart
+    response.header("Vary", "HX-Request, Turbo-Frame")
+}
+
+/// A value [`ember_stream`] sends the page without, see [`StreamData::defer`]
+type Deferred = std::pin::Pin<Box<dyn std::future::Future<Output = EmberValue> + Send>>;
+
+/// Data for [`ember_stream`]: what the page needs up front, and values it
+/// renders `@await` blocks with once they resolve
+pub struct StreamData {
+    #[cfg(feature = "templates")]
+    data: EmberData,
+    deferred: Vec<(String, Deferred)>,
+}
+
+impl StreamData {
+    pub fn new(data: EmberData) -> Self {
+        #[cfg(not(feature = "templates"))]
+        let _ = data; // Only rendered with the templates feature
+        Self {
+            #[cfg(feature = "templates")]
+            data,
+            deferred: Vec::new(),
+        }
+    }
+
+    /// Render the `@await('key')` blocks once `value` resolves, showing
+    /// their `@placeholder` until then
+    pub fn defer<K, F, V>(mut self, key: K, value: F) -> Self
+    where
+        K: Into<String>,
+        F: std::future::Future<Output = V> + Send + 'static,
+        V: Into<EmberValue>,
+    {
+        self.deferred.push((key.into(), Box::pin(async move { value.await.into() })));
+        self
+    }
+}
+
+impl From<EmberData> for StreamData {
+    fn from(data: EmberData) -> Self {
+        Self::new(data)
+    }
+}
+
+/// Render a template using the global Ember engine, sending the page as
+/// soon as it is rendered and its `@await` blocks as their data resolves.
+/// See [Streaming](self#streaming).
+pub async fn ember_stream(template_name: &str, data: impl Into<StreamData>) -> Response {
+    #[cfg(feature = "templates")]
+    {
+        match EMBER_ENGINE.render_stream(template_name, data.into()).await {
+            Ok(chunks) => Response::ok()
+                .header("Content-Type", "text/html; charset=utf-8")
+                .header("X-Accel-Buffering", "no")
+                .stream(chunks),
+            Err(err) => html_response(Err(err)),
+        }
+    }
+
+    #[cfg(not(feature = "templates"))]
+    {
+        let _ = (template_name, data.into()); // Suppress unused variable warnings
+        Response::internal_error()
+            .html("<h1>Template Error</h1><p>Template feature not enabled. Add 'templates' feature to use Ember.</p>")
+    }
+}
+
+/// Whether the request asks for part of a page: an htmx request
+/// (`HX-Request`) that isn't boosted or restoring history, both of which
+/// swap in the whole page, or a Turbo frame request (`Turbo-Frame`)
+pub fn wants_fragment(req: &Request) -> bool {
+    let flag = |name: &str| req.header(name).is_some_and(|value| value.eq_ignore_ascii_case("true"));
+    let htmx = flag("HX-Request") && !flag("HX-Boosted") && !flag("HX-History-Restore-Request");
+    htmx || req.header("Turbo-Frame").is_some()
+}
+
+#[cfg(feature = "templates")]
+fn html_response(result: Result<String, EmberError>) -> Response {
+    match result {
+        Ok(html) => Response::ok().html(html),
+        Err(err) => {
+            eprintln!("Ember template error: {}", err);
+            if std::env::var_os(crate::app::DEV_SERVER_ENV).is_some() {
+                return Response::internal_error().html(EMBER_ENGINE.error_overlay(&err));
+            }
+            Response::internal_error()
+                .html(format!("<h1>Template Error</h1><p>{}</p>", err))
+        }
+    }
+}
+
 /// Render a template with no data
 pub async fn ember_view(template_name: &str) -> Response {
     ember(template_name, EmberData::new()).await
@@ -317,7 +884,126 @@ impl EmberEngine {
         let compiled = self.load_template(template_name).await?;
 
         // Render the compiled template with data
-        self.execute_template(&compiled.content, &data)
+        self.execute_with_cache(template_name, &compiled.content, &data).await
+    }
+
+    /// Render like [`execute_template`](Self::execute_template), taking
+    /// `@cache` blocks from the fragment cache or storing them there.
+    ///
+    /// Cached HTML is put back only after everything else was rendered, so
+    /// the rest of the pipeline never reprocesses it.
+    async fn execute_with_cache(&self, template_name: &str, compiled: &str, data: &EmberData) -> Result<String, EmberError> {
+        static CACHE_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"(?s)@cache\s*\(\s*['"]([^'"]+)['"]\s*(?:,\s*(\d+)\s*)?\)(.*?)@endcache"#).unwrap()
+        });
+
+        if !compiled.contains("@cache") {
+            return self.execute_template(template_name, compiled, data);
+        }
+        // Blocks may sit in sections filled into a layout
+        let content = self.process_inheritance(compiled, template_name)?;
+
+        let cache = fragment_cache();
+        let global = cache.get(&fragment_generation_key(None)).await.unwrap_or_default();
+        let mut fragments = Vec::new();
+        let mut result = String::with_capacity(content.len());
+        let mut last = 0;
+        for caps in CACHE_REGEX.captures_iter(&content) {
+            let (name, body) = (&caps[1], &caps[3]);
+            let ttl = caps.get(2).and_then(|seconds| seconds.as_str().parse().ok()).map(std::time::Duration::from_secs);
+            let generation = cache.get(&fragment_generation_key(Some(name))).await.unwrap_or_default();
+            let key = format!("torch:ember:{}:{}.{}:{:016x}", name, global, generation, fragment_fingerprint(body, data));
+
+            let html = match cache.get(&key).await {
+                Some(html) => html,
+                None => {
+                    let html = self.execute_template(template_name, body, data)?;
+                    if let Err(error) = cache.set(&key, &html, ttl).await {
+                        eprintln!("⚠️  Could not cache fragment {}: {}", name, error);
+                    }
+                    html
+                }
+            };
+
+            let whole = caps.get(0).unwrap();
+            result.push_str(&content[last..whole.start()]);
+            result.push_str(&format!("\u{1}cache:{}\u{1}", fragments.len()));
+            last = whole.end();
+            fragments.push(html);
+        }
+        result.push_str(&content[last..]);
+
+        let mut html = self.execute_template(template_name, &result, data)?;
+        for (index, fragment) in fragments.iter().enumerate() {
+            html = html.replacen(&format!("\u{1}cache:{}\u{1}", index), fragment, 1);
+        }
+        Ok(html)
+    }
+
+    /// Render the page with the `@await` blocks of deferred values replaced
+    /// by their placeholders, then each block as its value resolves
+    async fn render_stream(&self, template_name: &str, data: StreamData) -> Result<crate::response::BodyStream, EmberError> {
+        use futures::stream::{self, FuturesUnordered, StreamExt};
+
+        let started = std::time::Instant::now();
+        let compiled = self.load_template(template_name).await?;
+        let content = self.process_inheritance(&compiled.content, template_name)?;
+        let StreamData { data, deferred } = data;
+        let data = with_form_state(data);
+
+        // Blocks waiting on a deferred value as (value, body, placeholder)
+        let mut blocks = Vec::new();
+        let shell = AWAIT_REGEX.replace_all(&content, |caps: &regex::Captures| {
+            if !deferred.iter().any(|(key, _)| key == &caps[1]) {
+                return caps[0].to_string();
+            }
+            blocks.push((caps[1].to_string(), caps[2].to_string(), caps.get(3).map_or("", |m| m.as_str()).to_string()));
+            format!("\u{1}await:{}\u{1}", blocks.len() - 1)
+        });
+        let mut page = self.execute_with_cache(template_name, &shell, &data).await?;
+        for (index, (_, _, placeholder)) in blocks.iter().enumerate() {
+            let placeholder = self.execute_template(template_name, placeholder, &data)?;
+            page = page.replacen(
+                &format!("\u{1}await:{}\u{1}", index),
+                &format!("<div id=\"ember-await-{}\">{}</div>", index, placeholder),
+                1,
+            );
+        }
+        crate::dev::profiler::record_render(started.elapsed());
+
+        // Keep the document open until the blocks are in
+        let tail = page.to_ascii_lowercase().rfind("</body>").map_or(String::new(), |at| page.split_off(at));
+        let waiting: FuturesUnordered<_> = deferred
+            .into_iter()
+            .map(|(key, value)| async move { (key, value.await) })
+            .collect();
+
+        let engine = self.clone();
+        let template_name = template_name.to_string();
+        let blocks = std::sync::Arc::new(blocks);
+        let resolved = waiting.then(move |(key, value)| {
+            let (engine, template_name, blocks) = (engine.clone(), template_name.clone(), blocks.clone());
+            let data = data.clone().with(key.clone(), value);
+            async move {
+                let mut html = String::new();
+                for (index, (_, body, _)) in blocks.iter().enumerate().filter(|(_, (name, _, _))| *name == key) {
+                    match engine.execute_with_cache(&template_name, body, &data).await {
+                        Ok(block) => html.push_str(&format!(
+                            "<template id=\"ember-await-{index}-content\">{block}</template>\
+                             <script>(function(){{var t=document.getElementById(\"ember-await-{index}-content\"),\
+                             p=document.getElementById(\"ember-await-{index}\");if(t&&p){{p.replaceWith(t.content);t.remove();}}}})();</script>"
+                        )),
+                        Err(err) => eprintln!("⚠️  Ember could not render @await('{}') in {}: {}", key, template_name, err),
+                    }
+                }
+                hyper::body::Bytes::from(html)
+            }
+        });
+
+        let chunks = stream::once(async move { hyper::body::Bytes::from(page) })
+            .chain(resolved.filter(|chunk| futures::future::ready(!chunk.is_empty())))
+            .chain(stream::once(async move { hyper::body::Bytes::from(tail) }));
+        Ok(Box::pin(chunks))
     }
 
     /// Load and compile a template, using cache if available
@@ -330,6 +1016,7 @@ impl EmberEngine {
                 message: format!("Template file not found: {}", template_path.display()),
                 template: Some(template_name.to_string()),
                 line: None,
+                directive: None,
             });
         }
 
@@ -338,6 +1025,7 @@ impl EmberEngine {
             message: format!("Failed to read template metadata: {}", e),
             template: Some(template_name.to_string()),
             line: None,
+            directive: None,
         })?;
 
         let last_modified = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
@@ -358,6 +1046,7 @@ impl EmberEngine {
             message: format!("Failed to read template file: {}", e),
             template: Some(template_name.to_string()),
             line: None,
+            directive: None,
         })?;
 
         let compiled_content = self.compile_template(&template_content, template_name)?;
@@ -385,27 +1074,104 @@ impl EmberEngine {
     }
 
     /// Compile Ember template syntax to executable template
-    fn compile_template(&self, content: &str, _template_name: &str) -> Result<String, EmberError> {
-        // For now, just return the content as-is since we'll process it directly
-        // In the future, we could add optimizations here
+    fn compile_template(&self, content: &str, template_name: &str) -> Result<String, EmberError> {
+        check_directives(content, template_name)?;
         Ok(content.to_string())
     }
 
+    /// A development page describing `err`, with the offending lines of the
+    /// template when it can be read. `torch serve --hot` refreshes it once a
+    /// template is saved.
+    pub fn error_overlay(&self, err: &EmberError) -> String {
+        let location = match (&err.template, err.line) {
+            (Some(template), Some(line)) => {
+                format!("{}:{}", self.get_template_path(template).display(), line)
+            }
+            (Some(template), None) => self.get_template_path(template).display().to_string(),
+            (None, _) => String::new(),
+        };
+
+        let mut excerpt = String::new();
+        if let (Some(template), Some(line)) = (&err.template, err.line) {
+            if let Ok(source) = fs::read_to_string(self.get_template_path(template)) {
+                let first = line.saturating_sub(4);
+                for (index, text) in source.lines().enumerate().skip(first).take(7) {
+                    let number = index + 1;
+                    let class = if number == line { " class=\"hit\"" } else { "" };
+                    excerpt.push_str(&format!(
+                        "<div{}><span>{:>4}</span>{}</div>",
+                        class,
+                        number,
+                        escape_html(text)
+                    ));
+                }
+            }
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Template Error</title>
+<style>
+body{{margin:0;background:#1b1b1f;color:#e6e6e6;font:15px/1.5 system-ui,sans-serif}}
+main{{max-width:960px;margin:48px auto;padding:0 24px}}
+h1{{color:#ff6b5b;font-size:22px;margin:0 0 8px}}
+.where{{color:#9a9aa5;font-family:ui-monospace,monospace}}
+.directive{{background:#ff6b5b;color:#1b1b1f;border-radius:4px;padding:1px 6px;font-family:ui-monospace,monospace}}
+pre{{background:#111114;border-radius:6px;padding:12px 0;overflow-x:auto;font:13px/1.6 ui-monospace,monospace}}
+pre div{{padding:0 16px;white-space:pre}}
+pre span{{color:#6b6b75;margin-right:16px}}
+pre .hit{{background:#4a1f1c}}
+footer{{color:#6b6b75;font-size:13px}}
+</style></head>
+<body><main>
+<h1>Template Error</h1>
+<p>{directive}{message}</p>
+<p class="where">{location}</p>
+{excerpt}
+<footer>Fix the template and save it; this page reloads automatically.</footer>
+</main></body></html>
+"#,
+            directive = err
+                .directive
+                .as_deref()
+                .map(|directive| format!("<span class=\"directive\">{}</span> ", escape_html(directive)))
+                .unwrap_or_default(),
+            message = escape_html(&err.message),
+            location = escape_html(&location),
+            excerpt = if excerpt.is_empty() { String::new() } else { format!("<pre>{}</pre>", excerpt) },
+        )
+    }
+
 
 
     /// Execute the compiled template with data
-    fn execute_template(&self, compiled: &str, data: &EmberData) -> Result<String, EmberError> {
+    fn execute_template(&self, template_name: &str, compiled: &str, data: &EmberData) -> Result<String, EmberError> {
         let mut result = compiled.to_string();
 
         // Handle template inheritance (@extends) first
-        result = self.process_inheritance(&result, data)?;
+        result = self.process_inheritance(&result, template_name)?;
 
         // Process includes
         result = self.process_includes(&result, data)?;
 
+        // Resolve asset URLs from the bundler manifest
+        result = self.process_assets(&result)?;
+
         // Process sections (for templates without inheritance)
         result = self.process_sections(&result, data)?;
 
+        // Fragments render in place when the whole page is rendered
+        result = self.process_fragments(&result)?;
+
+        // So do @await blocks, with the data already given
+        result = AWAIT_REGEX.replace_all(&result, "$2").into_owned();
+
+        // Render @pagination links
+        result = self.process_pagination(&result, data)?;
+
+        // Fill in @error blocks and old() input
+        result = self.process_form_helpers(&result, data)?;
+
         // Process conditionals
         result = self.process_conditionals(&result, data)?;
 
@@ -419,7 +1185,7 @@ impl EmberEngine {
     }
 
     /// Process template inheritance (@extends)
-    fn process_inheritance(&self, content: &str, _data: &EmberData) -> Result<String, EmberError> {
+    fn process_inheritance(&self, content: &str, template_name: &str) -> Result<String, EmberError> {
         static EXTENDS_REGEX: Lazy<Regex> = Lazy::new(|| {
             Regex::new(r#"@extends\s*\(\s*['"]([^'"]+)['"]\s*\)"#).unwrap()
         });
@@ -430,14 +1196,21 @@ impl EmberEngine {
             // Load the layout template
             let layout_path = self.get_template_path(layout_name);
             if !layout_path.exists() {
-                return Ok(content.to_string()); // If layout doesn't exist, return content as-is
+                return Err(EmberError {
+                    message: format!("Layout '{}' not found at {}", layout_name, layout_path.display()),
+                    template: Some(template_name.to_string()),
+                    line: Some(line_of(content, captures.get(0).map_or(0, |m| m.start()))),
+                    directive: Some("@extends".to_string()),
+                });
             }
 
             let layout_content = fs::read_to_string(&layout_path).map_err(|e| EmberError {
                 message: format!("Failed to read layout template: {}", e),
                 template: Some(layout_name.to_string()),
                 line: None,
+                directive: None,
             })?;
+            check_directives(&layout_content, layout_name)?;
 
             // Remove the @extends directive from child content
             let child_content = EXTENDS_REGEX.replace(content, "").to_string();
@@ -497,6 +1270,96 @@ impl EmberEngine {
         Ok(result)
     }
 
+    /// Replace `@asset('name')` with its URL and `@vite(...)` with the tags
+    /// loading its entry points
+    fn process_assets(&self, content: &str) -> Result<String, EmberError> {
+        static ASSET_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"@(asset|vite)\s*\(\s*(\[[^\]]*\]|['"][^'"]+['"])\s*\)"#).unwrap()
+        });
+        static NAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"['"]([^'"]+)['"]"#).unwrap());
+
+        let result = ASSET_REGEX.replace_all(content, |caps: &regex::Captures| {
+            let names: Vec<&str> = NAME_REGEX.captures_iter(&caps[2]).map(|name| name.get(1).unwrap().as_str()).collect();
+            match &caps[1] {
+                "asset" => crate::assets::asset(names[0]),
+                _ => crate::assets::vite(&names),
+            }
+        });
+        Ok(result.to_string())
+    }
+
+    /// Remove `@fragment`/`@endfragment` markers, keeping what they wrap
+    fn process_fragments(&self, content: &str) -> Result<String, EmberError> {
+        static FRAGMENT_MARKER_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"@fragment\s*\(\s*['"][^'"]+['"]\s*\)|@endfragment"#).unwrap()
+        });
+
+        Ok(FRAGMENT_MARKER_REGEX.replace_all(content, "").to_string())
+    }
+
+    /// Replace `@pagination($paginator)` and `@pagination($paginator, window)`
+    /// with page links
+    fn process_pagination(&self, content: &str, data: &EmberData) -> Result<String, EmberError> {
+        static PAGINATION_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"@pagination\s*\(\s*\$([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_]*)*)\s*(?:,\s*(\d+)\s*)?\)").unwrap()
+        });
+
+        let result = PAGINATION_REGEX.replace_all(content, |caps: &regex::Captures| {
+            let window = caps.get(2).and_then(|window| window.as_str().parse().ok()).unwrap_or(PAGINATION_WINDOW);
+            match data.lookup(&caps[1]) {
+                Some(EmberValue::Object(paginator)) => pagination_links(paginator, window),
+                _ => String::new(),
+            }
+        });
+        Ok(result.into_owned())
+    }
+
+    /// Render `@error('field')` blocks for fields with errors, with
+    /// `{{ $message }}` as the first message, and replace `{{ old(...) }}`
+    fn process_form_helpers(&self, content: &str, data: &EmberData) -> Result<String, EmberError> {
+        static ERROR_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"(?s)@error\s*\(\s*['"]([^'"]+)['"]\s*\)(.*?)@enderror"#).unwrap()
+        });
+        static MESSAGE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*\$message\s*\}\}").unwrap());
+        static OLD_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"\{\{\s*old\s*\(\s*['"]([^'"]+)['"]\s*(?:,\s*(?:['"]([^'"]*)['"]|\$([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_]*)*))\s*)?\)\s*\}\}"#).unwrap()
+        });
+
+        let field_value = |key: &str, field: &str| match data.get(key) {
+            Some(EmberValue::Object(fields)) => fields.get(field).cloned(),
+            _ => None,
+        };
+
+        let result = ERROR_REGEX.replace_all(content, |caps: &regex::Captures| {
+            let message = match field_value(ERRORS_KEY, &caps[1]) {
+                Some(EmberValue::Array(messages)) => messages.first().map(|message| self.value_to_string(message)),
+                Some(EmberValue::String(message)) => Some(message),
+                _ => None,
+            };
+            match message {
+                Some(message) => {
+                    let message = escape_html(&message);
+                    MESSAGE_REGEX.replace_all(&caps[2], regex::NoExpand(&message)).into_owned()
+                }
+                None => String::new(),
+            }
+        });
+
+        let result = OLD_REGEX.replace_all(&result, |caps: &regex::Captures| {
+            let value = match field_value(OLD_KEY, &caps[1]) {
+                Some(value) => self.value_to_string(&value),
+                None => match (caps.get(2), caps.get(3)) {
+                    (Some(default), _) => default.as_str().to_string(),
+                    (_, Some(variable)) => data.lookup(variable.as_str()).map(|value| self.value_to_string(value)).unwrap_or_default(),
+                    _ => String::new(),
+                },
+            };
+            escape_html(&value)
+        });
+
+        Ok(result.into_owned())
+    }
+
     /// Process conditional statements
     fn process_conditionals(&self, content: &str, data: &EmberData) -> Result<String, EmberError> {
         static IF_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -560,17 +1423,25 @@ impl EmberEngine {
             Regex::new(r#"@include\s*\(\s*['"]([^'"]+)['"]\s*\)"#).unwrap()
         });
 
+        let mut include_error = None;
         let result = INCLUDE_REGEX.replace_all(content, |caps: &regex::Captures| {
             let include_name = &caps[1];
 
             // Load and render the included template
             match self.load_and_render_include(include_name, data) {
                 Ok(rendered) => rendered,
+                Err(err) if err.line.is_some() => {
+                    include_error.get_or_insert(err);
+                    String::new()
+                }
                 Err(_) => format!("<!-- Include '{}' not found -->", include_name),
             }
         }).to_string();
 
-        Ok(result)
+        match include_error {
+            Some(err) => Err(err),
+            None => Ok(result),
+        }
     }
 
     /// Load and render an included template
@@ -581,6 +1452,7 @@ impl EmberEngine {
                 message: format!("Include template not found: {}", template_name),
                 template: Some(template_name.to_string()),
                 line: None,
+                directive: None,
             });
         }
 
@@ -588,24 +1460,34 @@ impl EmberEngine {
             message: format!("Failed to read include template: {}", e),
             template: Some(template_name.to_string()),
             line: None,
+            directive: None,
         })?;
 
         // Recursively process the included template
-        self.execute_template(&content, data)
+        check_directives(&content, template_name)?;
+        self.execute_template(template_name, &content, data)
     }
 
-    /// Replace variables in the template
+    /// Replace variables in the template, passing them through any filters
+    /// (`{{ $price | currency('EUR') }}`)
     fn replace_variables(&self, content: &str, data: &EmberData) -> Result<String, EmberError> {
         static VAR_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"\{\{\s*\$([a-zA-Z_][a-zA-Z0-9_]*)\s*\}\}").unwrap()
+            Regex::new(r"\{\{\s*\$([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_]*)*)((?:\s*\|\s*[a-zA-Z_]+(?:\s*\([^)]*\))?)*)\s*\}\}").unwrap()
+        });
+        static FILTER_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"\|\s*([a-zA-Z_]+)(?:\s*\(([^)]*)\))?").unwrap()
         });
 
         let result = VAR_REGEX.replace_all(content, |caps: &regex::Captures| {
             let var_name = &caps[1];
-            if let Some(value) = data.get(var_name) {
-                self.value_to_string(value)
+            if let Some(value) = data.lookup(var_name) {
+                let output = FILTER_REGEX.captures_iter(&caps[2]).fold(self.value_to_string(value), |output, filter| {
+                    let argument = filter.get(2).map(|argument| argument.as_str().trim().trim_matches(['\'', '"']));
+                    apply_filter(value, output, &filter[1], argument)
+                });
+                output
             } else {
-                format!("{{{{ ${} }}}}", var_name) // Keep placeholder if variable not found
+                caps[0].to_string() // Keep placeholder if variable not found
             }
         }).to_string();
 
@@ -630,7 +1512,7 @@ impl EmberEngine {
         // Handle simple variable checks
         if condition.starts_with('$') {
             let var_name = &condition[1..];
-            if let Some(value) = data.get(var_name) {
+            if let Some(value) = data.lookup(var_name) {
                 match value {
                     EmberValue::Boolean(b) => *b,
                     EmberValue::String(s) => !s.is_empty(),
@@ -666,3 +1548,744 @@ impl EmberEngine {
         }
     }
 }
+
+/// Hash of the values a `@cache` block reads, so it's cached once per
+/// combination of them
+#[cfg(feature = "templates")]
+fn fragment_fingerprint(body: &str, data: &EmberData) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    static VARIABLE_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"\$([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_]*)*)").unwrap()
+    });
+
+    fn write_value(value: &EmberValue, out: &mut String) {
+        match value {
+            EmberValue::String(text) => out.push_str(&format!("{:?}", text)),
+            EmberValue::Number(number) => out.push_str(&number.to_string()),
+            EmberValue::Boolean(flag) => out.push_str(&flag.to_string()),
+            EmberValue::Null => out.push_str("null"),
+            EmberValue::Array(items) => {
+                out.push('[');
+                for item in items {
+                    write_value(item, out);
+                    out.push(',');
+                }
+                out.push(']');
+            }
+            EmberValue::Object(fields) => {
+                let mut keys: Vec<&String> = fields.keys().collect();
+                keys.sort();
+                out.push('{');
+                for key in keys {
+                    out.push_str(&format!("{:?}:", key));
+                    write_value(&fields[key], out);
+                    out.push(',');
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    let mut names: Vec<&str> = VARIABLE_REGEX.captures_iter(body).map(|caps| caps.get(1).unwrap().as_str()).collect();
+    // Form helpers read the flashed errors and input without naming them
+    if body.contains("@error") {
+        names.push(ERRORS_KEY);
+    }
+    if body.contains("old(") {
+        names.push(OLD_KEY);
+    }
+    names.sort_unstable();
+    names.dedup();
+
+    let mut canonical = crate::i18n::locale().code.to_string();
+    for name in names {
+        canonical.push_str(&format!("|{}=", name));
+        if let Some(value) = data.lookup(name) {
+            write_value(value, &mut canonical);
+        }
+    }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    canonical.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The body of `@fragment('name')`, allowing other fragments inside it, or
+/// else of `@section('name')`
+#[cfg(feature = "templates")]
+fn find_fragment<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    static FRAGMENT_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"@fragment\s*\(\s*['"]([^'"]+)['"]\s*\)|@endfragment"#).unwrap()
+    });
+    static SECTION_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"(?s)@section\s*\(\s*['"]([^'"]+)['"]\s*\)(.*?)@endsection"#).unwrap()
+    });
+
+    // Start of the named fragment's body and the fragments opened inside it
+    let mut start: Option<(usize, usize)> = None;
+    for captures in FRAGMENT_REGEX.captures_iter(content) {
+        let marker = captures.get(0).unwrap();
+        match (start, captures.get(1)) {
+            (None, Some(opened)) if opened.as_str() == name => start = Some((marker.end(), 0)),
+            (Some((body, depth)), Some(_)) => start = Some((body, depth + 1)),
+            (Some((body, 0)), None) => return Some(&content[body..marker.start()]),
+            (Some((body, depth)), None) => start = Some((body, depth - 1)),
+            _ => {}
+        }
+    }
+
+    SECTION_REGEX
+        .captures_iter(content)
+        .find(|captures| &captures[1] == name)
+        .and_then(|captures| captures.get(2))
+        .map(|body| body.as_str())
+}
+
+/// Run a `{{ $value | filter(argument) }}` filter. `output` is the value
+/// as filtered so far; unknown filters and values they can't handle pass
+/// through unchanged.
+#[cfg(feature = "templates")]
+fn apply_filter(value: &EmberValue, output: String, filter: &str, argument: Option<&str>) -> String {
+    let locale = crate::i18n::locale();
+    let number = || match value {
+        EmberValue::Number(number) => Some(*number),
+        EmberValue::String(text) => text.trim().parse().ok(),
+        _ => None,
+    };
+
+    match filter {
+        "date" => match parse_datetime(value) {
+            Some(datetime) => format_date(&datetime, argument.unwrap_or(locale.date_format), locale),
+            None => output,
+        },
+        "since" => match parse_datetime(value) {
+            Some(datetime) => locale.format_relative((chrono::Utc::now() - datetime).num_seconds()),
+            None => output,
+        },
+        "number" => match number() {
+            Some(number) => locale.format_number(number, argument.and_then(|decimals| decimals.parse().ok()).unwrap_or(0)),
+            None => output,
+        },
+        "currency" => match number() {
+            Some(amount) => locale.format_currency(amount, argument.unwrap_or(DEFAULT_CURRENCY)),
+            None => output,
+        },
+        "upper" => output.to_uppercase(),
+        "lower" => output.to_lowercase(),
+        _ => output,
+    }
+}
+
+/// Currency of `| currency` without an argument
+#[cfg(feature = "templates")]
+const DEFAULT_CURRENCY: &str = "USD";
+
+/// A timestamp from RFC 3339 text (how chrono serializes `DateTime`),
+/// `YYYY-MM-DD HH:MM:SS`, a bare date or Unix seconds, taken as UTC
+#[cfg(feature = "templates")]
+fn parse_datetime(value: &EmberValue) -> Option<chrono::DateTime<chrono::Utc>> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+    match value {
+        EmberValue::Number(seconds) => DateTime::from_timestamp(*seconds as i64, 0),
+        EmberValue::String(text) => {
+            let text = text.trim();
+            DateTime::parse_from_rfc3339(text)
+                .map(|datetime| datetime.with_timezone(&Utc))
+                .ok()
+                .or_else(|| {
+                    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+                        .iter()
+                        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+                        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+                        .map(|datetime| datetime.and_utc())
+                })
+        }
+        _ => None,
+    }
+}
+
+/// Format like PHP's `date()`: `Y-m-d`, `j F Y`, `D, d M Y H:i`, with
+/// month and day names from `locale`. A backslash prints the next
+/// character as is.
+#[cfg(feature = "templates")]
+fn format_date(datetime: &chrono::DateTime<chrono::Utc>, format: &str, locale: &crate::i18n::Locale) -> String {
+    use chrono::{Datelike, Timelike};
+
+    let mut output = String::new();
+    let mut characters = format.chars();
+    while let Some(character) = characters.next() {
+        let hour12 = match datetime.hour() % 12 {
+            0 => 12,
+            hour => hour,
+        };
+        let weekday = datetime.weekday().num_days_from_monday() as usize;
+        let month = datetime.month0() as usize;
+        match character {
+            'd' => output.push_str(&format!("{:02}", datetime.day())),
+            'j' => output.push_str(&datetime.day().to_string()),
+            'D' => output.push_str(locale.weekdays_short[weekday]),
+            'l' => output.push_str(locale.weekdays[weekday]),
+            'N' => output.push_str(&(weekday + 1).to_string()),
+            'm' => output.push_str(&format!("{:02}", month + 1)),
+            'n' => output.push_str(&(month + 1).to_string()),
+            'M' => output.push_str(locale.months_short[month]),
+            'F' => output.push_str(locale.months[month]),
+            'Y' => output.push_str(&datetime.year().to_string()),
+            'y' => output.push_str(&format!("{:02}", datetime.year() % 100)),
+            'H' => output.push_str(&format!("{:02}", datetime.hour())),
+            'G' => output.push_str(&datetime.hour().to_string()),
+            'h' => output.push_str(&format!("{:02}", hour12)),
+            'g' => output.push_str(&hour12.to_string()),
+            'i' => output.push_str(&format!("{:02}", datetime.minute())),
+            's' => output.push_str(&format!("{:02}", datetime.second())),
+            'A' => output.push_str(if datetime.hour() < 12 { "AM" } else { "PM" }),
+            'a' => output.push_str(if datetime.hour() < 12 { "am" } else { "pm" }),
+            'U' => output.push_str(&datetime.timestamp().to_string()),
+            'c' => output.push_str(&datetime.to_rfc3339()),
+            '\\' => output.extend(characters.next()),
+            other => output.push(other),
+        }
+    }
+    output
+}
+
+/// Pages linked on each side of the current page by `@pagination`
+#[cfg(feature = "templates")]
+const PAGINATION_WINDOW: u32 = 2;
+
+/// Accessible page links for a serialized `Paginated`
+#[cfg(feature = "templates")]
+fn pagination_links(paginator: &HashMap<String, EmberValue>, window: u32) -> String {
+    let number = |field: &str| match paginator.get(field) {
+        Some(EmberValue::Number(number)) if *number >= 0.0 => *number as u32,
+        _ => 0,
+    };
+    let text = |field: &str| match paginator.get(field) {
+        Some(EmberValue::String(text)) => text.as_str(),
+        _ => "",
+    };
+    let (current, last) = (number("current_page").max(1), number("last_page"));
+    if last <= 1 {
+        return String::new();
+    }
+
+    let (path, query) = (text("path"), text("query"));
+    let url = |page: u32| {
+        let separator = if query.is_empty() { "" } else { "&" };
+        escape_html(&format!("{}?{}{}page={}", path, query, separator, page))
+    };
+
+    let mut html = String::from("<nav class=\"pagination\" role=\"navigation\" aria-label=\"Pagination\">\n<ul>\n");
+    if current > 1 {
+        html.push_str(&format!("<li><a href=\"{}\" rel=\"prev\" aria-label=\"Previous page\">&laquo; Previous</a></li>\n", url(current - 1)));
+    } else {
+        html.push_str("<li><span aria-disabled=\"true\">&laquo; Previous</span></li>\n");
+    }
+
+    let linked = |page: u32| page == 1 || page == last || (page + window >= current && page <= current + window);
+    let mut previous = 0;
+    for page in 1..=last {
+        // A gap of a single page shows that page rather than an ellipsis
+        if !(linked(page) || (linked(page - 1) && linked(page + 1))) {
+            continue;
+        }
+        if page > previous + 1 {
+            html.push_str("<li><span aria-hidden=\"true\">&hellip;</span></li>\n");
+        }
+        if page == current {
+            html.push_str(&format!("<li><span aria-current=\"page\">{}</span></li>\n", page));
+        } else {
+            html.push_str(&format!("<li><a href=\"{}\" aria-label=\"Page {}\">{}</a></li>\n", url(page), page, page));
+        }
+        previous = page;
+    }
+
+    if current < last {
+        html.push_str(&format!("<li><a href=\"{}\" rel=\"next\" aria-label=\"Next page\">Next &raquo;</a></li>\n", url(current + 1)));
+    } else {
+        html.push_str("<li><span aria-disabled=\"true\">Next &raquo;</span></li>\n");
+    }
+    html.push_str("</ul>\n</nav>");
+    html
+}
+
+/// 1-based line number of a byte offset
+#[cfg(feature = "templates")]
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+#[cfg(feature = "templates")]
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Check that block directives are balanced and well formed, reporting the
+/// first problem with its line and directive
+#[cfg(feature = "templates")]
+fn check_directives(content: &str, template_name: &str) -> Result<(), EmberError> {
+    static DIRECTIVE_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"@(if|foreach|section|fragment|cache|await|error|extends|include|asset|vite|pagination)\b|@(else|placeholder|endif|endforeach|endsection|endfragment|endcache|endawait|enderror)\b").unwrap()
+    });
+    static FOREACH_ARGS_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^\s*\$[a-zA-Z_][a-zA-Z0-9_]*\s+as\s+\$[a-zA-Z_][a-zA-Z0-9_]*\s*$").unwrap()
+    });
+
+    let error = |offset: usize, directive: &str, message: String| EmberError {
+        message,
+        template: Some(template_name.to_string()),
+        line: Some(line_of(content, offset)),
+        directive: Some(format!("@{}", directive)),
+    };
+
+    // Open blocks as (directive, byte offset)
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    for captures in DIRECTIVE_REGEX.captures_iter(content) {
+        let name = captures.get(1).or_else(|| captures.get(2)).unwrap();
+        let (directive, offset) = (name.as_str(), name.start() - 1);
+        // Opening directives need a non-word character or another directive before them, such as
+        // the `>` of a tag or the `)` of `@if($done)@include('x')`, so e-mail addresses like
+        // `ops@if.io` aren't read as directives. Closing ones also close a block straight after
+        // text (`@if($on)checked@endif`), as the renderer does, and are only text when nothing
+        // they could close is open
+        let text = &content[..offset];
+        let word = text.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        if text.ends_with('@') {
+            continue;
+        }
+        if word.len() < text.len() && !word.ends_with('@') {
+            let closes = match directive {
+                "else" => Some("if"),
+                "placeholder" => Some("await"),
+                other => other.strip_prefix("end"),
+            };
+            if closes.is_none() || open.last().map(|(opened, _)| *opened) != closes {
+                continue;
+            }
+        }
+        let arguments = directive_arguments(&content[name.end()..]);
+
+        match directive {
+            "if" | "foreach" | "section" | "fragment" | "cache" | "await" | "error" | "extends" | "include" | "asset" | "vite" | "pagination" => {
+                let Some(arguments) = arguments else {
+                    let example = match directive {
+                        "if" => "@if($user)",
+                        "foreach" => "@foreach($items as $item)",
+                        "section" => "@section('content')",
+                        "fragment" => "@fragment('table')",
+                        "cache" => "@cache('sidebar', 300)",
+                        "await" => "@await('comments')",
+                        "error" => "@error('email')",
+                        "pagination" => "@pagination($posts)",
+                        "asset" => "@asset('resources/css/app.css')",
+                        "vite" => "@vite('resources/js/app.js')",
+                        other => if other == "extends" { "@extends('layout')" } else { "@include('partial')" },
+                    };
+                    return Err(error(offset, directive, format!("@{} needs arguments, e.g. {}", directive, example)));
+                };
+                match directive {
+                    "foreach" if !FOREACH_ARGS_REGEX.is_match(arguments) => {
+                        return Err(error(offset, directive, format!("expected @foreach($items as $item), found @foreach({})", arguments)));
+                    }
+                    "section" | "fragment" | "cache" | "await" | "error" | "extends" | "include" | "asset" if !arguments.trim_start().starts_with(['\'', '"']) => {
+                        return Err(error(offset, directive, format!("@{} expects a quoted name", directive)));
+                    }
+                    "vite" if !arguments.trim_start().starts_with(['\'', '"', '[']) => {
+                        return Err(error(offset, directive, "@vite expects a quoted entry point or a list of them".to_string()));
+                    }
+                    "pagination" if !arguments.trim_start().starts_with('$') => {
+                        return Err(error(offset, directive, "@pagination expects a paginator variable, e.g. @pagination($posts)".to_string()));
+                    }
+                    "if" if arguments.trim().is_empty() => {
+                        return Err(error(offset, directive, "@if needs a condition".to_string()));
+                    }
+                    _ => {}
+                }
+                // `@section('title', 'Text')` is a one-line section with no @endsection
+                if matches!(directive, "if" | "foreach" | "fragment" | "cache" | "await" | "error") || (directive == "section" && !arguments.contains(',')) {
+                    open.push((directive, offset));
+                }
+            }
+            "else" => {
+                if open.last().map(|(opened, _)| *opened) != Some("if") {
+                    return Err(error(offset, directive, "@else outside of an @if block".to_string()));
+                }
+            }
+            "placeholder" => {
+                if open.last().map(|(opened, _)| *opened) != Some("await") {
+                    return Err(error(offset, directive, "@placeholder outside of an @await block".to_string()));
+                }
+            }
+            closing => {
+                let opener = &closing[3..];
+                match open.pop() {
+                    Some((opened, _)) if opened == opener => {}
+                    Some((opened, opened_at)) => {
+                        return Err(error(
+                            offset,
+                            closing,
+                            format!(
+                                "@{} found while @{} from line {} is still open",
+                                closing,
+                                opened,
+                                line_of(content, opened_at)
+                            ),
+                        ));
+                    }
+                    None => {
+                        return Err(error(offset, closing, format!("@{} without a matching @{}", closing, opener)));
+                    }
+                }
+            }
+        }
+    }
+
+    match open.pop() {
+        Some((opened, offset)) => Err(error(offset, opened, format!("@{} is never closed; add @end{}", opened, opened))),
+        None => Ok(()),
+    }
+}
+
+/// The text between the parentheses directly after a directive name, if any
+#[cfg(feature = "templates")]
+fn directive_arguments(rest: &str) -> Option<&str> {
+    let rest = rest.trim_start_matches([' ', '\t']);
+    let inner = rest.strip_prefix('(')?;
+    let mut depth = 1;
+    for (index, ch) in inner.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&inner[..index]);
+                }
+            }
+            '\n' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_lookup_dotted_path() {
+        let data = EmberData::new().with(
+            "post",
+            serde_json::json!({ "title": "Hello", "author": { "name": "Ada" } }),
+        );
+
+        assert!(matches!(data.lookup("post.title"), Some(EmberValue::String(s)) if s == "Hello"));
+        assert!(matches!(data.lookup("post.author.name"), Some(EmberValue::String(s)) if s == "Ada"));
+        assert!(data.lookup("post.missing").is_none());
+        assert!(data.lookup("post.title.length").is_none());
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn test_check_directives_reports_line_and_directive() {
+        assert!(check_directives("@if($a)\n@foreach($items as $item)\n@endforeach\n@else\n@endif", "ok").is_ok());
+        assert!(check_directives("@section('title', 'Home')\nmail me at a@if.com", "ok").is_ok());
+        assert!(check_directives("<title>@section('title')Torch App@endsection</title>", "ok").is_ok());
+        assert!(check_directives("<b>@if($a)@endif</b> or write to ops@endif.io", "ok").is_ok());
+        assert!(check_directives("@if($a)checked@endif", "ok").is_ok());
+        assert!(check_directives("@if($a)on@else off@endif", "ok").is_ok());
+
+        let err = check_directives("<p>\n@if($user)\n  <b>hi</b>\n@endforeach", "home").unwrap_err();
+        assert_eq!(err.template.as_deref(), Some("home"));
+        assert_eq!(err.line, Some(4));
+        assert_eq!(err.directive.as_deref(), Some("@endforeach"));
+        assert!(err.message.contains("@if from line 2"));
+
+        let err = check_directives("a\n@foreach($items)\n@endforeach", "list").unwrap_err();
+        assert_eq!((err.line, err.directive.as_deref()), (Some(2), Some("@foreach")));
+
+        let err = check_directives("@section('content')\n<p>", "page").unwrap_err();
+        assert_eq!(err.line, Some(1));
+        assert!(err.message.contains("never closed"));
+
+        let err = check_directives("@else", "page").unwrap_err();
+        assert_eq!(err.directive.as_deref(), Some("@else"));
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn test_error_overlay_escapes_details() {
+        let engine = EmberEngine::new();
+        let err = EmberError {
+            message: "<script>".to_string(),
+            template: Some("missing".to_string()),
+            line: Some(3),
+            directive: Some("@if".to_string()),
+        };
+
+        let html = engine.error_overlay(&err);
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.contains("templates/missing.ember:3"));
+        assert!(html.contains("<span class=\"directive\">@if</span>"));
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_closing_directives_can_follow_text() {
+        let dir = std::env::temp_dir().join(format!("torch-ember-closing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("layout.ember"), "<title>@section('title')Torch App@endsection</title>").unwrap();
+        fs::write(dir.join("page.ember"), "<input @if($on)checked@endif> ops@endif.io").unwrap();
+        let engine = EmberEngine::with_config(EmberConfig { template_dir: dir.clone(), ..EmberConfig::default() });
+
+        assert_eq!(engine.render("layout", EmberData::new()).await.unwrap(), "<title>Torch App</title>");
+        let page = engine.render("page", EmberData::new().with("on", true)).await.unwrap();
+        assert_eq!(page, "<input checked> ops@endif.io");
+        let page = engine.render("page", EmberData::new().with("on", false)).await.unwrap();
+        assert_eq!(page, "<input > ops@endif.io");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_fragments_render_alone_or_in_place() {
+        let dir = std::env::temp_dir().join(format!("torch-ember-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("layout.ember"), "<html>@section('content')@endsection</html>").unwrap();
+        fs::write(
+            dir.join("users.ember"),
+            "@extends('layout')\n@section('content')<h1>Users</h1>\
+             @fragment('table')<table>@fragment('row')<tr>{{ $name }}</tr>@endfragment</table>@endfragment\
+             @endsection",
+        )
+        .unwrap();
+        let engine = EmberEngine::with_config(EmberConfig { template_dir: dir.clone(), ..EmberConfig::default() });
+        let data = || EmberData::new().with("name", "Ada");
+
+        let page = engine.render("users", data()).await.unwrap();
+        assert_eq!(page, "<html><h1>Users</h1><table><tr>Ada</tr></table></html>");
+        let table = engine.render_fragment("users", "table", data()).await.unwrap();
+        assert_eq!(table, "<table><tr>Ada</tr></table>");
+        assert_eq!(engine.render_fragment("users", "row", data()).await.unwrap(), "<tr>Ada</tr>");
+        assert!(engine.render_fragment("users", "content", data()).await.unwrap().starts_with("<h1>Users</h1>"));
+        assert!(engine.render_fragment("users", "missing", data()).await.is_err());
+
+        let mut req = Request::new();
+        assert!(!wants_fragment(&req));
+        req.headers_mut().insert("HX-Request", "true".parse().unwrap());
+        assert!(wants_fragment(&req));
+        req.headers_mut().insert("HX-Boosted", "true".parse().unwrap());
+        assert!(!wants_fragment(&req));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_cache_directive_reuses_rendered_fragments() {
+        let _real = crate::cache::without_fake();
+        let dir = std::env::temp_dir().join(format!("torch-ember-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let page = |aside: &str| {
+            format!("<h1>{{{{ $title }}}}</h1>@cache('test-sidebar', 300)<aside>{} {{{{ $user.name }}}}</aside>@endcache", aside)
+        };
+        fs::write(dir.join("page.ember"), page("Hello")).unwrap();
+        let engine = EmberEngine::with_config(EmberConfig {
+            template_dir: dir.clone(),
+            cache_enabled: false,
+            ..EmberConfig::default()
+        });
+        let data = |title: &str, name: &str| EmberData::new().with("title", title).with("user", serde_json::json!({ "name": name }));
+
+        let first = engine.render("page", data("Home", "Ada")).await.unwrap();
+        assert_eq!(first, "<h1>Home</h1><aside>Hello Ada</aside>");
+
+        // Same block variables: the stored HTML comes back, the rest renders
+        fs::write(dir.join("page.ember"), page("Hi")).unwrap();
+        let cached = engine.render("page", data("About", "Ada")).await.unwrap();
+        assert_eq!(cached, "<h1>About</h1><aside>Hello Ada</aside>");
+
+        // Another user gets their own copy
+        assert_eq!(engine.render("page", data("About", "Grace")).await.unwrap(), "<h1>About</h1><aside>Hi Grace</aside>");
+
+        forget_fragment("test-sidebar").await.unwrap();
+        assert_eq!(engine.render("page", data("About", "Ada")).await.unwrap(), "<h1>About</h1><aside>Hi Ada</aside>");
+
+        assert!(check_directives("@cache(sidebar)x@endcache", "page").is_err());
+        assert!(check_directives("@cache('sidebar')x", "page").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_stream_sends_the_page_before_deferred_blocks() {
+        use futures::StreamExt;
+
+        let dir = std::env::temp_dir().join(format!("torch-ember-stream-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("layout.ember"), "<html><body>@section('content')@endsection</body></html>").unwrap();
+        fs::write(
+            dir.join("post.ember"),
+            "@extends('layout')\n@section('content')<h1>{{ $title }}</h1>\
+             @await('comments')@foreach($comments as $comment)<p>{{ $comment }}</p>@endforeach@placeholder<i>Loading</i>@endawait\
+             @endsection",
+        )
+        .unwrap();
+        let engine = EmberEngine::with_config(EmberConfig { template_dir: dir.clone(), ..EmberConfig::default() });
+
+        let (send, receive) = tokio::sync::oneshot::channel::<Vec<&str>>();
+        let data = StreamData::new(EmberData::new().with("title", "Hello"))
+            .defer("comments", async move { receive.await.unwrap() });
+        let mut chunks = engine.render_stream("post", data).await.unwrap();
+
+        // The page goes out while the comments are still loading
+        let page = chunks.next().await.unwrap();
+        assert_eq!(page, "<html><body><h1>Hello</h1><div id=\"ember-await-0\"><i>Loading</i></div>");
+
+        send.send(vec!["First", "Second"]).unwrap();
+        let block = String::from_utf8(chunks.next().await.unwrap().to_vec()).unwrap();
+        assert!(block.starts_with("<template id=\"ember-await-0-content\"><p>First</p><p>Second</p></template><script>"), "{}", block);
+        assert_eq!(chunks.next().await.unwrap(), "</body></html>");
+        assert!(chunks.next().await.is_none());
+
+        // Without deferring, the block renders in place
+        let data = EmberData::new().with("title", "Hello").with("comments", vec!["Only"]);
+        assert_eq!(engine.render("post", data).await.unwrap(), "<html><body><h1>Hello</h1><p>Only</p></body></html>");
+
+        assert!(check_directives("@placeholder", "post").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_asset_directives_use_the_manifest() {
+        let dir = std::env::temp_dir().join(format!("torch-ember-assets-{}", std::process::id()));
+        fs::create_dir_all(dir.join("public/build")).unwrap();
+        fs::write(
+            dir.join("public/build/manifest.json"),
+            r#"{"resources/js/app.js": {"file": "assets/app-4ed9.js", "css": ["assets/app-5b1a.css"]}}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("page.ember"), "@vite(['resources/js/app.js'])\n<img src=\"@asset('logo.png')\">").unwrap();
+        crate::assets::configure(crate::assets::AssetConfig {
+            public_dir: dir.join("public"),
+            ..Default::default()
+        });
+
+        let engine = EmberEngine::with_config(EmberConfig { template_dir: dir.clone(), ..EmberConfig::default() });
+        assert_eq!(
+            engine.render("page", EmberData::new()).await.unwrap(),
+            "<link rel=\"stylesheet\" href=\"/build/assets/app-5b1a.css\">\n\
+             <script type=\"module\" src=\"/build/assets/app-4ed9.js\"></script>\n<img src=\"/logo.png\">"
+        );
+
+        crate::assets::configure(Default::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_errors_and_old_input_survive_the_redirect() {
+        let dir = std::env::temp_dir().join(format!("torch-ember-form-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("signup.ember"),
+            "<input value=\"{{ old('email') }}\"><input value=\"{{ old('name', $user.name) }}\">\
+             <input value=\"{{ old('password', 'none') }}\">@error('email')<p>{{ $message }}</p>@enderror @error('name')!@enderror",
+        )
+        .unwrap();
+
+        let mut errors = ErrorBag::new();
+        errors.add("email", "The email must be a valid <email> address.");
+        let input: HashMap<String, String> = [("email", "ada@"), ("password", "secret")]
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect();
+        let submit = Request::mock(http::Method::POST, "/signup").with_header("referer", "https://example.com/signup?step=2");
+        let redirect = back_with_errors(&submit, &errors, &input);
+        assert_eq!(redirect.headers()["location"], "/signup?step=2");
+        let cookie = redirect.headers()["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+
+        let app = crate::App::new().middleware(form_state()).get("/signup", move |_req: Request| {
+            let dir = dir.clone();
+            async move {
+                let engine = EmberEngine::with_config(EmberConfig { template_dir: dir, ..EmberConfig::default() });
+                let data = EmberData::new().with("user", serde_json::json!({"name": "Ada"}));
+                Response::ok().body(engine.render("signup", data).await.unwrap())
+            }
+        });
+        let response = app.handle_request(Request::mock(http::Method::GET, "/signup").with_header("cookie", &cookie)).await;
+        assert_eq!(
+            String::from_utf8_lossy(response.body_data()),
+            "<input value=\"ada@\"><input value=\"Ada\"><input value=\"none\">\
+             <p>The email must be a valid &lt;email&gt; address.</p> "
+        );
+        assert!(response.headers()["set-cookie"].to_str().unwrap().contains("Max-Age=0"));
+
+        assert!(check_directives("@error('email')<p>{{ $message }}</p>@enderror", "ok").is_ok());
+        assert!(check_directives("@error('email')<p>", "form").is_err());
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn test_pagination_links_keep_the_query() {
+        let engine = EmberEngine::new();
+        let paginator = |page: u32| {
+            EmberData::new().with(
+                "posts",
+                serde_json::json!({"data": [], "current_page": page, "last_page": 10, "path": "/posts", "query": "tag=rust&sort=new"}),
+            )
+        };
+
+        let html = engine.execute_template("posts", "@pagination($posts, 1)", &paginator(5)).unwrap();
+        let items: Vec<&str> = html.lines().filter(|line| line.starts_with("<li>")).collect();
+        assert_eq!(items, vec![
+            "<li><a href=\"/posts?tag=rust&amp;sort=new&amp;page=4\" rel=\"prev\" aria-label=\"Previous page\">&laquo; Previous</a></li>",
+            "<li><a href=\"/posts?tag=rust&amp;sort=new&amp;page=1\" aria-label=\"Page 1\">1</a></li>",
+            "<li><span aria-hidden=\"true\">&hellip;</span></li>",
+            "<li><a href=\"/posts?tag=rust&amp;sort=new&amp;page=4\" aria-label=\"Page 4\">4</a></li>",
+            "<li><span aria-current=\"page\">5</span></li>",
+            "<li><a href=\"/posts?tag=rust&amp;sort=new&amp;page=6\" aria-label=\"Page 6\">6</a></li>",
+            "<li><span aria-hidden=\"true\">&hellip;</span></li>",
+            "<li><a href=\"/posts?tag=rust&amp;sort=new&amp;page=10\" aria-label=\"Page 10\">10</a></li>",
+            "<li><a href=\"/posts?tag=rust&amp;sort=new&amp;page=6\" rel=\"next\" aria-label=\"Next page\">Next &raquo;</a></li>",
+        ]);
+
+        // Page 2 is linked rather than hidden behind an ellipsis of its own
+        let html = engine.execute_template("posts", "@pagination($posts)", &paginator(4)).unwrap();
+        let pages: Vec<&str> = html.lines().filter_map(|line| line.strip_suffix("</a></li>")?.rsplit('>').next()).collect();
+        assert_eq!(pages, vec!["&laquo; Previous", "1", "2", "3", "5", "6", "10", "Next &raquo;"]);
+
+        let single = EmberData::new().with("posts", serde_json::json!({"current_page": 1, "last_page": 1}));
+        assert_eq!(engine.execute_template("posts", "@pagination($posts)", &single).unwrap(), "");
+        assert!(check_directives("@pagination(posts)", "posts").is_err());
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn test_filters_format_for_the_locale() {
+        let engine = EmberEngine::new();
+        let data = EmberData::new()
+            .with("post", serde_json::json!({"created_at": "2024-03-05T14:07:00Z", "views": 1234567}))
+            .with("price", 1999.5)
+            .with("name", "ada");
+        let template = "{{ $post.created_at | date('D j M Y, g:i a') }}|{{ $post.created_at|date }}|{{ $post.views | number }}|\
+                        {{ $price | number(1) }}|{{ $price | currency('EUR') }}|{{ $name | upper }}|{{ $missing | date }}";
+
+        let english = engine.execute_template("post", template, &data).unwrap();
+        assert_eq!(english, "Tue 5 Mar 2024, 2:07 pm|Mar 5, 2024|1,234,567|1,999.5|€1,999.50|ADA|{{ $missing | date }}");
+
+        let german = crate::i18n::with_locale("de", async { engine.execute_template("post", template, &data).unwrap() }).await;
+        assert_eq!(german, "Di. 5 März 2024, 2:07 pm|05.03.2024|1.234.567|1.999,5|1.999,50\u{a0}€|ADA|{{ $missing | date }}");
+
+        let an_hour_ago = (chrono::Utc::now() - chrono::Duration::minutes(61)).to_rfc3339();
+        let recent = EmberData::new().with("at", an_hour_ago);
+        assert_eq!(engine.execute_template("post", "{{ $at | since }}", &recent).unwrap(), "1 hour ago");
+    }
+}
@@ -64,38 +64,96 @@ impl ErrorPages {
     }
 
     /// Generate an error response for the given status code
-    pub fn render_error(&self, status_code: u16, message: Option<&str>, _req: &Request) -> Response {
+    ///
+    /// Clients that ask for JSON in their `Accept` header get a JSON body
+    /// instead of an HTML page.
+    pub fn render_error(&self, status_code: u16, message: Option<&str>, req: &Request) -> Response {
+        self.render_error_with_retry_after(status_code, message, None, req)
+    }
+
+    /// Generate an error response that tells the client when to try again
+    ///
+    /// `retry_after` is a `Retry-After` value, either seconds or an HTTP date.
+    /// It is sent back as a header and shown on the page, which matters most
+    /// for 429 and 503 responses. Custom pages can place it with
+    /// `{{retry_after}}`.
+    pub fn render_error_with_retry_after(
+        &self,
+        status_code: u16,
+        message: Option<&str>,
+        retry_after: Option<&str>,
+        req: &Request,
+    ) -> Response {
         let status = http::StatusCode::from_u16(status_code).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
-        
+        let mut response = Response::with_status(status);
+        if let Some(retry_after) = retry_after {
+            response = response.header("Retry-After", retry_after);
+        }
+
+        if wants_json(req) {
+            return response
+                .header("Content-Type", "application/json")
+                .body(self.generate_json_error(status_code, message, retry_after));
+        }
+
         // Check for custom page first
         if let Some(custom_html) = self.custom_pages.get(&status_code) {
-            return Response::with_status(status)
+            let retry_text = retry_after.map(retry_after_text).unwrap_or_default();
+            return response
                 .header("Content-Type", "text/html; charset=utf-8")
-                .body(custom_html.clone());
+                .body(custom_html.replace("{{retry_after}}", &retry_text));
         }
 
         // Generate default error page
         let html = if self.use_default_styling {
-            self.generate_styled_error_page(status_code, message)
+            self.generate_styled_error_page(status_code, message, retry_after)
         } else {
-            self.generate_plain_error_page(status_code, message)
+            self.generate_plain_error_page(status_code, message, retry_after)
         };
 
-        Response::with_status(status)
+        response
             .header("Content-Type", "text/html; charset=utf-8")
             .body(html)
     }
 
+    /// Generate the JSON body for API clients
+    fn generate_json_error(&self, status_code: u16, message: Option<&str>, retry_after: Option<&str>) -> String {
+        let (title, description) = self.get_error_info(status_code);
+        let mut body = format!(
+            r#"{{"error":{},"message":{},"status":{}"#,
+            json_string(title),
+            json_string(message.unwrap_or(description)),
+            status_code
+        );
+        if let Some(retry_after) = retry_after {
+            let value = match retry_after.trim().parse::<u64>() {
+                Ok(seconds) => seconds.to_string(),
+                Err(_) => json_string(retry_after),
+            };
+            body.push_str(&format!(r#","retry_after":{}"#, value));
+        }
+        body.push('}');
+        body
+    }
+
     /// Generate a beautifully styled error page with the Torch logo
-    fn generate_styled_error_page(&self, status_code: u16, message: Option<&str>) -> String {
+    fn generate_styled_error_page(&self, status_code: u16, message: Option<&str>, retry_after: Option<&str>) -> String {
         let (title, description) = self.get_error_info(status_code);
         let message = message.unwrap_or(description);
+        let retry = retry_after
+            .map(|value| format!("<p class=\"retry-after\">⏳ {}</p>", retry_after_text(value)))
+            .unwrap_or_default();
+        // A maintenance page can reload itself once the wait is over
+        let refresh = match retry_after.and_then(|value| value.trim().parse::<u64>().ok()) {
+            Some(seconds) if status_code == 503 => format!("\n    <meta http-equiv=\"refresh\" content=\"{}\">", seconds),
+            _ => String::new(),
+        };
 
         format!(r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">{}
     <title>{} - Torch</title>
     <style>
         * {{
@@ -155,6 +213,15 @@ impl ErrorPages {
             line-height: 1.6;
         }}
         
+        .retry-after {{
+            display: inline-block;
+            margin: -20px 0 40px;
+            padding: 10px 20px;
+            border: 1px solid #ff6b35;
+            border-radius: 8px;
+            color: #f7931e;
+        }}
+        
         .actions {{
             display: flex;
             gap: 20px;
@@ -231,6 +298,7 @@ impl ErrorPages {
         <div class="error-code">{}</div>
         <h1 class="error-title">{}</h1>
         <p class="error-message">{}</p>
+        {}
         
         <div class="actions">
             <a href="/" class="btn btn-primary">🏠 Go Home</a>
@@ -243,18 +311,23 @@ impl ErrorPages {
     </div>
 </body>
 </html>"#, 
+            refresh,
             title, 
             self.get_torch_logo_base64(),
             status_code, 
             title, 
-            message
+            message,
+            retry
         )
     }
 
     /// Generate a plain error page without styling
-    fn generate_plain_error_page(&self, status_code: u16, message: Option<&str>) -> String {
+    fn generate_plain_error_page(&self, status_code: u16, message: Option<&str>, retry_after: Option<&str>) -> String {
         let (title, description) = self.get_error_info(status_code);
         let message = message.unwrap_or(description);
+        let retry = retry_after
+            .map(|value| format!("\n    <p>{}</p>", retry_after_text(value)))
+            .unwrap_or_default();
 
         format!(r#"<!DOCTYPE html>
 <html lang="en">
@@ -265,11 +338,11 @@ impl ErrorPages {
 </head>
 <body>
     <h1>{} {}</h1>
-    <p>{}</p>
+    <p>{}</p>{}
     <hr>
     <p><a href="/">Go Home</a> | <a href="javascript:history.back()">Go Back</a></p>
 </body>
-</html>"#, title, status_code, title, message)
+</html>"#, title, status_code, title, message, retry)
     }
 
     /// Get error information for common status codes
@@ -325,8 +398,81 @@ impl ErrorPages {
     }
 }
 
+/// Whether the client asked for JSON rather than HTML
+fn wants_json(req: &Request) -> bool {
+    let accept = req.header("accept").unwrap_or("").to_ascii_lowercase();
+    let json = accept.find("application/json").or_else(|| accept.find("+json"));
+    match (json, accept.find("text/html")) {
+        (Some(json), Some(html)) => json < html,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+/// Describe a `Retry-After` value for people
+fn retry_after_text(retry_after: &str) -> String {
+    let retry_after = retry_after.trim();
+    match retry_after.parse::<u64>() {
+        Ok(0) => "Please try again now.".to_string(),
+        Ok(1) => "Please try again in 1 second.".to_string(),
+        Ok(seconds) if seconds < 120 => format!("Please try again in {} seconds.", seconds),
+        Ok(seconds) => format!("Please try again in {} minutes.", seconds.div_ceil(60)),
+        Err(_) => format!("Please try again after {}.", retry_after),
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 impl Default for ErrorPages {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after_pages_negotiate_html_and_json() {
+        let pages = ErrorPages::new().custom_page(429, "<p>Slow down. {{retry_after}}</p>".to_string());
+
+        let html = pages.render_error_with_retry_after(503, None, Some("90"), &Request::new());
+        let body = String::from_utf8(html.body_data().to_vec()).unwrap();
+        assert_eq!(html.headers().get("retry-after").unwrap(), "90");
+        assert!(body.contains("Please try again in 90 seconds."));
+        assert!(body.contains(r#"<meta http-equiv="refresh" content="90">"#));
+
+        let custom = pages.render_error_with_retry_after(429, None, Some("300"), &Request::new());
+        assert_eq!(custom.body_data(), b"<p>Slow down. Please try again in 5 minutes.</p>");
+
+        let api = Request::new().with_header("accept", "application/json, text/html;q=0.9");
+        let json = pages.render_error_with_retry_after(429, Some("Too many \"tries\""), Some("30"), &api);
+        assert_eq!(json.headers().get("content-type").unwrap(), "application/json");
+        assert_eq!(
+            json.body_data(),
+            br#"{"error":"Too Many Requests","message":"Too many \"tries\"","status":429,"retry_after":30}"#
+        );
+
+        let browser = Request::new().with_header("accept", "text/html,application/xhtml+xml,application/json;q=0.8");
+        let page = pages.render_error(404, None, &browser);
+        assert!(page.headers().get("content-type").unwrap().to_str().unwrap().starts_with("text/html"));
+    }
+}
+
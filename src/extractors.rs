@@ -9,6 +9,7 @@
 //! - **[`Path<T>`]** - Extract path parameters from the URL
 //! - **[`Query<T>`]** - Extract and parse query string parameters
 //! - **[`Json<T>`]** - Parse JSON request bodies (requires `json` feature)
+//! - **[`Patch<T>`]** - Apply JSON Patch or JSON Merge Patch bodies (requires `json` feature)
 //! - **[`Form<T>`]** - Parse form-encoded request bodies
 //! - **[`Headers`]** - Access request headers with convenience methods
 //! - **[`State<T>`]** - Access application state
@@ -327,6 +328,18 @@ impl IntoResponse for String {
     }
 }
 
+impl IntoResponse for () {
+    fn into_response(self) -> Response {
+        Response::ok()
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Response::ok().body(self)
+    }
+}
+
 impl IntoResponse for StatusCode {
     fn into_response(self) -> Response {
         Response::with_status(self)
@@ -344,24 +357,71 @@ where
     }
 }
 
+/// Handlers can use `?`: the error becomes the response
+impl<T, E> IntoResponse for Result<T, E>
+where
+    T: IntoResponse,
+    E: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+/// Any error becomes a 500; the details are logged rather than sent to
+/// the client
+impl IntoResponse for Box<dyn std::error::Error + Send + Sync> {
+    fn into_response(self) -> Response {
+        eprintln!("⚠️  Handler error: {}", self);
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_exception(&self);
+        Response::internal_error()
+    }
+}
+
 // Re-export common types for convenience
 pub use path::Path;
 pub use query::{Query, SerdeQuery};
 pub use headers::{Headers, HeaderExtractor, UserAgent, Authorization, ContentType};
 pub use state::State;
 pub use form::{Form, SerdeForm};
+pub use multipart::{Multipart, MultipartField, MultipartStream, UploadedFile};
+pub use list::{ListLimits, ListParams, Sort};
 pub use cookies::{Cookies, SessionCookie, CookieBuilder, SameSite, get_cookie, get_required_cookie};
 
 #[cfg(feature = "json")]
 pub use json::{Json, RawJson, JsonWithLimit};
 
+#[cfg(feature = "json")]
+pub use decoded::{BodyDecoder, BodyDecoders, Decoded};
+
+#[cfg(feature = "config")]
+pub use config::{Config, ConfigFile, ConfigSection, DEFAULT_CONFIG_FILE};
+
+#[cfg(feature = "json")]
+pub use patch::{Patch, PatchDocument, PatchError, PatchOperation};
+
 // Module declarations
 mod path;
 mod query;
 mod headers;
 pub mod state;
 mod form;
+mod multipart;
+mod list;
 mod cookies;
 
 #[cfg(feature = "json")]
 mod json;
+
+#[cfg(feature = "json")]
+mod decoded;
+
+#[cfg(feature = "config")]
+mod config;
+
+#[cfg(feature = "json")]
+pub mod patch;
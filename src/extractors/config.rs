@@ -0,0 +1,181 @@
+//! Typed configuration sections
+//!
+//! [`Config<T>`] hands a handler one table of `torch.toml`, such as
+//! `[stripe]` or `[features]`, deserialized into the app's own struct.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde::de::DeserializeOwned;
+
+use crate::config::secrets::Secrets;
+use crate::extractors::state::RequestStateExt;
+use crate::{Request, extractors::{ExtractionError, FromRequestParts}};
+
+/// The config file read when no [`ConfigFile`] is registered
+pub const DEFAULT_CONFIG_FILE: &str = "torch.toml";
+
+/// A table of the config file that [`Config`] can load
+pub trait ConfigSection: DeserializeOwned + Send + Sync + 'static {
+    /// Dotted path of the table, e.g. `stripe` or `services.mail`
+    const SECTION: &'static str;
+}
+
+/// Where [`Config`] reads sections from, registered as app state in place
+/// of `torch.toml` in the working directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFile(pub PathBuf);
+
+type Sections = HashMap<(PathBuf, TypeId), Arc<dyn Any + Send + Sync>>;
+
+/// Sections parsed so far, by file and type
+static SECTIONS: OnceLock<Mutex<Sections>> = OnceLock::new();
+
+/// A config section, parsed on first use and shared after that.
+///
+/// String values may be secret references such as `"env:STRIPE_SECRET"`,
+/// resolved like in [`TorchConfig::from_file`](crate::config::TorchConfig::from_file).
+/// A missing or malformed section is a server error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use serde::Deserialize;
+/// use torch_web::App;
+/// use torch_web::extractors::{Config, ConfigSection};
+///
+/// // [stripe]
+/// // secret = "env:STRIPE_SECRET"
+/// // currency = "eur"
+/// #[derive(Deserialize)]
+/// struct Stripe {
+///     secret: String,
+///     currency: String,
+/// }
+///
+/// impl ConfigSection for Stripe {
+///     const SECTION: &'static str = "stripe";
+/// }
+///
+/// let app = App::new().get("/checkout", |stripe: Config<Stripe>| async move {
+///     format!("charging in {}", stripe.currency)
+/// });
+///
+/// // Outside handlers
+/// let stripe = Config::<Stripe>::load().expect("[stripe] in torch.toml");
+/// ```
+pub struct Config<T>(pub Arc<T>);
+
+impl<T: ConfigSection> Config<T> {
+    /// Load the section from `torch.toml`
+    pub fn load() -> Result<Self, String> {
+        Self::load_from(DEFAULT_CONFIG_FILE)
+    }
+
+    /// Load the section from the config file at `path`
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let key = (path.as_ref().to_path_buf(), TypeId::of::<T>());
+        let sections = SECTIONS.get_or_init(Default::default);
+        if let Some(section) = sections.lock().unwrap().get(&key).and_then(|section| section.clone().downcast::<T>().ok()) {
+            return Ok(Config(section));
+        }
+
+        let section = Arc::new(parse::<T>(&key.0)?);
+        sections.lock().unwrap().insert(key, section.clone());
+        Ok(Config(section))
+    }
+}
+
+fn parse<T: ConfigSection>(path: &Path) -> Result<T, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let config: toml::Value = toml::from_str(&contents).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+    let mut section = T::SECTION
+        .split('.')
+        .try_fold(&config, |table, name| table.get(name))
+        .cloned()
+        .ok_or_else(|| format!("No [{}] section in {}", T::SECTION, path.display()))?;
+    Secrets::default().resolve_toml(&mut section).map_err(|e| format!("[{}]: {}", T::SECTION, e.message))?;
+    section.try_into().map_err(|e| format!("Invalid [{}] section in {}: {}", T::SECTION, path.display(), e))
+}
+
+impl<T> Deref for Config<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Clone for Config<T> {
+    fn clone(&self) -> Self {
+        Config(self.0.clone())
+    }
+}
+
+impl<T: ConfigSection> FromRequestParts for Config<T> {
+    type Error = ExtractionError;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let path = req
+            .get_state(TypeId::of::<ConfigFile>())
+            .and_then(|state| state.downcast_ref::<ConfigFile>())
+            .map_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE), |file| file.0.clone());
+        let result = Self::load_from(path).map_err(ExtractionError::MissingState);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Features {
+        signups: bool,
+        beta_users: Vec<String>,
+        token: String,
+    }
+
+    impl ConfigSection for Features {
+        const SECTION: &'static str = "app.features";
+    }
+
+    #[tokio::test]
+    async fn test_config_sections_are_typed_and_cached() {
+        let path = std::env::temp_dir().join(format!("torch-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[app.features]\nsignups = true\nbeta_users = [\"ada\"]\ntoken = \"env:TORCH_TEST_FEATURES_TOKEN\"\n",
+        )
+        .unwrap();
+        std::env::set_var("TORCH_TEST_FEATURES_TOKEN", "s3cret");
+
+        let app = App::new().with_state(ConfigFile(path.clone())).get("/beta", |features: Config<Features>| async move {
+            format!("{} {} {}", features.signups, features.beta_users.join(","), features.token)
+        });
+        let response = app.handle_request(Request::mock(Method::GET, "/beta")).await;
+        assert_eq!(response.body_data(), b"true ada s3cret");
+
+        // Parsed once: later edits don't show until restart
+        std::fs::write(&path, "[app.features]\nsignups = false\nbeta_users = []\ntoken = \"x\"\n").unwrap();
+        assert!(Config::<Features>::load_from(&path).unwrap().signups);
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = App::new()
+            .with_state(ConfigFile(path.with_extension("missing")))
+            .get("/beta", |_features: Config<Features>| async { "unreachable" });
+        let response = missing.handle_request(Request::mock(Method::GET, "/beta")).await;
+        assert_eq!(response.status_code().as_u16(), 500);
+    }
+}
@@ -0,0 +1,194 @@
+//! Bodies in any content type
+//!
+//! [`Decoded<T>`] deserializes the request body with the decoder registered
+//! for its `Content-Type`, so protobuf, CSV or MessagePack payloads get the
+//! same extractor ergonomics as JSON.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::extractors::state::RequestStateExt;
+use crate::{Request, extractors::{ExtractionError, FromRequestParts}};
+
+/// Turns a request body into a value that [`Decoded`] deserializes from.
+///
+/// Closures taking the body bytes implement it.
+pub trait BodyDecoder: Send + Sync + 'static {
+    fn decode(&self, body: &[u8]) -> Result<Value, String>;
+}
+
+impl<F> BodyDecoder for F
+where
+    F: Fn(&[u8]) -> Result<Value, String> + Send + Sync + 'static,
+{
+    fn decode(&self, body: &[u8]) -> Result<Value, String> {
+        self(body)
+    }
+}
+
+/// Decoders by media type, registered with
+/// [`App::body_decoder`](crate::App::body_decoder).
+///
+/// `application/json` and `+json` types are decoded out of the box unless a
+/// decoder is registered for them.
+#[derive(Clone, Default)]
+pub struct BodyDecoders {
+    decoders: HashMap<String, Arc<dyn BodyDecoder>>,
+}
+
+impl BodyDecoders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode bodies of `content_type`, e.g. `application/x-protobuf`,
+    /// with `decoder`, replacing any decoder registered for it before
+    pub fn register<D: BodyDecoder>(&mut self, content_type: &str, decoder: D) {
+        self.decoders.insert(content_type.trim().to_ascii_lowercase(), Arc::new(decoder));
+    }
+
+    /// Media types with a registered decoder
+    pub fn content_types(&self) -> Vec<&str> {
+        let mut types: Vec<&str> = self.decoders.keys().map(String::as_str).collect();
+        types.sort_unstable();
+        types
+    }
+
+    /// Decode `body` sent as `content_type`, which may carry parameters such
+    /// as `; charset=utf-8`
+    pub fn decode(&self, content_type: &str, body: &[u8]) -> Result<Value, ExtractionError> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        let invalid = |error: String| ExtractionError::Custom(format!("Invalid {} body: {}", media_type, error));
+
+        if let Some(decoder) = self.decoders.get(&media_type) {
+            return decoder.decode(body).map_err(invalid);
+        }
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            return serde_json::from_slice(body).map_err(|e| invalid(e.to_string()));
+        }
+        Err(ExtractionError::UnsupportedMediaType(if media_type.is_empty() {
+            "Missing content type".to_string()
+        } else {
+            format!("No decoder for {}", media_type)
+        }))
+    }
+}
+
+/// Extract a body of any registered content type, deserialized into `T`.
+///
+/// Bodies without a decoder for their `Content-Type` are rejected with
+/// `415 Unsupported Media Type`, ones that don't decode or don't fit `T`
+/// with `400 Bad Request`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use serde::Deserialize;
+/// use serde_json::{Map, Value};
+/// use torch_web::App;
+/// use torch_web::extractors::Decoded;
+///
+/// #[derive(Deserialize)]
+/// struct Contact {
+///     name: String,
+///     email: String,
+/// }
+///
+/// // A CSV body with a header row becomes a list of objects
+/// fn csv(body: &[u8]) -> Result<Value, String> {
+///     let text = std::str::from_utf8(body).map_err(|e| e.to_string())?;
+///     let mut lines = text.lines();
+///     let columns: Vec<&str> = lines.next().unwrap_or("").split(',').collect();
+///     Ok(lines
+///         .map(|line| {
+///             let row: Map<String, Value> = columns.iter().zip(line.split(','))
+///                 .map(|(column, cell)| (column.to_string(), Value::from(cell)))
+///                 .collect();
+///             Value::Object(row)
+///         })
+///         .collect())
+/// }
+///
+/// let app = App::new()
+///     .body_decoder("text/csv", csv)
+///     .post("/contacts/import", |Decoded(contacts): Decoded<Vec<Contact>>| async move {
+///         format!("imported {} contacts", contacts.len())
+///     });
+/// ```
+pub struct Decoded<T>(pub T);
+
+impl<T> FromRequestParts for Decoded<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Error = ExtractionError;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let decoders = req
+            .get_state(std::any::TypeId::of::<BodyDecoders>())
+            .and_then(|state| state.downcast_ref::<BodyDecoders>())
+            .cloned()
+            .unwrap_or_default();
+        let content_type = req.header("content-type").unwrap_or("").to_string();
+        let result = decoders.decode(&content_type, &req.take_body()).and_then(|value| {
+            serde_json::from_value(value)
+                .map(Decoded)
+                .map_err(|e| ExtractionError::Custom(format!("Invalid body: {}", e)))
+        });
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    #[tokio::test]
+    async fn test_decoded_uses_registered_decoders() {
+        // "x y" per line
+        let pairs = |body: &[u8]| -> Result<Value, String> {
+            let text = std::str::from_utf8(body).map_err(|e| e.to_string())?;
+            text.lines()
+                .map(|line| match line.split_once(' ').map(|(x, y)| (x.parse::<i64>(), y.parse::<i64>())) {
+                    Some((Ok(x), Ok(y))) => Ok(serde_json::json!({ "x": x, "y": y })),
+                    _ => Err(format!("bad line '{}'", line)),
+                })
+                .collect()
+        };
+        let app = App::new()
+            .body_decoder("text/x-points", pairs)
+            .post("/sum", |Decoded(points): Decoded<Vec<Point>>| async move {
+                points.iter().map(|point| point.x * point.y).sum::<i64>().to_string()
+            });
+
+        let post = |content_type: &str, body: &str| {
+            let mut req = Request::mock(Method::POST, "/sum").with_header("content-type", content_type);
+            req.set_body(body.as_bytes().to_vec());
+            app.handle_request(req)
+        };
+        let summed = post("text/x-points; charset=utf-8", "2 3\n4 5").await;
+        assert_eq!(summed.body_data(), b"26");
+        let json = post("application/json", r#"[{"x": 1, "y": 7}]"#).await;
+        assert_eq!(json.body_data(), b"7");
+
+        assert_eq!(post("text/x-points", "2 three").await.status_code().as_u16(), 400);
+        assert_eq!(post("application/json", r#"[{"x": 1}]"#).await.status_code().as_u16(), 400);
+        assert_eq!(post("application/x-protobuf", "\x08\x01").await.status_code().as_u16(), 415);
+    }
+}
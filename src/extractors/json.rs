@@ -202,6 +202,22 @@ where
     }
 }
 
+/// Respond with the value as JSON: `Json(post)` or `(StatusCode::CREATED, Json(post))`
+impl<T> crate::extractors::IntoResponse for Json<T>
+where
+    T: serde::Serialize,
+{
+    fn into_response(self) -> crate::Response {
+        match crate::Response::ok().json(&self.0) {
+            Ok(response) => response,
+            Err(error) => {
+                eprintln!("⚠️  Could not serialize JSON response: {}", error);
+                crate::Response::internal_error()
+            }
+        }
+    }
+}
+
 /// Extract raw JSON value without type checking
 ///
 /// # Example
@@ -0,0 +1,218 @@
+//! List query parameters
+//!
+//! One shape for the query strings of listing endpoints: `?page=2`,
+//! `?per_page=50`, `?sort=-created_at,title` and `?filter[status]=active`.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::extractors::state::RequestStateExt;
+use crate::{Request, extractors::{FromRequestParts, ExtractionError}};
+
+/// Page size limits for [`ListParams`], registered as app state to replace
+/// the defaults of 15 per page and at most 100
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListLimits {
+    pub default_per_page: u32,
+    pub max_per_page: u32,
+}
+
+impl Default for ListLimits {
+    fn default() -> Self {
+        Self {
+            default_per_page: 15,
+            max_per_page: 100,
+        }
+    }
+}
+
+/// One `?sort` field; a leading `-` sorts descending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub field: String,
+    pub descending: bool,
+}
+
+/// Paging, sorting and filtering parameters of a listing request
+///
+/// `page` starts at 1 and `per_page` is capped by [`ListLimits`]. Field names
+/// must be plain identifiers such as `created_at` or `author.name`; list the
+/// ones a client may use with [`allow_sort`](Self::allow_sort) and
+/// [`allow_filters`](Self::allow_filters) before handing the parameters to
+/// the ORM's [`QueryBuilder::list`](crate::orm::QueryBuilder::list).
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use torch_web::extractors::{ExtractionError, ListParams};
+///
+/// // GET /posts?page=2&sort=-published_at&filter[status]=draft,review
+/// async fn index(params: ListParams) -> Result<String, ExtractionError> {
+///     let params = params
+///         .allow_sort(&["published_at", "title"])?
+///         .allow_filters(&["status", "author_id"])?
+///         .default_sort("-published_at");
+///     Ok(format!("page {} of posts by {:?}", params.page, params.sort))
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListParams {
+    pub page: u32,
+    pub per_page: u32,
+    pub sort: Vec<Sort>,
+    /// `filter[field]=value` pairs in the order given; a comma separated
+    /// value matches any of its parts
+    pub filters: Vec<(String, String)>,
+}
+
+fn is_field(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').all(|part| {
+            part.chars().next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+fn check_field(kind: &str, name: &str) -> Result<(), ExtractionError> {
+    if is_field(name) {
+        Ok(())
+    } else {
+        Err(ExtractionError::InvalidQuery(format!("invalid {} field '{}'", kind, name)))
+    }
+}
+
+fn not_allowed(kind: &str, name: &str, allowed: &[&str]) -> ExtractionError {
+    ExtractionError::InvalidQuery(format!("cannot {} by '{}'; allowed: {}", kind, name, allowed.join(", ")))
+}
+
+impl ListParams {
+    /// Parse a query string, e.g. `page=2&sort=-created_at`
+    pub fn parse(query: &str, limits: ListLimits) -> Result<Self, ExtractionError> {
+        let mut params = Self {
+            page: 1,
+            per_page: limits.default_per_page,
+            sort: Vec::new(),
+            filters: Vec::new(),
+        };
+        let number = |name: &str, value: &str| {
+            value.parse::<u32>().map_err(|_| ExtractionError::InvalidQuery(format!("{} must be a whole number, got '{}'", name, value)))
+        };
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let key = urlencoding::decode(key).map_err(|e| ExtractionError::InvalidQuery(format!("Invalid key encoding: {}", e)))?;
+            let value = urlencoding::decode(&value.replace('+', " "))
+                .map_err(|e| ExtractionError::InvalidQuery(format!("Invalid value encoding: {}", e)))?
+                .into_owned();
+
+            match key.as_ref() {
+                "page" => params.page = number("page", &value)?.max(1),
+                "per_page" => {
+                    params.per_page = match number("per_page", &value)? {
+                        0 => limits.default_per_page,
+                        per_page => per_page.min(limits.max_per_page),
+                    }
+                }
+                "sort" => {
+                    for field in value.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+                        let (field, descending) = match field.strip_prefix('-') {
+                            Some(field) => (field, true),
+                            None => (field, false),
+                        };
+                        check_field("sort", field)?;
+                        params.sort.push(Sort { field: field.to_string(), descending });
+                    }
+                }
+                key => {
+                    if let Some(field) = key.strip_prefix("filter[").and_then(|rest| rest.strip_suffix(']')) {
+                        check_field("filter", field)?;
+                        params.filters.push((field.to_string(), value));
+                    }
+                }
+            }
+        }
+        Ok(params)
+    }
+
+    /// Reject sorting by fields other than `allowed`
+    pub fn allow_sort(self, allowed: &[&str]) -> Result<Self, ExtractionError> {
+        match self.sort.iter().find(|sort| !allowed.contains(&sort.field.as_str())) {
+            Some(sort) => Err(not_allowed("sort", &sort.field, allowed)),
+            None => Ok(self),
+        }
+    }
+
+    /// Reject filtering by fields other than `allowed`
+    pub fn allow_filters(self, allowed: &[&str]) -> Result<Self, ExtractionError> {
+        match self.filters.iter().find(|(field, _)| !allowed.contains(&field.as_str())) {
+            Some((field, _)) => Err(not_allowed("filter", field, allowed)),
+            None => Ok(self),
+        }
+    }
+
+    /// Sort by `sort`, written like the query parameter, when the request
+    /// didn't ask for an order
+    pub fn default_sort(mut self, sort: &str) -> Self {
+        if self.sort.is_empty() {
+            if let Ok(defaults) = Self::parse(&format!("sort={}", sort), ListLimits::default()) {
+                self.sort = defaults.sort;
+            }
+        }
+        self
+    }
+
+    /// The value of `filter[field]`
+    pub fn filter(&self, field: &str) -> Option<&str> {
+        self.filters.iter().find(|(name, _)| name == field).map(|(_, value)| value.as_str())
+    }
+
+    /// Rows to skip before this page
+    pub fn offset(&self) -> u32 {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+}
+
+impl FromRequestParts for ListParams {
+    type Error = ExtractionError;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let limits = req
+            .get_state(std::any::TypeId::of::<ListLimits>())
+            .and_then(|state| state.downcast_ref::<ListLimits>())
+            .copied()
+            .unwrap_or_default();
+        let result = Self::parse(req.query_string().unwrap_or(""), limits);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_params_parse_and_validate() {
+        let params = ListParams::parse("page=3&per_page=500&sort=-created_at,title&filter%5Bstatus%5D=draft,review&q=x", ListLimits::default()).unwrap();
+        assert_eq!((params.page, params.per_page, params.offset()), (3, 100, 200));
+        assert_eq!(
+            params.sort,
+            vec![
+                Sort { field: "created_at".to_string(), descending: true },
+                Sort { field: "title".to_string(), descending: false },
+            ]
+        );
+        assert_eq!(params.filter("status"), Some("draft,review"));
+
+        let defaults = ListParams::parse("page=0", ListLimits { default_per_page: 25, max_per_page: 50 }).unwrap();
+        assert_eq!((defaults.page, defaults.per_page), (1, 25));
+        assert_eq!(defaults.default_sort("-id").sort, vec![Sort { field: "id".to_string(), descending: true }]);
+
+        assert!(params.clone().allow_sort(&["created_at", "title"]).unwrap().allow_filters(&["status"]).is_ok());
+        assert!(params.clone().allow_sort(&["title"]).is_err());
+        assert!(params.allow_filters(&["author_id"]).is_err());
+        assert!(ListParams::parse("sort=name;drop table users", ListLimits::default()).is_err());
+        assert!(ListParams::parse("page=two", ListLimits::default()).is_err());
+    }
+}
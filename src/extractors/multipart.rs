@@ -0,0 +1,654 @@
+//! Multipart form extraction
+//!
+//! Extract the text fields and uploaded files of `multipart/form-data`
+//! request bodies. [`Multipart`] reads the whole form before the handler
+//! runs; [`MultipartStream`] hands over one field at a time while the body is
+//! still arriving, so big files go to storage part by part.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use futures::StreamExt;
+use hyper::body::Bytes;
+
+use crate::request::BodyStream;
+use crate::storage::{self, Storage, StorageError, UploadProgress};
+use crate::{Request, extractors::{FromRequestParts, ExtractionError}};
+
+/// Longest header block accepted for a single part
+const MAX_PART_HEADERS: usize = 16 * 1024;
+
+/// Extract a `multipart/form-data` body, as sent by forms with file inputs
+///
+/// The whole form is read into memory, files keep pointing into it, and
+/// [`UploadedFile::store`] hands them to a storage disk. Use
+/// [`MultipartStream`] for files too big to hold in memory.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use torch_web::extractors::{Multipart, Path};
+///
+/// async fn update_avatar(Path(user_id): Path<u64>, form: Multipart) -> Result<String, torch_web::storage::StorageError> {
+///     let name = form.text("name").unwrap_or("anonymous").to_string();
+///     if let Some(avatar) = form.file("avatar") {
+///         // The client's file name is only a hint, never part of the path
+///         avatar.store("public", &format!("avatars/{}.png", user_id)).await?;
+///     }
+///     Ok(format!("Saved {}", name))
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Multipart {
+    fields: Vec<(String, String)>,
+    files: Vec<UploadedFile>,
+}
+
+/// A file sent in a multipart form
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    field: String,
+    file_name: String,
+    content_type: Option<String>,
+    contents: Bytes,
+}
+
+impl Multipart {
+    /// The first text field called `name`
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Every text field, in the order sent
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// The first file sent in the field called `name`
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|file| file.field == name)
+    }
+
+    /// Every file, in the order sent
+    pub fn files(&self) -> &[UploadedFile] {
+        &self.files
+    }
+
+    /// Parse a body sent with the given multipart boundary
+    pub fn parse(boundary: &str, body: Bytes) -> Result<Self, ExtractionError> {
+        let mut parser = Parser::new(boundary);
+        parser.feed(body);
+
+        let mut form = Multipart::default();
+        let mut part = None;
+        // With the whole body fed, the parser never asks for more
+        while let Some(event) = parser.next(true)? {
+            if form.absorb(&mut part, event)? {
+                break;
+            }
+        }
+        Ok(form)
+    }
+
+    /// Read every field of a form as it arrives
+    async fn read(mut stream: MultipartStream) -> Result<Self, ExtractionError> {
+        let mut form = Multipart::default();
+        let mut part = None;
+        while !form.absorb(&mut part, stream.next_event().await?)? {}
+        Ok(form)
+    }
+
+    /// Add the parser's next event to the part being read, returning whether
+    /// the form is complete
+    fn absorb(&mut self, part: &mut Option<(PartHeaders, Vec<Bytes>)>, event: Event) -> Result<bool, ExtractionError> {
+        match event {
+            Event::Part(headers) => *part = Some((headers, Vec::new())),
+            Event::Data(chunk) => {
+                if let Some((_, chunks)) = part {
+                    chunks.push(chunk);
+                }
+            }
+            Event::PartEnd => {
+                if let Some((headers, mut chunks)) = part.take() {
+                    let contents = match chunks.len() {
+                        1 => chunks.remove(0),
+                        _ => Bytes::from(chunks.concat()),
+                    };
+                    self.add_part(headers, contents)?;
+                }
+            }
+            Event::End => return Ok(true),
+        }
+        Ok(false)
+    }
+
+    fn add_part(&mut self, headers: PartHeaders, contents: Bytes) -> Result<(), ExtractionError> {
+        let PartHeaders { field, file_name, content_type } = headers;
+        match file_name {
+            // An empty file input still sends a part, with no file name
+            Some(file_name) if file_name.is_empty() && contents.is_empty() => {}
+            Some(file_name) => self.files.push(UploadedFile { field, file_name, content_type, contents }),
+            None => {
+                let value = String::from_utf8(contents.to_vec())
+                    .map_err(|_| ExtractionError::InvalidForm(format!("field {} is not UTF-8", field)))?;
+                self.fields.push((field, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extract a `multipart/form-data` body one field at a time, while the client
+/// is still sending it
+///
+/// Nothing is buffered beyond the chunk being handed over: read a field with
+/// [`MultipartField::chunk`], or send it to a storage disk with
+/// [`MultipartField::store`], which writes it in parts as it arrives and
+/// aborts the upload if the client disconnects half way. Fields left unread
+/// are skipped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use torch_web::extractors::{MultipartStream, Path};
+/// use torch_web::Response;
+///
+/// async fn upload_video(Path(id): Path<u64>, mut form: MultipartStream) -> Response {
+///     loop {
+///         let field = match form.next_field().await {
+///             Ok(Some(field)) => field,
+///             Ok(None) => return Response::bad_request().body("No video"),
+///             Err(error) => return Response::bad_request().body(error.to_string()),
+///         };
+///         if field.name() == "video" {
+///             return match field.store("s3", &format!("videos/{}.mp4", id)).await {
+///                 Ok(()) => Response::ok().body("Uploaded"),
+///                 Err(error) => Response::internal_error().body(error.to_string()),
+///             };
+///         }
+///     }
+/// }
+/// ```
+pub struct MultipartStream {
+    parser: Parser,
+    body: Option<BodyStream>,
+}
+
+/// One field of a [`MultipartStream`], read as it arrives
+pub struct MultipartField<'a> {
+    headers: PartHeaders,
+    form: &'a mut MultipartStream,
+    done: bool,
+}
+
+impl std::fmt::Debug for MultipartStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultipartStream").finish_non_exhaustive()
+    }
+}
+
+impl MultipartStream {
+    fn new(boundary: &str, body: BodyStream) -> Self {
+        Self { parser: Parser::new(boundary), body: Some(body) }
+    }
+
+    /// The next field, or `None` once the form is complete. What is left of
+    /// the previous field is skipped.
+    pub async fn next_field(&mut self) -> Result<Option<MultipartField<'_>>, ExtractionError> {
+        loop {
+            match self.next_event().await? {
+                Event::Part(headers) => return Ok(Some(MultipartField { headers, form: self, done: false })),
+                Event::End => return Ok(None),
+                Event::Data(_) | Event::PartEnd => {}
+            }
+        }
+    }
+
+    /// The next event of the form, reading more of the body when needed
+    async fn next_event(&mut self) -> Result<Event, ExtractionError> {
+        loop {
+            if let Some(event) = self.parser.next(self.body.is_none())? {
+                return Ok(event);
+            }
+            let chunk = match &mut self.body {
+                Some(body) => body.next().await,
+                None => return Ok(Event::End),
+            };
+            match chunk {
+                Some(Ok(chunk)) => self.parser.feed(chunk),
+                Some(Err(error)) => return Err(ExtractionError::InvalidForm(format!("request body interrupted: {}", error))),
+                None => self.body = None,
+            }
+        }
+    }
+}
+
+impl MultipartField<'_> {
+    /// Name of the form field
+    pub fn name(&self) -> &str {
+        &self.headers.field
+    }
+
+    /// File name given by the client for file inputs, empty when no file was
+    /// chosen. Don't trust it as a storage path.
+    pub fn file_name(&self) -> Option<&str> {
+        self.headers.file_name.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.content_type.as_deref()
+    }
+
+    /// The next chunk of the field's contents, or `None` at its end
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, ExtractionError> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.form.next_event().await? {
+            Event::Data(chunk) => Ok(Some(chunk)),
+            _ => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// The rest of the field as text
+    pub async fn text(mut self) -> Result<String, ExtractionError> {
+        let mut contents = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            contents.extend_from_slice(&chunk);
+        }
+        String::from_utf8(contents).map_err(|_| ExtractionError::InvalidForm(format!("field {} is not UTF-8", self.headers.field)))
+    }
+
+    /// Write the rest of the field to `path` on the named storage disk
+    pub async fn store(self, disk: &str, path: &str) -> Result<(), StorageError> {
+        self.store_with_progress(disk, path, |_| {}).await
+    }
+
+    /// Write the rest of the field to `path` on the named storage disk,
+    /// calling `progress` after each part is sent. The total isn't known
+    /// while the field arrives, so it is reported as 0.
+    pub async fn store_with_progress(
+        self,
+        disk: &str,
+        path: &str,
+        progress: impl FnMut(UploadProgress) + Send,
+    ) -> Result<(), StorageError> {
+        let disk = Storage::disk(disk)?;
+        let chunks = futures::stream::unfold(self, |mut field| async move {
+            field.chunk().await.transpose().map(|chunk| (chunk, field))
+        });
+        storage::upload_stream(&*disk, path, chunks, progress).await
+    }
+}
+
+/// The headers of one part
+#[derive(Debug, Clone)]
+struct PartHeaders {
+    field: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+impl PartHeaders {
+    fn parse(headers: &str) -> Result<Self, ExtractionError> {
+        let mut disposition = None;
+        let mut content_type = None;
+        for line in headers.split("\r\n") {
+            let Some((name, value)) = line.split_once(':') else { continue };
+            if name.trim().eq_ignore_ascii_case("content-disposition") {
+                disposition = Some(value.trim());
+            } else if name.trim().eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.trim().to_string());
+            }
+        }
+
+        let disposition = disposition.ok_or_else(|| invalid("part without Content-Disposition"))?;
+        let field = disposition_param(disposition, "name").ok_or_else(|| invalid("part without a field name"))?;
+        let file_name = disposition_param(disposition, "filename");
+        Ok(Self { field, file_name, content_type })
+    }
+}
+
+/// What the parser found next in the body
+enum Event {
+    /// A part starts, its contents follow
+    Part(PartHeaders),
+    Data(Bytes),
+    PartEnd,
+    /// The closing boundary
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Nothing read yet: the first boundary may open the body
+    Start,
+    Preamble,
+    /// Just after a boundary: a part or the end of the form follows
+    Boundary,
+    Headers,
+    Contents,
+    Done,
+}
+
+/// Incremental `multipart/form-data` parser, fed the body as it arrives
+struct Parser {
+    /// `\r\n--boundary`, which ends every part
+    delimiter: Vec<u8>,
+    buffer: Bytes,
+    state: State,
+}
+
+impl Parser {
+    fn new(boundary: &str) -> Self {
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            buffer: Bytes::new(),
+            state: State::Start,
+        }
+    }
+
+    fn feed(&mut self, chunk: Bytes) {
+        if self.buffer.is_empty() {
+            self.buffer = chunk;
+        } else if !chunk.is_empty() {
+            let mut joined = Vec::with_capacity(self.buffer.len() + chunk.len());
+            joined.extend_from_slice(&self.buffer);
+            joined.extend_from_slice(&chunk);
+            self.buffer = Bytes::from(joined);
+        }
+    }
+
+    /// The next event in what has been fed so far, or `None` when more of the
+    /// body is needed. `finished` says nothing more is coming.
+    fn next(&mut self, finished: bool) -> Result<Option<Event>, ExtractionError> {
+        loop {
+            match self.state {
+                State::Start => {
+                    let dash_boundary = &self.delimiter[2..];
+                    if self.buffer.starts_with(dash_boundary) {
+                        self.buffer = self.buffer.slice(dash_boundary.len()..);
+                        self.state = State::Boundary;
+                    } else if !finished && dash_boundary.starts_with(&self.buffer) {
+                        return Ok(None);
+                    } else {
+                        self.state = State::Preamble;
+                    }
+                }
+                State::Preamble => match find(&self.buffer, &self.delimiter) {
+                    Some(index) => {
+                        self.buffer = self.buffer.slice(index + self.delimiter.len()..);
+                        self.state = State::Boundary;
+                    }
+                    None if finished => return Err(invalid("missing multipart boundary")),
+                    None => {
+                        let keep = self.buffer.len().min(self.delimiter.len() - 1);
+                        self.buffer = self.buffer.slice(self.buffer.len() - keep..);
+                        return Ok(None);
+                    }
+                },
+                State::Boundary if self.buffer.len() < 2 => {
+                    return match finished {
+                        true => Err(invalid("missing closing multipart boundary")),
+                        false => Ok(None),
+                    };
+                }
+                State::Boundary if self.buffer.starts_with(b"--") => {
+                    self.state = State::Done;
+                    self.buffer = Bytes::new();
+                }
+                State::Boundary if self.buffer.starts_with(b"\r\n") => {
+                    self.buffer = self.buffer.slice(2..);
+                    self.state = State::Headers;
+                }
+                State::Boundary => return Err(invalid("malformed multipart boundary")),
+                State::Headers => match find(&self.buffer, b"\r\n\r\n") {
+                    Some(end) => {
+                        let headers = std::str::from_utf8(&self.buffer[..end]).map_err(|_| invalid("part headers are not UTF-8"))?;
+                        let headers = PartHeaders::parse(headers)?;
+                        self.buffer = self.buffer.slice(end + 4..);
+                        self.state = State::Contents;
+                        return Ok(Some(Event::Part(headers)));
+                    }
+                    None if self.buffer.len() > MAX_PART_HEADERS => return Err(invalid("part headers are too long")),
+                    None if finished => return Err(invalid("unterminated part headers")),
+                    None => return Ok(None),
+                },
+                State::Contents => match find(&self.buffer, &self.delimiter) {
+                    Some(0) => {
+                        self.buffer = self.buffer.slice(self.delimiter.len()..);
+                        self.state = State::Boundary;
+                        return Ok(Some(Event::PartEnd));
+                    }
+                    Some(end) => return Ok(Some(Event::Data(self.buffer.split_to(end)))),
+                    None if finished => return Err(invalid("missing closing multipart boundary")),
+                    None => {
+                        // Hold back what could be the start of the next boundary
+                        let ready = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+                        return Ok((ready > 0).then(|| Event::Data(self.buffer.split_to(ready))));
+                    }
+                },
+                State::Done => return Ok(Some(Event::End)),
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> ExtractionError {
+    ExtractionError::InvalidForm(message.to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// A parameter such as `name="avatar"` of a `Content-Disposition` value
+fn disposition_param(disposition: &str, param: &str) -> Option<String> {
+    disposition.split(';').skip(1).find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(param) {
+            return None;
+        }
+        let value = value.trim();
+        let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+        Some(value.replace("\\\"", "\""))
+    })
+}
+
+impl UploadedFile {
+    /// Name of the form field the file was sent in
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// File name given by the client. Don't trust it as a storage path.
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.contents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        &self.contents
+    }
+
+    /// Write the file to `path` on the named storage disk
+    pub async fn store(&self, disk: &str, path: &str) -> Result<(), StorageError> {
+        self.store_with_progress(disk, path, |_| {}).await
+    }
+
+    /// Write the file to `path` on the named storage disk, calling
+    /// `progress` after each part is sent
+    pub async fn store_with_progress(
+        &self,
+        disk: &str,
+        path: &str,
+        progress: impl FnMut(UploadProgress) + Send,
+    ) -> Result<(), StorageError> {
+        let disk = Storage::disk(disk)?;
+        storage::upload(&*disk, path, self.contents.clone(), progress).await
+    }
+}
+
+/// The boundary of a `multipart/form-data` request
+fn boundary(req: &Request) -> Result<String, ExtractionError> {
+    let content_type = req.header("content-type").unwrap_or("");
+    let (media_type, params) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !media_type.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return Err(ExtractionError::UnsupportedMediaType(format!(
+            "Expected multipart/form-data content type, got: {}",
+            content_type
+        )));
+    }
+    params
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"').to_string())
+        .ok_or_else(|| invalid("missing multipart boundary"))
+}
+
+impl FromRequestParts for Multipart {
+    type Error = ExtractionError;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let form = boundary(req).map(|boundary| MultipartStream::new(&boundary, req.take_body_stream()));
+        Box::pin(async move { Multipart::read(form?).await })
+    }
+}
+
+impl FromRequestParts for MultipartStream {
+    type Error = ExtractionError;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let form = boundary(req).map(|boundary| MultipartStream::new(&boundary, req.take_body_stream()));
+        Box::pin(async move { form })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+    use crate::storage::Disk;
+
+    #[tokio::test]
+    async fn test_multipart_fields_and_files() {
+        let body = "--XyZ\r\n\
+                    Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+                    Holiday\r\n\
+                    --XyZ\r\n\
+                    Content-Disposition: form-data; name=\"photo\"; filename=\"beach.png\"\r\n\
+                    Content-Type: image/png\r\n\r\n\
+                    \u{1}PNG\r\nrest\r\n\
+                    --XyZ\r\n\
+                    Content-Disposition: form-data; name=\"extra\"; filename=\"\"\r\n\
+                    Content-Type: application/octet-stream\r\n\r\n\
+                    \r\n\
+                    --XyZ--\r\n";
+        let mut req = Request::mock(Method::POST, "/photos")
+            .with_header("content-type", "multipart/form-data; boundary=\"XyZ\"")
+            .with_body(body);
+
+        let form = Multipart::from_request_parts(&mut req).await.unwrap();
+        assert!(req.body().is_empty());
+        assert_eq!(form.text("title"), Some("Holiday"));
+        assert_eq!(form.files().len(), 1);
+        let photo = form.file("photo").unwrap();
+        assert_eq!((photo.file_name(), photo.content_type()), ("beach.png", Some("image/png")));
+        assert_eq!(photo.bytes().as_ref(), b"\x01PNG\r\nrest");
+
+        let photos = Storage::fake("photos");
+        photo.store("photos", "2024/beach.png").await.unwrap();
+        assert_eq!(photos.get("2024/beach.png").await.unwrap(), b"\x01PNG\r\nrest");
+
+        let mut form_encoded = Request::mock(Method::POST, "/photos").with_header("content-type", "application/x-www-form-urlencoded");
+        assert!(matches!(Multipart::from_request_parts(&mut form_encoded).await, Err(ExtractionError::UnsupportedMediaType(_))));
+        assert!(Multipart::parse("XyZ", Bytes::from_static(b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nno end")).is_err());
+    }
+
+    /// A body arriving `size` bytes at a time, failing at the end when `cut`
+    fn arriving(body: &[u8], size: usize, cut: bool) -> crate::request::BodyStream {
+        let mut chunks: Vec<Result<Bytes, Box<dyn std::error::Error + Send + Sync>>> =
+            body.chunks(size).map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect();
+        if cut {
+            chunks.push(Err("connection reset".into()));
+        }
+        Box::pin(futures::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_multipart_stream_reads_fields_as_they_arrive() {
+        let form_body = |video: &[u8]| {
+            let mut body = b"preamble\r\n--XyZ\r\n\
+                             Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+                             Intro\r\n--XyZ\r\n\
+                             Content-Disposition: form-data; name=\"skipped\"\r\n\r\n\
+                             unread\r\n--XyZ\r\n\
+                             Content-Disposition: form-data; name=\"video\"; filename=\"intro.mp4\"\r\n\r\n"
+                .to_vec();
+            body.extend_from_slice(video);
+            body.extend_from_slice(b"\r\n--XyZ--\r\n");
+            body
+        };
+
+        // In chunks that split boundaries and headers anywhere
+        let clip = b"\r\n--Xy-frames-\r\n-".repeat(20);
+        let body = form_body(&clip);
+        for size in [1, 7, 64, body.len()] {
+            let mut req = Request::mock(Method::POST, "/videos")
+                .with_header("content-type", "multipart/form-data; boundary=XyZ")
+                .with_body_stream(arriving(&body, size, false));
+            let form = Multipart::from_request_parts(&mut req).await.unwrap();
+            assert_eq!((form.text("title"), form.text("skipped")), (Some("Intro"), Some("unread")));
+            assert_eq!(form.file("video").unwrap().bytes().as_ref(), &clip[..]);
+        }
+
+        let video = vec![9u8; storage::PART_SIZE + 3];
+        let body = form_body(&video);
+        let videos = Storage::fake("videos");
+        let mut req = Request::mock(Method::POST, "/videos")
+            .with_header("content-type", "multipart/form-data; boundary=XyZ")
+            .with_body_stream(arriving(&body, 1000, false));
+        let mut form = MultipartStream::from_request_parts(&mut req).await.unwrap();
+        assert_eq!(form.next_field().await.unwrap().unwrap().text().await.unwrap(), "Intro");
+        assert_eq!(form.next_field().await.unwrap().unwrap().name(), "skipped");
+        let field = form.next_field().await.unwrap().unwrap();
+        assert_eq!((field.name(), field.file_name()), ("video", Some("intro.mp4")));
+        let mut reports = Vec::new();
+        field.store_with_progress("videos", "1.mp4", |progress| reports.push(progress.sent)).await.unwrap();
+        assert_eq!(videos.get("1.mp4").await.unwrap(), video);
+        assert_eq!(reports.last(), Some(&(video.len() as u64)));
+        assert!(form.next_field().await.unwrap().is_none());
+
+        // The client goes away half way through the file
+        let mut req = Request::mock(Method::POST, "/videos")
+            .with_header("content-type", "multipart/form-data; boundary=XyZ")
+            .with_body_stream(arriving(&body[..body.len() - 1000], 1000, true));
+        let mut form = MultipartStream::from_request_parts(&mut req).await.unwrap();
+        while let Some(field) = form.next_field().await.unwrap() {
+            if field.name() == "video" {
+                let stored = field.store("videos", "2.mp4").await;
+                assert!(matches!(stored, Err(StorageError::Io(message)) if message.contains("connection reset")));
+                break;
+            }
+        }
+        videos.assert_missing("2.mp4");
+    }
+}
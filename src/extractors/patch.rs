@@ -0,0 +1,398 @@
+//! # JSON Patch and Merge Patch Bodies
+//!
+//! This module provides the [`Patch`] extractor for `PATCH` endpoints. It accepts
+//! `application/json-patch+json` ([RFC 6902]) and `application/merge-patch+json`
+//! ([RFC 7396]) bodies and applies them to an existing value or model.
+//!
+//! [RFC 6902]: https://www.rfc-editor.org/rfc/rfc6902
+//! [RFC 7396]: https://www.rfc-editor.org/rfc/rfc7396
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use crate::{Request, Response, extractors::{ExtractionError, FromRequest, IntoResponse}};
+use http::StatusCode;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+
+/// Content type of an RFC 6902 JSON Patch
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+/// Content type of an RFC 7396 JSON Merge Patch
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+
+/// Extractor for JSON Patch and JSON Merge Patch request bodies.
+///
+/// The body is parsed according to its `Content-Type`; any other content type is
+/// rejected with `415 Unsupported Media Type`. Apply the patch to the current
+/// state of the resource with [`Patch::apply`], which serializes the target,
+/// patches it and deserializes the result, so the patched value still has to be
+/// a valid `T`.
+///
+/// Patches are atomic: if any operation fails, the target is left untouched.
+///
+/// # Example
+///
+/// ```rust
+/// use torch_web::{App, Request, Response, extractors::{FromRequest, IntoResponse, Patch}};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Article {
+///     title: String,
+///     tags: Vec<String>,
+/// }
+///
+/// let app = App::new()
+///     .patch("/articles/:id", |req: Request| async move {
+///         let article = Article { title: "Draft".to_string(), tags: vec![] };
+///
+///         let patch = match Patch::<Article>::from_request(req).await {
+///             Ok((patch, _req)) => patch,
+///             Err(error) => return error.into_response(),
+///         };
+///         match patch.apply(&article) {
+///             Ok(article) => Response::ok().json(&article).unwrap(),
+///             Err(error) => error.into_response(),
+///         }
+///     });
+/// ```
+pub struct Patch<T = Value> {
+    document: PatchDocument,
+    _target: PhantomData<fn() -> T>,
+}
+
+/// A parsed patch body
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchDocument {
+    /// An `application/json-patch+json` list of operations
+    Json(Vec<PatchOperation>),
+    /// An `application/merge-patch+json` document
+    Merge(Value),
+}
+
+/// A single RFC 6902 operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// Why a patch could not be applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// A `test` operation found a different value
+    TestFailed(String),
+    /// A path points at something that does not exist
+    PathNotFound(String),
+    /// A path is not a valid JSON Pointer for the document
+    InvalidPath(String),
+    /// The patched document is no longer a valid target
+    InvalidResult(String),
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::TestFailed(path) => write!(f, "Patch test failed at '{}'", path),
+            PatchError::PathNotFound(path) => write!(f, "Patch path not found: '{}'", path),
+            PatchError::InvalidPath(path) => write!(f, "Invalid patch path: '{}'", path),
+            PatchError::InvalidResult(msg) => write!(f, "Patched document is invalid: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl IntoResponse for PatchError {
+    /// `409 Conflict` for a failed `test`, `422 Unprocessable Entity` otherwise
+    fn into_response(self) -> Response {
+        let status = match self {
+            PatchError::TestFailed(_) => StatusCode::CONFLICT,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        Response::with_status(status).body(self.to_string())
+    }
+}
+
+impl<T> Patch<T> {
+    /// Wrap an already parsed patch
+    pub fn new(document: PatchDocument) -> Self {
+        Self {
+            document,
+            _target: PhantomData,
+        }
+    }
+
+    /// The parsed patch body
+    pub fn document(&self) -> &PatchDocument {
+        &self.document
+    }
+
+    /// Apply the patch to a JSON value in place
+    pub fn apply_to_value(&self, target: &mut Value) -> Result<(), PatchError> {
+        match &self.document {
+            PatchDocument::Json(operations) => {
+                let mut patched = target.clone();
+                for operation in operations {
+                    apply_operation(&mut patched, operation)?;
+                }
+                *target = patched;
+            }
+            PatchDocument::Merge(patch) => merge(target, patch),
+        }
+        Ok(())
+    }
+}
+
+impl<T> Patch<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Apply the patch to `target` and return the patched copy
+    pub fn apply(&self, target: &T) -> Result<T, PatchError> {
+        let mut value = serde_json::to_value(target).map_err(|e| PatchError::InvalidResult(e.to_string()))?;
+        self.apply_to_value(&mut value)?;
+        serde_json::from_value(value).map_err(|e| PatchError::InvalidResult(e.to_string()))
+    }
+}
+
+impl<T> FromRequest for Patch<T> {
+    type Error = ExtractionError;
+
+    fn from_request(
+        req: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<(Self, Request), Self::Error>> + Send + 'static>> {
+        Box::pin(async move {
+            let content_type = req.headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+            let media_type = content_type.split(';').next().unwrap_or("").trim();
+
+            if media_type != JSON_PATCH && media_type != MERGE_PATCH {
+                return Err(ExtractionError::UnsupportedMediaType(format!(
+                    "Expected {} or {}, got: {}",
+                    JSON_PATCH, MERGE_PATCH, content_type
+                )));
+            }
+
+            let body_bytes = req.body_bytes();
+            if body_bytes.is_empty() {
+                return Err(ExtractionError::InvalidJson("Request body is empty".to_string()));
+            }
+
+            let document = if media_type == JSON_PATCH {
+                let operations = serde_json::from_slice(body_bytes)
+                    .map_err(|e| ExtractionError::InvalidJson(format!("Failed to parse JSON Patch: {}", e)))?;
+                PatchDocument::Json(operations)
+            } else {
+                let patch = serde_json::from_slice(body_bytes)
+                    .map_err(|e| ExtractionError::InvalidJson(format!("Failed to parse merge patch: {}", e)))?;
+                PatchDocument::Merge(patch)
+            };
+
+            Ok((Patch::new(document), req))
+        })
+    }
+}
+
+/// RFC 7396 merge: objects merge recursively, `null` removes, anything else replaces
+fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+fn apply_operation(document: &mut Value, operation: &PatchOperation) -> Result<(), PatchError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, path, value.clone()),
+        PatchOperation::Remove { path } => remove(document, path).map(|_| ()),
+        PatchOperation::Replace { path, value } => {
+            let target = document.pointer_mut(path).ok_or_else(|| PatchError::PathNotFound(path.clone()))?;
+            *target = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(PatchError::InvalidPath(path.clone()));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = document.pointer(from).cloned().ok_or_else(|| PatchError::PathNotFound(from.clone()))?;
+            add(document, path, value)
+        }
+        PatchOperation::Test { path, value } => match document.pointer(path) {
+            Some(actual) if actual == value => Ok(()),
+            Some(_) => Err(PatchError::TestFailed(path.clone())),
+            None => Err(PatchError::PathNotFound(path.clone())),
+        },
+    }
+}
+
+/// Split a JSON Pointer into its parent pointer and unescaped last token
+fn split_pointer(path: &str) -> Result<(&str, String), PatchError> {
+    let (parent, last) = path.rsplit_once('/').ok_or_else(|| PatchError::InvalidPath(path.to_string()))?;
+    Ok((parent, last.replace("~1", "/").replace("~0", "~")))
+}
+
+/// An array index token: digits without leading zeros
+fn array_index(token: &str, path: &str) -> Result<usize, PatchError> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) || !token.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(PatchError::InvalidPath(path.to_string()));
+    }
+    token.parse().map_err(|_| PatchError::InvalidPath(path.to_string()))
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), PatchError> {
+    if path.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+
+    let (parent, token) = split_pointer(path)?;
+    match document.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.insert(token, value);
+            Ok(())
+        }
+        Some(Value::Array(items)) => {
+            let index = if token == "-" { items.len() } else { array_index(&token, path)? };
+            if index > items.len() {
+                return Err(PatchError::PathNotFound(path.to_string()));
+            }
+            items.insert(index, value);
+            Ok(())
+        }
+        Some(_) => Err(PatchError::InvalidPath(path.to_string())),
+        None => Err(PatchError::PathNotFound(path.to_string())),
+    }
+}
+
+fn remove(document: &mut Value, path: &str) -> Result<Value, PatchError> {
+    if path.is_empty() {
+        return Ok(std::mem::take(document));
+    }
+
+    let (parent, token) = split_pointer(path)?;
+    match document.pointer_mut(parent) {
+        Some(Value::Object(map)) => map.remove(&token).ok_or_else(|| PatchError::PathNotFound(path.to_string())),
+        Some(Value::Array(items)) => {
+            let index = array_index(&token, path)?;
+            if index >= items.len() {
+                return Err(PatchError::PathNotFound(path.to_string()));
+            }
+            Ok(items.remove(index))
+        }
+        _ => Err(PatchError::PathNotFound(path.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patch_request(content_type: &str, body: Value) -> Request {
+        let mut req = Request::new();
+        req.headers_mut().insert("content-type", content_type.parse().unwrap());
+        req.set_body(serde_json::to_vec(&body).unwrap());
+        req
+    }
+
+    #[test]
+    fn test_json_patch_operations() {
+        let patch: Patch = Patch::new(PatchDocument::Json(
+            serde_json::from_value(json!([
+                { "op": "test", "path": "/a~1b", "value": 1 },
+                { "op": "add", "path": "/tags/-", "value": "new" },
+                { "op": "add", "path": "/tags/0", "value": "first" },
+                { "op": "replace", "path": "/title", "value": "Hello" },
+                { "op": "copy", "from": "/title", "path": "/subtitle" },
+                { "op": "move", "from": "/draft", "path": "/meta/draft" },
+                { "op": "remove", "path": "/a~1b" }
+            ]))
+            .unwrap(),
+        ));
+
+        let mut document = json!({ "a/b": 1, "title": "Hi", "tags": ["old"], "draft": true, "meta": {} });
+        patch.apply_to_value(&mut document).unwrap();
+        assert_eq!(
+            document,
+            json!({ "title": "Hello", "subtitle": "Hello", "tags": ["first", "old", "new"], "meta": { "draft": true } })
+        );
+    }
+
+    #[test]
+    fn test_json_patch_is_atomic() {
+        let patch: Patch = Patch::new(PatchDocument::Json(vec![
+            PatchOperation::Replace { path: "/title".to_string(), value: json!("Changed") },
+            PatchOperation::Test { path: "/version".to_string(), value: json!(2) },
+        ]));
+
+        let mut document = json!({ "title": "Original", "version": 1 });
+        let error = patch.apply_to_value(&mut document).unwrap_err();
+        assert_eq!(error, PatchError::TestFailed("/version".to_string()));
+        assert_eq!(error.into_response().status_code(), StatusCode::CONFLICT);
+        assert_eq!(document, json!({ "title": "Original", "version": 1 }));
+
+        let patch: Patch = Patch::new(PatchDocument::Json(vec![PatchOperation::Remove { path: "/tags/01".to_string() }]));
+        let mut document = json!({ "tags": ["a", "b"] });
+        assert_eq!(patch.apply_to_value(&mut document), Err(PatchError::InvalidPath("/tags/01".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_merge_patch_applies_to_typed_target() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Article {
+            title: String,
+            author: Option<String>,
+            tags: Vec<String>,
+        }
+
+        let req = patch_request(MERGE_PATCH, json!({ "title": "New", "author": null, "tags": ["rust"] }));
+        let (patch, _) = Patch::<Article>::from_request(req).await.ok().unwrap();
+
+        let article = Article { title: "Old".to_string(), author: Some("ann".to_string()), tags: vec![] };
+        assert_eq!(
+            patch.apply(&article).unwrap(),
+            Article { title: "New".to_string(), author: None, tags: vec!["rust".to_string()] }
+        );
+
+        let req = patch_request(MERGE_PATCH, json!({ "tags": "not a list" }));
+        let (patch, _) = Patch::<Article>::from_request(req).await.ok().unwrap();
+        assert!(matches!(patch.apply(&article), Err(PatchError::InvalidResult(_))));
+    }
+
+    #[tokio::test]
+    async fn test_patch_content_types() {
+        let req = patch_request("application/json", json!({ "title": "New" }));
+        let error = Patch::<Value>::from_request(req).await.err().unwrap();
+        assert_eq!(error.into_response().status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let req = patch_request(JSON_PATCH, json!([{ "op": "add", "path": "/x" }]));
+        assert!(Patch::<Value>::from_request(req).await.is_err());
+    }
+}
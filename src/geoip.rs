@@ -0,0 +1,464 @@
+//! # GeoIP
+//!
+//! Look up where requests come from in a MaxMind database (GeoLite2 or
+//! GeoIP2 Country and City, or any other `.mmdb` file with the same
+//! layout). [`GeoIp`] puts a [`GeoLocation`] on each request, and
+//! [`GeoBlock`] turns away countries a product may not be offered in:
+//!
+//! ```rust,no_run
+//! use torch_web::App;
+//! use torch_web::geoip::{GeoBlock, GeoIp, GeoIpDb, GeoLocation};
+//!
+//! let db = GeoIpDb::open("storage/geoip/GeoLite2-City.mmdb").expect("GeoIP database");
+//!
+//! let app = App::new()
+//!     .middleware(GeoIp::new(db))
+//!     .middleware(GeoBlock::deny(["CU", "IR", "KP", "SY"]))
+//!     .get("/", |geo: GeoLocation| async move {
+//!         format!("Hello from {}", geo.country.as_deref().unwrap_or("somewhere"))
+//!     });
+//! ```
+//!
+//! The database is read into memory once; MaxMind publishes updates weekly,
+//! so reopen it on deploy. Addresses come from [`Request::remote_addr`];
+//! behind a proxy, run [`GeoIp`] after middleware that resolves the client
+//! address.
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::extractors::FromRequestParts;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+/// Marks the start of the metadata section, near the end of the file
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// A MaxMind database, loaded into memory
+#[derive(Clone)]
+pub struct GeoIpDb {
+    bytes: Arc<Vec<u8>>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u64,
+    /// Node the IPv4 part of an IPv6 tree starts at
+    ipv4_start: usize,
+}
+
+impl GeoIpDb {
+    /// Read the `.mmdb` file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Use a database already in memory
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        let start = bytes
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or_else(|| invalid("not a MaxMind database: no metadata"))?
+            + METADATA_MARKER.len();
+        let (metadata, _) = decode(&bytes[start..], 0, 0)?;
+        let field = |name: &str| metadata.get(name).and_then(Value::as_u64).ok_or_else(|| invalid(format!("metadata has no {}", name)));
+        let node_count = field("node_count")? as usize;
+        let record_size = field("record_size")? as usize;
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(invalid(format!("unsupported record size {}", record_size)));
+        }
+
+        let mut db = Self { bytes: Arc::new(bytes), node_count, record_size, ip_version: field("ip_version")?, ipv4_start: 0 };
+        if db.search_tree_size() + 16 > start {
+            return Err(invalid("search tree is larger than the file"));
+        }
+        if db.ip_version == 6 {
+            for _ in 0..96 {
+                if db.ipv4_start >= node_count {
+                    break;
+                }
+                db.ipv4_start = db.record(db.ipv4_start, 0);
+            }
+        }
+        Ok(db)
+    }
+
+    fn search_tree_size(&self) -> usize {
+        self.node_count * self.record_size / 4
+    }
+
+    /// Left (`bit` 0) or right record of `node`
+    fn record(&self, node: usize, bit: u8) -> usize {
+        let at = node * self.record_size / 4;
+        let b = &self.bytes[at..at + self.record_size / 4];
+        let be = |bytes: &[u8]| bytes.iter().fold(0usize, |value, byte| value << 8 | *byte as usize);
+        match (self.record_size, bit) {
+            (24, 0) => be(&b[0..3]),
+            (24, _) => be(&b[3..6]),
+            (28, 0) => (b[3] as usize & 0xf0) << 20 | be(&b[0..3]),
+            (28, _) => (b[3] as usize & 0x0f) << 24 | be(&b[4..7]),
+            (_, 0) => be(&b[0..4]),
+            _ => be(&b[4..8]),
+        }
+    }
+
+    /// The raw record for `ip`, e.g. `{"country": {"iso_code": "DE", ...}}`
+    pub fn lookup(&self, ip: IpAddr) -> Option<Value> {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(_) => ip,
+        };
+        let (bits, mut node) = match ip {
+            IpAddr::V4(v4) => (v4.octets().to_vec(), self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return None,
+            IpAddr::V6(v6) => (v6.octets().to_vec(), 0),
+        };
+        for index in 0..bits.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, bits[index / 8] >> (7 - index % 8) & 1);
+        }
+        if node <= self.node_count {
+            return None;
+        }
+
+        let data = &self.bytes[self.search_tree_size() + 16..];
+        decode(data, node - self.node_count - 16, 0).ok().map(|(value, _)| value)
+    }
+
+    /// Country, region and city of `ip`
+    pub fn locate(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let record = self.lookup(ip)?;
+        let text = |value: Option<&Value>| value.and_then(Value::as_str).map(str::to_string);
+        let subdivision = record.get("subdivisions").and_then(|subdivisions| subdivisions.get(0));
+        Some(GeoLocation {
+            country: text(record.get("country").and_then(|country| country.get("iso_code"))),
+            region: text(subdivision.and_then(|region| region.get("iso_code"))),
+            city: text(record.get("city").and_then(|city| city.get("names")).and_then(|names| names.get("en"))),
+        })
+    }
+}
+
+/// Decode the value at `at` of a data section, returning it with the
+/// position after it. `depth` guards against pointer loops.
+fn decode(section: &[u8], at: usize, depth: u8) -> io::Result<(Value, usize)> {
+    if depth > 32 {
+        return Err(invalid("data nested too deeply"));
+    }
+    let byte = |at: usize| section.get(at).copied().ok_or_else(|| invalid("data runs past the end of the section"));
+    let slice = |at: usize, len: usize| section.get(at..at + len).ok_or_else(|| invalid("data runs past the end of the section"));
+    let be = |bytes: &[u8]| bytes.iter().fold(0u128, |value, byte| value << 8 | *byte as u128);
+
+    let control = byte(at)?;
+    let mut at = at + 1;
+    let mut kind = control >> 5;
+    if kind == 1 {
+        let size = (control >> 3 & 0x3) as usize;
+        let high = (control & 0x7) as usize;
+        let bytes = slice(at, size + 1)?;
+        let pointer = match size {
+            0 => high << 8 | bytes[0] as usize,
+            1 => 2048 + (high << 16 | be(bytes) as usize),
+            2 => 526_336 + (high << 24 | be(bytes) as usize),
+            _ => be(bytes) as usize,
+        };
+        let (value, _) = decode(section, pointer, depth + 1)?;
+        return Ok((value, at + size + 1));
+    }
+    if kind == 0 {
+        kind = 7 + byte(at)?;
+        at += 1;
+    }
+    let mut size = (control & 0x1f) as usize;
+    if size >= 29 {
+        let extra = size - 28;
+        let bytes = slice(at, extra)?;
+        size = [29, 285, 65_821][extra - 1] + be(bytes) as usize;
+        at += extra;
+    }
+
+    let value = match kind {
+        2 => Value::from(std::str::from_utf8(slice(at, size)?).map_err(|_| invalid("invalid UTF-8 string"))?),
+        3 => Value::from(f64::from_be_bytes(slice(at, 8)?.try_into().unwrap())),
+        4 => Value::from(slice(at, size)?.to_vec()),
+        5 | 6 | 9 => Value::from(be(slice(at, size)?) as u64),
+        8 => Value::from(be(slice(at, size)?) as u32 as i32),
+        10 => match be(slice(at, size)?) {
+            small if small <= u64::MAX as u128 => Value::from(small as u64),
+            large => Value::from(large.to_string()),
+        },
+        14 => return Ok((Value::from(size != 0), at)),
+        15 => Value::from(f32::from_be_bytes(slice(at, 4)?.try_into().unwrap()) as f64),
+        7 => {
+            let mut map = Map::new();
+            for _ in 0..size {
+                let (key, next) = decode(section, at, depth + 1)?;
+                let (value, next) = decode(section, next, depth + 1)?;
+                let key = key.as_str().ok_or_else(|| invalid("map key is not a string"))?.to_string();
+                map.insert(key, value);
+                at = next;
+            }
+            return Ok((Value::Object(map), at));
+        }
+        11 => {
+            let mut items = Vec::with_capacity(size.min(1024));
+            for _ in 0..size {
+                let (item, next) = decode(section, at, depth + 1)?;
+                items.push(item);
+                at = next;
+            }
+            return Ok((Value::Array(items), at));
+        }
+        other => return Err(invalid(format!("unsupported data type {}", other))),
+    };
+    let len = match kind {
+        3 => 8,
+        15 => 4,
+        _ => size,
+    };
+    Ok((value, at + len))
+}
+
+/// Where a request came from, as far as the database knows. Taken as an
+/// extractor it is empty when [`GeoIp`] found nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 country code, e.g. `DE`
+    pub country: Option<String>,
+    /// ISO 3166-2 subdivision code without the country, e.g. `BY`
+    pub region: Option<String>,
+    /// English city name
+    pub city: Option<String>,
+}
+
+impl FromRequestParts for GeoLocation {
+    type Error = std::convert::Infallible;
+
+    fn from_request_parts(req: &mut Request) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let location = req.get_extension::<GeoLocation>().cloned().unwrap_or_default();
+        Box::pin(async move { Ok(location) })
+    }
+}
+
+/// Middleware adding the [`GeoLocation`] of the client to each request
+#[derive(Clone)]
+pub struct GeoIp {
+    db: GeoIpDb,
+}
+
+impl GeoIp {
+    pub fn new(db: GeoIpDb) -> Self {
+        Self { db }
+    }
+}
+
+impl Middleware for GeoIp {
+    fn call(
+        &self,
+        mut req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if let Some(location) = req.remote_addr().and_then(|addr| self.db.locate(addr.ip())) {
+            req.insert_extension(location);
+        }
+        next(req)
+    }
+}
+
+/// Middleware answering `451 Unavailable For Legal Reasons` to countries
+/// outside an allow list or on a deny list. Runs after [`GeoIp`].
+#[derive(Debug, Clone)]
+pub struct GeoBlock {
+    countries: HashSet<String>,
+    allow: bool,
+    block_unknown: bool,
+}
+
+impl GeoBlock {
+    /// Only serve these countries; clients that can't be located are
+    /// blocked too
+    pub fn allow<I, S>(countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::new(countries, true)
+    }
+
+    /// Serve everyone but these countries
+    pub fn deny<I, S>(countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::new(countries, false)
+    }
+
+    fn new<I, S>(countries: I, allow: bool) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            countries: countries.into_iter().map(|country| country.as_ref().to_ascii_uppercase()).collect(),
+            allow,
+            block_unknown: allow,
+        }
+    }
+
+    /// Whether clients that can't be located are blocked
+    pub fn block_unknown(mut self, block: bool) -> Self {
+        self.block_unknown = block;
+        self
+    }
+
+    /// Whether a client from `country` is served
+    pub fn allows(&self, country: Option<&str>) -> bool {
+        match country {
+            Some(country) => self.countries.contains(&country.to_ascii_uppercase()) == self.allow,
+            None => !self.block_unknown,
+        }
+    }
+}
+
+impl Middleware for GeoBlock {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let country = req.get_extension::<GeoLocation>().and_then(|location| location.country.as_deref());
+        if !self.allows(country) {
+            return Box::pin(async { Response::with_status(http::StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS) });
+        }
+        next(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+    use std::net::SocketAddr;
+
+    fn string(text: &str) -> Vec<u8> {
+        let mut out = vec![0x40 | text.len() as u8];
+        out.extend_from_slice(text.as_bytes());
+        out
+    }
+
+    fn map(pairs: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![0xe0 | pairs.len() as u8];
+        for (key, value) in pairs {
+            out.extend(string(key));
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    fn uint(value: u8) -> Vec<u8> {
+        vec![0xc1, value]
+    }
+
+    /// An IPv4 database with 24-bit records, mapping each prefix to a record
+    fn database(prefixes: &[([u8; 4], usize, Vec<u8>)]) -> Vec<u8> {
+        // Nodes hold [left, right]; None marks an empty record
+        let mut nodes: Vec<[Option<Result<usize, usize>>; 2]> = vec![[None, None]];
+        let mut data = Vec::new();
+        for (addr, len, record) in prefixes {
+            let offset = data.len();
+            data.extend_from_slice(record);
+            let mut node = 0;
+            for index in 0..*len {
+                let bit = (addr[index / 8] >> (7 - index % 8) & 1) as usize;
+                if index == len - 1 {
+                    nodes[node][bit] = Some(Err(offset));
+                } else {
+                    node = match nodes[node][bit] {
+                        Some(Ok(next)) => next,
+                        _ => {
+                            nodes.push([None, None]);
+                            nodes[node][bit] = Some(Ok(nodes.len() - 1));
+                            nodes.len() - 1
+                        }
+                    };
+                }
+            }
+        }
+
+        let count = nodes.len();
+        let mut bytes = Vec::new();
+        for node in &nodes {
+            for record in node {
+                let value = match record {
+                    Some(Ok(next)) => *next,
+                    Some(Err(offset)) => count + 16 + offset,
+                    None => count,
+                };
+                bytes.extend_from_slice(&(value as u32).to_be_bytes()[1..]);
+            }
+        }
+        bytes.extend_from_slice(&[0; 16]);
+        bytes.extend(data);
+        bytes.extend_from_slice(METADATA_MARKER);
+        bytes.extend(map(&[
+            ("node_count", vec![0xc2, (count >> 8) as u8, count as u8]),
+            ("record_size", uint(24)),
+            ("ip_version", uint(4)),
+        ]));
+        bytes
+    }
+
+    fn city(country: &str, region: &str, name: &str) -> Vec<u8> {
+        map(&[
+            ("country", map(&[("iso_code", string(country))])),
+            ("subdivisions", [vec![0x01, 0x04], map(&[("iso_code", string(region))])].concat()),
+            ("city", map(&[("names", map(&[("en", string(name))]))])),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_geoip_locates_and_blocks_countries() {
+        let db = GeoIpDb::from_bytes(database(&[
+            ([81, 2, 0, 0], 16, city("DE", "BY", "Munich")),
+            ([175, 45, 176, 0], 22, city("KP", "01", "Pyongyang")),
+        ]))
+        .unwrap();
+        let munich = db.locate("81.2.69.160".parse().unwrap()).unwrap();
+        assert_eq!(
+            munich,
+            GeoLocation { country: Some("DE".into()), region: Some("BY".into()), city: Some("Munich".into()) }
+        );
+        assert_eq!(db.lookup("::ffff:81.2.1.1".parse().unwrap()).unwrap()["country"]["iso_code"], "DE");
+        assert!(db.locate("8.8.8.8".parse().unwrap()).is_none());
+        assert!(GeoIpDb::from_bytes(b"not a database".to_vec()).is_err());
+
+        let app = App::new()
+            .middleware(GeoIp::new(db))
+            .middleware(GeoBlock::deny(["kp"]))
+            .get("/", |geo: GeoLocation| async move { geo.city.unwrap_or_default() });
+        let from = |ip: &str| {
+            let addr: SocketAddr = format!("{}:5000", ip).parse().unwrap();
+            app.handle_request(Request::mock(Method::GET, "/").with_remote_addr(addr))
+        };
+        assert_eq!(from("81.2.69.160").await.body_data(), b"Munich");
+        assert_eq!(from("175.45.176.3").await.status_code().as_u16(), 451);
+        assert_eq!(from("8.8.8.8").await.status_code().as_u16(), 200);
+
+        let allow = GeoBlock::allow(["DE", "AT"]);
+        assert!(allow.allows(Some("de")) && !allow.allows(Some("US")) && !allow.allows(None));
+        assert!(allow.block_unknown(false).allows(None));
+    }
+}
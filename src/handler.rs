@@ -20,16 +20,19 @@ pub type HandlerFn = std::sync::Arc<
         + 'static,
 >;
 
-/// Implement Handler for async functions that take Request and return Response
-impl<F, Fut> Handler<(Request,)> for F
+/// Implement Handler for async functions that take Request and return
+/// anything that converts into a Response
+impl<F, Fut, Res> Handler<(Request,)> for F
 where
     F: Fn(Request) -> Fut + Clone + Send + Sync + 'static,
-    Fut: Future<Output = Response> + Send + 'static,
+    Fut: Future<Output = Res> + Send + 'static,
+    Res: IntoResponse,
 {
-    type Future = Fut;
+    type Future = Pin<Box<dyn Future<Output = Response> + Send + 'static>>;
 
     fn call(&self, req: Request) -> Self::Future {
-        self(req)
+        let fut = self(req);
+        Box::pin(async move { fut.await.into_response() })
     }
 }
 
@@ -219,7 +222,7 @@ mod tests {
             Response::ok().body("Hello from async handler")
         };
 
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -228,9 +231,7 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = handler.call(req).await;
         assert_eq!(response.body_data(), b"Hello from async handler");
@@ -238,9 +239,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_sync_handler() {
-        let handler = |_req: Request| Response::ok().body("Hello from sync handler");
+        // Handlers return a future of any IntoResponse type, so a closure
+        // that returns a Response directly isn't one; a handler that does
+        // no async work returns a future that is already complete
+        let handler = |_req: Request| std::future::ready(Response::ok().body("Hello from sync handler"));
 
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -249,11 +253,9 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
-        let response = handler.call(req).await;
+        let response = Handler::<(Request,)>::call(&handler, req).await;
         assert_eq!(response.body_data(), b"Hello from sync handler");
     }
 
@@ -264,4 +266,47 @@ mod tests {
             Response::ok().body(format!("Path: {}", req.path()))
         });
     }
+
+    #[tokio::test]
+    async fn test_handlers_return_into_response_types() {
+        use crate::extractors::Path;
+        use http::StatusCode;
+
+        fn request() -> Request {
+            Request::from_parts(http::Request::builder().uri("/").body(()).unwrap().into_parts().0, Vec::new())
+        }
+
+        let text = |_req: Request| async { "plain" };
+        let response = Handler::call(&text, request()).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.body_data(), b"plain");
+
+        let created = |_req: Request| async { (StatusCode::CREATED, format!("post {}", 7)) };
+        assert_eq!(Handler::call(&created, request()).await.status_code(), StatusCode::CREATED);
+
+        let missing = |Path(id): Path<u32>| async move {
+            if id == 0 {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            Ok(id.to_string())
+        };
+        let mut req = request();
+        req.set_param("id".to_string(), "0".to_string());
+        assert_eq!(Handler::call(&missing, req).await.status_code(), StatusCode::NOT_FOUND);
+
+        let failing = |_req: Request| async {
+            let number: u32 = "x".parse()?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(number.to_string())
+        };
+        assert_eq!(Handler::call(&failing, request()).await.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        #[cfg(feature = "json")]
+        {
+            let json = |_req: Request| async { (StatusCode::ACCEPTED, crate::extractors::Json(serde_json::json!({"ok": true}))) };
+            let response = Handler::call(&json, request()).await;
+            assert_eq!(response.status_code(), StatusCode::ACCEPTED);
+            assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+            assert_eq!(response.body_data(), br#"{"ok":true}"#);
+        }
+    }
 }
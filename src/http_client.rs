@@ -0,0 +1,111 @@
+//! The outbound HTTP/1.1 requests the framework makes itself (OIDC discovery,
+//! search engines, audit webhooks), with TLS for `https://` URLs when the
+//! `tls` feature is enabled
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Send `request` (method and headers set, no URI) to `url` with `body`,
+/// returning the status and the whole answer
+pub(crate) async fn send(
+    url: &str,
+    request: http::request::Builder,
+    body: Vec<u8>,
+) -> Result<(http::StatusCode, Vec<u8>), BoxError> {
+    let uri: http::Uri = url.parse()?;
+    let host = uri.host().ok_or("URL has no host")?.to_string();
+    let tls = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => return Err(format!("unsupported URL {}", url).into()),
+    };
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    let authority = uri.authority().map_or(host.clone(), |authority| authority.to_string());
+    let request = request
+        .uri(uri.path_and_query().map_or("/", |target| target.as_str()))
+        .header(http::header::HOST, authority)
+        .body(Full::new(Bytes::from(body)))?;
+
+    let stream = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+    let response = if tls {
+        let stream = connect_tls(host, stream).await?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+        sender.send_request(request).await?
+    } else {
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+        sender.send_request(request).await?
+    };
+
+    let status = response.status();
+    Ok((status, response.into_body().collect().await?.to_bytes().to_vec()))
+}
+
+#[cfg(feature = "tls")]
+async fn connect_tls(
+    host: String,
+    stream: tokio::net::TcpStream,
+) -> Result<tokio_rustls::client::TlsStream<tokio::net::TcpStream>, BoxError> {
+    use std::sync::{Arc, OnceLock};
+    use tokio_rustls::rustls::{self, pki_types::ServerName};
+
+    // One client configuration with the webpki roots, shared by every connection
+    static CONFIG: OnceLock<Arc<rustls::ClientConfig>> = OnceLock::new();
+
+    let config = match CONFIG.get() {
+        Some(config) => config.clone(),
+        None => {
+            let mut roots = rustls::RootCertStore::empty();
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            CONFIG.get_or_init(|| Arc::new(config)).clone()
+        }
+    };
+    Ok(tokio_rustls::TlsConnector::from(config).connect(ServerName::try_from(host)?, stream).await?)
+}
+
+#[cfg(not(feature = "tls"))]
+async fn connect_tls(_host: String, _stream: tokio::net::TcpStream) -> Result<tokio::net::TcpStream, BoxError> {
+    Err("https:// URLs need the `tls` feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_send_posts_and_reads_the_answer() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events?source=test", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !received.ends_with(b"{\"ok\":1}") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(b"HTTP/1.1 202 Accepted\r\ncontent-length: 4\r\n\r\ndone").await.unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        let request = http::Request::post(&url).header(http::header::CONTENT_TYPE, "application/json");
+        let (status, body) = send(&url, request, b"{\"ok\":1}".to_vec()).await.unwrap();
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+        assert_eq!(body, b"done");
+
+        let received = server.await.unwrap();
+        assert!(received.starts_with("POST /events?source=test HTTP/1.1\r\n"));
+        assert!(received.to_lowercase().contains("content-type: application/json"));
+
+        assert!(send("ftp://example.com/", http::Request::get("/"), Vec::new()).await.is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! # Localization
+//!
+//! Locale conventions for formatting numbers, money, dates and relative
+//! times, used by Ember's `number`, `currency`, `date` and `since` filters.
+//!
+//! The application's locale is set once with [`set_locale`], matching
+//! `[localization] default` in `torch.toml`. The [`localize`] middleware
+//! picks a locale per request from the `Accept-Language` header:
+//!
+//! ```rust,no_run
+//! use torch_web::{App, i18n};
+//!
+//! i18n::set_locale("en");
+//! let app = App::new().middleware(i18n::localize(&["en", "de", "fr", "es"]));
+//! ```
+//!
+//! ```rust
+//! use torch_web::i18n::Locale;
+//!
+//! let de = Locale::get("de-AT");
+//! assert_eq!(de.format_number(1234.5, 2), "1.234,50");
+//! assert_eq!(de.format_currency(1234.5, "EUR"), "1.234,50\u{a0}€");
+//! assert_eq!(Locale::get("en").format_currency(1234.5, "EUR"), "€1,234.50");
+//! ```
+//!
+//! English, German, French and Spanish are built in; other locales fall
+//! back to English.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::RwLock;
+
+use crate::{Request, Response};
+
+/// Formatting conventions of a language
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale {
+    /// Language code, such as `de`
+    pub code: &'static str,
+    pub decimal_separator: char,
+    pub group_separator: char,
+    /// Whether the currency symbol follows the amount (`12,00 €`)
+    pub currency_after: bool,
+    /// Date format used by `date` without an argument, in `date()` syntax
+    pub date_format: &'static str,
+    pub months: [&'static str; 12],
+    pub months_short: [&'static str; 12],
+    /// Monday first
+    pub weekdays: [&'static str; 7],
+    pub weekdays_short: [&'static str; 7],
+    /// `{}` is replaced with a span such as "3 days"
+    pub past: &'static str,
+    pub future: &'static str,
+    pub just_now: &'static str,
+    /// Singular and plural unit names, from seconds to years
+    pub units: [(&'static str, &'static str); 7],
+}
+
+const EN: Locale = Locale {
+    code: "en",
+    decimal_separator: '.',
+    group_separator: ',',
+    currency_after: false,
+    date_format: "M j, Y",
+    months: ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"],
+    months_short: ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"],
+    weekdays: ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"],
+    weekdays_short: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    past: "{} ago",
+    future: "in {}",
+    just_now: "just now",
+    units: [("second", "seconds"), ("minute", "minutes"), ("hour", "hours"), ("day", "days"), ("week", "weeks"), ("month", "months"), ("year", "years")],
+};
+
+const DE: Locale = Locale {
+    code: "de",
+    decimal_separator: ',',
+    group_separator: '.',
+    currency_after: true,
+    date_format: "d.m.Y",
+    months: ["Januar", "Februar", "März", "April", "Mai", "Juni", "Juli", "August", "September", "Oktober", "November", "Dezember"],
+    months_short: ["Jan.", "Feb.", "März", "Apr.", "Mai", "Juni", "Juli", "Aug.", "Sept.", "Okt.", "Nov.", "Dez."],
+    weekdays: ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"],
+    weekdays_short: ["Mo.", "Di.", "Mi.", "Do.", "Fr.", "Sa.", "So."],
+    past: "vor {}",
+    future: "in {}",
+    just_now: "gerade eben",
+    units: [("Sekunde", "Sekunden"), ("Minute", "Minuten"), ("Stunde", "Stunden"), ("Tag", "Tagen"), ("Woche", "Wochen"), ("Monat", "Monaten"), ("Jahr", "Jahren")],
+};
+
+const FR: Locale = Locale {
+    code: "fr",
+    decimal_separator: ',',
+    group_separator: '\u{202f}',
+    currency_after: true,
+    date_format: "d/m/Y",
+    months: ["janvier", "février", "mars", "avril", "mai", "juin", "juillet", "août", "septembre", "octobre", "novembre", "décembre"],
+    months_short: ["janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.", "déc."],
+    weekdays: ["lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche"],
+    weekdays_short: ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+    past: "il y a {}",
+    future: "dans {}",
+    just_now: "à l'instant",
+    units: [("seconde", "secondes"), ("minute", "minutes"), ("heure", "heures"), ("jour", "jours"), ("semaine", "semaines"), ("mois", "mois"), ("an", "ans")],
+};
+
+const ES: Locale = Locale {
+    code: "es",
+    decimal_separator: ',',
+    group_separator: '.',
+    currency_after: true,
+    date_format: "d/m/Y",
+    months: ["enero", "febrero", "marzo", "abril", "mayo", "junio", "julio", "agosto", "septiembre", "octubre", "noviembre", "diciembre"],
+    months_short: ["ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sept", "oct", "nov", "dic"],
+    weekdays: ["lunes", "martes", "miércoles", "jueves", "viernes", "sábado", "domingo"],
+    weekdays_short: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    past: "hace {}",
+    future: "dentro de {}",
+    just_now: "ahora mismo",
+    units: [("segundo", "segundos"), ("minuto", "minutos"), ("hora", "horas"), ("día", "días"), ("semana", "semanas"), ("mes", "meses"), ("año", "años")],
+};
+
+const LOCALES: [&Locale; 4] = [&EN, &DE, &FR, &ES];
+
+impl Locale {
+    /// The built-in locale for a language tag such as `de` or `de-AT`,
+    /// falling back to English
+    pub fn get(tag: &str) -> &'static Locale {
+        Self::find(tag).unwrap_or(&EN)
+    }
+
+    /// The built-in locale for a language tag, if there is one
+    pub fn find(tag: &str) -> Option<&'static Locale> {
+        let language = tag.split(['-', '_']).next().unwrap_or_default().trim();
+        LOCALES.into_iter().find(|locale| locale.code.eq_ignore_ascii_case(language))
+    }
+
+    /// `value` with `decimals` digits and grouped thousands
+    pub fn format_number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (whole, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+        let mut grouped = String::new();
+        for (index, digit) in whole.chars().enumerate() {
+            if index > 0 && (whole.len() - index) % 3 == 0 {
+                grouped.push(self.group_separator);
+            }
+            grouped.push(digit);
+        }
+        if !fraction.is_empty() {
+            grouped.push(self.decimal_separator);
+            grouped.push_str(fraction);
+        }
+        // -0.00 is just 0.00
+        if value < 0.0 && formatted.chars().any(|digit| digit.is_ascii_digit() && digit != '0') {
+            grouped.insert(0, '-');
+        }
+        grouped
+    }
+
+    /// `amount` in the ISO 4217 `currency`, such as `EUR`
+    pub fn format_currency(&self, amount: f64, currency: &str) -> String {
+        let currency = currency.to_ascii_uppercase();
+        let (symbol, decimals) = match currency.as_str() {
+            "EUR" => ("€", 2),
+            "USD" => ("$", 2),
+            "GBP" => ("£", 2),
+            "JPY" => ("¥", 0),
+            "CNY" => ("¥", 2),
+            "INR" => ("₹", 2),
+            "KRW" => ("₩", 0),
+            _ => (currency.as_str(), 2),
+        };
+        let number = self.format_number(amount.abs(), decimals);
+        let sign = if amount < 0.0 && number.chars().any(|digit| digit.is_ascii_digit() && digit != '0') { "-" } else { "" };
+        // Codes used as symbols need a space in front of the number too
+        match (self.currency_after, symbol.chars().all(char::is_alphabetic)) {
+            (true, _) => format!("{}{}\u{a0}{}", sign, number, symbol),
+            (false, true) => format!("{}{}\u{a0}{}", sign, symbol, number),
+            (false, false) => format!("{}{}{}", sign, symbol, number),
+        }
+    }
+
+    /// How long ago (or how far ahead, when negative) `seconds` is, in the
+    /// largest whole unit: "3 days ago", "in 2 hours"
+    pub fn format_relative(&self, seconds: i64) -> String {
+        const LENGTHS: [i64; 7] = [1, 60, 3_600, 86_400, 604_800, 2_592_000, 31_536_000];
+
+        let span = seconds.unsigned_abs() as i64;
+        if span < 10 {
+            return self.just_now.to_string();
+        }
+        let unit = LENGTHS.iter().rposition(|length| span >= *length).unwrap_or(0);
+        let count = span / LENGTHS[unit];
+        let (singular, plural) = self.units[unit];
+        let span = format!("{} {}", count, if count == 1 { singular } else { plural });
+        let phrase = if seconds >= 0 { self.past } else { self.future };
+        phrase.replace("{}", &span)
+    }
+}
+
+static DEFAULT_LOCALE: RwLock<&'static Locale> = RwLock::new(&EN);
+
+tokio::task_local! {
+    static REQUEST_LOCALE: &'static Locale;
+}
+
+/// Use `tag` (e.g. `fr`) for formatting wherever no request locale applies
+pub fn set_locale(tag: &str) {
+    *DEFAULT_LOCALE.write().unwrap() = Locale::get(tag);
+}
+
+/// The locale of the current request, or the application's locale
+pub fn locale() -> &'static Locale {
+    REQUEST_LOCALE
+        .try_with(|locale| *locale)
+        .unwrap_or_else(|_| *DEFAULT_LOCALE.read().unwrap())
+}
+
+/// Run `future` with `tag` as its locale
+pub async fn with_locale<F: Future>(tag: &str, future: F) -> F::Output {
+    REQUEST_LOCALE.scope(Locale::get(tag), future).await
+}
+
+/// The first of `available` the `Accept-Language` header asks for, by
+/// quality
+pub fn negotiate(accept_language: &str, available: &[&str]) -> Option<&'static Locale> {
+    let mut wanted: Vec<(f32, &str)> = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |quality| quality.parse().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((quality, tag))
+        })
+        .collect();
+    wanted.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    wanted.into_iter().find_map(|(_, tag)| {
+        let locale = Locale::find(tag)?;
+        available.iter().any(|code| Locale::find(code) == Some(locale)).then_some(locale)
+    })
+}
+
+/// Middleware formatting each request in the best of `available` for its
+/// `Accept-Language` header, or the application's locale
+pub fn localize(available: &[&str]) -> Localize {
+    Localize { available: available.iter().map(|code| code.to_string()).collect() }
+}
+
+/// See [`localize`]
+#[derive(Debug, Clone)]
+pub struct Localize {
+    available: Vec<String>,
+}
+
+impl crate::middleware::Middleware for Localize {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let available: Vec<&str> = self.available.iter().map(String::as_str).collect();
+        let locale = req
+            .header("accept-language")
+            .and_then(|header| negotiate(header, &available))
+            .unwrap_or_else(locale);
+
+        Box::pin(async move {
+            let response = REQUEST_LOCALE.scope(locale, next(req)).await;
+            response.header("content-language", locale.code)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_follow_the_locale() {
+        let (en, fr) = (Locale::get("en-GB"), Locale::get("fr"));
+        assert_eq!(en.format_number(1234567.891, 2), "1,234,567.89");
+        assert_eq!(en.format_number(-999.0, 0), "-999");
+        assert_eq!(en.format_number(-0.001, 2), "0.00");
+        assert_eq!(fr.format_number(1234.5, 1), "1\u{202f}234,5");
+        assert_eq!(en.format_currency(-5.0, "USD"), "-$5.00");
+        assert_eq!(en.format_currency(1500.0, "CHF"), "CHF\u{a0}1,500.00");
+        assert_eq!(fr.format_currency(1500.0, "JPY"), "1\u{202f}500\u{a0}¥");
+
+        assert_eq!(en.format_relative(5), "just now");
+        assert_eq!(en.format_relative(3 * 86_400 + 60), "3 days ago");
+        assert_eq!(en.format_relative(-3_600), "in 1 hour");
+        assert_eq!(Locale::get("de").format_relative(2 * 604_800), "vor 2 Wochen");
+
+        assert_eq!(negotiate("fr-CH, fr;q=0.9, en;q=0.8", &["en", "fr"]).map(|l| l.code), Some("fr"));
+        assert_eq!(negotiate("fr;q=0.5, de;q=0.9", &["en", "fr"]).map(|l| l.code), Some("fr"));
+        assert_eq!(negotiate("ja", &["en", "fr"]), None);
+    }
+}
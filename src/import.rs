@@ -0,0 +1,802 @@
+//! # CSV Imports
+//!
+//! Take an uploaded CSV, check every row against a [`Schema`], and save the
+//! valid rows from queued jobs a chunk at a time while the browser follows
+//! along:
+//!
+//! ```rust,no_run
+//! use torch_web::import::{ImportFuture, ImportRow, Importer, Imports, Rule, Schema};
+//! use torch_web::{App, Request, websocket::WebSocketManager};
+//!
+//! struct Contacts;
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Contact {
+//!     email: String,
+//!     name: String,
+//!     age: Option<u32>,
+//! }
+//!
+//! impl Importer for Contacts {
+//!     fn schema(&self) -> Schema {
+//!         Schema::new()
+//!             .column("email", [Rule::Required, Rule::Email])
+//!             .column("name", [Rule::Required, Rule::MaxLength(100)])
+//!             .column("age", [Rule::Integer])
+//!     }
+//!
+//!     fn import<'a>(&'a self, row: &'a ImportRow) -> ImportFuture<'a> {
+//!         Box::pin(async move {
+//!             let contact: Contact = row.parse()?;
+//!             // Insert the contact, or update the one with this email
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//!
+//! let events = WebSocketManager::new();
+//! Imports::register("contacts", Contacts);
+//! Imports::broadcast_to(events.clone());
+//!
+//! let app = App::new()
+//!     .with_state(events)
+//!     // Only signed-in users follow imports; check the import is theirs too
+//!     .sse("/events", |req: &Request, channel: &str| {
+//!         channel.starts_with("imports.") && req.header("authorization").is_some()
+//!     })
+//!     .post("/contacts/import", Imports::upload_handler("contacts"))
+//!     .get("/imports/:id", Imports::progress_handler());
+//! ```
+//!
+//! The upload handler takes the `file` field of a multipart form, or a
+//! `text/csv` body. The first line must name the columns; missing required
+//! columns or a malformed file are answered with `422` straight away.
+//! Otherwise the rows go onto the queue as [`ImportChunk`] jobs of
+//! [`Importer::chunk_size`] rows and the answer is `202` with the import's
+//! [`ImportProgress`].
+//!
+//! Each row that breaks a rule, or that [`Importer::import`] returns an
+//! error for, is reported with its line number. After every chunk a summary
+//! is broadcast to the [`channel`](Imports::channel) `imports.<id>`, which
+//! reaches WebSocket clients in that room and
+//! `new EventSource("/events?channel=imports.<id>")`. The full report,
+//! errors included, is at the progress endpoint.
+//!
+//! Progress lives in process memory for a day unless [`Imports::store`] is
+//! given a shared cache, which is needed when workers run elsewhere; workers
+//! must register the same importers. A chunk that fails as a whole is
+//! retried by the queue, so `import` should insert or update rather than
+//! only insert. Import ids are not secrets: put the progress endpoint behind
+//! the same authentication as the upload.
+
+mod csv;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::cache::{Cache, MemoryCache};
+use crate::clock::unix_now;
+use crate::extractors::{FromRequestParts, Multipart};
+use crate::queue::{Job, JobFuture, Queue, QueueError};
+use crate::websocket::WebSocketManager;
+use crate::{Request, Response, StatusCode};
+
+use self::csv::Record;
+
+/// How long import progress is kept
+const RECORD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Future returned by [`Importer::import`]
+pub type ImportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'a>>;
+
+/// Saves the rows of one kind of import
+pub trait Importer: Send + Sync + 'static {
+    /// Columns the file must or may have
+    fn schema(&self) -> Schema;
+
+    /// Save one row that passed the schema. An error is reported against
+    /// the row and the import carries on.
+    fn import<'a>(&'a self, row: &'a ImportRow) -> ImportFuture<'a>;
+
+    /// Rows per queued job
+    fn chunk_size(&self) -> usize {
+        500
+    }
+
+    /// Name of the queue the chunks are pushed onto
+    fn queue(&self) -> &str {
+        "default"
+    }
+
+    fn delimiter(&self) -> char {
+        ','
+    }
+}
+
+/// A check on a column's value. Empty values pass every rule but
+/// [`Required`](Rule::Required).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Required,
+    /// A whole number, read as one
+    Integer,
+    /// Any number, read as one
+    Decimal,
+    /// `true`/`false`, `yes`/`no` or `1`/`0`, read as a boolean
+    Boolean,
+    Email,
+    /// A `YYYY-MM-DD` date
+    Date,
+    /// At most this many characters
+    MaxLength(usize),
+    /// One of these values, compared case-sensitively
+    OneOf(Vec<String>),
+}
+
+/// The columns an import reads
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    columns: Vec<(String, Vec<Rule>)>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the column headed `name` (ignoring case and surrounding
+    /// spaces), checked by `rules`. Columns not named here are ignored.
+    pub fn column(mut self, name: impl Into<String>, rules: impl IntoIterator<Item = Rule>) -> Self {
+        self.columns.push((name.into(), rules.into_iter().collect()));
+        self
+    }
+
+    /// Where each column is in a file with these headers, or the required
+    /// columns it lacks
+    fn positions(&self, headers: &[String]) -> Result<Vec<Option<usize>>, ImportError> {
+        let position = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+        let positions: Vec<Option<usize>> = self.columns.iter().map(|(name, _)| position(name)).collect();
+        let missing: Vec<String> = self
+            .columns
+            .iter()
+            .zip(&positions)
+            .filter(|((_, rules), position)| position.is_none() && rules.contains(&Rule::Required))
+            .map(|((name, _), _)| name.clone())
+            .collect();
+        if missing.is_empty() {
+            Ok(positions)
+        } else {
+            Err(ImportError::MissingColumns(missing))
+        }
+    }
+
+    /// The row, or every rule it breaks
+    fn validate(&self, positions: &[Option<usize>], width: usize, record: &Record) -> Result<ImportRow, Vec<RowError>> {
+        let error = |column: Option<&str>, message: String| RowError {
+            line: record.line,
+            column: column.map(str::to_string),
+            message,
+        };
+        if record.fields.len() != width {
+            let message = format!("expected {} fields, found {}", width, record.fields.len());
+            return Err(vec![error(None, message)]);
+        }
+
+        let mut row = ImportRow { line: record.line, values: Map::new() };
+        let mut errors = Vec::new();
+        for ((name, rules), position) in self.columns.iter().zip(positions) {
+            let raw = position.map_or("", |position| record.fields[position].trim());
+            match check(raw, rules) {
+                Ok(value) => {
+                    row.values.insert(name.clone(), value);
+                }
+                Err(message) => errors.push(error(Some(name), format!("{} {}", name, message))),
+            }
+        }
+        if errors.is_empty() {
+            Ok(row)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The value of a field that passes `rules`, typed by them
+fn check(raw: &str, rules: &[Rule]) -> Result<Value, String> {
+    if raw.is_empty() {
+        return if rules.contains(&Rule::Required) { Err("is required".to_string()) } else { Ok(Value::Null) };
+    }
+    let mut value = Value::String(raw.to_string());
+    for rule in rules {
+        match rule {
+            Rule::Required => {}
+            Rule::Integer => value = raw.parse::<i64>().map(Value::from).map_err(|_| "must be a whole number".to_string())?,
+            Rule::Decimal => {
+                value = raw
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(Value::Number)
+                    .ok_or_else(|| "must be a number".to_string())?
+            }
+            Rule::Boolean => {
+                value = match raw.to_ascii_lowercase().as_str() {
+                    "true" | "yes" | "1" => Value::Bool(true),
+                    "false" | "no" | "0" => Value::Bool(false),
+                    _ => return Err("must be true or false".to_string()),
+                }
+            }
+            Rule::Email if !is_email(raw) => return Err("must be an email address".to_string()),
+            Rule::Date if !is_date(raw) => return Err("must be a date (YYYY-MM-DD)".to_string()),
+            Rule::MaxLength(max) if raw.chars().count() > *max => return Err(format!("must be at most {} characters", max)),
+            Rule::OneOf(allowed) if !allowed.iter().any(|candidate| candidate == raw) => {
+                return Err(format!("must be one of {}", allowed.join(", ")))
+            }
+            Rule::Email | Rule::Date | Rule::MaxLength(_) | Rule::OneOf(_) => {}
+        }
+    }
+    Ok(value)
+}
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !value.chars().any(char::is_whitespace)
+        && domain.split('.').count() > 1
+        && domain.split('.').all(|label| !label.is_empty())
+}
+
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return false;
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return false;
+    }
+    let (Ok(year), Ok(month), Ok(day)) = (year.parse::<u32>(), month.parse::<u32>(), day.parse::<u32>()) else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// A row that passed its schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportRow {
+    /// Line of the file the row starts on
+    pub line: usize,
+    /// Every schema column, typed by its rules; empty values are `null`
+    pub values: Map<String, Value>,
+}
+
+impl ImportRow {
+    /// The column's value as text, unless it was empty
+    pub fn get(&self, column: &str) -> Option<String> {
+        match self.values.get(column)? {
+            Value::Null => None,
+            Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// Read the row into a struct whose fields are named after the columns
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_value(Value::Object(self.values.clone()))
+    }
+}
+
+/// Why a row wasn't imported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowError {
+    pub line: usize,
+    /// The column at fault, when it is one column
+    pub column: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Why a file couldn't be imported at all
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    /// The file isn't UTF-8 CSV; holds the line and problem
+    Csv(usize, String),
+    /// The file has no header line
+    Empty,
+    /// Required columns the header lacks
+    MissingColumns(Vec<String>),
+    /// No importer was registered under this name
+    UnknownImporter(String),
+    /// Progress couldn't be read or written
+    Store(String),
+    Queue(QueueError),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Csv(line, message) => write!(f, "Invalid CSV on line {}: {}", line, message),
+            ImportError::Empty => write!(f, "The file is empty"),
+            ImportError::MissingColumns(columns) => write!(f, "Missing columns: {}", columns.join(", ")),
+            ImportError::UnknownImporter(name) => write!(f, "No importer is registered as {}", name),
+            ImportError::Store(message) => write!(f, "Import store error: {}", message),
+            ImportError::Queue(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    /// No chunk has run yet
+    Queued,
+    Running,
+    Finished,
+}
+
+/// How far an import has got
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportProgress {
+    pub id: String,
+    pub importer: String,
+    pub file_name: Option<String>,
+    pub status: ImportStatus,
+    pub total_rows: u64,
+    pub processed_rows: u64,
+    pub imported_rows: u64,
+    pub failed_rows: u64,
+    /// Every problem found so far, by line
+    pub errors: Vec<RowError>,
+    /// Unix timestamp, in seconds
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+impl ImportProgress {
+    /// Percentage of rows processed, from 0 to 100
+    pub fn progress(&self) -> u8 {
+        match self.total_rows {
+            0 => 100,
+            total => (self.processed_rows * 100 / total) as u8,
+        }
+    }
+
+    /// What is broadcast after each chunk: everything but the errors
+    fn summary(&self) -> Value {
+        serde_json::json!({
+            "id": self.id,
+            "status": self.status,
+            "total_rows": self.total_rows,
+            "processed_rows": self.processed_rows,
+            "imported_rows": self.imported_rows,
+            "failed_rows": self.failed_rows,
+            "progress": self.progress(),
+        })
+    }
+}
+
+/// What is stored when an import starts
+#[derive(Serialize, Deserialize)]
+struct ImportRecord {
+    importer: String,
+    file_name: Option<String>,
+    total_rows: u64,
+    chunks: usize,
+    created_at: u64,
+}
+
+/// What each chunk stores once it has run
+#[derive(Serialize, Deserialize)]
+struct ChunkResult {
+    rows: u64,
+    imported: u64,
+    errors: Vec<RowError>,
+    finished_at: u64,
+}
+
+/// Queued job importing part of a file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportChunk {
+    import: String,
+    importer: String,
+    index: usize,
+    queue: String,
+    headers: Vec<String>,
+    rows: Vec<(usize, Vec<String>)>,
+}
+
+impl Job for ImportChunk {
+    fn handle(&self) -> JobFuture<'_> {
+        Box::pin(async move {
+            let importer = importer(&self.importer).ok_or_else(|| ImportError::UnknownImporter(self.importer.clone()))?;
+            let schema = importer.schema();
+            let positions = schema.positions(&self.headers)?;
+            let mut result = ChunkResult { rows: self.rows.len() as u64, imported: 0, errors: Vec::new(), finished_at: 0 };
+            for (line, fields) in &self.rows {
+                let record = Record { line: *line, fields: fields.clone() };
+                let row = match schema.validate(&positions, self.headers.len(), &record) {
+                    Ok(row) => row,
+                    Err(errors) => {
+                        result.errors.extend(errors);
+                        continue;
+                    }
+                };
+                match importer.import(&row).await {
+                    Ok(()) => result.imported += 1,
+                    Err(error) => result.errors.push(RowError { line: *line, column: None, message: error.to_string() }),
+                }
+            }
+            result.finished_at = unix_now();
+
+            let key = format!("imports:{}:chunks:{}", self.import, self.index);
+            write(&key, &result).await?;
+            if let Some(progress) = Imports::find(&self.import).await? {
+                Imports::broadcast(&progress).await;
+            }
+            Ok(())
+        })
+    }
+
+    fn queue(&self) -> &str {
+        &self.queue
+    }
+}
+
+type Registered = Vec<(String, Arc<dyn Importer>)>;
+
+static IMPORTERS: RwLock<Registered> = RwLock::new(Vec::new());
+static STORE: RwLock<Option<Arc<dyn Cache>>> = RwLock::new(None);
+static EVENTS: RwLock<Option<WebSocketManager>> = RwLock::new(None);
+
+fn importer(name: &str) -> Option<Arc<dyn Importer>> {
+    IMPORTERS.read().unwrap().iter().find(|(registered, _)| registered == name).map(|(_, importer)| importer.clone())
+}
+
+/// The cache fake while one is active, else the configured store, or one
+/// in process memory
+fn store() -> Arc<dyn Cache> {
+    static MEMORY: OnceLock<Arc<MemoryCache>> = OnceLock::new();
+    if let Some(fake) = crate::cache::active_fake() {
+        return fake;
+    }
+    STORE
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| MEMORY.get_or_init(|| Arc::new(MemoryCache::new(None))).clone())
+}
+
+async fn read<T: DeserializeOwned>(key: &str) -> Result<Option<T>, ImportError> {
+    let Some(value) = store().get(key).await else {
+        return Ok(None);
+    };
+    serde_json::from_str(&value).map(Some).map_err(|error| ImportError::Store(format!("{}: {}", key, error)))
+}
+
+async fn write<T: Serialize>(key: &str, value: &T) -> Result<(), ImportError> {
+    let value = serde_json::to_string(value).map_err(|error| ImportError::Store(error.to_string()))?;
+    let result = store().set(key, &value, Some(RECORD_TTL)).await.map_err(|error| error.to_string());
+    result.map_err(ImportError::Store)
+}
+
+/// Unique, roughly time-ordered import id
+fn new_import_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+    format!("{:x}-{:x}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn json_response(status: StatusCode, value: &impl Serialize) -> Response {
+    Response::with_status(status).json(value).unwrap_or_else(|_| Response::internal_error())
+}
+
+/// Import facade
+pub struct Imports;
+
+impl Imports {
+    /// Make `importer` available as `name`, in the process handling uploads
+    /// and in every worker
+    pub fn register(name: impl Into<String>, importer: impl Importer) {
+        Queue::register::<ImportChunk>();
+        let name = name.into();
+        let mut importers = IMPORTERS.write().unwrap();
+        importers.retain(|(registered, _)| *registered != name);
+        importers.push((name, Arc::new(importer)));
+    }
+
+    /// Keep progress in `cache` instead of process memory
+    pub fn store(cache: impl Cache + 'static) {
+        *STORE.write().unwrap() = Some(Arc::new(cache));
+    }
+
+    /// Broadcast progress through `manager`
+    pub fn broadcast_to(manager: WebSocketManager) {
+        *EVENTS.write().unwrap() = Some(manager);
+    }
+
+    /// Room and event stream channel an import's progress is broadcast on
+    pub fn channel(id: &str) -> String {
+        format!("imports.{}", id)
+    }
+
+    /// Check the header of `contents` and queue its rows for the importer
+    /// registered as `name`
+    pub async fn start(name: &str, file_name: Option<&str>, contents: &[u8]) -> Result<ImportProgress, ImportError> {
+        let importer = importer(name).ok_or_else(|| ImportError::UnknownImporter(name.to_string()))?;
+        let contents = std::str::from_utf8(contents).map_err(|_| ImportError::Csv(1, "the file is not UTF-8 text".to_string()))?;
+        let mut records = csv::parse(contents, importer.delimiter())
+            .map_err(|(line, message)| ImportError::Csv(line, message))?
+            .into_iter();
+        let headers = records.next().ok_or(ImportError::Empty)?.fields;
+        importer.schema().positions(&headers)?;
+
+        let rows: Vec<(usize, Vec<String>)> = records.map(|record| (record.line, record.fields)).collect();
+        let chunks: Vec<&[(usize, Vec<String>)]> = rows.chunks(importer.chunk_size().max(1)).collect();
+        let id = new_import_id();
+        let record = ImportRecord {
+            importer: name.to_string(),
+            file_name: file_name.map(str::to_string),
+            total_rows: rows.len() as u64,
+            chunks: chunks.len(),
+            created_at: unix_now(),
+        };
+        write(&format!("imports:{}", id), &record).await?;
+
+        for (index, rows) in chunks.into_iter().enumerate() {
+            let chunk = ImportChunk {
+                import: id.clone(),
+                importer: name.to_string(),
+                index,
+                queue: importer.queue().to_string(),
+                headers: headers.clone(),
+                rows: rows.to_vec(),
+            };
+            Queue::dispatch(chunk).await.map_err(ImportError::Queue)?;
+        }
+        Self::find(&id).await?.ok_or_else(|| ImportError::Store(format!("import {} was not stored", id)))
+    }
+
+    /// An import's progress, with every row error found so far
+    pub async fn find(id: &str) -> Result<Option<ImportProgress>, ImportError> {
+        let Some(record) = read::<ImportRecord>(&format!("imports:{}", id)).await? else {
+            return Ok(None);
+        };
+        let mut progress = ImportProgress {
+            id: id.to_string(),
+            importer: record.importer,
+            file_name: record.file_name,
+            status: ImportStatus::Queued,
+            total_rows: record.total_rows,
+            processed_rows: 0,
+            imported_rows: 0,
+            failed_rows: 0,
+            errors: Vec::new(),
+            created_at: record.created_at,
+            finished_at: None,
+        };
+        let mut finished = 0;
+        let mut finished_at = record.created_at;
+        for index in 0..record.chunks {
+            let Some(chunk) = read::<ChunkResult>(&format!("imports:{}:chunks:{}", id, index)).await? else {
+                continue;
+            };
+            finished += 1;
+            finished_at = finished_at.max(chunk.finished_at);
+            progress.processed_rows += chunk.rows;
+            progress.imported_rows += chunk.imported;
+            progress.failed_rows += chunk.rows - chunk.imported;
+            progress.errors.extend(chunk.errors);
+        }
+        progress.errors.sort_by_key(|error| error.line);
+        progress.status = match finished {
+            _ if finished == record.chunks => ImportStatus::Finished,
+            0 => ImportStatus::Queued,
+            _ => ImportStatus::Running,
+        };
+        if progress.status == ImportStatus::Finished {
+            progress.finished_at = Some(finished_at);
+        }
+        Ok(Some(progress))
+    }
+
+    async fn broadcast(progress: &ImportProgress) {
+        let Some(manager) = EVENTS.read().unwrap().clone() else {
+            return;
+        };
+        let channel = Self::channel(&progress.id);
+        if let Err(error) = manager.broadcast_to_room(&channel, &progress.summary().to_string()).await {
+            eprintln!("⚠️  Could not broadcast progress on {}: {}", channel, error);
+        }
+    }
+
+    /// Route handler starting an import for the importer registered as
+    /// `name` from the `file` field of a multipart form or a CSV body
+    pub fn upload_handler(
+        name: impl Into<String>,
+    ) -> impl Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Clone + Send + Sync + 'static {
+        let name: Arc<str> = Arc::from(name.into());
+        move |mut req: Request| {
+            let name = name.clone();
+            Box::pin(async move {
+                let multipart = req
+                    .header("content-type")
+                    .is_some_and(|content_type| content_type.trim_start().to_ascii_lowercase().starts_with("multipart/form-data"));
+                let (file_name, contents) = if multipart {
+                    let form = match Multipart::from_request_parts(&mut req).await {
+                        Ok(form) => form,
+                        Err(error) => return Response::bad_request().body(error.to_string()),
+                    };
+                    match form.file("file") {
+                        Some(file) => (Some(file.file_name().to_string()), file.bytes().to_vec()),
+                        None => return Response::unprocessable_entity().body("Attach the CSV as the file field"),
+                    }
+                } else {
+                    (None, req.body().to_vec())
+                };
+
+                match Self::start(&name, file_name.as_deref(), &contents).await {
+                    Ok(progress) => json_response(StatusCode::ACCEPTED, &progress),
+                    Err(error @ (ImportError::Csv(..) | ImportError::Empty | ImportError::MissingColumns(_))) => {
+                        json_response(StatusCode::UNPROCESSABLE_ENTITY, &serde_json::json!({ "error": error.to_string() }))
+                    }
+                    Err(error) => {
+                        eprintln!("⚠️  Import for {} failed to start: {}", name, error);
+                        Response::internal_error()
+                    }
+                }
+            })
+        }
+    }
+
+    /// Route handler answering with the progress of the import whose id is
+    /// the `:id` path parameter
+    pub fn progress_handler() -> impl Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Clone + Send + Sync + 'static
+    {
+        move |req: Request| {
+            let id = req.param("id").unwrap_or_default().to_string();
+            Box::pin(async move {
+                match Self::find(&id).await {
+                    Ok(Some(progress)) => json_response(StatusCode::OK, &progress),
+                    Ok(None) => Response::not_found(),
+                    Err(error) => {
+                        eprintln!("⚠️  Could not read import {}: {}", id, error);
+                        Response::internal_error()
+                    }
+                }
+            })
+        }
+    }
+}
+
+// The import test follows progress over the websocket manager's event stream
+#[cfg(all(test, feature = "websocket"))]
+mod tests {
+    use super::*;
+    use crate::{App, Method};
+    use futures::StreamExt;
+    use std::sync::Mutex;
+
+    struct Contacts(Arc<Mutex<Vec<String>>>);
+
+    impl Importer for Contacts {
+        fn schema(&self) -> Schema {
+            Schema::new()
+                .column("email", [Rule::Required, Rule::Email])
+                .column("name", [Rule::Required, Rule::MaxLength(20)])
+                .column("age", [Rule::Integer])
+                .column("joined", [Rule::Date])
+        }
+
+        fn import<'a>(&'a self, row: &'a ImportRow) -> ImportFuture<'a> {
+            Box::pin(async move {
+                #[derive(Deserialize)]
+                struct Contact {
+                    email: String,
+                    age: Option<u32>,
+                }
+                let contact: Contact = row.parse()?;
+                if contact.email == "taken@example.com" {
+                    return Err("email is already taken".into());
+                }
+                self.0.lock().unwrap().push(format!("{} {:?}", contact.email, contact.age));
+                Ok(())
+            })
+        }
+
+        fn chunk_size(&self) -> usize {
+            2
+        }
+    }
+
+    fn json(response: &Response) -> Value {
+        serde_json::from_slice(response.body_data()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_csv_import_reports_row_errors_and_broadcasts_progress() {
+        let _cache = crate::cache::without_fake();
+        let _queue = Queue::fake();
+        let imported = Arc::new(Mutex::new(Vec::new()));
+        Imports::register("test-contacts", Contacts(imported.clone()));
+        let manager = WebSocketManager::new();
+        Imports::broadcast_to(manager.clone());
+        let app = App::new()
+            .post("/contacts/import", Imports::upload_handler("test-contacts"))
+            .get("/imports/:id", Imports::progress_handler());
+
+        let upload = |body: &str| {
+            Request::mock(Method::POST, "/contacts/import").with_header("content-type", "text/csv").with_body(body)
+        };
+        let response = app.handle_request(upload("email,age\nada@example.com,36\n")).await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(json(&response)["error"], "Missing columns: name");
+
+        let csv = "Email, Name ,Age\n\
+                   ada@example.com,Ada,36\n\
+                   not-an-email,,thirty\n\
+                   taken@example.com,Taken,\n\
+                   grace@example.com,Grace\n";
+        let response = app.handle_request(upload(csv)).await;
+        assert_eq!(response.status_code(), StatusCode::ACCEPTED);
+        let started = json(&response);
+        assert_eq!((started["status"].as_str(), started["total_rows"].as_u64()), (Some("queued"), Some(4)));
+        let id = started["id"].as_str().unwrap().to_string();
+
+        let chunks = Queue::pushed::<ImportChunk>();
+        assert_eq!(chunks.len(), 2);
+        let mut events = manager.sse(&[Imports::channel(&id)]).await.take_stream().unwrap();
+        events.next().await.unwrap();
+
+        chunks[0].handle().await.unwrap();
+        let event = String::from_utf8(events.next().await.unwrap().to_vec()).unwrap();
+        assert!(event.starts_with(&format!("event: imports.{}\n", id)), "{}", event);
+        assert!(event.contains("\"status\":\"running\"") && event.contains("\"progress\":50"), "{}", event);
+
+        chunks[1].handle().await.unwrap();
+        let response = app.handle_request(Request::mock(Method::GET, &format!("/imports/{}", id))).await;
+        let progress: ImportProgress = serde_json::from_value(json(&response)).unwrap();
+        assert_eq!(progress.status, ImportStatus::Finished);
+        assert_eq!((progress.processed_rows, progress.imported_rows, progress.failed_rows), (4, 1, 3));
+        let errors: Vec<(usize, Option<&str>, &str)> =
+            progress.errors.iter().map(|error| (error.line, error.column.as_deref(), error.message.as_str())).collect();
+        assert_eq!(
+            errors,
+            vec![
+                (3, Some("email"), "email must be an email address"),
+                (3, Some("name"), "name is required"),
+                (3, Some("age"), "age must be a whole number"),
+                (4, None, "email is already taken"),
+                (5, None, "expected 3 fields, found 2"),
+            ]
+        );
+        assert_eq!(*imported.lock().unwrap(), vec!["ada@example.com Some(36)"]);
+
+        assert!(is_date("2024-02-29") && !is_date("2023-02-29") && !is_date("2024-13-01"));
+        let response = app.handle_request(Request::mock(Method::GET, "/imports/missing")).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }
+}
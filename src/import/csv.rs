@@ -0,0 +1,85 @@
+//! CSV records as written by spreadsheets (RFC 4180): quoted fields may hold
+//! the delimiter, doubled quotes and line breaks, and lines end in `\n` or
+//! `\r\n`
+
+/// One record and the line it starts on, counting from 1
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Record {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+/// Split `input` into records, skipping blank lines
+pub(crate) fn parse(input: &str, delimiter: char) -> Result<Vec<Record>, (usize, String)> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut records = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            match chars.next() {
+                None => {
+                    if quoted {
+                        return Err((start, "quoted field is never closed".to_string()));
+                    }
+                    fields.push(field);
+                    break;
+                }
+                Some('"') if quoted => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                }
+                Some('"') if field.is_empty() => quoted = true,
+                Some('\n') if quoted => {
+                    line += 1;
+                    field.push('\n');
+                }
+                Some('\r') if quoted && chars.peek() == Some(&'\n') => {}
+                Some(c) if quoted => field.push(c),
+                Some(c) if c == delimiter => fields.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') => {
+                    line += 1;
+                    fields.push(field);
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if fields.len() > 1 || !fields[0].trim().is_empty() {
+            records.push(Record { line: start, fields });
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_records_with_quotes_and_line_breaks() {
+        let input = "\u{feff}email,note\r\nada@example.com,\"says \"\"hi\"\", twice\"\r\n\r\ngrace@example.com,\"two\nlines\"\nlast,";
+        let records = parse(input, ',').unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record { line: 1, fields: vec!["email".into(), "note".into()] },
+                Record { line: 2, fields: vec!["ada@example.com".into(), "says \"hi\", twice".into()] },
+                Record { line: 4, fields: vec!["grace@example.com".into(), "two\nlines".into()] },
+                Record { line: 6, fields: vec!["last".into(), "".into()] },
+            ]
+        );
+        assert_eq!(parse("a;b\n1;2", ';').unwrap()[1].fields, vec!["1", "2"]);
+        assert_eq!(parse("a\n\"open", ',').unwrap_err().0, 2);
+    }
+}
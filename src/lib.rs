@@ -25,21 +25,58 @@
 
 pub mod api;
 pub mod app;
+#[cfg(feature = "json")]
+pub mod assets;
+#[cfg(feature = "json")]
+pub mod audit;
+#[cfg(feature = "security")]
+pub mod auth;
 pub mod cache;
+pub(crate) mod clock;
 pub mod config;
+#[cfg(feature = "cli")]
+pub mod console;
 pub mod database;
+#[cfg(feature = "json")]
+pub mod dev;
 pub mod ember;
 pub mod error_pages;
 pub mod extractors;
+#[cfg(feature = "geoip")]
+pub mod geoip;
 pub mod handler;
+#[cfg(feature = "json")]
+pub(crate) mod http_client;
+pub mod i18n;
+#[cfg(feature = "json")]
+pub mod import;
+#[cfg(feature = "logging")]
+pub mod logging;
 pub mod macros;
+pub mod mail;
 pub mod middleware;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(feature = "payments")]
+pub mod payments;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod policy;
 pub mod production;
+pub mod queue;
 pub mod request;
 pub mod response;
 pub mod router;
+#[cfg(feature = "json")]
+pub mod schedule;
+#[cfg(feature = "search")]
+pub mod search;
+#[cfg(feature = "security")]
 pub mod security;
 pub mod server;
+pub mod storage;
+pub mod task;
+pub mod tenancy;
 pub mod websocket;
 
 #[cfg(feature = "cli")]
@@ -53,8 +90,8 @@ pub use app::App;
 pub use error_pages::ErrorPages;
 pub use handler::{Handler, HandlerFn};
 pub use request::Request;
-pub use response::Response;
-pub use router::Router;
+pub use response::{CacheControl, Response};
+pub use router::{Router, RouteInfo};
 
 // HTTP essentials from the http crate
 pub use http::{Method, StatusCode, HeaderMap, HeaderName, HeaderValue};
@@ -62,5 +99,13 @@ pub use http::{Method, StatusCode, HeaderMap, HeaderName, HeaderValue};
 // Re-export tokio main macro for convenience
 pub use tokio::main;
 
+// `#[cache(ttl = "60s")]` for handlers, see `cache::Cached`
+#[cfg(feature = "macros")]
+pub use torch_web_macros::cache;
+
+// Lets the attribute macros' `::torch_web` paths resolve inside this crate
+#[cfg(feature = "macros")]
+extern crate self as torch_web;
+
 #[cfg(feature = "json")]
 pub use serde_json::{json, Value as JsonValue};
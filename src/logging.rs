@@ -0,0 +1,424 @@
+//! # Log channels
+//!
+//! Turns the `[logging]` section of `torch.toml` into `tracing` subscribers,
+//! so `tracing::info!` and friends (and `log` records) end up where the
+//! config says:
+//!
+//! ```toml
+//! [logging]
+//! default = "stack"
+//! level = "info"
+//!
+//! [logging.channels.stack]
+//! driver = "stack"
+//! channels = ["daily", "stderr"]
+//!
+//! [logging.channels.daily]
+//! driver = "daily"
+//! path = "storage/logs/torch.log"   # storage/logs/torch-2024-05-01.log, ...
+//! level = "debug"
+//! days = 14
+//!
+//! [logging.channels.stderr]
+//! driver = "stderr"
+//! level = "warn"
+//! ```
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), String> {
+//! torch_web::logging::init()?;
+//! tracing::info!(user = 42, "signed in");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Drivers:
+//! - `single`: appends to `path`
+//! - `daily`: one file per day next to `path`, keeping the newest `days`
+//!   files (14 by default, `0` keeps them all)
+//! - `stderr` and `stdout`
+//! - `stack`: every channel listed in `channels`
+//!
+//! Each channel can set its own `level` and `format` (`text`, `compact` or
+//! `json`), falling back to those of the section. Without any channels, the
+//! shorter form `level`/`format`/`file` (with `rotate` and `max_files`)
+//! logs to stderr and, when `file` is set, to that file too.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::extractors::{Config, ConfigSection};
+
+/// A channel, ready to be added to a `tracing` registry
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// How a channel writes each event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One readable line per event
+    #[default]
+    #[serde(alias = "pretty", alias = "full")]
+    Text,
+    /// Shorter lines, without span fields
+    Compact,
+    /// One JSON object per line
+    Json,
+}
+
+/// Where a channel writes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogDriver {
+    #[serde(alias = "file")]
+    Single,
+    Daily,
+    #[serde(alias = "console")]
+    Stderr,
+    Stdout,
+    Stack,
+}
+
+/// One `[logging.channels.<name>]` table
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelConfig {
+    pub driver: LogDriver,
+    /// Log file for `single`, and the name `daily` dates
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Most verbose level written, e.g. `debug`
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub format: Option<LogFormat>,
+    /// Daily files to keep
+    #[serde(default)]
+    pub days: Option<usize>,
+    /// Channels a `stack` writes to
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+/// The `[logging]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Channel used for all logs
+    pub default: Option<String>,
+    /// Level for channels that don't set one
+    pub level: String,
+    /// Format for channels that don't set one
+    pub format: LogFormat,
+    /// Log file when no channels are defined
+    pub file: Option<PathBuf>,
+    /// Rotate `file` daily
+    pub rotate: bool,
+    /// Daily files of `file` to keep
+    pub max_files: Option<usize>,
+    pub channels: HashMap<String, ChannelConfig>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            default: None,
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            file: None,
+            rotate: false,
+            max_files: None,
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl ConfigSection for LoggingConfig {
+    const SECTION: &'static str = "logging";
+}
+
+/// Daily files kept when a channel doesn't say
+const DEFAULT_DAYS: usize = 14;
+
+impl LoggingConfig {
+    /// The subscriber for the default channel
+    pub fn subscriber(&self) -> Result<Layered<BoxedLayer, Registry>, String> {
+        Ok(Registry::default().with(self.layer()?))
+    }
+
+    /// The layer for the default channel, or stderr and `file` when no
+    /// channels are defined
+    pub fn layer(&self) -> Result<BoxedLayer, String> {
+        if let Some(name) = &self.default {
+            return self.channel(name);
+        }
+        if !self.channels.is_empty() {
+            let mut names: Vec<&str> = self.channels.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            return Err(format!("Set `default` in [logging] to one of: {}", names.join(", ")));
+        }
+
+        let level = parse_level(&self.level)?;
+        let mut layers = vec![console(io::stderr, io::stderr().is_terminal(), self.format, level)];
+        if let Some(path) = &self.file {
+            layers.push(match self.rotate {
+                true => file(DailyFile::open(path, self.max_files.unwrap_or(DEFAULT_DAYS))?, self.format, level),
+                false => file(single(path)?, self.format, level),
+            });
+        }
+        Ok(layers.boxed())
+    }
+
+    /// The layer for the channel called `name`
+    pub fn channel(&self, name: &str) -> Result<BoxedLayer, String> {
+        self.resolve(name, &mut Vec::new())
+    }
+
+    fn resolve(&self, name: &str, stacked: &mut Vec<String>) -> Result<BoxedLayer, String> {
+        if stacked.iter().any(|outer| outer == name) {
+            return Err(format!("Log channel `{}` includes itself", name));
+        }
+        let channel = self.channels.get(name).ok_or_else(|| format!("No log channel called `{}`", name))?;
+        let level = parse_level(channel.level.as_deref().unwrap_or(&self.level))?;
+        let format = channel.format.unwrap_or(self.format);
+        let path = || channel.path.as_deref().ok_or_else(|| format!("Log channel `{}` needs a `path`", name));
+
+        Ok(match channel.driver {
+            LogDriver::Single => file(single(path()?)?, format, level),
+            LogDriver::Daily => file(DailyFile::open(path()?, channel.days.unwrap_or(DEFAULT_DAYS))?, format, level),
+            LogDriver::Stderr => console(io::stderr, io::stderr().is_terminal(), format, level),
+            LogDriver::Stdout => console(io::stdout, io::stdout().is_terminal(), format, level),
+            LogDriver::Stack => {
+                stacked.push(name.to_string());
+                let layers = channel
+                    .channels
+                    .iter()
+                    .map(|inner| self.resolve(inner, stacked))
+                    .collect::<Result<Vec<_>, _>>()?;
+                stacked.pop();
+                layers.boxed()
+            }
+        })
+    }
+}
+
+/// Log through the channels in the `[logging]` section of `torch.toml`.
+///
+/// Without a `torch.toml` or a `[logging]` section, logs at `info` to
+/// stderr. Fails when a channel can't be set up or another subscriber is
+/// already installed.
+pub fn init() -> Result<(), String> {
+    let config = match Config::<LoggingConfig>::load() {
+        Ok(config) => config.0.as_ref().clone(),
+        Err(error) => {
+            eprintln!("⚠️  {}, logging to stderr", error);
+            LoggingConfig::default()
+        }
+    };
+    install(&config)
+}
+
+/// Log through the channels of `config`
+pub fn install(config: &LoggingConfig) -> Result<(), String> {
+    config.subscriber()?.try_init().map_err(|error| format!("Could not install the log channels: {}", error))
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level.parse().map_err(|_| format!("Unknown log level `{}`", level))
+}
+
+fn console<W>(writer: W, ansi: bool, format: LogFormat, level: LevelFilter) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.with_filter(level).boxed(),
+        LogFormat::Compact => layer.compact().with_filter(level).boxed(),
+        LogFormat::Json => layer.json().with_filter(level).boxed(),
+    }
+}
+
+fn file<W>(writer: W, format: LogFormat, level: LevelFilter) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    console(writer, false, format, level)
+}
+
+fn single(path: &Path) -> Result<Mutex<File>, String> {
+    open_append(path).map(Mutex::new)
+}
+
+fn open_append(path: &Path) -> Result<File, String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))
+}
+
+/// A log file per day, `torch.log` becoming `torch-2024-05-01.log`
+struct DailyFile {
+    dir: PathBuf,
+    stem: String,
+    extension: String,
+    days: usize,
+    current: Mutex<Option<(String, File)>>,
+}
+
+impl DailyFile {
+    fn open(path: &Path, days: usize) -> Result<Self, String> {
+        let daily = Self {
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            stem: path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| "torch".to_string()),
+            extension: path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default(),
+            days,
+            current: Mutex::new(None),
+        };
+        let mut current = daily.current.lock().unwrap();
+        daily.roll(&mut current)?;
+        drop(current);
+        Ok(daily)
+    }
+
+    fn path_for(&self, date: &str) -> PathBuf {
+        self.dir.join(format!("{}-{}{}", self.stem, date, self.extension))
+    }
+
+    /// Switch to today's file when the date changed, then drop old files
+    fn roll(&self, current: &mut Option<(String, File)>) -> Result<(), String> {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        if current.as_ref().is_some_and(|(date, _)| *date == today) {
+            return Ok(());
+        }
+        *current = Some((today.clone(), open_append(&self.path_for(&today))?));
+        if self.days > 0 {
+            for old in self.dated_files().into_iter().skip(self.days) {
+                let _ = fs::remove_file(old);
+            }
+        }
+        Ok(())
+    }
+
+    /// This channel's files, newest first
+    fn dated_files(&self) -> Vec<PathBuf> {
+        let prefix = format!("{}-", self.stem);
+        let is_date = |date: &str| {
+            date.len() == 10 && date.char_indices().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() })
+        };
+        let Ok(entries) = fs::read_dir(if self.dir.as_os_str().is_empty() { Path::new(".") } else { &self.dir }) else {
+            return Vec::new();
+        };
+        let mut files: Vec<(String, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let date = name.strip_prefix(&prefix)?.strip_suffix(self.extension.as_str())?.to_string();
+                is_date(&date).then(|| (date, entry.path()))
+            })
+            .collect();
+        files.sort_by(|a, b| b.0.cmp(&a.0));
+        files.into_iter().map(|(_, path)| path).collect()
+    }
+}
+
+struct DailyWriter<'a>(&'a DailyFile);
+
+impl Write for DailyWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut current = self.0.current.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.0.roll(&mut current).map_err(io::Error::other)?;
+        match current.as_mut() {
+            Some((_, file)) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut current = self.0.current.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        current.as_mut().map_or(Ok(()), |(_, file)| file.flush())
+    }
+}
+
+impl<'a> MakeWriter<'a> for DailyFile {
+    type Writer = DailyWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        DailyWriter(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels_write_where_the_config_says() {
+        let dir = std::env::temp_dir().join(format!("torch-logging-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for old in ["2000-01-01", "2000-01-02", "2000-01-03"] {
+            fs::write(dir.join(format!("torch-{}.log", old)), "old\n").unwrap();
+        }
+
+        let config: LoggingConfig = toml::from_str(&format!(
+            r#"
+            default = "stack"
+            level = "info"
+
+            [channels.stack]
+            driver = "stack"
+            channels = ["daily", "audit"]
+
+            [channels.daily]
+            driver = "daily"
+            path = "{dir}/torch.log"
+            days = 2
+
+            [channels.audit]
+            driver = "single"
+            path = "{dir}/audit/audit.log"
+            level = "warn"
+            format = "json"
+            "#,
+            dir = dir.display()
+        ))
+        .unwrap();
+
+        tracing::subscriber::with_default(config.subscriber().unwrap(), || {
+            tracing::debug!("too chatty");
+            tracing::info!(user = 42, "signed in");
+            tracing::warn!("password reset");
+        });
+
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let daily = fs::read_to_string(dir.join(format!("torch-{}.log", today))).unwrap();
+        assert!(daily.contains("signed in") && daily.contains("user=42") && daily.contains("password reset"));
+        assert!(!daily.contains("too chatty"));
+        // Today's file and the newest old one are kept
+        assert!(dir.join("torch-2000-01-03.log").exists());
+        assert!(!dir.join("torch-2000-01-02.log").exists() && !dir.join("torch-2000-01-01.log").exists());
+
+        let audit = fs::read_to_string(dir.join("audit/audit.log")).unwrap();
+        assert_eq!(audit.lines().count(), 1);
+        let event: serde_json::Value = serde_json::from_str(audit.trim()).unwrap();
+        assert_eq!(event["fields"]["message"], "password reset");
+
+        let looped: LoggingConfig = toml::from_str("default = \"a\"\n[channels.a]\ndriver = \"stack\"\nchannels = [\"a\"]").unwrap();
+        assert_eq!(looped.layer().err().unwrap(), "Log channel `a` includes itself");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,710 @@
+//! # Mail
+//!
+//! Describe emails as [`Mailable`] types and send them through a pluggable
+//! [`Mailer`] transport.
+//!
+//! ## Transports
+//!
+//! - [`LogMailer`] prints each message to stdout. This is the default, so
+//!   nothing leaves the machine until a real transport is configured.
+//! - [`ArrayMailer`] keeps sent messages in memory, matching
+//!   `mail = "array"` in the `[testing]` section of `torch.toml`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use torch_web::mail::{Mail, MailMessage, Mailable};
+//!
+//! struct WelcomeEmail {
+//!     name: String,
+//! }
+//!
+//! impl Mailable for WelcomeEmail {
+//!     fn build(&self) -> MailMessage {
+//!         MailMessage::new()
+//!             .subject("Welcome to Torch")
+//!             .text(format!("Hi {}, thanks for signing up!", self.name))
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), torch_web::mail::MailError> {
+//! Mail::to("ada@example.com")
+//!     .send(WelcomeEmail { name: "Ada".into() })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Testing
+//!
+//! ```rust,no_run
+//! # use torch_web::mail::{Mail, MailMessage, Mailable};
+//! # struct WelcomeEmail { name: String }
+//! # impl Mailable for WelcomeEmail {
+//! #     fn build(&self) -> MailMessage { MailMessage::new() }
+//! # }
+//! # async fn example() {
+//! let _mail = Mail::fake();
+//!
+//! Mail::to("ada@example.com").send(WelcomeEmail { name: "Ada".into() }).await.unwrap();
+//!
+//! Mail::assert_sent::<WelcomeEmail>();
+//! Mail::assert_sent_to::<WelcomeEmail>("ada@example.com");
+//! # }
+//! ```
+//!
+//! ## Previews
+//!
+//! Register mailables with sample data to look at them in the browser while
+//! working on their templates. Previews are only served by debug builds and
+//! apps run through `torch serve`.
+//!
+//! ```rust,no_run
+//! # use torch_web::{App, mail::{Mail, MailMessage, Mailable}};
+//! # struct WelcomeEmail { name: String }
+//! # impl Mailable for WelcomeEmail {
+//! #     fn build(&self) -> MailMessage { MailMessage::new() }
+//! # }
+//! Mail::preview("welcome", || WelcomeEmail { name: "Ada".into() });
+//!
+//! // Lists previews at /torch/mail-preview and renders one at
+//! // /torch/mail-preview/welcome (add ?format=text for the text part)
+//! let app = App::new().mail_previews();
+//! ```
+//!
+//! Like [`Queue::fake`](crate::queue::Queue::fake), the fake applies to the
+//! whole process, including tasks spawned onto other threads, and fakes in
+//! tests running in parallel take turns.
+//!
+//! ## Queued mail
+//!
+//! Mailables that return a queue from [`Mailable::queue`] are built right
+//! away but sent by a queue worker, as are messages passed to
+//! [`PendingMail::queue`]. Queued sends retry transient failures, such as an
+//! SMTP `4xx` reply, with exponential backoff, and can be throttled per
+//! provider:
+//!
+//! ```rust,no_run
+//! # use std::time::Duration;
+//! # use torch_web::mail::{LogMailer, Mail};
+//! Mail::set_mailer(LogMailer);
+//! // At most 100 queued messages a minute through the "log" mailer
+//! Mail::throttle("log", 100, Duration::from_secs(60));
+//! // Three more tries after a transient failure, waiting 2s, 4s and 8s
+//! Mail::retry(3, Duration::from_secs(2));
+//! ```
+//!
+//! Sends that still fail are appended to `storage/mail/failed.jsonl`, which
+//! `torch mail failed` lists.
+//!
+//! ## Receiving mail
+//!
+//! With the `inbound-mail` feature, [`InboundEmail`] extracts mail posted by
+//! SendGrid, Mailgun or Postmark inbound webhooks; see the
+//! [`inbound`](self::inbound) module.
+
+use std::any::{type_name, Any};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+use crate::{Request, Response};
+
+#[cfg(feature = "inbound-mail")]
+pub mod inbound;
+mod queued;
+
+#[cfg(feature = "inbound-mail")]
+pub use inbound::{InboundAttachment, InboundEmail, InboundProvider};
+pub use queued::{FailedMail, SendQueuedMail, FAILED_MAIL_FILE};
+
+/// A fully built email
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct MailMessage {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub html: Option<String>,
+    pub text: Option<String>,
+}
+
+impl MailMessage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from(mut self, address: impl Into<String>) -> Self {
+        self.from = Some(address.into());
+        self
+    }
+
+    pub fn to(mut self, address: impl Into<String>) -> Self {
+        self.to.push(address.into());
+        self
+    }
+
+    pub fn cc(mut self, address: impl Into<String>) -> Self {
+        self.cc.push(address.into());
+        self
+    }
+
+    pub fn bcc(mut self, address: impl Into<String>) -> Self {
+        self.bcc.push(address.into());
+        self
+    }
+
+    pub fn reply_to(mut self, address: impl Into<String>) -> Self {
+        self.reply_to = Some(address.into());
+        self
+    }
+
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = subject.into();
+        self
+    }
+
+    pub fn html(mut self, html: impl Into<String>) -> Self {
+        self.html = Some(html.into());
+        self
+    }
+
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Whether `address` is among the to, cc or bcc recipients
+    pub fn has_recipient(&self, address: &str) -> bool {
+        self.to.iter()
+            .chain(&self.cc)
+            .chain(&self.bcc)
+            .any(|recipient| recipient.eq_ignore_ascii_case(address))
+    }
+}
+
+/// An email that knows how to build itself
+pub trait Mailable: Send + Sync + 'static {
+    fn build(&self) -> MailMessage;
+
+    /// Queue to send the mailable through instead of sending it inline
+    fn queue(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Errors raised while sending mail
+#[derive(Debug, Clone, PartialEq)]
+pub enum MailError {
+    /// The message can't be sent as built, e.g. it has no recipients
+    InvalidMessage(String),
+    /// The transport failed to deliver the message
+    Transport(String),
+    /// The transport failed in a way that may clear up on its own, such as
+    /// an SMTP `4xx` reply or a dropped connection
+    Transient(String),
+}
+
+impl MailError {
+    /// Whether sending again later might succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, MailError::Transient(_))
+    }
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::InvalidMessage(message) => write!(f, "Invalid mail message: {}", message),
+            MailError::Transport(message) => write!(f, "Mail transport error: {}", message),
+            MailError::Transient(message) => write!(f, "Temporary mail transport error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Transport that delivers built messages
+pub trait Mailer: Send + Sync + 'static {
+    fn send<'a>(&'a self, message: &'a MailMessage) -> Pin<Box<dyn Future<Output = Result<(), MailError>> + Send + 'a>>;
+
+    /// Provider name that [`Mail::throttle`] limits are looked up by
+    fn name(&self) -> &str {
+        "default"
+    }
+}
+
+/// Transport that prints messages instead of delivering them
+#[derive(Debug, Clone, Default)]
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send<'a>(&'a self, message: &'a MailMessage) -> Pin<Box<dyn Future<Output = Result<(), MailError>> + Send + 'a>> {
+        Box::pin(async move {
+            println!("📧 Mail to {}: {}", message.to.join(", "), message.subject);
+            Ok(())
+        })
+    }
+
+    fn name(&self) -> &str {
+        "log"
+    }
+}
+
+/// Transport that keeps every message in memory
+#[derive(Debug, Clone, Default)]
+pub struct ArrayMailer {
+    messages: Arc<Mutex<Vec<MailMessage>>>,
+}
+
+impl ArrayMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages sent so far, oldest first
+    pub fn messages(&self) -> Vec<MailMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+impl Mailer for ArrayMailer {
+    fn send<'a>(&'a self, message: &'a MailMessage) -> Pin<Box<dyn Future<Output = Result<(), MailError>> + Send + 'a>> {
+        self.messages.lock().unwrap().push(message.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn name(&self) -> &str {
+        "array"
+    }
+}
+
+static MAILER: RwLock<Option<Arc<dyn Mailer>>> = RwLock::new(None);
+
+type PreviewFactory = Box<dyn Fn() -> MailMessage + Send + Sync>;
+
+/// Mailables registered with [`Mail::preview`], in registration order
+static PREVIEWS: RwLock<Vec<(String, PreviewFactory)>> = RwLock::new(Vec::new());
+
+/// A mailable recorded by [`Mail::fake`]
+struct SentMail {
+    name: &'static str,
+    /// Pushed onto this queue instead of being sent inline
+    queue: Option<String>,
+    message: MailMessage,
+    mailable: Arc<dyn Any + Send + Sync>,
+}
+
+type SentMails = Arc<Mutex<Vec<SentMail>>>;
+
+static FAKE: Mutex<Option<SentMails>> = Mutex::new(None);
+
+/// Held by the active [`MailFake`], so fakes take turns
+static FAKE_TURN: Mutex<()> = Mutex::new(());
+
+/// Mail facade
+pub struct Mail;
+
+impl Mail {
+    /// Deliver every message through `mailer` from now on
+    pub fn set_mailer<M: Mailer>(mailer: M) {
+        *MAILER.write().unwrap() = Some(Arc::new(mailer));
+        #[cfg(feature = "json")]
+        crate::queue::Queue::register::<SendQueuedMail>();
+    }
+
+    /// Start a message to `address`
+    pub fn to(address: impl Into<String>) -> PendingMail {
+        PendingMail::default().to(address)
+    }
+
+    /// Send a mailable to the recipients it sets itself
+    pub async fn send<M: Mailable>(mailable: M) -> Result<(), MailError> {
+        PendingMail::default().send(mailable).await
+    }
+
+    /// Make the mailable built by `sample` viewable at
+    /// `/torch/mail-preview/{name}`. Registering a name again replaces it.
+    pub fn preview<M, F>(name: impl Into<String>, sample: F)
+    where
+        M: Mailable,
+        F: Fn() -> M + Send + Sync + 'static,
+    {
+        let name = name.into();
+        let factory: PreviewFactory = Box::new(move || sample().build());
+        let mut previews = PREVIEWS.write().unwrap();
+        match previews.iter_mut().find(|(existing, _)| *existing == name) {
+            Some(entry) => entry.1 = factory,
+            None => previews.push((name, factory)),
+        }
+    }
+
+    /// Record sent mail instead of delivering it until the returned guard is
+    /// dropped, waiting first for any other fake to be dropped
+    pub fn fake() -> MailFake {
+        let turn = FAKE_TURN.lock().unwrap_or_else(PoisonError::into_inner);
+        *FAKE.lock().unwrap() = Some(Arc::default());
+        MailFake { _turn: turn }
+    }
+
+    /// Assert at least one `M` was sent
+    pub fn assert_sent<M: Mailable>() {
+        let count = Self::sent::<M>().len();
+        assert!(count > 0, "expected mailable {} to be sent, but it was not", type_name::<M>());
+    }
+
+    /// Assert exactly `times` `M` mailables were sent
+    pub fn assert_sent_times<M: Mailable>(times: usize) {
+        let count = Self::sent::<M>().len();
+        assert_eq!(count, times, "expected mailable {} to be sent {} times, but it was sent {} times", type_name::<M>(), times, count);
+    }
+
+    /// Assert an `M` was sent to `address` (as to, cc or bcc)
+    pub fn assert_sent_to<M: Mailable>(address: &str) {
+        let found = Self::sent::<M>().iter().any(|(_, message)| message.has_recipient(address));
+        assert!(found, "expected mailable {} to be sent to {}", type_name::<M>(), address);
+    }
+
+    /// Assert a sent `M` matches `predicate`
+    pub fn assert_sent_where<M: Mailable, F: Fn(&M, &MailMessage) -> bool>(predicate: F) {
+        let found = Self::sent::<M>().iter().any(|(mailable, message)| predicate(mailable, message));
+        assert!(found, "expected a sent {} mailable matching the predicate", type_name::<M>());
+    }
+
+    /// Assert no `M` was sent
+    pub fn assert_not_sent<M: Mailable>() {
+        let count = Self::sent::<M>().len();
+        assert_eq!(count, 0, "expected mailable {} not to be sent, but it was sent {} times", type_name::<M>(), count);
+    }
+
+    /// Assert at least one `M` was queued, on `queue` when given
+    pub fn assert_queued<M: Mailable>(queue: Option<&str>) {
+        let found = Self::with_sent(|sent| {
+            sent.iter().any(|mail| {
+                mail.name == type_name::<M>() && mail.queue.as_deref().is_some_and(|queued| queue.map_or(true, |queue| queue == queued))
+            })
+        });
+        match queue {
+            Some(queue) => assert!(found, "expected mailable {} to be queued on {}", type_name::<M>(), queue),
+            None => assert!(found, "expected mailable {} to be queued, but it was not", type_name::<M>()),
+        }
+    }
+
+    /// Assert no mail at all was sent or queued
+    pub fn assert_nothing_sent() {
+        let names = Self::with_sent(|sent| sent.iter().map(|mail| mail.name).collect::<Vec<_>>());
+        assert!(names.is_empty(), "expected no mail to be sent, but found {:?}", names);
+    }
+
+    /// `M` mailables sent inline while faked, with the message each one
+    /// produced
+    pub fn sent<M: Mailable>() -> Vec<(Arc<M>, MailMessage)> {
+        Self::with_sent(|sent| {
+            sent.iter()
+                .filter(|mail| mail.queue.is_none())
+                .filter_map(|mail| {
+                    let mailable = mail.mailable.clone().downcast::<M>().ok()?;
+                    Some((mailable, mail.message.clone()))
+                })
+                .collect()
+        })
+    }
+
+    fn with_sent<T>(f: impl FnOnce(&[SentMail]) -> T) -> T {
+        let sent = FAKE
+            .lock()
+            .unwrap()
+            .clone()
+            .expect("Mail::fake() must be called before making mail assertions");
+        let sent = sent.lock().unwrap();
+        f(&sent)
+    }
+}
+
+/// A message being addressed before it is sent
+#[derive(Debug, Clone, Default)]
+pub struct PendingMail {
+    to: Vec<String>,
+    cc: Vec<String>,
+    bcc: Vec<String>,
+}
+
+impl PendingMail {
+    pub fn to(mut self, address: impl Into<String>) -> Self {
+        self.to.push(address.into());
+        self
+    }
+
+    pub fn cc(mut self, address: impl Into<String>) -> Self {
+        self.cc.push(address.into());
+        self
+    }
+
+    pub fn bcc(mut self, address: impl Into<String>) -> Self {
+        self.bcc.push(address.into());
+        self
+    }
+
+    /// Build the mailable, add these recipients and send it, or queue it
+    /// when [`Mailable::queue`] names a queue
+    pub async fn send<M: Mailable>(self, mailable: M) -> Result<(), MailError> {
+        let queue = mailable.queue().map(str::to_string);
+        let message = self.address(&mailable)?;
+        if record_fake(mailable, &message, queue.as_deref()) {
+            return Ok(());
+        }
+
+        match queue {
+            Some(queue) => queued::dispatch(type_name::<M>(), &queue, message).await,
+            None => mailer().send(&message).await,
+        }
+    }
+
+    /// Build the mailable and add these recipients now, and send it from the
+    /// queue it names, or the `default` queue
+    pub async fn queue<M: Mailable>(self, mailable: M) -> Result<(), MailError> {
+        let queue = mailable.queue().unwrap_or("default").to_string();
+        let message = self.address(&mailable)?;
+        if record_fake(mailable, &message, Some(&queue)) {
+            return Ok(());
+        }
+
+        queued::dispatch(type_name::<M>(), &queue, message).await
+    }
+
+    /// The mailable's message with these recipients added
+    fn address<M: Mailable>(self, mailable: &M) -> Result<MailMessage, MailError> {
+        let mut message = mailable.build();
+        message.to.extend(self.to);
+        message.cc.extend(self.cc);
+        message.bcc.extend(self.bcc);
+
+        if message.to.is_empty() && message.cc.is_empty() && message.bcc.is_empty() {
+            return Err(MailError::InvalidMessage(format!("{} has no recipients", type_name::<M>())));
+        }
+        Ok(message)
+    }
+}
+
+/// Record the mailable if [`Mail::fake`] is active, returning whether it
+/// was
+fn record_fake<M: Mailable>(mailable: M, message: &MailMessage, queue: Option<&str>) -> bool {
+    let Some(sent) = FAKE.lock().unwrap().clone() else {
+        return false;
+    };
+    sent.lock().unwrap().push(SentMail {
+        name: type_name::<M>(),
+        queue: queue.map(str::to_string),
+        message: message.clone(),
+        mailable: Arc::new(mailable),
+    });
+    true
+}
+
+/// The configured transport, or [`LogMailer`] when none is set
+fn mailer() -> Arc<dyn Mailer> {
+    MAILER.read().unwrap().clone().unwrap_or_else(|| Arc::new(LogMailer))
+}
+
+/// Guard returned by [`Mail::fake`]; mail is delivered normally again once
+/// it is dropped
+#[must_use = "the mail fake is removed when this guard is dropped"]
+pub struct MailFake {
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Drop for MailFake {
+    fn drop(&mut self) {
+        *FAKE.lock().unwrap() = None;
+    }
+}
+
+/// Handler for `/torch/mail-preview` and `/torch/mail-preview/:mailable`,
+/// mounted by [`App::mail_previews`](crate::App::mail_previews).
+///
+/// The index lists every registered preview. A preview responds with the
+/// message's HTML part, or its text part for `?format=text` or when it has
+/// no HTML. Outside development every request gets a 404.
+pub async fn preview(req: Request) -> Response {
+    if !previews_enabled() {
+        return Response::not_found();
+    }
+
+    let Some(name) = req.param("mailable") else {
+        return preview_index();
+    };
+    let message = {
+        let previews = PREVIEWS.read().unwrap();
+        match previews.iter().find(|(existing, _)| existing == name) {
+            Some((_, factory)) => factory(),
+            None => return Response::not_found().body(format!("No mail preview named '{}'", name)),
+        }
+    };
+
+    match (req.query("format"), &message.html, &message.text) {
+        (Some("text"), _, Some(text)) | (_, None, Some(text)) => Response::ok()
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(text.clone()),
+        (Some("text"), _, None) => Response::not_found().body(format!("Mail preview '{}' has no text part", name)),
+        (_, Some(html), _) => Response::ok()
+            .header("content-type", "text/html; charset=utf-8")
+            .body(html.clone()),
+        (_, None, None) => Response::ok()
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(format!("Mail preview '{}' has no body", name)),
+    }
+}
+
+/// Previews are for designers working locally, never for production
+fn previews_enabled() -> bool {
+    cfg!(debug_assertions) || std::env::var_os(crate::app::DEV_SERVER_ENV).is_some()
+}
+
+/// HTML page linking to every registered preview with its envelope
+fn preview_index() -> Response {
+    let previews = PREVIEWS.read().unwrap();
+    let mut rows = String::new();
+    for (name, factory) in previews.iter() {
+        let message = factory();
+        let name = escape(name);
+        let text_link = match message.text {
+            Some(_) => format!(" <a href=\"/torch/mail-preview/{}?format=text\">text</a>", name),
+            None => String::new(),
+        };
+        rows.push_str(&format!(
+            "<tr><td><a href=\"/torch/mail-preview/{0}\">{0}</a>{1}</td><td>{2}</td><td>{3}</td><td>{4}</td></tr>\n",
+            name,
+            text_link,
+            escape(&message.subject),
+            escape(message.from.as_deref().unwrap_or("")),
+            escape(&message.to.join(", ")),
+        ));
+    }
+    if previews.is_empty() {
+        rows.push_str("<tr><td colspan=\"4\">No previews registered. Add one with Mail::preview(name, || mailable).</td></tr>\n");
+    }
+
+    Response::ok()
+        .header("content-type", "text/html; charset=utf-8")
+        .body(format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Mail previews</title></head>\n<body>\n<h1>Mail previews</h1>\n<table>\n<tr><th>Mailable</th><th>Subject</th><th>From</th><th>To</th></tr>\n{}</table>\n</body>\n</html>\n",
+            rows
+        ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct WelcomeEmail {
+        name: String,
+    }
+
+    impl Mailable for WelcomeEmail {
+        fn build(&self) -> MailMessage {
+            MailMessage::new()
+                .subject("Welcome")
+                .text(format!("Hi {}", self.name))
+        }
+    }
+
+    struct InvoiceEmail;
+
+    impl Mailable for InvoiceEmail {
+        fn build(&self) -> MailMessage {
+            MailMessage::new().to("billing@example.com").subject("Invoice")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fake_records_mail() {
+        let _mail = Mail::fake();
+
+        Mail::to("ada@example.com")
+            .bcc("audit@example.com")
+            .send(WelcomeEmail { name: "Ada".to_string() })
+            .await
+            .unwrap();
+
+        Mail::assert_sent::<WelcomeEmail>();
+        Mail::assert_sent_times::<WelcomeEmail>(1);
+        Mail::assert_sent_to::<WelcomeEmail>("ada@example.com");
+        Mail::assert_sent_to::<WelcomeEmail>("audit@example.com");
+        Mail::assert_sent_where::<WelcomeEmail, _>(|mail, message| {
+            mail.name == "Ada" && message.subject == "Welcome"
+        });
+        Mail::assert_not_sent::<InvoiceEmail>();
+    }
+
+    #[tokio::test]
+    async fn test_fake_starts_empty() {
+        let _mail = Mail::fake();
+        Mail::assert_nothing_sent();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_fake_records_mail_sent_from_spawned_tasks() {
+        let _mail = Mail::fake();
+
+        let sends: Vec<_> = ["Ada", "Grace"]
+            .into_iter()
+            .map(|name| tokio::spawn(Mail::to("team@example.com").send(WelcomeEmail { name: name.to_string() })))
+            .collect();
+        for send in sends {
+            send.await.unwrap().unwrap();
+        }
+
+        Mail::assert_sent_times::<WelcomeEmail>(2);
+    }
+
+    #[tokio::test]
+    async fn test_requires_recipients() {
+        let _mail = Mail::fake();
+
+        let result = Mail::send(WelcomeEmail { name: "Ada".to_string() }).await;
+        assert!(matches!(result, Err(MailError::InvalidMessage(_))));
+
+        Mail::send(InvoiceEmail).await.unwrap();
+        Mail::assert_sent_to::<InvoiceEmail>("billing@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_array_mailer_keeps_messages() {
+        let mailer = ArrayMailer::new();
+        let message = InvoiceEmail.build();
+
+        mailer.send(&message).await.unwrap();
+
+        assert_eq!(mailer.messages(), vec![message]);
+    }
+
+    #[tokio::test]
+    async fn test_previews_render_registered_mailables() {
+        Mail::preview("test-welcome", || WelcomeEmail { name: "Grace".to_string() });
+        let app = crate::App::new().mail_previews();
+        let get = |uri: &str| {
+            Request::from_parts(http::Request::builder().uri(uri).body(()).unwrap().into_parts().0, Vec::new())
+        };
+
+        let index = app.handle_request(get("/torch/mail-preview")).await;
+        let index = String::from_utf8_lossy(index.body_data()).into_owned();
+        assert!(index.contains("<a href=\"/torch/mail-preview/test-welcome\">test-welcome</a>"));
+        assert!(index.contains("<td>Welcome</td>"));
+
+        let text = app.handle_request(get("/torch/mail-preview/test-welcome?format=text")).await;
+        assert_eq!(text.body_data(), b"Hi Grace");
+
+        let missing = app.handle_request(get("/torch/mail-preview/unknown")).await;
+        assert_eq!(missing.status_code(), http::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,533 @@
+//! Inbound email webhooks
+//!
+//! SendGrid's Inbound Parse, Mailgun routes and Postmark's inbound webhook
+//! all POST received email to the app, each in its own shape. [`InboundEmail`]
+//! reads any of them into one struct, so a reply-by-email handler doesn't
+//! care which provider is in front of it:
+//!
+//! ```rust,no_run
+//! use torch_web::mail::InboundEmail;
+//! use torch_web::{App, Response};
+//!
+//! let app = App::new().post("/webhooks/inbound-mail", |mut email: InboundEmail| async move {
+//!     // Replies go to reply+{ticket}@inbound.example.com
+//!     let Some(ticket) = email.plus_tag("reply").map(str::to_string) else {
+//!         return Response::ok();
+//!     };
+//!     if let Err(error) = email.store_attachments("local", &format!("tickets/{}", ticket)).await {
+//!         return Response::internal_error().body(error.to_string());
+//!     }
+//!     println!("{} replied to ticket {}: {:?}", email.from, ticket, email.reply_text());
+//!     Response::ok()
+//! });
+//! ```
+//!
+//! The provider is told apart by the body: JSON is Postmark, and a
+//! multipart form with `body-plain` is Mailgun, otherwise SendGrid. None of
+//! them sign the payload in a way that is checked here, so keep the webhook
+//! URL secret or put it behind basic auth.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use base64::{engine::general_purpose, Engine as _};
+use hyper::body::Bytes;
+use serde::Deserialize;
+
+use crate::extractors::{ExtractionError, FromRequestParts, Multipart};
+use crate::storage::{Storage, StorageError};
+use crate::Request;
+
+/// The service that delivered an [`InboundEmail`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboundProvider {
+    SendGrid,
+    Mailgun,
+    Postmark,
+}
+
+/// An email received through a provider's inbound webhook
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboundEmail {
+    pub provider: InboundProvider,
+    /// The sender's address
+    pub from: String,
+    /// The sender's display name, if the `From` header has one
+    pub from_name: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub subject: String,
+    pub text: Option<String>,
+    pub html: Option<String>,
+    /// The text without the quoted message it replies to, when the provider
+    /// works that out (Mailgun and Postmark)
+    pub stripped_text: Option<String>,
+    /// `Message-ID`, without angle brackets
+    pub message_id: Option<String>,
+    /// `In-Reply-To`, without angle brackets
+    pub in_reply_to: Option<String>,
+    /// `References`, oldest first and without angle brackets
+    pub references: Vec<String>,
+    /// Every header, in the order received
+    pub headers: Vec<(String, String)>,
+    pub attachments: Vec<InboundAttachment>,
+}
+
+/// A file attached to an [`InboundEmail`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboundAttachment {
+    pub file_name: String,
+    pub content_type: Option<String>,
+    /// `Content-ID` of an inline image referenced from the HTML body
+    pub content_id: Option<String>,
+    /// Path on the disk once [`InboundEmail::store_attachments`] saved it
+    pub path: Option<String>,
+    contents: Bytes,
+}
+
+impl InboundAttachment {
+    pub fn bytes(&self) -> &Bytes {
+        &self.contents
+    }
+
+    pub fn len(&self) -> usize {
+        self.contents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+}
+
+fn invalid(message: impl Into<String>) -> ExtractionError {
+    ExtractionError::InvalidForm(message.into())
+}
+
+impl InboundEmail {
+    /// Read a SendGrid Inbound Parse post (the default, non-raw format)
+    pub fn sendgrid(form: &Multipart) -> Result<Self, ExtractionError> {
+        let headers = form.text("headers").map(parse_headers).unwrap_or_default();
+        let from = form.text("from").ok_or_else(|| invalid("SendGrid payload without `from`"))?;
+        let info: serde_json::Map<String, serde_json::Value> = form
+            .text("attachment-info")
+            .map(|info| serde_json::from_str(info).map_err(|error| invalid(format!("attachment-info: {}", error))))
+            .transpose()?
+            .unwrap_or_default();
+
+        let attachments = form
+            .files()
+            .iter()
+            .map(|file| {
+                let info = info.get(file.field());
+                let text = |key: &str| info.and_then(|info| info.get(key)).and_then(|value| value.as_str()).map(str::to_string);
+                InboundAttachment {
+                    file_name: text("filename").unwrap_or_else(|| file.file_name().to_string()),
+                    content_type: text("type").or_else(|| file.content_type().map(str::to_string)),
+                    content_id: text("content-id").map(|id| strip_angles(&id)),
+                    path: None,
+                    contents: file.bytes().clone(),
+                }
+            })
+            .collect();
+
+        Ok(Self::from_parts(InboundProvider::SendGrid, from, headers, form, attachments, None))
+    }
+
+    /// Read a Mailgun route's `forward()` post
+    pub fn mailgun(form: &Multipart) -> Result<Self, ExtractionError> {
+        let headers: Vec<(String, String)> = match form.text("message-headers") {
+            Some(headers) => serde_json::from_str(headers).map_err(|error| invalid(format!("message-headers: {}", error)))?,
+            None => Vec::new(),
+        };
+        let from = form
+            .text("from")
+            .or_else(|| form.text("sender"))
+            .ok_or_else(|| invalid("Mailgun payload without `from`"))?;
+
+        let attachments = form
+            .files()
+            .iter()
+            .filter(|file| file.field().starts_with("attachment-"))
+            .map(|file| InboundAttachment {
+                file_name: file.file_name().to_string(),
+                content_type: file.content_type().map(str::to_string),
+                content_id: None,
+                path: None,
+                contents: file.bytes().clone(),
+            })
+            .collect();
+
+        let mut email = Self::from_parts(InboundProvider::Mailgun, from, headers, form, attachments, Some("stripped-text"));
+        email.text = form.text("body-plain").map(str::to_string);
+        email.html = form.text("body-html").map(str::to_string);
+        if email.to.is_empty() {
+            email.to = form.text("recipient").map(addresses).unwrap_or_default();
+        }
+        Ok(email)
+    }
+
+    /// Read Postmark's inbound webhook JSON
+    pub fn postmark(body: &[u8]) -> Result<Self, ExtractionError> {
+        let payload: PostmarkInbound =
+            serde_json::from_slice(body).map_err(|error| ExtractionError::InvalidJson(error.to_string()))?;
+        let headers: Vec<(String, String)> = payload.headers.into_iter().map(|header| (header.name, header.value)).collect();
+
+        let attachments = payload
+            .attachments
+            .into_iter()
+            .map(|attachment| {
+                let contents = general_purpose::STANDARD
+                    .decode(attachment.content.as_bytes())
+                    .map_err(|error| ExtractionError::InvalidJson(format!("attachment {}: {}", attachment.name, error)))?;
+                Ok(InboundAttachment {
+                    file_name: attachment.name,
+                    content_type: attachment.content_type,
+                    content_id: attachment.content_id.filter(|id| !id.is_empty()).map(|id| strip_angles(&id)),
+                    path: None,
+                    contents: Bytes::from(contents),
+                })
+            })
+            .collect::<Result<_, ExtractionError>>()?;
+
+        let (from, from_name) = mailbox(&payload.from);
+        let non_empty = |text: Option<String>| text.filter(|text| !text.trim().is_empty());
+        let mut email = InboundEmail {
+            provider: InboundProvider::Postmark,
+            from,
+            from_name,
+            to: addresses(&payload.to),
+            cc: addresses(&payload.cc),
+            subject: payload.subject,
+            text: non_empty(payload.text_body),
+            html: non_empty(payload.html_body),
+            stripped_text: non_empty(payload.stripped_text_reply),
+            message_id: None,
+            in_reply_to: None,
+            references: Vec::new(),
+            headers,
+            attachments,
+        };
+        email.read_threading_headers();
+        // Postmark lists the original Message-ID among the headers only when
+        // the sender set one, and its own id otherwise
+        if email.message_id.is_none() {
+            email.message_id = payload.message_id.filter(|id| !id.is_empty());
+        }
+        Ok(email)
+    }
+
+    /// The fields SendGrid and Mailgun name the same way
+    fn from_parts(
+        provider: InboundProvider,
+        from: &str,
+        headers: Vec<(String, String)>,
+        form: &Multipart,
+        attachments: Vec<InboundAttachment>,
+        stripped_field: Option<&str>,
+    ) -> Self {
+        let (from, from_name) = mailbox(from);
+        let field = |name: &str| form.text(name).filter(|value| !value.is_empty()).map(str::to_string);
+        let mut email = InboundEmail {
+            provider,
+            from,
+            from_name,
+            to: form.text("to").or_else(|| form.text("To")).map(addresses).unwrap_or_default(),
+            cc: form.text("cc").or_else(|| form.text("Cc")).map(addresses).unwrap_or_default(),
+            subject: form.text("subject").unwrap_or_default().to_string(),
+            text: field("text"),
+            html: field("html"),
+            stripped_text: stripped_field.and_then(field),
+            message_id: None,
+            in_reply_to: None,
+            references: Vec::new(),
+            headers,
+            attachments,
+        };
+        email.read_threading_headers();
+        email
+    }
+
+    fn read_threading_headers(&mut self) {
+        let ids = |value: &str| value.split_whitespace().map(strip_angles).filter(|id| !id.is_empty()).collect::<Vec<_>>();
+        self.message_id = self.header("message-id").and_then(|value| ids(value).into_iter().next());
+        self.in_reply_to = self.header("in-reply-to").and_then(|value| ids(value).into_iter().next());
+        self.references = self.header("references").map(ids).unwrap_or_default();
+    }
+
+    /// The first header called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The new text of a reply: the provider's stripped text when there is
+    /// one, or the plain text up to the first quoted line
+    pub fn reply_text(&self) -> Option<String> {
+        if let Some(stripped) = &self.stripped_text {
+            return Some(stripped.trim().to_string());
+        }
+        let text = self.text.as_deref()?;
+        let mut reply = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            // "On Mon, 1 Jan 2024 at 10:00, Ada <ada@example.com> wrote:"
+            if trimmed.starts_with('>') || (trimmed.starts_with("On ") && trimmed.trim_end().ends_with("wrote:")) {
+                break;
+            }
+            reply.push(line);
+        }
+        Some(reply.join("\n").trim().to_string())
+    }
+
+    /// The part after `+` of a recipient addressed as `{local}+{tag}@...`,
+    /// e.g. the ticket id in `reply+8812@inbound.example.com`
+    pub fn plus_tag(&self, local: &str) -> Option<&str> {
+        self.to.iter().chain(&self.cc).find_map(|address| {
+            let (user, _) = address.split_once('@')?;
+            let (name, tag) = user.split_once('+')?;
+            (name.eq_ignore_ascii_case(local) && !tag.is_empty()).then_some(tag)
+        })
+    }
+
+    /// Save every attachment under `dir` on `disk` and note where each one
+    /// went in its [`path`](InboundAttachment::path). Files are named
+    /// `{n}-{file name}` so two attachments with the same name don't clash.
+    pub async fn store_attachments(&mut self, disk: &str, dir: &str) -> Result<(), StorageError> {
+        let disk = Storage::disk(disk)?;
+        let dir = dir.trim_end_matches('/');
+        for (index, attachment) in self.attachments.iter_mut().enumerate() {
+            let path = format!("{}/{}-{}", dir, index + 1, safe_file_name(&attachment.file_name));
+            disk.put(&path, attachment.contents.to_vec()).await?;
+            attachment.path = Some(path);
+        }
+        Ok(())
+    }
+}
+
+impl FromRequestParts for InboundEmail {
+    type Error = ExtractionError;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let content_type = req.header("content-type").unwrap_or("").to_ascii_lowercase();
+        if content_type.starts_with("application/json") {
+            let result = InboundEmail::postmark(req.body());
+            return Box::pin(async move { result });
+        }
+
+        let form = Multipart::from_request_parts(req);
+        Box::pin(async move {
+            let form = form.await?;
+            if form.text("body-plain").is_some() || form.text("message-headers").is_some() {
+                InboundEmail::mailgun(&form)
+            } else {
+                InboundEmail::sendgrid(&form)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkInbound {
+    from: String,
+    #[serde(default)]
+    to: String,
+    #[serde(default)]
+    cc: String,
+    #[serde(default)]
+    subject: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    text_body: Option<String>,
+    html_body: Option<String>,
+    stripped_text_reply: Option<String>,
+    #[serde(default)]
+    headers: Vec<PostmarkHeader>,
+    #[serde(default)]
+    attachments: Vec<PostmarkAttachment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment {
+    name: String,
+    content: String,
+    content_type: Option<String>,
+    #[serde(rename = "ContentID")]
+    content_id: Option<String>,
+}
+
+/// Split a raw header block, joining folded lines
+fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in raw.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    headers
+}
+
+/// Split an address list on the commas that aren't inside quotes or angle
+/// brackets, keeping the bare addresses
+fn addresses(list: &str) -> Vec<String> {
+    let mut mailboxes = Vec::new();
+    let (mut start, mut quoted, mut angled) = (0, false, false);
+    for (index, c) in list.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => angled = true,
+            '>' if !quoted => angled = false,
+            ',' if !quoted && !angled => {
+                mailboxes.push(&list[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    mailboxes.push(&list[start..]);
+    mailboxes
+        .into_iter()
+        .map(|mailbox| self::mailbox(mailbox).0)
+        .filter(|address| !address.is_empty())
+        .collect()
+}
+
+/// The address and display name of `"Ada Lovelace" <ada@example.com>`
+fn mailbox(mailbox: &str) -> (String, Option<String>) {
+    let mailbox = mailbox.trim();
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(open), Some(close)) if open < close => {
+            let name = mailbox[..open].trim().trim_matches('"').trim();
+            let name = (!name.is_empty()).then(|| name.to_string());
+            (mailbox[open + 1..close].trim().to_string(), name)
+        }
+        _ => (mailbox.to_string(), None),
+    }
+}
+
+fn strip_angles(id: &str) -> String {
+    id.trim().trim_start_matches('<').trim_end_matches('>').to_string()
+}
+
+/// Keep an attachment's name from reaching outside its directory
+fn safe_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let safe: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+    match safe.trim_start_matches('.') {
+        "" => "attachment".to_string(),
+        safe => safe.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+
+    #[tokio::test]
+    async fn test_providers_parse_into_the_same_email() {
+        let sendgrid = "--B\r\n\
+            Content-Disposition: form-data; name=\"headers\"\r\n\r\n\
+            Message-ID: <m2@example.com>\r\nIn-Reply-To: <m1@app.example.com>\r\nReferences: <m0@app.example.com>\r\n <m1@app.example.com>\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"from\"\r\n\r\n\
+            \"Lovelace, Ada\" <ada@example.com>\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"to\"\r\n\r\n\
+            Support <reply+8812@inbound.example.com>, bob@example.com\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"subject\"\r\n\r\n\
+            Re: Ticket 8812\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+            Works now, thanks!\r\n\r\nOn Mon, 1 Jan 2024 at 10:00, Support <support@example.com> wrote:\r\n> Try again?\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"attachment-info\"\r\n\r\n\
+            {\"attachment1\": {\"filename\": \"../log.txt\", \"type\": \"text/plain\"}}\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"attachment1\"; filename=\"log.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            all good\r\n\
+            --B--\r\n";
+        let mut req = Request::mock(Method::POST, "/inbound")
+            .with_header("content-type", "multipart/form-data; boundary=B")
+            .with_body(sendgrid);
+        let mut email = InboundEmail::from_request_parts(&mut req).await.unwrap();
+        assert_eq!(email.provider, InboundProvider::SendGrid);
+        assert_eq!((email.from.as_str(), email.from_name.as_deref()), ("ada@example.com", Some("Lovelace, Ada")));
+        assert_eq!(email.to, ["reply+8812@inbound.example.com", "bob@example.com"]);
+        assert_eq!(email.plus_tag("reply"), Some("8812"));
+        assert_eq!(email.message_id.as_deref(), Some("m2@example.com"));
+        assert_eq!(email.in_reply_to.as_deref(), Some("m1@app.example.com"));
+        assert_eq!(email.references, ["m0@app.example.com", "m1@app.example.com"]);
+        assert_eq!(email.reply_text().as_deref(), Some("Works now, thanks!"));
+
+        let disk = Storage::fake("inbound-test");
+        email.store_attachments("inbound-test", "tickets/8812/").await.unwrap();
+        assert_eq!(email.attachments[0].path.as_deref(), Some("tickets/8812/1-log.txt"));
+        disk.assert_exists("tickets/8812/1-log.txt");
+
+        let mailgun = "--B\r\n\
+            Content-Disposition: form-data; name=\"from\"\r\n\r\n\
+            Ada <ada@example.com>\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"recipient\"\r\n\r\n\
+            reply+8812@inbound.example.com\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"body-plain\"\r\n\r\n\
+            Works now\r\n> old\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"stripped-text\"\r\n\r\n\
+            Works now\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"message-headers\"\r\n\r\n\
+            [[\"Message-Id\", \"<m2@example.com>\"], [\"In-Reply-To\", \"<m1@app.example.com>\"]]\r\n\
+            --B\r\n\
+            Content-Disposition: form-data; name=\"attachment-1\"; filename=\"log.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            all good\r\n\
+            --B--\r\n";
+        let form = Multipart::parse("B", Bytes::from(mailgun)).unwrap();
+        let email = InboundEmail::mailgun(&form).unwrap();
+        assert_eq!(email.to, ["reply+8812@inbound.example.com"]);
+        assert_eq!(email.in_reply_to.as_deref(), Some("m1@app.example.com"));
+        assert_eq!(email.reply_text().as_deref(), Some("Works now"));
+        assert_eq!(email.attachments[0].bytes().as_ref(), b"all good");
+
+        let postmark = r#"{
+            "From": "Ada <ada@example.com>", "To": "reply+8812@inbound.example.com", "Cc": "",
+            "Subject": "Re: Ticket 8812", "MessageID": "pm-1", "TextBody": "Works now", "HtmlBody": "",
+            "Headers": [{"Name": "In-Reply-To", "Value": "<m1@app.example.com>"}],
+            "Attachments": [{"Name": "log.txt", "Content": "YWxsIGdvb2Q=", "ContentType": "text/plain", "ContentID": ""}]
+        }"#;
+        let mut req = Request::mock(Method::POST, "/inbound")
+            .with_header("content-type", "application/json")
+            .with_body(postmark);
+        let email = InboundEmail::from_request_parts(&mut req).await.unwrap();
+        assert_eq!(email.provider, InboundProvider::Postmark);
+        assert_eq!((email.message_id.as_deref(), email.in_reply_to.as_deref()), (Some("pm-1"), Some("m1@app.example.com")));
+        assert_eq!((email.html.as_deref(), email.cc.len()), (None, 0));
+        assert_eq!(email.attachments[0].bytes().as_ref(), b"all good");
+        assert_eq!(email.attachments[0].content_id, None);
+    }
+}
@@ -0,0 +1,294 @@
+//! Sending queued mail: the job that carries a built message, per-provider
+//! throttles, retries with backoff and the log of sends that failed for good
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use super::{mailer, Mail, MailError, MailMessage, Mailer};
+use crate::clock::unix_now;
+use crate::queue::{middleware, Job, JobFuture, JobMiddleware, Queue};
+
+/// Where failed sends are appended, one JSON object per line, unless
+/// [`Mail::record_failures_in`] picks another file
+pub const FAILED_MAIL_FILE: &str = "storage/mail/failed.jsonl";
+
+/// `(provider, limit, per)` limits set with [`Mail::throttle`]
+static THROTTLES: RwLock<Vec<(String, u32, Duration)>> = RwLock::new(Vec::new());
+
+/// Retries after a transient failure, and the delay before the first one
+static RETRY: RwLock<(u32, Duration)> = RwLock::new((3, Duration::from_secs(1)));
+
+static FAILED_LOG: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+fn failed_log() -> PathBuf {
+    FAILED_LOG.read().unwrap().clone().unwrap_or_else(|| PathBuf::from(FAILED_MAIL_FILE))
+}
+
+/// Job that sends a message built when its mailable was queued
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct SendQueuedMail {
+    /// Type name of the mailable the message was built from
+    pub mailable: String,
+    pub queue: String,
+    pub message: MailMessage,
+}
+
+impl Job for SendQueuedMail {
+    fn handle(&self) -> JobFuture<'_> {
+        Box::pin(async move {
+            let (retries, backoff) = *RETRY.read().unwrap();
+            send_with_retries(&*mailer(), self, retries, backoff, &failed_log()).await?;
+            Ok(())
+        })
+    }
+
+    fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    /// Transient failures are retried inside [`handle`](Job::handle), and
+    /// anything else would fail again
+    fn max_retries(&self) -> u32 {
+        0
+    }
+
+    fn middleware(&self) -> Vec<JobMiddleware> {
+        let mailer = mailer();
+        THROTTLES
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(provider, _, _)| provider == mailer.name())
+            .map(|(provider, limit, per)| middleware::rate_limited(&format!("mail:{}", provider), *limit, *per))
+            .collect()
+    }
+}
+
+/// Push a built message onto `queue`
+pub(super) async fn dispatch(mailable: &str, queue: &str, message: MailMessage) -> Result<(), MailError> {
+    #[cfg(feature = "json")]
+    Queue::register::<SendQueuedMail>();
+
+    let job = SendQueuedMail {
+        mailable: mailable.to_string(),
+        queue: queue.to_string(),
+        message,
+    };
+    Queue::dispatch(job)
+        .await
+        .map_err(|error| MailError::Transport(format!("could not queue mail: {}", error)))
+}
+
+/// Send the job's message, retrying transient failures `retries` times with
+/// the delay doubling from `backoff`, and record it in `log` if it still fails
+async fn send_with_retries(
+    mailer: &dyn Mailer,
+    job: &SendQueuedMail,
+    retries: u32,
+    backoff: Duration,
+    log: &Path,
+) -> Result<(), MailError> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match mailer.send(&job.message).await {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+        if error.is_transient() && attempts <= retries {
+            tokio::time::sleep(backoff.saturating_mul(2u32.saturating_pow(attempts - 1))).await;
+            continue;
+        }
+
+        let failed = FailedMail {
+            id: 0,
+            mailable: job.mailable.clone(),
+            provider: mailer.name().to_string(),
+            to: job.message.to.iter().chain(&job.message.cc).chain(&job.message.bcc).cloned().collect(),
+            subject: job.message.subject.clone(),
+            error: error.to_string(),
+            attempts,
+            failed_at: unix_now(),
+        };
+        eprintln!("⚠️  Could not send {} to {}: {}", failed.mailable, failed.to.join(", "), failed.error);
+        #[cfg(feature = "json")]
+        if let Err(write_error) = failed.append_to(log) {
+            eprintln!("⚠️  Could not record failed mail in {}: {}", log.display(), write_error);
+        }
+        #[cfg(not(feature = "json"))]
+        let _ = log;
+        return Err(error);
+    }
+}
+
+/// A queued send that failed for good, as listed by `torch mail failed`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct FailedMail {
+    /// Position in the log, starting at 1
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub id: u64,
+    pub mailable: String,
+    /// [`Mailer::name`] of the transport that refused it
+    pub provider: String,
+    /// Every to, cc and bcc recipient
+    pub to: Vec<String>,
+    pub subject: String,
+    pub error: String,
+    pub attempts: u32,
+    /// Unix timestamp, in seconds
+    pub failed_at: u64,
+}
+
+#[cfg(feature = "json")]
+impl FailedMail {
+    fn append_to(&self, log: &Path) -> std::io::Result<()> {
+        use std::io::Write;
+
+        if let Some(dir) = log.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let line = serde_json::to_string(self).map_err(std::io::Error::other)?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(log)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Every failed send recorded in `log`, oldest first. Unreadable lines
+    /// are skipped but keep their id.
+    pub fn read(log: &Path) -> std::io::Result<Vec<FailedMail>> {
+        let contents = match std::fs::read_to_string(log) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        Ok(contents
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let failed: FailedMail = serde_json::from_str(line).ok()?;
+                Some(FailedMail { id: index as u64 + 1, ..failed })
+            })
+            .collect())
+    }
+}
+
+impl Mail {
+    /// Run at most `limit` queued sends per `per` through the mailer whose
+    /// [`Mailer::name`] is `provider`. The limit is shared by every worker
+    /// using the cache from [`Queue::use_cache`]; sends over it wait on
+    /// the queue.
+    pub fn throttle(provider: &str, limit: u32, per: Duration) {
+        let mut throttles = THROTTLES.write().unwrap();
+        throttles.retain(|(existing, _, _)| existing != provider);
+        throttles.push((provider.to_string(), limit, per));
+    }
+
+    /// Retry queued sends that fail transiently up to `times` times, waiting
+    /// `backoff` before the first retry and twice as long before each next
+    /// one (default 3 times from 1 second)
+    pub fn retry(times: u32, backoff: Duration) {
+        *RETRY.write().unwrap() = (times, backoff);
+    }
+
+    /// Append failed queued sends to `path` instead of
+    /// [`FAILED_MAIL_FILE`]
+    pub fn record_failures_in(path: impl Into<PathBuf>) {
+        *FAILED_LOG.write().unwrap() = Some(path.into());
+    }
+
+    /// Queued sends that failed for good, oldest first
+    #[cfg(feature = "json")]
+    pub fn failed() -> std::io::Result<Vec<FailedMail>> {
+        FailedMail::read(&failed_log())
+    }
+
+    /// Forget every failed send, returning how many there were
+    #[cfg(feature = "json")]
+    pub fn clear_failed() -> std::io::Result<usize> {
+        let log = failed_log();
+        let count = FailedMail::read(&log)?.len();
+        match std::fs::remove_file(&log) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(count),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first `transient` sends with a `4xx`, then refuses
+    /// messages to `rejected@example.com`
+    struct FlakySmtp {
+        transient: u32,
+        calls: AtomicU32,
+    }
+
+    impl Mailer for FlakySmtp {
+        fn send<'a>(&'a self, message: &'a MailMessage) -> Pin<Box<dyn Future<Output = Result<(), MailError>> + Send + 'a>> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                if call <= self.transient {
+                    Err(MailError::Transient("421 try again later".to_string()))
+                } else if message.has_recipient("rejected@example.com") {
+                    Err(MailError::Transport("550 no such user".to_string()))
+                } else {
+                    Ok(())
+                }
+            })
+        }
+
+        fn name(&self) -> &str {
+            "smtp"
+        }
+    }
+
+    fn job(to: &str) -> SendQueuedMail {
+        SendQueuedMail {
+            mailable: "app::WelcomeEmail".to_string(),
+            queue: "mail".to_string(),
+            message: MailMessage::new().to(to).subject("Welcome"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queued_mail_retries_transient_failures_and_records_the_rest() {
+        let log = std::env::temp_dir().join(format!("torch-failed-mail-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let backoff = Duration::from_millis(1);
+
+        // Two 421s, then delivered on the third attempt
+        let smtp = FlakySmtp { transient: 2, calls: AtomicU32::new(0) };
+        send_with_retries(&smtp, &job("ada@example.com"), 3, backoff, &log).await.unwrap();
+        assert_eq!(smtp.calls.load(Ordering::SeqCst), 3);
+
+        // Out of retries
+        let smtp = FlakySmtp { transient: 5, calls: AtomicU32::new(0) };
+        let error = send_with_retries(&smtp, &job("ada@example.com"), 1, backoff, &log).await.unwrap_err();
+        assert!(error.is_transient());
+        assert_eq!(smtp.calls.load(Ordering::SeqCst), 2);
+
+        // Permanent failures aren't retried
+        let smtp = FlakySmtp { transient: 0, calls: AtomicU32::new(0) };
+        send_with_retries(&smtp, &job("rejected@example.com"), 3, backoff, &log).await.unwrap_err();
+        assert_eq!(smtp.calls.load(Ordering::SeqCst), 1);
+
+        let failed = FailedMail::read(&log).unwrap();
+        assert_eq!(failed.len(), 2);
+        assert_eq!((failed[0].id, failed[0].attempts, failed[0].provider.as_str()), (1, 2, "smtp"));
+        assert_eq!((failed[1].id, failed[1].attempts), (2, 1));
+        assert_eq!(failed[1].to, ["rejected@example.com"]);
+        assert_eq!(failed[1].error, "Mail transport error: 550 no such user");
+        std::fs::remove_file(&log).unwrap();
+
+        // Throttles apply to the job through queue middleware
+        Mail::throttle("log", 10, Duration::from_secs(60));
+        assert_eq!(job("ada@example.com").middleware(), vec![middleware::rate_limited("mail:log", 10, Duration::from_secs(60))]);
+    }
+}
@@ -60,7 +60,7 @@
 
 use std::future::Future;
 use std::pin::Pin;
-use crate::{Request, Response};
+use crate::{CacheControl, Request, Response};
 
 /// Type alias for middleware functions.
 ///
@@ -76,6 +76,11 @@ pub type MiddlewareFn = std::sync::Arc<
         + 'static,
 >;
 
+/// Shared form of the `next` continuation used while assembling the chain.
+type NextFn = std::sync::Arc<
+    dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync,
+>;
+
 /// Trait for implementing middleware components.
 ///
 /// Middleware can intercept requests before they reach handlers and modify
@@ -242,7 +247,8 @@ impl MiddlewareStack {
         self.middleware.push(middleware_fn);
     }
 
-    /// Run a request through the middleware pipeline
+    /// Run a request through the middleware pipeline, the first layer added
+    /// being the outermost one
     pub async fn execute<F>(&self, req: Request, handler: F) -> Response
     where
         F: Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync + 'static,
@@ -252,18 +258,19 @@ impl MiddlewareStack {
             return handler(req).await;
         }
 
-        // For now, execute middleware in sequence (simplified implementation)
-        let response = handler(req).await;
-
-        // Apply middleware effects to the response (simplified)
-        for middleware in &self.middleware {
-            // This is a simplified approach - in a full implementation,
-            // you would need to restructure the middleware trait to support
-            // proper chaining with async closures
-            let _ = middleware; // Suppress unused warning
+        // Wrap the handler from the inside out so the first middleware added
+        // is the outermost layer and sees the request first
+        let mut next: NextFn = std::sync::Arc::new(handler);
+        for middleware in self.middleware.iter().rev() {
+            let middleware = middleware.clone();
+            let inner = next;
+            next = std::sync::Arc::new(move |req| {
+                let inner = inner.clone();
+                middleware(req, Box::new(move |req| inner(req)))
+            });
         }
 
-        response
+        next(req).await
     }
 }
 
@@ -330,6 +337,88 @@ pub fn security_headers() -> impl Middleware {
     }
 }
 
+/// Default Cache-Control policies by path prefix
+///
+/// The longest matching prefix wins, and a prefix only matches whole path
+/// segments, so `/assets` covers `/assets/app.css` but not `/assets-old`.
+/// Responses that already set Cache-Control keep it, so per-route policies
+/// win, and error responses are left alone so CDNs don't hold on to them.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use torch_web::{App, CacheControl, middleware::CachePolicies};
+///
+/// let app = App::new().middleware(
+///     CachePolicies::new()
+///         .prefix("/assets", CacheControl::new().public().max_age(Duration::from_secs(31_536_000)).immutable())
+///         .prefix("/api", CacheControl::new().no_store())
+///         .fallback(CacheControl::new().no_cache()),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicies {
+    prefixes: Vec<(String, CacheControl)>,
+    fallback: Option<CacheControl>,
+}
+
+impl CachePolicies {
+    /// No policies yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `policy` to paths under `prefix`
+    pub fn prefix(mut self, prefix: &str, policy: CacheControl) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        self.prefixes.push((prefix.to_string(), policy));
+        // Longest first, so the most specific prefix is found first
+        self.prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Apply `policy` to paths no prefix matches
+    pub fn fallback(mut self, policy: CacheControl) -> Self {
+        self.fallback = Some(policy);
+        self
+    }
+
+    /// The policy for `path`, if any
+    pub fn policy_for(&self, path: &str) -> Option<&CacheControl> {
+        self.prefixes
+            .iter()
+            .find(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.is_empty())
+            })
+            .map(|(_, policy)| policy)
+            .or(self.fallback.as_ref())
+    }
+}
+
+impl Middleware for CachePolicies {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let policy = self.policy_for(req.path()).cloned();
+
+        Box::pin(async move {
+            let response = next(req).await;
+            match policy {
+                Some(policy)
+                    if !response.headers().contains_key(http::header::CACHE_CONTROL)
+                        && !response.status_code().is_client_error()
+                        && !response.status_code().is_server_error() =>
+                {
+                    response.cache_control(policy)
+                }
+                _ => response,
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -352,7 +441,7 @@ mod tests {
             Box::pin(async { Response::ok().body("Hello") })
         };
 
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -361,15 +450,47 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = stack.execute(req, handler).await;
         assert_eq!(response.headers().get("X-Test").unwrap(), "middleware");
         assert_eq!(response.body_data(), b"Hello");
     }
 
+    #[tokio::test]
+    async fn test_middleware_stack_runs_layers_in_the_order_added() {
+        let mut stack = MiddlewareStack::new();
+        for name in ["outer", "inner"] {
+            stack.add(move |req: Request, next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                Box::pin(async move {
+                    let seen = req.header("X-Seen").unwrap_or_default().to_string();
+                    let mut req = req;
+                    req.headers_mut().insert("X-Seen", format!("{}{},", seen, name).parse().unwrap());
+                    next(req).await
+                })
+            });
+        }
+
+        let handler = |req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            let seen = req.header("X-Seen").unwrap_or_default().to_string();
+            Box::pin(async move { Response::ok().body(seen) })
+        };
+
+        let req = Request::from_parts(
+            http::Request::builder()
+                .method("GET")
+                .uri("/")
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0,
+            Vec::new(),
+        );
+
+        let response = stack.execute(req, handler).await;
+        assert_eq!(response.body_data(), b"outer,inner,");
+    }
+
     #[tokio::test]
     async fn test_cors_middleware() {
         let cors_middleware = cors();
@@ -378,7 +499,7 @@ mod tests {
             Box::pin(async { Response::ok().body("Hello") })
         });
 
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -387,9 +508,7 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = cors_middleware.call(req, next).await;
         assert_eq!(
@@ -397,4 +516,40 @@ mod tests {
             "*"
         );
     }
+
+    #[tokio::test]
+    async fn test_cache_policies_by_prefix() {
+        use std::time::Duration;
+
+        let policies = CachePolicies::new()
+            .prefix("/assets", CacheControl::new().public().max_age(Duration::from_secs(600)).immutable())
+            .prefix("/assets/private/", CacheControl::new().private())
+            .fallback(CacheControl::new().no_cache());
+
+        assert_eq!(policies.policy_for("/assets/app.css").unwrap().to_string(), "public, max-age=600, immutable");
+        assert_eq!(policies.policy_for("/assets/private/a.pdf").unwrap().to_string(), "private");
+        assert_eq!(policies.policy_for("/assets-old/a.css").unwrap().to_string(), "no-cache");
+
+        let call = |path: &'static str, response: fn() -> Response| {
+            let req = Request::from_parts(
+                http::Request::builder().uri(path).body(()).unwrap().into_parts().0,
+                Vec::new(),
+            );
+            policies.call(
+                req,
+                Box::new(move |_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                    Box::pin(async move { response() })
+                }),
+            )
+        };
+
+        let response = call("/assets/app.css", Response::ok).await;
+        assert_eq!(response.headers().get("cache-control").unwrap(), "public, max-age=600, immutable");
+
+        let response = call("/assets/app.css", || Response::ok().header("cache-control", "no-store")).await;
+        assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+
+        let response = call("/assets/missing.css", Response::not_found).await;
+        assert!(response.headers().get("cache-control").is_none());
+    }
 }
@@ -0,0 +1,273 @@
+//! # Query Cache
+//!
+//! Keep the results of [`QueryBuilder::get`](crate::orm::QueryBuilder::get)
+//! and [`count`](crate::orm::QueryBuilder::count) in a cache, keyed by the
+//! SQL and its bindings, so repeated reads skip the database until the TTL
+//! runs out.
+//!
+//! Cached results are tagged with the table the query starts from, and
+//! only that table. Saving or deleting a model invalidates every result
+//! cached for its table, so plain queries on that table never see a model
+//! older than its last save through the ORM. A query that reads other
+//! tables too, through a join or subquery in
+//! [`where_raw`](crate::orm::QueryBuilder::where_raw), isn't invalidated
+//! when those change; use [`fresh`](crate::orm::QueryBuilder::fresh) for
+//! it. Writes made with raw SQL, including those inside a
+//! [`transaction`](crate::orm::transaction), should call
+//! [`QueryCache::invalidate`] themselves once the transaction has committed,
+//! since a result cached before the commit would otherwise outlive it.
+//!
+//! Tenants with a database or schema of their own get keys and tags of
+//! their own too, so one tenant never reads another's cached rows and its
+//! writes only invalidate its own results.
+//!
+//! The cache is off until turned on, with `query_cache` in torch.toml:
+//!
+//! ```toml
+//! [database.orm]
+//! query_cache = true
+//! query_cache_ttl = 300  # seconds
+//! ```
+//!
+//! ```rust,ignore
+//! use torch_web::orm::cache::QueryCache;
+//!
+//! QueryCache::from_config(&config.database.unwrap().orm);
+//!
+//! // Cached for the configured TTL
+//! let posts = Post::query().where_eq("published", true).get().await?;
+//!
+//! // Cached for a minute instead, or not at all
+//! let count = Post::query().remember(Duration::from_secs(60)).count().await?;
+//! let latest = Post::query().fresh().order_by_desc("id").first().await?;
+//! ```
+//!
+//! Models are cached as their serde JSON, so attributes skipped when
+//! serializing come back with their default values. Use
+//! [`fresh`](crate::orm::QueryBuilder::fresh) for those queries, or share
+//! a Redis cache with [`QueryCache::enable_with`] when several servers write
+//! to the same database.
+
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::cache::{Cache, MemoryCache};
+use crate::config::DatabaseOrmConfig;
+use crate::orm::Result;
+use crate::tenancy::Tenancy;
+
+struct State {
+    store: Arc<dyn Cache>,
+    ttl: Duration,
+}
+
+static STATE: RwLock<Option<State>> = RwLock::new(None);
+
+/// Switches and invalidation for the ORM query cache
+pub struct QueryCache;
+
+impl QueryCache {
+    /// Cache query results in process memory for `ttl`
+    pub fn enable(ttl: Duration) {
+        Self::enable_with(Arc::new(MemoryCache::new(None)), ttl);
+    }
+
+    /// Cache query results in `store` for `ttl`
+    pub fn enable_with(store: Arc<dyn Cache>, ttl: Duration) {
+        *STATE.write().unwrap() = Some(State { store, ttl });
+    }
+
+    pub fn disable() {
+        *STATE.write().unwrap() = None;
+    }
+
+    pub fn is_enabled() -> bool {
+        STATE.read().unwrap().is_some()
+    }
+
+    /// Turn the cache on or off from the `[database.orm]` section
+    pub fn from_config(config: &DatabaseOrmConfig) {
+        if config.query_cache {
+            Self::enable(Duration::from_secs(config.query_cache_ttl));
+        } else {
+            Self::disable();
+        }
+    }
+
+    /// Drop every result cached for `table`
+    pub async fn invalidate(table: &str) {
+        let Some(store) = store() else {
+            return;
+        };
+        let version = uuid::Uuid::new_v4().to_string();
+        if let Err(error) = store.set(&tag_key(table), &version, None).await.map_err(|e| e.to_string()) {
+            eprintln!("⚠️  Failed to invalidate query cache for {}: {}", table, error);
+        }
+    }
+}
+
+fn store() -> Option<Arc<dyn Cache>> {
+    STATE.read().unwrap().as_ref().map(|state| state.store.clone())
+}
+
+/// How long a query should be cached: `ttl` when given, else the
+/// configured TTL, or `None` while the cache is off
+pub(crate) fn ttl(ttl: Option<Duration>) -> Option<Duration> {
+    STATE.read().unwrap().as_ref().map(|state| ttl.unwrap_or(state.ttl))
+}
+
+/// Which database the current queries go to: empty for the app's own,
+/// or the tenant's database and schema when it has its own
+fn connection_scope() -> String {
+    match Tenancy::current() {
+        Some(tenant) if tenant.database_url.is_some() || tenant.schema.is_some() => format!(
+            "{}/{}:",
+            tenant.database_url.as_deref().unwrap_or_default(),
+            tenant.schema.as_deref().unwrap_or_default()
+        ),
+        _ => String::new(),
+    }
+}
+
+fn tag_key(table: &str) -> String {
+    format!("orm:tags:{}{}", connection_scope(), table)
+}
+
+/// Current version of `table`'s tag, starting one when there's none yet
+async fn tag_version(store: &dyn Cache, table: &str) -> std::result::Result<String, String> {
+    let key = tag_key(table);
+    if let Some(version) = store.get(&key).await {
+        return Ok(version);
+    }
+    let version = uuid::Uuid::new_v4().to_string();
+    if store.add(&key, &version, None).await.map_err(|e| e.to_string())? {
+        return Ok(version);
+    }
+    // Someone else started it first
+    store.get(&key).await.ok_or_else(|| format!("{} disappeared", key))
+}
+
+/// Return the cached result of `sql` with `bindings` on `table`, or run
+/// `load` and cache what it returns for `ttl`.
+///
+/// Without a `ttl`, or with a zero one, the cache is skipped. Failures of
+/// the cache itself are logged and the query runs as if it were off.
+pub(crate) async fn remember<V, F>(table: &str, sql: &str, bindings: &[Value], ttl: Option<Duration>, load: F) -> Result<V>
+where
+    V: Serialize + DeserializeOwned,
+    F: Future<Output = Result<V>>,
+{
+    let (Some(ttl), Some(store)) = (ttl, store()) else {
+        return load.await;
+    };
+    if ttl.is_zero() {
+        return load.await;
+    }
+    let version = match tag_version(store.as_ref(), table).await {
+        Ok(version) => version,
+        Err(error) => {
+            eprintln!("⚠️  Query cache unavailable: {}", error);
+            return load.await;
+        }
+    };
+    let key = format!("orm:query:{}{}:{}:{}", connection_scope(), version, sql, Value::from(bindings.to_vec()));
+
+    if let Some(cached) = store.get(&key).await {
+        match serde_json::from_str(&cached) {
+            Ok(value) => return Ok(value),
+            Err(error) => eprintln!("⚠️  Ignoring unreadable query cache entry: {}", error),
+        }
+    }
+    let value = load.await?;
+    let stored = serde_json::to_string(&value)?;
+    if let Err(error) = store.set(&key, &stored, Some(ttl)).await.map_err(|e| e.to_string()) {
+        eprintln!("⚠️  Failed to cache query result: {}", error);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Tests that turn the global query cache on and off take turns
+    async fn isolated() -> tokio::sync::MutexGuard<'static, ()> {
+        static CACHE_STATE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+        CACHE_STATE.lock().await
+    }
+
+    #[tokio::test]
+    async fn test_query_results_are_cached_until_their_table_is_written() {
+        let _state = isolated().await;
+        let loads = AtomicU32::new(0);
+        let load = |rows: Vec<i64>| {
+            let loads = &loads;
+            async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok(rows)
+            }
+        };
+        let sql = "SELECT * FROM cache_test_posts WHERE author_id = ?";
+        let run = |author: i64, rows: Vec<i64>| {
+            let bindings = vec![Value::from(author)];
+            let future = load(rows);
+            async move { remember("cache_test_posts", sql, &bindings, ttl(None), future).await.unwrap() }
+        };
+
+        QueryCache::enable(Duration::from_secs(60));
+        assert_eq!(run(1, vec![1, 2]).await, vec![1, 2]);
+        assert_eq!(run(1, vec![9]).await, vec![1, 2]);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // Other bindings are another entry
+        assert_eq!(run(2, vec![3]).await, vec![3]);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        // Writes to another table leave the entry alone, writes to this one
+        // drop it
+        QueryCache::invalidate("cache_test_comments").await;
+        assert_eq!(run(1, vec![9]).await, vec![1, 2]);
+        QueryCache::invalidate("cache_test_posts").await;
+        assert_eq!(run(1, vec![4]).await, vec![4]);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+
+        // A TTL of zero skips the cache
+        let bindings = [Value::from(3)];
+        remember("cache_test_posts", sql, &bindings, ttl(Some(Duration::ZERO)), load(vec![5])).await.unwrap();
+        let rows = remember("cache_test_posts", sql, &bindings, ttl(Some(Duration::ZERO)), load(vec![6])).await.unwrap();
+        assert_eq!(rows, vec![6]);
+
+        QueryCache::disable();
+        assert_eq!(ttl(None), None);
+        assert_eq!(run(1, vec![7]).await, vec![7]);
+    }
+
+    #[tokio::test]
+    async fn test_tenants_with_their_own_schema_do_not_share_cached_results() {
+        use crate::tenancy::Tenant;
+
+        let _state = isolated().await;
+        let sql = "SELECT * FROM cache_test_invoices";
+        let run = |rows: Vec<i64>| async move {
+            remember("cache_test_invoices", sql, &[], Some(Duration::from_secs(60)), async { Ok(rows) }).await.unwrap()
+        };
+        let acme = Tenant::new("acme").schema("tenant_acme");
+        let globex = Tenant::new("globex").schema("tenant_globex");
+
+        QueryCache::enable(Duration::from_secs(60));
+        assert_eq!(Tenancy::scope(acme.clone(), run(vec![1])).await, vec![1]);
+        assert_eq!(Tenancy::scope(acme.clone(), run(vec![9])).await, vec![1]);
+        assert_eq!(Tenancy::scope(globex.clone(), run(vec![2])).await, vec![2]);
+
+        // Invalidating one tenant's table leaves the other's results alone
+        Tenancy::scope(globex.clone(), QueryCache::invalidate("cache_test_invoices")).await;
+        assert_eq!(Tenancy::scope(acme, run(vec![9])).await, vec![1]);
+        assert_eq!(Tenancy::scope(globex, run(vec![3])).await, vec![3]);
+        QueryCache::disable();
+    }
+}
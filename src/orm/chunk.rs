@@ -0,0 +1,167 @@
+//! # Chunked Processing
+//!
+//! Work through large tables a batch at a time, for backfills, exports and
+//! reports, without loading every row into memory.
+//!
+//! ```rust,ignore
+//! use torch_web::orm::Model;
+//!
+//! // Pages with LIMIT/OFFSET, in primary key order
+//! User::chunk(1000, |users| async move {
+//!     export(&users).await
+//! }).await?;
+//!
+//! // Pages by primary key, which is safe when the callback changes the
+//! // rows being paged through
+//! User::query()
+//!     .where_null("email_verified_at")
+//!     .chunks(500)
+//!     .by_id()
+//!     .on_progress(|progress| println!("{:.0}% done", progress.percent().unwrap_or(0.0)))
+//!     .each(|users| async move {
+//!         for mut user in users {
+//!             user.email_verified_at = Some(chrono::Utc::now());
+//!             user.save().await?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .await?;
+//! ```
+
+use std::future::Future;
+
+use serde_json::Value;
+
+use crate::orm::{Model, QueryBuilder, Result};
+
+/// How far a chunked run has got, passed to
+/// [`on_progress`](Chunks::on_progress) after each batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkProgress {
+    /// Rows handled so far
+    pub processed: u64,
+    /// Batches handled so far
+    pub chunks: u64,
+    /// Rows matching the query when the run started, if counted
+    pub total: Option<u64>,
+}
+
+impl ChunkProgress {
+    /// Percentage of rows handled, when the total is known
+    pub fn percent(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(100.0),
+            Some(total) => Some((self.processed as f64 / total as f64 * 100.0).min(100.0)),
+            None => None,
+        }
+    }
+}
+
+type ProgressCallback = Box<dyn FnMut(&ChunkProgress) + Send>;
+
+/// A query processed in batches, made by [`QueryBuilder::chunks`]
+pub struct Chunks<T: Model> {
+    query: QueryBuilder<T>,
+    size: u32,
+    by_id: bool,
+    progress: Option<ProgressCallback>,
+}
+
+impl<T: Model> Chunks<T> {
+    pub fn new(query: QueryBuilder<T>, size: u32) -> Self {
+        Self {
+            query,
+            size: size.max(1),
+            by_id: false,
+            progress: None,
+        }
+    }
+
+    /// Page by primary key (`WHERE id > last ORDER BY id`) instead of by
+    /// offset, so rows changed or deleted by the callback don't shift the
+    /// pages. Any ordering on the query is replaced by primary key order.
+    pub fn by_id(mut self) -> Self {
+        self.by_id = true;
+        self
+    }
+
+    /// Report progress after each batch, e.g. to a job's progress or a CLI
+    /// progress bar. The query is counted once up front to give a total.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&ChunkProgress) + Send + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Run `callback` on each batch in turn, stopping at the first error
+    pub async fn each<F, Fut>(mut self, mut callback: F) -> Result<ChunkProgress>
+    where
+        F: FnMut(Vec<T>) -> Fut + Send,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        let total = match self.progress {
+            Some(_) => Some(self.query.clone().count().await?.max(0) as u64),
+            None => None,
+        };
+        let mut progress = ChunkProgress {
+            processed: 0,
+            chunks: 0,
+            total,
+        };
+        let mut last_id: Option<Value> = None;
+
+        loop {
+            let batch = self.page(progress.chunks, last_id.take()).get().await?;
+            let count = batch.len();
+            if count == 0 {
+                break;
+            }
+            if self.by_id {
+                last_id = batch.last().and_then(|model| model.id()).map(serde_json::to_value).transpose()?;
+            }
+
+            callback(batch).await?;
+            progress.processed += count as u64;
+            progress.chunks += 1;
+            if let Some(report) = self.progress.as_mut() {
+                report(&progress);
+            }
+
+            if count < self.size as usize || (self.by_id && last_id.is_none()) {
+                break;
+            }
+        }
+
+        Ok(progress)
+    }
+
+    /// Query for the batch after `chunks` batches, or after `last_id`
+    fn page(&self, chunks: u64, last_id: Option<Value>) -> QueryBuilder<T> {
+        let query = self.query.clone().limit(self.size);
+        if self.by_id {
+            let query = query.reorder().order_by_asc(T::primary_key());
+            return match last_id {
+                Some(id) => query.where_gt(T::primary_key(), id),
+                None => query,
+            };
+        }
+
+        let query = if self.query.is_ordered() { query } else { query.order_by_asc(T::primary_key()) };
+        query.offset((chunks * self.size as u64) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_percent() {
+        let progress = ChunkProgress { processed: 250, chunks: 1, total: Some(1000) };
+        assert_eq!(progress.percent(), Some(25.0));
+        assert_eq!(ChunkProgress { total: Some(0), ..progress }.percent(), Some(100.0));
+        assert_eq!(ChunkProgress { total: None, ..progress }.percent(), None);
+    }
+}
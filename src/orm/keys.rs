@@ -0,0 +1,194 @@
+//! # Primary Keys
+//!
+//! Models can use UUIDs or ULIDs as primary keys instead of auto-increment
+//! integers. Such keys are generated in the application when the model is
+//! first saved, so the id is known before the `INSERT` and can't be guessed
+//! from other ids. A [`Ulid`] starts with its creation time, which keeps new
+//! rows at the end of the index and makes the ids sort by age.
+//!
+//! ```rust,ignore
+//! use torch_web::orm::keys::{Ulid, Uuid};
+//!
+//! Schema::create_table("orders", |table| {
+//!     table.ulid_primary("id");
+//!     table.uuid("customer_id");
+//! });
+//!
+//! impl_model!(Order, table = "orders", primary_key = "id", primary_key_type = Ulid, generate_key = Ulid::new);
+//!
+//! let mut order = Order { id: None, customer_id: customer.id.unwrap().to_string() };
+//! order.save().await?; // order.id is now Some(01J9...)
+//! let order = Order::find(order.id.unwrap()).await?;
+//! ```
+//!
+//! Keys are stored as text (`VARCHAR(36)` for UUIDs, `VARCHAR(26)` for ULIDs)
+//! on every database, since the `Any` driver can't decode native UUID columns.
+//! [`Ulid`] can be read from and bound to queries directly; read UUID
+//! columns as `String` and parse them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::any::{Any, AnyTypeInfo};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::{Database, Decode, Encode, Type};
+
+pub use uuid::Uuid;
+
+/// Crockford's base 32, which leaves out I, L, O and U
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Length of a ULID in text form
+pub const ULID_LENGTH: usize = 26;
+
+/// A text key that couldn't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidKey(pub String);
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid key: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidKey {}
+
+/// Universally unique, lexicographically sortable identifier: a 48-bit
+/// millisecond timestamp followed by 80 random bits, written as 26
+/// characters of base 32
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    /// A new ULID for the current time
+    pub fn new() -> Self {
+        Self::from_parts(Utc::now().timestamp_millis().max(0) as u64, random_bits())
+    }
+
+    /// A ULID from a millisecond timestamp and 80 bits of randomness
+    pub fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        let timestamp = (timestamp_ms as u128) & ((1 << 48) - 1);
+        Self(timestamp << 80 | (random & ((1 << 80) - 1)))
+    }
+
+    /// Milliseconds since the Unix epoch when the ULID was made
+    pub fn timestamp_ms(&self) -> u64 {
+        (self.0 >> 80) as u64
+    }
+
+    /// When the ULID was made
+    pub fn datetime(&self) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(self.timestamp_ms() as i64).single().unwrap_or_default()
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for Ulid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 80 random bits, taken from the parts of a v4 UUID that aren't version
+/// or variant markers
+fn random_bits() -> u128 {
+    let bytes = Uuid::new_v4().into_bytes();
+    bytes[..6]
+        .iter()
+        .chain(&bytes[10..14])
+        .fold(0u128, |bits, byte| bits << 8 | *byte as u128)
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = [0u8; ULID_LENGTH];
+        for (index, character) in text.iter_mut().enumerate() {
+            let shift = 5 * (ULID_LENGTH - 1 - index);
+            *character = ALPHABET[((self.0 >> shift) & 0x1f) as usize];
+        }
+        f.write_str(std::str::from_utf8(&text).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = InvalidKey;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidKey(text.to_string());
+        if text.len() != ULID_LENGTH {
+            return Err(invalid());
+        }
+        let mut value: u128 = 0;
+        for (index, character) in text.bytes().enumerate() {
+            let digit = ALPHABET
+                .iter()
+                .position(|known| *known == character.to_ascii_uppercase())
+                .ok_or_else(invalid)?;
+            // The first character only holds the top 3 of the 128 bits
+            if index == 0 && digit > 7 {
+                return Err(invalid());
+            }
+            value = value << 5 | digit as u128;
+        }
+        Ok(Self(value))
+    }
+}
+
+impl Serialize for Ulid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ulid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Type<Any> for Ulid {
+    fn type_info() -> AnyTypeInfo {
+        <String as Type<Any>>::type_info()
+    }
+}
+
+impl<'q> Encode<'q, Any> for Ulid {
+    fn encode_by_ref(&self, buf: &mut <Any as Database>::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        <String as Encode<'q, Any>>::encode(self.to_string(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Any> for Ulid {
+    fn decode(value: <Any as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        let text = <String as Decode<'r, Any>>::decode(value)?;
+        Ok(text.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ulid_text_and_ordering() {
+        let ulid = Ulid::from_parts(1_469_918_176_385, 0xD0B2_C4A1_9F3E_5A7B_6C8D);
+        assert_eq!(ulid.to_string(), "01ARYZ6S41T2SC98CZ7SD7PV4D");
+        assert_eq!("01aryz6s41t2sc98cz7sd7pv4d".parse::<Ulid>().unwrap(), ulid);
+        assert_eq!(ulid.timestamp_ms(), 1_469_918_176_385);
+        assert_eq!(serde_json::to_value(ulid).unwrap(), serde_json::json!("01ARYZ6S41T2SC98CZ7SD7PV4D"));
+
+        assert!("81ARYZ6S41T2SC98CZ7SD7PV4D".parse::<Ulid>().is_err());
+        assert!("01ARYZ6S41T2SC98CZ7SD7PV4U".parse::<Ulid>().is_err());
+
+        let later = Ulid::new();
+        assert!(later > ulid && later.to_string() > ulid.to_string());
+        assert_ne!(Ulid::new(), Ulid::new());
+    }
+}
@@ -0,0 +1,156 @@
+//! # Optimistic Locking
+//!
+//! Detect concurrent edits of the same row instead of letting the last save
+//! silently win. A versioned table has a `version` column (add it with
+//! [`TableBuilder::version`](crate::orm::migration::TableBuilder::version)), and
+//! every update is made with `WHERE version = ?` and bumps it. When someone
+//! else saved the row since it was loaded, nothing matches and the update
+//! fails with [`OrmError::StaleModel`].
+//!
+//! ```rust,ignore
+//! use torch_web::impl_versioned;
+//! use torch_web::orm::{locking::Versioned, Model, OrmError};
+//!
+//! impl_versioned!(Document);
+//!
+//! #[async_trait]
+//! impl Model for Document {
+//!     // ...
+//!     async fn update_in_database(&mut self) -> Result<()> {
+//!         self.update_versioned().await
+//!     }
+//! }
+//!
+//! match document.save().await {
+//!     Err(OrmError::StaleModel { .. }) => Response::with_status(StatusCode::CONFLICT).body("Someone else changed this document, reload and try again"),
+//!     // ...
+//! }
+//! ```
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::orm::connection::{bind_value, try_get_pool, placeholders};
+use crate::orm::{Model, OrmError, Result};
+
+/// Column holding the row version
+pub const VERSION_COLUMN: &str = "version";
+
+/// Models whose updates are guarded by a version column
+#[async_trait]
+pub trait Versioned: Model {
+    /// Version of the row when the model was loaded or last saved
+    fn version(&self) -> i64;
+
+    fn set_version(&mut self, version: i64);
+
+    /// Name of the version column
+    fn version_column() -> &'static str {
+        VERSION_COLUMN
+    }
+
+    /// Write every attribute to the row, provided it is still at
+    /// [`version`](Versioned::version), and bump the version.
+    ///
+    /// Call it from [`Model::update_in_database`].
+    async fn update_versioned(&mut self) -> Result<()> {
+        let id = self.id().ok_or(OrmError::ModelNotFound)?;
+        let id = serde_json::to_value(id)?;
+        let version = self.version();
+        let (sql, bindings) = versioned_update(
+            Self::table_name(),
+            Self::primary_key(),
+            Self::version_column(),
+            self.to_attributes()?,
+            &id,
+            version,
+        );
+
+        let sql = placeholders(&sql);
+        let mut query = sqlx::query(&sql);
+        for binding in &bindings {
+            query = bind_value(query, binding);
+        }
+        let result = query.execute(&try_get_pool()?).await?;
+
+        if result.rows_affected() == 0 {
+            return Err(OrmError::StaleModel {
+                table: Self::table_name(),
+                id: match id {
+                    Value::String(id) => id,
+                    other => other.to_string(),
+                },
+                version,
+            });
+        }
+        self.set_version(version + 1);
+        Ok(())
+    }
+}
+
+/// `UPDATE` statement writing `attributes` and bumping the version, with
+/// its bindings
+pub(crate) fn versioned_update(
+    table: &str,
+    primary_key: &str,
+    version_column: &str,
+    attributes: std::collections::HashMap<String, Value>,
+    id: &Value,
+    version: i64,
+) -> (String, Vec<Value>) {
+    let mut attributes: Vec<(String, Value)> = attributes
+        .into_iter()
+        .filter(|(name, _)| name != primary_key && name != version_column)
+        .collect();
+    attributes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut assignments: Vec<String> = attributes.iter().map(|(name, _)| format!("{} = ?", name)).collect();
+    assignments.push(format!("{0} = {0} + 1", version_column));
+
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {} = ? AND {} = ?",
+        table,
+        assignments.join(", "),
+        primary_key,
+        version_column
+    );
+    let mut bindings: Vec<Value> = attributes.into_iter().map(|(_, value)| value).collect();
+    bindings.push(id.clone());
+    bindings.push(Value::from(version));
+    (sql, bindings)
+}
+
+/// Implement [`Versioned`] for a model with a `version: i64` field
+#[macro_export]
+macro_rules! impl_versioned {
+    ($struct_name:ident) => {
+        impl $crate::orm::locking::Versioned for $struct_name {
+            fn version(&self) -> i64 {
+                self.version
+            }
+
+            fn set_version(&mut self, version: i64) {
+                self.version = version;
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_update_is_guarded_by_version() {
+        let attributes = [("id", json!(7)), ("title", json!("Draft")), ("body", json!(null)), ("version", json!(3))]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        let (sql, bindings) = versioned_update("documents", "id", "version", attributes, &json!(7), 3);
+
+        assert_eq!(sql, "UPDATE documents SET body = ?, title = ?, version = version + 1 WHERE id = ? AND version = ?");
+        assert_eq!(bindings, vec![json!(null), json!("Draft"), json!(7), json!(3)]);
+    }
+}
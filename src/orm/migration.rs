@@ -3,11 +3,17 @@
 //! This module provides a comprehensive migration system similar to Laravel's migrations,
 //! allowing you to version control your database schema changes.
 
+#[cfg(feature = "cli")]
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::orm::Result;
-use crate::orm::connection::get_pool;
+use crate::orm::{DatabaseDriver, OrmError, Result};
+use crate::orm::connection::{bind_value, driver, try_get_pool, placeholders};
+use crate::orm::spatial::{GeometryType, WGS84};
 
 /// Migration trait that all migrations must implement
 pub trait Migration: Send + Sync {
@@ -34,6 +40,25 @@ pub struct MigrationRecord {
 }
 
 /// Schema builder for creating and modifying database tables
+///
+/// The builders render SQL for the driver of the global pool (PostgreSQL
+/// when no pool has been initialized), so migrations can return them from
+/// [`Migration::up_sql`] and [`Migration::down_sql`]:
+///
+/// ```rust
+/// use torch_web::orm::migration::Schema;
+///
+/// let sql = Schema::create_table("posts", |table| {
+///     table.id("id");
+///     table.string("title", None);
+///     table.boolean("published").default("false");
+///     table.timestamps();
+/// })
+/// .to_sql();
+///
+/// assert!(sql.starts_with("CREATE TABLE posts ("));
+/// assert!(sql.contains("published BOOLEAN NOT NULL DEFAULT false"));
+/// ```
 pub struct Schema;
 
 impl Schema {
@@ -44,6 +69,8 @@ impl Schema {
     {
         let mut builder = TableBuilder::new(table_name);
         callback(&mut builder);
+        #[cfg(feature = "cli")]
+        record(|| SchemaChange::Create(builder.clone()));
         CreateTableBuilder { builder }
     }
     
@@ -54,11 +81,15 @@ impl Schema {
     {
         let mut builder = TableBuilder::new(table_name);
         callback(&mut builder);
+        #[cfg(feature = "cli")]
+        record(|| SchemaChange::Alter(builder.clone()));
         AlterTableBuilder { builder }
     }
     
     /// Drop a table
     pub fn drop_table(table_name: &str) -> DropTableBuilder {
+        #[cfg(feature = "cli")]
+        record(|| SchemaChange::Drop(table_name.to_string()));
         DropTableBuilder {
             table_name: table_name.to_string(),
         }
@@ -66,23 +97,83 @@ impl Schema {
     
     /// Check if a table exists
     pub async fn has_table(table_name: &str) -> Result<bool> {
-        let _pool = get_pool();
-
-        // This is a simplified check - in a real implementation,
-        // this would query the information schema
-        println!("Checking if table '{}' exists", table_name);
-        Ok(false) // Placeholder
+        let sql = match current_driver() {
+            DatabaseDriver::Postgres => {
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = ?"
+            }
+            DatabaseDriver::MySql => {
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?"
+            }
+            DatabaseDriver::Sqlite => "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+        };
+        count(sql, &[table_name]).await.map(|count| count > 0)
     }
-    
+
     /// Check if a column exists in a table
     pub async fn has_column(table_name: &str, column_name: &str) -> Result<bool> {
-        let _pool = get_pool();
+        let sql = match current_driver() {
+            DatabaseDriver::Postgres => {
+                "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = current_schema() AND table_name = ? AND column_name = ?"
+            }
+            DatabaseDriver::MySql => {
+                "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?"
+            }
+            DatabaseDriver::Sqlite => "SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?",
+        };
+        count(sql, &[table_name, column_name]).await.map(|count| count > 0)
+    }
+}
+
+/// A table built with [`Schema`] while
+/// [`MigrationRunner::schema_changes`] runs a migration
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone)]
+pub(crate) enum SchemaChange {
+    Create(TableBuilder),
+    Alter(TableBuilder),
+    Drop(String),
+}
+
+#[cfg(feature = "cli")]
+thread_local! {
+    static RECORDED: RefCell<Option<Vec<SchemaChange>>> = const { RefCell::new(None) };
+}
+
+/// Keep `change` if a migration is being recorded on this thread
+#[cfg(feature = "cli")]
+fn record(change: impl FnOnce() -> SchemaChange) {
+    RECORDED.with(|recorded| {
+        if let Some(changes) = recorded.borrow_mut().as_mut() {
+            changes.push(change());
+        }
+    });
+}
 
-        // This is a simplified check - in a real implementation,
-        // this would query the information schema
-        println!("Checking if column '{}' exists in table '{}'", column_name, table_name);
-        Ok(false) // Placeholder
+/// Run a `SELECT COUNT(*)` query with string bindings against the global pool
+async fn count(sql: &str, bindings: &[&str]) -> Result<i64> {
+    let sql = placeholders(sql);
+    let mut query = sqlx::query_scalar::<_, i64>(&sql);
+    for binding in bindings {
+        query = query.bind(binding.to_string());
     }
+    query.fetch_one(&try_get_pool()?).await.map_err(OrmError::Database)
+}
+
+/// Dialect used when rendering schema SQL
+fn current_driver() -> DatabaseDriver {
+    driver().cloned().unwrap_or(DatabaseDriver::Postgres)
+}
+
+/// Run each statement against the global pool
+async fn execute_statements(statements: Vec<String>) -> Result<()> {
+    let pool = &try_get_pool()?;
+    for statement in statements {
+        sqlx::query(&statement)
+            .execute(pool)
+            .await
+            .map_err(OrmError::Database)?;
+    }
+    Ok(())
 }
 
 /// Builder for creating tables
@@ -91,13 +182,19 @@ pub struct CreateTableBuilder {
 }
 
 impl CreateTableBuilder {
+    /// Render the SQL for the current driver
+    pub fn to_sql(&self) -> String {
+        self.to_sql_for(&current_driver())
+    }
+
+    /// Render the SQL for a specific driver
+    pub fn to_sql_for(&self, driver: &DatabaseDriver) -> String {
+        join_statements(self.builder.build_create_sql(driver))
+    }
+
     /// Execute the table creation
     pub async fn execute(self) -> Result<()> {
-        let sql = self.builder.build_create_sql();
-        println!("Creating table: {}", sql);
-        
-        // In a real implementation, this would execute the SQL
-        Ok(())
+        execute_statements(self.builder.build_create_sql(&current_driver())).await
     }
 }
 
@@ -107,13 +204,19 @@ pub struct AlterTableBuilder {
 }
 
 impl AlterTableBuilder {
+    /// Render the SQL for the current driver
+    pub fn to_sql(&self) -> String {
+        self.to_sql_for(&current_driver())
+    }
+
+    /// Render the SQL for a specific driver
+    pub fn to_sql_for(&self, driver: &DatabaseDriver) -> String {
+        join_statements(self.builder.build_alter_sql(driver))
+    }
+
     /// Execute the table alteration
     pub async fn execute(self) -> Result<()> {
-        let sql = self.builder.build_alter_sql();
-        println!("Altering table: {}", sql);
-        
-        // In a real implementation, this would execute the SQL
-        Ok(())
+        execute_statements(self.builder.build_alter_sql(&current_driver())).await
     }
 }
 
@@ -123,21 +226,28 @@ pub struct DropTableBuilder {
 }
 
 impl DropTableBuilder {
+    /// Render the SQL
+    pub fn to_sql(&self) -> String {
+        format!("DROP TABLE IF EXISTS {}", self.table_name)
+    }
+
     /// Execute the table drop
     pub async fn execute(self) -> Result<()> {
-        let sql = format!("DROP TABLE IF EXISTS {}", self.table_name);
-        println!("Dropping table: {}", sql);
-        
-        // In a real implementation, this would execute the SQL
-        Ok(())
+        execute_statements(vec![self.to_sql()]).await
     }
 }
 
+fn join_statements(statements: Vec<String>) -> String {
+    statements.join(";\n")
+}
+
 /// Table builder for defining table structure
+#[derive(Debug, Clone)]
 pub struct TableBuilder {
-    table_name: String,
-    columns: Vec<ColumnDefinition>,
-    indexes: Vec<IndexDefinition>,
+    pub(crate) table_name: String,
+    pub(crate) columns: Vec<ColumnDefinition>,
+    pub(crate) dropped_columns: Vec<String>,
+    pub(crate) indexes: Vec<IndexDefinition>,
     foreign_keys: Vec<ForeignKeyDefinition>,
 }
 
@@ -146,99 +256,143 @@ impl TableBuilder {
         Self {
             table_name: table_name.to_string(),
             columns: Vec::new(),
+            dropped_columns: Vec::new(),
             indexes: Vec::new(),
             foreign_keys: Vec::new(),
         }
     }
-    
-    /// Add an auto-incrementing primary key column
-    pub fn id(&mut self, name: &str) -> &mut Self {
+
+    fn column(&mut self, name: &str, column_type: ColumnType) -> &mut Self {
         self.columns.push(ColumnDefinition {
             name: name.to_string(),
-            column_type: ColumnType::Integer,
+            column_type,
             nullable: false,
             default: None,
-            primary_key: true,
-            auto_increment: true,
+            primary_key: false,
+            auto_increment: false,
             unique: false,
         });
         self
     }
     
+    /// Add an auto-incrementing primary key column
+    pub fn id(&mut self, name: &str) -> &mut Self {
+        self.column(name, ColumnType::BigInteger);
+        if let Some(column) = self.columns.last_mut() {
+            column.primary_key = true;
+            column.auto_increment = true;
+        }
+        self
+    }
+    
+    /// Add a primary key column holding a [`Uuid`](crate::orm::keys::Uuid),
+    /// generated by the model rather than the database
+    pub fn uuid_primary(&mut self, name: &str) -> &mut Self {
+        self.uuid(name);
+        if let Some(column) = self.columns.last_mut() {
+            column.primary_key = true;
+        }
+        self
+    }
+
+    /// Add a primary key column holding a [`Ulid`](crate::orm::keys::Ulid),
+    /// generated by the model rather than the database
+    pub fn ulid_primary(&mut self, name: &str) -> &mut Self {
+        self.ulid(name);
+        if let Some(column) = self.columns.last_mut() {
+            column.primary_key = true;
+        }
+        self
+    }
+
+    /// Add a column holding a UUID as text, e.g. a key of another table
+    pub fn uuid(&mut self, name: &str) -> &mut Self {
+        self.column(name, ColumnType::String(36))
+    }
+
+    /// Add a column holding a ULID as text, e.g. a key of another table
+    pub fn ulid(&mut self, name: &str) -> &mut Self {
+        self.column(name, ColumnType::String(crate::orm::keys::ULID_LENGTH as u32))
+    }
+
     /// Add a string column
     pub fn string(&mut self, name: &str, length: Option<u32>) -> &mut Self {
-        self.columns.push(ColumnDefinition {
-            name: name.to_string(),
-            column_type: ColumnType::String(length.unwrap_or(255)),
-            nullable: false,
-            default: None,
-            primary_key: false,
-            auto_increment: false,
-            unique: false,
-        });
-        self
+        self.column(name, ColumnType::String(length.unwrap_or(255)))
     }
     
     /// Add an integer column
     pub fn integer(&mut self, name: &str) -> &mut Self {
-        self.columns.push(ColumnDefinition {
-            name: name.to_string(),
-            column_type: ColumnType::Integer,
-            nullable: false,
-            default: None,
-            primary_key: false,
-            auto_increment: false,
-            unique: false,
-        });
-        self
+        self.column(name, ColumnType::Integer)
+    }
+
+    /// Add a 64-bit integer column
+    pub fn big_integer(&mut self, name: &str) -> &mut Self {
+        self.column(name, ColumnType::BigInteger)
+    }
+
+    /// Add a double precision floating point column
+    pub fn float(&mut self, name: &str) -> &mut Self {
+        self.column(name, ColumnType::Float)
+    }
+
+    /// Add a fixed precision decimal column
+    pub fn decimal(&mut self, name: &str, precision: u8, scale: u8) -> &mut Self {
+        self.column(name, ColumnType::Decimal(precision, scale))
     }
     
     /// Add a boolean column
     pub fn boolean(&mut self, name: &str) -> &mut Self {
-        self.columns.push(ColumnDefinition {
-            name: name.to_string(),
-            column_type: ColumnType::Boolean,
-            nullable: false,
-            default: None,
-            primary_key: false,
-            auto_increment: false,
-            unique: false,
-        });
-        self
+        self.column(name, ColumnType::Boolean)
     }
     
     /// Add a text column
     pub fn text(&mut self, name: &str) -> &mut Self {
-        self.columns.push(ColumnDefinition {
-            name: name.to_string(),
-            column_type: ColumnType::Text,
-            nullable: false,
-            default: None,
-            primary_key: false,
-            auto_increment: false,
-            unique: false,
-        });
-        self
+        self.column(name, ColumnType::Text)
     }
     
     /// Add a timestamp column
     pub fn timestamp(&mut self, name: &str) -> &mut Self {
-        self.columns.push(ColumnDefinition {
-            name: name.to_string(),
-            column_type: ColumnType::Timestamp,
-            nullable: false,
-            default: None,
-            primary_key: false,
-            auto_increment: false,
-            unique: false,
-        });
-        self
+        self.column(name, ColumnType::Timestamp)
     }
     
-    /// Add created_at and updated_at timestamp columns
+    /// Add a spatial column in planar coordinates of `srid`
+    pub fn geometry(&mut self, name: &str, shape: GeometryType, srid: u32) -> &mut Self {
+        self.column(name, ColumnType::Geometry(shape, srid))
+    }
+
+    /// Add a spatial column in degrees on the globe, so distances come out
+    /// in meters. PostGIS has a separate geography type for this; other
+    /// databases get a geometry column with SRID 4326.
+    pub fn geography(&mut self, name: &str, shape: GeometryType) -> &mut Self {
+        self.column(name, ColumnType::Geography(shape))
+    }
+
+    /// Add a column holding a [`Point`](crate::orm::spatial::Point)
+    pub fn point(&mut self, name: &str) -> &mut Self {
+        self.geography(name, GeometryType::Point)
+    }
+
+    /// Add a column holding a [`Polygon`](crate::orm::spatial::Polygon)
+    pub fn polygon(&mut self, name: &str) -> &mut Self {
+        self.geography(name, GeometryType::Polygon)
+    }
+
+    /// Add nullable created_at and updated_at timestamp columns
     pub fn timestamps(&mut self) -> &mut Self {
-        self.timestamp("created_at");
-        self.timestamp("updated_at");
+        self.timestamp("created_at").nullable().default("CURRENT_TIMESTAMP");
+        self.timestamp("updated_at").nullable().default("CURRENT_TIMESTAMP");
+        self
+    }
+
+    /// Add the `version` column used for optimistic locking, see
+    /// [`Versioned`](crate::orm::locking::Versioned)
+    pub fn version(&mut self) -> &mut Self {
+        self.big_integer(crate::orm::locking::VERSION_COLUMN).default("1")
+    }
+
+    /// Drop a column (only meaningful in [`Schema::alter_table`])
+    pub fn drop_column(&mut self, name: &str) -> &mut Self {
+        self.dropped_columns.push(name.to_string());
         self
     }
     
@@ -251,6 +405,9 @@ impl TableBuilder {
     }
     
     /// Set a default value for the last added column
+    ///
+    /// The value is inserted into the SQL as is, so quote string literals
+    /// yourself: `.default("'draft'")`.
     pub fn default(&mut self, value: &str) -> &mut Self {
         if let Some(column) = self.columns.last_mut() {
             column.default = Some(value.to_string());
@@ -274,6 +431,20 @@ impl TableBuilder {
             name: index_name.to_string(),
             columns: columns.iter().map(|s| s.to_string()).collect(),
             unique: false,
+            spatial: false,
+        });
+        self
+    }
+
+    /// Add an index for spatial queries on geometry columns (GiST on
+    /// PostgreSQL, `SPATIAL` on MySQL, which needs the columns to be
+    /// `NOT NULL`)
+    pub fn spatial_index(&mut self, columns: &[&str]) -> &mut Self {
+        self.indexes.push(IndexDefinition {
+            name: format!("{}_{}_spatial", self.table_name, columns.join("_")),
+            columns: columns.iter().map(|s| s.to_string()).collect(),
+            unique: false,
+            spatial: true,
         });
         self
     }
@@ -286,6 +457,7 @@ impl TableBuilder {
             name: index_name.to_string(),
             columns: columns.iter().map(|s| s.to_string()).collect(),
             unique: true,
+            spatial: false,
         });
         self
     }
@@ -302,49 +474,130 @@ impl TableBuilder {
         self
     }
     
-    fn build_create_sql(&self) -> String {
-        let mut sql = format!("CREATE TABLE {} (\n", self.table_name);
-        
-        // Add columns
-        let column_definitions: Vec<String> = self.columns.iter().map(|col| col.to_sql()).collect();
-        sql.push_str(&column_definitions.join(",\n"));
-        
-        // Add indexes and foreign keys would go here
-        
-        sql.push_str("\n)");
-        sql
+    fn build_create_sql(&self, driver: &DatabaseDriver) -> Vec<String> {
+        let mut definitions: Vec<String> = self.columns.iter().map(|col| format!("  {}", col.to_sql(driver))).collect();
+        definitions.extend(self.foreign_keys.iter().map(|fk| format!("  {}", fk.to_sql())));
+
+        let mut statements = vec![format!("CREATE TABLE {} (\n{}\n)", self.table_name, definitions.join(",\n"))];
+        statements.extend(self.index_statements(driver));
+        statements
     }
     
-    fn build_alter_sql(&self) -> String {
-        // This would build ALTER TABLE statements
-        format!("ALTER TABLE {} ADD COLUMN ...", self.table_name)
+    fn build_alter_sql(&self, driver: &DatabaseDriver) -> Vec<String> {
+        let mut statements: Vec<String> = self
+            .columns
+            .iter()
+            .map(|col| format!("ALTER TABLE {} ADD COLUMN {}", self.table_name, col.to_sql(driver)))
+            .collect();
+        statements.extend(
+            self.dropped_columns
+                .iter()
+                .map(|column| format!("ALTER TABLE {} DROP COLUMN {}", self.table_name, column)),
+        );
+        statements.extend(
+            self.foreign_keys
+                .iter()
+                .map(|fk| format!("ALTER TABLE {} ADD {}", self.table_name, fk.to_sql())),
+        );
+        statements.extend(self.index_statements(driver));
+        statements
+    }
+
+    fn index_statements(&self, driver: &DatabaseDriver) -> Vec<String> {
+        self.indexes
+            .iter()
+            .map(|index| {
+                let columns = index.columns.join(", ");
+                match (index.spatial, driver) {
+                    (true, DatabaseDriver::Postgres) => {
+                        format!("CREATE INDEX {} ON {} USING GIST ({})", index.name, self.table_name, columns)
+                    }
+                    (true, DatabaseDriver::MySql) => {
+                        format!("CREATE SPATIAL INDEX {} ON {} ({})", index.name, self.table_name, columns)
+                    }
+                    _ => format!(
+                        "CREATE {}INDEX {} ON {} ({})",
+                        if index.unique { "UNIQUE " } else { "" },
+                        index.name,
+                        self.table_name,
+                        columns
+                    ),
+                }
+            })
+            .collect()
     }
 }
 
 /// Column definition
 #[derive(Debug, Clone)]
-struct ColumnDefinition {
-    name: String,
+pub(crate) struct ColumnDefinition {
+    pub(crate) name: String,
     column_type: ColumnType,
     nullable: bool,
     default: Option<String>,
     primary_key: bool,
     auto_increment: bool,
-    unique: bool,
+    pub(crate) unique: bool,
 }
 
 impl ColumnDefinition {
-    fn to_sql(&self) -> String {
-        let mut sql = format!("  {} {}", self.name, self.column_type.to_sql());
+    /// The [`TableBuilder`] call adding the column, e.g.
+    /// `table.string("title", None).nullable()`
+    #[cfg(feature = "cli")]
+    pub(crate) fn to_builder_call(&self) -> String {
+        let name = &self.name;
+        let mut call = match (&self.column_type, self.primary_key) {
+            (ColumnType::BigInteger, true) if self.auto_increment => return format!("table.id({:?})", name),
+            (ColumnType::String(36), true) => format!("table.uuid_primary({:?})", name),
+            (ColumnType::String(length), true) if *length == crate::orm::keys::ULID_LENGTH as u32 => {
+                format!("table.ulid_primary({:?})", name)
+            }
+            (ColumnType::Integer, _) => format!("table.integer({:?})", name),
+            (ColumnType::BigInteger, _) => format!("table.big_integer({:?})", name),
+            (ColumnType::Float, _) => format!("table.float({:?})", name),
+            (ColumnType::String(255), _) => format!("table.string({:?}, None)", name),
+            (ColumnType::String(length), _) => format!("table.string({:?}, Some({}))", name, length),
+            (ColumnType::Text, _) => format!("table.text({:?})", name),
+            (ColumnType::Boolean, _) => format!("table.boolean({:?})", name),
+            (ColumnType::Timestamp, _) => format!("table.timestamp({:?})", name),
+            (ColumnType::Decimal(precision, scale), _) => format!("table.decimal({:?}, {}, {})", name, precision, scale),
+            (ColumnType::Geography(GeometryType::Point), _) => format!("table.point({:?})", name),
+            (ColumnType::Geography(GeometryType::Polygon), _) => format!("table.polygon({:?})", name),
+            (ColumnType::Geography(shape), _) => {
+                format!("table.geography({:?}, torch_web::orm::spatial::GeometryType::{:?})", name, shape)
+            }
+            (ColumnType::Geometry(shape, srid), _) => format!(
+                "table.geometry({:?}, torch_web::orm::spatial::GeometryType::{:?}, {})",
+                name, shape, srid
+            ),
+        };
+        if self.nullable {
+            call.push_str(".nullable()");
+        }
+        if let Some(default) = &self.default {
+            call.push_str(&format!(".default({:?})", default));
+        }
+        if self.unique && !self.primary_key {
+            call.push_str(".unique()");
+        }
+        call
+    }
+
+    fn to_sql(&self, driver: &DatabaseDriver) -> String {
+        if self.primary_key && self.auto_increment {
+            return match driver {
+                DatabaseDriver::Postgres => format!("{} BIGSERIAL PRIMARY KEY", self.name),
+                DatabaseDriver::MySql => format!("{} BIGINT AUTO_INCREMENT PRIMARY KEY", self.name),
+                DatabaseDriver::Sqlite => format!("{} INTEGER PRIMARY KEY AUTOINCREMENT", self.name),
+            };
+        }
+
+        let mut sql = format!("{} {}", self.name, self.column_type.to_sql(driver));
         
         if self.primary_key {
             sql.push_str(" PRIMARY KEY");
         }
         
-        if self.auto_increment {
-            sql.push_str(" AUTO_INCREMENT");
-        }
-        
         if !self.nullable {
             sql.push_str(" NOT NULL");
         }
@@ -365,32 +618,67 @@ impl ColumnDefinition {
 #[derive(Debug, Clone)]
 enum ColumnType {
     Integer,
+    BigInteger,
+    Float,
     String(u32),
     Text,
     Boolean,
     Timestamp,
     Decimal(u8, u8),
+    Geometry(GeometryType, u32),
+    Geography(GeometryType),
 }
 
 impl ColumnType {
-    fn to_sql(&self) -> String {
+    fn to_sql(&self, driver: &DatabaseDriver) -> String {
         match self {
             ColumnType::Integer => "INTEGER".to_string(),
+            ColumnType::BigInteger => "BIGINT".to_string(),
+            ColumnType::Float => "DOUBLE PRECISION".to_string(),
             ColumnType::String(length) => format!("VARCHAR({})", length),
             ColumnType::Text => "TEXT".to_string(),
             ColumnType::Boolean => "BOOLEAN".to_string(),
             ColumnType::Timestamp => "TIMESTAMP".to_string(),
             ColumnType::Decimal(precision, scale) => format!("DECIMAL({}, {})", precision, scale),
+            ColumnType::Geography(shape) if *driver == DatabaseDriver::Postgres => {
+                format!("GEOGRAPHY({}, {})", shape.as_sql(), WGS84)
+            }
+            ColumnType::Geography(shape) => ColumnType::Geometry(*shape, WGS84).to_sql(driver),
+            ColumnType::Geometry(shape, srid) => match driver {
+                DatabaseDriver::Postgres => format!("GEOMETRY({}, {})", shape.as_sql(), srid),
+                DatabaseDriver::MySql => format!("{} SRID {}", shape.as_sql(), srid),
+                DatabaseDriver::Sqlite => shape.as_sql().to_string(),
+            },
         }
     }
 }
 
 /// Index definition
 #[derive(Debug, Clone)]
-struct IndexDefinition {
+pub(crate) struct IndexDefinition {
     name: String,
-    columns: Vec<String>,
-    unique: bool,
+    pub(crate) columns: Vec<String>,
+    pub(crate) unique: bool,
+    spatial: bool,
+}
+
+impl IndexDefinition {
+    /// The [`TableBuilder`] call adding the index to `table`, e.g.
+    /// `table.index(&["title"], None)`
+    #[cfg(feature = "cli")]
+    pub(crate) fn to_builder_call(&self, table: &str) -> String {
+        let columns = self.columns.iter().map(|column| format!("{:?}", column)).collect::<Vec<_>>().join(", ");
+        if self.spatial {
+            return format!("table.spatial_index(&[{}])", columns);
+        }
+        let (method, suffix) = if self.unique { ("unique_index", "unique") } else { ("index", "index") };
+        let name = if self.name == format!("{}_{}_{}", table, self.columns.join("_"), suffix) {
+            "None".to_string()
+        } else {
+            format!("Some({:?})", self.name)
+        };
+        format!("table.{}(&[{}], {})", method, columns, name)
+    }
 }
 
 /// Foreign key definition
@@ -403,77 +691,502 @@ struct ForeignKeyDefinition {
     on_update: String,
 }
 
+impl ForeignKeyDefinition {
+    fn to_sql(&self) -> String {
+        format!(
+            "FOREIGN KEY ({}) REFERENCES {} ({}) ON DELETE {} ON UPDATE {}",
+            self.column, self.references_table, self.references_column, self.on_delete, self.on_update
+        )
+    }
+}
+
+/// A migration that hasn't run yet and the statements it would execute,
+/// as listed by [`MigrationRunner::pretend`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingMigration {
+    pub name: String,
+    pub statements: Vec<String>,
+}
+
 /// Migration runner
+///
+/// Applied migrations are recorded in the `migrations` table, one batch per
+/// run. [`migrate_isolated`](MigrationRunner::migrate_isolated) holds a
+/// database lock while it runs, so servers deploying at the same time apply
+/// each migration once.
 pub struct MigrationRunner {
     migrations: Vec<Box<dyn Migration>>,
+    table: String,
+    lock_timeout: Duration,
 }
 
 impl MigrationRunner {
     pub fn new() -> Self {
         Self {
             migrations: Vec::new(),
+            table: "migrations".to_string(),
+            lock_timeout: Duration::from_secs(600),
         }
     }
-    
+
+    /// Record applied migrations in `table` instead of `migrations`
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
+
+    /// How long [`migrate_isolated`](MigrationRunner::migrate_isolated)
+    /// waits for another process to finish migrating (10 minutes by default)
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
     /// Add a migration to the runner
     pub fn add_migration(&mut self, migration: Box<dyn Migration>) {
         self.migrations.push(migration);
     }
-    
+
     /// Run all pending migrations
     pub async fn migrate(&self) -> Result<()> {
         // Create migrations table if it doesn't exist
         self.create_migrations_table().await?;
 
-        // Get executed migrations
         let executed = self.get_executed_migrations().await?;
+        let pending = self.pending(&executed);
+        let batch = executed.iter().map(|(_, batch)| *batch).max().unwrap_or(0) + 1;
 
-        // Run pending migrations
-        for migration in &self.migrations {
-            if !executed.contains(&migration.name().to_string()) {
-                println!("Running migration: {}", migration.name());
-                let sql = migration.up_sql();
-                self.execute_sql(&sql).await?;
-                self.record_migration(migration.name()).await?;
-            }
+        for migration in pending {
+            println!("Running migration: {}", migration.name);
+            execute_statements(migration.statements).await?;
+            self.record_migration(&migration.name, batch).await?;
         }
 
         Ok(())
     }
-    
+
+    /// Run all pending migrations while holding a lock on the database:
+    /// an advisory lock on PostgreSQL and MySQL, a row in
+    /// `<table>_lock` on SQLite.
+    ///
+    /// A process that finds the lock taken waits for it, then sees the
+    /// migrations already applied. On SQLite the holder refreshes the row's
+    /// timestamp every few seconds while it migrates, and a row nobody has
+    /// refreshed for a minute is taken to be left by a process killed while
+    /// migrating and is taken over.
+    pub async fn migrate_isolated(&self) -> Result<()> {
+        let mut lock = MigrationLock::acquire(&self.table, self.lock_timeout).await?;
+        let migrate = self.migrate();
+        tokio::pin!(migrate);
+        let result = tokio::select! {
+            result = &mut migrate => result,
+            error = lock.heartbeat() => {
+                eprintln!("⚠️  Couldn't refresh the migration lock, another process may take it over: {}", error);
+                migrate.await
+            }
+        };
+        let released = lock.release().await;
+        result.and(released)
+    }
+
+    /// The migrations [`migrate`](MigrationRunner::migrate) would run and
+    /// the SQL each would execute, for the configured database's driver.
+    /// Nothing is written, not even the migrations table.
+    pub async fn pretend(&self) -> Result<Vec<PendingMigration>> {
+        let executed = if Schema::has_table(&self.table).await? {
+            self.get_executed_migrations().await?
+        } else {
+            Vec::new()
+        };
+        Ok(self.pending(&executed))
+    }
+
+    /// The tables each migration's `up_sql` builds with [`Schema`], in the
+    /// order the migrations were added. Schema changed with raw SQL isn't
+    /// seen.
+    #[cfg(feature = "cli")]
+    pub(crate) fn schema_changes(&self) -> Vec<(String, Vec<SchemaChange>)> {
+        self.migrations
+            .iter()
+            .map(|migration| {
+                RECORDED.with(|recorded| *recorded.borrow_mut() = Some(Vec::new()));
+                migration.up_sql();
+                let changes = RECORDED.with(|recorded| recorded.borrow_mut().take()).unwrap_or_default();
+                (migration.name().to_string(), changes)
+            })
+            .collect()
+    }
+
     /// Rollback the last batch of migrations
     pub async fn rollback(&self) -> Result<()> {
         // Implementation would rollback migrations
         println!("Rolling back migrations...");
         Ok(())
     }
-    
+
+    /// Migrations not in `executed`, in the order they were added
+    fn pending(&self, executed: &[(String, i64)]) -> Vec<PendingMigration> {
+        self.migrations
+            .iter()
+            .filter(|migration| !executed.iter().any(|(name, _)| name == migration.name()))
+            .map(|migration| PendingMigration {
+                name: migration.name().to_string(),
+                statements: split_statements(&migration.up_sql()),
+            })
+            .collect()
+    }
+
     async fn create_migrations_table(&self) -> Result<()> {
-        // Create the migrations table if it doesn't exist
-        println!("Creating migrations table...");
-        Ok(())
+        let create = Schema::create_table(&self.table, |table| {
+            table.id("id");
+            table.string("migration", None);
+            table.big_integer("batch");
+            table.timestamp("executed_at").default("CURRENT_TIMESTAMP");
+        });
+        let sql = create.to_sql().replacen("CREATE TABLE ", "CREATE TABLE IF NOT EXISTS ", 1);
+        execute_statements(vec![sql]).await
     }
-    
-    async fn get_executed_migrations(&self) -> Result<Vec<String>> {
-        // Get list of executed migrations from database
-        Ok(Vec::new())
+
+    /// Applied migrations and their batches, in the order they ran
+    async fn get_executed_migrations(&self) -> Result<Vec<(String, i64)>> {
+        let sql = format!("SELECT migration, batch FROM {} ORDER BY batch, id", self.table);
+        sqlx::query_as::<_, (String, i64)>(&sql)
+            .fetch_all(&try_get_pool()?)
+            .await
+            .map_err(OrmError::Database)
     }
-    
-    async fn record_migration(&self, name: &str) -> Result<()> {
-        // Record migration as executed
-        println!("Recording migration: {}", name);
+
+    async fn record_migration(&self, name: &str, batch: i64) -> Result<()> {
+        let sql = placeholders(&format!("INSERT INTO {} (migration, batch) VALUES (?, ?)", self.table));
+        sqlx::query(&sql)
+            .bind(name.to_string())
+            .bind(batch)
+            .execute(&try_get_pool()?)
+            .await
+            .map_err(OrmError::Database)?;
         Ok(())
     }
+}
+
+/// Split SQL into statements where a `;` ends a line, as the schema
+/// builders join them
+fn split_statements(sql: &str) -> Vec<String> {
+    sql.split(";\n")
+        .map(|statement| statement.trim().trim_end_matches(';').trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Held by [`MigrationRunner::migrate_isolated`] on a connection of its own,
+/// since advisory locks belong to the session that took them
+struct MigrationLock {
+    connection: sqlx::pool::PoolConnection<sqlx::Any>,
+    refresh: Option<String>,
+    release: String,
+    binding: Value,
+}
+
+/// How often the SQLite lock row is refreshed while migrations run
+const SQLITE_LOCK_HEARTBEAT: Duration = Duration::from_secs(10);
+
+/// How long an SQLite lock row can go unrefreshed before its holder is
+/// taken to be dead
+const SQLITE_LOCK_STALE_AFTER: Duration = Duration::from_secs(60);
+
+impl MigrationLock {
+    async fn acquire(table: &str, timeout: Duration) -> Result<Self> {
+        let mut connection = try_get_pool()?.acquire().await.map_err(OrmError::Database)?;
+        let name = format!("torch_migrations:{}", table);
+        let lock_table = format!("{}_lock", table);
+        let (acquire, refresh, release, binding) = match current_driver() {
+            DatabaseDriver::Postgres => (
+                "SELECT CAST(CASE WHEN pg_try_advisory_lock(?) THEN 1 ELSE 0 END AS BIGINT)".to_string(),
+                None,
+                "SELECT pg_advisory_unlock(?)".to_string(),
+                Value::from(advisory_key(&name)),
+            ),
+            DatabaseDriver::MySql => (
+                "SELECT CAST(GET_LOCK(?, 0) AS SIGNED)".to_string(),
+                None,
+                "SELECT RELEASE_LOCK(?)".to_string(),
+                Value::from(name),
+            ),
+            DatabaseDriver::Sqlite => {
+                let [create, acquire, refresh, release] = sqlite_lock_statements(&lock_table, SQLITE_LOCK_STALE_AFTER);
+                sqlx::query(&create).execute(&mut *connection).await.map_err(OrmError::Database)?;
+                (acquire, Some(refresh), release, Value::from(1))
+            }
+        };
+
+        let started = Instant::now();
+        loop {
+            if try_lock(&mut connection, &acquire, &binding).await? {
+                return Ok(Self { connection, refresh, release, binding });
+            }
+            if started.elapsed() >= timeout {
+                return Err(OrmError::Query(format!(
+                    "Timed out after {}s waiting for another process to finish migrating",
+                    timeout.as_secs()
+                )));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Keep an SQLite lock row fresh so it isn't taken for abandoned.
+    /// Runs until refreshing fails; advisory locks need no refreshing and
+    /// never finish.
+    async fn heartbeat(&mut self) -> OrmError {
+        let Some(refresh) = &self.refresh else {
+            return std::future::pending().await;
+        };
+        let sql = placeholders(refresh);
+        loop {
+            tokio::time::sleep(SQLITE_LOCK_HEARTBEAT).await;
+            if let Err(error) = bind_value(sqlx::query(&sql), &self.binding).execute(&mut *self.connection).await {
+                return OrmError::Database(error);
+            }
+        }
+    }
 
-    async fn execute_sql(&self, sql: &str) -> Result<()> {
-        // Execute SQL against the database
-        println!("Executing SQL: {}", sql);
+    async fn release(mut self) -> Result<()> {
+        let sql = placeholders(&self.release);
+        bind_value(sqlx::query(&sql), &self.binding)
+            .execute(&mut *self.connection)
+            .await
+            .map_err(OrmError::Database)?;
         Ok(())
     }
 }
 
+/// Create the SQLite lock table, take the lock, refresh it and give it
+/// back. Taking it inserts the row, or takes over one last refreshed at
+/// least `stale_after` ago, returning nothing while a fresher row holds it.
+fn sqlite_lock_statements(lock_table: &str, stale_after: Duration) -> [String; 4] {
+    [
+        format!("CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, acquired_at BIGINT NOT NULL)", lock_table),
+        format!(
+            "INSERT INTO {table} (id, acquired_at) VALUES (?, CAST(strftime('%s', 'now') AS INTEGER)) \
+             ON CONFLICT (id) DO UPDATE SET acquired_at = excluded.acquired_at \
+             WHERE {table}.acquired_at <= excluded.acquired_at - {stale} RETURNING 1",
+            table = lock_table,
+            stale = stale_after.as_secs(),
+        ),
+        format!("UPDATE {} SET acquired_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = ?", lock_table),
+        format!("DELETE FROM {} WHERE id = ?", lock_table),
+    ]
+}
+
+/// Run the `acquire` statement once, returning whether the lock was taken
+async fn try_lock(connection: &mut sqlx::AnyConnection, acquire: &str, binding: &Value) -> Result<bool> {
+    let sql = placeholders(acquire);
+    let row = bind_value(sqlx::query(&sql), binding)
+        .fetch_optional(connection)
+        .await
+        .map_err(OrmError::Database)?;
+    match row {
+        Some(row) => Ok(sqlx::Row::try_get::<Option<i64>, _>(&row, 0)? == Some(1)),
+        None => Ok(false),
+    }
+}
+
+/// PostgreSQL advisory locks take a 64-bit key; FNV-1a of the lock name
+/// keeps it the same across processes and releases
+fn advisory_key(name: &str) -> i64 {
+    let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    hash as i64
+}
+
 impl Default for MigrationRunner {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_table_sql() {
+        let create = Schema::create_table("comments", |table| {
+            table.id("id");
+            table.big_integer("post_id");
+            table.text("body");
+            table.timestamps();
+            table.foreign_key("post_id", "posts", "id");
+            table.index(&["post_id"], None);
+        });
+
+        assert_eq!(
+            create.to_sql_for(&DatabaseDriver::Postgres),
+            "CREATE TABLE comments (\n  id BIGSERIAL PRIMARY KEY,\n  post_id BIGINT NOT NULL,\n  body TEXT NOT NULL,\n  \
+             created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,\n  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,\n  \
+             FOREIGN KEY (post_id) REFERENCES posts (id) ON DELETE RESTRICT ON UPDATE CASCADE\n);\n\
+             CREATE INDEX comments_post_id_index ON comments (post_id)"
+        );
+        assert!(create.to_sql_for(&DatabaseDriver::Sqlite).contains("id INTEGER PRIMARY KEY AUTOINCREMENT"));
+
+        let keyed = Schema::create_table("orders", |table| {
+            table.ulid_primary("id");
+            table.uuid("customer_id");
+        });
+        assert_eq!(
+            keyed.to_sql_for(&DatabaseDriver::MySql),
+            "CREATE TABLE orders (\n  id VARCHAR(26) PRIMARY KEY NOT NULL,\n  customer_id VARCHAR(36) NOT NULL\n)"
+        );
+    }
+
+    #[test]
+    fn test_alter_and_drop_table_sql() {
+        let add = Schema::alter_table("posts", |table| {
+            table.string("status", Some(20)).default("'draft'");
+        });
+        assert_eq!(
+            add.to_sql_for(&DatabaseDriver::MySql),
+            "ALTER TABLE posts ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'draft'"
+        );
+
+        let remove = Schema::alter_table("posts", |table| {
+            table.drop_column("status");
+        });
+        assert_eq!(remove.to_sql_for(&DatabaseDriver::Postgres), "ALTER TABLE posts DROP COLUMN status");
+
+        assert_eq!(Schema::drop_table("posts").to_sql(), "DROP TABLE IF EXISTS posts");
+    }
+
+    struct CreatePosts;
+
+    impl Migration for CreatePosts {
+        fn name(&self) -> &str {
+            "2024_02_01_000000_create_posts_table"
+        }
+
+        fn version(&self) -> &str {
+            "2024_02_01_000000"
+        }
+
+        fn up_sql(&self) -> String {
+            Schema::create_table("posts", |table| {
+                table.id("id");
+                table.string("slug", None);
+                table.unique_index(&["slug"], None);
+            })
+            // Other tests in this binary install a SQLite pool, which would change the current driver
+            .to_sql_for(&DatabaseDriver::Postgres)
+        }
+
+        fn down_sql(&self) -> String {
+            Schema::drop_table("posts").to_sql()
+        }
+    }
+
+    struct Backfill;
+
+    impl Migration for Backfill {
+        fn name(&self) -> &str {
+            "2024_03_01_000000_backfill_slugs"
+        }
+
+        fn version(&self) -> &str {
+            "2024_03_01_000000"
+        }
+
+        fn up_sql(&self) -> String {
+            "UPDATE posts SET slug = id;\nUPDATE posts SET slug = LOWER(slug);\n".to_string()
+        }
+
+        fn down_sql(&self) -> String {
+            String::new()
+        }
+    }
+
+    #[test]
+    fn test_pending_migrations_and_their_statements() {
+        let mut runner = MigrationRunner::new();
+        runner.add_migration(Box::new(CreatePosts));
+        runner.add_migration(Box::new(Backfill));
+
+        assert_eq!(
+            runner.pending(&[]),
+            vec![
+                PendingMigration {
+                    name: "2024_02_01_000000_create_posts_table".to_string(),
+                    statements: vec![
+                        "CREATE TABLE posts (\n  id BIGSERIAL PRIMARY KEY,\n  slug VARCHAR(255) NOT NULL\n)".to_string(),
+                        "CREATE UNIQUE INDEX posts_slug_unique ON posts (slug)".to_string(),
+                    ],
+                },
+                PendingMigration {
+                    name: "2024_03_01_000000_backfill_slugs".to_string(),
+                    statements: vec!["UPDATE posts SET slug = id".to_string(), "UPDATE posts SET slug = LOWER(slug)".to_string()],
+                },
+            ]
+        );
+
+        let executed = vec![("2024_02_01_000000_create_posts_table".to_string(), 1)];
+        let pending: Vec<String> = runner.pending(&executed).into_iter().map(|migration| migration.name).collect();
+        assert_eq!(pending, vec!["2024_03_01_000000_backfill_slugs"]);
+        assert_ne!(advisory_key("torch_migrations:migrations"), advisory_key("torch_migrations:other"));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_lock_is_taken_once_until_it_goes_stale() {
+        use sqlx::Connection;
+
+        sqlx::any::install_default_drivers();
+        let mut connection = sqlx::AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let [create, acquire, refresh, release] = sqlite_lock_statements("migrations_lock", SQLITE_LOCK_STALE_AFTER);
+        sqlx::query(&create).execute(&mut connection).await.unwrap();
+        let id = Value::from(1);
+        let age = |seconds: u64| format!("UPDATE migrations_lock SET acquired_at = acquired_at - {}", seconds);
+
+        assert!(try_lock(&mut connection, &acquire, &id).await.unwrap());
+        assert!(!try_lock(&mut connection, &acquire, &id).await.unwrap());
+
+        // A holder still migrating past the stale threshold keeps the lock
+        // as long as it refreshes the row
+        assert!(SQLITE_LOCK_HEARTBEAT * 3 <= SQLITE_LOCK_STALE_AFTER);
+        for _ in 0..3 {
+            sqlx::query(&age(SQLITE_LOCK_STALE_AFTER.as_secs() - 5)).execute(&mut connection).await.unwrap();
+            assert!(!try_lock(&mut connection, &acquire, &id).await.unwrap());
+            bind_value(sqlx::query(&refresh), &id).execute(&mut connection).await.unwrap();
+        }
+        assert!(!try_lock(&mut connection, &acquire, &id).await.unwrap());
+
+        // A row left by a process that stopped refreshing it is taken over
+        sqlx::query(&age(SQLITE_LOCK_STALE_AFTER.as_secs() + 1))
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        assert!(try_lock(&mut connection, &acquire, &id).await.unwrap());
+        assert!(!try_lock(&mut connection, &acquire, &id).await.unwrap());
+
+        bind_value(sqlx::query(&release), &id).execute(&mut connection).await.unwrap();
+        assert!(try_lock(&mut connection, &acquire, &id).await.unwrap());
+
+        // Other failures aren't mistaken for a held lock
+        sqlx::query("DROP TABLE migrations_lock").execute(&mut connection).await.unwrap();
+        assert!(try_lock(&mut connection, &acquire, &id).await.is_err());
+    }
+
+    #[test]
+    fn test_spatial_columns_sql() {
+        let create = Schema::create_table("stores", |table| {
+            table.point("location");
+            table.geometry("footprint", GeometryType::Polygon, 27700).nullable();
+            table.spatial_index(&["location"]);
+        });
+
+        assert_eq!(
+            create.to_sql_for(&DatabaseDriver::Postgres),
+            "CREATE TABLE stores (\n  location GEOGRAPHY(POINT, 4326) NOT NULL,\n  footprint GEOMETRY(POLYGON, 27700)\n);\n\
+             CREATE INDEX stores_location_spatial ON stores USING GIST (location)"
+        );
+        assert_eq!(
+            create.to_sql_for(&DatabaseDriver::MySql),
+            "CREATE TABLE stores (\n  location POINT SRID 4326 NOT NULL,\n  footprint POLYGON SRID 27700\n);\n\
+             CREATE SPATIAL INDEX stores_location_spatial ON stores (location)"
+        );
+    }
+}
@@ -74,9 +74,12 @@ use sqlx::{FromRow, Any};
 use std::collections::HashMap;
 use std::fmt::Debug;
 
-use crate::orm::{OrmError, Result};
-use crate::orm::query::QueryBuilder;
-use crate::orm::connection::get_pool;
+use crate::orm::{DatabaseDriver, OrmError, Result};
+use crate::orm::cache::QueryCache;
+use crate::orm::query::{GlobalScope, QueryBuilder};
+use crate::orm::serialization::Serialized;
+use crate::orm::chunk::ChunkProgress;
+use crate::orm::connection::{bind_value, driver, try_get_pool, placeholders};
 
 /// State of a model instance
 #[derive(Debug, Clone, PartialEq)]
@@ -116,7 +119,8 @@ pub trait Model:
     Clone +
     'static
 {
-    /// The primary key type (usually i32 or i64)
+    /// The primary key type (usually i32 or i64, or a
+    /// [`Uuid`](crate::orm::keys::Uuid) or [`Ulid`](crate::orm::keys::Ulid))
     type PrimaryKey: Clone + Send + Sync + Debug + Serialize + for<'de> Deserialize<'de> + 'static;
     
     /// Get the table name for this model
@@ -132,6 +136,13 @@ pub trait Model:
     
     /// Set the primary key value
     fn set_id(&mut self, id: Self::PrimaryKey);
+
+    /// A key for a new model, set just before it is created when it has no
+    /// id yet. Return one for UUID or ULID keys; integer keys are left to the
+    /// database.
+    fn generate_key() -> Option<Self::PrimaryKey> {
+        None
+    }
     
     /// Get the current state of the model
     fn state(&self) -> ModelState;
@@ -200,17 +211,27 @@ pub trait Model:
         self.before_save().await?;
         
         if self.is_new() {
+            if self.id().is_none() {
+                if let Some(key) = Self::generate_key() {
+                    self.set_id(key);
+                }
+            }
             self.before_create().await?;
             self.create_in_database().await?;
+            QueryCache::invalidate(Self::table_name()).await;
             self.after_create().await?;
         } else {
             self.before_update().await?;
             self.update_in_database().await?;
+            QueryCache::invalidate(Self::table_name()).await;
             self.after_update().await?;
         }
-        
-        self.after_save().await?;
-        Ok(())
+
+        // The row is written either way, so index it even if the hook fails
+        let after_save = self.after_save().await;
+        #[cfg(feature = "search")]
+        crate::search::saved(&*self).await;
+        after_save
     }
     
     /// Delete the model from the database
@@ -221,9 +242,13 @@ pub trait Model:
 
         self.before_delete().await?;
 
-        // For now, just mark as deleted without actual database operation
-        println!("Delete query would execute for table: {}", Self::table_name());
+        let id = serde_json::to_value(self.id())?;
+        let sql = placeholders(&format!("DELETE FROM {} WHERE {} = ?", Self::table_name(), Self::primary_key()));
+        bind_value(sqlx::query(&sql), &id).execute(&try_get_pool()?).await?;
         self.set_state(ModelState::Deleted);
+        QueryCache::invalidate(Self::table_name()).await;
+        #[cfg(feature = "search")]
+        crate::search::deleted(&*self).await;
         self.after_delete().await?;
 
         Ok(())
@@ -235,12 +260,10 @@ pub trait Model:
     /// Update the model in the database
     async fn update_in_database(&mut self) -> Result<()>;
     
-    /// Find a model by its primary key
-    async fn find(_id: Self::PrimaryKey) -> Result<Option<Self>> {
-        // For now, return None as a placeholder
-        // In a real implementation, this would query the database
-        println!("Find query would execute for table: {}", Self::table_name());
-        Ok(None)
+    /// Find a model by its primary key, whatever its type
+    async fn find(id: Self::PrimaryKey) -> Result<Option<Self>> {
+        let id = serde_json::to_value(id)?;
+        Self::query().where_eq(Self::primary_key(), id).first().await
     }
     
     /// Find a model by its primary key or return an error if not found
@@ -253,9 +276,26 @@ pub trait Model:
         Self::query().get().await
     }
     
+    /// Constraints added to every query for this model, such as hiding
+    /// soft-deleted rows or filtering by tenant. Turn one off for a single
+    /// query with [`QueryBuilder::without_global_scope`].
+    fn global_scopes() -> Vec<GlobalScope<Self>> {
+        Vec::new()
+    }
+
+    /// Columns loaded into the model; when empty, every column is. List them
+    /// when the table has columns the model doesn't read, such as timestamps
+    fn columns() -> &'static [&'static str] {
+        &[]
+    }
+
     /// Create a new query builder for this model
     fn query() -> QueryBuilder<Self> {
-        QueryBuilder::new(Self::table_name())
+        let query = QueryBuilder::new(Self::table_name());
+        match Self::columns() {
+            [] => query,
+            columns => query.select(columns.to_vec()),
+        }
     }
     
     /// Create a new model instance and save it to the database
@@ -284,6 +324,34 @@ pub trait Model:
         }
     }
 
+    /// Attributes left out when the model is serialized for output, such as
+    /// password hashes and tokens
+    fn hidden() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Attributes included when the model is serialized for output; when
+    /// empty, every attribute that isn't [`hidden`](Model::hidden) is
+    fn visible() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Computed attributes added when the model is serialized for output
+    fn appends(&self) -> serde_json::Map<String, serde_json::Value> {
+        serde_json::Map::new()
+    }
+
+    /// The model as it should appear in responses, shaped further with
+    /// [`Serialized::only`] and friends
+    fn serialized(&self) -> Serialized<'_, Self> {
+        Serialized::new(self)
+    }
+
+    /// The model as it should appear in responses
+    fn to_json(&self) -> Result<serde_json::Value> {
+        self.serialized().to_value()
+    }
+
     /// Add where clause methods for common queries
     fn where_column(column: &str, value: serde_json::Value) -> QueryBuilder<Self> {
         Self::query().where_eq(column, value)
@@ -298,4 +366,102 @@ pub trait Model:
     async fn count() -> Result<i64> {
         Self::query().count().await
     }
+
+    /// Run `callback` on every model in batches of `size`, see
+    /// [`QueryBuilder::chunk`]
+    async fn chunk<F, Fut>(size: u32, callback: F) -> Result<ChunkProgress>
+    where
+        F: FnMut(Vec<Self>) -> Fut + Send,
+        Fut: std::future::Future<Output = Result<()>> + Send,
+    {
+        Self::query().chunk(size, callback).await
+    }
+
+    /// Run `callback` on every model in batches of `size`, paging by primary
+    /// key, see [`QueryBuilder::chunk_by_id`]
+    async fn chunk_by_id<F, Fut>(size: u32, callback: F) -> Result<ChunkProgress>
+    where
+        F: FnMut(Vec<Self>) -> Fut + Send,
+        Fut: std::future::Future<Output = Result<()>> + Send,
+    {
+        Self::query().chunk_by_id(size, callback).await
+    }
+}
+
+/// A model's attributes as `(column, value)` pairs in column order
+fn columns<M: Model>(model: &M) -> Result<Vec<(String, serde_json::Value)>> {
+    let mut columns: Vec<_> = model.to_attributes()?.into_iter().collect();
+    columns.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(columns)
+}
+
+/// Insert `model` into its table, one column per serialized attribute.
+///
+/// `null` attributes are left out so column defaults apply, and an id the
+/// database assigns is set on the model. Used by [`impl_model!`](crate::impl_model).
+#[doc(hidden)]
+pub async fn insert_attributes<M: Model>(model: &mut M) -> Result<()> {
+    let columns: Vec<_> = columns(model)?.into_iter().filter(|(_, value)| !value.is_null()).collect();
+    let mysql = driver() == Some(&DatabaseDriver::MySql);
+
+    let mut sql = if columns.is_empty() {
+        match mysql {
+            true => format!("INSERT INTO {} () VALUES ()", M::table_name()),
+            false => format!("INSERT INTO {} DEFAULT VALUES", M::table_name()),
+        }
+    } else {
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            M::table_name(),
+            columns.iter().map(|(column, _)| column.as_str()).collect::<Vec<_>>().join(", "),
+            vec!["?"; columns.len()].join(", "),
+        )
+    };
+    // MySQL reports the new id itself, the others only through RETURNING
+    let returning = !mysql && model.id().is_none();
+    if returning {
+        sql.push_str(&format!(" RETURNING {}", M::primary_key()));
+    }
+
+    let sql = placeholders(&sql);
+    let mut query = sqlx::query(&sql);
+    for (_, value) in &columns {
+        query = bind_value(query, value);
+    }
+    let id = if returning {
+        let row = query.fetch_one(&try_get_pool()?).await?;
+        Some(sqlx::Row::try_get::<i64, _>(&row, 0)?)
+    } else {
+        query.execute(&try_get_pool()?).await?.last_insert_id()
+    };
+
+    if let (None, Some(id)) = (model.id(), id) {
+        model.set_id(serde_json::from_value(serde_json::Value::from(id))?);
+    }
+    model.set_state(ModelState::Persisted);
+    Ok(())
+}
+
+/// Write every attribute of `model` except its primary key back to its row.
+/// Used by [`impl_model!`](crate::impl_model).
+#[doc(hidden)]
+pub async fn update_attributes<M: Model>(model: &M) -> Result<()> {
+    let id = serde_json::to_value(model.id().ok_or(OrmError::ModelNotFound)?)?;
+    let columns: Vec<_> = columns(model)?.into_iter().filter(|(column, _)| column != M::primary_key()).collect();
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let sql = placeholders(&format!(
+        "UPDATE {} SET {} WHERE {} = ?",
+        M::table_name(),
+        columns.iter().map(|(column, _)| format!("{} = ?", column)).collect::<Vec<_>>().join(", "),
+        M::primary_key(),
+    ));
+    let mut query = sqlx::query(&sql);
+    for (_, value) in &columns {
+        query = bind_value(query, value);
+    }
+    bind_value(query, &id).execute(&try_get_pool()?).await?;
+    Ok(())
 }
@@ -44,15 +44,31 @@
 //! let avg_age = User::query()
 //!     .avg("age")
 //!     .await?;
+//!
+//! // Search, written for whichever database is configured
+//! let results = Post::query()
+//!     .where_fts_columns(&["title", "body"], "rust web")
+//!     .where_json("settings->visibility", "public")
+//!     .where_json_contains("metadata", json!({"tags": ["rust"]}))
+//!     .get()
+//!     .await?;
 //! ```
 
 use serde_json::Value;
 use sqlx::{Any};
+use std::future::Future;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::orm::{OrmError, Result};
-use crate::orm::connection::get_pool;
+use crate::extractors::ListParams;
+use crate::orm::cache;
+use crate::orm::chunk::{ChunkProgress, Chunks};
+use crate::orm::{DatabaseDriver, OrmError, Result};
+use crate::orm::connection::try_get_pool;
+use crate::orm::connection::{bind_value, driver, placeholders};
 use crate::orm::model::{Model, ModelState};
+use crate::orm::spatial::{Point, Polygon, WGS84};
 
 /// Represents a WHERE clause condition
 #[derive(Debug, Clone)]
@@ -83,6 +99,16 @@ pub enum WhereClause {
     IsNotNull(String),
     /// column BETWEEN value1 AND value2
     Between(String, Value, Value),
+    /// Full-text match of the search terms against the columns
+    FullText(Vec<String>, String),
+    /// JSON column contains the given JSON document
+    JsonContains(String, Value),
+    /// Value at a path inside a JSON column = value
+    JsonPath(String, Vec<String>, Value),
+    /// Spatial column within a distance in meters of a point
+    WithinRadius(String, Point, f64),
+    /// Spatial column inside a polygon
+    WithinPolygon(String, Polygon),
     /// Raw SQL condition
     Raw(String, Vec<Value>),
 }
@@ -94,8 +120,15 @@ pub struct OrderBy {
     pub direction: String,
 }
 
+/// Query parameter holding the page number in page links
+pub const PAGE_PARAM: &str = "page";
+
 /// Pagination result
-#[derive(Debug, Clone)]
+///
+/// Pass it to a template with `serde_json::to_value` and render its links
+/// with `@pagination($posts)`. Call [`with_request`](Paginated::with_request)
+/// first so the links keep the request's other query parameters.
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Paginated<T> {
     pub data: Vec<T>,
     pub current_page: u32,
@@ -104,6 +137,78 @@ pub struct Paginated<T> {
     pub last_page: u32,
     pub from: Option<u32>,
     pub to: Option<u32>,
+    /// Path page links point at; empty links are relative to the current page
+    pub path: String,
+    /// Query string kept in page links, without the page number
+    pub query: String,
+}
+
+impl<T> Paginated<T> {
+    /// Point page links at the request's path and keep its query
+    /// parameters, such as filters and sorting
+    pub fn with_request(mut self, req: &crate::Request) -> Self {
+        self.path = req.path().to_string();
+        self.query = req
+            .query_string()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty() && pair.split('=').next() != Some(PAGE_PARAM))
+            .collect::<Vec<_>>()
+            .join("&");
+        self
+    }
+
+    pub fn has_more_pages(&self) -> bool {
+        self.current_page < self.last_page
+    }
+}
+
+/// A named constraint added to every query for a model, returned from
+/// [`Model::global_scopes`]
+///
+/// ```rust,ignore
+/// fn global_scopes() -> Vec<GlobalScope<Self>> {
+///     vec![
+///         GlobalScope::new("not_deleted", |query| query.where_null("deleted_at")),
+///         GlobalScope::new("tenant", |query| query.where_eq("tenant_id", current_tenant_id())),
+///     ]
+/// }
+/// ```
+pub struct GlobalScope<T: Model> {
+    name: &'static str,
+    apply: Arc<dyn Fn(QueryBuilder<T>) -> QueryBuilder<T> + Send + Sync>,
+}
+
+impl<T: Model> GlobalScope<T> {
+    pub fn new<F>(name: &'static str, apply: F) -> Self
+    where
+        F: Fn(QueryBuilder<T>) -> QueryBuilder<T> + Send + Sync + 'static,
+    {
+        Self {
+            name,
+            apply: Arc::new(apply),
+        }
+    }
+
+    /// Name used to turn the scope off with [`QueryBuilder::without_global_scope`]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T: Model> Clone for GlobalScope<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            apply: self.apply.clone(),
+        }
+    }
+}
+
+impl<T: Model> std::fmt::Debug for GlobalScope<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GlobalScope").field("name", &self.name).finish_non_exhaustive()
+    }
 }
 
 /// Fluent query builder for constructing database queries
@@ -119,6 +224,15 @@ pub struct QueryBuilder<T: Model> {
     group_by_columns: Vec<String>,
     #[allow(dead_code)]
     having_clauses: Vec<WhereClause>,
+    /// Global scopes turned off for this query
+    removed_scopes: Vec<String>,
+    without_scopes: bool,
+    /// SQL dialect, when not the configured database's
+    dialect: Option<DatabaseDriver>,
+    /// Query cache TTL for this query, instead of the configured one
+    cache_ttl: Option<Duration>,
+    /// Read from the database even when the query cache is on
+    fresh: bool,
     _phantom: PhantomData<T>,
 }
 
@@ -135,6 +249,11 @@ impl<T: Model> QueryBuilder<T> {
             with_relations: Vec::new(),
             group_by_columns: Vec::new(),
             having_clauses: Vec::new(),
+            removed_scopes: Vec::new(),
+            without_scopes: false,
+            dialect: None,
+            cache_ttl: None,
+            fresh: false,
             _phantom: PhantomData,
         }
     }
@@ -220,6 +339,103 @@ impl<T: Model> QueryBuilder<T> {
         self
     }
     
+    /// Add a full-text search of `column` for `terms`
+    ///
+    /// Uses `to_tsvector(...) @@ websearch_to_tsquery(...)` on PostgreSQL, so
+    /// quoted phrases, `or` and `-excluded` words work, and `MATCH ... AGAINST`
+    /// on MySQL, which needs a `FULLTEXT` index on the column. SQLite falls
+    /// back to `LIKE` on the whole search string.
+    pub fn where_fts(self, column: &str, terms: &str) -> Self {
+        self.where_fts_columns(&[column], terms)
+    }
+
+    /// Add a full-text search of several columns at once; on MySQL they
+    /// need a `FULLTEXT` index covering exactly these columns
+    pub fn where_fts_columns(mut self, columns: &[&str], terms: &str) -> Self {
+        if !columns.is_empty() && columns.iter().all(|column| Self::is_safe_column_name(column)) {
+            let columns = columns.iter().map(|column| column.to_string()).collect();
+            self.where_clauses.push(WhereClause::FullText(columns, terms.to_string()));
+        }
+        self
+    }
+
+    /// Add a WHERE clause matching rows whose JSON `column` contains
+    /// `document`, e.g. `json!({"tags": ["rust"]})` matches any row with
+    /// `"rust"` among its tags
+    pub fn where_json_contains(mut self, column: &str, document: impl Into<Value>) -> Self {
+        if Self::is_safe_column_name(column) {
+            self.where_clauses.push(WhereClause::JsonContains(column.to_string(), document.into()));
+        }
+        self
+    }
+
+    /// Add a WHERE clause on a value inside a JSON column, addressed as
+    /// `column->key->key`, e.g. `where_json("settings->theme", "dark")`.
+    /// Numeric keys index into arrays.
+    pub fn where_json(mut self, path: &str, value: impl Into<Value>) -> Self {
+        let mut segments = path.split("->").map(str::trim);
+        let column = segments.next().unwrap_or_default();
+        let keys: Vec<String> = segments.map(str::to_string).collect();
+        let keys_are_safe = keys.iter().all(|key| !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_'));
+        if Self::is_safe_column_name(column) && !keys.is_empty() && keys_are_safe {
+            self.where_clauses.push(WhereClause::JsonPath(column.to_string(), keys, value.into()));
+        }
+        self
+    }
+
+    /// Add a WHERE clause matching rows whose spatial `column` lies within
+    /// `meters` of the point, measured on the globe
+    pub fn where_within_radius(mut self, column: &str, lat: f64, lng: f64, meters: f64) -> Self {
+        if Self::is_safe_column_name(column) {
+            self.where_clauses.push(WhereClause::WithinRadius(column.to_string(), Point::new(lat, lng), meters));
+        }
+        self
+    }
+
+    /// Add a WHERE clause matching rows whose spatial `column` lies inside
+    /// `area`, e.g. a delivery zone
+    pub fn where_within_polygon(mut self, column: &str, area: &Polygon) -> Self {
+        if Self::is_safe_column_name(column) {
+            self.where_clauses.push(WhereClause::WithinPolygon(column.to_string(), area.clone()));
+        }
+        self
+    }
+
+    /// Order by distance from the point, nearest first. The SQL is written
+    /// for the dialect set when this is called.
+    pub fn order_by_distance(self, column: &str, lat: f64, lng: f64) -> Self {
+        if !Self::is_safe_column_name(column) || !lat.is_finite() || !lng.is_finite() {
+            return self;
+        }
+        let distance = match self.driver() {
+            DatabaseDriver::Postgres => format!(
+                "ST_Distance({}::geography, ST_SetSRID(ST_MakePoint({}, {}), {})::geography)",
+                column, lng, lat, WGS84
+            ),
+            DatabaseDriver::MySql => format!(
+                "ST_Distance_Sphere({}, ST_GeomFromText('{}', {}, 'axis-order=long-lat'))",
+                column,
+                Point::new(lat, lng).to_wkt(),
+                WGS84
+            ),
+            DatabaseDriver::Sqlite => {
+                format!("ST_Distance(GeomFromText({0}, {1}), MakePoint({2}, {3}, {1}), 1)", column, WGS84, lng, lat)
+            }
+        };
+        self.order_by(&distance, "ASC")
+    }
+
+    /// Build SQL for `driver` instead of the configured database
+    pub fn dialect(mut self, driver: DatabaseDriver) -> Self {
+        self.dialect = Some(driver);
+        self
+    }
+
+    /// Dialect the SQL is written in, PostgreSQL unless configured otherwise
+    fn driver(&self) -> DatabaseDriver {
+        self.dialect.clone().or_else(|| driver().cloned()).unwrap_or(DatabaseDriver::Postgres)
+    }
+
     /// Add an ORDER BY clause
     pub fn order_by(mut self, column: &str, direction: &str) -> Self {
         self.order_by.push(OrderBy {
@@ -239,6 +455,17 @@ impl<T: Model> QueryBuilder<T> {
         self.order_by(column, "DESC")
     }
     
+    /// Remove every ORDER BY clause
+    pub fn reorder(mut self) -> Self {
+        self.order_by.clear();
+        self
+    }
+
+    /// Whether the query has an ORDER BY clause
+    pub fn is_ordered(&self) -> bool {
+        !self.order_by.is_empty()
+    }
+
     /// Set the LIMIT
     pub fn limit(mut self, limit: u32) -> Self {
         self.limit_value = Some(limit);
@@ -269,12 +496,83 @@ impl<T: Model> QueryBuilder<T> {
         self
     }
     
+    /// Skip the model's global scope called `name` for this query
+    pub fn without_global_scope(mut self, name: &str) -> Self {
+        self.removed_scopes.push(name.to_string());
+        self
+    }
+
+    /// Skip all of the model's global scopes for this query
+    pub fn without_global_scopes(mut self) -> Self {
+        self.without_scopes = true;
+        self
+    }
+
+    /// Keep this query's results in the [query cache](crate::orm::cache)
+    /// for `ttl` instead of the configured TTL. Has no effect while the
+    /// cache is off.
+    pub fn remember(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Read from the database even when the [query cache](crate::orm::cache)
+    /// is on, as queries that read other tables through `where_raw` should
+    pub fn fresh(mut self) -> Self {
+        self.fresh = true;
+        self
+    }
+
+    /// How long this query's results are cached, if at all
+    fn cache_for(&self) -> Option<Duration> {
+        if self.fresh {
+            return None;
+        }
+        cache::ttl(self.cache_ttl)
+    }
+
+    /// The query with the model's global scopes applied
+    fn scoped(&self) -> Self {
+        let mut query = self.clone();
+        if self.without_scopes {
+            return query;
+        }
+        query.without_scopes = true;
+        for scope in T::global_scopes() {
+            if !self.removed_scopes.iter().any(|name| name == scope.name) {
+                query = (scope.apply)(query);
+            }
+        }
+        query
+    }
+
+    /// The SELECT statement this query runs, with its bindings
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        self.build_select_query()
+    }
+
     /// Execute the query and return all matching models
     pub async fn get(self) -> Result<Vec<T>> {
-        // For now, return an empty vector as a placeholder
-        // In a real implementation, this would execute the query
-        println!("Query would execute: {}", self.build_select_query().0);
-        Ok(Vec::new())
+        let (sql, bindings) = self.build_select_query();
+        let fetch = async {
+            let sql = placeholders(&sql);
+            let mut query = sqlx::query(&sql);
+            for binding in &bindings {
+                query = bind_value(query, binding);
+            }
+            let started = std::time::Instant::now();
+            let rows = query.fetch_all(&try_get_pool()?).await?;
+            #[cfg(feature = "json")]
+            crate::dev::inspector::record_query(&sql, bindings.len(), started.elapsed());
+            #[cfg(feature = "otel")]
+            crate::otel::record_query(&sql, started.elapsed());
+            rows.iter().map(|row| Ok(T::from_row(row)?)).collect::<Result<Vec<T>>>()
+        };
+        let mut models = cache::remember(&self.table, &sql, &bindings, self.cache_for(), fetch).await?;
+        for model in &mut models {
+            model.set_state(ModelState::Persisted);
+        }
+        Ok(models)
     }
     
     /// Execute the query and return the first matching model
@@ -290,11 +588,49 @@ impl<T: Model> QueryBuilder<T> {
 
     /// Count the number of matching records
     pub async fn count(self) -> Result<i64> {
-        // For now, return 0 as a placeholder
-        println!("Count query would execute: {}", self.build_count_query().0);
-        Ok(0)
+        let (sql, bindings) = self.build_count_query();
+        let fetch = async {
+            let sql = placeholders(&sql);
+            let mut query = sqlx::query(&sql);
+            for binding in &bindings {
+                query = bind_value(query, binding);
+            }
+            let started = std::time::Instant::now();
+            let row = query.fetch_one(&try_get_pool()?).await?;
+            #[cfg(feature = "json")]
+            crate::dev::inspector::record_query(&sql, bindings.len(), started.elapsed());
+            #[cfg(feature = "otel")]
+            crate::otel::record_query(&sql, started.elapsed());
+            Ok(sqlx::Row::try_get::<i64, _>(&row, 0)?)
+        };
+        cache::remember(&self.table, &sql, &bindings, self.cache_for(), fetch).await
     }
     
+    /// Process the results in batches of `size`, see [`Chunks`]
+    pub fn chunks(self, size: u32) -> Chunks<T> {
+        Chunks::new(self, size)
+    }
+
+    /// Run `callback` on the results in batches of `size`, paging with
+    /// LIMIT/OFFSET
+    pub async fn chunk<F, Fut>(self, size: u32, callback: F) -> Result<ChunkProgress>
+    where
+        F: FnMut(Vec<T>) -> Fut + Send,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        self.chunks(size).each(callback).await
+    }
+
+    /// Run `callback` on the results in batches of `size`, paging by primary
+    /// key so the callback can safely update or delete the rows
+    pub async fn chunk_by_id<F, Fut>(self, size: u32, callback: F) -> Result<ChunkProgress>
+    where
+        F: FnMut(Vec<T>) -> Fut + Send,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        self.chunks(size).by_id().each(callback).await
+    }
+
     /// Paginate the results
     pub async fn paginate(self, page: u32, per_page: u32) -> Result<Paginated<T>> {
         let total = self.clone().count().await?;
@@ -318,41 +654,79 @@ impl<T: Model> QueryBuilder<T> {
             last_page,
             from,
             to,
+            path: String::new(),
+            query: String::new(),
         })
     }
     
+    /// Apply a listing request's filters and sort order, replacing any
+    /// order already set
+    ///
+    /// A filter value with commas matches any of its parts. Validate the
+    /// fields with [`ListParams::allow_sort`] and
+    /// [`ListParams::allow_filters`] first so clients can't reach columns
+    /// they shouldn't.
+    pub fn filter_and_sort(mut self, params: &ListParams) -> Self {
+        for (field, value) in &params.filters {
+            self = if value.contains(',') {
+                self.where_in(field, value.split(',').map(str::to_string).collect())
+            } else {
+                self.where_eq(field, value.as_str())
+            };
+        }
+        if !params.sort.is_empty() {
+            self = self.reorder();
+            for sort in &params.sort {
+                self = self.order_by(&sort.field, if sort.descending { "DESC" } else { "ASC" });
+            }
+        }
+        self
+    }
+
+    /// [`filter_and_sort`](Self::filter_and_sort) plus the LIMIT and OFFSET
+    /// of the requested page
+    pub fn list(self, params: &ListParams) -> Self {
+        self.filter_and_sort(params).limit(params.per_page).offset(params.offset())
+    }
+
+    /// Paginate the results the way a listing request asked for
+    pub async fn paginate_list(self, params: &ListParams) -> Result<Paginated<T>> {
+        self.filter_and_sort(params).paginate(params.page, params.per_page).await
+    }
+
     /// Build the SELECT SQL query
     fn build_select_query(&self) -> (String, Vec<Value>) {
-        let mut sql = format!("SELECT {} FROM {}", self.select_columns.join(", "), self.table);
+        let query = &self.scoped();
+        let mut sql = format!("SELECT {} FROM {}", query.select_columns.join(", "), query.table);
         let mut bindings = Vec::new();
         
-        if !self.where_clauses.is_empty() {
+        if !query.where_clauses.is_empty() {
             sql.push_str(" WHERE ");
-            let where_parts: Vec<String> = self.where_clauses.iter().map(|clause| {
-                let (clause_sql, mut clause_bindings) = build_where_clause(clause);
+            let where_parts: Vec<String> = query.where_clauses.iter().map(|clause| {
+                let (clause_sql, mut clause_bindings) = build_where_clause(clause, &query.driver());
                 bindings.append(&mut clause_bindings);
                 clause_sql
             }).collect();
             sql.push_str(&where_parts.join(" AND "));
         }
         
-        if !self.group_by_columns.is_empty() {
-            sql.push_str(&format!(" GROUP BY {}", self.group_by_columns.join(", ")));
+        if !query.group_by_columns.is_empty() {
+            sql.push_str(&format!(" GROUP BY {}", query.group_by_columns.join(", ")));
         }
         
-        if !self.order_by.is_empty() {
+        if !query.order_by.is_empty() {
             sql.push_str(" ORDER BY ");
-            let order_parts: Vec<String> = self.order_by.iter().map(|order| {
+            let order_parts: Vec<String> = query.order_by.iter().map(|order| {
                 format!("{} {}", order.column, order.direction)
             }).collect();
             sql.push_str(&order_parts.join(", "));
         }
         
-        if let Some(limit) = self.limit_value {
+        if let Some(limit) = query.limit_value {
             sql.push_str(&format!(" LIMIT {}", limit));
         }
         
-        if let Some(offset) = self.offset_value {
+        if let Some(offset) = query.offset_value {
             sql.push_str(&format!(" OFFSET {}", offset));
         }
         
@@ -361,13 +735,14 @@ impl<T: Model> QueryBuilder<T> {
     
     /// Build the COUNT SQL query
     fn build_count_query(&self) -> (String, Vec<Value>) {
-        let mut sql = format!("SELECT COUNT(*) FROM {}", self.table);
+        let query = &self.scoped();
+        let mut sql = format!("SELECT COUNT(*) FROM {}", query.table);
         let mut bindings = Vec::new();
         
-        if !self.where_clauses.is_empty() {
+        if !query.where_clauses.is_empty() {
             sql.push_str(" WHERE ");
-            let where_parts: Vec<String> = self.where_clauses.iter().map(|clause| {
-                let (clause_sql, mut clause_bindings) = build_where_clause(clause);
+            let where_parts: Vec<String> = query.where_clauses.iter().map(|clause| {
+                let (clause_sql, mut clause_bindings) = build_where_clause(clause, &query.driver());
                 bindings.append(&mut clause_bindings);
                 clause_sql
             }).collect();
@@ -390,7 +765,7 @@ impl<T: Model> QueryBuilder<T> {
 }
 
 /// Build SQL and bindings for a WHERE clause
-fn build_where_clause(clause: &WhereClause) -> (String, Vec<Value>) {
+fn build_where_clause(clause: &WhereClause, driver: &DatabaseDriver) -> (String, Vec<Value>) {
     match clause {
         WhereClause::Eq(column, value) => (format!("{} = ?", column), vec![value.clone()]),
         WhereClause::NotEq(column, value) => (format!("{} != ?", column), vec![value.clone()]),
@@ -413,9 +788,297 @@ fn build_where_clause(clause: &WhereClause) -> (String, Vec<Value>) {
         WhereClause::Between(column, min, max) => {
             (format!("{} BETWEEN ? AND ?", column), vec![min.clone(), max.clone()])
         },
+        WhereClause::FullText(columns, terms) => build_full_text(columns, terms, driver),
+        WhereClause::JsonContains(column, document) => build_json_contains(column, document, driver),
+        WhereClause::JsonPath(column, keys, value) => build_json_path(column, keys, value, driver),
+        WhereClause::WithinRadius(column, center, meters) => build_within_radius(column, center, *meters, driver),
+        WhereClause::WithinPolygon(column, area) => build_within_polygon(column, area, driver),
         WhereClause::Raw(sql, bindings) => (sql.clone(), bindings.clone()),
     }
 }
 
+// Spatial columns are read as PostGIS geography, MySQL geometry with SRID
+// 4326, or SpatiaLite geometry from the WKT text stored in SQLite
+fn build_within_radius(column: &str, center: &Point, meters: f64, driver: &DatabaseDriver) -> (String, Vec<Value>) {
+    match driver {
+        DatabaseDriver::Postgres => (
+            format!("ST_DWithin({}::geography, ST_SetSRID(ST_MakePoint(?, ?), {})::geography, ?)", column, WGS84),
+            vec![Value::from(center.lng), Value::from(center.lat), Value::from(meters)],
+        ),
+        DatabaseDriver::MySql => (
+            format!("ST_Distance_Sphere({}, ST_GeomFromText(?, {}, 'axis-order=long-lat')) <= ?", column, WGS84),
+            vec![Value::String(center.to_wkt()), Value::from(meters)],
+        ),
+        DatabaseDriver::Sqlite => (
+            format!("PtDistWithin(GeomFromText({}, {1}), MakePoint(?, ?, {1}), ?)", column, WGS84),
+            vec![Value::from(center.lng), Value::from(center.lat), Value::from(meters)],
+        ),
+    }
+}
+
+fn build_within_polygon(column: &str, area: &Polygon, driver: &DatabaseDriver) -> (String, Vec<Value>) {
+    let area = vec![Value::String(area.to_wkt())];
+    match driver {
+        DatabaseDriver::Postgres => (format!("ST_Within({}::geometry, ST_GeomFromText(?, {}))", column, WGS84), area),
+        DatabaseDriver::MySql => (
+            format!("ST_Within({}, ST_GeomFromText(?, {}, 'axis-order=long-lat'))", column, WGS84),
+            area,
+        ),
+        DatabaseDriver::Sqlite => (format!("ST_Within(GeomFromText({}, {1}), GeomFromText(?, {1}))", column, WGS84), area),
+    }
+}
+
+fn build_full_text(columns: &[String], terms: &str, driver: &DatabaseDriver) -> (String, Vec<Value>) {
+    let terms = Value::String(terms.to_string());
+    match driver {
+        DatabaseDriver::Postgres => {
+            let document: Vec<String> = columns.iter().map(|column| format!("coalesce({}, '')", column)).collect();
+            (format!("to_tsvector({}) @@ websearch_to_tsquery(?)", document.join(" || ' ' || ")), vec![terms])
+        }
+        DatabaseDriver::MySql => (format!("MATCH ({}) AGAINST (? IN NATURAL LANGUAGE MODE)", columns.join(", ")), vec![terms]),
+        DatabaseDriver::Sqlite => {
+            let pattern = Value::String(format!("%{}%", terms.as_str().unwrap_or_default()));
+            let matches: Vec<String> = columns.iter().map(|column| format!("{} LIKE ?", column)).collect();
+            (format!("({})", matches.join(" OR ")), vec![pattern; columns.len()])
+        }
+    }
+}
+
+fn build_json_contains(column: &str, document: &Value, driver: &DatabaseDriver) -> (String, Vec<Value>) {
+    match driver {
+        DatabaseDriver::Postgres => (format!("{}::jsonb @> ?::jsonb", column), vec![Value::String(document.to_string())]),
+        DatabaseDriver::MySql => (format!("JSON_CONTAINS({}, ?)", column), vec![Value::String(document.to_string())]),
+        // SQLite has no containment operator, so compare each leaf of the document
+        DatabaseDriver::Sqlite => {
+            let mut conditions = Vec::new();
+            let mut bindings = Vec::new();
+            json_leaves(column, "$", document, &mut conditions, &mut bindings);
+            if conditions.is_empty() {
+                return ("1 = 1".to_string(), bindings);
+            }
+            (format!("({})", conditions.join(" AND ")), bindings)
+        }
+    }
+}
+
+fn json_leaves(column: &str, path: &str, value: &Value, conditions: &mut Vec<String>, bindings: &mut Vec<Value>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                json_leaves(column, &format!("{}.\"{}\"", path, key.replace('"', "")), value, conditions, bindings);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                conditions.push(format!("EXISTS (SELECT 1 FROM json_each({}, '{}') WHERE value = ?)", column, path));
+                bindings.push(item.clone());
+            }
+        }
+        scalar => {
+            conditions.push(format!("json_extract({}, '{}') = ?", column, path));
+            bindings.push(scalar.clone());
+        }
+    }
+}
+
+fn build_json_path(column: &str, keys: &[String], value: &Value, driver: &DatabaseDriver) -> (String, Vec<Value>) {
+    // PostgreSQL and MySQL extract the value as text, so compare it as text
+    let text = match value {
+        Value::String(text) => Value::String(text.clone()),
+        other => Value::String(other.to_string()),
+    };
+    let json_path = || {
+        keys.iter().fold("$".to_string(), |path, key| match key.parse::<usize>() {
+            Ok(index) => format!("{}[{}]", path, index),
+            Err(_) => format!("{}.{}", path, key),
+        })
+    };
+    match driver {
+        DatabaseDriver::Postgres => (format!("{}::jsonb #>> '{{{}}}' = ?", column, keys.join(",")), vec![text]),
+        DatabaseDriver::MySql => (format!("JSON_UNQUOTE(JSON_EXTRACT({}, '{}')) = ?", column, json_path()), vec![text]),
+        DatabaseDriver::Sqlite => (format!("json_extract({}, '{}') = ?", column, json_path()), vec![value.clone()]),
+    }
+}
+
 // Note: In a full implementation, this would include proper parameter binding
 // For now, we're focusing on the API design and structure
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Post {
+        id: Option<i64>,
+        title: String,
+    }
+
+    impl<'r> sqlx::FromRow<'r, sqlx::any::AnyRow> for Post {
+        fn from_row(row: &'r sqlx::any::AnyRow) -> std::result::Result<Self, sqlx::Error> {
+            use sqlx::Row;
+            Ok(Self {
+                id: row.try_get("id")?,
+                title: row.try_get("title")?,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Model for Post {
+        type PrimaryKey = i64;
+
+        fn table_name() -> &'static str {
+            "posts"
+        }
+
+        fn id(&self) -> Option<i64> {
+            self.id
+        }
+
+        fn set_id(&mut self, id: i64) {
+            self.id = Some(id);
+        }
+
+        fn state(&self) -> ModelState {
+            ModelState::New
+        }
+
+        fn set_state(&mut self, _state: ModelState) {}
+
+        fn global_scopes() -> Vec<GlobalScope<Self>> {
+            vec![
+                GlobalScope::new("not_deleted", |query| query.where_null("deleted_at")),
+                GlobalScope::new("tenant", |query| query.where_eq("tenant_id", 7)),
+            ]
+        }
+
+        async fn create_in_database(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn update_in_database(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    crate::query_scopes! {
+        trait PostScopes for Post {
+            fn published(query) { query.where_not_null("published_at") }
+            fn by(query, author: i64) { query.where_eq("author_id", author) }
+        }
+    }
+
+    #[test]
+    fn test_global_and_local_scopes() {
+        let (sql, bindings) = Post::query().published().by(3).to_sql();
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE published_at IS NOT NULL AND author_id = ? AND deleted_at IS NULL AND tenant_id = ?"
+        );
+        assert_eq!(bindings, vec![Value::from(3), Value::from(7)]);
+
+        let (sql, _) = Post::query().without_global_scope("not_deleted").to_sql();
+        assert_eq!(sql, "SELECT * FROM posts WHERE tenant_id = ?");
+
+        let (sql, _) = Post::query().without_global_scopes().to_sql();
+        assert_eq!(sql, "SELECT * FROM posts");
+        assert_eq!(
+            Post::query().published().build_count_query().0,
+            "SELECT COUNT(*) FROM posts WHERE published_at IS NOT NULL AND deleted_at IS NULL AND tenant_id = ?"
+        );
+    }
+
+    #[test]
+    fn test_list_params_feed_the_query() {
+        let params = crate::extractors::ListParams::parse(
+            "page=3&per_page=20&sort=-published_at,title&filter[status]=draft,review&filter[author_id]=4",
+            crate::extractors::ListLimits::default(),
+        )
+        .unwrap();
+        let (sql, bindings) = Post::query().without_global_scopes().order_by_asc("id").list(&params).to_sql();
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE status IN (?, ?) AND author_id = ? ORDER BY published_at DESC, title ASC LIMIT 20 OFFSET 40"
+        );
+        assert_eq!(bindings, vec![Value::from("draft"), Value::from("review"), Value::from("4")]);
+    }
+
+    #[test]
+    fn test_full_text_and_json_conditions_per_dialect() {
+        let search = |driver: DatabaseDriver| {
+            Post::query()
+                .without_global_scopes()
+                .dialect(driver)
+                .where_fts_columns(&["title", "body"], "rust web")
+                .where_json("meta->seo->index", true)
+                .where_json_contains("meta", serde_json::json!({"tags": ["rust"]}))
+                .to_sql()
+        };
+
+        let (sql, bindings) = search(DatabaseDriver::Postgres);
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE to_tsvector(coalesce(title, '') || ' ' || coalesce(body, '')) @@ websearch_to_tsquery(?) \
+             AND meta::jsonb #>> '{seo,index}' = ? AND meta::jsonb @> ?::jsonb"
+        );
+        assert_eq!(bindings, vec![Value::from("rust web"), Value::from("true"), Value::from(r#"{"tags":["rust"]}"#)]);
+
+        let (sql, _) = search(DatabaseDriver::MySql);
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE MATCH (title, body) AGAINST (? IN NATURAL LANGUAGE MODE) \
+             AND JSON_UNQUOTE(JSON_EXTRACT(meta, '$.seo.index')) = ? AND JSON_CONTAINS(meta, ?)"
+        );
+
+        let (sql, bindings) = search(DatabaseDriver::Sqlite);
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE (title LIKE ? OR body LIKE ?) AND json_extract(meta, '$.seo.index') = ? \
+             AND (EXISTS (SELECT 1 FROM json_each(meta, '$.\"tags\"') WHERE value = ?))"
+        );
+        assert_eq!(bindings[0], Value::from("%rust web%"));
+        assert_eq!(bindings[2], Value::from(true));
+
+        // Unsafe identifiers are ignored rather than spliced into the SQL
+        let (sql, _) = Post::query().without_global_scopes().where_json("meta->x'; --", 1).to_sql();
+        assert_eq!(sql, "SELECT * FROM posts");
+    }
+
+    #[test]
+    fn test_spatial_conditions_per_dialect() {
+        let zone = Polygon::new(vec![Point::new(51.5, -0.2), Point::new(51.6, -0.2), Point::new(51.6, 0.0)]);
+        let nearby = |driver: DatabaseDriver| {
+            Post::query()
+                .without_global_scopes()
+                .dialect(driver)
+                .where_within_radius("location", 51.5074, -0.1278, 2000.0)
+                .where_within_polygon("location", &zone)
+                .order_by_distance("location", 51.5074, -0.1278)
+                .to_sql()
+        };
+
+        let (sql, bindings) = nearby(DatabaseDriver::Postgres);
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE ST_DWithin(location::geography, ST_SetSRID(ST_MakePoint(?, ?), 4326)::geography, ?) \
+             AND ST_Within(location::geometry, ST_GeomFromText(?, 4326)) \
+             ORDER BY ST_Distance(location::geography, ST_SetSRID(ST_MakePoint(-0.1278, 51.5074), 4326)::geography) ASC"
+        );
+        assert_eq!(bindings[..3], [Value::from(-0.1278), Value::from(51.5074), Value::from(2000.0)]);
+        assert_eq!(bindings[3], Value::from("POLYGON((-0.2 51.5, -0.2 51.6, 0 51.6, -0.2 51.5))"));
+
+        let (sql, bindings) = nearby(DatabaseDriver::MySql);
+        assert_eq!(
+            sql,
+            "SELECT * FROM posts WHERE ST_Distance_Sphere(location, ST_GeomFromText(?, 4326, 'axis-order=long-lat')) <= ? \
+             AND ST_Within(location, ST_GeomFromText(?, 4326, 'axis-order=long-lat')) \
+             ORDER BY ST_Distance_Sphere(location, ST_GeomFromText('POINT(-0.1278 51.5074)', 4326, 'axis-order=long-lat')) ASC"
+        );
+        assert_eq!(bindings[0], Value::from("POINT(-0.1278 51.5074)"));
+
+        let (sql, _) = nearby(DatabaseDriver::Sqlite);
+        assert!(sql.contains("WHERE PtDistWithin(GeomFromText(location, 4326), MakePoint(?, ?, 4326), ?)"));
+    }
+}
@@ -46,8 +46,8 @@ use serde::{Deserialize, Serialize};
 
 
 
-use crate::orm::{Result, OrmError};
-use crate::orm::connection::get_pool;
+use crate::orm::{DatabaseDriver, Result, OrmError};
+use crate::orm::connection::{driver, try_get_pool, placeholders};
 
 /// Schema introspection interface
 #[derive(Debug, Clone)]
@@ -56,10 +56,14 @@ pub struct Schema {
     database_name: String,
 }
 
+/// A row of the columns query: name, type, nullable, default, length,
+/// precision, scale, position
+type ColumnRow = (String, String, i64, Option<String>, Option<i64>, Option<i64>, Option<i64>, i64);
+
 impl Schema {
     /// Create a new schema introspector
     pub async fn new() -> Result<Self> {
-        let _pool = get_pool();
+        try_get_pool()?;
 
         // Get the current database name (simplified approach)
         // For now, we'll use a generic approach that works across databases
@@ -70,17 +74,28 @@ impl Schema {
     
     /// Get all table names in the database
     pub async fn table_names(&self) -> Result<Vec<String>> {
-        // For now, return a placeholder list
-        // In a real implementation, this would query the database
-        println!("Table names query would execute");
-        Ok(vec!["users".to_string(), "posts".to_string(), "profiles".to_string()])
+        let sql = match current_driver() {
+            DatabaseDriver::Postgres => {
+                "SELECT table_name::text FROM information_schema.tables \
+                 WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' ORDER BY 1"
+            }
+            DatabaseDriver::MySql => {
+                "SELECT CAST(table_name AS CHAR) FROM information_schema.tables \
+                 WHERE table_schema = DATABASE() AND table_type = 'BASE TABLE' ORDER BY 1"
+            }
+            DatabaseDriver::Sqlite => {
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY 1"
+            }
+        };
+        sqlx::query_scalar::<_, String>(sql)
+            .fetch_all(&try_get_pool()?)
+            .await
+            .map_err(OrmError::Database)
     }
     
     /// Check if a table exists
     pub async fn table_exists(&self, table_name: &str) -> Result<bool> {
-        // For now, return true for common table names
-        println!("Table exists query would execute for: {}", table_name);
-        Ok(matches!(table_name, "users" | "posts" | "profiles" | "roles"))
+        Ok(self.table_names().await?.iter().any(|name| name == table_name))
     }
     
     /// Get detailed information about a table
@@ -89,35 +104,177 @@ impl Schema {
             return Err(OrmError::Query(format!("Table '{}' does not exist", table_name)));
         }
 
-        // For now, return placeholder data
-        println!("Table info query would execute for: {}", table_name);
         Ok(TableInfo {
             name: table_name.to_string(),
-            columns: Vec::new(),
-            indexes: Vec::new(),
-            foreign_keys: Vec::new(),
+            columns: self.columns(table_name).await?,
+            indexes: self.indexes(table_name).await?,
+            foreign_keys: self.foreign_keys(table_name).await?,
         })
     }
     
-    /// Get column information for a table
-    pub async fn columns(&self, _table_name: &str) -> Result<Vec<ColumnInfo>> {
-        // For now, return empty list
-        println!("Columns query would execute");
-        Ok(Vec::new())
+    /// Get column information for a table, in table order
+    pub async fn columns(&self, table_name: &str) -> Result<Vec<ColumnInfo>> {
+        let sql = match current_driver() {
+            DatabaseDriver::Postgres => {
+                "SELECT column_name::text, data_type::text, \
+                 CAST(CASE WHEN is_nullable = 'YES' THEN 1 ELSE 0 END AS BIGINT), column_default::text, \
+                 CAST(character_maximum_length AS BIGINT), CAST(numeric_precision AS BIGINT), \
+                 CAST(numeric_scale AS BIGINT), CAST(ordinal_position AS BIGINT) \
+                 FROM information_schema.columns \
+                 WHERE table_schema = current_schema() AND table_name = ? ORDER BY ordinal_position"
+            }
+            DatabaseDriver::MySql => {
+                "SELECT CAST(column_name AS CHAR), CAST(data_type AS CHAR), \
+                 CAST(is_nullable = 'YES' AS SIGNED), CAST(column_default AS CHAR), \
+                 CAST(character_maximum_length AS SIGNED), CAST(numeric_precision AS SIGNED), \
+                 CAST(numeric_scale AS SIGNED), CAST(ordinal_position AS SIGNED) \
+                 FROM information_schema.columns \
+                 WHERE table_schema = DATABASE() AND table_name = ? ORDER BY ordinal_position"
+            }
+            // SQLite keeps the declared type, e.g. VARCHAR(255), and no
+            // length of its own
+            DatabaseDriver::Sqlite => {
+                "SELECT name, type, CASE WHEN \"notnull\" = 0 AND pk = 0 THEN 1 ELSE 0 END, dflt_value, \
+                 NULL, NULL, NULL, cid + 1 FROM pragma_table_info(?) ORDER BY cid"
+            }
+        };
+        let rows = sqlx::query_as::<_, ColumnRow>(&placeholders(sql))
+            .bind(table_name.to_string())
+            .fetch_all(&try_get_pool()?)
+            .await
+            .map_err(OrmError::Database)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(name, declared, nullable, default_value, length, precision, scale, position)| {
+                let (data_type, declared_length) = split_declared_type(&declared);
+                ColumnInfo {
+                    name,
+                    data_type,
+                    is_nullable: nullable != 0,
+                    default_value,
+                    max_length: length.or(declared_length).map(|length| length as i32),
+                    precision: precision.map(|precision| precision as i32),
+                    scale: scale.map(|scale| scale as i32),
+                    position: position as i32,
+                }
+            })
+            .collect())
     }
     
-    /// Get index information for a table
-    pub async fn indexes(&self, _table_name: &str) -> Result<Vec<IndexInfo>> {
-        // For now, return empty list
-        println!("Indexes query would execute");
-        Ok(Vec::new())
+    /// Get index information for a table, including the primary key
+    pub async fn indexes(&self, table_name: &str) -> Result<Vec<IndexInfo>> {
+        let sql = match current_driver() {
+            DatabaseDriver::Postgres => {
+                "SELECT i.relname::text, CAST(CASE WHEN x.indisunique THEN 1 ELSE 0 END AS BIGINT), \
+                 CAST(CASE WHEN x.indisprimary THEN 1 ELSE 0 END AS BIGINT), a.attname::text \
+                 FROM pg_index x \
+                 JOIN pg_class t ON t.oid = x.indrelid \
+                 JOIN pg_class i ON i.oid = x.indexrelid \
+                 JOIN pg_namespace n ON n.oid = t.relnamespace \
+                 JOIN LATERAL unnest(x.indkey::int2[]) WITH ORDINALITY AS k(attnum, position) ON true \
+                 JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = k.attnum \
+                 WHERE n.nspname = current_schema() AND t.relname = ? ORDER BY i.relname, k.position"
+            }
+            DatabaseDriver::MySql => {
+                "SELECT CAST(index_name AS CHAR), CAST(1 - non_unique AS SIGNED), \
+                 CAST(index_name = 'PRIMARY' AS SIGNED), CAST(column_name AS CHAR) \
+                 FROM information_schema.statistics \
+                 WHERE table_schema = DATABASE() AND table_name = ? ORDER BY index_name, seq_in_index"
+            }
+            DatabaseDriver::Sqlite => {
+                "SELECT l.name, l.\"unique\", CASE WHEN l.origin = 'pk' THEN 1 ELSE 0 END, i.name \
+                 FROM pragma_index_list(?) AS l JOIN pragma_index_info(l.name) AS i ORDER BY l.name, i.seqno"
+            }
+        };
+        let rows = sqlx::query_as::<_, (String, i64, i64, String)>(&placeholders(sql))
+            .bind(table_name.to_string())
+            .fetch_all(&try_get_pool()?)
+            .await
+            .map_err(OrmError::Database)?;
+
+        let mut indexes: Vec<IndexInfo> = Vec::new();
+        for (name, unique, primary, column) in rows {
+            match indexes.last_mut() {
+                Some(index) if index.name == name => index.columns.push(column),
+                _ => indexes.push(IndexInfo {
+                    name,
+                    is_unique: unique != 0,
+                    is_primary: primary != 0,
+                    columns: vec![column],
+                }),
+            }
+        }
+
+        // An INTEGER PRIMARY KEY is SQLite's rowid and has no index of its own
+        if current_driver() == DatabaseDriver::Sqlite && !indexes.iter().any(|index| index.is_primary) {
+            let columns = sqlx::query_scalar::<_, String>("SELECT name FROM pragma_table_info(?) WHERE pk > 0 ORDER BY pk")
+                .bind(table_name.to_string())
+                .fetch_all(&try_get_pool()?)
+                .await
+                .map_err(OrmError::Database)?;
+            if !columns.is_empty() {
+                indexes.insert(0, IndexInfo {
+                    name: "PRIMARY".to_string(),
+                    is_unique: true,
+                    is_primary: true,
+                    columns,
+                });
+            }
+        }
+
+        Ok(indexes)
     }
     
     /// Get foreign key information for a table
-    pub async fn foreign_keys(&self, _table_name: &str) -> Result<Vec<ForeignKeyInfo>> {
-        // For now, return empty list
-        println!("Foreign keys query would execute");
-        Ok(Vec::new())
+    pub async fn foreign_keys(&self, table_name: &str) -> Result<Vec<ForeignKeyInfo>> {
+        let sql = match current_driver() {
+            DatabaseDriver::Postgres => {
+                "SELECT c.conname::text, a.attname::text, r.relname::text, f.attname::text, \
+                 CASE c.confupdtype WHEN 'c' THEN 'CASCADE' WHEN 'n' THEN 'SET NULL' WHEN 'd' THEN 'SET DEFAULT' \
+                 WHEN 'r' THEN 'RESTRICT' ELSE 'NO ACTION' END, \
+                 CASE c.confdeltype WHEN 'c' THEN 'CASCADE' WHEN 'n' THEN 'SET NULL' WHEN 'd' THEN 'SET DEFAULT' \
+                 WHEN 'r' THEN 'RESTRICT' ELSE 'NO ACTION' END \
+                 FROM pg_constraint c \
+                 JOIN pg_class t ON t.oid = c.conrelid \
+                 JOIN pg_namespace n ON n.oid = t.relnamespace \
+                 JOIN pg_class r ON r.oid = c.confrelid \
+                 JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = c.conkey[1] \
+                 JOIN pg_attribute f ON f.attrelid = c.confrelid AND f.attnum = c.confkey[1] \
+                 WHERE c.contype = 'f' AND n.nspname = current_schema() AND t.relname = ? ORDER BY 1"
+            }
+            DatabaseDriver::MySql => {
+                "SELECT CAST(k.constraint_name AS CHAR), CAST(k.column_name AS CHAR), \
+                 CAST(k.referenced_table_name AS CHAR), CAST(k.referenced_column_name AS CHAR), \
+                 CAST(r.update_rule AS CHAR), CAST(r.delete_rule AS CHAR) \
+                 FROM information_schema.key_column_usage k \
+                 JOIN information_schema.referential_constraints r \
+                 ON r.constraint_schema = k.constraint_schema AND r.constraint_name = k.constraint_name \
+                 WHERE k.table_schema = DATABASE() AND k.table_name = ? AND k.referenced_table_name IS NOT NULL \
+                 ORDER BY 1"
+            }
+            DatabaseDriver::Sqlite => {
+                "SELECT 'fk_' || id, \"from\", \"table\", COALESCE(\"to\", ''), on_update, on_delete \
+                 FROM pragma_foreign_key_list(?) ORDER BY id, seq"
+            }
+        };
+        let rows = sqlx::query_as::<_, (String, String, String, String, String, String)>(&placeholders(sql))
+            .bind(table_name.to_string())
+            .fetch_all(&try_get_pool()?)
+            .await
+            .map_err(OrmError::Database)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(name, column, foreign_table, foreign_column, on_update, on_delete)| ForeignKeyInfo {
+                name,
+                column,
+                foreign_table,
+                foreign_column,
+                on_update,
+                on_delete,
+            })
+            .collect())
     }
     
     /// Get the primary key column(s) for a table
@@ -180,6 +337,23 @@ impl Schema {
     }
 }
 
+/// Dialect of the queries, the global pool's driver
+fn current_driver() -> DatabaseDriver {
+    driver().cloned().unwrap_or(DatabaseDriver::Postgres)
+}
+
+/// Split a declared type such as `VARCHAR(255)` into `varchar` and its
+/// length
+fn split_declared_type(declared: &str) -> (String, Option<i64>) {
+    match declared.split_once('(') {
+        Some((name, rest)) => {
+            let length = rest.trim_end_matches(')').split(',').next().and_then(|length| length.trim().parse().ok());
+            (name.trim().to_lowercase(), length)
+        }
+        None => (declared.trim().to_lowercase(), None),
+    }
+}
+
 /// Information about a database table
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableInfo {
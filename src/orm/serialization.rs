@@ -0,0 +1,169 @@
+//! # Model Serialization
+//!
+//! Control which attributes of a model end up in JSON output. A model lists
+//! its [`hidden`](Model::hidden) and [`visible`](Model::visible) attributes
+//! and computed [`appends`](Model::appends) once, and each response can
+//! narrow that further with [`Serialized::only`] and [`Serialized::except`].
+//!
+//! Saving a model still uses its plain `Serialize` impl, so hidden
+//! attributes are stored as usual.
+
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::orm::{Model, OrmError, Result};
+
+/// A model prepared for output, made by [`Model::serialized`]
+#[derive(Debug, Clone)]
+pub struct Serialized<'a, M: Model> {
+    model: &'a M,
+    only: Option<Vec<String>>,
+    except: Vec<String>,
+    make_visible: Vec<String>,
+}
+
+impl<'a, M: Model> Serialized<'a, M> {
+    pub fn new(model: &'a M) -> Self {
+        Self {
+            model,
+            only: None,
+            except: Vec::new(),
+            make_visible: Vec::new(),
+        }
+    }
+
+    /// Include only these attributes
+    pub fn only(mut self, attributes: &[&str]) -> Self {
+        self.only = Some(attributes.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Leave out these attributes as well
+    pub fn except(mut self, attributes: &[&str]) -> Self {
+        self.except.extend(attributes.iter().map(|name| name.to_string()));
+        self
+    }
+
+    /// Include these attributes even though the model hides them, e.g. an
+    /// email address in the owner's own profile
+    pub fn make_visible(mut self, attributes: &[&str]) -> Self {
+        self.make_visible.extend(attributes.iter().map(|name| name.to_string()));
+        self
+    }
+
+    /// The attributes as a JSON object
+    pub fn to_value(&self) -> Result<Value> {
+        let Value::Object(attributes) = serde_json::to_value(self.model)? else {
+            return Err(OrmError::Query("Expected object".to_string()));
+        };
+        let shown = |name: &str| self.make_visible.iter().any(|visible| visible == name);
+
+        let mut output: Map<String, Value> = attributes
+            .into_iter()
+            .filter(|(name, _)| !M::hidden().contains(&name.as_str()) || shown(name))
+            .filter(|(name, _)| M::visible().is_empty() || M::visible().contains(&name.as_str()) || shown(name))
+            .collect();
+        output.extend(self.model.appends());
+
+        if let Some(only) = &self.only {
+            output.retain(|name, _| only.contains(name));
+        }
+        output.retain(|name, _| !self.except.contains(name));
+        Ok(Value::Object(output))
+    }
+}
+
+impl<M: Model> Serialize for Serialized<'_, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_value().map_err(serde::ser::Error::custom)?.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::orm::{Model, ModelState, Result};
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct User {
+        id: Option<i64>,
+        name: String,
+        email: String,
+        password_hash: String,
+    }
+
+    impl<'r> sqlx::FromRow<'r, sqlx::any::AnyRow> for User {
+        fn from_row(row: &'r sqlx::any::AnyRow) -> std::result::Result<Self, sqlx::Error> {
+            use sqlx::Row;
+            Ok(Self {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                email: row.try_get("email")?,
+                password_hash: row.try_get("password_hash")?,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Model for User {
+        type PrimaryKey = i64;
+
+        fn table_name() -> &'static str {
+            "users"
+        }
+
+        fn id(&self) -> Option<i64> {
+            self.id
+        }
+
+        fn set_id(&mut self, id: i64) {
+            self.id = Some(id);
+        }
+
+        fn state(&self) -> ModelState {
+            ModelState::Persisted
+        }
+
+        fn set_state(&mut self, _state: ModelState) {}
+
+        fn hidden() -> &'static [&'static str] {
+            &["password_hash", "email"]
+        }
+
+        fn appends(&self) -> serde_json::Map<String, serde_json::Value> {
+            let mut computed = serde_json::Map::new();
+            computed.insert("initial".into(), self.name[..1].into());
+            computed
+        }
+
+        async fn create_in_database(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn update_in_database(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hidden_attributes_appends_and_shaping() {
+        let user = User {
+            id: Some(1),
+            name: "Ada".to_string(),
+            email: "ada@example.com".to_string(),
+            password_hash: "$argon2id$...".to_string(),
+        };
+
+        assert_eq!(user.to_json().unwrap(), json!({"id": 1, "name": "Ada", "initial": "A"}));
+        assert_eq!(
+            serde_json::to_value(user.serialized().make_visible(&["email"]).except(&["initial"])).unwrap(),
+            json!({"id": 1, "name": "Ada", "email": "ada@example.com"})
+        );
+        assert_eq!(user.serialized().only(&["name", "password_hash"]).to_value().unwrap(), json!({"name": "Ada"}));
+
+        // Saving still sees every attribute
+        assert!(user.to_attributes().unwrap().contains_key("password_hash"));
+    }
+}
@@ -0,0 +1,320 @@
+//! # Spatial Types
+//!
+//! Points and polygons for location features, stored in spatial columns:
+//! PostGIS on PostgreSQL, the built-in spatial types on MySQL 8 and
+//! SpatiaLite on SQLite. Install the extension before migrating
+//! (`CREATE EXTENSION postgis`, or load `mod_spatialite`).
+//!
+//! ```rust,ignore
+//! use torch_web::orm::spatial::Point;
+//!
+//! Schema::create_table("stores", |table| {
+//!     table.id("id");
+//!     table.string("name", None);
+//!     table.point("location");
+//!     table.spatial_index(&["location"]);
+//! });
+//!
+//! let here = Point::new(51.5074, -0.1278);
+//! let nearby = Store::query()
+//!     .where_within_radius("location", here.lat, here.lng, 2_000.0)
+//!     .order_by_distance("location", here.lat, here.lng)
+//!     .get()
+//!     .await?;
+//! ```
+//!
+//! Coordinates are WGS 84 (SRID 4326) degrees. [`Point`] and [`Polygon`]
+//! serialize as EWKT text (`SRID=4326;POINT(-0.1278 51.5074)`), which is
+//! what model attributes are saved as, and deserialize from WKT, EWKT or
+//! GeoJSON. Use [`Point::to_geojson`] for API output. The `Any` driver can't
+//! decode spatial columns, so select them as text when loading models:
+//! `.select(vec!["id", "name", "ST_AsText(location) AS location"])`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// Spatial reference of GPS coordinates
+pub const WGS84: u32 = 4326;
+
+/// Mean radius of the Earth in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Shape stored in a spatial column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeometryType {
+    Point,
+    LineString,
+    Polygon,
+    MultiPoint,
+    MultiPolygon,
+    /// Any shape
+    Geometry,
+}
+
+impl GeometryType {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            GeometryType::Point => "POINT",
+            GeometryType::LineString => "LINESTRING",
+            GeometryType::Polygon => "POLYGON",
+            GeometryType::MultiPoint => "MULTIPOINT",
+            GeometryType::MultiPolygon => "MULTIPOLYGON",
+            GeometryType::Geometry => "GEOMETRY",
+        }
+    }
+}
+
+/// A spatial value that couldn't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpatialError(pub String);
+
+impl fmt::Display for SpatialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid geometry: {}", self.0)
+    }
+}
+
+impl std::error::Error for SpatialError {}
+
+/// A location in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+impl Point {
+    pub fn new(lat: f64, lng: f64) -> Self {
+        Self { lat, lng }
+    }
+
+    /// Great-circle distance in meters
+    pub fn distance_to(&self, other: &Point) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlng = (other.lng - self.lng).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlng / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    /// `POINT(lng lat)`
+    pub fn to_wkt(&self) -> String {
+        format!("POINT({} {})", self.lng, self.lat)
+    }
+
+    /// GeoJSON geometry
+    pub fn to_geojson(&self) -> Value {
+        json!({"type": "Point", "coordinates": [self.lng, self.lat]})
+    }
+}
+
+impl FromStr for Point {
+    type Err = SpatialError;
+
+    /// Parse WKT or EWKT, such as `SRID=4326;POINT(-0.1278 51.5074)`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let body = wkt_body(text, "POINT")?;
+        coordinates(body)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| SpatialError(text.to_string()))
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_wkt())
+    }
+}
+
+/// An area bounded by a ring of points
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    /// The boundary, with the first point repeated at the end
+    pub exterior: Vec<Point>,
+}
+
+impl Polygon {
+    /// A polygon through `points`, closing the ring if needed
+    pub fn new(points: Vec<Point>) -> Self {
+        let mut exterior = points;
+        if let (Some(first), Some(last)) = (exterior.first().copied(), exterior.last()) {
+            if first != *last {
+                exterior.push(first);
+            }
+        }
+        Self { exterior }
+    }
+
+    /// Whether `point` lies inside the polygon, treating coordinates as
+    /// planar, which is accurate for areas the size of a city
+    pub fn contains(&self, point: &Point) -> bool {
+        let mut inside = false;
+        for edge in self.exterior.windows(2) {
+            let (a, b) = (edge[0], edge[1]);
+            if (a.lat > point.lat) != (b.lat > point.lat) {
+                let crossing = a.lng + (point.lat - a.lat) / (b.lat - a.lat) * (b.lng - a.lng);
+                if point.lng < crossing {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    /// `POLYGON((lng lat, ...))`
+    pub fn to_wkt(&self) -> String {
+        let ring: Vec<String> = self.exterior.iter().map(|p| format!("{} {}", p.lng, p.lat)).collect();
+        format!("POLYGON(({}))", ring.join(", "))
+    }
+
+    /// GeoJSON geometry
+    pub fn to_geojson(&self) -> Value {
+        let ring: Vec<[f64; 2]> = self.exterior.iter().map(|p| [p.lng, p.lat]).collect();
+        json!({"type": "Polygon", "coordinates": [ring]})
+    }
+}
+
+impl FromStr for Polygon {
+    type Err = SpatialError;
+
+    /// Parse the exterior ring of a WKT or EWKT polygon
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let body = wkt_body(text, "POLYGON")?;
+        let ring = body
+            .trim()
+            .strip_prefix('(')
+            .and_then(|ring| ring.split(')').next())
+            .ok_or_else(|| SpatialError(text.to_string()))?;
+        let points = coordinates(ring)?;
+        if points.len() < 3 {
+            return Err(SpatialError(format!("a polygon needs at least 3 points: {}", text)));
+        }
+        Ok(Polygon::new(points))
+    }
+}
+
+impl fmt::Display for Polygon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_wkt())
+    }
+}
+
+/// The part between the outer parentheses of `KIND(...)`, without an
+/// `SRID=...;` prefix
+fn wkt_body<'a>(text: &'a str, kind: &str) -> Result<&'a str, SpatialError> {
+    let text = text.trim();
+    let text = match text.split_once(';') {
+        Some((srid, rest)) if srid.to_ascii_uppercase().starts_with("SRID=") => rest.trim(),
+        _ => text,
+    };
+    let invalid = || SpatialError(text.to_string());
+    let (name, rest) = text.split_once('(').ok_or_else(invalid)?;
+    if !name.trim().eq_ignore_ascii_case(kind) {
+        return Err(invalid());
+    }
+    rest.trim_end().strip_suffix(')').ok_or_else(invalid)
+}
+
+/// `lng lat, lng lat, ...`
+fn coordinates(text: &str) -> Result<Vec<Point>, SpatialError> {
+    text.split(',')
+        .map(|pair| {
+            let mut numbers = pair.split_whitespace().map(str::parse::<f64>);
+            match (numbers.next(), numbers.next()) {
+                (Some(Ok(lng)), Some(Ok(lat))) => Ok(Point::new(lat, lng)),
+                _ => Err(SpatialError(pair.trim().to_string())),
+            }
+        })
+        .collect()
+}
+
+/// `[lng, lat]` pairs of a GeoJSON geometry
+fn geojson_positions(value: &Value) -> Option<Vec<Point>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|position| {
+            let position = position.as_array()?;
+            Some(Point::new(position.get(1)?.as_f64()?, position.first()?.as_f64()?))
+        })
+        .collect()
+}
+
+impl Serialize for Point {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("SRID={};{}", WGS84, self.to_wkt()))
+    }
+}
+
+/// Accepts a GeoJSON point, `{"lat": .., "lng": ..}` or WKT
+impl<'de> Deserialize<'de> for Point {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if let Some(text) = value.as_str() {
+            return text.parse().map_err(D::Error::custom);
+        }
+        if let (Some(lat), Some(lng)) = (value["lat"].as_f64(), value["lng"].as_f64()) {
+            return Ok(Point::new(lat, lng));
+        }
+        if value["type"] == "Point" {
+            let position = json!([value["coordinates"]]);
+            if let Some(point) = geojson_positions(&position).and_then(|points| points.into_iter().next()) {
+                return Ok(point);
+            }
+        }
+        Err(D::Error::custom(format!("expected a point, got {}", value)))
+    }
+}
+
+impl Serialize for Polygon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("SRID={};{}", WGS84, self.to_wkt()))
+    }
+}
+
+/// Accepts a GeoJSON polygon or WKT
+impl<'de> Deserialize<'de> for Polygon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if let Some(text) = value.as_str() {
+            return text.parse().map_err(D::Error::custom);
+        }
+        if value["type"] == "Polygon" {
+            if let Some(points) = geojson_positions(&value["coordinates"][0]) {
+                return Ok(Polygon::new(points));
+            }
+        }
+        Err(D::Error::custom(format!("expected a polygon, got {}", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_points_and_polygons() {
+        let london = Point::new(51.5074, -0.1278);
+        let paris: Point = "SRID=4326;POINT(2.3522 48.8566)".parse().unwrap();
+        assert!((london.distance_to(&paris) - 343_900.0).abs() < 1_000.0);
+
+        assert_eq!(serde_json::to_value(london).unwrap(), json!("SRID=4326;POINT(-0.1278 51.5074)"));
+        assert_eq!(london.to_geojson(), json!({"type": "Point", "coordinates": [-0.1278, 51.5074]}));
+        assert_eq!(serde_json::from_value::<Point>(london.to_geojson()).unwrap(), london);
+        assert_eq!(serde_json::from_value::<Point>(json!({"lat": 51.5074, "lng": -0.1278})).unwrap(), london);
+
+        let square = Polygon::new(vec![Point::new(0.0, 0.0), Point::new(0.0, 1.0), Point::new(1.0, 1.0), Point::new(1.0, 0.0)]);
+        assert_eq!(square.to_wkt(), "POLYGON((0 0, 1 0, 1 1, 0 1, 0 0))");
+        assert_eq!(square.to_wkt().parse::<Polygon>().unwrap(), square);
+        assert!(square.contains(&Point::new(0.5, 0.5)));
+        assert!(!square.contains(&Point::new(1.5, 0.5)));
+        assert_eq!(serde_json::from_value::<Polygon>(serde_json::to_value(&square).unwrap()).unwrap(), square);
+        assert_eq!(serde_json::from_value::<Polygon>(square.to_geojson()).unwrap(), square);
+        assert!("LINESTRING(0 0, 1 1)".parse::<Polygon>().is_err());
+    }
+}
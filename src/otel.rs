@@ -0,0 +1,430 @@
+//! # OpenTelemetry export
+//!
+//! Sends traces and metrics to an OpenTelemetry collector over OTLP/HTTP,
+//! configured from the `[monitoring.otel]` section of `torch.toml`:
+//!
+//! ```toml
+//! [monitoring.otel]
+//! enabled = true
+//! endpoint = "http://otel-collector:4318"
+//! service_name = "blog"
+//! sample_ratio = 0.25
+//! headers = { "x-api-key" = "..." }
+//! ```
+//!
+//! ```rust,no_run
+//! use torch_web::{App, config::TorchConfig, otel::{self, Telemetry}};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = TorchConfig::from_file("torch.toml")?;
+//! // Keep the guard alive: dropping it flushes what is still buffered
+//! let _otel = otel::install(&config.monitoring.otel)?;
+//!
+//! let app = App::new().middleware(Telemetry::new());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! What gets exported:
+//! - a server span per request, continuing the caller's trace from its
+//!   `traceparent` header and named after the route pattern
+//! - a client span per ORM query and cache operation, nested under the
+//!   request or job that ran it
+//! - a consumer span per queue job attempt
+//! - everything recorded through the `metrics` macros, such as the
+//!   request, queue and circuit breaker metrics of
+//!   [`production`](crate::production)
+//!
+//! The standard `OTEL_EXPORTER_OTLP_*` environment variables override the
+//! endpoint, headers and timeout from the config file.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use opentelemetry::global;
+use opentelemetry::metrics::Meter;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{FutureExt, Span, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+
+use crate::config::OtelConfig;
+use crate::middleware::Middleware;
+use crate::router::MatchedRoute;
+use crate::{Request, Response};
+
+/// Instrumentation scope of Torch's spans and metrics
+const SCOPE: &str = "torch";
+
+/// Keeps the exporters running; dropping it flushes and shuts them down
+#[must_use = "dropping the guard shuts the exporters down"]
+pub struct OtelGuard {
+    tracer_provider: Option<TracerProvider>,
+    meter_provider: Option<SdkMeterProvider>,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(error) = provider.shutdown() {
+                eprintln!("⚠️  Could not flush traces: {}", error);
+            }
+        }
+        if let Some(provider) = self.meter_provider.take() {
+            if let Err(error) = provider.shutdown() {
+                eprintln!("⚠️  Could not flush metrics: {}", error);
+            }
+        }
+    }
+}
+
+/// Start exporting as `config` says, from inside the Tokio runtime.
+///
+/// Does nothing unless `config.enabled` is set, so it can be called
+/// unconditionally. Metrics are only bridged when no other `metrics`
+/// recorder, such as a Prometheus exporter, was installed first.
+pub fn install(config: &OtelConfig) -> Result<OtelGuard, Box<dyn std::error::Error>> {
+    let mut guard = OtelGuard { tracer_provider: None, meter_provider: None };
+    if !config.enabled {
+        return Ok(guard);
+    }
+    let endpoint = config.endpoint.trim_end_matches('/');
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let resource = Resource::new([KeyValue::new("service.name", config.service_name.clone())]);
+
+    if config.traces {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .with_timeout(timeout)
+            .with_headers(config.headers.clone())
+            .build()?;
+        let ratio = config.sample_ratio.clamp(0.0, 1.0);
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio))))
+            .with_resource(resource.clone())
+            .build();
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        global::set_tracer_provider(provider.clone());
+        guard.tracer_provider = Some(provider);
+    }
+
+    if config.metrics {
+        let exporter = opentelemetry_otlp::MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .with_timeout(timeout)
+            .with_headers(config.headers.clone())
+            .build()?;
+        let reader = PeriodicReader::builder(exporter, runtime::Tokio)
+            .with_interval(Duration::from_secs(config.metrics_interval_secs.max(1)))
+            .build();
+        let provider = SdkMeterProvider::builder().with_reader(reader).with_resource(resource).build();
+        global::set_meter_provider(provider.clone());
+        if metrics::set_boxed_recorder(Box::new(MetricsBridge::new(global::meter(SCOPE)))).is_err() {
+            eprintln!("⚠️  A metrics recorder is already installed, so Torch's metrics won't be sent over OTLP");
+        }
+        guard.meter_provider = Some(provider);
+    }
+
+    Ok(guard)
+}
+
+/// Middleware tracing every request as a server span.
+///
+/// The span continues the trace from an incoming `traceparent` header and
+/// is named after the route pattern, e.g. `GET /users/:id`. Queries, cache
+/// operations and outgoing spans started while the handler runs become its
+/// children. Register it first, so the span covers the other middleware.
+#[derive(Debug, Clone, Default)]
+pub struct Telemetry;
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+struct HeaderExtractor<'a>(&'a http::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+impl Middleware for Telemetry {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(req.headers())));
+        let method = req.method().to_string();
+        let span = global::tracer(SCOPE)
+            .span_builder(method.clone())
+            .with_kind(SpanKind::Server)
+            .with_attributes([
+                KeyValue::new("http.request.method", method.clone()),
+                KeyValue::new("url.path", req.path().to_string()),
+            ])
+            .start_with_context(&global::tracer(SCOPE), &parent);
+        let cx = parent.with_span(span);
+
+        Box::pin(async move {
+            let response = next(req).with_context(cx.clone()).await;
+
+            let span = cx.span();
+            let status = response.status_code();
+            if let Some(route) = response.extensions().get::<MatchedRoute>() {
+                span.update_name(format!("{} {}", method, route.as_str()));
+                span.set_attribute(KeyValue::new("http.route", route.as_str().to_string()));
+            }
+            span.set_attribute(KeyValue::new("http.response.status_code", status.as_u16() as i64));
+            if status.is_server_error() {
+                span.set_status(Status::error(status.to_string()));
+            }
+            span.end();
+            response
+        })
+    }
+}
+
+/// Record a query that ran for `duration` up to now as a span of the
+/// current trace
+pub(crate) fn record_query(sql: &str, duration: Duration) {
+    let operation = sql.split_whitespace().next().unwrap_or("QUERY").to_ascii_uppercase();
+    let attributes = vec![
+        KeyValue::new("db.operation.name", operation.clone()),
+        KeyValue::new("db.query.text", sql.to_string()),
+    ];
+    finished_span(operation, attributes, duration, None);
+}
+
+/// Record a cache operation started at `started` as a span of the current
+/// trace; `hit` is whether a read found the key
+pub(crate) fn record_cache(operation: &str, key: &str, hit: Option<bool>, started: Instant) {
+    let mut attributes = vec![
+        KeyValue::new("cache.operation", operation.to_string()),
+        KeyValue::new("cache.key", key.to_string()),
+    ];
+    if let Some(hit) = hit {
+        attributes.push(KeyValue::new("cache.hit", hit));
+    }
+    finished_span(format!("cache {}", operation), attributes, started.elapsed(), None);
+}
+
+fn finished_span(name: String, attributes: Vec<KeyValue>, duration: Duration, error: Option<String>) {
+    let tracer = global::tracer(SCOPE);
+    let now = SystemTime::now();
+    let mut span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Client)
+        .with_start_time(now.checked_sub(duration).unwrap_or(now))
+        .with_attributes(attributes)
+        .start_with_context(&tracer, &Context::current());
+    if let Some(error) = error {
+        span.set_status(Status::error(error));
+    }
+    span.end_with_timestamp(now);
+}
+
+/// Run one attempt of a queued job inside a consumer span
+pub(crate) async fn traced_job<T, E: Display>(
+    name: &str,
+    queue: &str,
+    attempt: u32,
+    job: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let tracer = global::tracer(SCOPE);
+    let span = tracer
+        .span_builder(format!("{} process", queue))
+        .with_kind(SpanKind::Consumer)
+        .with_attributes([
+            KeyValue::new("messaging.destination.name", queue.to_string()),
+            KeyValue::new("messaging.operation.type", "process"),
+            KeyValue::new("torch.job", name.to_string()),
+            KeyValue::new("torch.job.attempt", attempt as i64),
+        ])
+        .start_with_context(&tracer, &Context::current());
+    let cx = Context::current_with_span(span);
+    let result = job.with_context(cx.clone()).await;
+    if let Err(error) = &result {
+        cx.span().set_status(Status::error(error.to_string()));
+    }
+    cx.span().end();
+    result
+}
+
+/// Forwards the `metrics` macros to OpenTelemetry instruments
+struct MetricsBridge {
+    meter: Meter,
+    counters: Mutex<HashMap<metrics::Key, metrics::Counter>>,
+    gauges: Mutex<HashMap<metrics::Key, metrics::Gauge>>,
+    histograms: Mutex<HashMap<metrics::Key, metrics::Histogram>>,
+}
+
+impl MetricsBridge {
+    fn new(meter: Meter) -> Self {
+        Self {
+            meter,
+            counters: Mutex::default(),
+            gauges: Mutex::default(),
+            histograms: Mutex::default(),
+        }
+    }
+}
+
+fn attributes(key: &metrics::Key) -> Vec<KeyValue> {
+    key.labels().map(|label| KeyValue::new(label.key().to_string(), label.value().to_string())).collect()
+}
+
+struct BridgedCounter(opentelemetry::metrics::Counter<u64>, Vec<KeyValue>);
+
+impl metrics::CounterFn for BridgedCounter {
+    fn increment(&self, value: u64) {
+        self.0.add(value, &self.1);
+    }
+
+    fn absolute(&self, _value: u64) {}
+}
+
+/// OpenTelemetry gauges only take absolute values, so increments are
+/// applied to the last one here
+struct BridgedGauge(opentelemetry::metrics::Gauge<f64>, Vec<KeyValue>, AtomicU64);
+
+impl BridgedGauge {
+    fn update(&self, change: impl Fn(f64) -> f64) {
+        let mut value = 0.0;
+        let _ = self.2.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            value = change(f64::from_bits(bits));
+            Some(value.to_bits())
+        });
+        self.0.record(value, &self.1);
+    }
+}
+
+impl metrics::GaugeFn for BridgedGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.update(|_| value);
+    }
+}
+
+struct BridgedHistogram(opentelemetry::metrics::Histogram<f64>, Vec<KeyValue>);
+
+impl metrics::HistogramFn for BridgedHistogram {
+    fn record(&self, value: f64) {
+        self.0.record(value, &self.1);
+    }
+}
+
+impl metrics::Recorder for MetricsBridge {
+    fn describe_counter(&self, _key: metrics::KeyName, _unit: Option<metrics::Unit>, _description: metrics::SharedString) {}
+
+    fn describe_gauge(&self, _key: metrics::KeyName, _unit: Option<metrics::Unit>, _description: metrics::SharedString) {}
+
+    fn describe_histogram(&self, _key: metrics::KeyName, _unit: Option<metrics::Unit>, _description: metrics::SharedString) {}
+
+    fn register_counter(&self, key: &metrics::Key) -> metrics::Counter {
+        let mut counters = self.counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let counter = counters.entry(key.clone()).or_insert_with(|| {
+            let instrument = self.meter.u64_counter(key.name().to_string()).build();
+            metrics::Counter::from_arc(Arc::new(BridgedCounter(instrument, attributes(key))))
+        });
+        counter.clone()
+    }
+
+    fn register_gauge(&self, key: &metrics::Key) -> metrics::Gauge {
+        let mut gauges = self.gauges.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let gauge = gauges.entry(key.clone()).or_insert_with(|| {
+            let instrument = self.meter.f64_gauge(key.name().to_string()).build();
+            metrics::Gauge::from_arc(Arc::new(BridgedGauge(instrument, attributes(key), AtomicU64::new(0))))
+        });
+        gauge.clone()
+    }
+
+    fn register_histogram(&self, key: &metrics::Key) -> metrics::Histogram {
+        let mut histograms = self.histograms.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let histogram = histograms.entry(key.clone()).or_insert_with(|| {
+            let instrument = self.meter.f64_histogram(key.name().to_string()).build();
+            metrics::Histogram::from_arc(Arc::new(BridgedHistogram(instrument, attributes(key))))
+        });
+        histogram.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::App;
+    use futures::future::BoxFuture;
+    use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+
+    #[derive(Debug, Clone, Default)]
+    struct Collected(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for Collected {
+        fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_telemetry_traces_requests_and_cache_operations() {
+        let collected = Collected::default();
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        global::set_tracer_provider(TracerProvider::builder().with_simple_exporter(collected.clone()).build());
+
+        let cache = MemoryCache::new(None);
+        let app = App::new().middleware(Telemetry::new()).get("/users/:id", move |_req: Request| {
+            let cache = cache.clone();
+            async move {
+                cache.get("user:7").await;
+                traced_job("SendWelcome", "mail", 1, async { Err::<(), _>("mailbox full") }).await.ok();
+                Response::ok()
+            }
+        });
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let req = Request::mock(http::Method::GET, "/users/7")
+            .with_header("traceparent", &format!("00-{}-00f067aa0ba902b7-01", trace_id));
+        app.handle_request(req).await;
+
+        let spans: Vec<SpanData> = collected.0.lock().unwrap().iter().filter(|span| span.span_context.trace_id().to_string() == trace_id).cloned().collect();
+        let named = |name: &str| spans.iter().find(|span| span.name == name).unwrap_or_else(|| panic!("no {} span in {:?}", name, spans));
+        let request = named("GET /users/:id");
+        assert_eq!(request.span_kind, SpanKind::Server);
+        assert_eq!(request.parent_span_id.to_string(), "00f067aa0ba902b7");
+        assert!(request.attributes.contains(&KeyValue::new("http.response.status_code", 200)));
+
+        let cache = named("cache get");
+        assert_eq!(cache.parent_span_id, request.span_context.span_id());
+        assert!(cache.attributes.contains(&KeyValue::new("cache.hit", false)));
+        let job = named("mail process");
+        assert_eq!(job.parent_span_id, request.span_context.span_id());
+        assert_eq!(job.status, Status::error("mailbox full"));
+    }
+}
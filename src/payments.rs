@@ -0,0 +1,10 @@
+//! # Payments
+//!
+//! Webhook wiring for payment providers, behind the `payments` feature.
+//!
+//! - [`stripe`]: verifies `Stripe-Signature`, reads events into
+//!   [`StripeEvent`](stripe::StripeEvent) and processes each event id once.
+
+pub mod stripe;
+
+pub use stripe::{StripeEvent, StripeWebhook, WebhookError};
@@ -0,0 +1,443 @@
+//! Stripe webhooks
+//!
+//! [`StripeWebhook::handler`] turns a function over verified events into a
+//! route handler:
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//! use torch_web::App;
+//! use torch_web::cache::MemoryCache;
+//! use torch_web::payments::stripe::{Event, StripeEvent, StripeWebhook};
+//!
+//! async fn handle(event: Event) -> Result<(), String> {
+//!     match event.kind {
+//!         StripeEvent::CheckoutSessionCompleted(session) => {
+//!             println!("Customer {:?} paid for order {:?}", session.customer, session.client_reference_id);
+//!         }
+//!         StripeEvent::CustomerSubscriptionDeleted(subscription) => {
+//!             println!("Subscription {} ended", subscription.id);
+//!         }
+//!         _ => {}
+//!     }
+//!     Ok(())
+//! }
+//!
+//! let webhook = StripeWebhook::new(std::env::var("STRIPE_WEBHOOK_SECRET").unwrap())
+//!     .idempotency_store(Arc::new(MemoryCache::new(None)));
+//! let app = App::new().post("/webhooks/stripe", webhook.handler(handle));
+//! ```
+//!
+//! - Requests whose `Stripe-Signature` doesn't match the raw body, or was
+//!   made more than five minutes ago, get `400`.
+//! - Each event id is handled once. Stripe redelivers events it isn't sure
+//!   arrived, and those get `200` without running the handler again; a
+//!   delivery that arrives while the first is still running gets `409`.
+//! - When the handler returns an error the response is `500` and the event
+//!   is forgotten, so Stripe's retry runs it again.
+//!
+//! The idempotency store is process-local unless given a shared cache such
+//! as `RedisCache`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::cache::{Cache, MemoryCache};
+use crate::clock::unix_now;
+use crate::{Request, Response};
+
+/// How long a handled event id is remembered; Stripe stops retrying after
+/// three days
+const PROCESSED_TTL: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// How long a delivery that never finished blocks redeliveries
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Why a webhook request was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookError {
+    /// No `Stripe-Signature` header
+    MissingSignature,
+    /// The header is malformed or no signature in it matches the body
+    InvalidSignature,
+    /// The signature's timestamp is outside the tolerance
+    Expired,
+    /// The body isn't a Stripe event
+    InvalidPayload(String),
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::MissingSignature => write!(f, "Missing Stripe-Signature header"),
+            WebhookError::InvalidSignature => write!(f, "Invalid Stripe signature"),
+            WebhookError::Expired => write!(f, "Stripe signature timestamp is too old"),
+            WebhookError::InvalidPayload(message) => write!(f, "Invalid Stripe event: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// A verified Stripe event
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// `evt_...`, the same for every delivery of the event
+    pub id: String,
+    /// Such as `invoice.paid`
+    pub event_type: String,
+    /// Unix timestamp, in seconds
+    pub created: i64,
+    pub livemode: bool,
+    pub api_version: Option<String>,
+    /// The event's object, typed for the events listed in [`StripeEvent`]
+    pub kind: StripeEvent,
+    /// `data.object` as sent
+    pub object: serde_json::Value,
+    /// `data.previous_attributes` of `*.updated` events
+    pub previous_attributes: Option<serde_json::Value>,
+}
+
+impl Event {
+    /// Read `data.object` as `T`, for events [`StripeEvent`] doesn't type
+    pub fn object<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(&self.object)
+    }
+}
+
+/// Events SaaS apps usually act on, with their object. Anything else is
+/// [`Other`](StripeEvent::Other); read it with [`Event::object`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StripeEvent {
+    CheckoutSessionCompleted(CheckoutSession),
+    CheckoutSessionExpired(CheckoutSession),
+    PaymentIntentSucceeded(PaymentIntent),
+    PaymentIntentPaymentFailed(PaymentIntent),
+    InvoicePaid(Invoice),
+    InvoicePaymentFailed(Invoice),
+    CustomerSubscriptionCreated(Subscription),
+    CustomerSubscriptionUpdated(Subscription),
+    CustomerSubscriptionDeleted(Subscription),
+    ChargeRefunded(Charge),
+    Other,
+}
+
+impl StripeEvent {
+    fn parse(event_type: &str, object: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        fn read<T: DeserializeOwned>(object: &serde_json::Value) -> Result<T, serde_json::Error> {
+            T::deserialize(object)
+        }
+
+        Ok(match event_type {
+            "checkout.session.completed" => StripeEvent::CheckoutSessionCompleted(read(object)?),
+            "checkout.session.expired" => StripeEvent::CheckoutSessionExpired(read(object)?),
+            "payment_intent.succeeded" => StripeEvent::PaymentIntentSucceeded(read(object)?),
+            "payment_intent.payment_failed" => StripeEvent::PaymentIntentPaymentFailed(read(object)?),
+            "invoice.paid" => StripeEvent::InvoicePaid(read(object)?),
+            "invoice.payment_failed" => StripeEvent::InvoicePaymentFailed(read(object)?),
+            "customer.subscription.created" => StripeEvent::CustomerSubscriptionCreated(read(object)?),
+            "customer.subscription.updated" => StripeEvent::CustomerSubscriptionUpdated(read(object)?),
+            "customer.subscription.deleted" => StripeEvent::CustomerSubscriptionDeleted(read(object)?),
+            "charge.refunded" => StripeEvent::ChargeRefunded(read(object)?),
+            _ => StripeEvent::Other,
+        })
+    }
+}
+
+/// A Checkout Session. Amounts are in the currency's smallest unit.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CheckoutSession {
+    pub id: String,
+    pub customer: Option<String>,
+    /// The id the app passed when creating the session, e.g. an order id
+    pub client_reference_id: Option<String>,
+    /// `payment`, `subscription` or `setup`
+    pub mode: Option<String>,
+    pub payment_status: Option<String>,
+    pub payment_intent: Option<String>,
+    pub subscription: Option<String>,
+    pub amount_total: Option<i64>,
+    pub currency: Option<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PaymentIntent {
+    pub id: String,
+    pub customer: Option<String>,
+    pub amount: i64,
+    pub amount_received: i64,
+    pub currency: String,
+    pub status: String,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Invoice {
+    pub id: String,
+    pub customer: Option<String>,
+    pub subscription: Option<String>,
+    pub amount_due: i64,
+    pub amount_paid: i64,
+    pub currency: String,
+    pub status: Option<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    pub id: String,
+    pub customer: Option<String>,
+    /// `active`, `past_due`, `canceled`, ...
+    pub status: String,
+    pub current_period_end: Option<i64>,
+    pub cancel_at_period_end: bool,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Charge {
+    pub id: String,
+    pub customer: Option<String>,
+    pub payment_intent: Option<String>,
+    pub amount: i64,
+    pub amount_refunded: i64,
+    pub currency: String,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    id: String,
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    created: i64,
+    #[serde(default)]
+    livemode: bool,
+    api_version: Option<String>,
+    data: RawEventData,
+}
+
+#[derive(Deserialize)]
+struct RawEventData {
+    object: serde_json::Value,
+    previous_attributes: Option<serde_json::Value>,
+}
+
+/// Verifies and dispatches Stripe webhook deliveries, see the
+/// [module docs](self)
+#[derive(Clone)]
+pub struct StripeWebhook {
+    secret: String,
+    tolerance: Duration,
+    store: Arc<dyn Cache>,
+    prefix: String,
+}
+
+impl StripeWebhook {
+    /// `secret` is the endpoint's signing secret (`whsec_...`)
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            tolerance: Duration::from_secs(300),
+            store: Arc::new(MemoryCache::new(None)),
+            prefix: "torch_stripe_event:".to_string(),
+        }
+    }
+
+    /// How old a signature may be (default 5 minutes)
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Remember handled event ids in `store` instead of process memory
+    pub fn idempotency_store(mut self, store: Arc<dyn Cache>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Check `signature` (the `Stripe-Signature` header) against the raw
+    /// body and read the event
+    pub fn verify(&self, payload: &[u8], signature: &str) -> Result<Event, WebhookError> {
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for pair in signature.split(',') {
+            match pair.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<u64>().ok(),
+                Some(("v1", value)) => signatures.push(hex::decode(value).map_err(|_| WebhookError::InvalidSignature)?),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.ok_or(WebhookError::InvalidSignature)?;
+
+        let matches = signatures.iter().any(|signature| self.mac(timestamp, payload).verify_slice(signature).is_ok());
+        if !matches {
+            return Err(WebhookError::InvalidSignature);
+        }
+        let now = unix_now();
+        if now.abs_diff(timestamp) > self.tolerance.as_secs() {
+            return Err(WebhookError::Expired);
+        }
+
+        let raw: RawEvent =
+            serde_json::from_slice(payload).map_err(|error| WebhookError::InvalidPayload(error.to_string()))?;
+        let kind = StripeEvent::parse(&raw.event_type, &raw.data.object).unwrap_or_else(|error| {
+            eprintln!("⚠️  Stripe event {} ({}) has an unexpected object: {}", raw.id, raw.event_type, error);
+            StripeEvent::Other
+        });
+        Ok(Event {
+            id: raw.id,
+            event_type: raw.event_type,
+            created: raw.created,
+            livemode: raw.livemode,
+            api_version: raw.api_version,
+            kind,
+            object: raw.data.object,
+            previous_attributes: raw.data.previous_attributes,
+        })
+    }
+
+    /// A `Stripe-Signature` header for `payload` signed at `timestamp`, for
+    /// testing webhook handlers
+    pub fn signature_header(&self, payload: &[u8], timestamp: u64) -> String {
+        format!("t={},v1={}", timestamp, hex::encode(self.mac(timestamp, payload).finalize().into_bytes()))
+    }
+
+    fn mac(&self, timestamp: u64, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(payload);
+        mac
+    }
+
+    /// Route handler that verifies each delivery and runs `handle` once per
+    /// event id
+    pub fn handler<F, Fut, E>(
+        self,
+        handle: F,
+    ) -> impl Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Clone + Send + Sync + 'static
+    where
+        F: Fn(Event) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send,
+    {
+        let webhook = Arc::new(self);
+        move |req: Request| {
+            let webhook = webhook.clone();
+            let handle = handle.clone();
+            Box::pin(async move { webhook.process(req, handle).await })
+        }
+    }
+
+    async fn process<F, Fut, E>(&self, req: Request, handle: F) -> Response
+    where
+        F: Fn(Event) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let event = req
+            .header("stripe-signature")
+            .ok_or(WebhookError::MissingSignature)
+            .and_then(|signature| self.verify(req.body(), signature));
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => return Response::bad_request().body(error.to_string()),
+        };
+
+        let key = format!("{}{}", self.prefix, event.id);
+        match self.store.add(&key, "processing", Some(LOCK_TIMEOUT)).await.map_err(|e| e.to_string()) {
+            Ok(true) => {}
+            Ok(false) => {
+                return match self.store.get(&key).await.as_deref() {
+                    Some("processed") => Response::ok().body("Event already processed"),
+                    _ => Response::with_status(http::StatusCode::CONFLICT).body("Event is still being processed"),
+                };
+            }
+            // Without the store there is no protection, but the event still gets handled
+            Err(error) => eprintln!("⚠️  Stripe idempotency store unavailable: {}", error),
+        }
+
+        let (id, event_type) = (event.id.clone(), event.event_type.clone());
+        match handle(event).await {
+            Ok(()) => {
+                if let Err(error) = self.store.set(&key, "processed", Some(PROCESSED_TTL)).await.map_err(|e| e.to_string()) {
+                    eprintln!("⚠️  Could not mark Stripe event {} processed: {}", id, error);
+                }
+                Response::ok().body("OK")
+            }
+            Err(error) => {
+                eprintln!("⚠️  Stripe event {} ({}) failed: {}", id, event_type, error);
+                let _ = self.store.delete(&key).await.map_err(|e| e.to_string());
+                Response::internal_error().body("Event handler failed")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_webhook_verifies_types_and_processes_events_once() {
+        let webhook = StripeWebhook::new("whsec_test");
+        let payload = br#"{"id":"evt_1","type":"checkout.session.completed","created":1700000000,"livemode":false,
+            "data":{"object":{"id":"cs_1","customer":"cus_1","client_reference_id":"order-7","amount_total":4200,"currency":"eur","metadata":{"plan":"pro"}}}}"#;
+        let now = unix_now();
+
+        let event = webhook.verify(payload, &webhook.signature_header(payload, now)).unwrap();
+        let StripeEvent::CheckoutSessionCompleted(session) = &event.kind else {
+            panic!("unexpected event {:?}", event.kind);
+        };
+        assert_eq!((session.client_reference_id.as_deref(), session.amount_total), (Some("order-7"), Some(4200)));
+        assert_eq!(session.metadata["plan"], "pro");
+
+        let other = StripeWebhook::new("whsec_other");
+        assert_eq!(webhook.verify(payload, &other.signature_header(payload, now)), Err(WebhookError::InvalidSignature));
+        assert_eq!(webhook.verify(payload, &webhook.signature_header(payload, now - 600)), Err(WebhookError::Expired));
+
+        // The first run fails, Stripe's retry succeeds, and later deliveries are skipped
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        let handler = webhook.clone().handler(move |_event: Event| {
+            let run = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { if run == 1 { Err("database down") } else { Ok(()) } }
+        });
+        let deliver = |signature: String| {
+            Request::mock(Method::POST, "/webhooks/stripe")
+                .with_header("stripe-signature", &signature)
+                .with_body(payload.to_vec())
+        };
+        let statuses = [
+            handler(deliver(webhook.signature_header(payload, now))).await.status_code(),
+            handler(deliver(webhook.signature_header(payload, now))).await.status_code(),
+            handler(deliver(webhook.signature_header(payload, now))).await.status_code(),
+            handler(deliver("t=1,v1=00".to_string())).await.status_code(),
+        ];
+        assert_eq!(statuses.map(|status| status.as_u16()), [500, 200, 200, 400]);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,384 @@
+//! # PDF Generation
+//!
+//! Render Ember templates to PDF for invoices, receipts and reports. The
+//! printing is done by a [`PdfBackend`]; the built-in [`PdfCommand`] runs a
+//! command line tool such as headless Chromium or WeasyPrint, so the server
+//! needs one installed.
+//!
+//! ```rust,no_run
+//! use torch_web::ember::EmberData;
+//! use torch_web::pdf::{PageSize, Pdf, PdfCommand};
+//! use torch_web::{App, Response};
+//!
+//! torch_web::pdf::use_renderer(
+//!     Pdf::new(PdfCommand::weasyprint())
+//!         .page_size(PageSize::Letter)
+//!         .base_url("https://app.acme.com/"),
+//! );
+//!
+//! let app = App::new().get("/invoices/:id/pdf", |req: torch_web::Request| async move {
+//!     let id = req.param("id").unwrap_or_default().to_string();
+//!     let data = EmberData::new().with("number", id.clone());
+//!     Response::ember_pdf("invoices/show", data, &format!("invoice-{}.pdf", id)).await
+//! });
+//! ```
+//!
+//! Page size, orientation and margins are applied as an `@page` rule at the
+//! start of the document, so a template's own `@page` rules win. Relative
+//! links to stylesheets and images resolve against [`Pdf::base_url`];
+//! without one, use absolute URLs.
+//!
+//! Without [`use_renderer`], PDFs are printed by headless Chromium found on
+//! the `PATH` with A4 pages.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::ember::{EmberData, EmberEngine, EmberError};
+use crate::Response;
+
+/// Future returned by [`PdfBackend::print`]
+pub type PdfFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, PdfError>> + Send + 'a>>;
+
+/// Turns a complete HTML document into a PDF
+pub trait PdfBackend: Send + Sync + 'static {
+    fn print<'a>(&'a self, html: &'a str) -> PdfFuture<'a>;
+}
+
+/// Why a PDF couldn't be made
+#[derive(Debug)]
+pub enum PdfError {
+    Template(EmberError),
+    /// The backend failed; holds what it reported
+    Backend(String),
+    /// The backend took longer than its timeout
+    Timeout(Duration),
+}
+
+impl std::fmt::Display for PdfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PdfError::Template(error) => write!(f, "{}", error),
+            PdfError::Backend(message) => write!(f, "PDF backend error: {}", message),
+            PdfError::Timeout(timeout) => write!(f, "PDF backend timed out after {}s", timeout.as_secs()),
+        }
+    }
+}
+
+impl std::error::Error for PdfError {}
+
+impl From<EmberError> for PdfError {
+    fn from(error: EmberError) -> Self {
+        PdfError::Template(error)
+    }
+}
+
+/// A command line tool that prints an HTML file to a PDF file. In its
+/// arguments, `{input}` is the HTML file, `{output}` the PDF to write and
+/// `{dir}` a scratch directory that is removed afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfCommand {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl PdfCommand {
+    pub fn new<I, S>(program: impl Into<PathBuf>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Headless Chromium or Chrome, the first of `chromium`,
+    /// `chromium-browser`, `google-chrome` and `google-chrome-stable` on the
+    /// `PATH`
+    pub fn chromium() -> Self {
+        let program = ["chromium", "chromium-browser", "google-chrome", "google-chrome-stable"]
+            .into_iter()
+            .find_map(find_program)
+            .unwrap_or_else(|| PathBuf::from("chromium"));
+        Self::chromium_at(program)
+    }
+
+    /// Headless Chromium installed at `program`
+    pub fn chromium_at(program: impl Into<PathBuf>) -> Self {
+        Self::new(
+            program,
+            [
+                "--headless",
+                "--disable-gpu",
+                "--no-first-run",
+                "--user-data-dir={dir}/profile",
+                "--no-pdf-header-footer",
+                "--print-to-pdf={output}",
+                "file://{input}",
+            ],
+        )
+    }
+
+    /// [WeasyPrint](https://weasyprint.org), found on the `PATH`
+    pub fn weasyprint() -> Self {
+        Self::new("weasyprint", ["--quiet", "{input}", "{output}"])
+    }
+
+    /// Add an option ahead of the file arguments, such as `--no-sandbox`
+    /// for Chromium running as root in a container
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        let position = self.args.iter().position(|arg| arg.contains("{input}")).unwrap_or(self.args.len());
+        self.args.insert(position, arg.into());
+        self
+    }
+
+    /// How long the tool may run before it is killed (default 30 seconds)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn run(&self, html: &str, dir: &Path) -> Result<Vec<u8>, PdfError> {
+        let failed = |action: &str, error: std::io::Error| PdfError::Backend(format!("could not {}: {}", action, error));
+        tokio::fs::create_dir_all(dir).await.map_err(|error| failed("create a scratch directory", error))?;
+        let input = dir.join("input.html");
+        let output = dir.join("output.pdf");
+        tokio::fs::write(&input, html).await.map_err(|error| failed("write the HTML", error))?;
+
+        let args = self.args.iter().map(|arg| {
+            arg.replace("{input}", &input.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+                .replace("{dir}", &dir.to_string_lossy())
+        });
+        let child = tokio::process::Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let program = self.program.display();
+        let finished = tokio::time::timeout(self.timeout, child)
+            .await
+            .map_err(|_| PdfError::Timeout(self.timeout))?
+            .map_err(|error| PdfError::Backend(format!("could not run {}: {}", program, error)))?;
+        if !finished.status.success() {
+            let stderr = String::from_utf8_lossy(&finished.stderr);
+            return Err(PdfError::Backend(format!("{} failed: {}", program, stderr.trim())));
+        }
+        match tokio::fs::read(&output).await {
+            Ok(pdf) if !pdf.is_empty() => Ok(pdf),
+            _ => Err(PdfError::Backend(format!("{} wrote no PDF", program))),
+        }
+    }
+}
+
+impl PdfBackend for PdfCommand {
+    fn print<'a>(&'a self, html: &'a str) -> PdfFuture<'a> {
+        Box::pin(async move {
+            static COUNTER: AtomicU64 = AtomicU64::new(0);
+            let dir = std::env::temp_dir().join(format!("torch-pdf-{}-{}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+            let result = self.run(html, &dir).await;
+            let _ = tokio::fs::remove_dir_all(&dir).await;
+            result
+        })
+    }
+}
+
+/// The named program in a directory of the `PATH`
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).map(|dir| dir.join(name)).find(|candidate| candidate.is_file())
+}
+
+/// Paper sizes for [`Pdf::page_size`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageSize {
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+    /// Width and height in millimetres
+    Custom(f32, f32),
+}
+
+impl PageSize {
+    fn css(self) -> String {
+        match self {
+            PageSize::A3 => "A3".to_string(),
+            PageSize::A4 => "A4".to_string(),
+            PageSize::A5 => "A5".to_string(),
+            PageSize::Letter => "letter".to_string(),
+            PageSize::Legal => "legal".to_string(),
+            PageSize::Custom(width, height) => format!("{}mm {}mm", width, height),
+        }
+    }
+}
+
+/// Templates, page setup and the backend that prints them
+#[derive(Clone)]
+pub struct Pdf {
+    backend: Arc<dyn PdfBackend>,
+    engine: EmberEngine,
+    page_size: PageSize,
+    landscape: bool,
+    margin_mm: f32,
+    base_url: Option<String>,
+}
+
+impl Pdf {
+    /// A4 portrait pages with 15mm margins, printed by `backend`, with
+    /// templates from the default Ember engine
+    pub fn new(backend: impl PdfBackend) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            engine: EmberEngine::new(),
+            page_size: PageSize::A4,
+            landscape: false,
+            margin_mm: 15.0,
+            base_url: None,
+        }
+    }
+
+    /// Render templates with this engine instead
+    pub fn engine(mut self, engine: EmberEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn page_size(mut self, size: PageSize) -> Self {
+        self.page_size = size;
+        self
+    }
+
+    pub fn landscape(mut self) -> Self {
+        self.landscape = true;
+        self
+    }
+
+    /// Margin on every side, in millimetres
+    pub fn margin_mm(mut self, margin: f32) -> Self {
+        self.margin_mm = margin;
+        self
+    }
+
+    /// Where relative URLs in the document point, usually the app's own
+    /// URL so stylesheets and images load as they do in the browser
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Print an HTML document
+    pub async fn html(&self, html: &str) -> Result<Vec<u8>, PdfError> {
+        self.backend.print(&self.prepare(html)).await
+    }
+
+    /// Render an Ember template and print it
+    pub async fn template(&self, template_name: &str, data: EmberData) -> Result<Vec<u8>, PdfError> {
+        let html = self.engine.render(template_name, data).await?;
+        self.html(&html).await
+    }
+
+    /// The document with the page setup, and base URL, at the start of its
+    /// head
+    fn prepare(&self, html: &str) -> String {
+        let orientation = if self.landscape { " landscape" } else { "" };
+        let mut setup = format!(
+            "<style>@page {{ size: {}{}; margin: {}mm; }} html {{ -webkit-print-color-adjust: exact; print-color-adjust: exact; }}</style>",
+            self.page_size.css(),
+            orientation,
+            self.margin_mm
+        );
+        if let Some(url) = &self.base_url {
+            setup.insert_str(0, &format!("<base href=\"{}\">", url.replace('&', "&amp;").replace('"', "&quot;")));
+        }
+
+        let lower = html.to_ascii_lowercase();
+        let position = match lower.find("<head") {
+            Some(head) => lower[head..].find('>').map(|end| head + end + 1),
+            None => None,
+        };
+        match position {
+            Some(position) => format!("{}{}{}", &html[..position], setup, &html[position..]),
+            None => format!("{}{}", setup, html),
+        }
+    }
+}
+
+static RENDERER: RwLock<Option<Pdf>> = RwLock::new(None);
+
+/// Print with `pdf` wherever the app makes PDFs without naming a
+/// renderer, such as [`Response::ember_pdf`]
+pub fn use_renderer(pdf: Pdf) {
+    *RENDERER.write().unwrap() = Some(pdf);
+}
+
+/// The renderer set with [`use_renderer`], or headless Chromium with A4
+/// pages
+pub fn renderer() -> Pdf {
+    RENDERER.read().unwrap().clone().unwrap_or_else(|| Pdf::new(PdfCommand::chromium()))
+}
+
+impl Response {
+    /// Render an Ember template to PDF with the app's [`renderer`] and send
+    /// it as a download called `filename`
+    pub async fn ember_pdf(template_name: &str, data: EmberData, filename: &str) -> Self {
+        match renderer().template(template_name, data).await {
+            Ok(pdf) => Response::pdf(pdf).attachment(filename),
+            Err(error) => {
+                eprintln!("⚠️  Could not make {}: {}", filename, error);
+                Response::internal_error()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ember::EmberConfig;
+
+    #[tokio::test]
+    async fn test_templates_print_with_page_setup() {
+        let dir = std::env::temp_dir().join(format!("torch-pdf-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("invoice.ember"), "<html><HEAD><title>Invoice</title></HEAD><body>Invoice {{ $number }}</body></html>").unwrap();
+        let engine = EmberEngine::with_config(EmberConfig { template_dir: dir.clone(), cache_enabled: false, ..Default::default() });
+
+        // Stands in for a real printer by copying the HTML it is given
+        let copy = PdfCommand::new("sh", ["-c", "cp \"$0\" \"$1\"", "{input}", "{output}"]);
+        let pdf = Pdf::new(copy).engine(engine).page_size(PageSize::Letter).landscape().base_url("https://acme.test/");
+        let printed = String::from_utf8(pdf.template("invoice", EmberData::new().with("number", "INV-7")).await.unwrap()).unwrap();
+        assert_eq!(
+            printed,
+            "<html><HEAD><base href=\"https://acme.test/\"><style>@page { size: letter landscape; margin: 15mm; } \
+             html { -webkit-print-color-adjust: exact; print-color-adjust: exact; }</style><title>Invoice</title></HEAD>\
+             <body>Invoice INV-7</body></html>"
+        );
+        assert!(pdf.html("<p>no head</p>").await.unwrap().ends_with(b"</style><p>no head</p>"));
+
+        let failing = Pdf::new(PdfCommand::new("sh", ["-c", "echo broken >&2; exit 3"]));
+        assert!(matches!(failing.html("<p>x</p>").await, Err(PdfError::Backend(message)) if message == "sh failed: broken"));
+        let slow = Pdf::new(PdfCommand::new("sleep", ["5"]).timeout(Duration::from_millis(50)));
+        assert!(matches!(slow.html("<p>x</p>").await, Err(PdfError::Timeout(_))));
+
+        let response = Response::pdf(b"%PDF-1.7".to_vec()).attachment("Rechnung März.pdf");
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/pdf");
+        assert_eq!(
+            response.headers().get("content-disposition").unwrap(),
+            "attachment; filename=\"Rechnung M_rz.pdf\"; filename*=UTF-8''Rechnung%20M%C3%A4rz.pdf"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+//! # Policies
+//!
+//! A policy decides what a user may do with a model. Register one per model
+//! with [`App::policy`](crate::App::policy), and take [`Can<Ability, Model>`]
+//! in a handler: it loads the model named by the route's `:id`, checks the
+//! current user's ability, and answers `401`, `403` or `404` through the
+//! app's error pages before the handler runs.
+//!
+//! ```rust,no_run
+//! use torch_web::App;
+//! use torch_web::policy::{Can, Policy, Update};
+//! # use torch_web::policy::{RouteModel, ResolveFuture};
+//!
+//! #[derive(Clone)]
+//! struct User {
+//!     id: i64,
+//!     admin: bool,
+//! }
+//!
+//! struct Post {
+//!     author_id: i64,
+//!     title: String,
+//! }
+//! # impl RouteModel for Post {
+//! #     fn resolve(_key: String) -> ResolveFuture<Self> {
+//! #         Box::pin(async { Ok(None) })
+//! #     }
+//! # }
+//!
+//! struct PostPolicy;
+//!
+//! impl Policy<Post> for PostPolicy {
+//!     type User = User;
+//!
+//!     fn allows(&self, user: &User, ability: &str, post: &Post) -> bool {
+//!         match ability {
+//!             "view" => true,
+//!             _ => user.admin || user.id == post.author_id,
+//!         }
+//!     }
+//! }
+//!
+//! let app = App::new()
+//!     .policy(PostPolicy)
+//!     .put("/posts/:id", |post: Can<Update, Post>| async move {
+//!         format!("editing {}", post.title)
+//!     });
+//! ```
+//!
+//! The current user is the request extension of the policy's `User` type,
+//! which the app's authentication middleware inserts with
+//! [`Request::insert_extension`]. ORM models are bound by their primary key;
+//! other types implement [`RouteModel`].
+//!
+//! Abilities other than [`View`], [`Create`], [`Update`] and [`Delete`] are
+//! unit structs implementing [`Ability`]. Checks outside an extractor go
+//! through [`authorize`].
+
+use std::any::TypeId;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::extractors::state::RequestStateExt;
+use crate::extractors::{FromRequestParts, IntoResponse};
+use crate::{Request, Response, StatusCode};
+
+/// Path parameter naming the model to load
+pub const ROUTE_PARAM: &str = "id";
+
+/// Something a user may or may not do, named in [`Policy::allows`]
+pub trait Ability: Send + 'static {
+    const NAME: &'static str;
+}
+
+macro_rules! abilities {
+    ($($ability:ident => $name:literal),* $(,)?) => {
+        $(
+            #[doc = concat!("The `", $name, "` ability")]
+            #[derive(Debug, Clone, Copy)]
+            pub struct $ability;
+
+            impl Ability for $ability {
+                const NAME: &'static str = $name;
+            }
+        )*
+    };
+}
+
+abilities!(View => "view", Create => "create", Update => "update", Delete => "delete");
+
+/// Decides what users may do with models of type `M`
+pub trait Policy<M>: Send + Sync + 'static {
+    /// The signed-in user, found among the request's extensions
+    type User: Clone + Send + Sync + 'static;
+
+    fn allows(&self, user: &Self::User, ability: &str, model: &M) -> bool;
+}
+
+/// Future returned by [`RouteModel::resolve`]
+pub type ResolveFuture<M> = Pin<Box<dyn Future<Output = Result<Option<M>, String>> + Send + 'static>>;
+
+/// A type [`Can`] loads from the route's `:id` parameter
+pub trait RouteModel: Sized + Send + 'static {
+    /// Load the value `key` names, or `None` if there is none
+    fn resolve(key: String) -> ResolveFuture<Self>;
+}
+
+#[cfg(feature = "database")]
+impl<M: crate::orm::Model> RouteModel for M {
+    fn resolve(key: String) -> ResolveFuture<Self> {
+        Box::pin(async move {
+            // Numeric keys parse as JSON numbers, UUIDs and slugs as strings
+            let id = serde_json::from_str::<M::PrimaryKey>(&key)
+                .or_else(|_| serde_json::from_value(serde_json::Value::String(key)))
+                .map_err(|_| "invalid key".to_string());
+            match id {
+                Ok(id) => M::find(id).await.map_err(|e| e.to_string()),
+                Err(_) => Ok(None),
+            }
+        })
+    }
+}
+
+/// Checks an ability against one model, for the user of the request it was
+/// made from
+type Check<M> = Box<dyn Fn(&str, &M) -> bool + Send>;
+
+/// Builds the [`Check`] for a request's user, if there is one
+type Checker<M> = Arc<dyn Fn(&Request) -> Option<Check<M>> + Send + Sync>;
+
+/// The policy registered for `M`, as app state
+pub(crate) struct Registered<M: 'static> {
+    check: Checker<M>,
+}
+
+impl<M: 'static> Clone for Registered<M> {
+    fn clone(&self) -> Self {
+        Self { check: self.check.clone() }
+    }
+}
+
+impl<M: 'static> Registered<M> {
+    pub(crate) fn new<P: Policy<M>>(policy: P) -> Self {
+        let policy = Arc::new(policy);
+        Self {
+            check: Arc::new(move |req: &Request| {
+                let user = req.get_extension::<P::User>()?.clone();
+                let policy = policy.clone();
+                Some(Box::new(move |ability: &str, model: &M| policy.allows(&user, ability, model)) as Check<M>)
+            }),
+        }
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthorizationError {
+    /// Nobody is signed in
+    Unauthenticated,
+    /// The policy doesn't allow it
+    Forbidden,
+    /// The route's model doesn't exist
+    NotFound,
+    /// No policy is registered for the model
+    NoPolicy(&'static str),
+    /// Loading the model failed
+    Load(String),
+}
+
+impl fmt::Display for AuthorizationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthorizationError::Unauthenticated => write!(f, "Not signed in"),
+            AuthorizationError::Forbidden => write!(f, "Not allowed"),
+            AuthorizationError::NotFound => write!(f, "Not found"),
+            AuthorizationError::NoPolicy(model) => write!(f, "No policy registered for {}", model),
+            AuthorizationError::Load(error) => write!(f, "Could not load model: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AuthorizationError {}
+
+/// Plain status responses, so the app's error pages render them
+impl IntoResponse for AuthorizationError {
+    fn into_response(self) -> Response {
+        match self {
+            AuthorizationError::Unauthenticated => Response::unauthorized(),
+            AuthorizationError::Forbidden => Response::forbidden(),
+            AuthorizationError::NotFound => Response::not_found(),
+            AuthorizationError::NoPolicy(_) | AuthorizationError::Load(_) => {
+                eprintln!("⚠️  {}", self);
+                Response::with_status(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+fn checker<M: 'static>(req: &Request) -> Result<Check<M>, AuthorizationError> {
+    let registered = req
+        .get_state(TypeId::of::<Registered<M>>())
+        .and_then(|state| state.downcast_ref::<Registered<M>>())
+        .ok_or(AuthorizationError::NoPolicy(std::any::type_name::<M>()))?;
+    (registered.check)(req).ok_or(AuthorizationError::Unauthenticated)
+}
+
+/// Check that the request's user may do `ability` with `model`
+pub fn authorize<M: 'static>(req: &Request, ability: &str, model: &M) -> Result<(), AuthorizationError> {
+    if checker::<M>(req)?(ability, model) {
+        Ok(())
+    } else {
+        Err(AuthorizationError::Forbidden)
+    }
+}
+
+/// The route's model, loaded and checked for ability `A`
+pub struct Can<A, M>(pub M, pub PhantomData<A>);
+
+impl<A, M> Can<A, M> {
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<A, M> Deref for Can<A, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<A: Ability, M: RouteModel> FromRequestParts for Can<A, M> {
+    type Error = AuthorizationError;
+
+    fn from_request_parts(req: &mut Request) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let key = req.param(ROUTE_PARAM).map(str::to_string);
+        let check = checker::<M>(req);
+        Box::pin(async move {
+            let check = check?;
+            let key = key.ok_or(AuthorizationError::NotFound)?;
+            let model = M::resolve(key).await.map_err(AuthorizationError::Load)?.ok_or(AuthorizationError::NotFound)?;
+            if check(A::NAME, &model) {
+                Ok(Can(model, PhantomData))
+            } else {
+                Err(AuthorizationError::Forbidden)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+
+    type Next = Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>;
+
+    #[derive(Clone)]
+    struct User(u32);
+
+    struct Note {
+        owner: u32,
+        text: &'static str,
+    }
+
+    impl RouteModel for Note {
+        fn resolve(key: String) -> ResolveFuture<Self> {
+            Box::pin(async move {
+                Ok(match key.as_str() {
+                    "1" => Some(Note { owner: 1, text: "groceries" }),
+                    "2" => Some(Note { owner: 2, text: "diary" }),
+                    _ => None,
+                })
+            })
+        }
+    }
+
+    struct NotePolicy;
+
+    impl Policy<Note> for NotePolicy {
+        type User = User;
+
+        fn allows(&self, user: &User, ability: &str, note: &Note) -> bool {
+            ability == "view" || user.0 == note.owner
+        }
+    }
+
+    #[tokio::test]
+    async fn test_can_loads_and_authorizes_models() {
+        let app = App::new()
+            .middleware(|mut req: Request, next: Next| {
+                if let Some(user) = req.header("x-user").and_then(|id| id.parse().ok()) {
+                    req.insert_extension(User(user));
+                }
+                next(req)
+            })
+            .policy(NotePolicy)
+            .get("/notes/:id", |note: Can<View, Note>| async move { note.text })
+            .delete("/notes/:id", |Can(note, _): Can<Delete, Note>| async move { format!("deleted {}", note.text) })
+            .post("/notes/:id/share", |req: Request| async move {
+                let note = Note { owner: 2, text: "diary" };
+                match authorize(&req, "share", &note) {
+                    Ok(()) => Response::ok().body("shared"),
+                    Err(error) => error.into_response(),
+                }
+            });
+
+        let call = |method: Method, path: &str, user: Option<&str>| {
+            let req = Request::mock(method, path);
+            app.handle_request(match user {
+                Some(user) => req.with_header("x-user", user),
+                None => req,
+            })
+        };
+        assert_eq!(call(Method::GET, "/notes/2", Some("1")).await.body_data(), b"diary");
+        assert_eq!(call(Method::DELETE, "/notes/1", Some("1")).await.body_data(), b"deleted groceries");
+        assert_eq!(call(Method::DELETE, "/notes/2", Some("1")).await.status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(call(Method::DELETE, "/notes/2", None).await.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(call(Method::GET, "/notes/9", Some("1")).await.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(call(Method::POST, "/notes/2/share", Some("2")).await.body_data(), b"shared");
+        assert_eq!(call(Method::POST, "/notes/2/share", Some("1")).await.status_code(), StatusCode::FORBIDDEN);
+
+        let unregistered = App::new().get("/notes/:id", |note: Can<View, Note>| async move { note.text });
+        let response = unregistered.handle_request(Request::mock(Method::GET, "/notes/1")).await;
+        assert_eq!(response.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -13,6 +13,7 @@
 //! - **Health Checks**: Built-in health check endpoints
 //! - **Graceful Shutdown**: Handle shutdown signals gracefully
 //! - **Connection Limits**: Control concurrent connection limits
+//! - **HTML Minification**: Shrink server-rendered pages with [`MinifyHtml`]
 //!
 //! ## Quick Start
 //!
@@ -112,6 +113,7 @@ use {
 ///     keep_alive_timeout: Duration::from_secs(30),
 ///     max_body_size: 8 * 1024 * 1024, // 8MB limit
 ///     enable_compression: true,
+///     minify_assets: true,
 ///     enable_http2: true,
 ///     rate_limit_rps: Some(5000), // Higher rate limit
 ///     worker_threads: Some(32), // More workers
@@ -159,6 +161,13 @@ pub struct ProductionConfig {
     /// Default: true
     pub enable_compression: bool,
 
+    /// Whether to minify server-rendered HTML, like `minify_assets` in the
+    /// `[production]` section of `torch.toml`.
+    ///
+    /// See [`MinifyHtml`] for what is removed.
+    /// Default: false
+    pub minify_assets: bool,
+
     /// Number of worker threads for the async runtime.
     ///
     /// If None, the runtime will use the default number of threads
@@ -199,6 +208,7 @@ impl Default for ProductionConfig {
             keep_alive_timeout: Duration::from_secs(60),
             max_body_size: 16 * 1024 * 1024, // 16MB
             enable_compression: true,
+            minify_assets: false,
             worker_threads: None, // Use default (number of CPU cores)
             enable_http2: true,
             rate_limit_rps: Some(1000), // 1000 requests per second per IP
@@ -207,6 +217,26 @@ impl Default for ProductionConfig {
     }
 }
 
+impl ProductionConfig {
+    /// Production settings from torch.toml: limits from `[server]`,
+    /// compression from `[performance]` and `minify_assets` from
+    /// `[production]`
+    pub fn from_torch_config(config: &crate::config::TorchConfig) -> Self {
+        Self {
+            max_connections: config.server.max_connections,
+            request_timeout: Duration::from_secs(config.server.request_timeout_secs),
+            keep_alive_timeout: Duration::from_secs(config.server.keep_alive_timeout_secs),
+            max_body_size: config.server.max_body_size,
+            enable_compression: config.performance.enable_compression,
+            minify_assets: config.production.minify_assets,
+            worker_threads: config.server.worker_threads,
+            enable_http2: config.server.enable_http2,
+            graceful_shutdown_timeout: Duration::from_secs(config.server.graceful_shutdown_timeout_secs),
+            ..Default::default()
+        }
+    }
+}
+
 /// Connection pool middleware for managing database connections
 pub struct ConnectionPool<T> {
     pool: Arc<T>,
@@ -256,6 +286,7 @@ impl Middleware for RateLimiter {
                 Ok(permit) => permit,
                 Err(_) => {
                     return Response::with_status(http::StatusCode::TOO_MANY_REQUESTS)
+                        .header("retry-after", "1")
                         .body("Rate limit exceeded");
                 }
             };
@@ -266,6 +297,84 @@ impl Middleware for RateLimiter {
     }
 }
 
+/// File `torch down` writes while the application is in maintenance mode
+pub const MAINTENANCE_FILE: &str = "storage/framework/down";
+
+/// Cookie set for visitors who bypassed maintenance mode with the secret
+const MAINTENANCE_COOKIE: &str = "torch_maintenance";
+
+/// Maintenance mode middleware
+///
+/// While the file written by `torch down` exists, every request gets
+/// `503 Service Unavailable` with the file's `retry` value as `Retry-After`,
+/// which the app's error pages turn into a "try again in..." page. A request
+/// with `?secret=<secret>` (from `torch down --secret`) is let through and
+/// gets a cookie so the rest of that visitor's requests are too. `torch up`
+/// removes the file and the app is live again.
+#[cfg(feature = "json")]
+pub struct MaintenanceMode {
+    file: std::path::PathBuf,
+}
+
+#[cfg(feature = "json")]
+impl MaintenanceMode {
+    pub fn new() -> Self {
+        Self::from_file(MAINTENANCE_FILE)
+    }
+
+    /// Watch a different file than `storage/framework/down`
+    pub fn from_file(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { file: path.into() }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "json")]
+impl Middleware for MaintenanceMode {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        let file = self.file.clone();
+        Box::pin(async move {
+            let Ok(contents) = tokio::fs::read_to_string(&file).await else {
+                return next(req).await;
+            };
+            // A half-written or hand-edited file still means the app is down
+            let down: serde_json::Value = serde_json::from_str(&contents).unwrap_or_default();
+            let retry = down["retry"].as_u64().unwrap_or(60);
+
+            if let Some(secret) = down["secret"].as_str().filter(|secret| !secret.is_empty()) {
+                let has_cookie = req.header("cookie").is_some_and(|cookies| {
+                    cookies.split(';').any(|cookie| {
+                        cookie.trim().split_once('=') == Some((MAINTENANCE_COOKIE, secret))
+                    })
+                });
+                if has_cookie {
+                    return next(req).await;
+                }
+                if req.query("secret") == Some(secret) {
+                    let cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Lax", MAINTENANCE_COOKIE, secret);
+                    return next(req).await.header("set-cookie", cookie);
+                }
+            }
+
+            let message = down["message"].as_str()
+                .unwrap_or("Application is temporarily unavailable for maintenance.");
+            Response::with_status(http::StatusCode::SERVICE_UNAVAILABLE)
+                .header("retry-after", retry.to_string())
+                .body(message.to_string())
+        })
+    }
+}
+
 /// Request timeout middleware
 pub struct RequestTimeout {
     timeout: Duration,
@@ -295,6 +404,10 @@ impl Middleware for RequestTimeout {
 }
 
 /// Request size limiting middleware
+///
+/// Bodies still being received, such as multipart uploads, are refused up
+/// front when their `Content-Length` is too big, and fail to read once they
+/// go over the limit otherwise.
 pub struct RequestSizeLimit {
     max_size: usize,
 }
@@ -308,20 +421,171 @@ impl RequestSizeLimit {
 impl Middleware for RequestSizeLimit {
     fn call(
         &self,
-        req: Request,
+        mut req: Request,
         next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
         let max_size = self.max_size;
         Box::pin(async move {
-            if req.body().len() > max_size {
+            let declared = req.header("content-length").and_then(|length| length.parse::<usize>().ok());
+            if req.body().len() > max_size || (req.is_streaming() && declared.is_some_and(|length| length > max_size)) {
                 return Response::with_status(http::StatusCode::PAYLOAD_TOO_LARGE)
                     .body("Request body too large");
             }
+            req.limit_body_stream(max_size);
             next(req).await
         })
     }
 }
 
+/// Minifies `text/html` responses: comments are stripped and runs of
+/// whitespace between and inside text collapse to a single space.
+///
+/// Tags and their attributes are copied as they are, as are the contents of
+/// `<pre>`, `<textarea>`, `<script>` and `<style>` and IE conditional
+/// comments. Streamed and already encoded responses are left alone.
+///
+/// [`App::with_production`](crate::App::with_production) installs it when
+/// `minify_assets` is set; it can also be added by hand:
+///
+/// ```rust
+/// use torch_web::{App, production::{MinifyHtml, ProductionConfig}};
+///
+/// let config = ProductionConfig { minify_assets: true, ..Default::default() };
+/// let app = App::new().middleware(MinifyHtml::from_config(&config));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MinifyHtml {
+    enabled: bool,
+}
+
+impl MinifyHtml {
+    pub fn new() -> Self {
+        Self { enabled: true }
+    }
+
+    /// Minify only when [`ProductionConfig::minify_assets`] is set
+    pub fn from_config(config: &ProductionConfig) -> Self {
+        Self { enabled: config.minify_assets }
+    }
+}
+
+impl Default for MinifyHtml {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for MinifyHtml {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        let enabled = self.enabled;
+        Box::pin(async move {
+            let response = next(req).await;
+            let is_html = response
+                .headers()
+                .get("content-type")
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.trim_start().to_ascii_lowercase().starts_with("text/html"));
+            if !enabled || !is_html || response.is_streaming() || response.headers().contains_key("content-encoding") {
+                return response;
+            }
+            let Ok(html) = std::str::from_utf8(response.body_data()) else {
+                return response;
+            };
+
+            let minified = minify_html(html);
+            if minified.len() >= html.len() {
+                return response;
+            }
+            let length = minified.len();
+            let mut response = response.body_from_bytes(minified.into_bytes());
+            if response.headers().contains_key("content-length") {
+                response.headers_mut().insert("content-length", length.into());
+            }
+            response
+        })
+    }
+}
+
+/// Elements whose contents are copied without collapsing whitespace
+const RAW_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+/// Strip comments and collapse whitespace in `html`, as done by [`MinifyHtml`]
+pub fn minify_html(html: &str) -> String {
+    let mut minified = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        collapse_whitespace(&rest[..start], &mut minified);
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").map_or(rest.len(), |end| end + "<!---->".len());
+            if comment.starts_with("[if") {
+                minified.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = tag_end(rest);
+        let tag = &rest[..end];
+        minified.push_str(tag);
+        rest = &rest[end..];
+
+        let name: String = tag[1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if RAW_ELEMENTS.contains(&name.as_str()) {
+            let close = rest.to_ascii_lowercase().find(&format!("</{}", name)).unwrap_or(rest.len());
+            minified.push_str(&rest[..close]);
+            rest = &rest[close..];
+        }
+    }
+    collapse_whitespace(rest, &mut minified);
+
+    minified.trim().to_string()
+}
+
+/// Length of the tag at the start of `html`, up to and including its `>`
+fn tag_end(html: &str) -> usize {
+    let mut quote = None;
+    for (index, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return index + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Append `text` with each run of whitespace as one space, also across a
+/// stripped comment
+fn collapse_whitespace(text: &str, into: &mut String) {
+    let mut pending = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            pending = true;
+            continue;
+        }
+        if pending && !into.ends_with(' ') {
+            into.push(' ');
+        }
+        pending = false;
+        into.push(c);
+    }
+    if pending && !into.ends_with(' ') {
+        into.push(' ');
+    }
+}
+
 /// Performance monitoring middleware
 pub struct PerformanceMonitor;
 
@@ -358,9 +622,26 @@ impl Middleware for PerformanceMonitor {
 }
 
 /// Health check middleware
+///
+/// Answers `/health` for liveness probes and `/ready` for readiness probes,
+/// which is the pair `torch deploy init` wires into its manifests. `/ready`
+/// answers `503 Service Unavailable` while no database host is answering.
 pub fn health_check() -> impl Middleware {
     |req: Request, next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>| {
         Box::pin(async move {
+            if req.path() == "/ready" {
+                #[cfg(feature = "database")]
+                if crate::orm::connection::is_initialized()
+                    && crate::orm::connection::circuit_state() == crate::orm::CircuitState::Open
+                {
+                    return Response::with_status(http::StatusCode::SERVICE_UNAVAILABLE)
+                        .json(&serde_json::json!({ "status": "unavailable", "database": "open" }))
+                        .unwrap_or_else(|_| Response::with_status(http::StatusCode::SERVICE_UNAVAILABLE).body("unavailable"));
+                }
+                return Response::ok()
+                    .json(&serde_json::json!({ "status": "ready" }))
+                    .unwrap_or_else(|_| Response::ok().body("ready"));
+            }
             if req.path() == "/health" {
                 return Response::ok()
                     .json(&{
@@ -389,25 +670,106 @@ pub fn health_check() -> impl Middleware {
 }
 
 /// Advanced metrics collection middleware with real monitoring integration
+///
+/// Requests are counted in `torch_http_requests_total` and timed in
+/// `torch_http_request_duration_seconds`, labelled with the method, the
+/// status and the route *pattern* that handled them (`/users/:id`, not
+/// `/users/42`), so the number of series stays bounded:
+///
+/// ```rust
+/// use torch_web::{App, Request, Response, production::MetricsCollector};
+///
+/// let app = App::new()
+///     // First, so it sees the final response of every other middleware
+///     .middleware(MetricsCollector::new().max_routes(200))
+///     .get("/users/:id", |_req: Request| async { Response::ok() });
+/// ```
+///
+/// Requests no route matched, such as scanners probing random URLs, share
+/// the `unmatched` route label, routes past [`max_routes`](Self::max_routes)
+/// share `other`, and unusual methods share `OTHER`.
+///
+/// Clones share their counters, so a clone can be handed to [`ResponseTiming`].
+#[derive(Clone)]
 pub struct MetricsCollector {
     #[cfg(feature = "monitoring")]
     request_counter: Arc<AtomicU64>,
     #[cfg(feature = "monitoring")]
     active_requests: Arc<AtomicU64>,
-    #[cfg(not(feature = "monitoring"))]
-    _phantom: std::marker::PhantomData<()>,
+    #[cfg(feature = "monitoring")]
+    response_bytes: Arc<AtomicU64>,
+    routes: Arc<std::sync::Mutex<std::collections::HashSet<Arc<str>>>>,
+    max_routes: usize,
 }
 
 impl MetricsCollector {
+    /// Route label for requests no route matched
+    pub const UNMATCHED: &'static str = "unmatched";
+    /// Route label once [`max_routes`](Self::max_routes) is reached
+    pub const OTHER: &'static str = "other";
+
     pub fn new() -> Self {
         Self {
             #[cfg(feature = "monitoring")]
             request_counter: Arc::new(AtomicU64::new(0)),
             #[cfg(feature = "monitoring")]
             active_requests: Arc::new(AtomicU64::new(0)),
-            #[cfg(not(feature = "monitoring"))]
-            _phantom: std::marker::PhantomData,
+            #[cfg(feature = "monitoring")]
+            response_bytes: Arc::new(AtomicU64::new(0)),
+            routes: Arc::default(),
+            max_routes: 500,
+        }
+    }
+
+    /// Label at most `max` distinct routes (default 500); requests to any
+    /// further route are labelled `other`
+    pub fn max_routes(mut self, max: usize) -> Self {
+        self.max_routes = max;
+        self
+    }
+
+    /// The `route` label for a response: the pattern of the route that
+    /// produced it, `unmatched` or `other`
+    pub fn route_label(&self, response: &Response) -> String {
+        let Some(route) = response.extensions().get::<crate::router::MatchedRoute>() else {
+            return Self::UNMATCHED.to_string();
+        };
+        let mut routes = self.routes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if routes.contains(route.as_str()) {
+            return route.as_str().to_string();
+        }
+        if routes.len() >= self.max_routes {
+            return Self::OTHER.to_string();
+        }
+        routes.insert(route.0.clone());
+        route.as_str().to_string()
+    }
+
+    /// The `method` label for a request, `OTHER` for non-standard methods
+    fn method_label(method: &http::Method) -> &'static str {
+        const KNOWN: [&str; 9] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS", "CONNECT", "TRACE"];
+        KNOWN.into_iter().find(|known| *known == method.as_str()).unwrap_or("OTHER")
+    }
+
+    /// Record how long a handler took and how large its request and response
+    /// bodies were, as `torch_handler_duration_seconds`,
+    /// `torch_request_size_bytes` and `torch_response_size_bytes` histograms
+    pub fn record_timing(&self, duration: Duration, request_bytes: usize, response_bytes: usize) {
+        #[cfg(feature = "monitoring")]
+        {
+            self.response_bytes.fetch_add(response_bytes as u64, Ordering::Relaxed);
+            metrics::histogram!("torch_handler_duration_seconds", duration.as_secs_f64());
+            metrics::histogram!("torch_request_size_bytes", request_bytes as f64);
+            metrics::histogram!("torch_response_size_bytes", response_bytes as f64);
         }
+        #[cfg(not(feature = "monitoring"))]
+        let _ = (duration, request_bytes, response_bytes);
+    }
+
+    /// Total response body bytes passed to [`record_timing`](Self::record_timing)
+    #[cfg(feature = "monitoring")]
+    pub fn get_response_bytes(&self) -> u64 {
+        self.response_bytes.load(Ordering::Relaxed)
     }
 
     #[cfg(feature = "monitoring")]
@@ -427,26 +789,415 @@ impl Middleware for MetricsCollector {
         req: Request,
         next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        let collector = self.clone();
         Box::pin(async move {
             let start = Instant::now();
-            let method = req.method().clone();
+            let method = Self::method_label(req.method());
             let path = req.path().to_string();
-            
+            #[cfg(feature = "monitoring")]
+            {
+                collector.request_counter.fetch_add(1, Ordering::Relaxed);
+                collector.active_requests.fetch_add(1, Ordering::Relaxed);
+            }
+
             let response = next(req).await;
-            
+
             let duration = start.elapsed();
             let status = response.status_code();
-            
-            // In production, send metrics to your monitoring system
-            // For now, just log them
+            let route = collector.route_label(&response);
+            #[cfg(feature = "monitoring")]
+            {
+                collector.active_requests.fetch_sub(1, Ordering::Relaxed);
+                let status = status.as_u16().to_string();
+                metrics::counter!("torch_http_requests_total", 1, "method" => method, "route" => route.clone(), "status" => status);
+                metrics::histogram!("torch_http_request_duration_seconds", duration.as_secs_f64(), "method" => method, "route" => route.clone());
+            }
+
+            // Without a metrics exporter installed, the log is the only output
             println!(
-                "METRIC: method={} path={} status={} duration_ms={:.2}",
+                "METRIC: method={} route={} path={} status={} duration_ms={:.2}",
                 method,
+                route,
                 path,
                 status.as_u16(),
                 duration.as_secs_f64() * 1000.0
             );
-            
+
+            response
+        })
+    }
+}
+
+/// Response timing middleware
+///
+/// Measures the rest of the middleware chain and the handler, then adds
+/// `X-Response-Time: 12.34ms` and an `app;dur=12.34` entry to `Server-Timing`
+/// so the time shows up in the browser devtools. Entries a handler already
+/// put in `Server-Timing` are kept.
+///
+/// ```rust
+/// use torch_web::{App, production::{MetricsCollector, ResponseTiming}};
+///
+/// let metrics = MetricsCollector::new();
+/// let app = App::new().middleware(ResponseTiming::new().with_metrics(metrics.clone()));
+/// ```
+#[derive(Clone, Default)]
+pub struct ResponseTiming {
+    metrics: Option<MetricsCollector>,
+}
+
+impl ResponseTiming {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also record each request's timing and sizes in `metrics`
+    pub fn with_metrics(mut self, metrics: MetricsCollector) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+impl Middleware for ResponseTiming {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        let metrics = self.metrics.clone();
+        Box::pin(async move {
+            let request_bytes = req.body().len();
+            let start = Instant::now();
+
+            let response = next(req).await;
+
+            let duration = start.elapsed();
+            if let Some(metrics) = &metrics {
+                metrics.record_timing(duration, request_bytes, response.body_data().len());
+            }
+
+            let millis = duration.as_secs_f64() * 1000.0;
+            let server_timing = match response.headers().get("server-timing").and_then(|value| value.to_str().ok()) {
+                Some(existing) => format!("{}, app;dur={:.2}", existing, millis),
+                None => format!("app;dur={:.2}", millis),
+            };
+            response
+                .header("server-timing", server_timing)
+                .header("x-response-time", format!("{:.2}ms", millis))
+        })
+    }
+}
+
+/// State of a [`CircuitBreaker`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through and their outcomes are counted
+    Closed,
+    /// Calls are rejected straight away until the cool-down ends
+    Open,
+    /// A few trial calls are let through to see if the dependency recovered
+    HalfOpen,
+}
+
+/// Counters for a [`CircuitBreaker`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitMetrics {
+    pub state: CircuitState,
+    pub successes: u64,
+    pub failures: u64,
+    /// Calls turned away while the circuit was open
+    pub rejected: u64,
+    /// How many times the circuit has opened
+    pub opened: u64,
+}
+
+/// Returned by [`CircuitBreaker::call`]
+#[derive(Debug, PartialEq)]
+pub enum CircuitError<E> {
+    /// The circuit is open; try again after `retry_after`
+    Open { retry_after: Duration },
+    /// The call ran and failed
+    Failed(E),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for CircuitError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitError::Open { retry_after } => {
+                write!(f, "Circuit open, retry in {}s", retry_after_secs(*retry_after))
+            }
+            CircuitError::Failed(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for CircuitError<E> {}
+
+enum BreakerState {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { trials: usize, successes: usize },
+}
+
+struct BreakerInner {
+    name: String,
+    failure_rate: f64,
+    minimum_calls: usize,
+    window_size: usize,
+    open_for: Duration,
+    half_open_calls: usize,
+    state: std::sync::Mutex<(BreakerState, std::collections::VecDeque<bool>)>,
+    successes: std::sync::atomic::AtomicU64,
+    failures: std::sync::atomic::AtomicU64,
+    rejected: std::sync::atomic::AtomicU64,
+    opened: std::sync::atomic::AtomicU64,
+}
+
+/// Circuit breaker for calls to a downstream service.
+///
+/// While closed, the outcomes of the last `window_size` calls are kept. Once at
+/// least `minimum_calls` are recorded and the share of failures reaches
+/// `failure_rate`, the circuit opens and calls fail fast for `open_for`. After
+/// that it lets `half_open_calls` trial calls through: if they all succeed the
+/// circuit closes again, and any failure opens it for another `open_for`.
+///
+/// Clones share their state, so keep one breaker per dependency.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use torch_web::production::{CircuitBreaker, CircuitError};
+///
+/// let payments = CircuitBreaker::new("payments")
+///     .failure_rate(0.5)
+///     .minimum_calls(10)
+///     .open_for(Duration::from_secs(30));
+///
+/// # tokio_test::block_on(async {
+/// let result: Result<u32, CircuitError<String>> = payments.call(async { Ok(42) }).await;
+/// assert_eq!(result, Ok(42));
+/// # });
+/// ```
+///
+/// As middleware it fails fast with `503 Service Unavailable` and a
+/// `Retry-After` header while open, and counts `5xx` responses as failures.
+/// That suits proxy routes and apps that mostly front one dependency.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str) -> Self {
+        Self {
+            inner: Arc::new(BreakerInner {
+                name: name.to_string(),
+                failure_rate: 0.5,
+                minimum_calls: 10,
+                window_size: 20,
+                open_for: Duration::from_secs(30),
+                half_open_calls: 1,
+                state: std::sync::Mutex::new((BreakerState::Closed, std::collections::VecDeque::new())),
+                successes: Default::default(),
+                failures: Default::default(),
+                rejected: Default::default(),
+                opened: Default::default(),
+            }),
+        }
+    }
+
+    fn configure(mut self, apply: impl FnOnce(&mut BreakerInner)) -> Self {
+        apply(Arc::get_mut(&mut self.inner).expect("configure a CircuitBreaker before cloning it"));
+        self
+    }
+
+    /// Share of failed calls (0.0 to 1.0) that opens the circuit, default 0.5
+    pub fn failure_rate(self, rate: f64) -> Self {
+        self.configure(|inner| inner.failure_rate = rate.clamp(0.0, 1.0))
+    }
+
+    /// Calls to see before the failure rate counts, default 10
+    pub fn minimum_calls(self, calls: usize) -> Self {
+        self.configure(|inner| inner.minimum_calls = calls.max(1))
+    }
+
+    /// How many recent calls the failure rate is taken over, default 20
+    pub fn window_size(self, calls: usize) -> Self {
+        self.configure(|inner| inner.window_size = calls.max(1))
+    }
+
+    /// How long the circuit stays open before trying again, default 30s
+    pub fn open_for(self, duration: Duration) -> Self {
+        self.configure(|inner| inner.open_for = duration)
+    }
+
+    /// Trial calls let through while half-open, default 1
+    pub fn half_open_calls(self, calls: usize) -> Self {
+        self.configure(|inner| inner.half_open_calls = calls.max(1))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match &self.inner.state.lock().unwrap().0 {
+            BreakerState::Closed => CircuitState::Closed,
+            BreakerState::Open { until } if Instant::now() < *until => CircuitState::Open,
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    pub fn metrics(&self) -> CircuitMetrics {
+        use std::sync::atomic::Ordering;
+        CircuitMetrics {
+            state: self.state(),
+            successes: self.inner.successes.load(Ordering::Relaxed),
+            failures: self.inner.failures.load(Ordering::Relaxed),
+            rejected: self.inner.rejected.load(Ordering::Relaxed),
+            opened: self.inner.opened.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Run `call` if the circuit allows it, recording whether it succeeded
+    pub async fn call<T, E, F>(&self, call: F) -> Result<T, CircuitError<E>>
+    where
+        F: std::future::Future<Output = Result<T, E>>,
+    {
+        let permit = self.acquire().map_err(|retry_after| CircuitError::Open { retry_after })?;
+        let result = call.await;
+        permit.record(result.is_ok());
+        result.map_err(CircuitError::Failed)
+    }
+
+    /// A permit to make one call, or how long until the circuit half-opens
+    fn acquire(&self) -> Result<CircuitPermit, Duration> {
+        let mut guard = self.inner.state.lock().unwrap();
+        let now = Instant::now();
+        let state = &mut guard.0;
+
+        if let BreakerState::Open { until } = *state {
+            if now < until {
+                drop(guard);
+                self.inner.rejected.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                #[cfg(feature = "monitoring")]
+                metrics::counter!("torch_circuit_breaker_rejected_total", 1, "name" => self.inner.name.clone());
+                return Err(until - now);
+            }
+            *state = BreakerState::HalfOpen { trials: 0, successes: 0 };
+        }
+
+        let trial = match state {
+            BreakerState::HalfOpen { trials, .. } if *trials >= self.inner.half_open_calls => {
+                drop(guard);
+                self.inner.rejected.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                return Err(self.inner.open_for.min(Duration::from_secs(1)));
+            }
+            BreakerState::HalfOpen { trials, .. } => {
+                *trials += 1;
+                true
+            }
+            _ => false,
+        };
+
+        Ok(CircuitPermit {
+            breaker: self.clone(),
+            trial,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, trial: bool, success: bool) {
+        use std::sync::atomic::Ordering;
+        let counter = if success { &self.inner.successes } else { &self.inner.failures };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let mut guard = self.inner.state.lock().unwrap();
+        let (state, window) = &mut *guard;
+        let open = match state {
+            BreakerState::HalfOpen { successes, .. } if trial => {
+                if !success {
+                    true
+                } else {
+                    *successes += 1;
+                    if *successes >= self.inner.half_open_calls {
+                        *state = BreakerState::Closed;
+                        window.clear();
+                    }
+                    false
+                }
+            }
+            BreakerState::Closed => {
+                window.push_back(success);
+                while window.len() > self.inner.window_size {
+                    window.pop_front();
+                }
+                let failures = window.iter().filter(|ok| !**ok).count();
+                window.len() >= self.inner.minimum_calls
+                    && failures as f64 / window.len() as f64 >= self.inner.failure_rate
+            }
+            // Outcomes of calls that started before the circuit changed state
+            _ => false,
+        };
+
+        if open {
+            *state = BreakerState::Open { until: Instant::now() + self.inner.open_for };
+            window.clear();
+            self.inner.opened.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "monitoring")]
+            metrics::counter!("torch_circuit_breaker_opened_total", 1, "name" => self.inner.name.clone());
+        }
+    }
+}
+
+/// Whole seconds to wait, rounded up so clients don't retry too early
+fn retry_after_secs(wait: Duration) -> u64 {
+    (wait.as_millis() as u64).div_ceil(1000).max(1)
+}
+
+/// One call through the breaker; dropping it unrecorded frees a trial slot
+struct CircuitPermit {
+    breaker: CircuitBreaker,
+    trial: bool,
+    recorded: bool,
+}
+
+impl CircuitPermit {
+    fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.breaker.record(self.trial, success);
+    }
+}
+
+impl Drop for CircuitPermit {
+    fn drop(&mut self) {
+        if self.trial && !self.recorded {
+            if let BreakerState::HalfOpen { trials, .. } = &mut self.breaker.inner.state.lock().unwrap().0 {
+                *trials = trials.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl Middleware for CircuitBreaker {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + 'static>> {
+        let permit = self.acquire();
+        let name = self.inner.name.clone();
+        Box::pin(async move {
+            let permit = match permit {
+                Ok(permit) => permit,
+                Err(retry_after) => {
+                    return Response::with_status(http::StatusCode::SERVICE_UNAVAILABLE)
+                        .header("retry-after", retry_after_secs(retry_after).to_string())
+                        .body(format!("{} is unavailable, try again shortly", name));
+                }
+            };
+
+            let response = next(req).await;
+            permit.record(!response.status_code().is_server_error());
             response
         })
     }
@@ -467,7 +1218,7 @@ mod tests {
             Box::pin(async { Response::ok().body("success") })
         });
 
-        let req = crate::Request::from_hyper(
+        let req = crate::Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -476,9 +1227,7 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         // First request should succeed
         let response = rate_limiter.call(req, next.clone()).await;
@@ -496,7 +1245,7 @@ mod tests {
             })
         });
 
-        let req = crate::Request::from_hyper(
+        let req = crate::Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -505,11 +1254,241 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = timeout_middleware.call(req, next).await;
         assert_eq!(response.status_code(), http::StatusCode::REQUEST_TIMEOUT);
     }
+
+    #[tokio::test]
+    async fn test_request_size_limit_caps_streamed_bodies() {
+        use futures::StreamExt;
+
+        let limit = RequestSizeLimit::new(8);
+        let next = Box::new(|mut req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            Box::pin(async move {
+                let mut body = req.take_body_stream();
+                while let Some(chunk) = body.next().await {
+                    if let Err(error) = chunk {
+                        return Response::with_status(http::StatusCode::PAYLOAD_TOO_LARGE).body(error.to_string());
+                    }
+                }
+                Response::ok()
+            })
+        });
+        let streamed = |length: &str| {
+            let chunks = vec![Ok(hyper::body::Bytes::from_static(b"12345")), Ok(hyper::body::Bytes::from_static(b"67890"))];
+            Request::mock(http::Method::POST, "/upload")
+                .with_header("content-length", length)
+                .with_body_stream(Box::pin(futures::stream::iter(chunks)))
+        };
+
+        // Refused from its declared length, then from what actually arrived
+        let response = limit.call(streamed("10"), next.clone()).await;
+        assert_eq!((response.status_code(), response.body_bytes()), (http::StatusCode::PAYLOAD_TOO_LARGE, &b"Request body too large"[..]));
+        let response = limit.call(streamed("4"), next.clone()).await;
+        assert_eq!(response.status_code(), http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(String::from_utf8_lossy(response.body_bytes()).contains("larger than 8 bytes"));
+
+        let response = RequestSizeLimit::new(10).call(streamed("10"), next).await;
+        assert_eq!(response.status_code(), http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_readiness() {
+        let health = health_check();
+
+        let next = Box::new(|_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            Box::pin(async { Response::not_found() })
+        });
+
+        let req = crate::Request::from_parts(
+            http::Request::builder()
+                .method("GET")
+                .uri("/ready")
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0,
+            Vec::new(),
+        );
+
+        let response = health.call(req, next).await;
+        assert_eq!(response.status_code(), http::StatusCode::OK);
+        assert_eq!(response.body_data(), br#"{"status":"ready"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_response_timing_headers() {
+        let timing = ResponseTiming::new().with_metrics(MetricsCollector::new());
+
+        let next = Box::new(|_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                Response::ok().header("server-timing", "db;dur=1.5").body("hello")
+            })
+        });
+
+        let req = crate::Request::from_parts(
+            http::Request::builder()
+                .method("GET")
+                .uri("/")
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0,
+            Vec::new(),
+        );
+
+        let response = timing.call(req, next).await;
+        let server_timing = response.headers().get("server-timing").unwrap().to_str().unwrap();
+        assert!(server_timing.starts_with("db;dur=1.5, app;dur="), "{}", server_timing);
+        let response_time = response.headers().get("x-response-time").unwrap().to_str().unwrap();
+        let millis: f64 = response_time.trim_end_matches("ms").parse().unwrap();
+        assert!(millis >= 5.0, "{}", response_time);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_label_requests_by_route_pattern() {
+        let metrics = MetricsCollector::new().max_routes(1);
+        let app = crate::App::new()
+            .middleware(metrics.clone())
+            .get("/users/:id", |_req: Request| async { Response::ok() })
+            .get("/posts/:id", |_req: Request| async { Response::ok() });
+        let label = |path: &'static str| {
+            let app = &app;
+            let metrics = metrics.clone();
+            async move { metrics.route_label(&app.handle_request(Request::mock(http::Method::GET, path)).await) }
+        };
+
+        assert_eq!(label("/users/7").await, "/users/:id");
+        assert_eq!(label("/posts/3").await, MetricsCollector::OTHER);
+        assert_eq!(label("/users/8").await, "/users/:id");
+        assert_eq!(label("/wp-login.php").await, MetricsCollector::UNMATCHED);
+        assert_eq!(MetricsCollector::method_label(&http::Method::from_bytes(b"PROPFIND").unwrap()), "OTHER");
+        #[cfg(feature = "monitoring")]
+        assert_eq!((metrics.get_request_count(), metrics.get_active_requests()), (4, 0));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new("payments")
+            .minimum_calls(4)
+            .failure_rate(0.5)
+            .open_for(Duration::from_millis(50));
+
+        for ok in [true, false, true] {
+            let _ = breaker.call(async move { if ok { Ok(()) } else { Err("down") } }).await;
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        let _ = breaker.call(async { Err::<(), _>("down") }).await;
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let rejected = breaker.call(async { Ok::<_, &str>(()) }).await;
+        assert!(matches!(rejected, Err(CircuitError::Open { .. })));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        // A failed trial opens it again, a successful one closes it
+        let _ = breaker.call(async { Err::<(), _>("still down") }).await;
+        assert_eq!(breaker.state(), CircuitState::Open);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(breaker.call(async { Ok::<_, &str>(7) }).await, Ok(7));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        let metrics = breaker.metrics();
+        assert_eq!((metrics.successes, metrics.failures, metrics.rejected, metrics.opened), (3, 3, 1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_middleware_fails_fast() {
+        let breaker = CircuitBreaker::new("upstream").minimum_calls(1).open_for(Duration::from_secs(30));
+
+        let call = || {
+            let next = Box::new(|_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                Box::pin(async { Response::with_status(http::StatusCode::BAD_GATEWAY) })
+            });
+            Middleware::call(&breaker, Request::mock(http::Method::GET, "/proxy"), next)
+        };
+
+        assert_eq!(call().await.status_code(), http::StatusCode::BAD_GATEWAY);
+        let response = call().await;
+        assert_eq!(response.status_code(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("retry-after").unwrap(), "30");
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_maintenance_mode_bypass_secret() {
+        let file = std::env::temp_dir().join(format!("torch-down-{}", std::process::id()));
+        std::fs::write(&file, r#"{"retry": 120, "secret": "let-me-in"}"#).unwrap();
+        let maintenance = MaintenanceMode::from_file(&file);
+
+        let call = |req: Request| {
+            let next = Box::new(|_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                Box::pin(async { Response::ok().body("live") })
+            });
+            maintenance.call(req, next)
+        };
+
+        let down = call(Request::mock(http::Method::GET, "/")).await;
+        assert_eq!(down.status_code(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(down.headers().get("retry-after").unwrap(), "120");
+
+        let bypass = call(Request::mock(http::Method::GET, "/?secret=let-me-in")).await;
+        assert_eq!(bypass.body_data(), b"live");
+        let cookie = bypass.headers().get("set-cookie").unwrap().to_str().unwrap();
+        assert!(cookie.starts_with("torch_maintenance=let-me-in;"));
+
+        let returning = Request::mock(http::Method::GET, "/").with_header("cookie", "torch_maintenance=let-me-in");
+        assert_eq!(call(returning).await.status_code(), http::StatusCode::OK);
+
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(call(Request::mock(http::Method::GET, "/")).await.status_code(), http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_minify_html_responses() {
+        let page = "<!DOCTYPE html>\n<html>\n  <!-- nav -->\n  <body class=\"a  b\">\n    <p>Hello,\n      world</p>\n    <pre>  keep\n  this</pre>\n    <script>let a = 1; // note\n  a++;</script>\n  </body>\n</html>\n";
+        assert_eq!(
+            minify_html(page),
+            "<!DOCTYPE html> <html> <body class=\"a  b\"> <p>Hello, world</p> <pre>  keep\n  this</pre> <script>let a = 1; // note\n  a++;</script> </body> </html>"
+        );
+
+        let call = |minify: MinifyHtml, content_type: &'static str, body: &'static str| {
+            let next = Box::new(move |_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+                Box::pin(async move { Response::ok().content_type(content_type).body(body) })
+            });
+            Middleware::call(&minify, Request::mock(http::Method::GET, "/"), next)
+        };
+        let html = call(MinifyHtml::new(), "text/html; charset=utf-8", page).await;
+        assert!(html.body_data().len() < page.len());
+        let json = call(MinifyHtml::new(), "application/json", "{ \"a\":  1 }").await;
+        assert_eq!(json.body_data(), b"{ \"a\":  1 }");
+        let disabled = call(MinifyHtml::from_config(&ProductionConfig::default()), "text/html", page).await;
+        assert_eq!(disabled.body_data(), page.as_bytes());
+    }
+
+    #[cfg(feature = "config")]
+    #[tokio::test]
+    async fn test_minify_assets_from_torch_toml() {
+        let page = "<html>\n  <body>\n    <p>Hello</p>\n  </body>\n</html>\n";
+        let load = |production: &str| {
+            let mut value = toml::Value::try_from(crate::config::TorchConfig::default()).unwrap();
+            value.as_table_mut().unwrap().insert("production".to_string(), toml::from_str(production).unwrap());
+            let config: crate::config::TorchConfig = value.try_into().unwrap();
+            crate::App::with_production(&ProductionConfig::from_torch_config(&config))
+                .get("/", move |_req: Request| async move { Response::ok().content_type("text/html").body(page) })
+        };
+
+        let app = load("minify_assets = true\noptimize_autoloader = true");
+        let response = app.handle_request(Request::mock(http::Method::GET, "/")).await;
+        assert_eq!(response.body_data(), b"<html> <body> <p>Hello</p> </body> </html>");
+
+        let app = load("");
+        let response = app.handle_request(Request::mock(http::Method::GET, "/")).await;
+        assert_eq!(response.body_data(), page.as_bytes());
+    }
 }
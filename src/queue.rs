@@ -0,0 +1,812 @@
+//! # Job Queues
+//!
+//! Defer slow work (sending email, calling third-party APIs, generating
+//! reports) out of the request cycle by dispatching jobs onto a queue.
+//!
+//! ## Drivers
+//!
+//! - [`SyncQueue`] runs jobs immediately, retrying failures in place. This is
+//!   the default and matches `queue = "sync"` in the `[testing]` section of
+//!   `torch.toml`.
+//! - [`MemoryQueue`] keeps jobs in process until a worker pulls them with
+//!   [`MemoryQueue::work_next`].
+//! - `DatabaseQueue` (with the `database` feature) stores jobs in the `jobs`
+//!   and `failed_jobs` tables, where `torch queue stats`, `retry` and
+//!   `prune-failed` can see them. Jobs must be registered with
+//!   [`Queue::register`] so they can be stored and rebuilt by the worker.
+//!
+//! Jobs on `MemoryQueue` and `DatabaseQueue` are run by a [`Worker`], which
+//! finishes the jobs it is running before exiting on `SIGTERM` or Ctrl+C.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use torch_web::queue::{Job, JobFuture, Queue};
+//!
+//! struct SendWelcomeEmail {
+//!     user_id: u64,
+//! }
+//!
+//! impl Job for SendWelcomeEmail {
+//!     fn handle(&self) -> JobFuture<'_> {
+//!         Box::pin(async move {
+//!             println!("Welcoming user {}", self.user_id);
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), torch_web::queue::QueueError> {
+//! Queue::dispatch(SendWelcomeEmail { user_id: 1 }).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Testing
+//!
+//! [`Queue::fake`] swaps the driver for a recorder so tests can assert on what
+//! was dispatched without running anything:
+//!
+//! ```rust,no_run
+//! # use torch_web::queue::{Job, JobFuture, Queue};
+//! # struct SendWelcomeEmail { user_id: u64 }
+//! # impl Job for SendWelcomeEmail {
+//! #     fn handle(&self) -> JobFuture<'_> { Box::pin(async { Ok(()) }) }
+//! # }
+//! # async fn example() {
+//! let _queue = Queue::fake();
+//!
+//! Queue::dispatch(SendWelcomeEmail { user_id: 7 }).await.unwrap();
+//!
+//! Queue::assert_pushed::<SendWelcomeEmail>();
+//! Queue::assert_pushed_where::<SendWelcomeEmail, _>(|job| job.user_id == 7);
+//! # }
+//! ```
+//!
+//! The fake applies to the whole process, so jobs dispatched from tasks
+//! spawned onto other threads are recorded too. Only one fake is active at
+//! a time: tests running in parallel wait for each other's fake to be
+//! dropped, so they never see each other's jobs.
+
+use std::any::{type_name, Any};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+use crate::clock::unix_now;
+
+mod batch;
+mod chain;
+pub mod middleware;
+mod worker;
+#[cfg(all(feature = "database", feature = "json"))]
+mod database;
+
+pub use batch::{Batch, BatchStore, JobOutcome, MemoryBatches, PendingBatch, StoreFuture};
+pub use chain::PendingChain;
+pub use middleware::JobMiddleware;
+pub use worker::{Worker, WorkerHandle, WorkerReport};
+#[cfg(feature = "json")]
+pub(crate) use worker::backlog as serve_backlog;
+
+#[cfg(all(feature = "database", feature = "json"))]
+pub use database::DatabaseQueue;
+
+/// Future returned by [`Job::handle`]
+pub type JobFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'a>>;
+
+/// A unit of work that can be pushed onto a queue
+pub trait Job: Send + Sync + 'static {
+    /// Execute the job
+    fn handle(&self) -> JobFuture<'_>;
+
+    /// Name of the queue this job is pushed onto
+    fn queue(&self) -> &str {
+        "default"
+    }
+
+    /// How many times a failed job is retried before it is given up on
+    fn max_retries(&self) -> u32 {
+        3
+    }
+
+    /// Rules checked before the job is queued or run, such as
+    /// [`middleware::unique_for`]
+    fn middleware(&self) -> Vec<JobMiddleware> {
+        Vec::new()
+    }
+}
+
+/// Errors raised while dispatching or running jobs
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueError {
+    /// The job ran out of retries; holds the job name and last error
+    JobFailed { job: String, message: String },
+    /// The queue driver could not accept the job
+    Driver(String),
+}
+
+impl std::fmt::Display for QueueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::JobFailed { job, message } => write!(f, "Job {} failed: {}", job, message),
+            QueueError::Driver(message) => write!(f, "Queue driver error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+/// A job waiting on a queue, along with its bookkeeping
+pub struct QueuedJob {
+    name: String,
+    queue: String,
+    attempts: u32,
+    payload: Option<String>,
+    batch_id: Option<String>,
+    available_at: Option<u64>,
+    chained: Vec<QueuedJob>,
+    /// Overlap locks held while the job runs
+    locks: Vec<String>,
+    /// Unix timestamp of when the job was created
+    queued_at: u64,
+    job: Box<dyn Job>,
+}
+
+impl QueuedJob {
+    pub fn new<J: Job>(job: J) -> Self {
+        Self {
+            name: type_name::<J>().to_string(),
+            queue: job.queue().to_string(),
+            attempts: 0,
+            payload: serialize_job(&job),
+            batch_id: None,
+            available_at: None,
+            chained: Vec::new(),
+            locks: Vec::new(),
+            queued_at: unix_now(),
+            job: Box::new(job),
+        }
+    }
+
+    /// Rebuild a job read back from storage
+    #[cfg(all(feature = "database", feature = "json"))]
+    pub(crate) fn restore(
+        name: String,
+        queue: String,
+        attempts: u32,
+        payload: String,
+        batch_id: Option<String>,
+        chained: Vec<QueuedJob>,
+        job: Box<dyn Job>,
+    ) -> Self {
+        Self {
+            name,
+            queue,
+            attempts,
+            payload: Some(payload),
+            batch_id,
+            available_at: None,
+            chained,
+            locks: Vec::new(),
+            queued_at: unix_now(),
+            job,
+        }
+    }
+
+    /// Type name of the wrapped job
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The job serialized as JSON, if its type was passed to [`Queue::register`]
+    pub fn payload(&self) -> Option<&str> {
+        self.payload.as_deref()
+    }
+
+    /// Queue the job was pushed onto
+    pub fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    /// How many times the job has been run so far
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Whether another attempt is allowed after a failure
+    pub fn can_retry(&self) -> bool {
+        self.attempts <= self.job.max_retries()
+    }
+
+    /// Run the job once
+    pub async fn run(&mut self) -> Result<(), QueueError> {
+        self.attempts += 1;
+        #[cfg(feature = "json")]
+        let started = std::time::Instant::now();
+        let handled = self.job.handle();
+        #[cfg(feature = "otel")]
+        let handled = crate::otel::traced_job(&self.name, &self.queue, self.attempts, handled);
+        let result = handled.await.map_err(|e| QueueError::JobFailed {
+            job: self.name.clone(),
+            message: e.to_string(),
+        });
+        #[cfg(feature = "json")]
+        match &result {
+            Ok(()) => crate::dev::inspector::record_job(&self.name, &self.queue, "processed", Some(started.elapsed()), None),
+            Err(error) => {
+                crate::dev::inspector::record_job(&self.name, &self.queue, "failed", Some(started.elapsed()), Some(&error.to_string()))
+            }
+        }
+        result
+    }
+
+    /// Run the job, retrying in place until it succeeds or runs out of retries
+    pub async fn run_with_retries(&mut self) -> Result<(), QueueError> {
+        loop {
+            match self.run().await {
+                Ok(()) => return Ok(()),
+                Err(e) if !self.can_retry() => return Err(e),
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+/// Backend that accepts dispatched jobs
+pub trait QueueDriver: Send + Sync + 'static {
+    fn push(&self, job: QueuedJob) -> Pin<Box<dyn Future<Output = Result<(), QueueError>> + Send + '_>>;
+
+    /// Where batches of jobs pushed to this driver are tracked
+    fn batches(&self) -> Arc<dyn BatchStore> {
+        MemoryBatches::global()
+    }
+
+    /// Run the next due job on `queue`, returning whether there was one.
+    /// Drivers without a backlog have nothing to hand a [`Worker`].
+    fn run_next<'a>(&'a self, _queue: &'a str) -> StoreFuture<'a, bool> {
+        Box::pin(async { Ok(false) })
+    }
+
+    /// Job counts and latency for every queue with jobs
+    fn backlog(&self) -> StoreFuture<'_, Vec<QueueStats>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+}
+
+/// Driver that runs every job as soon as it is dispatched, ignoring delays
+#[derive(Debug, Clone, Default)]
+pub struct SyncQueue;
+
+impl QueueDriver for SyncQueue {
+    fn push(&self, mut job: QueuedJob) -> Pin<Box<dyn Future<Output = Result<(), QueueError>> + Send + '_>> {
+        Box::pin(async move {
+            let batches = self.batches();
+            if job.skip_if_cancelled(&*batches).await? {
+                return Ok(());
+            }
+            while let Some(wait) = job.acquire_locks().await? {
+                tokio::time::sleep(wait).await;
+            }
+            let result = job.run_with_retries().await;
+            job.release_locks(true).await?;
+            job.finish_in_batch(&*batches, result.as_ref().err()).await?;
+            result?;
+            job.dispatch_next(self).await
+        })
+    }
+}
+
+/// Job counts for one queue, as shown by `torch queue stats` and used to
+/// scale workers
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct QueueStats {
+    pub queue: String,
+    /// Due and waiting to be picked up by a worker
+    pub pending: u64,
+    /// Waiting for their delay to pass
+    pub delayed: u64,
+    /// Claimed by a worker that hasn't finished yet
+    pub processing: u64,
+    /// Out of retries
+    pub failed: u64,
+    /// Seconds the oldest pending job has been due
+    pub latency: u64,
+}
+
+/// A job that ran out of retries
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedJob {
+    pub id: u64,
+    pub job: String,
+    pub queue: String,
+    pub error: String,
+    /// Unix timestamp, in seconds
+    pub failed_at: u64,
+}
+
+/// Current time as a Unix timestamp in seconds
+/// In-process driver that holds jobs until a worker runs them
+#[derive(Clone, Default)]
+pub struct MemoryQueue {
+    queues: Arc<Mutex<HashMap<String, VecDeque<QueuedJob>>>>,
+    failed: Arc<Mutex<Vec<FailedJob>>>,
+}
+
+impl MemoryQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of jobs waiting on a queue
+    pub fn size(&self, queue: &str) -> usize {
+        self.queues
+            .lock()
+            .unwrap()
+            .get(queue)
+            .map_or(0, VecDeque::len)
+    }
+
+    /// Take the next job that is due off a queue without running it
+    pub fn pop(&self, queue: &str) -> Option<QueuedJob> {
+        let mut queues = self.queues.lock().unwrap();
+        let jobs = queues.get_mut(queue)?;
+        let position = jobs.iter().position(QueuedJob::is_available)?;
+        jobs.remove(position)
+    }
+
+    /// Run the next job on a queue.
+    ///
+    /// Failed jobs go to the back of the queue until they run out of retries
+    /// and are moved to [`failed`](Self::failed), and jobs held up by their
+    /// middleware go back with a delay. Returns `false` when no
+    /// job on the queue was due.
+    pub async fn work_next(&self, queue: &str) -> bool {
+        let Some(mut job) = self.pop(queue) else {
+            return false;
+        };
+        let batches = self.batches();
+        if job.skip_if_cancelled(&*batches).await.unwrap_or(false) {
+            return true;
+        }
+        if let Ok(Some(wait)) = job.acquire_locks().await {
+            self.enqueue(job.delay(wait));
+            return true;
+        }
+
+        let result = job.run().await;
+        let _ = job.release_locks(result.is_ok() || !job.can_retry()).await;
+        match result {
+            Ok(()) => {
+                let _ = job.finish_in_batch(&*batches, None).await;
+                let _ = job.dispatch_next(self).await;
+            }
+            Err(_) if job.can_retry() => self.enqueue(job),
+            Err(e) => {
+                {
+                    let mut failed = self.failed.lock().unwrap();
+                    let id = failed.len() as u64 + 1;
+                    failed.push(FailedJob {
+                        id,
+                        job: job.name().to_string(),
+                        queue: job.queue().to_string(),
+                        error: e.to_string(),
+                        failed_at: unix_now(),
+                    });
+                }
+                let _ = job.finish_in_batch(&*batches, Some(&e)).await;
+            }
+        }
+
+        true
+    }
+
+    /// Jobs that ran out of retries
+    pub fn failed(&self) -> Vec<FailedJob> {
+        self.failed.lock().unwrap().clone()
+    }
+
+    fn enqueue(&self, job: QueuedJob) {
+        self.queues
+            .lock()
+            .unwrap()
+            .entry(job.queue().to_string())
+            .or_default()
+            .push_back(job);
+    }
+}
+
+impl QueueDriver for MemoryQueue {
+    fn push(&self, job: QueuedJob) -> Pin<Box<dyn Future<Output = Result<(), QueueError>> + Send + '_>> {
+        self.enqueue(job);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn run_next<'a>(&'a self, queue: &'a str) -> StoreFuture<'a, bool> {
+        Box::pin(async move { Ok(self.work_next(queue).await) })
+    }
+
+    fn backlog(&self) -> StoreFuture<'_, Vec<QueueStats>> {
+        let now = unix_now();
+        let mut stats: BTreeMap<String, QueueStats> = BTreeMap::new();
+        for (queue, jobs) in self.queues.lock().unwrap().iter() {
+            let entry = stats.entry(queue.clone()).or_default();
+            for job in jobs {
+                if job.is_available() {
+                    entry.pending += 1;
+                    let due_at = job.available_at.unwrap_or(job.queued_at).max(job.queued_at);
+                    entry.latency = entry.latency.max(now.saturating_sub(due_at));
+                } else {
+                    entry.delayed += 1;
+                }
+            }
+        }
+        for job in self.failed.lock().unwrap().iter() {
+            stats.entry(job.queue.clone()).or_default().failed += 1;
+        }
+
+        let stats = stats
+            .into_iter()
+            .filter(|(_, stats)| stats.pending + stats.delayed + stats.failed > 0)
+            .map(|(queue, stats)| QueueStats { queue, ..stats })
+            .collect();
+        Box::pin(async { Ok(stats) })
+    }
+}
+
+static DRIVER: RwLock<Option<Arc<dyn QueueDriver>>> = RwLock::new(None);
+
+/// The configured driver, or [`SyncQueue`] when none is set
+fn driver() -> Arc<dyn QueueDriver> {
+    DRIVER.read().unwrap().clone().unwrap_or_else(|| Arc::new(SyncQueue))
+}
+
+/// How a registered job type is stored and rebuilt
+#[cfg(feature = "json")]
+struct JobCodec {
+    name: &'static str,
+    serialize: fn(&dyn Any) -> Option<String>,
+    #[cfg_attr(not(feature = "database"), allow(dead_code))]
+    deserialize: fn(&str) -> Result<Box<dyn Job>, serde_json::Error>,
+}
+
+#[cfg(feature = "json")]
+static REGISTRY: RwLock<Vec<JobCodec>> = RwLock::new(Vec::new());
+
+#[cfg(feature = "json")]
+fn serialize_job<J: Job>(job: &J) -> Option<String> {
+    let registry = REGISTRY.read().unwrap();
+    let codec = registry.iter().find(|codec| codec.name == type_name::<J>())?;
+    (codec.serialize)(job)
+}
+
+#[cfg(not(feature = "json"))]
+fn serialize_job<J: Job>(_job: &J) -> Option<String> {
+    None
+}
+
+/// Rebuild a stored job from its registered type name and payload
+#[cfg(feature = "json")]
+#[cfg_attr(not(feature = "database"), allow(dead_code))]
+pub(crate) fn deserialize_job(name: &str, payload: &str) -> Result<Box<dyn Job>, String> {
+    let registry = REGISTRY.read().unwrap();
+    let codec = registry
+        .iter()
+        .find(|codec| codec.name == name)
+        .ok_or_else(|| format!("job {} is not registered; call Queue::register::<{}>()", name, name))?;
+    (codec.deserialize)(payload).map_err(|e| format!("job {} could not be decoded: {}", name, e))
+}
+
+/// A job recorded by [`Queue::fake`]
+struct PushedJob {
+    name: &'static str,
+    queue: String,
+    job: Arc<dyn Any + Send + Sync>,
+}
+
+type PushedJobs = Arc<Mutex<Vec<PushedJob>>>;
+
+static FAKE: Mutex<Option<PushedJobs>> = Mutex::new(None);
+
+/// Held by the active [`QueueFake`], so fakes take turns
+static FAKE_TURN: Mutex<()> = Mutex::new(());
+
+/// Wait for the active fake, if any, to be dropped
+fn fake_turn() -> MutexGuard<'static, ()> {
+    FAKE_TURN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keep fakes out while a test dispatches jobs for real
+#[cfg(test)]
+pub(crate) fn without_fake() -> NoFake {
+    NoFake { _turn: fake_turn() }
+}
+
+/// Held by tests that must not run under another test's fake
+#[cfg(test)]
+pub(crate) struct NoFake {
+    _turn: MutexGuard<'static, ()>,
+}
+
+/// Queue facade
+pub struct Queue;
+
+impl Queue {
+    /// Use `driver` for every job dispatched from now on
+    pub fn set_driver<D: QueueDriver>(driver: D) {
+        *DRIVER.write().unwrap() = Some(Arc::new(driver));
+    }
+
+    /// Allow jobs of type `J` to be stored outside the process, so drivers
+    /// like `DatabaseQueue` can persist them and workers can rebuild them
+    #[cfg(feature = "json")]
+    pub fn register<J>()
+    where
+        J: Job + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        fn serialize<J: Job + serde::Serialize>(job: &dyn Any) -> Option<String> {
+            serde_json::to_string(job.downcast_ref::<J>()?).ok()
+        }
+
+        fn deserialize<J: Job + serde::de::DeserializeOwned>(payload: &str) -> Result<Box<dyn Job>, serde_json::Error> {
+            Ok(Box::new(serde_json::from_str::<J>(payload)?))
+        }
+
+        let mut registry = REGISTRY.write().unwrap();
+        if !registry.iter().any(|codec| codec.name == type_name::<J>()) {
+            registry.push(JobCodec {
+                name: type_name::<J>(),
+                serialize: serialize::<J>,
+                deserialize: deserialize::<J>,
+            });
+        }
+    }
+
+    /// Push a job onto its queue
+    pub async fn dispatch<J: Job>(job: J) -> Result<(), QueueError> {
+        let Some(job) = Self::record(job) else {
+            return Ok(());
+        };
+
+        let job = QueuedJob::new(job);
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_job(&job.name, &job.queue, "dispatched", None, None);
+        middleware::push_unique(&*driver(), job).await
+    }
+
+    /// Job counts and latency per queue from the configured driver, for
+    /// dashboards and autoscalers
+    pub async fn backlog() -> Result<Vec<QueueStats>, QueueError> {
+        driver().backlog().await
+    }
+
+    /// Run a job right away, bypassing the configured driver
+    pub async fn dispatch_sync<J: Job>(job: J) -> Result<(), QueueError> {
+        let Some(job) = Self::record(job) else {
+            return Ok(());
+        };
+
+        QueuedJob::new(job).run_with_retries().await
+    }
+
+    /// Record dispatched jobs instead of running them until the returned
+    /// guard is dropped, waiting first for any other fake to be dropped
+    pub fn fake() -> QueueFake {
+        let turn = fake_turn();
+        *FAKE.lock().unwrap() = Some(Arc::default());
+        QueueFake { _turn: turn }
+    }
+
+    /// Assert at least one job of type `J` was dispatched
+    pub fn assert_pushed<J: Job>() {
+        let count = Self::pushed::<J>().len();
+        assert!(count > 0, "expected job {} to be pushed, but it was not", type_name::<J>());
+    }
+
+    /// Assert exactly `times` jobs of type `J` were dispatched
+    pub fn assert_pushed_times<J: Job>(times: usize) {
+        let count = Self::pushed::<J>().len();
+        assert_eq!(count, times, "expected job {} to be pushed {} times, but it was pushed {} times", type_name::<J>(), times, count);
+    }
+
+    /// Assert a job of type `J` was dispatched onto `queue`
+    pub fn assert_pushed_on<J: Job>(queue: &str) {
+        let found = Self::with_pushed(|jobs| {
+            jobs.iter().any(|pushed| pushed.name == type_name::<J>() && pushed.queue == queue)
+        });
+        assert!(found, "expected job {} to be pushed on queue {:?}", type_name::<J>(), queue);
+    }
+
+    /// Assert a dispatched job of type `J` matches `predicate`
+    pub fn assert_pushed_where<J: Job, F: Fn(&J) -> bool>(predicate: F) {
+        let found = Self::pushed::<J>().iter().any(|job| predicate(job));
+        assert!(found, "expected a pushed {} job matching the predicate", type_name::<J>());
+    }
+
+    /// Assert no job of type `J` was dispatched
+    pub fn assert_not_pushed<J: Job>() {
+        let count = Self::pushed::<J>().len();
+        assert_eq!(count, 0, "expected job {} not to be pushed, but it was pushed {} times", type_name::<J>(), count);
+    }
+
+    /// Assert nothing at all was dispatched
+    pub fn assert_nothing_pushed() {
+        let names = Self::with_pushed(|jobs| jobs.iter().map(|pushed| pushed.name).collect::<Vec<_>>());
+        assert!(names.is_empty(), "expected no jobs to be pushed, but found {:?}", names);
+    }
+
+    /// Jobs of type `J` dispatched while faked, in dispatch order
+    pub fn pushed<J: Job>() -> Vec<Arc<J>> {
+        Self::with_pushed(|jobs| {
+            jobs.iter()
+                .filter_map(|pushed| pushed.job.clone().downcast::<J>().ok())
+                .collect()
+        })
+    }
+
+    /// Hand the job to the active fake, or give it back if there is none
+    fn record<J: Job>(job: J) -> Option<J> {
+        let Some(jobs) = FAKE.lock().unwrap().clone() else {
+            return Some(job);
+        };
+
+        jobs.lock().unwrap().push(PushedJob {
+            name: type_name::<J>(),
+            queue: job.queue().to_string(),
+            job: Arc::new(job),
+        });
+        None
+    }
+
+    fn with_pushed<T>(f: impl FnOnce(&[PushedJob]) -> T) -> T {
+        let jobs = FAKE
+            .lock()
+            .unwrap()
+            .clone()
+            .expect("Queue::fake() must be called before making queue assertions");
+        let jobs = jobs.lock().unwrap();
+        f(&jobs)
+    }
+}
+
+/// Guard returned by [`Queue::fake`]; dispatching goes back to the real
+/// driver once it is dropped
+#[must_use = "the queue fake is removed when this guard is dropped"]
+pub struct QueueFake {
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Drop for QueueFake {
+    fn drop(&mut self) {
+        *FAKE.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct SendWelcomeEmail {
+        user_id: u64,
+    }
+
+    impl Job for SendWelcomeEmail {
+        fn handle(&self) -> JobFuture<'_> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn queue(&self) -> &str {
+            "emails"
+        }
+    }
+
+    struct Flaky {
+        runs: Arc<AtomicU32>,
+        succeed_on: u32,
+    }
+
+    impl Job for Flaky {
+        fn handle(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                let run = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+                if run >= self.succeed_on {
+                    Ok(())
+                } else {
+                    Err(format!("attempt {} failed", run).into())
+                }
+            })
+        }
+
+        fn max_retries(&self) -> u32 {
+            2
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fake_records_jobs() {
+        let _queue = Queue::fake();
+
+        Queue::dispatch(SendWelcomeEmail { user_id: 7 }).await.unwrap();
+
+        Queue::assert_pushed::<SendWelcomeEmail>();
+        Queue::assert_pushed_times::<SendWelcomeEmail>(1);
+        Queue::assert_pushed_on::<SendWelcomeEmail>("emails");
+        Queue::assert_pushed_where::<SendWelcomeEmail, _>(|job| job.user_id == 7);
+        Queue::assert_not_pushed::<Flaky>();
+        assert_eq!(Queue::pushed::<SendWelcomeEmail>()[0].user_id, 7);
+    }
+
+    #[tokio::test]
+    async fn test_fake_starts_empty() {
+        let _queue = Queue::fake();
+        Queue::assert_nothing_pushed();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_fake_records_jobs_dispatched_from_spawned_tasks() {
+        let _queue = Queue::fake();
+
+        let dispatches: Vec<_> = (1..=2)
+            .map(|user_id| tokio::spawn(Queue::dispatch(SendWelcomeEmail { user_id })))
+            .collect();
+        for dispatch in dispatches {
+            dispatch.await.unwrap().unwrap();
+        }
+
+        Queue::assert_pushed_times::<SendWelcomeEmail>(2);
+    }
+
+    #[tokio::test]
+    async fn test_sync_dispatch_retries() {
+        let _real = without_fake();
+        let runs = Arc::new(AtomicU32::new(0));
+        Queue::dispatch_sync(Flaky { runs: runs.clone(), succeed_on: 3 }).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        let runs = Arc::new(AtomicU32::new(0));
+        let result = Queue::dispatch_sync(Flaky { runs: runs.clone(), succeed_on: 10 }).await;
+        assert!(matches!(result, Err(QueueError::JobFailed { .. })));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_moves_exhausted_jobs_to_failed() {
+        let queue = MemoryQueue::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        queue.push(QueuedJob::new(Flaky { runs: runs.clone(), succeed_on: 10 })).await.unwrap();
+        assert_eq!(queue.size("default"), 1);
+
+        while queue.work_next("default").await {}
+
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(queue.size("default"), 0);
+        assert_eq!(queue.failed().len(), 1);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_registered_jobs_round_trip_through_payload() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct SendInvoice {
+            invoice_id: u64,
+        }
+
+        impl Job for SendInvoice {
+            fn handle(&self) -> JobFuture<'_> {
+                Box::pin(async { Ok(()) })
+            }
+        }
+
+        assert!(QueuedJob::new(SendWelcomeEmail { user_id: 1 }).payload().is_none());
+
+        Queue::register::<SendInvoice>();
+        let job = QueuedJob::new(SendInvoice { invoice_id: 42 });
+        assert_eq!(job.payload(), Some(r#"{"invoice_id":42}"#));
+        assert!(deserialize_job(job.name(), job.payload().unwrap()).is_ok());
+        assert!(deserialize_job("app::Unknown", "{}").is_err());
+    }
+}
@@ -0,0 +1,457 @@
+//! Batches of jobs that finish together
+//!
+//! A batch tracks a group of jobs, such as one email per subscriber, so the
+//! app can show progress and react once they have all run:
+//!
+//! ```rust,no_run
+//! use torch_web::queue::{Batch, Job, JobFuture};
+//!
+//! struct SendNewsletter {
+//!     subscriber_id: u64,
+//! }
+//!
+//! impl Job for SendNewsletter {
+//!     fn handle(&self) -> JobFuture<'_> {
+//!         Box::pin(async { Ok(()) })
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), torch_web::queue::QueueError> {
+//! let batch = Batch::dispatch((1..=10_000).map(|subscriber_id| SendNewsletter { subscriber_id }))
+//!     .name("October newsletter")
+//!     .allow_failures()
+//!     .then(|batch| async move { println!("{} sent", batch.processed_jobs()) })
+//!     .finally(|batch| async move { println!("{} failed", batch.failed_jobs) })
+//!     .await?;
+//!
+//! // Later, e.g. from a progress endpoint
+//! if let Some(batch) = Batch::find(&batch.id).await? {
+//!     println!("{}% done", batch.progress());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Without [`allow_failures`](PendingBatch::allow_failures), the first job
+//! to run out of retries cancels the batch: `catch` runs, jobs not yet
+//! started are skipped, and `then` never runs. `finally` runs once every job
+//! has run or been skipped. [`Batch::cancel`] stops a batch the same way.
+//!
+//! Batch counts are kept by the queue driver (the `job_batches` table for
+//! `DatabaseQueue`), so any process can read them. Callbacks are closures
+//! and stay in the dispatching process, so they only run for jobs worked
+//! there.
+
+use std::collections::HashMap;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{driver, Job, Queue, QueueDriver, QueueError, QueuedJob};
+use crate::clock::unix_now;
+
+/// Future returned by [`BatchStore`] methods
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, QueueError>> + Send + 'a>>;
+
+/// A group of jobs and how far along they are
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub id: String,
+    pub name: String,
+    pub total_jobs: u64,
+    /// Jobs that haven't succeeded, failed for good or been skipped yet
+    pub pending_jobs: u64,
+    /// Jobs that ran out of retries
+    pub failed_jobs: u64,
+    pub allow_failures: bool,
+    /// Unix timestamp, in seconds
+    pub created_at: u64,
+    pub cancelled_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+/// How a job in a batch ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    Succeeded,
+    /// Ran out of retries
+    Failed,
+    /// Not run because the batch was cancelled
+    Skipped,
+}
+
+/// Where a queue driver keeps its batches
+pub trait BatchStore: Send + Sync + 'static {
+    fn create(&self, batch: Batch) -> StoreFuture<'_, ()>;
+
+    fn find(&self, id: String) -> StoreFuture<'_, Option<Batch>>;
+
+    /// Count one job off the batch, setting `finished_at` when it was the
+    /// last, and return the batch as updated
+    fn record(&self, id: String, outcome: JobOutcome) -> StoreFuture<'_, Option<Batch>>;
+
+    /// Mark the batch cancelled. Returns `false` if it already was, or
+    /// doesn't exist.
+    fn cancel(&self, id: String) -> StoreFuture<'_, bool>;
+}
+
+/// Batches held in process, used by `SyncQueue` and `MemoryQueue`
+#[derive(Default)]
+pub struct MemoryBatches {
+    batches: Mutex<HashMap<String, Batch>>,
+}
+
+impl MemoryBatches {
+    /// The store shared by the in-process drivers
+    pub fn global() -> Arc<MemoryBatches> {
+        static GLOBAL: OnceLock<Arc<MemoryBatches>> = OnceLock::new();
+        GLOBAL.get_or_init(Arc::default).clone()
+    }
+}
+
+impl BatchStore for MemoryBatches {
+    fn create(&self, batch: Batch) -> StoreFuture<'_, ()> {
+        self.batches.lock().unwrap().insert(batch.id.clone(), batch);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn find(&self, id: String) -> StoreFuture<'_, Option<Batch>> {
+        let batch = self.batches.lock().unwrap().get(&id).cloned();
+        Box::pin(async { Ok(batch) })
+    }
+
+    fn record(&self, id: String, outcome: JobOutcome) -> StoreFuture<'_, Option<Batch>> {
+        let batch = self.batches.lock().unwrap().get_mut(&id).map(|batch| {
+            batch.pending_jobs = batch.pending_jobs.saturating_sub(1);
+            if outcome == JobOutcome::Failed {
+                batch.failed_jobs += 1;
+            }
+            if batch.pending_jobs == 0 {
+                batch.finished_at.get_or_insert_with(unix_now);
+            }
+            batch.clone()
+        });
+        Box::pin(async { Ok(batch) })
+    }
+
+    fn cancel(&self, id: String) -> StoreFuture<'_, bool> {
+        let cancelled = match self.batches.lock().unwrap().get_mut(&id) {
+            Some(batch) if batch.cancelled_at.is_none() => {
+                batch.cancelled_at = Some(unix_now());
+                true
+            }
+            _ => false,
+        };
+        Box::pin(async move { Ok(cancelled) })
+    }
+}
+
+type BatchCallback = Box<dyn FnOnce(Batch) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+type CatchCallback = Box<dyn FnOnce(Batch, QueueError) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+#[derive(Default)]
+struct BatchCallbacks {
+    then: Option<BatchCallback>,
+    catch: Option<CatchCallback>,
+    finally: Option<BatchCallback>,
+}
+
+/// Callbacks of batches dispatched from this process, by batch id
+fn callbacks() -> &'static Mutex<HashMap<String, BatchCallbacks>> {
+    static CALLBACKS: OnceLock<Mutex<HashMap<String, BatchCallbacks>>> = OnceLock::new();
+    CALLBACKS.get_or_init(Default::default)
+}
+
+/// Unique, roughly time-ordered batch id
+fn new_batch_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+    format!("{:x}-{:x}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+impl Batch {
+    /// Start building a batch of jobs; add callbacks and options, then
+    /// `.await` it to dispatch every job
+    pub fn dispatch<J: Job>(jobs: impl IntoIterator<Item = J>) -> PendingBatch {
+        jobs.into_iter().fold(PendingBatch::default(), PendingBatch::push)
+    }
+
+    /// Look a batch up in the current driver's store
+    pub async fn find(id: &str) -> Result<Option<Batch>, QueueError> {
+        driver().batches().find(id.to_string()).await
+    }
+
+    /// Jobs that have succeeded, failed or been skipped
+    pub fn processed_jobs(&self) -> u64 {
+        self.total_jobs - self.pending_jobs
+    }
+
+    /// Percentage of jobs processed, from 0 to 100
+    pub fn progress(&self) -> u8 {
+        match self.total_jobs {
+            0 => 100,
+            total => (self.processed_jobs() * 100 / total) as u8,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled_at.is_some()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.failed_jobs > 0
+    }
+
+    /// Stop the batch: jobs that haven't started are skipped and `then`
+    /// won't run
+    pub async fn cancel(&self) -> Result<(), QueueError> {
+        driver().batches().cancel(self.id.clone()).await.map(|_| ())
+    }
+}
+
+/// A batch being built by [`Batch::dispatch`]
+#[derive(Default)]
+#[must_use = "a batch is only dispatched when awaited"]
+pub struct PendingBatch {
+    name: String,
+    jobs: Vec<QueuedJob>,
+    /// Jobs taken by `Queue::fake` instead of being queued
+    faked: u64,
+    allow_failures: bool,
+    callbacks: BatchCallbacks,
+}
+
+impl PendingBatch {
+    /// Add another job, which may be of a different type
+    pub fn push<J: Job>(mut self, job: J) -> Self {
+        match Queue::record(job) {
+            Some(job) => self.jobs.push(QueuedJob::new(job)),
+            None => self.faked += 1,
+        }
+        self
+    }
+
+    /// Name shown when inspecting the batch
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Keep going when jobs fail, and still run `then` at the end
+    pub fn allow_failures(mut self) -> Self {
+        self.allow_failures = true;
+        self
+    }
+
+    /// Run once every job has finished, unless the batch was cancelled
+    pub fn then<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: FnOnce(Batch) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.then = Some(Box::new(move |batch| Box::pin(callback(batch))));
+        self
+    }
+
+    /// Run when the first job fails for good
+    pub fn catch<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: FnOnce(Batch, QueueError) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.catch = Some(Box::new(move |batch, error| Box::pin(callback(batch, error))));
+        self
+    }
+
+    /// Run once every job has run or been skipped, however the batch ended
+    pub fn finally<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: FnOnce(Batch) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.finally = Some(Box::new(move |batch| Box::pin(callback(batch))));
+        self
+    }
+
+    /// Store the batch and push its jobs. Jobs failing under the sync
+    /// driver count against the batch instead of failing the dispatch.
+    async fn send(self, driver: Arc<dyn QueueDriver>) -> Result<Batch, QueueError> {
+        let store = driver.batches();
+        let total = self.jobs.len() as u64 + self.faked;
+        let batch = Batch {
+            id: new_batch_id(),
+            name: self.name,
+            total_jobs: total,
+            pending_jobs: total,
+            failed_jobs: 0,
+            allow_failures: self.allow_failures,
+            created_at: unix_now(),
+            cancelled_at: None,
+            finished_at: (total == 0).then(unix_now),
+        };
+        store.create(batch.clone()).await?;
+        callbacks().lock().unwrap().insert(batch.id.clone(), self.callbacks);
+        if total == 0 {
+            complete(batch.clone()).await;
+            return Ok(batch);
+        }
+
+        for mut job in self.jobs {
+            job.batch_id = Some(batch.id.clone());
+            match driver.push(job).await {
+                Ok(()) | Err(QueueError::JobFailed { .. }) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(store.find(batch.id.clone()).await?.unwrap_or(batch))
+    }
+}
+
+impl IntoFuture for PendingBatch {
+    type Output = Result<Batch, QueueError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.send(driver()))
+    }
+}
+
+/// Run the callbacks of a batch whose jobs have all been processed
+async fn complete(batch: Batch) {
+    let Some(callbacks) = callbacks().lock().unwrap().remove(&batch.id) else {
+        return;
+    };
+    if let Some(then) = callbacks.then.filter(|_| !batch.cancelled()) {
+        then(batch.clone()).await;
+    }
+    if let Some(finally) = callbacks.finally {
+        finally(batch).await;
+    }
+}
+
+impl QueuedJob {
+    /// Batch the job was dispatched in, if any
+    pub fn batch_id(&self) -> Option<&str> {
+        self.batch_id.as_deref()
+    }
+
+    /// Whether the job's batch was cancelled, in which case the job is
+    /// counted off the batch and must not be run
+    pub(crate) async fn skip_if_cancelled(&self, store: &dyn BatchStore) -> Result<bool, QueueError> {
+        let Some(id) = self.batch_id.clone() else {
+            return Ok(false);
+        };
+        if !store.find(id.clone()).await?.is_some_and(|batch| batch.cancelled()) {
+            return Ok(false);
+        }
+
+        if let Some(batch) = store.record(id, JobOutcome::Skipped).await? {
+            if batch.pending_jobs == 0 {
+                complete(batch).await;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Tell the job's batch it succeeded, or failed with `error` after its
+    /// last retry
+    pub(crate) async fn finish_in_batch(&self, store: &dyn BatchStore, error: Option<&QueueError>) -> Result<(), QueueError> {
+        let Some(id) = self.batch_id.clone() else {
+            return Ok(());
+        };
+        let outcome = if error.is_some() { JobOutcome::Failed } else { JobOutcome::Succeeded };
+        let Some(mut batch) = store.record(id.clone(), outcome).await? else {
+            return Ok(());
+        };
+
+        if let Some(error) = error {
+            if !batch.allow_failures && store.cancel(id.clone()).await? {
+                batch.cancelled_at = Some(unix_now());
+            }
+            let catch = match batch.failed_jobs {
+                1 => callbacks().lock().unwrap().get_mut(&id).and_then(|callbacks| callbacks.catch.take()),
+                _ => None,
+            };
+            if let Some(catch) = catch {
+                catch(batch.clone(), error.clone()).await;
+            }
+        }
+        if batch.pending_jobs == 0 {
+            complete(batch).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{JobFuture, MemoryQueue};
+
+    struct Deliver {
+        fail: bool,
+    }
+
+    impl Job for Deliver {
+        fn handle(&self) -> JobFuture<'_> {
+            let fail = self.fail;
+            Box::pin(async move { if fail { Err("mailbox full".into()) } else { Ok(()) } })
+        }
+
+        fn max_retries(&self) -> u32 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_track_progress_and_run_callbacks() {
+        let _real = crate::queue::without_fake();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = |events: &Arc<Mutex<Vec<String>>>, label: &'static str| {
+            let events = events.clone();
+            move |batch: Batch| async move {
+                events.lock().unwrap().push(format!("{} {}/{}", label, batch.processed_jobs(), batch.failed_jobs));
+            }
+        };
+
+        // The sync driver runs jobs as they are pushed
+        let batch = Batch::dispatch([false, true, false].map(|fail| Deliver { fail }))
+            .allow_failures()
+            .then(log(&events, "then"))
+            .finally(log(&events, "finally"))
+            .await
+            .unwrap();
+        assert!(batch.finished() && !batch.cancelled());
+        assert_eq!((batch.total_jobs, batch.failed_jobs, batch.progress()), (3, 1, 100));
+        assert_eq!(*events.lock().unwrap(), vec!["then 3/1", "finally 3/1"]);
+
+        // A failure cancels a batch that doesn't allow them, skipping the rest
+        events.lock().unwrap().clear();
+        let queue = MemoryQueue::new();
+        let batch = Batch::dispatch([Deliver { fail: true }])
+            .push(Deliver { fail: false })
+            .then(log(&events, "then"))
+            .finally(log(&events, "finally"))
+            .catch({
+                let events = events.clone();
+                move |_, error| async move { events.lock().unwrap().push(error.to_string()) }
+            })
+            .send(Arc::new(queue.clone()))
+            .await
+            .unwrap();
+        assert_eq!((batch.pending_jobs, batch.progress()), (2, 0));
+        assert_eq!(queue.size("default"), 2);
+
+        while queue.work_next("default").await {}
+        let batch = Batch::find(&batch.id).await.unwrap().unwrap();
+        assert!(batch.cancelled() && batch.finished());
+        assert_eq!((batch.failed_jobs, batch.pending_jobs), (1, 0));
+        assert_eq!(*events.lock().unwrap(), vec!["Job torch_web::queue::batch::tests::Deliver failed: mailbox full", "finally 2/1"]);
+    }
+}
@@ -0,0 +1,192 @@
+//! Chained and delayed jobs
+//!
+//! A chain runs jobs one after another: each job is only pushed once the one
+//! before it has succeeded, and a job that runs out of retries stops the
+//! chain. With a delay, the first job waits before it can be picked up, which
+//! is how drip campaigns are built:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use torch_web::queue::{Job, JobFuture, Queue};
+//!
+//! struct SendEmail {
+//!     template: &'static str,
+//! }
+//!
+//! impl Job for SendEmail {
+//!     fn handle(&self) -> JobFuture<'_> {
+//!         Box::pin(async { Ok(()) })
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), torch_web::queue::QueueError> {
+//! Queue::chain([SendEmail { template: "welcome" }, SendEmail { template: "tips" }])
+//!     .delay(Duration::from_secs(60 * 60))
+//!     .await?;
+//!
+//! Queue::dispatch_in(SendEmail { template: "check-in" }, Duration::from_secs(3 * 24 * 60 * 60)).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `MemoryQueue` and `DatabaseQueue` hold delayed jobs back until they are
+//! due. `SyncQueue` has no worker to wait for, so it runs them right away.
+
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::time::Duration;
+
+use super::middleware::push_unique;
+use super::{driver, Job, Queue, QueueDriver, QueueError, QueuedJob};
+use crate::clock::unix_now;
+
+/// A chain being built by [`Queue::chain`]
+#[derive(Default)]
+#[must_use = "a chain is only dispatched when awaited"]
+pub struct PendingChain {
+    jobs: Vec<QueuedJob>,
+    delay: Option<Duration>,
+}
+
+impl PendingChain {
+    /// Add a job to the end of the chain, which may be of a different type
+    pub fn push<J: Job>(mut self, job: J) -> Self {
+        if let Some(job) = Queue::record(job) {
+            self.jobs.push(QueuedJob::new(job));
+        }
+        self
+    }
+
+    /// Wait before the first job can run
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Push the first job, carrying the rest along with it
+    async fn send(self, driver: &dyn QueueDriver) -> Result<(), QueueError> {
+        let mut jobs = self.jobs.into_iter();
+        let Some(mut first) = jobs.next() else {
+            return Ok(());
+        };
+        first.chained = jobs.collect();
+        if let Some(delay) = self.delay {
+            first = first.delay(delay);
+        }
+        push_unique(driver, first).await
+    }
+}
+
+impl IntoFuture for PendingChain {
+    type Output = Result<(), QueueError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { self.send(&*driver()).await })
+    }
+}
+
+impl Queue {
+    /// Run jobs one after another, each once the previous one succeeds
+    pub fn chain<J: Job>(jobs: impl IntoIterator<Item = J>) -> PendingChain {
+        jobs.into_iter().fold(PendingChain::default(), PendingChain::push)
+    }
+
+    /// Push a job that can't run until `delay` has passed
+    pub async fn dispatch_in<J: Job>(job: J, delay: Duration) -> Result<(), QueueError> {
+        let Some(job) = Self::record(job) else {
+            return Ok(());
+        };
+
+        push_unique(&*driver(), QueuedJob::new(job).delay(delay)).await
+    }
+
+    /// Push a job that can't run until `at`
+    #[cfg(feature = "chrono")]
+    pub async fn dispatch_at<J: Job>(job: J, at: chrono::DateTime<chrono::Utc>) -> Result<(), QueueError> {
+        let delay = (at - chrono::Utc::now()).to_std().unwrap_or_default();
+        Self::dispatch_in(job, delay).await
+    }
+}
+
+impl QueuedJob {
+    /// Hold the job back until `delay` has passed
+    pub fn delay(mut self, delay: Duration) -> Self {
+        // Rounded up, so short delays still wait rather than run at once
+        self.available_at = Some(unix_now() + delay.as_secs() + u64::from(delay.subsec_nanos() > 0));
+        self
+    }
+
+    /// Unix timestamp before which the job won't run, if it was delayed
+    pub fn available_at(&self) -> Option<u64> {
+        self.available_at
+    }
+
+    /// Whether the job is due to run
+    pub fn is_available(&self) -> bool {
+        self.available_at.map_or(true, |at| at <= unix_now())
+    }
+
+    /// Jobs that run after this one, in order
+    pub fn chained(&self) -> &[QueuedJob] {
+        &self.chained
+    }
+
+    /// Push the next job in the chain after this one succeeded
+    pub(crate) async fn dispatch_next(&mut self, driver: &dyn QueueDriver) -> Result<(), QueueError> {
+        if self.chained.is_empty() {
+            return Ok(());
+        }
+        let mut next = self.chained.remove(0);
+        next.chained = std::mem::take(&mut self.chained);
+        push_unique(driver, next).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{JobFuture, MemoryQueue, SyncQueue};
+    use std::sync::{Arc, Mutex};
+
+    struct Step {
+        name: &'static str,
+        fail: bool,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Job for Step {
+        fn handle(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                self.log.lock().unwrap().push(self.name);
+                if self.fail { Err("step failed".into()) } else { Ok(()) }
+            })
+        }
+
+        fn max_retries(&self) -> u32 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chains_run_in_order_and_delays_hold_jobs_back() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let step = |name, fail| Step { name, fail, log: log.clone() };
+
+        // A failure stops the chain
+        let result = Queue::chain([step("a", false), step("b", true), step("c", false)]).send(&SyncQueue).await;
+        assert!(matches!(result, Err(QueueError::JobFailed { .. })));
+        assert_eq!(*log.lock().unwrap(), vec!["a", "b"]);
+
+        log.lock().unwrap().clear();
+        let queue = MemoryQueue::new();
+        Queue::chain([step("a", false), step("b", false)]).send(&queue).await.unwrap();
+        queue.push(QueuedJob::new(step("later", false)).delay(Duration::from_secs(60))).await.unwrap();
+        assert_eq!(queue.size("default"), 2);
+
+        while queue.work_next("default").await {}
+        assert_eq!(*log.lock().unwrap(), vec!["a", "b"]);
+        assert_eq!(queue.size("default"), 1);
+        assert!(queue.pop("default").is_none());
+    }
+}
@@ -0,0 +1,701 @@
+//! Queue driver backed by the ORM connection pool
+//!
+//! Pending jobs live in the `jobs` table and jobs that ran out of retries in
+//! `failed_jobs`. A worker claims a job by setting `reserved_at`; a claim older
+//! than [`DatabaseQueue::retry_after`] is treated as abandoned by a crashed
+//! worker and handed out again. Batches are counted in `job_batches`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::Row;
+
+use super::{
+    deserialize_job, Batch, BatchStore, FailedJob, JobOutcome, QueueDriver, QueueError, QueueStats, QueuedJob,
+    StoreFuture,
+};
+use crate::clock::unix_now;
+use crate::orm::connection::{try_get_pool, placeholders};
+use crate::orm::migration::Schema;
+
+/// Table holding jobs waiting to run
+pub const JOBS_TABLE: &str = "jobs";
+
+/// Table holding jobs that ran out of retries
+pub const FAILED_JOBS_TABLE: &str = "failed_jobs";
+
+/// Table holding job batches and their progress
+pub const JOB_BATCHES_TABLE: &str = "job_batches";
+
+/// Driver that stores jobs in the database configured for the ORM
+#[derive(Debug, Clone)]
+pub struct DatabaseQueue {
+    retry_after: Duration,
+}
+
+impl Default for DatabaseQueue {
+    fn default() -> Self {
+        Self {
+            retry_after: Duration::from_secs(300),
+        }
+    }
+}
+
+fn database_error(error: impl std::fmt::Display) -> QueueError {
+    QueueError::Driver(error.to_string())
+}
+
+impl DatabaseQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long a job may stay reserved before another worker may take it over
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Create the `jobs`, `failed_jobs` and `job_batches` tables if they
+    /// don't exist yet, adding columns that newer versions need
+    pub async fn install(&self) -> Result<(), QueueError> {
+        if !Schema::has_table(JOBS_TABLE).await.map_err(database_error)? {
+            Schema::create_table(JOBS_TABLE, |table| {
+                table.id("id");
+                table.string("queue", None);
+                table.string("job", None);
+                table.text("payload");
+                table.big_integer("attempts");
+                table.big_integer("available_at");
+                table.big_integer("reserved_at").nullable();
+                table.big_integer("created_at");
+                table.string("batch_id", Some(64)).nullable();
+                table.text("chain").nullable();
+                table.index(&["queue", "reserved_at"], None);
+            })
+            .execute()
+            .await
+            .map_err(database_error)?;
+        } else {
+            if !Schema::has_column(JOBS_TABLE, "batch_id").await.map_err(database_error)? {
+                Schema::alter_table(JOBS_TABLE, |table| {
+                    table.string("batch_id", Some(64)).nullable();
+                })
+                .execute()
+                .await
+                .map_err(database_error)?;
+            }
+            add_chain_column(JOBS_TABLE).await?;
+        }
+
+        if !Schema::has_table(FAILED_JOBS_TABLE).await.map_err(database_error)? {
+            Schema::create_table(FAILED_JOBS_TABLE, |table| {
+                table.id("id");
+                table.string("queue", None);
+                table.string("job", None);
+                table.text("payload");
+                table.text("error");
+                table.big_integer("failed_at");
+                table.text("chain").nullable();
+                table.index(&["failed_at"], None);
+            })
+            .execute()
+            .await
+            .map_err(database_error)?;
+        } else {
+            add_chain_column(FAILED_JOBS_TABLE).await?;
+        }
+
+        if !Schema::has_table(JOB_BATCHES_TABLE).await.map_err(database_error)? {
+            Schema::create_table(JOB_BATCHES_TABLE, |table| {
+                table.string("id", Some(64)).unique();
+                table.string("name", None);
+                table.big_integer("total_jobs");
+                table.big_integer("pending_jobs");
+                table.big_integer("failed_jobs");
+                table.boolean("allow_failures");
+                table.big_integer("created_at");
+                table.big_integer("cancelled_at").nullable();
+                table.big_integer("finished_at").nullable();
+            })
+            .execute()
+            .await
+            .map_err(database_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Claim and run the next job on `queue`.
+    ///
+    /// A failed job is released for another attempt until it runs out of
+    /// retries, then moved to `failed_jobs`. A job held up by its middleware
+    /// is released with a delay. Returns `false` when there was
+    /// nothing to run.
+    pub async fn work_next(&self, queue: &str) -> Result<bool, QueueError> {
+        let Some((id, mut job)) = self.reserve(queue).await? else {
+            return Ok(false);
+        };
+        if job.skip_if_cancelled(&DatabaseBatches).await? {
+            execute(&format!("DELETE FROM {} WHERE id = ?", JOBS_TABLE), |query| query.bind(id)).await?;
+            return Ok(true);
+        }
+        if let Some(wait) = job.acquire_locks().await? {
+            let available_at = job.delay(wait).available_at().unwrap_or_default() as i64;
+            execute(
+                &format!("UPDATE {} SET reserved_at = NULL, available_at = ? WHERE id = ?", JOBS_TABLE),
+                |query| query.bind(available_at).bind(id),
+            )
+            .await?;
+            return Ok(true);
+        }
+
+        let result = job.run().await;
+        job.release_locks(result.is_ok() || !job.can_retry()).await?;
+        match result {
+            Ok(()) => {
+                execute(&format!("DELETE FROM {} WHERE id = ?", JOBS_TABLE), |query| query.bind(id)).await?;
+                job.finish_in_batch(&DatabaseBatches, None).await?;
+                job.dispatch_next(self).await?;
+            }
+            Err(_) if job.can_retry() => {
+                execute(
+                    &format!("UPDATE {} SET attempts = ?, reserved_at = NULL, available_at = ? WHERE id = ?", JOBS_TABLE),
+                    |query| query.bind(i64::from(job.attempts())).bind(unix_now() as i64).bind(id),
+                )
+                .await?;
+            }
+            Err(error) => {
+                self.fail(id, &error.to_string()).await?;
+                job.finish_in_batch(&DatabaseBatches, Some(&error)).await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Claim the oldest available job, rebuilding it from its payload
+    async fn reserve(&self, queue: &str) -> Result<Option<(i64, QueuedJob)>, QueueError> {
+        let now = unix_now() as i64;
+        let expired = now - self.retry_after.as_secs() as i64;
+
+        loop {
+            let sql = placeholders(&format!(
+                "SELECT id, job, payload, attempts, batch_id, chain FROM {} \
+                 WHERE queue = ? AND available_at <= ? AND (reserved_at IS NULL OR reserved_at < ?) \
+                 ORDER BY id LIMIT 1",
+                JOBS_TABLE
+            ));
+            let row = sqlx::query(&sql)
+                .bind(queue.to_string())
+                .bind(now)
+                .bind(expired)
+                .fetch_optional(&try_get_pool().map_err(database_error)?)
+                .await
+                .map_err(database_error)?;
+            let Some(row) = row else {
+                return Ok(None);
+            };
+
+            let id: i64 = row.try_get(0).map_err(database_error)?;
+            let name: String = row.try_get(1).map_err(database_error)?;
+            let payload: String = row.try_get(2).map_err(database_error)?;
+            let attempts: i64 = row.try_get(3).map_err(database_error)?;
+            let batch_id: Option<String> = row.try_get(4).map_err(database_error)?;
+            let chain: Option<String> = row.try_get(5).map_err(database_error)?;
+
+            // Another worker may have claimed the job since it was selected
+            let claimed = execute(
+                &format!(
+                    "UPDATE {} SET reserved_at = ? WHERE id = ? AND (reserved_at IS NULL OR reserved_at < ?)",
+                    JOBS_TABLE
+                ),
+                |query| query.bind(now).bind(id).bind(expired),
+            )
+            .await?;
+            if claimed == 0 {
+                continue;
+            }
+
+            let restored = deserialize_job(&name, &payload).and_then(|job| Ok((job, restore_chain(chain.as_deref())?)));
+            match restored {
+                Ok((job, chained)) => {
+                    let job = QueuedJob::restore(name, queue.to_string(), attempts as u32, payload, batch_id, chained, job);
+                    return Ok(Some((id, job)));
+                }
+                Err(error) => self.fail(id, &error).await?,
+            }
+        }
+    }
+
+    /// Move a job from `jobs` to `failed_jobs`
+    async fn fail(&self, id: i64, error: &str) -> Result<(), QueueError> {
+        let mut transaction = try_get_pool().map_err(database_error)?.begin().await.map_err(database_error)?;
+
+        let sql = placeholders(&format!(
+            "INSERT INTO {} (queue, job, payload, error, failed_at, chain) \
+             SELECT queue, job, payload, ?, ?, chain FROM {} WHERE id = ?",
+            FAILED_JOBS_TABLE, JOBS_TABLE
+        ));
+        sqlx::query(&sql)
+            .bind(error.to_string())
+            .bind(unix_now() as i64)
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+
+        let sql = placeholders(&format!("DELETE FROM {} WHERE id = ?", JOBS_TABLE));
+        sqlx::query(&sql)
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+
+        transaction.commit().await.map_err(database_error)
+    }
+
+    /// Job counts and latency for every queue with jobs
+    pub async fn stats(&self) -> Result<Vec<QueueStats>, QueueError> {
+        let now = unix_now() as i64;
+        let expired = now - self.retry_after.as_secs() as i64;
+        let mut stats: BTreeMap<String, QueueStats> = BTreeMap::new();
+
+        let waiting = "(reserved_at IS NULL OR reserved_at < ?)";
+        let columns = [
+            (format!("SELECT queue, COUNT(*) FROM {} WHERE {} AND available_at <= ? GROUP BY queue", JOBS_TABLE, waiting), vec![expired, now]),
+            (format!("SELECT queue, COUNT(*) FROM {} WHERE {} AND available_at > ? GROUP BY queue", JOBS_TABLE, waiting), vec![expired, now]),
+            (format!("SELECT queue, COUNT(*) FROM {} WHERE reserved_at >= ? GROUP BY queue", JOBS_TABLE), vec![expired]),
+            (format!("SELECT queue, COUNT(*) FROM {} GROUP BY queue", FAILED_JOBS_TABLE), vec![]),
+            (format!("SELECT queue, MIN(available_at) FROM {} WHERE {} AND available_at <= ? GROUP BY queue", JOBS_TABLE, waiting), vec![expired, now]),
+        ];
+
+        for (column, (sql, binds)) in columns.iter().enumerate() {
+            let sql = placeholders(sql);
+            let query = binds.iter().fold(sqlx::query(&sql), |query, value| query.bind(*value));
+            let rows = query.fetch_all(&try_get_pool().map_err(database_error)?).await.map_err(database_error)?;
+
+            for row in rows {
+                let queue: String = row.try_get(0).map_err(database_error)?;
+                let value: i64 = row.try_get(1).map_err(database_error)?;
+                let entry = stats.entry(queue.clone()).or_insert_with(|| QueueStats {
+                    queue,
+                    ..Default::default()
+                });
+                match column {
+                    0 => entry.pending = value as u64,
+                    1 => entry.delayed = value as u64,
+                    2 => entry.processing = value as u64,
+                    3 => entry.failed = value as u64,
+                    _ => entry.latency = now.saturating_sub(value) as u64,
+                }
+            }
+        }
+
+        Ok(stats.into_values().collect())
+    }
+
+    /// Jobs in `failed_jobs`, oldest first
+    pub async fn failed(&self) -> Result<Vec<FailedJob>, QueueError> {
+        let sql = format!("SELECT id, job, queue, error, failed_at FROM {} ORDER BY id", FAILED_JOBS_TABLE);
+        let rows = sqlx::query(&sql).fetch_all(&try_get_pool().map_err(database_error)?).await.map_err(database_error)?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(FailedJob {
+                    id: row.try_get::<i64, _>(0).map_err(database_error)? as u64,
+                    job: row.try_get(1).map_err(database_error)?,
+                    queue: row.try_get(2).map_err(database_error)?,
+                    error: row.try_get(3).map_err(database_error)?,
+                    failed_at: row.try_get::<i64, _>(4).map_err(database_error)? as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Push a failed job back onto its queue with a fresh set of retries.
+    /// Returns `false` if there is no failed job with that id.
+    pub async fn retry(&self, id: u64) -> Result<bool, QueueError> {
+        Ok(self.requeue(Some(id as i64)).await? > 0)
+    }
+
+    /// Push every failed job back onto its queue, returning how many were moved
+    pub async fn retry_all(&self) -> Result<u64, QueueError> {
+        self.requeue(None).await
+    }
+
+    async fn requeue(&self, id: Option<i64>) -> Result<u64, QueueError> {
+        let filter = if id.is_some() { " WHERE id = ?" } else { "" };
+        let now = unix_now() as i64;
+        let mut transaction = try_get_pool().map_err(database_error)?.begin().await.map_err(database_error)?;
+
+        let sql = placeholders(&format!(
+            "INSERT INTO {} (queue, job, payload, attempts, available_at, reserved_at, created_at, chain) \
+             SELECT queue, job, payload, 0, ?, NULL, ?, chain FROM {}{}",
+            JOBS_TABLE, FAILED_JOBS_TABLE, filter
+        ));
+        let mut query = sqlx::query(&sql).bind(now).bind(now);
+        if let Some(id) = id {
+            query = query.bind(id);
+        }
+        let moved = query.execute(&mut *transaction).await.map_err(database_error)?.rows_affected();
+
+        let sql = placeholders(&format!("DELETE FROM {}{}", FAILED_JOBS_TABLE, filter));
+        let mut query = sqlx::query(&sql);
+        if let Some(id) = id {
+            query = query.bind(id);
+        }
+        query.execute(&mut *transaction).await.map_err(database_error)?;
+
+        transaction.commit().await.map_err(database_error)?;
+        Ok(moved)
+    }
+
+    /// Delete failed jobs older than `age`, returning how many were removed
+    pub async fn prune_failed(&self, age: Duration) -> Result<u64, QueueError> {
+        let cutoff = unix_now() as i64 - age.as_secs() as i64;
+        execute(&format!("DELETE FROM {} WHERE failed_at < ?", FAILED_JOBS_TABLE), |query| {
+            query.bind(cutoff)
+        })
+        .await
+    }
+
+    /// Delete every failed job, returning how many were removed
+    pub async fn clear_failed(&self) -> Result<u64, QueueError> {
+        execute(&format!("DELETE FROM {}", FAILED_JOBS_TABLE), |query| query).await
+    }
+}
+
+/// Add the `chain` column to tables created before chains existed
+async fn add_chain_column(table: &str) -> Result<(), QueueError> {
+    if Schema::has_column(table, "chain").await.map_err(database_error)? {
+        return Ok(());
+    }
+    Schema::alter_table(table, |table| {
+        table.text("chain").nullable();
+    })
+    .execute()
+    .await
+    .map_err(database_error)
+}
+
+/// Jobs chained after a job, stored as a JSON array of `[name, payload]` pairs
+fn serialize_chain(chained: &[QueuedJob]) -> Result<Option<String>, QueueError> {
+    if chained.is_empty() {
+        return Ok(None);
+    }
+    let jobs = chained
+        .iter()
+        .map(|job| match job.payload() {
+            Some(payload) => Ok((job.name(), payload)),
+            None => Err(QueueError::Driver(format!(
+                "chained job {} is not registered; call Queue::register::<{}>() before dispatching it",
+                job.name(),
+                job.name()
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    serde_json::to_string(&jobs).map(Some).map_err(database_error)
+}
+
+fn restore_chain(chain: Option<&str>) -> Result<Vec<QueuedJob>, String> {
+    let Some(chain) = chain else {
+        return Ok(Vec::new());
+    };
+    let jobs: Vec<(String, String)> = serde_json::from_str(chain).map_err(|e| format!("invalid job chain: {}", e))?;
+    jobs.into_iter()
+        .map(|(name, payload)| {
+            let job = deserialize_job(&name, &payload)?;
+            let queue = job.queue().to_string();
+            Ok(QueuedJob::restore(name, queue, 0, payload, None, Vec::new(), job))
+        })
+        .collect()
+}
+
+type AnyQuery<'q> = sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>;
+
+/// Run a statement with `?` placeholders, returning the affected row count
+async fn execute(sql: &str, bind: impl for<'q> FnOnce(AnyQuery<'q>) -> AnyQuery<'q>) -> Result<u64, QueueError> {
+    let sql = placeholders(sql);
+    let result = bind(sqlx::query(&sql)).execute(&try_get_pool().map_err(database_error)?).await.map_err(database_error)?;
+    Ok(result.rows_affected())
+}
+
+impl QueueDriver for DatabaseQueue {
+    fn push(&self, job: QueuedJob) -> Pin<Box<dyn Future<Output = Result<(), QueueError>> + Send + '_>> {
+        Box::pin(async move {
+            let Some(payload) = job.payload() else {
+                return Err(QueueError::Driver(format!(
+                    "job {} is not registered; call Queue::register::<{}>() before dispatching it",
+                    job.name(),
+                    job.name()
+                )));
+            };
+
+            let chain = serialize_chain(job.chained())?;
+
+            let now = unix_now() as i64;
+            let available_at = job.available_at().map_or(now, |at| at as i64);
+            let sql = format!(
+                "INSERT INTO {} (queue, job, payload, attempts, available_at, reserved_at, created_at, batch_id, chain) \
+                 VALUES (?, ?, ?, 0, ?, NULL, ?, ?, ?)",
+                JOBS_TABLE
+            );
+            execute(&sql, |query| {
+                query
+                    .bind(job.queue().to_string())
+                    .bind(job.name().to_string())
+                    .bind(payload.to_string())
+                    .bind(available_at)
+                    .bind(now)
+                    .bind(job.batch_id().map(str::to_string))
+                    .bind(chain)
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn batches(&self) -> Arc<dyn BatchStore> {
+        Arc::new(DatabaseBatches)
+    }
+
+    fn run_next<'a>(&'a self, queue: &'a str) -> StoreFuture<'a, bool> {
+        Box::pin(self.work_next(queue))
+    }
+
+    fn backlog(&self) -> StoreFuture<'_, Vec<QueueStats>> {
+        Box::pin(self.stats())
+    }
+}
+
+/// Batches kept in `job_batches`, so every worker process updates the same counts
+struct DatabaseBatches;
+
+impl BatchStore for DatabaseBatches {
+    fn create(&self, batch: Batch) -> StoreFuture<'_, ()> {
+        Box::pin(async move {
+            let sql = format!(
+                "INSERT INTO {} (id, name, total_jobs, pending_jobs, failed_jobs, allow_failures, created_at, cancelled_at, finished_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                JOB_BATCHES_TABLE
+            );
+            execute(&sql, |query| {
+                query
+                    .bind(batch.id)
+                    .bind(batch.name)
+                    .bind(batch.total_jobs as i64)
+                    .bind(batch.pending_jobs as i64)
+                    .bind(batch.failed_jobs as i64)
+                    .bind(batch.allow_failures)
+                    .bind(batch.created_at as i64)
+                    .bind(batch.cancelled_at.map(|at| at as i64))
+                    .bind(batch.finished_at.map(|at| at as i64))
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn find(&self, id: String) -> StoreFuture<'_, Option<Batch>> {
+        Box::pin(async move {
+            let sql = placeholders(&format!(
+                "SELECT id, name, total_jobs, pending_jobs, failed_jobs, allow_failures, created_at, cancelled_at, finished_at \
+                 FROM {} WHERE id = ?",
+                JOB_BATCHES_TABLE
+            ));
+            let row = sqlx::query(&sql).bind(id).fetch_optional(&try_get_pool().map_err(database_error)?).await.map_err(database_error)?;
+            let Some(row) = row else {
+                return Ok(None);
+            };
+
+            let count = |index: usize| row.try_get::<i64, _>(index).map(|count| count as u64).map_err(database_error);
+            let time = |index: usize| {
+                row.try_get::<Option<i64>, _>(index).map(|at| at.map(|at| at as u64)).map_err(database_error)
+            };
+            Ok(Some(Batch {
+                id: row.try_get(0).map_err(database_error)?,
+                name: row.try_get(1).map_err(database_error)?,
+                total_jobs: count(2)?,
+                pending_jobs: count(3)?,
+                failed_jobs: count(4)?,
+                allow_failures: row.try_get(5).map_err(database_error)?,
+                created_at: count(6)?,
+                cancelled_at: time(7)?,
+                finished_at: time(8)?,
+            }))
+        })
+    }
+
+    fn record(&self, id: String, outcome: JobOutcome) -> StoreFuture<'_, Option<Batch>> {
+        Box::pin(async move {
+            // `finished_at` comes first because MySQL applies assignments in
+            // order, so it must see the old `pending_jobs` like other databases
+            let sql = format!(
+                "UPDATE {} SET finished_at = CASE WHEN pending_jobs <= 1 THEN ? ELSE finished_at END, \
+                 pending_jobs = pending_jobs - 1, failed_jobs = failed_jobs + ? WHERE id = ? AND pending_jobs > 0",
+                JOB_BATCHES_TABLE
+            );
+            let failed = i64::from(outcome == JobOutcome::Failed);
+            execute(&sql, |query| query.bind(unix_now() as i64).bind(failed).bind(id.clone())).await?;
+            self.find(id).await
+        })
+    }
+
+    fn cancel(&self, id: String) -> StoreFuture<'_, bool> {
+        Box::pin(async move {
+            let sql = format!("UPDATE {} SET cancelled_at = ? WHERE id = ? AND cancelled_at IS NULL", JOB_BATCHES_TABLE);
+            Ok(execute(&sql, |query| query.bind(unix_now() as i64).bind(id)).await? > 0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::connection::sqlite_test_pool;
+    use crate::queue::{middleware, Job, JobFuture, JobMiddleware, Queue};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Import {
+        queue: String,
+        fail: bool,
+        /// Held back by a rate limiter that never has a free slot
+        throttled: bool,
+    }
+
+    impl Job for Import {
+        fn handle(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                if self.fail {
+                    return Err("the file is corrupt".into());
+                }
+                Ok(())
+            })
+        }
+
+        fn queue(&self) -> &str {
+            &self.queue
+        }
+
+        fn max_retries(&self) -> u32 {
+            1
+        }
+
+        fn middleware(&self) -> Vec<JobMiddleware> {
+            match self.throttled {
+                true => vec![middleware::rate_limited(&self.queue, 0, Duration::from_secs(60))],
+                false => Vec::new(),
+            }
+        }
+    }
+
+    /// A queue on the shared test database, with its tables installed once.
+    /// Each test pushes onto a queue named after itself.
+    async fn database_queue() -> DatabaseQueue {
+        static INSTALLED: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+        sqlite_test_pool().await;
+        Queue::register::<Import>();
+        INSTALLED.get_or_init(|| async { DatabaseQueue::new().install().await.unwrap() }).await;
+        DatabaseQueue::new().retry_after(Duration::from_secs(60))
+    }
+
+    async fn push(driver: &DatabaseQueue, queue: &str, fail: bool, throttled: bool) {
+        driver.push(QueuedJob::new(Import { queue: queue.to_string(), fail, throttled })).await.unwrap();
+    }
+
+    /// `(attempts, available_at, reserved_at)` of every job on `queue`
+    async fn jobs(queue: &str) -> Vec<(i64, i64, Option<i64>)> {
+        let sql = placeholders(&format!("SELECT attempts, available_at, reserved_at FROM {} WHERE queue = ? ORDER BY id", JOBS_TABLE));
+        let rows = sqlx::query(&sql).bind(queue.to_string()).fetch_all(&try_get_pool().unwrap()).await.unwrap();
+        rows.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect()
+    }
+
+    #[tokio::test]
+    async fn test_jobs_are_claimed_oldest_first_and_only_once() {
+        let driver = database_queue().await;
+        push(&driver, "db-claim", false, false).await;
+        push(&driver, "db-claim", false, false).await;
+
+        let (first, job) = driver.reserve("db-claim").await.unwrap().unwrap();
+        assert_eq!((job.queue(), job.attempts()), ("db-claim", 0));
+        let (second, _) = driver.reserve("db-claim").await.unwrap().unwrap();
+        assert!(first < second);
+        assert!(driver.reserve("db-claim").await.unwrap().is_none());
+        assert!(jobs("db-claim").await.iter().all(|(_, _, reserved_at)| reserved_at.is_some()));
+
+        // Two workers racing for the same job: one of them gets it
+        push(&driver, "db-claim-race", false, false).await;
+        let (a, b) = tokio::join!(driver.reserve("db-claim-race"), driver.reserve("db-claim-race"));
+        assert_eq!([a.unwrap().is_some(), b.unwrap().is_some()].iter().filter(|claimed| **claimed).count(), 1);
+
+        // A job that runs is removed
+        push(&driver, "db-claim-run", false, false).await;
+        assert!(driver.work_next("db-claim-run").await.unwrap());
+        assert!(jobs("db-claim-run").await.is_empty());
+        assert!(!driver.work_next("db-claim-run").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_held_back_jobs_are_released_with_a_delay() {
+        let driver = database_queue().await;
+        push(&driver, "db-release", false, true).await;
+
+        assert!(driver.work_next("db-release").await.unwrap());
+        let [(attempts, available_at, reserved_at)] = jobs("db-release").await[..] else {
+            panic!("the job should still be queued");
+        };
+        assert_eq!((attempts, reserved_at), (0, None));
+        assert!(available_at > unix_now() as i64 && available_at <= unix_now() as i64 + 60);
+        assert!(driver.reserve("db-release").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_jobs_are_retried_then_moved_to_failed_jobs() {
+        let driver = database_queue().await;
+        push(&driver, "db-retry", true, false).await;
+
+        assert!(driver.work_next("db-retry").await.unwrap());
+        let [(attempts, _, reserved_at)] = jobs("db-retry").await[..] else {
+            panic!("the job should be released for another attempt");
+        };
+        assert_eq!((attempts, reserved_at), (1, None));
+
+        assert!(driver.work_next("db-retry").await.unwrap());
+        assert!(jobs("db-retry").await.is_empty());
+        let failed: Vec<FailedJob> = driver.failed().await.unwrap().into_iter().filter(|job| job.queue == "db-retry").collect();
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].job.ends_with("Import"));
+        assert!(failed[0].error.contains("the file is corrupt"), "{}", failed[0].error);
+
+        // Retrying it starts over with a fresh set of attempts
+        assert!(driver.retry(failed[0].id).await.unwrap());
+        assert!(!driver.retry(failed[0].id).await.unwrap());
+        assert!(matches!(jobs("db-retry").await[..], [(0, _, None)]));
+    }
+
+    #[tokio::test]
+    async fn test_expired_reservations_are_picked_up_again() {
+        let driver = database_queue().await;
+        push(&driver, "db-expired", false, false).await;
+
+        let (id, _) = driver.reserve("db-expired").await.unwrap().unwrap();
+        assert!(driver.reserve("db-expired").await.unwrap().is_none());
+
+        // The worker holding it went away before `retry_after` ran out
+        let age = |seconds: i64| {
+            let sql = format!("UPDATE {} SET reserved_at = reserved_at - ? WHERE id = ?", JOBS_TABLE);
+            async move { execute(&sql, |query| query.bind(seconds).bind(id)).await.unwrap() }
+        };
+        age(50).await;
+        assert!(driver.reserve("db-expired").await.unwrap().is_none());
+        age(11).await;
+        let (reclaimed, _) = driver.reserve("db-expired").await.unwrap().unwrap();
+        assert_eq!(reclaimed, id);
+        assert!(driver.reserve("db-expired").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Job middleware
+//!
+//! Jobs can list middleware that decides whether they may be queued or run
+//! right now:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use torch_web::queue::{middleware, Job, JobFuture, JobMiddleware};
+//!
+//! struct SyncContacts {
+//!     account_id: u64,
+//! }
+//!
+//! impl Job for SyncContacts {
+//!     fn handle(&self) -> JobFuture<'_> {
+//!         Box::pin(async { Ok(()) })
+//!     }
+//!
+//!     fn middleware(&self) -> Vec<JobMiddleware> {
+//!         vec![
+//!             // At most one queued sync per account each 10 minutes
+//!             middleware::unique_for(Duration::from_secs(600)).by(self.account_id),
+//!             // Never two syncs of the same account at once
+//!             middleware::without_overlapping().by(self.account_id),
+//!             // Shared by every job calling the Mailgun API
+//!             middleware::rate_limited("mailgun", 100, Duration::from_secs(60)),
+//!         ]
+//!     }
+//! }
+//! ```
+//!
+//! - [`unique_for`] drops a dispatch while an identical job is waiting or
+//!   running, until it finishes or the lock expires.
+//! - [`without_overlapping`] puts a job back on the queue while another job
+//!   with the same key is running.
+//! - [`rate_limited`] puts a job back on the queue until the named limiter has
+//!   room again.
+//!
+//! A job that is put back doesn't use up an attempt. `SyncQueue` has nowhere
+//! to put it, so it waits instead. Locks and counters live in the cache set
+//! with [`Queue::use_cache`]; give it a `RedisCache` when workers run in more
+//! than one process.
+
+use std::fmt::Display;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::{Queue, QueueDriver, QueueError, QueuedJob};
+use crate::cache::{Cache, MemoryCache};
+use crate::clock::unix_now_ms;
+
+/// How long an overlapping job waits before it is tried again
+const DEFAULT_RELEASE_AFTER: Duration = Duration::from_secs(5);
+
+static CACHE: RwLock<Option<Arc<dyn Cache>>> = RwLock::new(None);
+
+/// The cache holding job locks and rate limit counters
+fn cache() -> Arc<dyn Cache> {
+    if let Some(fake) = crate::cache::active_fake() {
+        return fake;
+    }
+    let mut cache = CACHE.write().unwrap();
+    cache.get_or_insert_with(|| Arc::new(MemoryCache::new(None))).clone()
+}
+
+fn cache_error(error: Box<dyn std::error::Error>) -> QueueError {
+    QueueError::Driver(format!("job lock cache: {}", error))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    UniqueFor(Duration),
+    WithoutOverlapping,
+    RateLimited { limiter: String, limit: u32, per: Duration },
+}
+
+/// A rule checked before a job is queued or run, from [`Job::middleware`]
+///
+/// [`Job::middleware`]: super::Job::middleware
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobMiddleware {
+    kind: Kind,
+    key: Option<String>,
+    release_after: Duration,
+    expire_after: Option<Duration>,
+}
+
+/// Don't queue the job while one of the same type and key is waiting or
+/// running, for at most `ttl`
+pub fn unique_for(ttl: Duration) -> JobMiddleware {
+    JobMiddleware::new(Kind::UniqueFor(ttl))
+}
+
+/// Don't run the job while one of the same type and key is running
+pub fn without_overlapping() -> JobMiddleware {
+    JobMiddleware::new(Kind::WithoutOverlapping)
+}
+
+/// Run at most `limit` jobs per `per` through the limiter called `limiter`,
+/// which may be shared by different job types
+pub fn rate_limited(limiter: &str, limit: u32, per: Duration) -> JobMiddleware {
+    JobMiddleware::new(Kind::RateLimited {
+        limiter: limiter.to_string(),
+        limit,
+        per: per.max(Duration::from_secs(1)),
+    })
+}
+
+impl JobMiddleware {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            key: None,
+            release_after: DEFAULT_RELEASE_AFTER,
+            expire_after: None,
+        }
+    }
+
+    /// Scope the lock to one key, such as a user id, instead of the whole
+    /// job type
+    pub fn by(mut self, key: impl Display) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    /// How long an overlapping job waits before it is tried again
+    /// (default 5 seconds)
+    pub fn release_after(mut self, delay: Duration) -> Self {
+        self.release_after = delay;
+        self
+    }
+
+    /// Let the overlap lock lapse after `ttl`, in case the worker holding it
+    /// dies without releasing it
+    pub fn expire_after(mut self, ttl: Duration) -> Self {
+        self.expire_after = Some(ttl);
+        self
+    }
+
+    fn lock_key(&self, kind: &str, job: &str) -> String {
+        match &self.key {
+            Some(key) => format!("torch:job:{}:{}:{}", kind, job, key),
+            None => format!("torch:job:{}:{}", kind, job),
+        }
+    }
+}
+
+/// Take a slot from a fixed-window limiter, or say how long until the
+/// window resets. Slots are claimed with `add` so concurrent workers never
+/// share one.
+async fn take_rate_slot(cache: &dyn Cache, limiter: &str, limit: u32, per: Duration) -> Result<Option<Duration>, QueueError> {
+    let per_millis = per.as_millis().max(1) as u64;
+    let remaining = Duration::from_millis(per_millis - unix_now_ms() % per_millis);
+
+    let counter = format!("torch:job:rate:{}", limiter);
+    let mut slot: u32 = cache.get(&counter).await.and_then(|count| count.parse().ok()).unwrap_or(0);
+    while slot < limit {
+        if cache.add(&format!("{}:{}", counter, slot), "1", Some(remaining)).await.map_err(cache_error)? {
+            let _ = cache.set(&counter, &(slot + 1).to_string(), Some(remaining)).await;
+            return Ok(None);
+        }
+        slot += 1;
+    }
+    Ok(Some(remaining))
+}
+
+/// Push a job unless an identical one holds its `unique_for` lock
+pub(crate) async fn push_unique(driver: &dyn QueueDriver, job: QueuedJob) -> Result<(), QueueError> {
+    if !job.acquire_unique().await? {
+        return Ok(());
+    }
+    driver.push(job).await
+}
+
+impl Queue {
+    /// Keep job locks and rate limit counters in `cache` instead of in
+    /// process memory
+    pub fn use_cache(cache: Arc<dyn Cache>) {
+        *CACHE.write().unwrap() = Some(cache);
+    }
+}
+
+impl QueuedJob {
+    /// Take the job's `unique_for` lock, if it has one. Returns `false` when
+    /// an identical job already holds it and this one shouldn't be queued.
+    pub(crate) async fn acquire_unique(&self) -> Result<bool, QueueError> {
+        let cache = cache();
+        for middleware in self.job.middleware() {
+            if let Kind::UniqueFor(ttl) = middleware.kind {
+                let key = middleware.lock_key("unique", self.name());
+                if !cache.add(&key, "1", Some(ttl)).await.map_err(cache_error)? {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Check the job's overlap and rate limit middleware before running it.
+    /// Returns how long to wait when it can't run yet; otherwise its overlap
+    /// locks are held until [`release_locks`](Self::release_locks).
+    pub(crate) async fn acquire_locks(&mut self) -> Result<Option<Duration>, QueueError> {
+        let cache = cache();
+        for middleware in self.job.middleware() {
+            let wait = match &middleware.kind {
+                Kind::UniqueFor(_) => None,
+                Kind::WithoutOverlapping => {
+                    let key = middleware.lock_key("overlap", self.name());
+                    if cache.add(&key, "1", middleware.expire_after).await.map_err(cache_error)? {
+                        self.locks.push(key);
+                        None
+                    } else {
+                        Some(middleware.release_after)
+                    }
+                }
+                Kind::RateLimited { limiter, limit, per } => take_rate_slot(&*cache, limiter, *limit, *per).await?,
+            };
+            if wait.is_some() {
+                self.release_locks(false).await?;
+                return Ok(wait);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Release the overlap locks taken for this run, and the `unique_for`
+    /// lock once the job is `finished` for good
+    pub(crate) async fn release_locks(&mut self, finished: bool) -> Result<(), QueueError> {
+        let cache = cache();
+        for key in std::mem::take(&mut self.locks) {
+            cache.delete(&key).await.map_err(cache_error)?;
+        }
+        if finished {
+            for middleware in self.job.middleware() {
+                if matches!(middleware.kind, Kind::UniqueFor(_)) {
+                    cache.delete(&middleware.lock_key("unique", self.name())).await.map_err(cache_error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Job, JobFuture, MemoryQueue};
+    use std::sync::Mutex;
+
+    struct Export {
+        account: u64,
+        log: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Job for Export {
+        fn handle(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                self.log.lock().unwrap().push(self.account);
+                Ok(())
+            })
+        }
+
+        fn middleware(&self) -> Vec<JobMiddleware> {
+            vec![
+                unique_for(Duration::from_secs(60)).by(self.account),
+                without_overlapping().by(self.account),
+                rate_limited("test-exports", 2, Duration::from_secs(3600)),
+            ]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unique_overlap_and_rate_limit_middleware() {
+        let _real = crate::cache::without_fake();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let export = |account| Export { account, log: log.clone() };
+        let queue = MemoryQueue::new();
+
+        // The second dispatch for account 1 is dropped while the first waits
+        for account in [1, 1, 2] {
+            push_unique(&queue, QueuedJob::new(export(account))).await.unwrap();
+        }
+        assert_eq!(queue.size("default"), 2);
+
+        // A running job for account 1 makes another one wait
+        let mut running = QueuedJob::new(export(1));
+        assert_eq!(running.acquire_locks().await.unwrap(), None);
+        let mut overlapping = QueuedJob::new(export(1));
+        assert_eq!(overlapping.acquire_locks().await.unwrap(), Some(DEFAULT_RELEASE_AFTER));
+        running.release_locks(false).await.unwrap();
+
+        // Two slots per hour, one of them taken by `running` above
+        while queue.work_next("default").await {}
+        assert_eq!(*log.lock().unwrap(), vec![1]);
+        assert_eq!(queue.size("default"), 1);
+
+        // Finishing released the unique lock
+        push_unique(&queue, QueuedJob::new(export(1))).await.unwrap();
+        assert_eq!(queue.size("default"), 2);
+    }
+}
@@ -0,0 +1,313 @@
+//! Long-running queue workers
+//!
+//! A [`Worker`] pulls jobs from a driver until it is told to stop. On
+//! `SIGTERM` (what Kubernetes and most process managers send) or Ctrl+C it
+//! stops taking new jobs, lets the ones it is running finish, and returns,
+//! so deploys don't cut jobs off halfway:
+//!
+//! ```rust,ignore
+//! use std::time::Duration;
+//! use torch_web::queue::{DatabaseQueue, Worker};
+//!
+//! # async fn example() {
+//! let report = Worker::new(DatabaseQueue::new())
+//!     .queues(&["high", "default"])
+//!     .concurrency(4)
+//!     .sleep(Duration::from_secs(1))
+//!     .run()
+//!     .await;
+//! println!("processed {} jobs", report.processed);
+//! # }
+//! ```
+//!
+//! Earlier queues take priority: a worker only looks at `default` when
+//! `high` has nothing due. With the `monitoring` feature the worker records
+//! `torch_queue_jobs_processed_total` and `torch_queue_job_duration_seconds`,
+//! and every [`metrics_interval`](Worker::metrics_interval) the
+//! `torch_queue_depth` and `torch_queue_latency_seconds` gauges from
+//! [`Queue::backlog`](super::Queue::backlog). The same numbers can be served
+//! to an autoscaler with [`App::queue_backlog`](crate::App::queue_backlog).
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use super::QueueDriver;
+
+/// Stops a running [`Worker`] the same way a shutdown signal does
+#[derive(Clone, Default)]
+pub struct WorkerHandle {
+    stopping: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl WorkerHandle {
+    /// Stop taking new jobs; the worker returns once jobs in flight finish
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Wait until `timeout` passes or the worker is stopped
+    async fn idle(&self, timeout: Duration) {
+        let stopped = self.notify.notified();
+        tokio::pin!(stopped);
+        stopped.as_mut().enable();
+        if self.is_stopping() {
+            return;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(timeout) => {}
+            _ = stopped => {}
+        }
+    }
+}
+
+/// What a worker did before it stopped
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerReport {
+    /// Jobs run, whether they succeeded or not
+    pub processed: u64,
+    /// Times the driver couldn't be read, such as a lost database connection
+    pub errors: u64,
+}
+
+/// Runs jobs from a queue driver until stopped
+pub struct Worker {
+    driver: Arc<dyn QueueDriver>,
+    queues: Vec<String>,
+    concurrency: usize,
+    sleep: Duration,
+    max_jobs: Option<u64>,
+    #[cfg_attr(not(feature = "monitoring"), allow(dead_code))]
+    metrics_interval: Duration,
+    handle: WorkerHandle,
+}
+
+impl Worker {
+    pub fn new<D: QueueDriver>(driver: D) -> Self {
+        Self {
+            driver: Arc::new(driver),
+            queues: vec!["default".to_string()],
+            concurrency: 1,
+            sleep: Duration::from_secs(3),
+            max_jobs: None,
+            metrics_interval: Duration::from_secs(15),
+            handle: WorkerHandle::default(),
+        }
+    }
+
+    /// Queues to take jobs from, highest priority first (default `default`)
+    pub fn queues(mut self, queues: &[&str]) -> Self {
+        self.queues = queues.iter().map(|queue| queue.to_string()).collect();
+        self
+    }
+
+    /// How many jobs to run at once (default 1)
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How long to wait before polling again when no job is due (default 3 seconds)
+    pub fn sleep(mut self, sleep: Duration) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    /// Stop after about this many jobs, e.g. to recycle the process
+    pub fn max_jobs(mut self, max_jobs: u64) -> Self {
+        self.max_jobs = Some(max_jobs);
+        self
+    }
+
+    /// How often queue depth and latency gauges are recorded (default 15 seconds)
+    pub fn metrics_interval(mut self, interval: Duration) -> Self {
+        self.metrics_interval = interval;
+        self
+    }
+
+    /// A handle that stops the worker from another task
+    pub fn handle(&self) -> WorkerHandle {
+        self.handle.clone()
+    }
+
+    /// Work until stopped by a signal, the handle or `max_jobs`
+    pub async fn run(self) -> WorkerReport {
+        let handle = self.handle.clone();
+        let signals = tokio::spawn(async move {
+            shutdown_signal().await;
+            eprintln!("🛑 Shutdown requested, finishing jobs in flight");
+            handle.stop();
+        });
+        #[cfg(feature = "monitoring")]
+        let gauges = tokio::spawn(record_backlog(self.driver.clone(), self.metrics_interval, self.handle.clone()));
+
+        let worker = Arc::new(self);
+        let processed = Arc::new(AtomicU64::new(0));
+        let errors = Arc::new(AtomicU64::new(0));
+        let loops: Vec<_> = (0..worker.concurrency)
+            .map(|_| tokio::spawn(worker.clone().work(processed.clone(), errors.clone())))
+            .collect();
+        for task in loops {
+            let _ = task.await;
+        }
+
+        signals.abort();
+        #[cfg(feature = "monitoring")]
+        gauges.abort();
+        WorkerReport {
+            processed: processed.load(Ordering::SeqCst),
+            errors: errors.load(Ordering::SeqCst),
+        }
+    }
+
+    /// One slot's loop: run the first due job from the queues in priority
+    /// order, or wait when there is none
+    async fn work(self: Arc<Self>, processed: Arc<AtomicU64>, errors: Arc<AtomicU64>) {
+        while !self.handle.is_stopping() {
+            let mut worked = false;
+            for queue in &self.queues {
+                let started = Instant::now();
+                match self.driver.run_next(queue).await {
+                    Ok(true) => {
+                        worked = true;
+                        let total = processed.fetch_add(1, Ordering::SeqCst) + 1;
+                        record_job(queue, started.elapsed());
+                        if self.max_jobs.is_some_and(|max| total >= max) {
+                            self.handle.stop();
+                        }
+                        break;
+                    }
+                    Ok(false) => {}
+                    Err(error) => {
+                        errors.fetch_add(1, Ordering::SeqCst);
+                        eprintln!("⚠️  Queue worker could not read {}: {}", queue, error);
+                    }
+                }
+            }
+            if !worked {
+                self.handle.idle(self.sleep).await;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "monitoring")]
+fn record_job(queue: &str, duration: Duration) {
+    metrics::counter!("torch_queue_jobs_processed_total", 1, "queue" => queue.to_string());
+    metrics::histogram!("torch_queue_job_duration_seconds", duration.as_secs_f64(), "queue" => queue.to_string());
+}
+
+#[cfg(not(feature = "monitoring"))]
+fn record_job(_queue: &str, _duration: Duration) {}
+
+/// Record depth and latency gauges for every queue until the worker stops
+#[cfg(feature = "monitoring")]
+async fn record_backlog(driver: Arc<dyn QueueDriver>, interval: Duration, handle: WorkerHandle) {
+    while !handle.is_stopping() {
+        if let Ok(stats) = driver.backlog().await {
+            for queue in stats {
+                metrics::gauge!("torch_queue_depth", queue.pending as f64, "queue" => queue.queue.clone());
+                metrics::gauge!("torch_queue_latency_seconds", queue.latency as f64, "queue" => queue.queue);
+            }
+        }
+        handle.idle(interval).await;
+    }
+}
+
+/// The backlog as JSON, with totals up front for autoscalers that read a
+/// single value: `{"pending": 12, "latency": 30, "queues": [...]}`
+#[cfg(feature = "json")]
+pub(crate) async fn backlog() -> crate::Response {
+    match super::Queue::backlog().await {
+        Ok(queues) => {
+            let pending: u64 = queues.iter().map(|queue| queue.pending).sum();
+            let latency = queues.iter().map(|queue| queue.latency).max().unwrap_or(0);
+            crate::Response::ok()
+                .json(&serde_json::json!({ "pending": pending, "latency": latency, "queues": queues }))
+                .unwrap_or_else(|_| crate::Response::internal_error())
+        }
+        Err(error) => crate::Response::with_status(http::StatusCode::SERVICE_UNAVAILABLE).body(error.to_string()),
+    }
+}
+
+/// Resolves on Ctrl+C, or `SIGTERM` on Unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Job, JobFuture, MemoryQueue, QueueStats, QueuedJob};
+
+    struct Resize {
+        millis: u64,
+    }
+
+    impl Job for Resize {
+        fn handle(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(self.millis)).await;
+                Ok(())
+            })
+        }
+
+        fn queue(&self) -> &str {
+            "images"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_drains_queues_and_stops_gracefully() {
+        let queue = MemoryQueue::new();
+        for _ in 0..3 {
+            queue.push(QueuedJob::new(Resize { millis: 10 })).await.unwrap();
+        }
+        queue.push(QueuedJob::new(Resize { millis: 10 }).delay(Duration::from_secs(60))).await.unwrap();
+        let backlog = queue.backlog().await.unwrap();
+        assert_eq!(
+            backlog,
+            vec![QueueStats { queue: "images".into(), pending: 3, delayed: 1, ..Default::default() }]
+        );
+
+        let report = Worker::new(queue.clone()).queues(&["default", "images"]).concurrency(2).max_jobs(3).run().await;
+        assert_eq!(report, WorkerReport { processed: 3, errors: 0 });
+        assert_eq!(queue.size("images"), 1);
+
+        // Stopping waits for the job in flight
+        queue.push(QueuedJob::new(Resize { millis: 100 })).await.unwrap();
+        let worker = Worker::new(queue.clone()).queues(&["images"]).sleep(Duration::from_millis(10));
+        let handle = worker.handle();
+        let running = tokio::spawn(worker.run());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        handle.stop();
+        assert_eq!(running.await.unwrap().processed, 1);
+        assert_eq!(queue.backlog().await.unwrap()[0].pending, 0);
+    }
+}
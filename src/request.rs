@@ -6,12 +6,31 @@
 
 use std::collections::HashMap;
 use std::any::{Any, TypeId};
-use std::sync::Arc;
-use http::{HeaderMap, Method, Uri, Version};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use futures::{Stream, StreamExt};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Uri, Version};
 use http_body_util::BodyExt;
-use hyper::body::Incoming;
+use hyper::body::{Bytes, Incoming};
 use crate::extractors::state::StateMap;
 
+/// Client address stored in the request extensions
+#[derive(Debug, Clone, Copy)]
+struct RemoteAddr(std::net::SocketAddr);
+
+/// Chunks of a request body, read from the client as they are polled
+pub(crate) type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, Box<dyn std::error::Error + Send + Sync>>> + Send>>;
+
+/// A body that is still being received. The mutex only makes the stream
+/// `Sync`; it is only ever reached through `&mut Request`.
+struct StreamingBody(Mutex<BodyStream>);
+
+impl std::fmt::Debug for StreamingBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StreamingBody")
+    }
+}
+
 /// HTTP request wrapper that provides convenient access to request data.
 ///
 /// The `Request` struct encapsulates all the information about an incoming HTTP request,
@@ -90,6 +109,7 @@ pub struct Request {
     version: Version,
     headers: HeaderMap,
     body: Vec<u8>,
+    stream: Option<StreamingBody>,
     params: HashMap<String, String>,
     query: HashMap<String, String>,
     extensions: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
@@ -117,6 +137,7 @@ impl Request {
             version: Version::HTTP_11,
             headers: HeaderMap::new(),
             body: Vec::new(),
+            stream: None,
             params: HashMap::new(),
             query: HashMap::new(),
             extensions: HashMap::new(),
@@ -163,18 +184,91 @@ impl Request {
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let body_bytes = body.collect().await?.to_bytes().to_vec();
 
+        Ok(Self::from_parts(parts, body_bytes))
+    }
+
+    /// Creates a new Request from already-buffered request parts and body.
+    ///
+    /// Useful for tests and in-process clients that build requests with
+    /// `http::Request::builder()` instead of receiving them from the network.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::Request;
+    ///
+    /// let (parts, _) = http::Request::builder()
+    ///     .uri("/users?page=2")
+    ///     .body(())
+    ///     .unwrap()
+    ///     .into_parts();
+    ///
+    /// let req = Request::from_parts(parts, Vec::new());
+    /// assert_eq!(req.path(), "/users");
+    /// assert_eq!(req.query("page"), Some("2"));
+    /// ```
+    pub fn from_parts(parts: http::request::Parts, body: Vec<u8>) -> Self {
         let query = Self::parse_query_string(parts.uri.query().unwrap_or(""));
 
-        Ok(Request {
+        Request {
             method: parts.method,
             uri: parts.uri,
             version: parts.version,
             headers: parts.headers,
-            body: body_bytes,
+            body,
+            stream: None,
             params: HashMap::new(),
             query,
             extensions: HashMap::new(),
-        })
+        }
+    }
+
+    /// Creates a request for in-process dispatch, e.g. from tests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `uri` is not a valid request target.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::{Method, Request};
+    ///
+    /// let req = Request::mock(Method::POST, "/posts?draft=1")
+    ///     .with_header("content-type", "application/x-www-form-urlencoded")
+    ///     .with_body("title=Hello");
+    ///
+    /// assert_eq!(req.path(), "/posts");
+    /// assert_eq!(req.query("draft"), Some("1"));
+    /// assert_eq!(req.body(), b"title=Hello");
+    /// ```
+    pub fn mock(method: Method, uri: &str) -> Self {
+        let (parts, _) = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .expect("invalid request URI")
+            .into_parts();
+
+        Self::from_parts(parts, Vec::new())
+    }
+
+    /// Adds a header, replacing any existing value
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` is not a valid header.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        let value = HeaderValue::from_str(value).expect("invalid header value");
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Replaces the request body
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
     }
 
     /// Returns the HTTP method of the request.
@@ -443,6 +537,54 @@ impl Request {
         &self.body
     }
 
+    /// Move the body out, leaving it empty (for extractors that keep it)
+    pub(crate) fn take_body(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.body)
+    }
+
+    /// Leave the body with the client, to be read as it is consumed (see
+    /// [`take_body_stream`](Self::take_body_stream))
+    pub(crate) fn with_body_stream(mut self, stream: BodyStream) -> Self {
+        self.stream = Some(StreamingBody(Mutex::new(stream)));
+        self
+    }
+
+    /// Whether the body is still being received from the client, as for
+    /// `multipart/form-data` uploads. [`body`](Self::body) is empty until an
+    /// extractor such as [`Multipart`](crate::extractors::Multipart) reads it.
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Move the body out as a stream of chunks: the one still being
+    /// received, or the buffered body as a single chunk
+    pub(crate) fn take_body_stream(&mut self) -> BodyStream {
+        match self.stream.take() {
+            Some(StreamingBody(stream)) => stream.into_inner().unwrap_or_else(PoisonError::into_inner),
+            None => {
+                let body = self.take_body();
+                Box::pin(futures::stream::iter((!body.is_empty()).then(|| Ok(Bytes::from(body)))))
+            }
+        }
+    }
+
+    /// Fail a body still being received once it goes over `max` bytes
+    pub(crate) fn limit_body_stream(&mut self, max: usize) {
+        let Some(StreamingBody(stream)) = self.stream.take() else { return };
+        let stream = stream.into_inner().unwrap_or_else(PoisonError::into_inner);
+        let limited = stream.scan(0usize, move |received, chunk| {
+            let chunk = chunk.and_then(|chunk| {
+                *received += chunk.len();
+                match *received > max {
+                    true => Err(format!("request body is larger than {} bytes", max).into()),
+                    false => Ok(chunk),
+                }
+            });
+            futures::future::ready(Some(chunk))
+        });
+        self.stream = Some(StreamingBody(Mutex::new(Box::pin(limited))));
+    }
+
     /// Set the request body (for testing)
     #[cfg(test)]
     pub fn set_body(&mut self, body: Vec<u8>) {
@@ -454,6 +596,20 @@ impl Request {
         &mut self.headers
     }
 
+    /// Address of the connected client, when the request came in over the network
+    ///
+    /// Behind a proxy or load balancer this is the proxy's address; forwarded
+    /// headers like `X-Forwarded-For` are not trusted automatically.
+    pub fn remote_addr(&self) -> Option<std::net::SocketAddr> {
+        self.get_extension::<RemoteAddr>().map(|RemoteAddr(addr)| *addr)
+    }
+
+    /// Sets the client address, as the server does for every connection
+    pub fn with_remote_addr(mut self, addr: std::net::SocketAddr) -> Self {
+        self.insert_extension(RemoteAddr(addr));
+        self
+    }
+
     /// Parse query string into a HashMap
     fn parse_query_string(query: &str) -> HashMap<String, String> {
         let mut params = HashMap::new();
@@ -4,9 +4,41 @@
 //! fluent, chainable API. It supports setting status codes, headers, and body content
 //! with convenient methods for common response types.
 
+use std::convert::Infallible;
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
 use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
-use http_body_util::Full;
-use hyper::body::Bytes;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Bytes, Frame};
+
+mod feed;
+mod range;
+pub use feed::{FeedBuilder, FeedEntry, FeedFormat};
+pub use range::{ByteRange, MultiRange};
+
+/// Body of a response on the wire: buffered, or streamed as it is produced
+pub type ResponseBody = UnsyncBoxBody<Bytes, Infallible>;
+
+/// Chunks of a streamed response body
+pub type BodyStream = Pin<Box<dyn Stream<Item = Bytes> + Send>>;
+
+/// A streamed body. The mutex only makes `Response` `Sync`; the stream is
+/// never shared.
+struct StreamedBody(std::sync::Mutex<BodyStream>);
+
+impl StreamedBody {
+    fn into_inner(self) -> BodyStream {
+        self.0.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for StreamedBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StreamedBody")
+    }
+}
 
 /// HTTP response builder with a fluent API for creating responses.
 ///
@@ -80,16 +112,18 @@ use hyper::body::Bytes;
 /// use torch_web::Response;
 ///
 /// let file_data = std::fs::read("document.pdf")?;
-/// let response = Response::ok()
-///     .header("Content-Type", "application/pdf")
-///     .header("Content-Disposition", "attachment; filename=\"document.pdf\"")
-///     .body(file_data);
+/// let response = Response::pdf(file_data).attachment("document.pdf");
 /// ```
+///
+/// [`Response::file`] streams a file from disk instead and answers `Range`
+/// requests, for video playback and resumable downloads.
 #[derive(Debug)]
 pub struct Response {
     status: StatusCode,
     headers: HeaderMap,
     body: Vec<u8>,
+    stream: Option<StreamedBody>,
+    extensions: http::Extensions,
 }
 
 impl Response {
@@ -99,6 +133,8 @@ impl Response {
             status: StatusCode::OK,
             headers: HeaderMap::new(),
             body: Vec::new(),
+            stream: None,
+            extensions: http::Extensions::new(),
         }
     }
 
@@ -113,6 +149,8 @@ impl Response {
             status,
             headers: HeaderMap::new(),
             body: Vec::new(),
+            stream: None,
+            extensions: http::Extensions::new(),
         }
     }
 
@@ -186,6 +224,28 @@ impl Response {
         self
     }
 
+    /// Stream the body, sending each chunk as it is produced instead of
+    /// buffering the whole body. Replaces any body set before.
+    pub fn stream<S>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Bytes> + Send + 'static,
+    {
+        self.body.clear();
+        self.stream = Some(StreamedBody(std::sync::Mutex::new(Box::pin(stream))));
+        self
+    }
+
+    /// Whether the body is streamed, in which case [`body_data`](Self::body_data)
+    /// is empty
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Take the streamed body, e.g. to read it in a test
+    pub fn take_stream(&mut self) -> Option<BodyStream> {
+        self.stream.take().map(StreamedBody::into_inner)
+    }
+
     /// Set a header
     pub fn header<K, V>(mut self, key: K, value: V) -> Self
     where
@@ -205,6 +265,20 @@ impl Response {
         self.header("content-type", content_type)
     }
 
+    /// Set the Cache-Control header from a [`CacheControl`] policy
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use torch_web::{CacheControl, Response};
+    ///
+    /// let response = Response::ok()
+    ///     .cache_control(CacheControl::new().public().max_age(Duration::from_secs(3600)).s_maxage(Duration::from_secs(86400)))
+    ///     .body("cached by the CDN for a day");
+    /// ```
+    pub fn cache_control(self, policy: CacheControl) -> Self {
+        self.header("cache-control", policy.to_string())
+    }
+
     /// Set response as JSON and serialize the value (requires "json" feature)
     #[cfg(feature = "json")]
     pub fn json<T: serde::Serialize>(self, value: &T) -> Result<Self, serde_json::Error> {
@@ -238,6 +312,34 @@ impl Response {
         crate::ember::ember_view(template_name).await
     }
 
+    /// A PDF document, shown in the browser unless it is also made an
+    /// [`attachment`](Self::attachment)
+    pub fn pdf<T: Into<Vec<u8>>>(pdf: T) -> Self {
+        Self::ok().content_type("application/pdf").body(pdf)
+    }
+
+    /// Have browsers save the body as a file called `filename` instead of
+    /// showing it
+    pub fn attachment(self, filename: &str) -> Self {
+        self.disposition("attachment", filename)
+    }
+
+    /// Have browsers show the body, and use `filename` if it is saved
+    pub fn inline(self, filename: &str) -> Self {
+        self.disposition("inline", filename)
+    }
+
+    /// `Content-Disposition` with an ASCII `filename` for old clients and
+    /// the exact name as `filename*` (RFC 6266)
+    fn disposition(self, kind: &str, filename: &str) -> Self {
+        let fallback: String = filename
+            .chars()
+            .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' { c } else { '_' })
+            .collect();
+        let value = format!("{}; filename=\"{}\"; filename*=UTF-8''{}", kind, fallback, urlencoding::encode(filename));
+        self.header("Content-Disposition", &value)
+    }
+
     /// Redirect to another URL
     pub fn redirect(status: StatusCode, location: &str) -> Self {
         Self::with_status(status)
@@ -269,6 +371,21 @@ impl Response {
         &self.headers
     }
 
+    /// Get a mutable reference to the headers
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// Values handlers pass to the server alongside the response, such as
+    /// the identity a WebSocket upgrade was authorized for
+    pub fn extensions(&self) -> &http::Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut http::Extensions {
+        &mut self.extensions
+    }
+
     /// Get the body as bytes
     pub fn body_data(&self) -> &[u8] {
         &self.body
@@ -280,7 +397,7 @@ impl Response {
     }
 
     /// Convert to hyper Response
-    pub fn into_hyper_response(self) -> hyper::Response<Full<Bytes>> {
+    pub fn into_hyper_response(self) -> hyper::Response<ResponseBody> {
         let mut response = hyper::Response::builder()
             .status(self.status);
 
@@ -289,9 +406,152 @@ impl Response {
             response = response.header(key, value);
         }
 
-        response
-            .body(Full::new(Bytes::from(self.body)))
-            .expect("Failed to build response")
+        let body = match self.stream {
+            Some(stream) => StreamBody::new(stream.into_inner().map(|chunk| Ok(Frame::data(chunk)))).boxed_unsync(),
+            None => Full::new(Bytes::from(self.body)).boxed_unsync(),
+        };
+        response.body(body).expect("Failed to build response")
+    }
+}
+
+/// Guess a file's `Content-Type` from its extension
+pub(crate) fn content_type_for(file: &std::path::Path) -> &'static str {
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript",
+        Some("css") => "text/css",
+        Some("json") | Some("map") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("csv") => "text/csv",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A `Cache-Control` header value, built from its directives
+///
+/// ```rust
+/// use std::time::Duration;
+/// use torch_web::CacheControl;
+///
+/// let assets = CacheControl::new().public().max_age(Duration::from_secs(31_536_000)).immutable();
+/// assert_eq!(assets.to_string(), "public, max-age=31536000, immutable");
+///
+/// assert_eq!(CacheControl::new().no_store().to_string(), "no-store");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+    must_revalidate: bool,
+    immutable: bool,
+}
+
+impl CacheControl {
+    /// An empty policy; add directives with the builder methods
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let shared caches such as CDNs store the response
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self.private = false;
+        self
+    }
+
+    /// Only the browser may store the response, not shared caches
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self.public = false;
+        self
+    }
+
+    /// Caches must revalidate with the server before each reuse
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Nothing may store the response. Any other directive is dropped.
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// How long the response stays fresh, in whole seconds
+    pub fn max_age(mut self, age: std::time::Duration) -> Self {
+        self.max_age = Some(age.as_secs());
+        self
+    }
+
+    /// Freshness lifetime for shared caches, overriding `max_age` there
+    pub fn s_maxage(mut self, age: std::time::Duration) -> Self {
+        self.s_maxage = Some(age.as_secs());
+        self
+    }
+
+    /// Stale responses must not be served without revalidating
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// The response never changes while fresh, e.g. fingerprinted assets
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+}
+
+impl std::fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.no_store {
+            return f.write_str("no-store");
+        }
+
+        let mut directives = Vec::new();
+        if self.public {
+            directives.push("public".to_string());
+        }
+        if self.private {
+            directives.push("private".to_string());
+        }
+        if self.no_cache {
+            directives.push("no-cache".to_string());
+        }
+        if let Some(age) = self.max_age {
+            directives.push(format!("max-age={}", age));
+        }
+        if let Some(age) = self.s_maxage {
+            directives.push(format!("s-maxage={}", age));
+        }
+        if self.must_revalidate {
+            directives.push("must-revalidate".to_string());
+        }
+        if self.immutable {
+            directives.push("immutable".to_string());
+        }
+        f.write_str(&directives.join(", "))
     }
 }
 
@@ -348,6 +608,26 @@ mod tests {
         assert_eq!(response.headers().get("location").unwrap(), "/new-path");
     }
 
+    #[test]
+    fn test_cache_control() {
+        use std::time::Duration;
+
+        let response = Response::ok().cache_control(
+            CacheControl::new()
+                .public()
+                .max_age(Duration::from_secs(60))
+                .s_maxage(Duration::from_secs(600))
+                .must_revalidate(),
+        );
+        assert_eq!(
+            response.headers().get("cache-control").unwrap(),
+            "public, max-age=60, s-maxage=600, must-revalidate"
+        );
+
+        assert_eq!(CacheControl::new().public().private().no_cache().to_string(), "private, no-cache");
+        assert_eq!(CacheControl::new().public().max_age(Duration::from_secs(60)).no_store().to_string(), "no-store");
+    }
+
     #[cfg(feature = "json")]
     #[test]
     fn test_json_response() {
@@ -0,0 +1,380 @@
+//! RSS 2.0 and Atom feeds
+
+use std::time::SystemTime;
+
+use super::range::{civil_from_days, http_date};
+use super::Response;
+
+/// Which syndication format [`Response::feed`] writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FeedFormat {
+    #[default]
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+/// One post of a feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    title: String,
+    link: String,
+    id: Option<String>,
+    summary: Option<String>,
+    content: Option<String>,
+    author: Option<String>,
+    published: Option<SystemTime>,
+    updated: Option<SystemTime>,
+    categories: Vec<String>,
+}
+
+impl FeedEntry {
+    pub fn new(title: impl Into<String>, link: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            link: link.into(),
+            id: None,
+            summary: None,
+            content: None,
+            author: None,
+            published: None,
+            updated: None,
+            categories: Vec::new(),
+        }
+    }
+
+    /// A permanent id, when the link may change (defaults to the link)
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Plain text summary
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    /// The full post as HTML
+    pub fn content(mut self, html: impl Into<String>) -> Self {
+        self.content = Some(html.into());
+        self
+    }
+
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Accepts `SystemTime`, or a `chrono::DateTime<Utc>` through `.into()`
+    pub fn published(mut self, published: impl Into<SystemTime>) -> Self {
+        self.published = Some(published.into());
+        self
+    }
+
+    pub fn updated(mut self, updated: impl Into<SystemTime>) -> Self {
+        self.updated = Some(updated.into());
+        self
+    }
+
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.categories.push(category.into());
+        self
+    }
+}
+
+/// Builds an RSS 2.0 or Atom document, sent with [`Response::feed`].
+///
+/// ```rust
+/// use torch_web::response::{FeedBuilder, FeedEntry};
+/// use torch_web::{App, Response};
+///
+/// let app = App::new().get("/feed.xml", |_req: torch_web::Request| async {
+///     // e.g. from Post::query().order_by("published_at", "DESC").limit(20)
+///     let posts = vec![("Hello & welcome", "hello-world", "<p>First post</p>")];
+///     Response::feed(
+///         FeedBuilder::new("Torch blog", "https://blog.example.com")
+///             .description("Notes on building with Torch")
+///             .self_link("https://blog.example.com/feed.xml")
+///             .entries(posts.into_iter().map(|(title, slug, html)| {
+///                 FeedEntry::new(title, format!("https://blog.example.com/posts/{}", slug)).content(html)
+///             })),
+///     )
+/// });
+/// ```
+///
+/// Text is escaped for XML, so titles and HTML content can be passed as
+/// they are. Call [`atom`](Self::atom) for an Atom feed instead of RSS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedBuilder {
+    format: FeedFormat,
+    title: String,
+    link: String,
+    id: Option<String>,
+    description: String,
+    self_link: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    updated: Option<SystemTime>,
+    entries: Vec<FeedEntry>,
+}
+
+impl FeedBuilder {
+    /// A feed for the site at `link`
+    pub fn new(title: impl Into<String>, link: impl Into<String>) -> Self {
+        Self {
+            format: FeedFormat::Rss,
+            title: title.into(),
+            link: link.into(),
+            id: None,
+            description: String::new(),
+            self_link: None,
+            author: None,
+            language: None,
+            updated: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Write Atom instead of RSS 2.0
+    pub fn atom(mut self) -> Self {
+        self.format = FeedFormat::Atom;
+        self
+    }
+
+    pub fn format(mut self, format: FeedFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// A permanent id for the feed (defaults to the site link)
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// The feed's own URL, which feed validators expect
+    pub fn self_link(mut self, url: impl Into<String>) -> Self {
+        self.self_link = Some(url.into());
+        self
+    }
+
+    /// Author of entries that don't name their own
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Language tag, e.g. `en-us`
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    /// When the feed last changed (defaults to its newest entry)
+    pub fn updated(mut self, updated: impl Into<SystemTime>) -> Self {
+        self.updated = Some(updated.into());
+        self
+    }
+
+    pub fn entry(mut self, entry: FeedEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    pub fn entries<I: IntoIterator<Item = FeedEntry>>(mut self, entries: I) -> Self {
+        self.entries.extend(entries);
+        self
+    }
+
+    fn last_updated(&self) -> SystemTime {
+        self.updated
+            .or_else(|| self.entries.iter().filter_map(|entry| entry.updated.or(entry.published)).max())
+            .unwrap_or_else(SystemTime::now)
+    }
+
+    /// The feed as an XML document
+    pub fn to_xml(&self) -> String {
+        match self.format {
+            FeedFormat::Rss => self.rss(),
+            FeedFormat::Atom => self.atom_xml(),
+        }
+    }
+
+    fn rss(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n");
+        element(&mut xml, "title", &self.title);
+        element(&mut xml, "link", &self.link);
+        element(&mut xml, "description", &self.description);
+        if let Some(url) = &self.self_link {
+            xml.push_str(&format!("<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n", escape(url)));
+        }
+        if let Some(language) = &self.language {
+            element(&mut xml, "language", language);
+        }
+        element(&mut xml, "lastBuildDate", &http_date(self.last_updated()));
+
+        for entry in &self.entries {
+            xml.push_str("<item>\n");
+            element(&mut xml, "title", &entry.title);
+            element(&mut xml, "link", &entry.link);
+            match &entry.id {
+                Some(id) => xml.push_str(&format!("<guid isPermaLink=\"false\">{}</guid>\n", escape(id))),
+                None => xml.push_str(&format!("<guid isPermaLink=\"true\">{}</guid>\n", escape(&entry.link))),
+            }
+            if let Some(description) = entry.content.as_ref().or(entry.summary.as_ref()) {
+                element(&mut xml, "description", description);
+            }
+            // RSS 2.0 only allows an email in <author>, so plain names go in dc:creator
+            if let Some(author) = entry.author.as_ref().or(self.author.as_ref()) {
+                element(&mut xml, if author.contains('@') { "author" } else { "dc:creator" }, author);
+            }
+            for category in &entry.categories {
+                element(&mut xml, "category", category);
+            }
+            if let Some(published) = entry.published.or(entry.updated) {
+                element(&mut xml, "pubDate", &http_date(published));
+            }
+            xml.push_str("</item>\n");
+        }
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+
+    fn atom_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        match &self.language {
+            Some(language) => xml.push_str(&format!("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">\n", escape(language))),
+            None => xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n"),
+        }
+        element(&mut xml, "id", self.id.as_ref().unwrap_or(&self.link));
+        element(&mut xml, "title", &self.title);
+        if !self.description.is_empty() {
+            element(&mut xml, "subtitle", &self.description);
+        }
+        xml.push_str(&format!("<link href=\"{}\"/>\n", escape(&self.link)));
+        if let Some(url) = &self.self_link {
+            xml.push_str(&format!("<link href=\"{}\" rel=\"self\"/>\n", escape(url)));
+        }
+        element(&mut xml, "updated", &rfc3339(self.last_updated()));
+        if let Some(author) = &self.author {
+            xml.push_str(&format!("<author><name>{}</name></author>\n", escape(author)));
+        }
+
+        for entry in &self.entries {
+            xml.push_str("<entry>\n");
+            element(&mut xml, "id", entry.id.as_ref().unwrap_or(&entry.link));
+            element(&mut xml, "title", &entry.title);
+            xml.push_str(&format!("<link href=\"{}\"/>\n", escape(&entry.link)));
+            let updated = entry.updated.or(entry.published).unwrap_or_else(|| self.last_updated());
+            element(&mut xml, "updated", &rfc3339(updated));
+            if let Some(published) = entry.published {
+                element(&mut xml, "published", &rfc3339(published));
+            }
+            if let Some(author) = &entry.author {
+                xml.push_str(&format!("<author><name>{}</name></author>\n", escape(author)));
+            }
+            if let Some(summary) = &entry.summary {
+                element(&mut xml, "summary", summary);
+            }
+            if let Some(content) = &entry.content {
+                xml.push_str(&format!("<content type=\"html\">{}</content>\n", escape(content)));
+            }
+            for category in &entry.categories {
+                xml.push_str(&format!("<category term=\"{}\"/>\n", escape(category)));
+            }
+            xml.push_str("</entry>\n");
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+fn element(xml: &mut String, name: &str, text: &str) {
+    xml.push_str(&format!("<{0}>{1}</{0}>\n", name, escape(text)));
+}
+
+/// Escape text for XML content and attributes, dropping characters XML 1.0
+/// doesn't allow
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{fffe}' || c == '\u{ffff}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a time as RFC 3339 in UTC, e.g. `1994-11-06T08:49:37Z`
+fn rfc3339(time: SystemTime) -> String {
+    let timestamp = time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days((timestamp / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds / 3_600, seconds / 60 % 60, seconds % 60)
+}
+
+impl Response {
+    /// Send `feed` as RSS or Atom with the matching content type
+    pub fn feed(feed: FeedBuilder) -> Self {
+        Response::ok().content_type(feed.format.content_type()).body(feed.to_xml())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_feeds_are_escaped_in_both_formats() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let feed = FeedBuilder::new("Tips & <tricks>", "https://blog.example.com")
+            .self_link("https://blog.example.com/feed?page=1&size=20")
+            .author("Ada")
+            .entry(
+                FeedEntry::new("Rust \"fearless\" concurrency", "https://blog.example.com/posts/1")
+                    .content("<p>Arc &amp; Mutex</p>\u{1}")
+                    .published(at)
+                    .category("rust"),
+            );
+
+        let rss = Response::feed(feed.clone());
+        assert_eq!(rss.headers().get("content-type").unwrap(), "application/rss+xml; charset=utf-8");
+        let rss = String::from_utf8(rss.body_data().to_vec()).unwrap();
+        assert!(rss.contains("<title>Tips &amp; &lt;tricks&gt;</title>"));
+        assert!(rss.contains("href=\"https://blog.example.com/feed?page=1&amp;size=20\""));
+        assert!(rss.contains("<description>&lt;p&gt;Arc &amp;amp; Mutex&lt;/p&gt;</description>"));
+        assert!(rss.contains("<pubDate>Sun, 06 Nov 1994 08:49:37 GMT</pubDate>"));
+        assert!(rss.contains("<guid isPermaLink=\"true\">https://blog.example.com/posts/1</guid>"));
+        assert!(rss.contains("<dc:creator>Ada</dc:creator>"));
+
+        let atom = Response::feed(feed.atom());
+        assert_eq!(atom.headers().get("content-type").unwrap(), "application/atom+xml; charset=utf-8");
+        let atom = String::from_utf8(atom.body_data().to_vec()).unwrap();
+        assert!(atom.contains("<title>Rust &quot;fearless&quot; concurrency</title>"));
+        assert!(atom.contains("<updated>1994-11-06T08:49:37Z</updated>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;Arc &amp;amp; Mutex&lt;/p&gt;</content>"));
+        assert!(atom.contains("<author><name>Ada</name></author>"));
+    }
+}
@@ -0,0 +1,331 @@
+//! Range requests
+//!
+//! [`Response::ranged`] and [`Response::file`] answer `Range: bytes=...`
+//! headers with `206 Partial Content`, so browsers can seek in a video and
+//! download managers can resume where they stopped.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use futures::stream;
+use http::{HeaderMap, StatusCode};
+use hyper::body::Bytes;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::Response;
+use crate::extractors::state::RequestStateExt;
+use crate::{Method, Request};
+
+/// Size of the chunks a file is streamed in
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// An inclusive range of bytes, as in `Content-Range: bytes 0-499/1234`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Parse a `Range` header for a body of `size` bytes.
+    ///
+    /// `None` means the header isn't a valid `bytes` range and should be
+    /// ignored. Ranges starting past the end are left out, so an empty list
+    /// means nothing could be served.
+    pub fn parse(header: &str, size: u64) -> Option<Vec<ByteRange>> {
+        let (unit, specs) = header.split_once('=')?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+
+        let mut ranges = Vec::new();
+        for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+            let (first, last) = spec.split_once('-')?;
+            let range = match (first.trim(), last.trim()) {
+                ("", suffix) => {
+                    let suffix: u64 = suffix.parse().ok()?;
+                    (suffix > 0 && size > 0).then(|| ByteRange {
+                        start: size.saturating_sub(suffix),
+                        end: size - 1,
+                    })
+                }
+                (first, "") => {
+                    let start: u64 = first.parse().ok()?;
+                    (start < size).then(|| ByteRange { start, end: size - 1 })
+                }
+                (first, last) => {
+                    let (start, end): (u64, u64) = (first.parse().ok()?, last.parse().ok()?);
+                    if end < start {
+                        return None;
+                    }
+                    (start < size).then(|| ByteRange { start, end: end.min(size - 1) })
+                }
+            };
+            ranges.extend(range);
+        }
+        Some(ranges)
+    }
+
+    /// Number of bytes in the range
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// A parsed range always holds at least one byte
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// How to answer a request for several ranges at once, registered as app
+/// state. Multipart range responses aren't supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultiRange {
+    /// Send the whole body with `200 OK`
+    #[default]
+    ServeFull,
+    /// Refuse with `416 Range Not Satisfiable`
+    Reject,
+}
+
+/// What part of the body a request gets
+enum Selection {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+fn select(req: &Request, headers: &HeaderMap, size: u64) -> Selection {
+    let Some(range) = req.header("range").filter(|_| *req.method() == Method::GET) else {
+        return Selection::Full;
+    };
+    // A resumed download whose file changed since gets the whole new file
+    if let Some(validator) = req.header("if-range") {
+        let current = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let matches = if validator.starts_with('"') {
+            current("etag") == Some(validator)
+        } else {
+            current("last-modified") == Some(validator)
+        };
+        if !matches {
+            return Selection::Full;
+        }
+    }
+
+    match ByteRange::parse(range, size) {
+        None => Selection::Full,
+        Some(ranges) => match ranges.as_slice() {
+            [] => Selection::Unsatisfiable,
+            [range] => Selection::Partial(*range),
+            _ => {
+                let policy = req
+                    .get_state(std::any::TypeId::of::<MultiRange>())
+                    .and_then(|state| state.downcast_ref::<MultiRange>())
+                    .copied()
+                    .unwrap_or_default();
+                match policy {
+                    MultiRange::ServeFull => Selection::Full,
+                    MultiRange::Reject => Selection::Unsatisfiable,
+                }
+            }
+        },
+    }
+}
+
+fn unsatisfiable(size: u64) -> Response {
+    Response::with_status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header("Accept-Ranges", "bytes")
+        .header("Content-Range", format!("bytes */{}", size))
+}
+
+impl Response {
+    /// Send `body`, or the part of it the request's `Range` header asks for.
+    ///
+    /// Headers set before, such as `ETag` or `Last-Modified`, are checked
+    /// against `If-Range`. Responses other than `200 OK` are left as they are.
+    ///
+    /// ```rust
+    /// use torch_web::{Request, Response};
+    ///
+    /// async fn report(req: Request) -> Response {
+    ///     let csv = b"id,name\n1,Ada\n".to_vec();
+    ///     Response::ok().content_type("text/csv").ranged(&req, csv)
+    /// }
+    /// ```
+    pub fn ranged<T: Into<Vec<u8>>>(self, req: &Request, body: T) -> Self {
+        let body = body.into();
+        if self.status != StatusCode::OK {
+            return self.body(body);
+        }
+        let size = body.len() as u64;
+        match select(req, &self.headers, size) {
+            Selection::Full => self.header("Accept-Ranges", "bytes").body(body),
+            Selection::Partial(range) => self
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header("Accept-Ranges", "bytes")
+                .header("Content-Range", format!("bytes {}-{}/{}", range.start, range.end, size))
+                .body(body[range.start as usize..=range.end as usize].to_vec()),
+            Selection::Unsatisfiable => {
+                let mut response = unsatisfiable(size);
+                response.headers.extend(self.headers);
+                response
+            }
+        }
+    }
+
+    /// Stream the file at `path`, answering range requests, with its
+    /// `Content-Type` guessed from the extension and a `Last-Modified`
+    /// header. Missing files get a 404.
+    ///
+    /// ```rust,no_run
+    /// use torch_web::{App, Request, Response};
+    ///
+    /// let app = App::new().get("/videos/intro", |req: Request| async move {
+    ///     Response::file(&req, "storage/videos/intro.mp4").await
+    /// });
+    /// ```
+    pub async fn file<P: AsRef<Path>>(req: &Request, path: P) -> Self {
+        let path = path.as_ref();
+        let Ok(mut file) = tokio::fs::File::open(path).await else {
+            return Response::not_found().body("Not Found");
+        };
+        let metadata = match file.metadata().await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return Response::not_found().body("Not Found"),
+        };
+        let size = metadata.len();
+
+        let mut response = Response::ok()
+            .header("Content-Type", super::content_type_for(path))
+            .header("Accept-Ranges", "bytes");
+        if let Ok(modified) = metadata.modified() {
+            response = response.header("Last-Modified", http_date(modified));
+        }
+
+        let range = match select(req, &response.headers, size) {
+            Selection::Full => ByteRange { start: 0, end: size.saturating_sub(1) },
+            Selection::Partial(range) => {
+                response = response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header("Content-Range", format!("bytes {}-{}/{}", range.start, range.end, size));
+                range
+            }
+            Selection::Unsatisfiable => return unsatisfiable(size),
+        };
+        let length = if size == 0 { 0 } else { range.len() };
+        response = response.header("Content-Length", length.to_string());
+
+        if *req.method() == Method::HEAD || length == 0 {
+            return response;
+        }
+        if file.seek(std::io::SeekFrom::Start(range.start)).await.is_err() {
+            return Response::internal_error();
+        }
+
+        // Read one chunk at a time so a large video never sits in memory
+        let chunks = stream::unfold((file, length), |(mut file, remaining)| async move {
+            if remaining == 0 {
+                return None;
+            }
+            let mut chunk = vec![0; remaining.min(CHUNK_SIZE) as usize];
+            match file.read(&mut chunk).await {
+                Ok(0) | Err(_) => None,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Some((Bytes::from(chunk), (file, remaining - read as u64)))
+                }
+            }
+        });
+        response.stream(chunks)
+    }
+}
+
+/// Format a time as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub(super) fn http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let timestamp = time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Days since the Unix epoch to `(year, month, day)`, from Howard Hinnant's
+/// `civil_from_days`
+pub(super) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_range_requests() {
+        assert_eq!(ByteRange::parse("bytes=0-499", 1000), Some(vec![ByteRange { start: 0, end: 499 }]));
+        assert_eq!(ByteRange::parse("bytes=-200", 1000), Some(vec![ByteRange { start: 800, end: 999 }]));
+        assert_eq!(ByteRange::parse("bytes=900-5000", 1000), Some(vec![ByteRange { start: 900, end: 999 }]));
+        assert_eq!(ByteRange::parse("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(ByteRange::parse("items=0-1", 1000), None);
+        assert_eq!(ByteRange::parse("bytes=5-1", 1000), None);
+        assert_eq!(http_date(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(784_111_777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        let get = |range: &str| Request::mock(Method::GET, "/").with_header("range", range);
+        let partial = Response::ok().ranged(&get("bytes=2-4"), "0123456789");
+        assert_eq!(partial.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.headers().get("content-range").unwrap(), "bytes 2-4/10");
+        assert_eq!(partial.body_data(), b"234");
+        let beyond = Response::ok().ranged(&get("bytes=20-"), "0123456789");
+        assert_eq!(beyond.status_code(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(beyond.headers().get("content-range").unwrap(), "bytes */10");
+
+        // Several ranges: the whole body, unless the app rejects them
+        let multi = get("bytes=0-1,4-5");
+        assert_eq!(Response::ok().ranged(&multi, "0123456789").status_code(), StatusCode::OK);
+        let app = crate::App::new()
+            .with_state(MultiRange::Reject)
+            .get("/", |req: Request| async move { Response::ok().ranged(&req, "0123456789") });
+        assert_eq!(app.handle_request(get("bytes=0-1,4-5")).await.status_code(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        // A stale If-Range gets the full body
+        let stale = get("bytes=0-1").with_header("if-range", "\"v1\"");
+        let full = Response::ok().header("ETag", "\"v2\"").ranged(&stale, "0123456789");
+        assert_eq!((full.status_code(), full.body_data()), (StatusCode::OK, &b"0123456789"[..]));
+
+        let dir = std::env::temp_dir().join(format!("torch-range-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.mp4");
+        let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let mut response = Response::file(&get("bytes=100000-"), &path).await;
+        assert_eq!(response.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers().get("content-type").unwrap(), "video/mp4");
+        assert_eq!(response.headers().get("content-length").unwrap(), "100000");
+        let streamed: Vec<u8> = response.take_stream().unwrap().collect::<Vec<_>>().await.concat();
+        assert_eq!(streamed, &contents[100_000..]);
+        assert_eq!(Response::file(&get("bytes=0-0"), dir.join("missing.mp4")).await.status_code(), StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
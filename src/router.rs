@@ -5,6 +5,8 @@
 //! HTTP method and URL path patterns.
 
 use std::collections::HashMap;
+use std::panic::Location;
+use std::sync::Arc;
 use http::Method;
 use crate::{Request, Response, HandlerFn};
 
@@ -59,6 +61,39 @@ use crate::{Request, Response, HandlerFn};
 pub struct Router {
     routes: HashMap<Method, Vec<Route>>,
     not_found_handler: Option<HandlerFn>,
+    registered: usize,
+}
+
+/// Description of a registered route, as listed by `torch route list`.
+///
+/// `handler` is the handler's type name when it is known (routes added
+/// through [`App`](crate::App)) and empty for pre-boxed handlers.
+/// `location` is the `file:line` the route was registered at.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteInfo {
+    pub method: String,
+    pub path: String,
+    pub name: Option<String>,
+    pub handler: String,
+    pub middleware: Vec<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub location: Option<String>,
+}
+
+/// The pattern of the route that produced a response, e.g. `/users/:id`.
+///
+/// The router puts it in the response's extensions, so middleware such as
+/// [`MetricsCollector`](crate::production::MetricsCollector) can label
+/// requests by route instead of by raw path. Responses for paths no route
+/// matched don't have one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedRoute(pub Arc<str>);
+
+impl MatchedRoute {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 /// Represents a single route with its pattern and handler.
@@ -68,7 +103,15 @@ pub struct Router {
 #[derive(Clone)]
 struct Route {
     pattern: RoutePattern,
+    /// The pattern as registered, shared with [`MatchedRoute`]
+    template: Arc<str>,
     handler: HandlerFn,
+    name: Option<String>,
+    handler_name: String,
+    /// Where the route was registered, for listings
+    location: &'static Location<'static>,
+    /// Registration order, so listings don't depend on map ordering
+    order: usize,
 }
 
 /// Pattern matching engine for route paths.
@@ -76,7 +119,7 @@ struct Route {
 /// Parses route patterns into segments that can be efficiently matched against
 /// incoming request paths. Supports static segments, named parameters, and wildcards.
 #[derive(Debug, Clone)]
-struct RoutePattern {
+pub(crate) struct RoutePattern {
     segments: Vec<Segment>,
 }
 
@@ -114,6 +157,7 @@ impl Router {
         Self {
             routes: HashMap::new(),
             not_found_handler: None,
+            registered: 0,
         }
     }
 
@@ -154,9 +198,32 @@ impl Router {
     ///     Response::ok().body(format!("User: {}", id))
     /// });
     /// ```
+    #[track_caller]
     pub fn route(&mut self, method: Method, path: &str, handler: HandlerFn) {
+        self.add_route(method, path, handler, String::new(), None, Location::caller());
+    }
+
+    /// Register a route along with the metadata shown by `torch route list`
+    pub(crate) fn add_route(
+        &mut self,
+        method: Method,
+        path: &str,
+        handler: HandlerFn,
+        handler_name: String,
+        name: Option<String>,
+        location: &'static Location<'static>,
+    ) {
         let pattern = RoutePattern::parse(path);
-        let route = Route { pattern, handler };
+        let route = Route {
+            template: Arc::from(pattern.to_string()),
+            pattern,
+            handler,
+            name,
+            handler_name,
+            location,
+            order: self.registered,
+        };
+        self.registered += 1;
 
         self.routes
             .entry(method)
@@ -190,6 +257,7 @@ impl Router {
     ///     Response::ok().body(format!("User: {}", id))
     /// });
     /// ```
+    #[track_caller]
     pub fn get(&mut self, path: &str, handler: HandlerFn) {
         self.route(Method::GET, path, handler);
     }
@@ -219,6 +287,7 @@ impl Router {
     ///     Response::ok().body("Login successful")
     /// });
     /// ```
+    #[track_caller]
     pub fn post(&mut self, path: &str, handler: HandlerFn) {
         self.route(Method::POST, path, handler);
     }
@@ -245,6 +314,7 @@ impl Router {
     ///     Response::ok().body(format!("Updated user: {}", id))
     /// });
     /// ```
+    #[track_caller]
     pub fn put(&mut self, path: &str, handler: HandlerFn) {
         self.route(Method::PUT, path, handler);
     }
@@ -271,6 +341,7 @@ impl Router {
     ///     Response::ok().body(format!("Deleted user: {}", id))
     /// });
     /// ```
+    #[track_caller]
     pub fn delete(&mut self, path: &str, handler: HandlerFn) {
         self.route(Method::DELETE, path, handler);
     }
@@ -297,6 +368,7 @@ impl Router {
     ///     Response::ok().body(format!("Patched user: {}", id))
     /// });
     /// ```
+    #[track_caller]
     pub fn patch(&mut self, path: &str, handler: HandlerFn) {
         self.route(Method::PATCH, path, handler);
     }
@@ -326,19 +398,131 @@ impl Router {
         self.not_found_handler = Some(handler);
     }
 
-    /// Get all routes for mounting (internal use)
-    pub(crate) fn get_all_routes(&self) -> Vec<(Method, String, HandlerFn)> {
-        let mut all_routes = Vec::new();
+    /// Names the most recently registered route.
+    ///
+    /// Names only show up in `torch route list`; they don't affect matching.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use torch_web::{Router, Request, Response, handler::into_handler_fn};
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/users", into_handler_fn(|_req: Request| async { Response::ok() }));
+    /// router.name("users.index");
+    ///
+    /// assert_eq!(router.routes()[0].name.as_deref(), Some("users.index"));
+    /// ```
+    pub fn name(&mut self, name: &str) {
+        let last = self.registered.checked_sub(1);
+        if let Some(route) = self
+            .routes
+            .values_mut()
+            .flatten()
+            .find(|route| Some(route.order) == last)
+        {
+            route.name = Some(name.to_string());
+        }
+    }
 
-        for (method, routes) in &self.routes {
-            for route in routes {
-                // Convert the pattern back to a string representation
-                let path = route.pattern.to_string();
-                all_routes.push((method.clone(), path, route.handler.clone()));
-            }
+    /// How many routes have been registered so far
+    #[cfg(feature = "websocket")]
+    pub(crate) fn registered(&self) -> usize {
+        self.registered
+    }
+
+    /// Lists the registered routes in the order they were added
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        self.ordered()
+            .into_iter()
+            .map(|(method, route)| RouteInfo {
+                method: method.to_string(),
+                path: route.pattern.to_string(),
+                name: route.name.clone(),
+                handler: route.handler_name.clone(),
+                middleware: Vec::new(),
+                location: Some(format!("{}:{}", route.location.file(), route.location.line())),
+            })
+            .collect()
+    }
+
+    /// Duplicate and unreachable routes and reused names, see [`check_routes`]
+    pub fn conflicts(&self) -> Vec<RouteConflict> {
+        check_routes(&self.routes())
+    }
+
+    /// URL for the route called `name`, filling its `:params` from `params`
+    ///
+    /// Parameters the pattern doesn't use are added as a query string, and a
+    /// wildcard is filled from the `*` parameter.
+    ///
+    /// ```rust
+    /// use torch_web::{Router, Request, Response, handler::into_handler_fn};
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/users/:id", into_handler_fn(|_req: Request| async { Response::ok() }));
+    /// router.name("users.show");
+    ///
+    /// assert_eq!(router.url("users.show", &[("id", "42"), ("tab", "posts")]).as_deref(), Some("/users/42?tab=posts"));
+    /// assert_eq!(router.url("users.show", &[]), None);
+    /// ```
+    pub fn url(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.urls().url(name, params)
+    }
+
+    /// Named route patterns, for building URLs once the router is shared
+    pub fn urls(&self) -> Urls {
+        let routes = self
+            .ordered()
+            .into_iter()
+            .filter_map(|(_, route)| Some((route.name.clone()?, route.pattern.clone())))
+            .collect();
+        Urls { routes: std::sync::Arc::new(routes) }
+    }
+
+    /// Copy every route of `other` into this router under `prefix`
+    pub(crate) fn merge(&mut self, prefix: &str, other: &Router) {
+        let prefix = prefix.trim_end_matches('/');
+
+        for (method, route) in other.ordered() {
+            let path = format!("{}{}", prefix, route.pattern.to_string());
+            self.add_route(
+                method.clone(),
+                &path,
+                route.handler.clone(),
+                route.handler_name.clone(),
+                route.name.clone(),
+                route.location,
+            );
         }
+    }
+
+    fn ordered(&self) -> Vec<(&Method, &Route)> {
+        let mut all: Vec<(&Method, &Route)> = self
+            .routes
+            .iter()
+            .flat_map(|(method, routes)| routes.iter().map(move |route| (method, route)))
+            .collect();
+        all.sort_by_key(|(_, route)| route.order);
+        all
+    }
 
-        all_routes
+    /// Methods with a route matching `path`, in the order `Allow` headers
+    /// list them, with `OPTIONS` always last
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut methods: Vec<Method> = self
+            .routes
+            .iter()
+            .filter(|(method, routes)| **method != Method::OPTIONS && routes.iter().any(|route| route.pattern.matches(path).is_some()))
+            .map(|(method, _)| method.clone())
+            .collect();
+        if methods.is_empty() {
+            return methods;
+        }
+        let rank = |method: &Method| METHOD_ORDER.iter().position(|known| known == method).unwrap_or(METHOD_ORDER.len());
+        methods.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.as_str().cmp(b.as_str())));
+        methods.push(Method::OPTIONS);
+        methods
     }
 
     /// Route a request to the appropriate handler
@@ -350,11 +534,25 @@ impl Router {
                     for (name, value) in params {
                         req.set_param(name, value);
                     }
-                    return (route.handler)(req).await;
+                    let mut response = (route.handler)(req).await;
+                    response.extensions_mut().insert(MatchedRoute(route.template.clone()));
+                    return response;
                 }
             }
         }
 
+        // OPTIONS for a path with routes but no OPTIONS handler of its own
+        if *req.method() == Method::OPTIONS {
+            let methods = self.allowed_methods(req.path());
+            if !methods.is_empty() {
+                #[cfg(feature = "security")]
+                if let Some(response) = crate::security::cors::answer_preflight(&req, &methods) {
+                    return response;
+                }
+                return Response::no_content().header("Allow", allow_header(&methods));
+            }
+        }
+
         // No route found, use 404 handler or default
         if let Some(handler) = &self.not_found_handler {
             handler(req).await
@@ -364,6 +562,90 @@ impl Router {
     }
 }
 
+/// Order of methods in `Allow` headers
+const METHOD_ORDER: [Method; 6] = [Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE];
+
+/// The `Allow` header for `methods`
+pub(crate) fn allow_header(methods: &[Method]) -> String {
+    methods.iter().map(Method::as_str).collect::<Vec<_>>().join(", ")
+}
+
+/// A route definition that can't work as written, found by [`check_routes`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteConflict {
+    /// Both routes have the same method and pattern, so `second` never runs
+    Duplicate { first: RouteInfo, second: RouteInfo },
+    /// `by` comes first and matches every request `route` would, such as
+    /// `GET /files/*` before `GET /files/:id`
+    Shadowed { route: RouteInfo, by: RouteInfo },
+    /// Two routes share a name, so links only ever point at `second`
+    DuplicateName { name: String, first: RouteInfo, second: RouteInfo },
+}
+
+impl std::fmt::Display for RouteConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |route: &RouteInfo| match &route.location {
+            Some(location) => format!("{} {} ({})", route.method, route.path, location),
+            None => format!("{} {}", route.method, route.path),
+        };
+        match self {
+            RouteConflict::Duplicate { first, second } => {
+                write!(f, "{} is already registered as {}", describe(second), describe(first))
+            }
+            RouteConflict::Shadowed { route, by } => {
+                write!(f, "{} can never match: {} takes all of its requests first", describe(route), describe(by))
+            }
+            RouteConflict::DuplicateName { name, first, second } => {
+                write!(f, "route name `{}` is used by both {} and {}", name, describe(first), describe(second))
+            }
+        }
+    }
+}
+
+/// Find duplicate and unreachable routes and reused names in `routes`,
+/// listed in the order they were registered.
+///
+/// The router picks the first registered route that matches, so anything
+/// reported here is silently ignored at request time. [`App::listen`]
+/// refuses to start with any of these, and `torch route check` lists them.
+///
+/// ```rust
+/// use torch_web::{App, Request, Response, router::{check_routes, RouteConflict}};
+///
+/// let app = App::new()
+///     .get("/users/:id", |_req: Request| async { Response::ok() })
+///     .get("/users/new", |_req: Request| async { Response::ok() });
+///
+/// let conflicts = check_routes(&app.routes());
+/// assert!(matches!(&conflicts[..], [RouteConflict::Shadowed { .. }]));
+/// ```
+///
+/// [`App::listen`]: crate::App::listen
+pub fn check_routes(routes: &[RouteInfo]) -> Vec<RouteConflict> {
+    let patterns: Vec<RoutePattern> = routes.iter().map(|route| RoutePattern::parse(&route.path)).collect();
+    let mut conflicts = Vec::new();
+
+    for (index, route) in routes.iter().enumerate() {
+        let earlier = routes[..index].iter().zip(&patterns).filter(|(other, _)| other.method == route.method);
+        for (other, pattern) in earlier {
+            if !pattern.covers(&patterns[index]) {
+                continue;
+            }
+            conflicts.push(match patterns[index].covers(pattern) {
+                true => RouteConflict::Duplicate { first: other.clone(), second: route.clone() },
+                false => RouteConflict::Shadowed { route: route.clone(), by: other.clone() },
+            });
+            break;
+        }
+
+        let Some(name) = &route.name else { continue };
+        if let Some(other) = routes[..index].iter().rev().find(|other| other.name.as_ref() == Some(name)) {
+            conflicts.push(RouteConflict::DuplicateName { name: name.clone(), first: other.clone(), second: route.clone() });
+        }
+    }
+    conflicts
+}
+
 impl Default for Router {
     fn default() -> Self {
         Self::new()
@@ -375,11 +657,103 @@ impl Clone for Router {
         Self {
             routes: self.routes.clone(),
             not_found_handler: self.not_found_handler.clone(),
+            registered: self.registered,
+        }
+    }
+}
+
+/// URLs for named routes.
+///
+/// Handlers can take `Urls` as an extractor to link to other routes by name
+/// instead of hard-coding paths.
+///
+/// ```rust
+/// use torch_web::{App, Request, Response, router::Urls};
+///
+/// let app = App::new()
+///     .get("/posts/:id", |_req: Request| async { Response::ok() })
+///     .name("posts.show")
+///     .get("/", |urls: Urls| async move {
+///         let link = urls.url("posts.show", &[("id", "7")]).unwrap();
+///         Response::ok().body(format!("<a href=\"{}\">Latest post</a>", link))
+///     });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Urls {
+    routes: std::sync::Arc<HashMap<String, RoutePattern>>,
+}
+
+impl Urls {
+    /// URL for the route called `name`; see [`Router::url`]
+    pub fn url(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let (path, unused) = self.routes.get(name)?.fill(params)?;
+        if unused.is_empty() {
+            return Some(path);
         }
+        let query: Vec<String> = unused
+            .iter()
+            .map(|(key, value)| format!("{}={}", urlencoding::encode(key), urlencoding::encode(value)))
+            .collect();
+        Some(format!("{}?{}", path, query.join("&")))
+    }
+
+    /// Signed URL for the route called `name`, valid for `expires_in`
+    ///
+    /// Signed with `APP_KEY`; check it with
+    /// [`ValidateSignature`](crate::security::signed_urls::ValidateSignature).
+    #[cfg(feature = "security")]
+    pub fn url_signed(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        expires_in: std::time::Duration,
+    ) -> crate::security::SecurityResult<String> {
+        let url = self.url(name, params).ok_or_else(|| {
+            crate::security::SecurityError::InvalidInput(format!("no route named `{}` takes these parameters", name))
+        })?;
+        Ok(crate::security::signed_urls::UrlSigner::from_env()?.sign(&url, Some(expires_in)))
+    }
+}
+
+impl crate::extractors::FromRequestParts for Urls {
+    type Error = Response;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let urls = req.get_extension::<Urls>().cloned().unwrap_or_default();
+        Box::pin(async move { Ok(urls) })
     }
 }
 
 impl RoutePattern {
+    /// Fill the pattern's parameters, returning the path and the unused parameters
+    fn fill<'a>(&self, params: &[(&'a str, &'a str)]) -> Option<(String, Vec<(&'a str, &'a str)>)> {
+        let mut unused = params.to_vec();
+        let mut take = |name: &str| {
+            let index = unused.iter().position(|(key, _)| *key == name)?;
+            Some(unused.remove(index).1)
+        };
+
+        let mut path = String::new();
+        for segment in &self.segments {
+            path.push('/');
+            match segment {
+                Segment::Static(s) => path.push_str(s),
+                Segment::Param(name) => path.push_str(&urlencoding::encode(take(name)?)),
+                Segment::Wildcard => {
+                    let rest = take("*")?;
+                    let encoded: Vec<_> = rest.trim_start_matches('/').split('/').map(urlencoding::encode).collect();
+                    path.push_str(&encoded.join("/"));
+                }
+            }
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        Some((path, unused))
+    }
+
     /// Convert pattern back to string representation
     fn to_string(&self) -> String {
         let mut result = String::from("/");
@@ -408,7 +782,7 @@ impl RoutePattern {
     }
 
     /// Parse a route pattern string into segments
-    fn parse(pattern: &str) -> Self {
+    pub(crate) fn parse(pattern: &str) -> Self {
         let mut segments = Vec::new();
 
         for segment in pattern.split('/').filter(|s| !s.is_empty()) {
@@ -425,8 +799,23 @@ impl RoutePattern {
         Self { segments }
     }
 
+    /// Whether every path `other` matches is also matched by this pattern
+    fn covers(&self, other: &RoutePattern) -> bool {
+        for (index, segment) in self.segments.iter().enumerate() {
+            match (segment, other.segments.get(index)) {
+                // A wildcard takes everything that's left, even nothing
+                (Segment::Wildcard, _) => return true,
+                (_, None | Some(Segment::Wildcard)) => return false,
+                (Segment::Param(_), Some(_)) => {}
+                (Segment::Static(expected), Some(Segment::Static(found))) if expected == found => {}
+                (Segment::Static(_), Some(_)) => return false,
+            }
+        }
+        other.segments.len() == self.segments.len()
+    }
+
     /// Check if this pattern matches the given path and extract parameters
-    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+    pub(crate) fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
         let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
         
         // Handle root path
@@ -496,6 +885,51 @@ mod tests {
         assert!(pattern.matches("/users/123/extra").is_none());
     }
 
+    #[test]
+    fn test_named_route_urls() {
+        let mut router = Router::new();
+        router.get("/users/:id/posts/:post", crate::handler::into_handler_fn(|_req: Request| async { Response::ok() }));
+        router.name("posts.show");
+        router.get("/files/*", crate::handler::into_handler_fn(|_req: Request| async { Response::ok() }));
+        router.name("files");
+        router.get("/", crate::handler::into_handler_fn(|_req: Request| async { Response::ok() }));
+        router.name("home");
+
+        assert_eq!(router.url("posts.show", &[("post", "9"), ("id", "ada lovelace")]).as_deref(), Some("/users/ada%20lovelace/posts/9"));
+        assert_eq!(router.url("files", &[("*", "docs/a b.pdf"), ("dl", "1")]).as_deref(), Some("/files/docs/a%20b.pdf?dl=1"));
+        assert_eq!(router.url("home", &[]).as_deref(), Some("/"));
+        assert_eq!(router.url("posts.show", &[("id", "1")]), None);
+        assert_eq!(router.url("missing", &[]), None);
+    }
+
+    #[test]
+    fn test_check_routes_reports_dead_routes_and_reused_names() {
+        let ok = || crate::handler::into_handler_fn(|_req: Request| async { Response::ok() });
+        let mut router = Router::new();
+        router.get("/files/*", ok());
+        router.get("/files/:id", ok());
+        router.get("/users/:id", ok());
+        router.name("users.show");
+        router.get("/users/new", ok());
+        router.post("/users/new", ok());
+        router.get("/users/:user", ok());
+        router.name("users.show");
+        router.get("/users/:id/posts", ok());
+
+        let conflicts = router.conflicts();
+        let paths: Vec<(&str, &str)> = conflicts
+            .iter()
+            .map(|conflict| match conflict {
+                RouteConflict::Shadowed { route, by } => (route.path.as_str(), by.path.as_str()),
+                RouteConflict::Duplicate { first, second } => (second.path.as_str(), first.path.as_str()),
+                RouteConflict::DuplicateName { name, .. } => (name.as_str(), ""),
+            })
+            .collect();
+        assert_eq!(paths, [("/files/:id", "/files/*"), ("/users/new", "/users/:id"), ("/users/:user", "/users/:id"), ("users.show", "")]);
+        assert!(matches!(conflicts[2], RouteConflict::Duplicate { .. }));
+        assert!(conflicts[1].to_string().starts_with("GET /users/new (src/router.rs:"), "{}", conflicts[1]);
+    }
+
     #[test]
     fn test_wildcard_matching() {
         let pattern = RoutePattern::parse("/files/*");
@@ -517,7 +951,7 @@ mod tests {
         })));
 
         // Test root route
-        let req = Request::from_hyper(
+        let req = Request::from_parts(
             http::Request::builder()
                 .method("GET")
                 .uri("/")
@@ -526,9 +960,7 @@ mod tests {
                 .into_parts()
                 .0,
             Vec::new(),
-        )
-        .await
-        .unwrap();
+        );
 
         let response = router.route_request(req).await;
         assert_eq!(response.body_bytes(), b"Home");
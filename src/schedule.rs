@@ -0,0 +1,585 @@
+//! # Task Scheduling
+//!
+//! Run recurring work from the app itself instead of the system crontab.
+//! Tasks are Rust closures, shell commands, the app's own console commands or
+//! `torch` subcommands, each with a cron expression or one of the frequency
+//! helpers. Every run records its output and duration, and failing or slow
+//! runs can alert someone by email or webhook:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use torch_web::schedule::Schedule;
+//!
+//! # async fn prune_sessions() -> Result<(), String> { Ok(()) }
+//! # async fn example() {
+//! let mut schedule = Schedule::new();
+//!
+//! schedule
+//!     .call("prune-sessions", || async { prune_sessions().await })
+//!     .cron("*/30 * * * *");
+//!
+//! // Runs the app's own executable as `<app> report:daily --email`
+//! schedule.command("report:daily --email").daily_at("07:30");
+//! schedule.torch("queue prune --hours 48").hourly();
+//!
+//! schedule
+//!     .exec("backup", "pg_dump app > /backups/app.sql")
+//!     .cron("0 2 * * *")
+//!     .expected_runtime(Duration::from_secs(10 * 60))
+//!     .email_on_failure("ops@example.com")
+//!     .ping_on_failure("http://alerts.internal/torch");
+//!
+//! // Checks for due tasks at the start of every minute
+//! schedule.run().await;
+//! # }
+//! ```
+//!
+//! The last [`HISTORY_LIMIT`] runs are kept in memory, see
+//! [`Schedule::history`]. With the `database` feature,
+//! [`persist_history`](Schedule::persist_history) also writes every run to
+//! the `schedule_runs` table, which `torch schedule list --history` reads.
+//!
+//! Every process running the schedule runs every due task, unless the task
+//! is marked [`without_overlapping`](ScheduledTask::without_overlapping):
+//! those take a lock in the `schedule_locks` table first and are skipped
+//! while another run holds it.
+
+mod cron;
+#[cfg(feature = "database")]
+mod database;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::audit::WebhookSink;
+use crate::clock::{unix_now, unix_now_ms};
+use crate::mail::{Mail, Mailable, MailMessage};
+
+pub use cron::CronExpression;
+#[cfg(feature = "database")]
+pub use database::{SCHEDULE_LOCKS_TABLE, SCHEDULE_RUNS_TABLE};
+
+/// Runs kept in memory by [`Schedule::history`]
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Bytes of stdout and stderr kept per run
+pub const OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// Errors reading or writing the run history
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleError(pub String);
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Schedule error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+type TaskFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+enum TaskAction {
+    Call(Arc<dyn Fn() -> TaskFuture + Send + Sync>),
+    Exec(String),
+    /// A program run without a shell; `None` is the app's own executable
+    Program(Option<String>, Vec<String>),
+}
+
+/// A task registered on a [`Schedule`]
+pub struct ScheduledTask {
+    name: String,
+    action: TaskAction,
+    cron: CronExpression,
+    expected_runtime: Option<Duration>,
+    alert_emails: Vec<String>,
+    alert_webhooks: Vec<String>,
+    /// How long the overlap lock is held at most
+    #[cfg_attr(not(feature = "database"), allow(dead_code))]
+    lock_expires_after: Option<Duration>,
+}
+
+impl ScheduledTask {
+    fn new(name: &str, action: TaskAction) -> Self {
+        Self {
+            name: name.to_string(),
+            action,
+            cron: CronExpression::parse("* * * * *").unwrap(),
+            expected_runtime: None,
+            alert_emails: Vec::new(),
+            alert_webhooks: Vec::new(),
+            lock_expires_after: None,
+        }
+    }
+
+    /// When to run, as a cron expression in UTC (default every minute).
+    ///
+    /// # Panics
+    ///
+    /// If `expression` isn't a valid cron expression. The frequency helpers
+    /// below panic the same way on out of range values.
+    pub fn cron(&mut self, expression: &str) -> &mut Self {
+        self.cron = CronExpression::parse(expression)
+            .unwrap_or_else(|error| panic!("invalid cron expression for task '{}': {}", self.name, error));
+        self
+    }
+
+    /// Run every minute
+    pub fn every_minute(&mut self) -> &mut Self {
+        self.cron("* * * * *")
+    }
+
+    /// Run every `minutes` minutes, on the hour and at each multiple after it
+    pub fn every_minutes(&mut self, minutes: u32) -> &mut Self {
+        self.cron(&format!("*/{} * * * *", minutes))
+    }
+
+    /// Run at the start of every hour
+    pub fn hourly(&mut self) -> &mut Self {
+        self.cron("0 * * * *")
+    }
+
+    /// Run every hour at `minute` past
+    pub fn hourly_at(&mut self, minute: u32) -> &mut Self {
+        self.cron(&format!("{} * * * *", minute))
+    }
+
+    /// Run every day at midnight UTC
+    pub fn daily(&mut self) -> &mut Self {
+        self.cron("0 0 * * *")
+    }
+
+    /// Run every day at `time`, given as `"HH:MM"` in UTC
+    pub fn daily_at(&mut self, time: &str) -> &mut Self {
+        let (hour, minute) = self.parse_time(time);
+        self.cron(&format!("{} {} * * *", minute, hour))
+    }
+
+    /// Run at midnight UTC on Monday to Friday
+    pub fn weekdays(&mut self) -> &mut Self {
+        self.cron("0 0 * * 1-5")
+    }
+
+    /// Run every Sunday at midnight UTC
+    pub fn weekly(&mut self) -> &mut Self {
+        self.cron("0 0 * * 0")
+    }
+
+    /// Run every week on `weekday` (0 is Sunday) at `"HH:MM"` UTC
+    pub fn weekly_on(&mut self, weekday: u32, time: &str) -> &mut Self {
+        let (hour, minute) = self.parse_time(time);
+        self.cron(&format!("{} {} * * {}", minute, hour, weekday))
+    }
+
+    /// Run at midnight UTC on the first of every month
+    pub fn monthly(&mut self) -> &mut Self {
+        self.cron("0 0 1 * *")
+    }
+
+    /// Run every month on `day` at `"HH:MM"` UTC
+    pub fn monthly_on(&mut self, day: u32, time: &str) -> &mut Self {
+        let (hour, minute) = self.parse_time(time);
+        self.cron(&format!("{} {} {} * *", minute, hour, day))
+    }
+
+    fn parse_time(&self, time: &str) -> (u32, u32) {
+        time.split_once(':')
+            .and_then(|(hour, minute)| Some((hour.parse().ok()?, minute.parse().ok()?)))
+            .unwrap_or_else(|| panic!("invalid time '{}' for task '{}', expected HH:MM", time, self.name))
+    }
+
+    /// Alert when a run takes longer than this
+    pub fn expected_runtime(&mut self, duration: Duration) -> &mut Self {
+        self.expected_runtime = Some(duration);
+        self
+    }
+
+    /// Email `address` when a run fails or is slow, with its output
+    pub fn email_on_failure(&mut self, address: &str) -> &mut Self {
+        self.alert_emails.push(address.to_string());
+        self
+    }
+
+    /// POST the run as JSON to an `http://` URL when it fails or is slow
+    pub fn ping_on_failure(&mut self, url: &str) -> &mut Self {
+        self.alert_webhooks.push(url.to_string());
+        self
+    }
+
+    /// Skip the task while an earlier run, in this or any other process
+    /// sharing the database, is still going. The lock lapses after
+    /// `expires_after` in case the process holding it dies.
+    #[cfg(feature = "database")]
+    pub fn without_overlapping(&mut self, expires_after: Duration) -> &mut Self {
+        self.lock_expires_after = Some(expires_after);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn expression(&self) -> &CronExpression {
+        &self.cron
+    }
+
+    /// Whether the task runs in the minute containing `timestamp`
+    pub fn is_due(&self, timestamp: u64) -> bool {
+        self.cron.matches(timestamp)
+    }
+}
+
+/// One run of a task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskRun {
+    pub task: String,
+    /// Unix timestamp, in seconds
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Exit code of a command; `None` for closures or when killed
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    /// Standard error of a command, or the error a closure returned
+    pub stderr: String,
+    /// Whether the run took longer than the task's expected runtime
+    pub slow: bool,
+}
+
+impl TaskRun {
+    /// Whether anyone should be told about this run
+    pub fn needs_attention(&self) -> bool {
+        !self.success || self.slow
+    }
+}
+
+/// The email sent for a failed or slow run
+pub(crate) struct TaskAlert {
+    run: TaskRun,
+}
+
+impl Mailable for TaskAlert {
+    fn build(&self) -> MailMessage {
+        let run = &self.run;
+        let problem = if run.success { "ran slowly" } else { "failed" };
+        let mut text = format!(
+            "Task {} {} after {} ms (exit code {}).\n",
+            run.task,
+            problem,
+            run.duration_ms,
+            run.exit_code.map_or_else(|| "none".to_string(), |code| code.to_string())
+        );
+        for (label, output) in [("stdout", &run.stdout), ("stderr", &run.stderr)] {
+            if !output.is_empty() {
+                text.push_str(&format!("\n{}:\n{}\n", label, output));
+            }
+        }
+        MailMessage::new().subject(format!("[schedule] {} {}", run.task, problem)).text(text)
+    }
+}
+
+/// Output as text, cut to [`OUTPUT_LIMIT`] bytes
+fn capture(output: &[u8]) -> String {
+    let mut text = String::from_utf8_lossy(output).into_owned();
+    if text.len() > OUTPUT_LIMIT {
+        let mut end = OUTPUT_LIMIT;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n[output truncated]");
+    }
+    text
+}
+
+/// The tasks an app runs on a timetable
+#[derive(Default)]
+pub struct Schedule {
+    tasks: Vec<ScheduledTask>,
+    history: Arc<Mutex<VecDeque<TaskRun>>>,
+    #[cfg_attr(not(feature = "database"), allow(dead_code))]
+    persist: bool,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a closure. Returning an error marks the run failed.
+    pub fn call<F, Fut, E>(&mut self, name: &str, task: F) -> &mut ScheduledTask
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: fmt::Display + 'static,
+    {
+        let task = Arc::new(move || -> TaskFuture {
+            let future = task();
+            Box::pin(async move { future.await.map_err(|error| error.to_string()) })
+        });
+        self.add(ScheduledTask::new(name, TaskAction::Call(task)))
+    }
+
+    /// Run a shell command, capturing its output. A non-zero exit code
+    /// marks the run failed.
+    pub fn exec(&mut self, name: &str, command: &str) -> &mut ScheduledTask {
+        self.add(ScheduledTask::new(name, TaskAction::Exec(command.to_string())))
+    }
+
+    /// Run one of the app's own console commands, registered on the
+    /// `console::Console` it dispatches from `main`, by starting the app's
+    /// executable again with `command` as its arguments, split on whitespace.
+    /// The task is named after the command.
+    pub fn command(&mut self, command: &str) -> &mut ScheduledTask {
+        let args = command.split_whitespace().map(str::to_string).collect();
+        self.add(ScheduledTask::new(command, TaskAction::Program(None, args)))
+    }
+
+    /// Run a `torch` CLI subcommand, such as `"queue prune --hours 48"`, with
+    /// the `torch` binary found on `PATH`
+    pub fn torch(&mut self, command: &str) -> &mut ScheduledTask {
+        let args = command.split_whitespace().map(str::to_string).collect();
+        let name = format!("torch {}", command.trim());
+        self.add(ScheduledTask::new(&name, TaskAction::Program(Some("torch".to_string()), args)))
+    }
+
+    fn add(&mut self, task: ScheduledTask) -> &mut ScheduledTask {
+        self.tasks.push(task);
+        self.tasks.last_mut().unwrap()
+    }
+
+    /// Also write every run to the `schedule_runs` table, creating it if needed
+    #[cfg(feature = "database")]
+    pub fn persist_history(&mut self) -> &mut Self {
+        self.persist = true;
+        self
+    }
+
+    pub fn tasks(&self) -> &[ScheduledTask] {
+        &self.tasks
+    }
+
+    /// Recent runs, oldest first
+    pub fn history(&self) -> Vec<TaskRun> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Run every task due in the minute containing `timestamp`, one after
+    /// another. Tasks whose overlap lock is held elsewhere are left out.
+    pub async fn run_due(&self, timestamp: u64) -> Vec<TaskRun> {
+        let mut runs = Vec::new();
+        for task in self.tasks.iter().filter(|task| task.is_due(timestamp)) {
+            #[cfg(feature = "database")]
+            let holder = match task.lock_expires_after {
+                Some(expires_after) => match database::lock(&task.name, expires_after).await {
+                    Ok(Some(holder)) => Some(holder),
+                    Ok(None) => continue,
+                    Err(error) => {
+                        eprintln!("⚠️  Skipped task {}, its lock could not be taken: {}", task.name, error);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            runs.push(self.run_task(task).await);
+
+            #[cfg(feature = "database")]
+            if let Some(holder) = holder {
+                if let Err(error) = database::unlock(&task.name, &holder).await {
+                    eprintln!("⚠️  Could not release the lock of task {}: {}", task.name, error);
+                }
+            }
+        }
+        runs
+    }
+
+    /// Run a task now whatever its schedule or overlap lock, e.g. from a
+    /// console command
+    pub async fn run_now(&self, name: &str) -> Option<TaskRun> {
+        let task = self.tasks.iter().find(|task| task.name == name)?;
+        Some(self.run_task(task).await)
+    }
+
+    /// Run due tasks at the start of every minute, forever
+    pub async fn run(&self) {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60) - Duration::from_millis(unix_now_ms() % 60_000)).await;
+            self.run_due(unix_now()).await;
+        }
+    }
+
+    async fn run_task(&self, task: &ScheduledTask) -> TaskRun {
+        let started_at = unix_now();
+        let started = Instant::now();
+        let (success, exit_code, stdout, stderr) = match &task.action {
+            // Spawned so a panicking task fails its run instead of the scheduler
+            TaskAction::Call(call) => match tokio::spawn(call()).await {
+                Ok(Ok(())) => (true, None, String::new(), String::new()),
+                Ok(Err(error)) => (false, None, String::new(), error),
+                Err(error) => (false, None, String::new(), format!("task panicked: {}", error)),
+            },
+            TaskAction::Exec(command) => run_process(shell(command), command).await,
+            TaskAction::Program(program, args) => {
+                let program = match program {
+                    Some(program) => Ok(program.into()),
+                    None => std::env::current_exe(),
+                };
+                match program {
+                    Ok(program) => {
+                        let mut command = tokio::process::Command::new(program);
+                        command.args(args).kill_on_drop(true);
+                        run_process(command, &task.name).await
+                    }
+                    Err(error) => (false, None, String::new(), format!("could not find the app executable: {}", error)),
+                }
+            }
+        };
+        let duration = started.elapsed();
+
+        let run = TaskRun {
+            task: task.name.clone(),
+            started_at,
+            duration_ms: duration.as_millis() as u64,
+            success,
+            exit_code,
+            stdout,
+            stderr,
+            slow: task.expected_runtime.is_some_and(|expected| duration > expected),
+        };
+        self.record(&run).await;
+        if run.needs_attention() {
+            alert(task, &run).await;
+        }
+        run
+    }
+
+    async fn record(&self, run: &TaskRun) {
+        {
+            let mut history = self.history.lock().unwrap();
+            if history.len() == HISTORY_LIMIT {
+                history.pop_front();
+            }
+            history.push_back(run.clone());
+        }
+
+        #[cfg(feature = "database")]
+        if self.persist {
+            if let Err(error) = database::record(run).await {
+                eprintln!("⚠️  Could not store run of task {}: {}", run.task, error);
+            }
+        }
+    }
+}
+
+/// Run a process to completion, returning whether it succeeded, its exit
+/// code, stdout and stderr
+async fn run_process(mut command: tokio::process::Command, label: &str) -> (bool, Option<i32>, String, String) {
+    match command.output().await {
+        Ok(output) => (output.status.success(), output.status.code(), capture(&output.stdout), capture(&output.stderr)),
+        Err(error) => (false, None, String::new(), format!("could not start '{}': {}", label, error)),
+    }
+}
+
+fn shell(command: &str) -> tokio::process::Command {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = tokio::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = tokio::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command).kill_on_drop(true);
+    shell
+}
+
+/// Tell the task's email and webhook recipients about a failed or slow run
+async fn alert(task: &ScheduledTask, run: &TaskRun) {
+    if let Some((first, rest)) = task.alert_emails.split_first() {
+        let mail = rest.iter().fold(Mail::to(first), |mail, address| mail.to(address));
+        if let Err(error) = mail.send(TaskAlert { run: run.clone() }).await {
+            eprintln!("⚠️  Could not email alert for task {}: {}", run.task, error);
+        }
+    }
+
+    for url in &task.alert_webhooks {
+        let body = serde_json::to_vec(run).unwrap_or_default();
+        let sink = WebhookSink::new(url);
+        match tokio::time::timeout(Duration::from_secs(5), sink.send(body)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => eprintln!("⚠️  Could not ping {} for task {}: {}", url, run.task, error),
+            Err(_) => eprintln!("⚠️  Could not ping {} for task {}: timed out", url, run.task),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_runs_capture_output_and_alert_on_failure() {
+        let _mail = Mail::fake();
+        let mut schedule = Schedule::new();
+        schedule.exec("greet", "echo hello && echo careful >&2").cron("30 9 * * *");
+        schedule.call("broken", || async { Err::<(), _>("disk full") }).cron("30 9 * * *").email_on_failure("ops@example.com");
+        schedule
+            .call("slow", || async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, String>(())
+            })
+            .expected_runtime(Duration::from_millis(1))
+            .email_on_failure("ops@example.com");
+        schedule.exec("nightly", "exit 3").cron("@daily");
+
+        // 2024-02-29 09:30 UTC
+        let runs = schedule.run_due(1_709_199_000).await;
+        let summary: Vec<_> = runs.iter().map(|run| (run.task.as_str(), run.success, run.slow)).collect();
+        assert_eq!(summary, vec![("greet", true, false), ("broken", false, false), ("slow", true, true)]);
+        assert_eq!((runs[0].stdout.trim(), runs[0].stderr.trim(), runs[0].exit_code), ("hello", "careful", Some(0)));
+        assert_eq!(runs[1].stderr, "disk full");
+
+        let alerts = Mail::sent::<TaskAlert>();
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].1.subject, "[schedule] broken failed");
+        assert_eq!(alerts[1].1.subject, "[schedule] slow ran slowly");
+
+        let nightly = schedule.run_now("nightly").await.unwrap();
+        assert_eq!((nightly.success, nightly.exit_code), (false, Some(3)));
+        assert_eq!(schedule.history().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_frequency_helpers_and_app_commands() {
+        let mut schedule = Schedule::new();
+        schedule.command("--list --format terse").daily_at("07:05");
+        schedule.torch("queue prune --hours 48").every_minutes(15);
+        schedule.call("report", || async { Ok::<_, String>(()) }).weekly_on(1, "9:30");
+
+        let expressions: Vec<_> = schedule.tasks().iter().map(|task| (task.name(), task.expression().as_str())).collect();
+        assert_eq!(
+            expressions,
+            vec![
+                ("--list --format terse", "5 7 * * *"),
+                ("torch queue prune --hours 48", "*/15 * * * *"),
+                ("report", "30 9 * * 1"),
+            ]
+        );
+
+        // The test binary stands in for the app, listing its tests
+        let run = schedule.run_now("--list --format terse").await.unwrap();
+        assert!(run.success, "{}", run.stderr);
+        assert!(run.stdout.contains("test_frequency_helpers_and_app_commands"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Cron expressions
+//!
+//! The standard five fields, `minute hour day-of-month month day-of-week`,
+//! each a `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a comma
+//! separated list of those. Day of week runs from 0 (Sunday) to 6, with 7
+//! also meaning Sunday. `@hourly`, `@daily`, `@weekly`, `@monthly` and
+//! `@yearly` are accepted as shorthands. Times are matched in UTC.
+
+use std::fmt;
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpression {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month and day of week were both restricted, in which
+    /// case either may match, as in every other cron
+    either_day: bool,
+}
+
+/// A UTC calendar time, down to the minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CivilTime {
+    pub minute: u32,
+    pub hour: u32,
+    pub day: u32,
+    pub month: u32,
+    pub year: i64,
+    /// 0 is Sunday
+    pub weekday: u32,
+}
+
+impl CivilTime {
+    /// Break a Unix timestamp into calendar fields
+    pub(crate) fn from_timestamp(timestamp: u64) -> Self {
+        let days = (timestamp / 86_400) as i64;
+        let seconds = timestamp % 86_400;
+
+        // Days to civil date, from Howard Hinnant's `civil_from_days`
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            minute: (seconds / 60 % 60) as u32,
+            hour: (seconds / 3_600) as u32,
+            day,
+            month,
+            year,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+}
+
+impl fmt::Display for CivilTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute)
+    }
+}
+
+/// Parse one field into a bit set of the values it allows
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("step can't be zero in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start, part)?, parse_value(end, part)?),
+                // `5/15` means from 5 to the end in steps of 15
+                None if step > 1 => (parse_value(range, part)?, max),
+                None => {
+                    let value = parse_value(range, part)?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("'{}' is outside {}-{}", part, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, part: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid value in '{}'", part))
+}
+
+impl CronExpression {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields in '{}', found {}", expression, fields.len()));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            source: expression.trim().to_string(),
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            either_day: day != "*" && weekday != "*",
+        })
+    }
+
+    /// Whether the expression fires in the minute containing `timestamp`
+    pub fn matches(&self, timestamp: u64) -> bool {
+        let time = CivilTime::from_timestamp(timestamp);
+        let bit = |set: u64, value: u32| set & (1 << value) != 0;
+        let day = bit(self.days, time.day);
+        let weekday = bit(self.weekdays, time.weekday);
+        let day_matches = if self.either_day { day || weekday } else { day && weekday };
+
+        bit(self.minutes, time.minute) && bit(self.hours, time.hour) && bit(self.months, time.month) && day_matches
+    }
+
+    /// The first minute after `timestamp` when the expression fires, looking
+    /// up to a year ahead
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let start = timestamp - timestamp % 60 + 60;
+        (0..366 * 24 * 60).map(|minute| start + minute * 60).find(|&candidate| self.matches(candidate))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cron_expressions_match_utc_minutes() {
+        // 2024-02-29 09:30 UTC, a Thursday
+        let leap_day = 1_709_199_000;
+        assert_eq!(CivilTime::from_timestamp(leap_day).to_string(), "2024-02-29 09:30");
+        assert_eq!(CivilTime::from_timestamp(leap_day).weekday, 4);
+
+        let weekdays = CronExpression::parse("30 9 * * 1-5").unwrap();
+        assert!(weekdays.matches(leap_day + 59));
+        assert!(!weekdays.matches(leap_day + 60));
+        assert!(CronExpression::parse("*/15 9-17 * * *").unwrap().matches(leap_day));
+        assert!(CronExpression::parse("30 9 1 * 4").unwrap().matches(leap_day), "either day field may match");
+        assert!(!CronExpression::parse("30 9 * * 0,7").unwrap().matches(leap_day));
+        assert_eq!(CronExpression::parse("@daily").unwrap().next_after(leap_day), Some(1_709_251_200));
+
+        assert!(CronExpression::parse("60 * * * *").is_err());
+        assert!(CronExpression::parse("* * *").is_err());
+        assert!(CronExpression::parse("*/0 * * * *").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Run history and overlap locks stored through the ORM connection pool
+//!
+//! Every run becomes a row in `schedule_runs`. A task that runs
+//! `without_overlapping` holds a row in `schedule_locks` while it runs; a
+//! row past its `expires_at` is taken over by the next run. The tables are
+//! created the first time they're needed, so apps don't need a migration for
+//! them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sqlx::Row;
+
+use super::{Schedule, ScheduleError, TaskRun};
+use crate::clock::unix_now;
+use crate::orm::connection::{try_get_pool, placeholders};
+use crate::orm::migration::Schema;
+
+/// Table holding one row per scheduled task run
+pub const SCHEDULE_RUNS_TABLE: &str = "schedule_runs";
+
+/// Table holding the overlap lock of each running task
+pub const SCHEDULE_LOCKS_TABLE: &str = "schedule_locks";
+
+static INSTALLED: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+
+/// Tells apart the locks taken by this process
+static LOCKS_TAKEN: AtomicU64 = AtomicU64::new(0);
+
+fn database_error(error: impl std::fmt::Display) -> ScheduleError {
+    ScheduleError(error.to_string())
+}
+
+async fn install() -> Result<(), ScheduleError> {
+    INSTALLED.get_or_try_init(create_tables).await.map(|_| ())
+}
+
+async fn create_tables() -> Result<(), ScheduleError> {
+    if !Schema::has_table(SCHEDULE_RUNS_TABLE).await.map_err(database_error)? {
+        Schema::create_table(SCHEDULE_RUNS_TABLE, |table| {
+            table.id("id");
+            table.string("task", None);
+            table.big_integer("started_at");
+            table.big_integer("duration_ms");
+            table.boolean("success");
+            table.big_integer("exit_code").nullable();
+            table.text("stdout");
+            table.text("stderr");
+            table.boolean("slow");
+            table.index(&["started_at"], None);
+        })
+        .execute()
+        .await
+        .map_err(database_error)?;
+    }
+    if !Schema::has_table(SCHEDULE_LOCKS_TABLE).await.map_err(database_error)? {
+        Schema::create_table(SCHEDULE_LOCKS_TABLE, |table| {
+            table.string("task", None).unique();
+            table.string("holder", None);
+            table.big_integer("expires_at");
+        })
+        .execute()
+        .await
+        .map_err(database_error)?;
+    }
+    Ok(())
+}
+
+/// Take the overlap lock of `task` for a new run, returning the holder to
+/// unlock it with, or `None` while another run holds it
+pub(super) async fn lock(task: &str, expires_after: Duration) -> Result<Option<String>, ScheduleError> {
+    // Process ids repeat across containers, so the clock tells hosts apart
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let holder = format!("{}-{}-{}", std::process::id(), nanos, LOCKS_TAKEN.fetch_add(1, Ordering::Relaxed));
+    Ok(try_lock(task, &holder, expires_after).await?.then_some(holder))
+}
+
+/// Take the lock of `task` for `holder` if nobody holds it or the last
+/// holder's lock has expired
+async fn try_lock(task: &str, holder: &str, expires_after: Duration) -> Result<bool, ScheduleError> {
+    install().await?;
+    let pool = try_get_pool().map_err(database_error)?;
+    let now = unix_now() as i64;
+    let expires_at = now + expires_after.as_secs() as i64;
+
+    let sql = placeholders(&format!("UPDATE {} SET holder = ?, expires_at = ? WHERE task = ? AND expires_at <= ?", SCHEDULE_LOCKS_TABLE));
+    let taken_over = sqlx::query(&sql)
+        .bind(holder.to_string())
+        .bind(expires_at)
+        .bind(task.to_string())
+        .bind(now)
+        .execute(&pool)
+        .await
+        .map_err(database_error)?;
+    if taken_over.rows_affected() > 0 {
+        return Ok(true);
+    }
+
+    let sql = placeholders(&format!("INSERT INTO {} (task, holder, expires_at) VALUES (?, ?, ?)", SCHEDULE_LOCKS_TABLE));
+    let inserted = sqlx::query(&sql).bind(task.to_string()).bind(holder.to_string()).bind(expires_at).execute(&pool).await;
+    match inserted {
+        Ok(_) => Ok(true),
+        // The unique index refused it because another run holds the lock
+        Err(error) => {
+            let sql = placeholders(&format!("SELECT COUNT(*) FROM {} WHERE task = ?", SCHEDULE_LOCKS_TABLE));
+            let held: i64 = sqlx::query_scalar(&sql).bind(task.to_string()).fetch_one(&pool).await.map_err(database_error)?;
+            if held > 0 {
+                Ok(false)
+            } else {
+                Err(database_error(error))
+            }
+        }
+    }
+}
+
+/// Release the lock of `task` if `holder` still holds it
+pub(super) async fn unlock(task: &str, holder: &str) -> Result<(), ScheduleError> {
+    let sql = placeholders(&format!("DELETE FROM {} WHERE task = ? AND holder = ?", SCHEDULE_LOCKS_TABLE));
+    sqlx::query(&sql)
+        .bind(task.to_string())
+        .bind(holder.to_string())
+        .execute(&try_get_pool().map_err(database_error)?)
+        .await
+        .map_err(database_error)?;
+    Ok(())
+}
+
+pub(super) async fn record(run: &TaskRun) -> Result<(), ScheduleError> {
+    install().await?;
+    let sql = placeholders(&format!(
+        "INSERT INTO {} (task, started_at, duration_ms, success, exit_code, stdout, stderr, slow) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        SCHEDULE_RUNS_TABLE
+    ));
+    sqlx::query(&sql)
+        .bind(run.task.clone())
+        .bind(run.started_at as i64)
+        .bind(run.duration_ms as i64)
+        .bind(run.success)
+        .bind(run.exit_code.map(i64::from))
+        .bind(run.stdout.clone())
+        .bind(run.stderr.clone())
+        .bind(run.slow)
+        .execute(&try_get_pool().map_err(database_error)?)
+        .await
+        .map_err(database_error)?;
+    Ok(())
+}
+
+impl Schedule {
+    /// The latest `limit` runs stored by [`persist_history`](Self::persist_history),
+    /// newest first
+    pub async fn stored_history(limit: u32) -> Result<Vec<TaskRun>, ScheduleError> {
+        install().await?;
+        let sql = placeholders(&format!(
+            "SELECT task, started_at, duration_ms, success, exit_code, stdout, stderr, slow FROM {} \
+             ORDER BY started_at DESC, id DESC LIMIT ?",
+            SCHEDULE_RUNS_TABLE
+        ));
+        let rows = sqlx::query(&sql).bind(i64::from(limit)).fetch_all(&try_get_pool().map_err(database_error)?).await.map_err(database_error)?;
+        rows.iter()
+            .map(|row| {
+                let exit_code: Option<i64> = row.try_get(4).map_err(database_error)?;
+                Ok(TaskRun {
+                    task: row.try_get(0).map_err(database_error)?,
+                    started_at: row.try_get::<i64, _>(1).map_err(database_error)? as u64,
+                    duration_ms: row.try_get::<i64, _>(2).map_err(database_error)? as u64,
+                    success: row.try_get(3).map_err(database_error)?,
+                    exit_code: exit_code.map(|code| code as i32),
+                    stdout: row.try_get(5).map_err(database_error)?,
+                    stderr: row.try_get(6).map_err(database_error)?,
+                    slow: row.try_get(7).map_err(database_error)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::connection::sqlite_test_pool;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_the_lock_has_one_holder_until_it_is_released_or_expires() {
+        let pool = sqlite_test_pool().await;
+        let hour = Duration::from_secs(3600);
+
+        assert!(try_lock("db-lock", "a", hour).await.unwrap());
+        assert!(!try_lock("db-lock", "b", hour).await.unwrap());
+        assert!(!try_lock("db-lock", "a", hour).await.unwrap());
+        assert!(try_lock("db-lock-other", "b", hour).await.unwrap());
+
+        // Only the holder can release it
+        unlock("db-lock", "b").await.unwrap();
+        assert!(!try_lock("db-lock", "b", hour).await.unwrap());
+        unlock("db-lock", "a").await.unwrap();
+        assert!(try_lock("db-lock", "b", hour).await.unwrap());
+
+        // The holder died: once its lock lapses it is taken over,
+        // after which its late unlock doesn't free the new holder's lock
+        let sql = placeholders(&format!("UPDATE {} SET expires_at = ? WHERE task = ?", SCHEDULE_LOCKS_TABLE));
+        sqlx::query(&sql).bind(unix_now() as i64 + 60).bind("db-lock").execute(&pool).await.unwrap();
+        assert!(!try_lock("db-lock", "c", hour).await.unwrap());
+        sqlx::query(&sql).bind(unix_now() as i64).bind("db-lock").execute(&pool).await.unwrap();
+        assert!(try_lock("db-lock", "c", hour).await.unwrap());
+        unlock("db-lock", "b").await.unwrap();
+        assert!(!try_lock("db-lock", "a", hour).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_runs_are_skipped_across_schedules() {
+        sqlite_test_pool().await;
+        let runs = Arc::new(AtomicUsize::new(0));
+        let schedule = || {
+            let runs = runs.clone();
+            let mut schedule = Schedule::new();
+            schedule
+                .call("db-overlap", move || {
+                    let runs = runs.clone();
+                    async move {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        Ok::<_, String>(())
+                    }
+                })
+                .without_overlapping(Duration::from_secs(60));
+            schedule
+        };
+        // Two processes running the same schedule
+        let (first, second) = (schedule(), schedule());
+
+        let (a, b) = tokio::join!(first.run_due(unix_now()), second.run_due(unix_now()));
+        assert_eq!(a.len() + b.len(), 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // The lock is released once the run finishes
+        assert_eq!(second.run_due(unix_now()).await.len(), 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
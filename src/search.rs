@@ -0,0 +1,794 @@
+//! # Full-text search over ORM models
+//!
+//! Models implementing [`Searchable`] are copied into a search engine as
+//! JSON documents and queried there, then loaded back from the database in
+//! the order the engine ranked them. Engines implement [`SearchEngine`]:
+//!
+//! - [`Meilisearch`] talks to a Meilisearch server.
+//! - [`Elasticsearch`] talks to Elasticsearch or OpenSearch.
+//! - [`MemoryEngine`] keeps documents in process memory, for development
+//!   and tests. It is used until another engine is set.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! use torch_web::search::{Search, Searchable};
+//!
+//! Search::from_config(&config.search.unwrap_or_default())?;
+//!
+//! // Keep the index in step with the table on every save and delete
+//! Search::register::<Post>();
+//!
+//! impl Searchable for Post {
+//!     fn filterable_attributes() -> &'static [&'static str] {
+//!         &["author_id", "published"]
+//!     }
+//!
+//!     fn sortable_attributes() -> &'static [&'static str] {
+//!         &["created_at"]
+//!     }
+//! }
+//!
+//! // Once, or after changing the attributes above
+//! Post::configure_search_index().await?;
+//! Post::make_all_searchable(500).await?;
+//!
+//! let page = Post::search("rust async")
+//!     .where_eq("published", true)
+//!     .where_in("author_id", vec![1, 2])
+//!     .order_by_desc("created_at")
+//!     .paginate(1, 20)
+//!     .await?;
+//! ```
+
+mod elasticsearch;
+mod http;
+mod meilisearch;
+
+pub use elasticsearch::Elasticsearch;
+pub use meilisearch::Meilisearch;
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::config::SearchConfig;
+use crate::orm::{Model, OrmError, Paginated};
+
+/// A model as stored in the engine
+pub type Document = serde_json::Map<String, Value>;
+
+/// Errors raised while indexing or searching
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    /// The engine couldn't be reached or answered with something unreadable
+    #[error("Search engine error: {0}")]
+    Engine(String),
+    /// The engine refused the request
+    #[error("Search engine rejected the request ({status}): {body}")]
+    Rejected { status: u16, body: Value },
+    #[error("Unknown search driver: {0}")]
+    UnknownDriver(String),
+    #[error(transparent)]
+    Orm(#[from] OrmError),
+}
+
+/// Where a model's documents live and which attributes can be filtered and
+/// sorted on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub primary_key: String,
+    pub filterable: Vec<String>,
+    pub sortable: Vec<String>,
+}
+
+/// A condition on an attribute listed in
+/// [`Searchable::filterable_attributes`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Equals(String, Value),
+    In(String, Vec<Value>),
+    Gte(String, Value),
+    Lte(String, Value),
+}
+
+/// Order on an attribute listed in [`Searchable::sortable_attributes`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+    pub field: String,
+    pub descending: bool,
+}
+
+/// What to look for; an empty query matches every document
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub query: String,
+    pub filters: Vec<Filter>,
+    pub sort: Vec<Sort>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl SearchQuery {
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            filters: Vec::new(),
+            sort: Vec::new(),
+            offset: 0,
+            limit: 20,
+        }
+    }
+}
+
+/// Primary keys of the matching documents, best match first, and how many
+/// documents matched in all
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResults {
+    pub keys: Vec<Value>,
+    pub total: u64,
+}
+
+/// A search engine documents are indexed in
+#[async_trait]
+pub trait SearchEngine: Send + Sync + 'static {
+    /// Add documents, replacing those with the same primary key
+    async fn update(&self, index: &Index, documents: Vec<Document>) -> Result<(), SearchError>;
+
+    /// Remove documents by primary key
+    async fn delete(&self, index: &Index, keys: Vec<Value>) -> Result<(), SearchError>;
+
+    async fn search(&self, index: &Index, query: &SearchQuery) -> Result<SearchResults, SearchError>;
+
+    /// Remove every document from the index
+    async fn flush(&self, index: &Index) -> Result<(), SearchError>;
+
+    /// Create the index and declare its filterable and sortable attributes
+    async fn configure(&self, index: &Index) -> Result<(), SearchError>;
+}
+
+static ENGINE: RwLock<Option<Arc<dyn SearchEngine>>> = RwLock::new(None);
+static PREFIX: RwLock<String> = RwLock::new(String::new());
+static REGISTERED: RwLock<BTreeMap<TypeId, Hooks>> = RwLock::new(BTreeMap::new());
+
+type HookFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Index or unindex a registered model after a write
+#[derive(Clone, Copy)]
+struct Hooks {
+    saved: for<'a> fn(&'a (dyn Any + Send + Sync)) -> HookFuture<'a>,
+    deleted: for<'a> fn(&'a (dyn Any + Send + Sync)) -> HookFuture<'a>,
+}
+
+/// The app's search engine
+pub struct Search;
+
+impl Search {
+    /// Index and search models with `engine`
+    pub fn set_engine(engine: impl SearchEngine) {
+        *ENGINE.write().unwrap() = Some(Arc::new(engine));
+    }
+
+    /// The engine set with [`set_engine`](Search::set_engine), or a
+    /// [`MemoryEngine`] kept for the life of the process
+    pub fn engine() -> Arc<dyn SearchEngine> {
+        if let Some(engine) = ENGINE.read().unwrap().as_ref() {
+            return engine.clone();
+        }
+        ENGINE.write().unwrap().get_or_insert_with(|| Arc::new(MemoryEngine::new())).clone()
+    }
+
+    /// Put `prefix` before every index name, to share an engine between
+    /// apps or environments
+    pub fn set_prefix(prefix: impl Into<String>) {
+        *PREFIX.write().unwrap() = prefix.into();
+    }
+
+    /// Set the engine and index prefix from the `[search]` section
+    pub fn from_config(config: &SearchConfig) -> Result<(), SearchError> {
+        let timeout = Duration::from_secs(config.timeout_secs);
+        match config.driver.as_str() {
+            "meilisearch" => {
+                let mut engine = Meilisearch::new(&config.url).timeout(timeout);
+                if let Some(key) = &config.api_key {
+                    engine = engine.api_key(key);
+                }
+                Self::set_engine(engine);
+            }
+            "elasticsearch" => {
+                let mut engine = Elasticsearch::new(&config.url).timeout(timeout);
+                if let Some(key) = &config.api_key {
+                    engine = engine.api_key(key);
+                } else if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                    engine = engine.basic_auth(username, password);
+                }
+                Self::set_engine(engine);
+            }
+            "memory" => Self::set_engine(MemoryEngine::new()),
+            driver => return Err(SearchError::UnknownDriver(driver.to_string())),
+        }
+        Self::set_prefix(config.index_prefix.clone());
+        Ok(())
+    }
+
+    /// Index `M` whenever it's saved and remove it whenever it's deleted
+    /// through the ORM. Saves are indexed after
+    /// [`after_save`](Model::after_save) runs, so the document reflects the
+    /// model as the hook left it; deletes are removed from the index before
+    /// [`after_delete`](Model::after_delete).
+    pub fn register<M: Searchable>() {
+        fn saved<M: Searchable>(model: &(dyn Any + Send + Sync)) -> HookFuture<'_> {
+            let model = model.downcast_ref::<M>().expect("registered for this model");
+            Box::pin(async move {
+                if let Err(error) = model.searchable().await {
+                    eprintln!("⚠️  Failed to index {} in search: {}", M::table_name(), error);
+                }
+            })
+        }
+        fn deleted<M: Searchable>(model: &(dyn Any + Send + Sync)) -> HookFuture<'_> {
+            let model = model.downcast_ref::<M>().expect("registered for this model");
+            Box::pin(async move {
+                if let Err(error) = model.unsearchable().await {
+                    eprintln!("⚠️  Failed to remove {} from search: {}", M::table_name(), error);
+                }
+            })
+        }
+        REGISTERED
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<M>(), Hooks { saved: saved::<M>, deleted: deleted::<M> });
+    }
+
+    /// Go back to no engine, no index prefix and no registered models, e.g.
+    /// between tests that set them up
+    pub fn reset() {
+        *ENGINE.write().unwrap() = None;
+        PREFIX.write().unwrap().clear();
+        REGISTERED.write().unwrap().clear();
+    }
+
+    /// The index `M` is stored in
+    pub fn index<M: Searchable>() -> Index {
+        let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        Index {
+            name: format!("{}{}", PREFIX.read().unwrap(), M::searchable_as()),
+            primary_key: M::primary_key().to_string(),
+            filterable: strings(M::filterable_attributes()),
+            sortable: strings(M::sortable_attributes()),
+        }
+    }
+}
+
+/// A model kept in the search engine.
+///
+/// [`Search::register`] the model to keep the index in step with the table.
+#[async_trait]
+pub trait Searchable: Model {
+    /// Name of the index, before the prefix; the table name by default
+    fn searchable_as() -> String {
+        Self::table_name().to_string()
+    }
+
+    /// The document indexed for the model: its attributes, without
+    /// [`hidden`](Model::hidden) ones other than the primary key
+    fn to_search_document(&self) -> Result<Document, SearchError> {
+        let mut document = Document::new();
+        for (name, value) in self.to_attributes()? {
+            if name == Self::primary_key() || !Self::hidden().contains(&name.as_str()) {
+                document.insert(name, value);
+            }
+        }
+        Ok(document)
+    }
+
+    /// Whether the model belongs in the index, such as only published
+    /// posts; others are removed from it when saved
+    fn should_be_searchable(&self) -> bool {
+        true
+    }
+
+    fn filterable_attributes() -> &'static [&'static str] {
+        &[]
+    }
+
+    fn sortable_attributes() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Add or update the model's document, or remove it when the model
+    /// [shouldn't be searchable](Searchable::should_be_searchable)
+    async fn searchable(&self) -> Result<(), SearchError> {
+        let Some(key) = search_key(self) else {
+            return Ok(());
+        };
+        let index = Search::index::<Self>();
+        if self.should_be_searchable() {
+            Search::engine().update(&index, vec![self.to_search_document()?]).await
+        } else {
+            Search::engine().delete(&index, vec![key]).await
+        }
+    }
+
+    /// Remove the model's document
+    async fn unsearchable(&self) -> Result<(), SearchError> {
+        match search_key(self) {
+            Some(key) => Search::engine().delete(&Search::index::<Self>(), vec![key]).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Index every row of the table, `chunk` at a time, returning how many
+    /// were indexed
+    async fn make_all_searchable(chunk: u32) -> Result<u64, SearchError> {
+        let index = Search::index::<Self>();
+        let engine = Search::engine();
+        let mut indexed = 0;
+        Self::chunk_by_id(chunk, |models| {
+            let index = &index;
+            let engine = &engine;
+            let documents: Result<Vec<Document>, SearchError> = models
+                .iter()
+                .filter(|model| model.should_be_searchable())
+                .map(|model| model.to_search_document())
+                .collect();
+            indexed += documents.as_ref().map_or(0, |documents| documents.len() as u64);
+            async move {
+                let documents = documents.map_err(|error| OrmError::Query(error.to_string()))?;
+                engine
+                    .update(index, documents)
+                    .await
+                    .map_err(|error| OrmError::Query(error.to_string()))
+            }
+        })
+        .await?;
+        Ok(indexed)
+    }
+
+    /// Remove every document from the model's index
+    async fn remove_all_from_search() -> Result<(), SearchError> {
+        Search::engine().flush(&Search::index::<Self>()).await
+    }
+
+    /// Create the model's index and declare its filterable and sortable
+    /// attributes
+    async fn configure_search_index() -> Result<(), SearchError> {
+        Search::engine().configure(&Search::index::<Self>()).await
+    }
+
+    /// Start a search of the model's index
+    fn search(query: &str) -> SearchBuilder<Self> {
+        SearchBuilder::new(query)
+    }
+}
+
+/// Index `model` after [`Model::save`] wrote it and ran `after_save`, if
+/// its type was registered, logging rather than returning a failure since
+/// the row has been written by then
+pub(crate) async fn saved<M: Model>(model: &M) {
+    let hooks = REGISTERED.read().unwrap().get(&TypeId::of::<M>()).copied();
+    if let Some(hooks) = hooks {
+        (hooks.saved)(model).await;
+    }
+}
+
+/// Remove `model` from the index after [`Model::delete`], if its type was
+/// registered, logging any failure
+pub(crate) async fn deleted<M: Model>(model: &M) {
+    let hooks = REGISTERED.read().unwrap().get(&TypeId::of::<M>()).copied();
+    if let Some(hooks) = hooks {
+        (hooks.deleted)(model).await;
+    }
+}
+
+fn search_key<M: Model>(model: &M) -> Option<Value> {
+    model.id().and_then(|id| serde_json::to_value(id).ok())
+}
+
+/// Compare keys as text, since engines may hand numeric keys back as
+/// strings
+fn key_text(key: &Value) -> String {
+    match key {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// A search of one model's index, built up with filters and sort orders
+/// before running it
+#[derive(Debug, Clone)]
+pub struct SearchBuilder<M> {
+    query: SearchQuery,
+    model: PhantomData<fn() -> M>,
+}
+
+impl<M: Searchable> SearchBuilder<M> {
+    pub fn new(query: &str) -> Self {
+        Self { query: SearchQuery::new(query), model: PhantomData }
+    }
+
+    pub fn where_eq(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.query.filters.push(Filter::Equals(field.to_string(), value.into()));
+        self
+    }
+
+    pub fn where_in(mut self, field: &str, values: Vec<impl Into<Value>>) -> Self {
+        let values = values.into_iter().map(Into::into).collect();
+        self.query.filters.push(Filter::In(field.to_string(), values));
+        self
+    }
+
+    pub fn where_gte(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.query.filters.push(Filter::Gte(field.to_string(), value.into()));
+        self
+    }
+
+    pub fn where_lte(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.query.filters.push(Filter::Lte(field.to_string(), value.into()));
+        self
+    }
+
+    pub fn order_by_asc(mut self, field: &str) -> Self {
+        self.query.sort.push(Sort { field: field.to_string(), descending: false });
+        self
+    }
+
+    pub fn order_by_desc(mut self, field: &str) -> Self {
+        self.query.sort.push(Sort { field: field.to_string(), descending: true });
+        self
+    }
+
+    /// Return at most `limit` results (20 by default)
+    pub fn take(mut self, limit: usize) -> Self {
+        self.query.limit = limit;
+        self
+    }
+
+    /// The query as it will be sent to the engine
+    pub fn to_query(&self) -> &SearchQuery {
+        &self.query
+    }
+
+    /// Run the search, returning primary keys rather than models
+    pub async fn raw(self) -> Result<SearchResults, SearchError> {
+        Search::engine().search(&Search::index::<M>(), &self.query).await
+    }
+
+    /// Run the search and load the matching models, best match first
+    pub async fn get(self) -> Result<Vec<M>, SearchError> {
+        let results = self.raw().await?;
+        load(results.keys).await
+    }
+
+    /// Run the search for one page of models; pages count from 1
+    pub async fn paginate(mut self, page: u32, per_page: u32) -> Result<Paginated<M>, SearchError> {
+        let page = page.max(1);
+        let per_page = per_page.max(1);
+        self.query.offset = ((page - 1) * per_page) as usize;
+        self.query.limit = per_page as usize;
+        let offset = self.query.offset as u32;
+
+        let results = self.raw().await?;
+        let total = results.total as i64;
+        let data = load::<M>(results.keys).await?;
+        let from = if data.is_empty() { None } else { Some(offset + 1) };
+        let to = if data.is_empty() { None } else { Some(offset + data.len() as u32) };
+        Ok(Paginated {
+            data,
+            current_page: page,
+            per_page,
+            total,
+            last_page: ((total as f64) / (per_page as f64)).ceil() as u32,
+            from,
+            to,
+            path: String::new(),
+            query: String::new(),
+        })
+    }
+}
+
+/// Load models by primary key in the order given, skipping rows deleted
+/// since they were indexed
+async fn load<M: Searchable>(keys: Vec<Value>) -> Result<Vec<M>, SearchError> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut models: HashMap<String, M> = M::query()
+        .where_in(M::primary_key(), keys.clone())
+        .get()
+        .await?
+        .into_iter()
+        .filter_map(|model| search_key(&model).map(|key| (key_text(&key), model)))
+        .collect();
+    Ok(keys.iter().filter_map(|key| models.remove(&key_text(key))).collect())
+}
+
+/// Documents kept in process memory, matched by case-insensitive substring
+/// on their text attributes.
+///
+/// Every word of the query has to appear in the document; documents with
+/// more occurrences rank first unless a sort order is given.
+#[derive(Default)]
+pub struct MemoryEngine {
+    indexes: Mutex<HashMap<String, BTreeMap<String, Document>>>,
+}
+
+impl MemoryEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn matches_filter(document: &Document, filter: &Filter) -> bool {
+    let compare = |field: &str, value: &Value| {
+        let actual = document.get(field)?;
+        match (actual, value) {
+            (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    };
+    match filter {
+        Filter::Equals(field, value) => document.get(field) == Some(value),
+        Filter::In(field, values) => document.get(field).is_some_and(|actual| values.contains(actual)),
+        Filter::Gte(field, value) => compare(field, value).is_some_and(|order| order.is_ge()),
+        Filter::Lte(field, value) => compare(field, value).is_some_and(|order| order.is_le()),
+    }
+}
+
+fn compare_documents(a: &Document, b: &Document, sort: &[Sort]) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    for Sort { field, descending } in sort {
+        let order = match (a.get(field), b.get(field)) {
+            (Some(Value::Number(x)), Some(Value::Number(y))) => {
+                x.as_f64().partial_cmp(&y.as_f64()).unwrap_or(Ordering::Equal)
+            }
+            (Some(Value::String(x)), Some(Value::String(y))) => x.cmp(y),
+            (Some(Value::Bool(x)), Some(Value::Bool(y))) => x.cmp(y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            _ => Ordering::Equal,
+        };
+        let order = if *descending { order.reverse() } else { order };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+#[async_trait]
+impl SearchEngine for MemoryEngine {
+    async fn update(&self, index: &Index, documents: Vec<Document>) -> Result<(), SearchError> {
+        let mut indexes = self.indexes.lock().unwrap();
+        let stored = indexes.entry(index.name.clone()).or_default();
+        for document in documents {
+            let key = document
+                .get(&index.primary_key)
+                .ok_or_else(|| SearchError::Engine(format!("document has no `{}`", index.primary_key)))?;
+            stored.insert(key_text(key), document);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, index: &Index, keys: Vec<Value>) -> Result<(), SearchError> {
+        if let Some(stored) = self.indexes.lock().unwrap().get_mut(&index.name) {
+            for key in keys {
+                stored.remove(&key_text(&key));
+            }
+        }
+        Ok(())
+    }
+
+    async fn search(&self, index: &Index, query: &SearchQuery) -> Result<SearchResults, SearchError> {
+        let indexes = self.indexes.lock().unwrap();
+        let Some(stored) = indexes.get(&index.name) else {
+            return Ok(SearchResults::default());
+        };
+        let terms: Vec<String> = query.query.split_whitespace().map(str::to_lowercase).collect();
+
+        let mut hits: Vec<(usize, &Document)> = stored
+            .values()
+            .filter(|document| query.filters.iter().all(|filter| matches_filter(document, filter)))
+            .filter_map(|document| {
+                let text = document
+                    .values()
+                    .filter_map(|value| match value {
+                        Value::String(text) => Some(text.to_lowercase()),
+                        Value::Number(number) => Some(number.to_string()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let mut score = 0;
+                for term in &terms {
+                    match text.matches(term.as_str()).count() {
+                        0 => return None,
+                        count => score += count,
+                    }
+                }
+                Some((score, document))
+            })
+            .collect();
+        if query.sort.is_empty() {
+            hits.sort_by_key(|hit| std::cmp::Reverse(hit.0));
+        } else {
+            hits.sort_by(|a, b| compare_documents(a.1, b.1, &query.sort));
+        }
+
+        Ok(SearchResults {
+            total: hits.len() as u64,
+            keys: hits
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit)
+                .filter_map(|(_, document)| document.get(&index.primary_key).cloned())
+                .collect(),
+        })
+    }
+
+    async fn flush(&self, index: &Index) -> Result<(), SearchError> {
+        self.indexes.lock().unwrap().remove(&index.name);
+        Ok(())
+    }
+
+    async fn configure(&self, index: &Index) -> Result<(), SearchError> {
+        self.indexes.lock().unwrap().entry(index.name.clone()).or_default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::ModelState;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Article {
+        id: Option<i64>,
+        title: String,
+        author_id: i64,
+        published: bool,
+        secret: String,
+    }
+
+    impl<'r> sqlx::FromRow<'r, sqlx::any::AnyRow> for Article {
+        fn from_row(row: &'r sqlx::any::AnyRow) -> std::result::Result<Self, sqlx::Error> {
+            use sqlx::Row;
+            Ok(Self {
+                id: row.try_get("id")?,
+                title: row.try_get("title")?,
+                author_id: row.try_get("author_id")?,
+                published: row.try_get("published")?,
+                secret: row.try_get("secret")?,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Model for Article {
+        type PrimaryKey = i64;
+
+        fn table_name() -> &'static str {
+            "articles"
+        }
+
+        fn id(&self) -> Option<i64> {
+            self.id
+        }
+
+        fn set_id(&mut self, id: i64) {
+            self.id = Some(id);
+        }
+
+        fn state(&self) -> ModelState {
+            ModelState::Persisted
+        }
+
+        fn set_state(&mut self, _state: ModelState) {}
+
+        fn hidden() -> &'static [&'static str] {
+            &["secret"]
+        }
+
+        async fn create_in_database(&mut self) -> crate::orm::Result<()> {
+            Ok(())
+        }
+
+        async fn update_in_database(&mut self) -> crate::orm::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Searchable for Article {
+        fn searchable_as() -> String {
+            "search_test_articles".to_string()
+        }
+
+        fn should_be_searchable(&self) -> bool {
+            self.published
+        }
+
+        fn filterable_attributes() -> &'static [&'static str] {
+            &["author_id"]
+        }
+    }
+
+    fn article(id: i64, title: &str, author_id: i64, published: bool) -> Article {
+        Article { id: Some(id), title: title.to_string(), author_id, published, secret: "s3cret".to_string() }
+    }
+
+    /// Tests that set up the global engine and registered models take turns,
+    /// each starting from a clean slate
+    async fn isolated() -> tokio::sync::MutexGuard<'static, ()> {
+        static SEARCH_STATE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+        let guard = SEARCH_STATE.lock().await;
+        Search::reset();
+        guard
+    }
+
+    #[tokio::test]
+    async fn test_searchable_models_sync_and_query_the_engine() {
+        let _state = isolated().await;
+        let index = Search::index::<Article>();
+        assert_eq!(index.name, "search_test_articles");
+        assert_eq!(index.filterable, vec!["author_id"]);
+        assert!(!article(1, "Rust", 1, true).to_search_document().unwrap().contains_key("secret"));
+
+        // Saves aren't indexed until the model is registered
+        article(1, "Async Rust in practice", 1, true).save().await.unwrap();
+        assert_eq!(Article::search("").raw().await.unwrap().total, 0);
+
+        Search::register::<Article>();
+        for mut model in [
+            article(1, "Async Rust in practice", 1, true),
+            article(2, "Rust ownership, rust borrowing", 2, true),
+            article(3, "Gardening", 1, true),
+            article(4, "Rust drafts", 1, false),
+        ] {
+            model.save().await.unwrap();
+        }
+
+        let results = Article::search("rust").raw().await.unwrap();
+        assert_eq!(results, SearchResults { keys: vec![json!(2), json!(1)], total: 2 });
+
+        let results = Article::search("RUST").where_eq("author_id", 1).raw().await.unwrap();
+        assert_eq!(results.keys, vec![json!(1)]);
+        let results = Article::search("").where_in("author_id", vec![1, 2]).order_by_desc("id").take(2).raw().await.unwrap();
+        assert_eq!(results, SearchResults { keys: vec![json!(3), json!(2)], total: 3 });
+        let results = Article::search("").where_gte("id", 2).where_lte("id", 2).raw().await.unwrap();
+        assert_eq!(results.keys, vec![json!(2)]);
+
+        // Unpublishing or deleting takes a model out of the index
+        article(2, "Rust ownership", 2, false).save().await.unwrap();
+        deleted(&article(3, "Gardening", 1, true)).await;
+        assert_eq!(Article::search("").raw().await.unwrap().keys, vec![json!(1)]);
+
+        Article::remove_all_from_search().await.unwrap();
+        assert_eq!(Article::search("").raw().await.unwrap().total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reset_forgets_the_engine_and_registered_models() {
+        let _state = isolated().await;
+        Search::set_prefix("test_");
+        Search::register::<Article>();
+        article(1, "Rust", 1, true).save().await.unwrap();
+        assert_eq!(Search::index::<Article>().name, "test_search_test_articles");
+        assert_eq!(Article::search("").raw().await.unwrap().total, 1);
+
+        Search::reset();
+        assert_eq!(Search::index::<Article>().name, "search_test_articles");
+        article(2, "Rust", 1, true).save().await.unwrap();
+        assert_eq!(Article::search("").raw().await.unwrap().total, 0);
+    }
+}
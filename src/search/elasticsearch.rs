@@ -0,0 +1,227 @@
+//! [Elasticsearch](https://www.elastic.co/elasticsearch) driver, which also
+//! works with OpenSearch
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use http::Method;
+use serde_json::{json, Value};
+
+use super::http::Client;
+use super::{key_text, Document, Filter, Index, SearchEngine, SearchError, SearchQuery, SearchResults};
+
+/// Documents kept in an Elasticsearch cluster.
+///
+/// Writes become searchable at the next index refresh, about a second by
+/// default. Run [`configure`](SearchEngine::configure) before indexing so
+/// text attributes that are filtered or sorted on are mapped as keywords.
+#[derive(Debug, Clone)]
+pub struct Elasticsearch {
+    client: Client,
+}
+
+impl Elasticsearch {
+    /// A cluster at `url`, e.g. `http://localhost:9200`
+    pub fn new(url: &str) -> Self {
+        Self { client: Client::new(url) }
+    }
+
+    /// Sign in with an encoded API key
+    pub fn api_key(mut self, key: &str) -> Self {
+        self.client = self.client.authorization(format!("ApiKey {}", key));
+        self
+    }
+
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials = general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        self.client = self.client.authorization(format!("Basic {}", credentials));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.timeout(timeout);
+        self
+    }
+
+    async fn bulk(&self, lines: Vec<Value>) -> Result<(), SearchError> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let mut body = String::new();
+        for line in lines {
+            body.push_str(&line.to_string());
+            body.push('\n');
+        }
+        let answer = self.client.send(Method::POST, "/_bulk", "application/x-ndjson", body.into_bytes()).await?;
+        if answer["errors"] == true {
+            let error = answer["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|item| item.as_object()?.values().next()?.get("error"))
+                .next()
+                .cloned()
+                .unwrap_or_default();
+            return Err(SearchError::Engine(format!("bulk request failed: {}", error)));
+        }
+        Ok(())
+    }
+}
+
+/// The body of a `POST /{index}/_search` request
+pub(crate) fn search_body(index: &Index, query: &SearchQuery) -> Value {
+    let must = if query.query.trim().is_empty() {
+        json!({ "match_all": {} })
+    } else {
+        json!({ "multi_match": { "query": query.query, "fields": ["*"], "operator": "and" } })
+    };
+    let filter: Vec<Value> = query
+        .filters
+        .iter()
+        .map(|filter| match filter {
+            Filter::Equals(field, value) => json!({ "term": { field: value } }),
+            Filter::In(field, values) => json!({ "terms": { field: values } }),
+            Filter::Gte(field, value) => json!({ "range": { field: { "gte": value } } }),
+            Filter::Lte(field, value) => json!({ "range": { field: { "lte": value } } }),
+        })
+        .collect();
+    let sort: Vec<Value> = query
+        .sort
+        .iter()
+        .map(|sort| json!({ &sort.field: { "order": if sort.descending { "desc" } else { "asc" } } }))
+        .collect();
+    let mut body = json!({
+        "query": { "bool": { "must": must, "filter": filter } },
+        "from": query.offset,
+        "size": query.limit,
+        "track_total_hits": true,
+        "_source": [index.primary_key],
+    });
+    if !sort.is_empty() {
+        body["sort"] = sort.into();
+    }
+    body
+}
+
+#[async_trait]
+impl SearchEngine for Elasticsearch {
+    async fn update(&self, index: &Index, documents: Vec<Document>) -> Result<(), SearchError> {
+        let mut lines = Vec::with_capacity(documents.len() * 2);
+        for document in documents {
+            let key = document
+                .get(&index.primary_key)
+                .ok_or_else(|| SearchError::Engine(format!("document has no `{}`", index.primary_key)))?;
+            lines.push(json!({ "index": { "_index": index.name, "_id": key_text(key) } }));
+            lines.push(Value::Object(document));
+        }
+        self.bulk(lines).await
+    }
+
+    async fn delete(&self, index: &Index, keys: Vec<Value>) -> Result<(), SearchError> {
+        let lines = keys
+            .iter()
+            .map(|key| json!({ "delete": { "_index": index.name, "_id": key_text(key) } }))
+            .collect();
+        self.bulk(lines).await
+    }
+
+    async fn search(&self, index: &Index, query: &SearchQuery) -> Result<SearchResults, SearchError> {
+        let path = format!("/{}/_search", index.name);
+        let answer = match self.client.json(Method::POST, &path, Some(&search_body(index, query))).await {
+            // Nothing has been indexed yet
+            Err(SearchError::Rejected { status: 404, .. }) => return Ok(SearchResults::default()),
+            answer => answer?,
+        };
+        let hits = &answer["hits"];
+        let keys = hits["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .map(|hit| match &hit["_source"][&index.primary_key] {
+                        Value::Null => hit["_id"].clone(),
+                        key => key.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(SearchResults {
+            keys,
+            total: hits["total"]["value"].as_u64().unwrap_or_default(),
+        })
+    }
+
+    async fn flush(&self, index: &Index) -> Result<(), SearchError> {
+        let path = format!("/{}/_delete_by_query?conflicts=proceed", index.name);
+        let body = json!({ "query": { "match_all": {} } });
+        match self.client.json(Method::POST, &path, Some(&body)).await {
+            Err(SearchError::Rejected { status: 404, .. }) => Ok(()),
+            answer => answer.map(|_| ()),
+        }
+    }
+
+    async fn configure(&self, index: &Index) -> Result<(), SearchError> {
+        let mut keywords: Vec<&String> = index.filterable.iter().chain(&index.sortable).collect();
+        keywords.sort();
+        keywords.dedup();
+        let templates: Vec<Value> = keywords
+            .iter()
+            .map(|field| {
+                json!({ format!("torch_keyword_{}", field): {
+                    "match": field,
+                    "match_mapping_type": "string",
+                    "mapping": { "type": "keyword" },
+                } })
+            })
+            .collect();
+        let body = json!({ "mappings": { "dynamic_templates": templates } });
+        match self.client.json(Method::PUT, &format!("/{}", index.name), Some(&body)).await {
+            Err(SearchError::Rejected { body, .. }) if body["error"]["type"] == "resource_already_exists_exception" => Ok(()),
+            answer => answer.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Sort;
+
+    #[test]
+    fn test_elasticsearch_search_body() {
+        let index = Index {
+            name: "posts".to_string(),
+            primary_key: "id".to_string(),
+            filterable: vec![],
+            sortable: vec![],
+        };
+        let mut query = SearchQuery::new("rust async");
+        query.filters = vec![
+            Filter::Equals("status".into(), json!("published")),
+            Filter::In("author_id".into(), vec![json!(1), json!(2)]),
+            Filter::Lte("views".into(), json!(10)),
+        ];
+        query.sort = vec![Sort { field: "created_at".into(), descending: false }];
+        query.offset = 40;
+
+        assert_eq!(
+            search_body(&index, &query),
+            json!({
+                "query": { "bool": {
+                    "must": { "multi_match": { "query": "rust async", "fields": ["*"], "operator": "and" } },
+                    "filter": [
+                        { "term": { "status": "published" } },
+                        { "terms": { "author_id": [1, 2] } },
+                        { "range": { "views": { "lte": 10 } } },
+                    ],
+                } },
+                "from": 40,
+                "size": 20,
+                "track_total_hits": true,
+                "_source": ["id"],
+                "sort": [{ "created_at": { "order": "asc" } }],
+            })
+        );
+        assert_eq!(search_body(&index, &SearchQuery::new(" "))["query"]["bool"]["must"], json!({ "match_all": {} }));
+    }
+}
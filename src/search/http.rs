@@ -0,0 +1,77 @@
+//! The JSON requests search drivers make, through the shared
+//! [`http_client`](crate::http_client)
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::SearchError;
+
+/// Where an engine is and how to sign in to it
+#[derive(Debug, Clone)]
+pub(crate) struct Client {
+    base: String,
+    authorization: Option<String>,
+    timeout: Duration,
+}
+
+impl Client {
+    pub fn new(url: &str) -> Self {
+        Self {
+            base: url.trim_end_matches('/').to_string(),
+            authorization: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn authorization(mut self, value: impl Into<String>) -> Self {
+        self.authorization = Some(value.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send `body` as JSON, returning the JSON answer
+    pub async fn json(&self, method: http::Method, path: &str, body: Option<&Value>) -> Result<Value, SearchError> {
+        let body = body.map(|body| body.to_string().into_bytes());
+        self.send(method, path, "application/json", body.unwrap_or_default()).await
+    }
+
+    /// Send a raw body of `content_type`, returning the JSON answer
+    pub async fn send(&self, method: http::Method, path: &str, content_type: &str, body: Vec<u8>) -> Result<Value, SearchError> {
+        let url = format!("{}{}", self.base, path);
+        let (status, answer) = tokio::time::timeout(self.timeout, self.request(method.clone(), &url, content_type, body))
+            .await
+            .map_err(|_| SearchError::Engine(format!("{} {} timed out", method, url)))?
+            .map_err(|error| SearchError::Engine(format!("{} {}: {}", method, url, error)))?;
+        let value = if answer.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&answer).map_err(|error| SearchError::Engine(format!("{} {}: {}", method, url, error)))?
+        };
+        if !status.is_success() {
+            return Err(SearchError::Rejected { status: status.as_u16(), body: value });
+        }
+        Ok(value)
+    }
+
+    async fn request(
+        &self,
+        method: http::Method,
+        url: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(http::StatusCode, Vec<u8>), crate::http_client::BoxError> {
+        let mut request = http::Request::builder()
+            .method(method)
+            .header(http::header::ACCEPT, "application/json")
+            .header(http::header::CONTENT_TYPE, content_type);
+        if let Some(authorization) = &self.authorization {
+            request = request.header(http::header::AUTHORIZATION, authorization);
+        }
+        crate::http_client::send(url, request, body).await
+    }
+}
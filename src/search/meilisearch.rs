@@ -0,0 +1,167 @@
+//! [Meilisearch](https://www.meilisearch.com) driver
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use http::Method;
+use serde_json::{json, Value};
+
+use super::http::Client;
+use super::{Document, Filter, Index, SearchEngine, SearchError, SearchQuery, SearchResults};
+
+/// Documents kept in a Meilisearch server.
+///
+/// Meilisearch applies writes in the background, so a document may take a
+/// moment to show up in results after it was indexed.
+#[derive(Debug, Clone)]
+pub struct Meilisearch {
+    client: Client,
+}
+
+impl Meilisearch {
+    /// A server at `url`, e.g. `http://localhost:7700`
+    pub fn new(url: &str) -> Self {
+        Self { client: Client::new(url) }
+    }
+
+    /// Send `key` as a bearer token, needed when the server has a master key
+    pub fn api_key(mut self, key: &str) -> Self {
+        self.client = self.client.authorization(format!("Bearer {}", key));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.timeout(timeout);
+        self
+    }
+}
+
+/// The `filter` expression for `filters`, or `None` when there are none
+pub(crate) fn filter_expression(filters: &[Filter]) -> Option<String> {
+    let parts: Vec<String> = filters
+        .iter()
+        .map(|filter| match filter {
+            Filter::Equals(field, value) => format!("{} = {}", field, value),
+            Filter::In(field, values) => format!("{} IN {}", field, Value::from(values.clone())),
+            Filter::Gte(field, value) => format!("{} >= {}", field, value),
+            Filter::Lte(field, value) => format!("{} <= {}", field, value),
+        })
+        .collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" AND "))
+    }
+}
+
+/// The body of a `POST /indexes/{uid}/search` request
+pub(crate) fn search_body(index: &Index, query: &SearchQuery) -> Value {
+    let mut body = json!({
+        "q": query.query,
+        "offset": query.offset,
+        "limit": query.limit,
+        "attributesToRetrieve": [index.primary_key],
+    });
+    if let Some(filter) = filter_expression(&query.filters) {
+        body["filter"] = filter.into();
+    }
+    if !query.sort.is_empty() {
+        let sort: Vec<String> = query
+            .sort
+            .iter()
+            .map(|sort| format!("{}:{}", sort.field, if sort.descending { "desc" } else { "asc" }))
+            .collect();
+        body["sort"] = sort.into();
+    }
+    body
+}
+
+#[async_trait]
+impl SearchEngine for Meilisearch {
+    async fn update(&self, index: &Index, documents: Vec<Document>) -> Result<(), SearchError> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        let path = format!("/indexes/{}/documents?primaryKey={}", index.name, index.primary_key);
+        self.client.json(Method::POST, &path, Some(&Value::from(documents))).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, index: &Index, keys: Vec<Value>) -> Result<(), SearchError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let path = format!("/indexes/{}/documents/delete-batch", index.name);
+        self.client.json(Method::POST, &path, Some(&Value::from(keys))).await?;
+        Ok(())
+    }
+
+    async fn search(&self, index: &Index, query: &SearchQuery) -> Result<SearchResults, SearchError> {
+        let path = format!("/indexes/{}/search", index.name);
+        let answer = match self.client.json(Method::POST, &path, Some(&search_body(index, query))).await {
+            // Nothing has been indexed yet
+            Err(SearchError::Rejected { body, .. }) if body["code"] == "index_not_found" => return Ok(SearchResults::default()),
+            answer => answer?,
+        };
+        let keys = answer["hits"]
+            .as_array()
+            .map(|hits| hits.iter().map(|hit| hit[&index.primary_key].clone()).filter(|key| !key.is_null()).collect())
+            .unwrap_or_default();
+        Ok(SearchResults {
+            keys,
+            total: answer["estimatedTotalHits"].as_u64().or_else(|| answer["totalHits"].as_u64()).unwrap_or_default(),
+        })
+    }
+
+    async fn flush(&self, index: &Index) -> Result<(), SearchError> {
+        self.client.json(Method::DELETE, &format!("/indexes/{}/documents", index.name), None).await?;
+        Ok(())
+    }
+
+    async fn configure(&self, index: &Index) -> Result<(), SearchError> {
+        let create = json!({ "uid": index.name, "primaryKey": index.primary_key });
+        self.client.json(Method::POST, "/indexes", Some(&create)).await?;
+        let settings = json!({
+            "filterableAttributes": index.filterable,
+            "sortableAttributes": index.sortable,
+        });
+        self.client.json(Method::PATCH, &format!("/indexes/{}/settings", index.name), Some(&settings)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Sort;
+
+    #[test]
+    fn test_meilisearch_search_body() {
+        let index = Index {
+            name: "posts".to_string(),
+            primary_key: "id".to_string(),
+            filterable: vec![],
+            sortable: vec![],
+        };
+        let mut query = SearchQuery::new("rust");
+        query.filters = vec![
+            Filter::Equals("status".into(), json!("say \"hi\"")),
+            Filter::In("author_id".into(), vec![json!(1), json!(2)]),
+            Filter::Gte("views".into(), json!(10)),
+        ];
+        query.sort = vec![Sort { field: "created_at".into(), descending: true }];
+
+        assert_eq!(
+            search_body(&index, &query),
+            json!({
+                "q": "rust",
+                "offset": 0,
+                "limit": 20,
+                "attributesToRetrieve": ["id"],
+                "filter": "status = \"say \\\"hi\\\"\" AND author_id IN [1,2] AND views >= 10",
+                "sort": ["created_at:desc"],
+            })
+        );
+        assert_eq!(filter_expression(&[]), None);
+    }
+}
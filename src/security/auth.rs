@@ -1,6 +1,11 @@
 //! # Authentication and Authorization
 //!
-//! This module provides secure password hashing and authentication.
+//! This module provides secure password hashing, authentication and login
+//! throttling.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::security::{PasswordComplexity, SecurityError, SecurityResult};
 use crate::security::encryption::{generate_salt, hash_with_salt, verify_salted_hash};
@@ -55,3 +60,259 @@ pub fn validate_password_strength(password: &str, config: &PasswordComplexity) -
     
     Ok(())
 }
+
+/// Raised when an IP address or an account gets locked out
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockoutEvent {
+    /// `ip:<address>` or `account:<identifier>`
+    pub key: String,
+    /// Failed attempts counted against the key
+    pub failures: u32,
+    pub locked_for: Duration,
+}
+
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+type LockoutListener = Arc<dyn Fn(&LockoutEvent) + Send + Sync>;
+
+/// Login throttle with exponential backoff per IP address and per account.
+///
+/// Failed attempts are counted separately for the client's IP address and for
+/// the account being logged into. Once either passes its limit it is locked
+/// out, and every further failure doubles the lockout, up to a maximum.
+/// Failures are forgotten some time after the last one, and a successful
+/// login clears the account's count. The IP count is only forgotten over
+/// time, so logging into an account you own doesn't reset it.
+///
+/// Pass `None` for the IP address when the client's isn't known, so only the
+/// account is counted; a placeholder like `"unknown"` would lock out every
+/// such client at once. Behind a reverse proxy [`Request::remote_addr`] is
+/// the proxy's address: pass the client address the proxy forwards instead,
+/// taken only from connections coming from proxies you trust.
+///
+/// Clones share their counts.
+///
+/// [`Request::remote_addr`]: crate::Request::remote_addr
+///
+/// ```rust
+/// use std::time::Duration;
+/// use torch_web::security::auth::LoginThrottle;
+///
+/// let throttle = LoginThrottle::new()
+///     .max_attempts_per_account(3)
+///     .lockout(Duration::from_secs(60), Duration::from_secs(3600))
+///     .on_lockout(|event| eprintln!("locked out {} for {:?}", event.key, event.locked_for));
+///
+/// for _ in 0..3 {
+///     assert!(throttle.check("203.0.113.9", "ada@example.com").is_ok());
+///     throttle.failed("203.0.113.9", "ada@example.com");
+/// }
+///
+/// let retry_after = throttle.check("203.0.113.9", "Ada@Example.com").unwrap_err();
+/// assert!(retry_after <= Duration::from_secs(60));
+/// ```
+#[derive(Clone)]
+pub struct LoginThrottle {
+    attempts: Arc<Mutex<HashMap<String, Attempts>>>,
+    max_per_account: u32,
+    max_per_ip: u32,
+    base_lockout: Duration,
+    max_lockout: Duration,
+    forget_after: Duration,
+    listeners: Vec<LockoutListener>,
+}
+
+impl LoginThrottle {
+    /// Entries kept before forgotten ones are swept out
+    const SWEEP_THRESHOLD: usize = 1024;
+
+    /// 5 attempts per account and 20 per IP address, then lockouts from
+    /// 1 minute up to 1 hour; failures are forgotten after an hour
+    pub fn new() -> Self {
+        Self {
+            attempts: Arc::new(Mutex::new(HashMap::new())),
+            max_per_account: 5,
+            max_per_ip: 20,
+            base_lockout: Duration::from_secs(60),
+            max_lockout: Duration::from_secs(3600),
+            forget_after: Duration::from_secs(3600),
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn max_attempts_per_account(mut self, attempts: u32) -> Self {
+        self.max_per_account = attempts.max(1);
+        self
+    }
+
+    pub fn max_attempts_per_ip(mut self, attempts: u32) -> Self {
+        self.max_per_ip = attempts.max(1);
+        self
+    }
+
+    /// First lockout duration, doubled on every further failure up to `max`
+    pub fn lockout(mut self, base: Duration, max: Duration) -> Self {
+        self.base_lockout = base;
+        self.max_lockout = max.max(base);
+        self
+    }
+
+    /// How long after the last failure the count starts over
+    pub fn forget_after(mut self, duration: Duration) -> Self {
+        self.forget_after = duration;
+        self
+    }
+
+    /// Call `listener` whenever an IP address or account gets locked out
+    pub fn on_lockout<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(&LockoutEvent) + Send + Sync + 'static,
+    {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// `Err` with the time left when the IP address or the account is locked out
+    pub fn check<'a>(&self, ip: impl Into<Option<&'a str>>, account: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let attempts = self.attempts.lock().unwrap();
+        let remaining = ip
+            .into()
+            .map(ip_key)
+            .into_iter()
+            .chain([account_key(account)])
+            .filter_map(|key| attempts.get(&key)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max();
+        remaining.map_or(Ok(()), Err)
+    }
+
+    /// Record a failed login, returning the lockouts it caused
+    pub fn failed<'a>(&self, ip: impl Into<Option<&'a str>>, account: &str) -> Vec<LockoutEvent> {
+        let ip = ip.into();
+        let now = Instant::now();
+        let mut events = Vec::new();
+        {
+            let mut attempts = self.attempts.lock().unwrap();
+            if attempts.len() > Self::SWEEP_THRESHOLD {
+                attempts.retain(|_, entry| now.duration_since(entry.last_failure) < self.forget_after);
+            }
+
+            let ip = ip.map(|ip| (ip_key(ip), self.max_per_ip));
+            for (key, limit) in ip.into_iter().chain([(account_key(account), self.max_per_account)]) {
+                let entry = attempts.entry(key.clone()).or_insert(Attempts {
+                    failures: 0,
+                    last_failure: now,
+                    locked_until: None,
+                });
+                if now.duration_since(entry.last_failure) >= self.forget_after {
+                    entry.failures = 0;
+                    entry.locked_until = None;
+                }
+                entry.failures += 1;
+                entry.last_failure = now;
+
+                if entry.failures >= limit {
+                    let doublings = (entry.failures - limit).min(31);
+                    let locked_for = self.base_lockout.saturating_mul(1 << doublings).min(self.max_lockout);
+                    entry.locked_until = Some(now + locked_for);
+                    events.push(LockoutEvent {
+                        key,
+                        failures: entry.failures,
+                        locked_for,
+                    });
+                }
+            }
+        }
+
+        for event in &events {
+            eprintln!("🔒 Login lockout for {} after {} failed attempts ({}s)", event.key, event.failures, event.locked_for.as_secs());
+            for listener in &self.listeners {
+                listener(event);
+            }
+        }
+        events
+    }
+
+    /// Record a successful login, clearing the account's failures
+    pub fn succeeded(&self, account: &str) {
+        self.attempts.lock().unwrap().remove(&account_key(account));
+    }
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+fn account_key(account: &str) -> String {
+    format!("account:{}", account.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_throttle_backoff() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let throttle = LoginThrottle::new()
+            .max_attempts_per_account(2)
+            .max_attempts_per_ip(4)
+            .lockout(Duration::from_secs(10), Duration::from_secs(25))
+            .on_lockout(move |event| seen.lock().unwrap().push((event.key.clone(), event.locked_for.as_secs())));
+
+        throttle.failed("10.0.0.1", "ada");
+        assert!(throttle.check("10.0.0.1", "ada").is_ok());
+        throttle.failed("10.0.0.1", "ada");
+        assert!(throttle.check("10.0.0.2", " ADA ").is_err());
+        assert!(throttle.check("10.0.0.1", "grace").is_ok());
+
+        // Further failures double the lockout up to the maximum
+        throttle.failed("10.0.0.1", "ada");
+        throttle.failed("10.0.0.1", "ada");
+        // The IP address is now locked for every account
+        assert!(throttle.check("10.0.0.1", "grace").is_err());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("account:ada".to_string(), 10),
+                ("account:ada".to_string(), 20),
+                ("ip:10.0.0.1".to_string(), 10),
+                ("account:ada".to_string(), 25),
+            ]
+        );
+
+        throttle.succeeded("ada");
+        assert!(throttle.check("10.0.0.2", "ada").is_ok());
+        assert!(throttle.check("10.0.0.1", "ada").is_err());
+    }
+
+    #[test]
+    fn test_clients_without_an_address_share_no_ip_count() {
+        let throttle = LoginThrottle::new().max_attempts_per_account(2).max_attempts_per_ip(2);
+
+        throttle.failed(None, "ada");
+        throttle.failed(None, "grace");
+        throttle.failed(None, "linus");
+        assert!(throttle.check(None, "margaret").is_ok());
+        assert!(throttle.check("10.0.0.1", "margaret").is_ok());
+
+        // Accounts are still counted
+        throttle.failed(None, "ada");
+        assert!(throttle.check(None, "ada").is_err());
+        assert!(throttle.check("10.0.0.1", "ada").is_err());
+    }
+}
+
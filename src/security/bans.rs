@@ -0,0 +1,515 @@
+//! # Ban List
+//!
+//! [`IpGuard`] turns away clients on a deny list, caps how many requests
+//! one address may have in flight, and bans addresses that keep getting
+//! `4xx` responses (failed logins, scanners probing for `/wp-admin`, clients
+//! ignoring `429`s):
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use torch_web::App;
+//! use torch_web::security::bans::{BanList, IpGuard};
+//!
+//! let bans = BanList::new();
+//!
+//! let app = App::new()
+//!     .middleware(
+//!         IpGuard::new(bans.clone())
+//!             .max_in_flight(20)
+//!             .auto_ban(30, Duration::from_secs(60), Duration::from_secs(3600)),
+//!     )
+//!     .ban_admin("/admin/bans", bans, |req| req.header("authorization") == Some("Bearer admin-token"));
+//! ```
+//!
+//! Bans match single addresses or CIDR ranges such as `203.0.113.0/24` and
+//! `2001:db8::/32`, and lift themselves when their TTL runs out. Each ban is
+//! its own key in the cache given to [`BanList::with_cache`], so every
+//! instance behind a load balancer sees a ban within
+//! [`BanList::cache_for`] of one of them making it; the in-flight counts
+//! are per process.
+//!
+//! The admin API mounted by [`App::ban_admin`](crate::App::ban_admin) lists
+//! bans with `GET`, adds one with `POST {"net": "...", "ttl_secs": 3600,
+//! "reason": "..."}` and lifts one with `DELETE ?net=...`, for requests its
+//! `authorize` callback accepts.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::cache::{Cache, MemoryCache};
+use crate::clock::unix_now_ms;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+/// Prefix of the cache keys used for bans and strikes
+const BANS_KEY: &str = "torch:bans";
+
+/// An address or CIDR range, e.g. `198.51.100.7` or `10.0.0.0/8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// Whether `ip` is inside this range. IPv4 ranges also match
+    /// IPv4-mapped IPv6 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(_) => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_matches(&net.octets(), &ip.octets(), self.prefix),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_matches(&net.octets(), &ip.octets(), self.prefix),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let (bytes, bits) = ((prefix / 8) as usize, prefix % 8);
+    if net[..bytes] != ip[..bytes] {
+        return false;
+    }
+    bits == 0 || {
+        let mask = 0xffu8 << (8 - bits);
+        net[bytes] & mask == ip[bytes] & mask
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let net = IpNet::from(addr.parse::<IpAddr>().map_err(|_| format!("invalid address '{}'", value))?);
+        let Some(prefix) = prefix else {
+            return Ok(net);
+        };
+        match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= net.prefix => Ok(Self { prefix, ..net }),
+            _ => Err(format!("invalid prefix in '{}'", value)),
+        }
+    }
+}
+
+impl From<IpAddr> for IpNet {
+    fn from(addr: IpAddr) -> Self {
+        Self { addr, prefix: if addr.is_ipv4() { 32 } else { 128 } }
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.addr, self.prefix) {
+            (IpAddr::V4(_), 32) | (IpAddr::V6(_), 128) => write!(f, "{}", self.addr),
+            (addr, prefix) => write!(f, "{}/{}", addr, prefix),
+        }
+    }
+}
+
+/// One entry of the [`BanList`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    /// The banned address or range, as written by [`IpNet`]'s `Display`
+    pub net: String,
+    pub reason: String,
+    /// Whether [`IpGuard::auto_ban`] made it
+    pub automatic: bool,
+    /// Unix time in milliseconds
+    pub banned_at: u64,
+    /// Unix time in milliseconds; `None` bans until lifted
+    pub expires_at: Option<u64>,
+}
+
+impl Ban {
+    fn active(&self, now: u64) -> bool {
+        self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+}
+
+/// The bans as last read, with when they were read
+type Snapshot = (Instant, Arc<Vec<(IpNet, Ban)>>);
+
+/// The deny list, kept in a [`Cache`] so processes sharing the cache share
+/// the bans
+#[derive(Clone)]
+pub struct BanList {
+    cache: Arc<dyn Cache>,
+    cache_for: Duration,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        Self::with_cache(Arc::new(MemoryCache::new(None)))
+    }
+}
+
+impl BanList {
+    /// A list kept in process memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A list kept in `cache`, e.g. a `RedisCache` shared by every instance.
+    /// The cache has to be able to [list its keys](Cache::keys).
+    pub fn with_cache(cache: Arc<dyn Cache>) -> Self {
+        Self {
+            cache,
+            cache_for: Duration::from_secs(5),
+            snapshot: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// How long [`IpGuard`] keeps checking requests against the bans it last
+    /// read before reading them again, 5 seconds by default. Bans made or
+    /// lifted through this list apply at once in this process.
+    pub fn cache_for(mut self, duration: Duration) -> Self {
+        self.cache_for = duration;
+        self
+    }
+
+    fn key(net: &str) -> String {
+        format!("{}:net:{}", BANS_KEY, net)
+    }
+
+    async fn load(&self) -> Vec<Ban> {
+        let prefix = Self::key("");
+        let keys = match self.cache.keys(&prefix).await {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("⚠️  Could not read the ban list: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let now = unix_now_ms();
+        let mut bans = Vec::new();
+        for key in keys {
+            let ban = self.cache.get(&key).await.and_then(|ban| serde_json::from_str::<Ban>(&ban).ok());
+            bans.extend(ban.filter(|ban| ban.active(now)));
+        }
+        bans.sort_by(|a, b| a.banned_at.cmp(&b.banned_at).then_with(|| a.net.cmp(&b.net)));
+        bans
+    }
+
+    /// The bans with their parsed ranges, read again once the last read is
+    /// older than [`cache_for`](Self::cache_for)
+    async fn snapshot(&self) -> Arc<Vec<(IpNet, Ban)>> {
+        if let Some((read_at, bans)) = self.snapshot.lock().unwrap().as_ref() {
+            if read_at.elapsed() < self.cache_for {
+                return bans.clone();
+            }
+        }
+        let bans: Vec<_> = self.load().await.into_iter().filter_map(|ban| Some((ban.net.parse().ok()?, ban))).collect();
+        let bans = Arc::new(bans);
+        *self.snapshot.lock().unwrap() = Some((Instant::now(), bans.clone()));
+        bans
+    }
+
+    /// Ban `net`, an address or CIDR range, for `ttl` or until lifted,
+    /// replacing any ban on the same range
+    pub async fn ban(&self, net: &str, ttl: Option<Duration>, reason: &str) -> Result<Ban, String> {
+        self.add(net.parse()?, ttl, reason, false).await
+    }
+
+    async fn add(&self, net: IpNet, ttl: Option<Duration>, reason: &str, automatic: bool) -> Result<Ban, String> {
+        let now = unix_now_ms();
+        let ban = Ban {
+            net: net.to_string(),
+            reason: reason.to_string(),
+            automatic,
+            banned_at: now,
+            expires_at: ttl.map(|ttl| now + ttl.as_millis() as u64),
+        };
+        let json = serde_json::to_string(&ban).map_err(|e| e.to_string())?;
+        // Whole seconds, so caches that expire by the second keep it long enough
+        let expiry = ttl.map(|ttl| Duration::from_secs(ttl.as_secs() + 1));
+        self.cache.set(&Self::key(&ban.net), &json, expiry).await.map_err(|e| e.to_string())?;
+        self.snapshot.lock().unwrap().take();
+        Ok(ban)
+    }
+
+    /// Lift the ban on `net`, returning whether there was one
+    pub async fn unban(&self, net: &str) -> Result<bool, String> {
+        let key = Self::key(&net.parse::<IpNet>()?.to_string());
+        let banned = self
+            .cache
+            .get(&key)
+            .await
+            .and_then(|ban| serde_json::from_str::<Ban>(&ban).ok())
+            .is_some_and(|ban| ban.active(unix_now_ms()));
+        self.cache.delete(&key).await.map_err(|e| e.to_string())?;
+        self.snapshot.lock().unwrap().take();
+        Ok(banned)
+    }
+
+    /// Bans in force, oldest first
+    pub async fn bans(&self) -> Vec<Ban> {
+        self.load().await
+    }
+
+    /// The ban covering `ip`, if any
+    pub async fn banned(&self, ip: IpAddr) -> Option<Ban> {
+        let now = unix_now_ms();
+        self.snapshot().await.iter().find(|(net, ban)| ban.active(now) && net.contains(ip)).map(|(_, ban)| ban.clone())
+    }
+
+    /// Count a strike against `ip`, returning the strikes in the current
+    /// window of `within`
+    async fn strike(&self, ip: IpAddr, within: Duration) -> u32 {
+        let window = within.as_millis().max(1) as u64;
+        let now = unix_now_ms();
+        let key = format!("{}:strikes:{}:{}", BANS_KEY, ip, now / window);
+        match self.cache.increment(&key, Some(Duration::from_millis(window - now % window))).await {
+            Ok(strikes) => strikes.clamp(0, u32::MAX as i64) as u32,
+            Err(e) => {
+                eprintln!("⚠️  Could not count a strike against {}: {}", ip, e);
+                0
+            }
+        }
+    }
+
+    /// `GET`, `POST` and `DELETE` on the admin route
+    pub(crate) async fn serve_admin(self, req: Request) -> Response {
+        #[derive(Deserialize)]
+        struct NewBan {
+            net: String,
+            ttl_secs: Option<u64>,
+            #[serde(default)]
+            reason: String,
+        }
+
+        let result = match *req.method() {
+            http::Method::POST => match serde_json::from_slice::<NewBan>(req.body()) {
+                Ok(new) => self
+                    .ban(&new.net, new.ttl_secs.map(Duration::from_secs), &new.reason)
+                    .await
+                    .map(|ban| (http::StatusCode::CREATED, json!(ban))),
+                Err(e) => Err(e.to_string()),
+            },
+            http::Method::DELETE => match req.query("net") {
+                Some(net) => self.unban(net).await.map(|lifted| (http::StatusCode::OK, json!({ "unbanned": lifted }))),
+                None => Err("missing ?net=".to_string()),
+            },
+            _ => Ok((http::StatusCode::OK, json!({ "bans": self.bans().await }))),
+        };
+        let (status, body) = result.unwrap_or_else(|error| (http::StatusCode::UNPROCESSABLE_ENTITY, json!({ "error": error })));
+        Response::with_status(status).json(&body).unwrap_or_else(|_| Response::internal_error())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AutoBan {
+    strikes: u32,
+    within: Duration,
+    ban_for: Duration,
+}
+
+/// Middleware enforcing a [`BanList`] and per-address limits. Banned clients
+/// get `403 Forbidden`, ones over [`max_in_flight`](Self::max_in_flight)
+/// `429 Too Many Requests`.
+///
+/// Addresses come from [`Request::remote_addr`]; behind a proxy, run it
+/// after middleware that resolves the client address.
+#[derive(Clone)]
+pub struct IpGuard {
+    list: BanList,
+    max_in_flight: Option<usize>,
+    auto_ban: Option<AutoBan>,
+    in_flight: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl IpGuard {
+    pub fn new(list: BanList) -> Self {
+        Self {
+            list,
+            max_in_flight: None,
+            auto_ban: None,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Allow each address at most `max` requests in flight at once
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max.max(1));
+        self
+    }
+
+    /// Ban an address for `ban_for` once it gets `strikes` `4xx` responses
+    /// within `within`
+    pub fn auto_ban(mut self, strikes: u32, within: Duration, ban_for: Duration) -> Self {
+        self.auto_ban = Some(AutoBan { strikes: strikes.max(1), within, ban_for });
+        self
+    }
+}
+
+/// Frees an in-flight slot when the request finishes or is dropped
+struct Slot {
+    in_flight: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    ip: IpAddr,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.ip);
+            }
+        }
+    }
+}
+
+impl Middleware for IpGuard {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let guard = self.clone();
+        Box::pin(async move {
+            let Some(ip) = req.remote_addr().map(|addr| addr.ip()) else {
+                return next(req).await;
+            };
+            if guard.list.banned(ip).await.is_some() {
+                return Response::forbidden();
+            }
+
+            let _slot = match guard.max_in_flight {
+                Some(max) => {
+                    let mut in_flight = guard.in_flight.lock().unwrap();
+                    let count = in_flight.entry(ip).or_insert(0);
+                    if *count >= max {
+                        return Response::with_status(http::StatusCode::TOO_MANY_REQUESTS).header("retry-after", "1");
+                    }
+                    *count += 1;
+                    Some(Slot { in_flight: guard.in_flight.clone(), ip })
+                }
+                None => None,
+            };
+
+            let response = next(req).await;
+            if let Some(auto) = guard.auto_ban.filter(|_| response.status_code().is_client_error()) {
+                if guard.list.strike(ip, auto.within).await >= auto.strikes {
+                    let reason = format!("{} client errors within {}s", auto.strikes, auto.within.as_secs());
+                    match guard.list.add(ip.into(), Some(auto.ban_for), &reason, true).await {
+                        Ok(_) => eprintln!("🚫 Banned {}: {}", ip, reason),
+                        Err(e) => eprintln!("⚠️  Could not ban {}: {}", ip, e),
+                    }
+                }
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+    use http::Method;
+    use std::net::SocketAddr;
+
+    #[tokio::test]
+    async fn test_ip_guard_bans_ranges_and_repeat_offenders() {
+        let net: IpNet = "203.0.113.0/25".parse().unwrap();
+        assert!(net.contains("203.0.113.100".parse().unwrap()));
+        assert!(!net.contains("203.0.113.200".parse().unwrap()));
+        assert!(net.contains("::ffff:203.0.113.1".parse().unwrap()));
+        assert!("2001:db8::/32".parse::<IpNet>().unwrap().contains("2001:db8:1::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+
+        let bans = BanList::new();
+        let app = App::new()
+            .middleware(IpGuard::new(bans.clone()).max_in_flight(1).auto_ban(3, Duration::from_secs(60), Duration::from_secs(60)))
+            .ban_admin("/admin/bans", bans.clone(), |req| req.header("authorization") == Some("Bearer admin"))
+            .get("/", |_req: Request| async { "home" })
+            .get("/slow", |_req: Request| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                "slow"
+            });
+        let from = |ip: &str, method: Method, path: &str| {
+            let addr: SocketAddr = format!("{}:5000", ip).parse().unwrap();
+            Request::mock(method, path).with_remote_addr(addr)
+        };
+
+        // Banned ranges via the admin API, lifted again
+        let admin = |method: Method, path: &str| from("127.0.0.1", method, path).with_header("authorization", "Bearer admin");
+        let ban = br#"{"net": "198.51.100.0/24", "reason": "scraping"}"#;
+        let refused = app.handle_request(from("127.0.0.1", Method::POST, "/admin/bans").with_body(&ban[..])).await;
+        assert_eq!(refused.status_code().as_u16(), 403);
+        assert!(bans.bans().await.is_empty());
+        assert_eq!(app.handle_request(admin(Method::POST, "/admin/bans").with_body(&ban[..])).await.status_code().as_u16(), 201);
+        assert_eq!(app.handle_request(from("198.51.100.9", Method::GET, "/")).await.status_code().as_u16(), 403);
+        let listed = app.handle_request(admin(Method::GET, "/admin/bans")).await;
+        let listed: serde_json::Value = serde_json::from_slice(listed.body_data()).unwrap();
+        assert_eq!(listed["bans"][0]["reason"], "scraping");
+        let lift = app.handle_request(admin(Method::DELETE, "/admin/bans?net=198.51.100.0/24")).await;
+        assert_eq!(lift.status_code().as_u16(), 200);
+        assert_eq!(app.handle_request(from("198.51.100.9", Method::GET, "/")).await.body_data(), b"home");
+
+        // Repeated 404s earn an automatic ban
+        for _ in 0..3 {
+            app.handle_request(from("192.0.2.7", Method::GET, "/wp-admin")).await;
+        }
+        assert_eq!(app.handle_request(from("192.0.2.7", Method::GET, "/")).await.status_code().as_u16(), 403);
+        assert!(bans.banned("192.0.2.7".parse().unwrap()).await.unwrap().automatic);
+
+        // Expired bans lift themselves
+        bans.ban("192.0.2.8", Some(Duration::from_millis(20)), "brief").await.unwrap();
+        assert!(bans.banned("192.0.2.8".parse().unwrap()).await.is_some());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(bans.banned("192.0.2.8".parse().unwrap()).await.is_none());
+
+        // One request in flight per address
+        let (first, second) = tokio::join!(
+            app.handle_request(from("192.0.2.50", Method::GET, "/slow")),
+            app.handle_request(from("192.0.2.50", Method::GET, "/slow")),
+        );
+        let mut statuses = [first.status_code().as_u16(), second.status_code().as_u16()];
+        statuses.sort_unstable();
+        assert_eq!(statuses, [200, 429]);
+        assert_eq!(app.handle_request(from("192.0.2.50", Method::GET, "/")).await.status_code().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_bans_are_kept_per_key_and_read_again_after_cache_for() {
+        let cache = Arc::new(MemoryCache::new(None));
+        let (here, there) = (BanList::with_cache(cache.clone()), BanList::with_cache(cache).cache_for(Duration::from_millis(30)));
+        assert!(there.banned("192.0.2.1".parse().unwrap()).await.is_none());
+
+        // Concurrent bans and lifts don't overwrite each other
+        let hosts: Vec<_> = (1..=20).map(|host| format!("192.0.2.{}", host)).collect();
+        futures::future::join_all(hosts.iter().map(|host| here.ban(host, None, "spam"))).await;
+        let lifts = hosts[..10].iter().map(|host| here.unban(host));
+        assert!(futures::future::join_all(lifts).await.into_iter().all(|lifted| lifted == Ok(true)));
+        assert_eq!(here.bans().await.len(), 10);
+        assert!(!here.unban("192.0.2.1").await.unwrap());
+
+        // The other instance keeps its last read for a moment
+        assert!(there.banned("192.0.2.11".parse().unwrap()).await.is_none());
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert!(there.banned("192.0.2.11".parse().unwrap()).await.is_some());
+
+        let strikes = (0..50).map(|_| here.strike("192.0.2.99".parse().unwrap(), Duration::from_secs(60)));
+        let mut counts = futures::future::join_all(strikes).await;
+        counts.sort_unstable();
+        assert_eq!(counts, (1..=50).collect::<Vec<u32>>());
+    }
+}
@@ -0,0 +1,256 @@
+//! # CORS
+//!
+//! [`App::cors`](crate::App::cors) applies a [`CorsConfig`] to the app. The
+//! router answers preflight `OPTIONS` requests for every registered path by
+//! itself, so no `.options()` handlers are needed, and [`Cors`] adds the
+//! `Access-Control-*` headers to the actual responses.
+//!
+//! ```rust
+//! use torch_web::App;
+//! use torch_web::security::CorsConfig;
+//!
+//! let app = App::new()
+//!     .cors(CorsConfig {
+//!         allowed_origins: vec!["https://app.example.com".to_string()],
+//!         allow_credentials: true,
+//!         ..Default::default()
+//!     })
+//!     .post("/api/orders", |_req: torch_web::Request| async { "created" });
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+
+use http::StatusCode;
+
+use crate::extractors::state::RequestStateExt;
+use crate::middleware::Middleware;
+use crate::router::allow_header;
+use crate::security::CorsConfig;
+use crate::{Method, Request, Response};
+
+impl CorsConfig {
+    /// Whether requests from `origin` are allowed
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+    }
+
+    /// `Access-Control-Allow-Origin` for `origin`
+    fn allow_origin(&self, origin: &str) -> String {
+        if self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            "*".to_string()
+        } else {
+            origin.to_string()
+        }
+    }
+
+    /// Answer a preflight request for a path whose routes serve `methods`
+    fn preflight(&self, req: &Request, methods: &[Method]) -> Response {
+        let origin = req.header("origin").unwrap_or("");
+        let requested = req.header("access-control-request-method").unwrap_or("").trim();
+        if !self.allows_origin(origin) {
+            return Response::forbidden().body("CORS origin not allowed");
+        }
+        if !methods.iter().any(|method| method.as_str() == requested) {
+            return Response::with_status(StatusCode::METHOD_NOT_ALLOWED).header("Allow", allow_header(methods));
+        }
+
+        let allowed_methods: Vec<&str> = methods
+            .iter()
+            .map(Method::as_str)
+            .filter(|method| self.allowed_methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method)))
+            .collect();
+        if !allowed_methods.contains(&requested) {
+            return Response::forbidden().body("CORS method not allowed");
+        }
+
+        let requested_headers = req.header("access-control-request-headers").unwrap_or("");
+        let any_header = self.allowed_headers.iter().any(|allowed| allowed == "*");
+        let refused = requested_headers
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .find(|header| !any_header && !self.allowed_headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(header)));
+        if let Some(header) = refused {
+            return Response::forbidden().body(format!("CORS header not allowed: {}", header));
+        }
+
+        let mut response = Response::no_content()
+            .header("Access-Control-Allow-Origin", self.allow_origin(origin))
+            .header("Access-Control-Allow-Methods", allowed_methods.join(", "))
+            .header("Access-Control-Max-Age", self.max_age.to_string())
+            .header("Vary", "Origin, Access-Control-Request-Method, Access-Control-Request-Headers");
+        if !requested_headers.is_empty() {
+            let headers = if any_header { requested_headers.to_string() } else { self.allowed_headers.join(", ") };
+            response = response.header("Access-Control-Allow-Headers", headers);
+        }
+        if self.allow_credentials {
+            response = response.header("Access-Control-Allow-Credentials", "true");
+        }
+        response
+    }
+}
+
+/// Answer `req` if it's a CORS preflight and the app has a [`CorsConfig`]
+pub(crate) fn answer_preflight(req: &Request, methods: &[Method]) -> Option<Response> {
+    if req.header("origin").is_none() || req.header("access-control-request-method").is_none() {
+        return None;
+    }
+    let cors = req
+        .get_state(std::any::TypeId::of::<CorsConfig>())
+        .and_then(|state| state.downcast_ref::<CorsConfig>())?;
+    Some(cors.preflight(req, methods))
+}
+
+/// Adds the CORS headers of a [`CorsConfig`] to responses to allowed
+/// origins. Installed by [`App::cors`](crate::App::cors).
+#[derive(Debug, Clone)]
+pub struct Cors {
+    config: CorsConfig,
+}
+
+impl Cors {
+    /// # Panics
+    ///
+    /// Panics if `config` allows every origin (`*`) with credentials.
+    /// Browsers refuse that combination, and echoing each origin back instead
+    /// would let any site make requests with the user's cookies; list the
+    /// trusted origins instead.
+    pub fn new(config: CorsConfig) -> Self {
+        assert!(
+            !(config.allow_credentials && config.allowed_origins.iter().any(|allowed| allowed == "*")),
+            "CORS can't allow credentials from every origin (\"*\"); list the allowed origins instead"
+        );
+        Self { config }
+    }
+}
+
+impl Middleware for Cors {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let config = self.config.clone();
+        Box::pin(async move {
+            let origin = req.header("origin").map(str::to_string);
+            let response = next(req).await;
+
+            // Preflights were answered by the router
+            let Some(origin) = origin.filter(|origin| config.allows_origin(origin)) else {
+                return response;
+            };
+            if response.headers().contains_key("access-control-allow-origin") {
+                return response;
+            }
+            let mut response = vary_on_origin(response.header("Access-Control-Allow-Origin", config.allow_origin(&origin)));
+            if config.allow_credentials {
+                response = response.header("Access-Control-Allow-Credentials", "true");
+            }
+            if !config.exposed_headers.is_empty() {
+                response = response.header("Access-Control-Expose-Headers", config.exposed_headers.join(", "));
+            }
+            response
+        })
+    }
+}
+
+/// Add `Origin` to the response's `Vary`, keeping what the handler set
+fn vary_on_origin(response: Response) -> Response {
+    let vary: Vec<String> = response
+        .headers()
+        .get_all("vary")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    if vary.iter().any(|field| field == "*" || field.eq_ignore_ascii_case("origin")) {
+        return response;
+    }
+    let vary = vary.into_iter().chain(["Origin".to_string()]).collect::<Vec<_>>().join(", ");
+    response.header("Vary", vary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+
+    #[tokio::test]
+    async fn test_preflights_are_answered_for_registered_routes() {
+        let app = App::new()
+            .cors(CorsConfig {
+                allowed_origins: vec!["https://app.example.com".to_string()],
+                allowed_headers: vec!["Content-Type".to_string(), "Authorization".to_string()],
+                allow_credentials: true,
+                ..Default::default()
+            })
+            .get("/orders/:id", |_req: Request| async { "order" })
+            .put("/orders/:id", |_req: Request| async { "updated" });
+
+        let preflight = |origin: &str, method: &str, headers: &str| {
+            Request::mock(Method::OPTIONS, "/orders/7")
+                .with_header("origin", origin)
+                .with_header("access-control-request-method", method)
+                .with_header("access-control-request-headers", headers)
+        };
+        let response = app.handle_request(preflight("https://app.example.com", "PUT", "content-type")).await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        let header = |name: &str| response.headers().get(name).unwrap().to_str().unwrap().to_string();
+        assert_eq!(header("access-control-allow-origin"), "https://app.example.com");
+        assert_eq!(header("access-control-allow-methods"), "GET, PUT, OPTIONS");
+        assert_eq!(header("access-control-allow-headers"), "Content-Type, Authorization");
+        assert_eq!(header("access-control-allow-credentials"), "true");
+
+        let evil = app.handle_request(preflight("https://evil.example", "PUT", "")).await;
+        assert_eq!(evil.status_code(), StatusCode::FORBIDDEN);
+        let header = app.handle_request(preflight("https://app.example.com", "PUT", "x-secret")).await;
+        assert_eq!(header.status_code(), StatusCode::FORBIDDEN);
+        let method = app.handle_request(preflight("https://app.example.com", "DELETE", "")).await;
+        assert_eq!(method.status_code(), StatusCode::METHOD_NOT_ALLOWED);
+
+        // Plain OPTIONS lists the methods; unknown paths are still 404s
+        let options = app.handle_request(Request::mock(Method::OPTIONS, "/orders/7")).await;
+        assert_eq!(options.headers().get("allow").unwrap(), "GET, PUT, OPTIONS");
+        let missing = app.handle_request(Request::mock(Method::OPTIONS, "/invoices")).await;
+        assert_eq!(missing.status_code(), StatusCode::NOT_FOUND);
+
+        let actual = app
+            .handle_request(Request::mock(Method::GET, "/orders/7").with_header("origin", "https://app.example.com"))
+            .await;
+        assert_eq!(actual.headers().get("access-control-allow-origin").unwrap(), "https://app.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_origin_is_added_to_the_handlers_vary() {
+        let app = App::new()
+            .cors(CorsConfig {
+                allowed_origins: vec!["https://app.example.com".to_string()],
+                ..Default::default()
+            })
+            .get("/reports", |_req: Request| async { Response::ok().header("Vary", "Accept-Encoding").body("report") })
+            .get("/feed", |_req: Request| async { Response::ok().header("Vary", "origin").body("feed") })
+            .get("/orders", |_req: Request| async { "orders" });
+        let get = |path: &str| Request::mock(Method::GET, path).with_header("origin", "https://app.example.com");
+        let vary = |response: &Response| {
+            let values: Vec<_> = response.headers().get_all("vary").iter().map(|value| value.to_str().unwrap().to_string()).collect();
+            values.join(", ")
+        };
+
+        assert_eq!(vary(&app.handle_request(get("/reports")).await), "Accept-Encoding, Origin");
+        assert_eq!(vary(&app.handle_request(get("/feed")).await), "origin");
+        assert_eq!(vary(&app.handle_request(get("/orders")).await), "Origin");
+    }
+
+    #[test]
+    #[should_panic(expected = "can't allow credentials from every origin")]
+    fn test_credentials_from_every_origin_are_refused() {
+        let _ = App::new().cors(CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            allow_credentials: true,
+            ..Default::default()
+        });
+    }
+}
@@ -88,8 +88,11 @@ pub fn constant_time_eq(a: &str, b: &str) -> bool {
 }
 
 /// Generate a time-based one-time password (TOTP) secret
+///
+/// The secret is base32 encoded, as authenticator apps expect. See
+/// [`crate::auth::totp`] for generating and checking codes.
 pub fn generate_totp_secret() -> String {
-    generate_random_token(32)
+    crate::auth::totp::generate_secret()
 }
 
 /// Simple XOR encryption (for demonstration - use proper encryption in production)
@@ -125,6 +128,289 @@ pub fn generate_encryption_key() -> String {
     generate_hex_token(32) // 256-bit key
 }
 
+/// Environment variable holding the application key
+pub const APP_KEY_ENV: &str = "APP_KEY";
+
+/// Environment variable holding retired keys, comma separated, newest first
+pub const APP_PREVIOUS_KEYS_ENV: &str = "APP_PREVIOUS_KEYS";
+
+/// Length of the AES-GCM nonce prepended to every payload
+const NONCE_LENGTH: usize = 12;
+
+/// Generate a new application key in the `base64:...` form used for `APP_KEY`
+pub fn generate_app_key() -> String {
+    let key: [u8; 32] = thread_rng().gen();
+    format!("base64:{}", general_purpose::STANDARD.encode(key))
+}
+
+/// Parse an application key: `base64:` followed by 32 encoded bytes, or a
+/// raw 32-character string
+pub(crate) fn parse_app_key(key: &str) -> SecurityResult<[u8; 32]> {
+    let key = key.trim();
+    let bytes = match key.strip_prefix("base64:") {
+        Some(encoded) => general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| SecurityError::EncryptionError("Application key is not valid base64".to_string()))?,
+        None => key.as_bytes().to_vec(),
+    };
+
+    bytes.try_into().map_err(|bytes: Vec<u8>| {
+        SecurityError::EncryptionError(format!("Application key must be 32 bytes, got {}", bytes.len()))
+    })
+}
+
+/// AES-256-GCM encryption with the application key.
+///
+/// Values are always encrypted with the current key. Decryption also tries the
+/// previous keys, so data written before `torch key rotate` stays readable
+/// until it has been re-encrypted.
+///
+/// ```rust
+/// use torch_web::security::encryption::{generate_app_key, Encrypter};
+///
+/// let old = Encrypter::new(&generate_app_key()).unwrap();
+/// let secret = old.encrypt("4111 1111 1111 1111").unwrap();
+///
+/// let rotated = Encrypter::new(&generate_app_key())
+///     .unwrap()
+///     .with_previous_keys(&[old.key()])
+///     .unwrap();
+/// assert_eq!(rotated.decrypt(&secret).unwrap(), "4111 1111 1111 1111");
+/// assert!(rotated.reencrypt(&secret).unwrap().is_some());
+/// ```
+#[derive(Clone)]
+pub struct Encrypter {
+    key: [u8; 32],
+    previous: Vec<[u8; 32]>,
+}
+
+impl std::fmt::Debug for Encrypter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encrypter")
+            .field("previous_keys", &self.previous.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encrypter {
+    pub fn new(key: &str) -> SecurityResult<Self> {
+        Ok(Self {
+            key: parse_app_key(key)?,
+            previous: Vec::new(),
+        })
+    }
+
+    /// Keys that are still accepted for decryption
+    pub fn with_previous_keys<K: AsRef<str>>(mut self, keys: &[K]) -> SecurityResult<Self> {
+        for key in keys {
+            self.previous.push(parse_app_key(key.as_ref())?);
+        }
+        Ok(self)
+    }
+
+    /// Build from `APP_KEY` and `APP_PREVIOUS_KEYS`
+    pub fn from_env() -> SecurityResult<Self> {
+        let key = std::env::var(APP_KEY_ENV)
+            .map_err(|_| SecurityError::EncryptionError(format!("{} is not set", APP_KEY_ENV)))?;
+        let previous = std::env::var(APP_PREVIOUS_KEYS_ENV).unwrap_or_default();
+        let previous: Vec<&str> = previous.split(',').map(str::trim).filter(|key| !key.is_empty()).collect();
+        Self::new(&key)?.with_previous_keys(&previous)
+    }
+
+    /// The current key in `base64:...` form
+    pub fn key(&self) -> String {
+        format!("base64:{}", general_purpose::STANDARD.encode(self.key))
+    }
+
+    /// Encrypt with the current key, returning base64 of the nonce and ciphertext
+    pub fn encrypt(&self, plaintext: &str) -> SecurityResult<String> {
+        use aes_gcm::aead::{Aead, KeyInit};
+        use aes_gcm::{Aes256Gcm, Nonce};
+
+        let nonce: [u8; NONCE_LENGTH] = thread_rng().gen();
+        let ciphertext = Aes256Gcm::new(&self.key.into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|_| SecurityError::EncryptionError("Encryption failed".to_string()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(general_purpose::STANDARD.encode(payload))
+    }
+
+    /// Decrypt a payload written with the current or a previous key
+    pub fn decrypt(&self, payload: &str) -> SecurityResult<String> {
+        self.decrypt_with_index(payload).map(|(plaintext, _)| plaintext)
+    }
+
+    /// Re-encrypt a payload written with a previous key. Returns `None` when
+    /// it already uses the current key.
+    pub fn reencrypt(&self, payload: &str) -> SecurityResult<Option<String>> {
+        match self.decrypt_with_index(payload)? {
+            (_, 0) => Ok(None),
+            (plaintext, _) => self.encrypt(&plaintext).map(Some),
+        }
+    }
+
+    /// Plaintext and the index of the key that opened it (0 is the current key)
+    fn decrypt_with_index(&self, payload: &str) -> SecurityResult<(String, usize)> {
+        use aes_gcm::aead::{Aead, KeyInit};
+        use aes_gcm::{Aes256Gcm, Nonce};
+
+        let bytes = general_purpose::STANDARD
+            .decode(payload.trim())
+            .map_err(|_| SecurityError::EncryptionError("Invalid base64 data".to_string()))?;
+        if bytes.len() <= NONCE_LENGTH {
+            return Err(SecurityError::EncryptionError("Payload is too short".to_string()));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+
+        let keys = std::iter::once(&self.key).chain(&self.previous);
+        for (index, key) in keys.enumerate() {
+            if let Ok(plaintext) = Aes256Gcm::new(key.into()).decrypt(Nonce::from_slice(nonce), ciphertext) {
+                let plaintext = String::from_utf8(plaintext)
+                    .map_err(|_| SecurityError::EncryptionError("Invalid UTF-8 data".to_string()))?;
+                return Ok((plaintext, index));
+            }
+        }
+
+        Err(SecurityError::EncryptionError(
+            "The payload could not be decrypted with the current or previous keys".to_string(),
+        ))
+    }
+}
+
+/// Middleware that encrypts cookie values with the application key
+///
+/// Outgoing `Set-Cookie` values are encrypted with the current key, and
+/// incoming cookies are decrypted with the current or a previous key before
+/// handlers and extractors see them, so cookies issued before
+/// `torch key rotate` keep working. Cookies that cannot be decrypted are
+/// dropped from the request.
+///
+/// ```rust,no_run
+/// use torch_web::{App, Request, Response};
+/// use torch_web::security::encryption::EncryptCookies;
+///
+/// let app = App::new()
+///     .middleware(EncryptCookies::from_env().unwrap().except(["theme"]))
+///     .get("/", |_req: Request| async {
+///         Response::ok().header("Set-Cookie", "session_id=abc123; HttpOnly").body("Hello")
+///     });
+/// ```
+#[derive(Debug, Clone)]
+pub struct EncryptCookies {
+    encrypter: Encrypter,
+    except: Vec<String>,
+}
+
+impl EncryptCookies {
+    pub fn new(encrypter: Encrypter) -> Self {
+        Self {
+            encrypter,
+            except: Vec::new(),
+        }
+    }
+
+    /// Encrypt with `APP_KEY`, decrypting with `APP_PREVIOUS_KEYS` too
+    pub fn from_env() -> SecurityResult<Self> {
+        Encrypter::from_env().map(Self::new)
+    }
+
+    /// Leave these cookies in plain text
+    pub fn except<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.except.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    fn is_encrypted(&self, name: &str) -> bool {
+        !self.except.iter().any(|except| except == name)
+    }
+
+    /// The request's `Cookie` header with every encrypted value opened
+    fn decrypt_header(&self, header: &str) -> String {
+        header
+            .split(';')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .filter_map(|pair| match pair.split_once('=') {
+                Some((name, value)) if self.is_encrypted(name.trim()) => self
+                    .encrypter
+                    .decrypt(value)
+                    .ok()
+                    .map(|plaintext| format!("{}={}", name.trim(), plaintext)),
+                _ => Some(pair.to_string()),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// A `Set-Cookie` value with the cookie value encrypted
+    fn encrypt_set_cookie(&self, set_cookie: &str) -> Option<String> {
+        let (pair, attributes) = set_cookie.split_once(';').map_or((set_cookie, None), |(pair, rest)| (pair, Some(rest)));
+        let (name, value) = pair.split_once('=')?;
+        if !self.is_encrypted(name.trim()) || value.trim().is_empty() {
+            return None;
+        }
+        let encrypted = self.encrypter.encrypt(value.trim()).ok()?;
+        Some(match attributes {
+            Some(attributes) => format!("{}={};{}", name.trim(), encrypted, attributes),
+            None => format!("{}={}", name.trim(), encrypted),
+        })
+    }
+}
+
+impl crate::middleware::Middleware for EncryptCookies {
+    fn call(
+        &self,
+        mut req: crate::Request,
+        next: Box<dyn Fn(crate::Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> {
+        use http::header::{HeaderValue, COOKIE, SET_COOKIE};
+
+        if let Some(header) = req.headers().get(COOKIE).and_then(|value| value.to_str().ok()) {
+            let decrypted = self.decrypt_header(header);
+            match HeaderValue::from_str(&decrypted) {
+                Ok(value) if !decrypted.is_empty() => {
+                    req.headers_mut().insert(COOKIE, value);
+                }
+                _ => {
+                    req.headers_mut().remove(COOKIE);
+                }
+            }
+        }
+
+        let this = self.clone();
+        Box::pin(async move {
+            let mut response = next(req).await;
+            let set_cookies: Vec<HeaderValue> = response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|value| {
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|set_cookie| this.encrypt_set_cookie(set_cookie))
+                        .and_then(|encrypted| HeaderValue::from_str(&encrypted).ok())
+                        .unwrap_or_else(|| value.clone())
+                })
+                .collect();
+            if !set_cookies.is_empty() {
+                let headers = response.headers_mut();
+                headers.remove(SET_COOKIE);
+                for value in set_cookies {
+                    headers.append(SET_COOKIE, value);
+                }
+            }
+            response
+        })
+    }
+}
+
 /// Create a secure hash for file integrity checking
 pub fn hash_file_content(content: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -299,6 +585,76 @@ mod tests {
         assert_ne!(data, encrypted);
     }
 
+    #[test]
+    fn test_encrypter_rotation() {
+        let old = Encrypter::new(&generate_app_key()).unwrap();
+        let payload = old.encrypt("secret message").unwrap();
+        assert_eq!(old.decrypt(&payload).unwrap(), "secret message");
+        assert_eq!(old.reencrypt(&payload).unwrap(), None);
+
+        let current = Encrypter::new(&generate_app_key()).unwrap();
+        assert!(current.decrypt(&payload).is_err());
+
+        let rotated = current.with_previous_keys(&[old.key()]).unwrap();
+        let reencrypted = rotated.reencrypt(&payload).unwrap().unwrap();
+        assert_eq!(rotated.decrypt(&reencrypted).unwrap(), "secret message");
+        assert!(old.decrypt(&reencrypted).is_err());
+
+        assert!(Encrypter::new("base64:c2hvcnQ=").is_err());
+        assert!(rotated.decrypt("not encrypted").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_cookies_reads_cookies_from_previous_keys() {
+        use crate::middleware::Middleware;
+        use crate::{Request, Response};
+        use std::future::Future;
+        use std::pin::Pin;
+
+        let old = Encrypter::new(&generate_app_key()).unwrap();
+        let rotated = Encrypter::new(&generate_app_key()).unwrap().with_previous_keys(&[old.key()]).unwrap();
+        let cookies = EncryptCookies::new(rotated.clone()).except(["theme"]);
+
+        let next = Box::new(|req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            let seen = req.header("cookie").unwrap_or_default().to_string();
+            Box::pin(async move {
+                Response::ok()
+                    .header("Set-Cookie", "session_id=abc123; Path=/; HttpOnly")
+                    .header("X-Seen", seen)
+            })
+        });
+
+        let header = format!("session_id={}; theme=dark; forged=bm9wZQ==", old.encrypt("abc123").unwrap());
+        let req = Request::from_parts(
+            http::Request::builder().uri("/").header("cookie", header).body(()).unwrap().into_parts().0,
+            Vec::new(),
+        );
+        let response = cookies.call(req, next).await;
+
+        assert_eq!(response.headers().get("x-seen").unwrap(), "session_id=abc123; theme=dark");
+        let set_cookie = response.headers().get("set-cookie").unwrap().to_str().unwrap();
+        let (value, attributes) = set_cookie.strip_prefix("session_id=").unwrap().split_once(';').unwrap();
+        assert_eq!(attributes, " Path=/; HttpOnly");
+        assert_eq!(rotated.reencrypt(value).unwrap(), None);
+        assert_eq!(rotated.decrypt(value).unwrap(), "abc123");
+    }
+
+    #[test]
+    fn test_from_env_decrypts_with_app_previous_keys() {
+        let old = Encrypter::new(&generate_app_key()).unwrap();
+        let session = old.encrypt("session abc123").unwrap();
+
+        // The only test that sets these
+        std::env::set_var(APP_KEY_ENV, generate_app_key());
+        std::env::set_var(APP_PREVIOUS_KEYS_ENV, format!(" {} ,", old.key()));
+        let rotated = Encrypter::from_env().unwrap();
+        assert_eq!(rotated.decrypt(&session).unwrap(), "session abc123");
+
+        std::env::remove_var(APP_PREVIOUS_KEYS_ENV);
+        assert!(Encrypter::from_env().unwrap().decrypt(&session).is_err());
+        std::env::remove_var(APP_KEY_ENV);
+    }
+
     #[test]
     fn test_signature_verification() {
         let data = "important data";
@@ -2,7 +2,10 @@
 //!
 //! This module provides security headers to protect against various attacks.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use crate::security::{SecurityConfig, SecurityResult};
 
 /// Initialize security headers
@@ -37,3 +40,346 @@ pub fn get_security_headers() -> HashMap<String, String> {
     
     headers
 }
+
+/// Path the [`CspReports`] collector listens on by default
+pub const CSP_REPORT_PATH: &str = "/csp-report";
+
+/// Content-Security-Policy middleware
+///
+/// Sends an enforced policy, a report-only policy, or both. Tightening a policy
+/// usually means keeping the current one enforced while the stricter one runs
+/// report-only, and watching what [`CspReports`] collects until it is quiet
+/// enough to enforce. Responses that already carry a policy keep it.
+///
+/// ```rust
+/// use torch_web::App;
+/// use torch_web::security::{ContentSecurityPolicy, CspReports};
+///
+/// let app = App::new()
+///     .middleware(CspReports::new())
+///     .middleware(
+///         ContentSecurityPolicy::new()
+///             .enforce("default-src 'self' 'unsafe-inline'")
+///             .report_only("default-src 'self'; script-src 'self'")
+///             .report_uri("/csp-report"),
+///     );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ContentSecurityPolicy {
+    enforce: Option<String>,
+    report_only: Option<String>,
+    report_uri: Option<String>,
+}
+
+impl ContentSecurityPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the policies from the `[security]` section of the app config
+    pub fn from_config(config: &crate::config::SecurityConfig) -> Self {
+        Self {
+            enforce: config.content_security_policy.clone(),
+            report_only: config.content_security_policy_report_only.clone(),
+            report_uri: config.csp_report_uri.clone(),
+        }
+    }
+
+    /// Policy sent as `Content-Security-Policy`
+    pub fn enforce(mut self, policy: &str) -> Self {
+        self.enforce = Some(policy.to_string());
+        self
+    }
+
+    /// Policy sent as `Content-Security-Policy-Report-Only`
+    pub fn report_only(mut self, policy: &str) -> Self {
+        self.report_only = Some(policy.to_string());
+        self
+    }
+
+    /// Where browsers send violation reports, added to both policies
+    pub fn report_uri(mut self, uri: &str) -> Self {
+        self.report_uri = Some(uri.to_string());
+        self
+    }
+
+    fn header_value(&self, policy: &str) -> String {
+        let policy = policy.trim().trim_end_matches(';');
+        match &self.report_uri {
+            Some(uri) if !policy.contains("report-uri") => format!("{}; report-uri {}", policy, uri),
+            _ => policy.to_string(),
+        }
+    }
+}
+
+impl crate::middleware::Middleware for ContentSecurityPolicy {
+    fn call(
+        &self,
+        req: crate::Request,
+        next: Box<dyn Fn(crate::Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> {
+        let enforce = self.enforce.as_deref().map(|policy| self.header_value(policy));
+        let report_only = self.report_only.as_deref().map(|policy| self.header_value(policy));
+        Box::pin(async move {
+            let mut response = next(req).await;
+            for (name, policy) in [
+                ("content-security-policy", enforce),
+                ("content-security-policy-report-only", report_only),
+            ] {
+                if let Some(policy) = policy {
+                    if !response.headers().contains_key(name) {
+                        response = response.header(name, &policy);
+                    }
+                }
+            }
+            response
+        })
+    }
+}
+
+/// One Content-Security-Policy violation reported by a browser
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CspViolation {
+    pub document_uri: String,
+    /// The directive that blocked the resource, e.g. `script-src-elem`
+    pub directive: String,
+    pub blocked_uri: String,
+    pub source_file: Option<String>,
+    pub line_number: Option<u64>,
+    /// `enforce` or `report`, the latter for report-only policies
+    pub disposition: String,
+    /// Unix timestamp when the report arrived
+    pub received_at: u64,
+}
+
+type CspForward = Arc<dyn Fn(&CspViolation) + Send + Sync>;
+
+/// Collector for Content-Security-Policy violation reports
+///
+/// Answers `POST /csp-report` with `204 No Content`, accepting both the
+/// `application/csp-report` bodies from `report-uri` and the Reporting API's
+/// `application/reports+json` batches. The latest reports are kept in memory,
+/// and can also be appended to a JSON-lines file or forwarded to an error
+/// tracker with [`CspReports::forward`]. Clones share the stored reports.
+#[derive(Clone)]
+pub struct CspReports {
+    path: String,
+    capacity: usize,
+    reports: Arc<Mutex<VecDeque<CspViolation>>>,
+    log_file: Option<PathBuf>,
+    forward: Option<CspForward>,
+}
+
+impl CspReports {
+    /// Largest report body accepted, in bytes
+    const MAX_BODY: usize = 64 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            path: CSP_REPORT_PATH.to_string(),
+            capacity: 100,
+            reports: Arc::new(Mutex::new(VecDeque::new())),
+            log_file: None,
+            forward: None,
+        }
+    }
+
+    /// Listen on a different path than `/csp-report`
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// How many recent reports to keep in memory, default 100
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Also append every report to `path` as a line of JSON
+    pub fn store_in(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Call `forward` with every report, e.g. to send it to an error tracker
+    pub fn forward<F>(mut self, forward: F) -> Self
+    where
+        F: Fn(&CspViolation) + Send + Sync + 'static,
+    {
+        self.forward = Some(Arc::new(forward));
+        self
+    }
+
+    /// The reports kept in memory, oldest first
+    pub fn reports(&self) -> Vec<CspViolation> {
+        self.reports.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Parse either report format; `None` when the body is not a CSP report
+    pub fn parse(body: &[u8]) -> Option<Vec<CspViolation>> {
+        let received_at = crate::clock::unix_now();
+        let text = |report: &serde_json::Value, keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| report[*key].as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+        // `report-uri` sends one object, the Reporting API a batch of mixed report types
+        let reports: Vec<(&serde_json::Value, bool)> = match &value {
+            serde_json::Value::Object(object) => vec![(object.get("csp-report")?, true)],
+            serde_json::Value::Array(batch) => batch
+                .iter()
+                .filter(|report| report["type"] == "csp-violation")
+                .map(|report| (&report["body"], false))
+                .collect(),
+            _ => return None,
+        };
+
+        Some(
+            reports
+                .into_iter()
+                .map(|(report, legacy)| {
+                    let (document, directive, blocked, source, line) = if legacy {
+                        ("document-uri", ["effective-directive", "violated-directive"], "blocked-uri", "source-file", "line-number")
+                    } else {
+                        ("documentURL", ["effectiveDirective", "violatedDirective"], "blockedURL", "sourceFile", "lineNumber")
+                    };
+                    CspViolation {
+                        document_uri: text(report, &[document]),
+                        directive: text(report, &directive),
+                        blocked_uri: text(report, &[blocked]),
+                        source_file: report[source].as_str().map(str::to_string),
+                        line_number: report[line].as_u64(),
+                        disposition: Some(text(report, &["disposition"]))
+                            .filter(|disposition| !disposition.is_empty())
+                            .unwrap_or_else(|| "enforce".to_string()),
+                        received_at,
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn record(&self, violation: CspViolation) {
+        eprintln!(
+            "🛡️ CSP violation ({}): {} blocked {} on {}",
+            violation.disposition, violation.directive, violation.blocked_uri, violation.document_uri
+        );
+
+        if let Some(path) = &self.log_file {
+            let line = serde_json::to_string(&violation).unwrap_or_default();
+            let written = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line));
+            if let Err(e) = written {
+                eprintln!("Failed to store CSP report in {}: {}", path.display(), e);
+            }
+        }
+
+        if let Some(forward) = &self.forward {
+            forward(&violation);
+        }
+
+        let mut reports = self.reports.lock().unwrap();
+        reports.push_back(violation);
+        while reports.len() > self.capacity {
+            reports.pop_front();
+        }
+    }
+}
+
+impl Default for CspReports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl crate::middleware::Middleware for CspReports {
+    fn call(
+        &self,
+        req: crate::Request,
+        next: Box<dyn Fn(crate::Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> {
+        if req.path() != self.path || req.method() != http::Method::POST {
+            return next(req);
+        }
+
+        let collector = self.clone();
+        Box::pin(async move {
+            if req.body().len() > Self::MAX_BODY {
+                return crate::Response::with_status(http::StatusCode::PAYLOAD_TOO_LARGE).body("Report too large");
+            }
+            let Some(violations) = Self::parse(req.body()) else {
+                return crate::Response::bad_request();
+            };
+            // File writes and forwarding stay off the async workers
+            let _ = tokio::task::spawn_blocking(move || {
+                for violation in violations {
+                    collector.record(violation);
+                }
+            })
+            .await;
+            crate::Response::with_status(http::StatusCode::NO_CONTENT)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::Middleware;
+    use crate::{Request, Response};
+    use http::Method;
+    use std::future::Future;
+    use std::pin::Pin;
+
+    type Next = Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>;
+
+    fn next() -> Next {
+        Box::new(|_req: Request| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            Box::pin(async { Response::ok().body("page") })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_csp_report_only_and_collector() {
+        let csp = ContentSecurityPolicy::new()
+            .enforce("default-src *")
+            .report_only("default-src 'self';")
+            .report_uri(CSP_REPORT_PATH);
+        let response = csp.call(Request::mock(Method::GET, "/"), next()).await;
+        assert_eq!(response.headers().get("content-security-policy").unwrap(), "default-src *; report-uri /csp-report");
+        assert_eq!(
+            response.headers().get("content-security-policy-report-only").unwrap(),
+            "default-src 'self'; report-uri /csp-report"
+        );
+
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let sink = forwarded.clone();
+        let reports = CspReports::new().forward(move |violation| sink.lock().unwrap().push(violation.blocked_uri.clone()));
+
+        let legacy = r#"{"csp-report": {"document-uri": "https://app.test/", "violated-directive": "script-src",
+            "blocked-uri": "https://evil.test/x.js", "line-number": 12, "disposition": "report"}}"#;
+        let batch = r#"[{"type": "csp-violation", "body": {"documentURL": "https://app.test/a",
+            "effectiveDirective": "img-src", "blockedURL": "data", "disposition": "enforce"}},
+            {"type": "deprecation", "body": {}}]"#;
+        for body in [legacy, batch] {
+            let req = Request::mock(Method::POST, CSP_REPORT_PATH).with_body(body);
+            assert_eq!(reports.call(req, next()).await.status_code(), http::StatusCode::NO_CONTENT);
+        }
+        let bad = Request::mock(Method::POST, CSP_REPORT_PATH).with_body("{}");
+        assert_eq!(reports.call(bad, next()).await.status_code(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(reports.call(Request::mock(Method::GET, CSP_REPORT_PATH), next()).await.body_data(), b"page");
+
+        let stored = reports.reports();
+        assert_eq!(stored.len(), 2);
+        assert_eq!((stored[0].directive.as_str(), stored[0].line_number, stored[0].disposition.as_str()), ("script-src", Some(12), "report"));
+        assert_eq!((stored[1].document_uri.as_str(), stored[1].directive.as_str()), ("https://app.test/a", "img-src"));
+        assert_eq!(*forwarded.lock().unwrap(), vec!["https://evil.test/x.js", "data"]);
+    }
+}
@@ -11,6 +11,7 @@
 //! - **XSS Protection** - Output encoding and Content Security Policy
 //! - **CSRF Protection** - Token-based CSRF protection
 //! - **Rate Limiting** - Request rate limiting and DDoS protection
+//! - **Ban List** - CIDR deny lists with automatic bans for repeat offenders
 //! - **Secure Headers** - Security headers for HTTPS, HSTS, etc.
 //! - **Authentication** - Secure password hashing and session management
 //! - **Authorization** - Role-based access control
@@ -42,6 +43,13 @@ pub mod headers;
 pub mod rate_limit;
 pub mod auth;
 pub mod encryption;
+pub mod signed_urls;
+pub mod cors;
+#[cfg(feature = "json")]
+pub mod bans;
+
+pub use headers::{ContentSecurityPolicy, CspReports, CspViolation};
+pub use cors::Cors;
 
 use serde::{Deserialize, Serialize};
 
@@ -311,8 +319,201 @@ impl SecurityHeaders {
 /// Request ID middleware (placeholder for future implementation)
 pub struct RequestId;
 
-/// Input validation middleware (placeholder for future implementation)
-pub struct InputValidator;
+/// Limits applied by [`InputValidator`]
+#[derive(Debug, Clone)]
+pub struct InputRules {
+    /// Longest accepted path, in bytes
+    pub max_path_length: usize,
+
+    /// Longest accepted query string, in bytes
+    pub max_query_length: usize,
+
+    /// Longest accepted header value, in bytes
+    pub max_header_length: usize,
+
+    /// Reject `../` in query parameter values as well as in the path
+    pub check_query_traversal: bool,
+}
+
+impl Default for InputRules {
+    fn default() -> Self {
+        Self {
+            max_path_length: 2048,
+            max_query_length: 4096,
+            max_header_length: 8192,
+            check_query_traversal: true,
+        }
+    }
+}
+
+/// Input validation middleware
+///
+/// Scans the path, query string and headers of every request and rejects it
+/// with `400 Bad Request` when it finds path traversal (`..` segments, in
+/// plain or encoded form), null bytes, values over the configured lengths, or
+/// broken percent-encoding. Each rejection is logged with the request's
+/// `X-Request-Id`, which is generated and echoed back when the client did not
+/// send one.
+///
+/// Routes that legitimately take unusual input can get their own rules, or
+/// skip validation, by path prefix. The longest matching prefix wins.
+///
+/// ```rust
+/// use torch_web::App;
+/// use torch_web::security::{InputRules, InputValidator};
+///
+/// let app = App::new().middleware(
+///     InputValidator::new()
+///         .route("/search", InputRules { check_query_traversal: false, ..Default::default() })
+///         .skip("/webhooks"),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InputValidator {
+    rules: InputRules,
+    routes: Vec<(String, Option<InputRules>)>,
+}
+
+impl InputValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the rules used outside any configured route
+    pub fn rules(mut self, rules: InputRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Use different rules for paths under `prefix`
+    pub fn route(mut self, prefix: &str, rules: InputRules) -> Self {
+        self.routes.push((prefix.trim_end_matches('/').to_string(), Some(rules)));
+        self
+    }
+
+    /// Don't validate paths under `prefix`
+    pub fn skip(mut self, prefix: &str) -> Self {
+        self.routes.push((prefix.trim_end_matches('/').to_string(), None));
+        self
+    }
+
+    /// The rules for `path`, or `None` when it is skipped
+    fn rules_for(&self, path: &str) -> Option<&InputRules> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(Some(&self.rules), |(_, rules)| rules.as_ref())
+    }
+
+    /// Check a request against the rules for its path
+    pub fn validate(&self, req: &crate::Request) -> SecurityResult<()> {
+        let Some(rules) = self.rules_for(req.path()) else {
+            return Ok(());
+        };
+        let invalid = |reason: String| Err(SecurityError::InvalidInput(reason));
+
+        let path = req.uri().path();
+        if path.len() > rules.max_path_length {
+            return invalid(format!("path longer than {} bytes", rules.max_path_length));
+        }
+        let decoded = match percent_decode(path) {
+            Some(decoded) => decoded,
+            None => return invalid("malformed percent-encoding in path".to_string()),
+        };
+        if decoded.contains('\0') {
+            return invalid("null byte in path".to_string());
+        }
+        // Anything still encoded after one pass was double-encoded to slip past filters
+        let lower = decoded.to_ascii_lowercase();
+        if has_traversal(&decoded) || ["%2e", "%2f", "%5c"].iter().any(|encoded| lower.contains(encoded)) {
+            return invalid("path traversal in path".to_string());
+        }
+
+        if let Some(query) = req.uri().query() {
+            if query.len() > rules.max_query_length {
+                return invalid(format!("query string longer than {} bytes", rules.max_query_length));
+            }
+            for pair in query.split('&') {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                let (Some(name), Some(value)) = (
+                    percent_decode(&name.replace('+', " ")),
+                    percent_decode(&value.replace('+', " ")),
+                ) else {
+                    return invalid("malformed percent-encoding in query string".to_string());
+                };
+                if name.contains('\0') || value.contains('\0') {
+                    return invalid(format!("null byte in query parameter `{}`", name.replace('\0', "")));
+                }
+                if rules.check_query_traversal && has_traversal(&value) {
+                    return invalid(format!("path traversal in query parameter `{}`", name));
+                }
+            }
+        }
+
+        for (name, value) in req.headers() {
+            if value.len() > rules.max_header_length {
+                return invalid(format!("header `{}` longer than {} bytes", name, rules.max_header_length));
+            }
+            if value.as_bytes().contains(&0) {
+                return invalid(format!("null byte in header `{}`", name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode `%XX` escapes, rejecting truncated or non-hex escapes and invalid UTF-8
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            let hex = std::str::from_utf8(hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Whether a decoded value has a `..` segment, with either slash style
+fn has_traversal(value: &str) -> bool {
+    value.split(['/', '\\']).any(|segment| segment == "..")
+}
+
+impl crate::middleware::Middleware for InputValidator {
+    fn call(
+        &self,
+        req: crate::Request,
+        next: Box<dyn Fn(crate::Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> + Send + Sync>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::Response> + Send + 'static>> {
+        let Err(error) = self.validate(&req) else {
+            return next(req);
+        };
+
+        let request_id = req
+            .header("x-request-id")
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let path: String = req.path().chars().take(200).collect();
+        eprintln!("🔒 Blocked {} {} [request {}]: {}", req.method(), path, request_id, error);
+
+        Box::pin(async move {
+            crate::Response::bad_request()
+                .header("x-request-id", &request_id)
+        })
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -337,4 +538,36 @@ mod tests {
         assert!(complexity.require_special_chars);
         assert_eq!(complexity.min_char_types, 3);
     }
+
+    #[test]
+    fn test_input_validator_rejects_bad_input() {
+        use crate::Request;
+        use http::Method;
+
+        let validator = InputValidator::new()
+            .route("/files", InputRules { check_query_traversal: false, ..Default::default() })
+            .skip("/raw");
+        let check = |uri: &str| validator.validate(&Request::mock(Method::GET, uri));
+
+        assert!(check("/users/42?sort=name&q=caf%C3%A9+au+lait").is_ok());
+        assert!(check("/static/../../etc/passwd").is_err());
+        assert!(check("/static/%2e%2e/secret").is_err());
+        assert!(check("/static/%252e%252e/secret").is_err());
+        assert!(check("/static/..%5c..%5cwindows").is_err());
+        assert!(check("/download?file=..%2F..%2Fetc%2Fpasswd").is_err());
+        assert!(check("/search?q=a%00b").is_err());
+        assert!(check("/search?q=%zz").is_err());
+        assert!(check("/search?q=%C3").is_err());
+        assert!(check(&format!("/{}", "a".repeat(3000))).is_err());
+
+        // Per-route rules and skipped prefixes
+        assert!(check("/files/open?path=../shared").is_ok());
+        assert!(check("/files/%2e%2e/x").is_err());
+        assert!(check("/raw/%zz").is_ok());
+        assert!(check("/rawer/%zz").is_err());
+
+        let long_header = Request::mock(Method::GET, "/").with_header("x-note", &"x".repeat(9000));
+        assert!(validator.validate(&long_header).is_err());
+    }
 }
+
@@ -0,0 +1,271 @@
+//! # Signed URLs
+//!
+//! Tamper-proof, optionally time-limited links for email verification,
+//! unsubscribe links, file downloads and anything else that has to work
+//! without a session. Links are signed with `APP_KEY`, and links signed with
+//! a key listed in `APP_PREVIOUS_KEYS` keep working after `torch key rotate`.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use torch_web::security::encryption::generate_app_key;
+//! use torch_web::security::signed_urls::UrlSigner;
+//!
+//! let signer = UrlSigner::new(&generate_app_key()).unwrap();
+//! let url = signer.sign("/downloads/report.pdf?user=42", Some(Duration::from_secs(3600)));
+//!
+//! assert!(signer.verify(&url).is_ok());
+//! assert!(signer.verify(&url.replace("user=42", "user=43")).is_err());
+//! ```
+
+use crate::clock::unix_now;
+use crate::middleware::Middleware;
+use crate::security::encryption::{constant_time_eq, parse_app_key, APP_KEY_ENV, APP_PREVIOUS_KEYS_ENV};
+use crate::security::{SecurityError, SecurityResult};
+use crate::{Request, Response};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Query parameter holding the signature
+const SIGNATURE_PARAM: &str = "signature";
+
+/// Query parameter holding the expiry as a Unix timestamp
+const EXPIRES_PARAM: &str = "expires";
+
+/// Signs and verifies URLs with the application key
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: [u8; 32],
+    previous: Vec<[u8; 32]>,
+}
+
+impl std::fmt::Debug for UrlSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UrlSigner")
+            .field("previous_keys", &self.previous.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl UrlSigner {
+    /// Create a signer from an application key in `APP_KEY` form
+    pub fn new(key: &str) -> SecurityResult<Self> {
+        Ok(Self {
+            key: parse_app_key(key)?,
+            previous: Vec::new(),
+        })
+    }
+
+    /// Keys whose signatures are still accepted
+    pub fn with_previous_keys<K: AsRef<str>>(mut self, keys: &[K]) -> SecurityResult<Self> {
+        for key in keys {
+            self.previous.push(parse_app_key(key.as_ref())?);
+        }
+        Ok(self)
+    }
+
+    /// Build from `APP_KEY` and `APP_PREVIOUS_KEYS`
+    pub fn from_env() -> SecurityResult<Self> {
+        let key = std::env::var(APP_KEY_ENV)
+            .map_err(|_| SecurityError::EncryptionError(format!("{} is not set", APP_KEY_ENV)))?;
+        let previous = std::env::var(APP_PREVIOUS_KEYS_ENV).unwrap_or_default();
+        let previous: Vec<&str> = previous.split(',').map(str::trim).filter(|key| !key.is_empty()).collect();
+        Self::new(&key)?.with_previous_keys(&previous)
+    }
+
+    /// Sign a path with its query string, optionally expiring after `expires_in`
+    ///
+    /// Everything before the `?` and every query parameter is covered by the
+    /// signature, so none of them can be changed without breaking it.
+    pub fn sign(&self, url: &str, expires_in: Option<Duration>) -> String {
+        let mut url = url.to_string();
+        if let Some(expires_in) = expires_in {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            url = format!("{}{}{}={}", url, separator, EXPIRES_PARAM, unix_now() + expires_in.as_secs());
+        }
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let signature = hex::encode(signature(&self.key, &url));
+        format!("{}{}{}={}", url, separator, SIGNATURE_PARAM, signature)
+    }
+
+    /// Check a signed path with its query string
+    pub fn verify(&self, url: &str) -> SecurityResult<()> {
+        let invalid = || SecurityError::InvalidInput("Invalid signature".to_string());
+        let (path, query) = url.split_once('?').ok_or_else(invalid)?;
+
+        let mut provided = None;
+        let mut expires = None;
+        let mut signed_params = Vec::new();
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some((SIGNATURE_PARAM, value)) => provided = Some(value),
+                Some((EXPIRES_PARAM, value)) => {
+                    expires = Some(value.parse::<u64>().map_err(|_| invalid())?);
+                    signed_params.push(pair);
+                }
+                _ => signed_params.push(pair),
+            }
+        }
+
+        let provided = provided.ok_or_else(invalid)?;
+        let signed = if signed_params.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, signed_params.join("&"))
+        };
+        let valid = std::iter::once(&self.key)
+            .chain(&self.previous)
+            .any(|key| constant_time_eq(&hex::encode(signature(key, &signed)), provided));
+        if !valid {
+            return Err(invalid());
+        }
+
+        match expires {
+            Some(expires) if expires <= unix_now() => Err(SecurityError::InvalidInput("Link has expired".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Middleware that only lets through requests with a valid URL signature
+///
+/// Requests under the protected prefixes (every request, when none are given)
+/// need a signature made by [`UrlSigner::sign`] or
+/// [`Urls::url_signed`](crate::router::Urls::url_signed), and get
+/// `403 Forbidden` when it is missing, tampered with or expired.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use torch_web::{App, Request, Response, router::Urls};
+/// use torch_web::security::signed_urls::ValidateSignature;
+///
+/// let app = App::new()
+///     .middleware(ValidateSignature::from_env().unwrap().protect("/unsubscribe"))
+///     .get("/unsubscribe/:user", |_req: Request| async { Response::ok().body("Unsubscribed") })
+///     .name("unsubscribe")
+///     .get("/newsletter", |urls: Urls| async move {
+///         let link = urls.url_signed("unsubscribe", &[("user", "42")], Duration::from_secs(7 * 86400)).unwrap();
+///         Response::ok().body(link)
+///     });
+/// ```
+#[derive(Debug, Clone)]
+pub struct ValidateSignature {
+    signer: UrlSigner,
+    prefixes: Vec<String>,
+}
+
+impl ValidateSignature {
+    pub fn new(signer: UrlSigner) -> Self {
+        Self {
+            signer,
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Validate with the keys from `APP_KEY` and `APP_PREVIOUS_KEYS`
+    pub fn from_env() -> SecurityResult<Self> {
+        UrlSigner::from_env().map(Self::new)
+    }
+
+    /// Require a signature for paths under `prefix`
+    pub fn protect(mut self, prefix: &str) -> Self {
+        self.prefixes.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    fn is_protected(&self, path: &str) -> bool {
+        self.prefixes.is_empty()
+            || self.prefixes.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+impl Middleware for ValidateSignature {
+    fn call(
+        &self,
+        req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if !self.is_protected(req.path()) {
+            return next(req);
+        }
+        let target = req.uri().path_and_query().map_or(req.path(), |target| target.as_str());
+        match self.signer.verify(target) {
+            Ok(()) => next(req),
+            Err(error) => {
+                let message = match error {
+                    SecurityError::InvalidInput(message) => message,
+                    other => other.to_string(),
+                };
+                Box::pin(async move { Response::forbidden().body(message) })
+            }
+        }
+    }
+}
+
+fn signature(key: &[u8], url: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(url.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::encryption::generate_app_key;
+
+    #[test]
+    fn test_signed_urls_expire_and_survive_key_rotation() {
+        let old_key = generate_app_key();
+        let old = UrlSigner::new(&old_key).unwrap();
+        let rotated = UrlSigner::new(&generate_app_key()).unwrap().with_previous_keys(&[&old_key]).unwrap();
+
+        let forever = old.sign("/verify/7", None);
+        assert!(forever.starts_with("/verify/7?signature="));
+        assert!(rotated.verify(&forever).is_ok());
+        assert!(UrlSigner::new(&generate_app_key()).unwrap().verify(&forever).is_err());
+
+        let url = rotated.sign("/files/a.pdf?inline=1", Some(Duration::from_secs(60)));
+        assert!(rotated.verify(&url).is_ok());
+        assert!(rotated.verify(&url.replace("inline=1", "inline=0")).is_err());
+        assert!(rotated.verify("/files/a.pdf?inline=1").is_err());
+
+        let expired = rotated.sign("/files/a.pdf", Some(Duration::ZERO));
+        let error = rotated.verify(&expired).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid input: Link has expired");
+
+        // Moving the expiry forward breaks the signature
+        let expires = url.split("expires=").nth(1).unwrap().split('&').next().unwrap();
+        let extended = url.replace(expires, &(unix_now() + 86400).to_string());
+        assert!(rotated.verify(&extended).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_signature_middleware() {
+        use crate::router::Urls;
+        use crate::App;
+        use http::Method;
+
+        let signer = UrlSigner::new(&generate_app_key()).unwrap();
+        let app = App::new()
+            .middleware(ValidateSignature::new(signer.clone()).protect("/unsubscribe"))
+            .get("/unsubscribe/:user", |req: Request| async move {
+                Response::ok().body(format!("bye {}", req.param("user").unwrap()))
+            })
+            .name("unsubscribe")
+            .get("/link", |urls: Urls| async move { Response::ok().body(urls.url("unsubscribe", &[("user", "42")]).unwrap()) });
+
+        let link = app.handle_request(Request::mock(Method::GET, "/link")).await;
+        let signed = signer.sign(std::str::from_utf8(link.body_data()).unwrap(), Some(Duration::from_secs(60)));
+
+        let response = app.handle_request(Request::mock(Method::GET, &signed)).await;
+        assert_eq!(response.body_data(), b"bye 42");
+        let tampered = app.handle_request(Request::mock(Method::GET, &signed.replace("/42?", "/43?"))).await;
+        assert_eq!(tampered.status_code(), http::StatusCode::FORBIDDEN);
+    }
+}
+
@@ -1,11 +1,14 @@
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use futures::TryStreamExt;
+use http_body_util::BodyExt;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Request as HyperRequest, Response as HyperResponse};
 use hyper_util::rt::TokioIo;
 use tokio::net::TcpListener;
+use crate::response::ResponseBody;
 use crate::{App, Request};
 
 /// Start the HTTP server
@@ -19,17 +22,21 @@ pub async fn serve(
     println!("🔥 Torch server listening on http://{}", addr);
 
     loop {
-        let (stream, _) = listener.accept().await?;
+        let (stream, peer) = listener.accept().await?;
         let io = TokioIo::new(stream);
         let app = app.clone();
 
         tokio::task::spawn(async move {
             let service = service_fn(move |req| {
                 let app = app.clone();
-                async move { handle_request(req, app).await }
+                async move { handle_request(req, app, peer).await }
             });
 
-            if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(io, service)
+                .with_upgrades()
+                .await
+            {
                 eprintln!("Error serving connection: {:?}", err);
             }
         });
@@ -38,36 +45,77 @@ pub async fn serve(
 
 /// Handle a single HTTP request
 async fn handle_request(
-    hyper_req: HyperRequest<hyper::body::Incoming>,
+    #[cfg_attr(not(feature = "websocket"), allow(unused_mut))]
+    mut hyper_req: HyperRequest<hyper::body::Incoming>,
     app: Arc<App>,
-) -> Result<HyperResponse<http_body_util::Full<hyper::body::Bytes>>, Infallible> {
+    peer: SocketAddr,
+) -> Result<HyperResponse<ResponseBody>, Infallible> {
+    // Claim the connection upgrade before the request is taken apart
+    #[cfg(feature = "websocket")]
+    let on_upgrade = hyper_req
+        .headers()
+        .contains_key(http::header::UPGRADE)
+        .then(|| hyper::upgrade::on(&mut hyper_req));
+
     let (parts, body) = hyper_req.into_parts();
 
-    // Convert hyper request to our Request type
-    let request = match Request::from_hyper(parts, body).await {
-        Ok(req) => req,
+    // Convert hyper request to our Request type. Multipart bodies stay with
+    // the client until the handler reads them, so uploads can go to storage
+    // part by part and stop as soon as the client goes away.
+    let multipart = parts
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim_start().to_ascii_lowercase().starts_with("multipart/form-data"));
+    let request = if multipart {
+        let stream = body.into_data_stream().map_err(|error| error.into());
+        Ok(Request::from_parts(parts, Vec::new()).with_body_stream(Box::pin(stream)))
+    } else {
+        Request::from_hyper(parts, body).await
+    };
+    let request = match request {
+        Ok(req) => req.with_remote_addr(peer),
         Err(err) => {
             eprintln!("Error parsing request: {:?}", err);
             return Ok(create_error_response(500, "Internal Server Error"));
         }
     };
 
+    #[cfg(feature = "websocket")]
+    let path = request.path().to_string();
+
     // Handle the request with our app
     let response = app.handle_request(request).await;
 
+    // Once the 101 goes out, hand the raw connection to the WebSocket handler
+    #[cfg(feature = "websocket")]
+    if response.status_code() == http::StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(on_upgrade) = on_upgrade {
+            let extensions = response.extensions().clone();
+            tokio::spawn(async move {
+                match on_upgrade.await {
+                    Ok(upgraded) => {
+                        app.accept_websocket(&path, TokioIo::new(upgraded), &extensions).await;
+                    }
+                    Err(err) => eprintln!("WebSocket upgrade failed: {:?}", err),
+                }
+            });
+        }
+    }
+
     // Convert our Response back to hyper Response
     Ok(response.into_hyper_response())
 }
 
 /// Create an error response
-fn create_error_response(status: u16, message: &str) -> HyperResponse<http_body_util::Full<hyper::body::Bytes>> {
-    use http_body_util::Full;
+fn create_error_response(status: u16, message: &str) -> HyperResponse<ResponseBody> {
+    use http_body_util::{BodyExt, Full};
     use hyper::body::Bytes;
 
     HyperResponse::builder()
         .status(status)
         .header("content-type", "text/plain")
-        .body(Full::new(Bytes::from(message.to_string())))
+        .body(Full::new(Bytes::from(message.to_string())).boxed_unsync())
         .unwrap()
 }
 
@@ -0,0 +1,690 @@
+//! # File Storage
+//!
+//! Named disks for reading and writing files, mirroring the
+//! `[filesystem.disks]` section of `torch.toml`.
+//!
+//! Out of the box `local` points at `storage/app` and `public` at
+//! `storage/app/public` (served under `/storage`). Register other disks with
+//! [`Storage::register`].
+//!
+//! ```rust,no_run
+//! use torch_web::storage::Storage;
+//!
+//! # async fn example() -> Result<(), torch_web::storage::StorageError> {
+//! let disk = Storage::disk("public")?;
+//! disk.put("avatars/1.png", b"...".to_vec()).await?;
+//! assert_eq!(disk.url("avatars/1.png").as_deref(), Some("/storage/avatars/1.png"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Uploads
+//!
+//! Files from a [`MultipartStream`](crate::extractors::MultipartStream) form
+//! are sent to a disk in parts while the client is still sending them, so
+//! the `s3` disk (feature `s3`) can hand big files to S3's multipart upload
+//! API without ever holding the whole file:
+//!
+//! ```rust,no_run
+//! use torch_web::extractors::{MultipartStream, Path};
+//! use torch_web::{App, Response};
+//!
+//! let app = App::new().post("/videos/:id", |Path(id): Path<u64>, mut form: MultipartStream| async move {
+//!     while let Ok(Some(field)) = form.next_field().await {
+//!         if field.name() != "video" {
+//!             continue;
+//!         }
+//!         let stored = field
+//!             .store_with_progress("s3", &format!("videos/{}.mp4", id), |progress| {
+//!                 println!("{} bytes so far", progress.sent);
+//!             })
+//!             .await;
+//!         return match stored {
+//!             Ok(()) => Response::ok().body("Uploaded"),
+//!             Err(error) => Response::internal_error().body(error.to_string()),
+//!         };
+//!     }
+//!     Response::bad_request().body("No video")
+//! });
+//! ```
+//!
+//! An upload that fails or is dropped half way, e.g. because the client
+//! disconnected or the handler was cancelled, is cleaned up: the S3
+//! multipart upload is aborted and a local disk removes its partial file.
+//!
+//! ## Testing
+//!
+//! [`Storage::fake`] replaces a disk with an in-memory one for the current
+//! thread:
+//!
+//! ```rust,no_run
+//! use torch_web::storage::Storage;
+//!
+//! # async fn example() {
+//! let avatars = Storage::fake("public");
+//!
+//! Storage::disk("public").unwrap().put("avatars/1.png", vec![1, 2, 3]).await.unwrap();
+//!
+//! avatars.assert_exists("avatars/1.png");
+//! avatars.assert_missing("avatars/2.png");
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use hyper::body::Bytes;
+
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "s3")]
+pub use s3::S3Disk;
+
+/// Size of the parts [`upload`] sends; S3 accepts no smaller parts except
+/// the last one
+pub const PART_SIZE: usize = 5 * 1024 * 1024;
+
+type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, StorageError>> + Send + 'a>>;
+
+/// Errors raised by storage disks
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageError {
+    /// No disk is registered under this name
+    UnknownDisk(String),
+    /// The file doesn't exist
+    NotFound(String),
+    /// The path escapes the disk root or is otherwise unusable
+    InvalidPath(String),
+    /// The underlying filesystem or service failed
+    Io(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::UnknownDisk(name) => write!(f, "Storage disk '{}' is not configured", name),
+            StorageError::NotFound(path) => write!(f, "File not found: {}", path),
+            StorageError::InvalidPath(path) => write!(f, "Invalid storage path: {}", path),
+            StorageError::Io(message) => write!(f, "Storage error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// A place files can be stored
+pub trait Disk: Send + Sync + 'static {
+    fn put(&self, path: &str, contents: Vec<u8>) -> StorageFuture<'_, ()>;
+    fn get(&self, path: &str) -> StorageFuture<'_, Vec<u8>>;
+    fn exists(&self, path: &str) -> StorageFuture<'_, bool>;
+    /// Returns whether a file was actually removed
+    fn delete(&self, path: &str) -> StorageFuture<'_, bool>;
+
+    /// Public URL for a file, if this disk is web-accessible
+    fn url(&self, _path: &str) -> Option<String> {
+        None
+    }
+
+    /// Begin writing `path` part by part. By default the parts are collected
+    /// and [`put`](Self::put) once the upload finishes.
+    fn start_upload(&self, path: &str) -> StorageFuture<'_, Box<dyn Upload + '_>> {
+        let path = path.to_string();
+        Box::pin(async move {
+            let upload: Box<dyn Upload + '_> = Box::new(BufferedUpload {
+                put: Some(Box::new(move |contents| self.put(&path, contents))),
+                contents: Vec::new(),
+            });
+            Ok(upload)
+        })
+    }
+}
+
+/// A file being written to a disk in parts, from [`Disk::start_upload`].
+///
+/// Dropping an upload before [`finish`](Self::finish) discards what was
+/// written so far.
+pub trait Upload: Send {
+    fn write(&mut self, part: Bytes) -> StorageFuture<'_, ()>;
+    /// Make the file visible at its path
+    fn finish(&mut self) -> StorageFuture<'_, ()>;
+    /// Discard the parts written so far
+    fn abort(&mut self) -> StorageFuture<'_, ()>;
+}
+
+type PutFn<'a> = Box<dyn FnOnce(Vec<u8>) -> StorageFuture<'a, ()> + Send + 'a>;
+
+struct BufferedUpload<'a> {
+    put: Option<PutFn<'a>>,
+    contents: Vec<u8>,
+}
+
+impl Upload for BufferedUpload<'_> {
+    fn write(&mut self, part: Bytes) -> StorageFuture<'_, ()> {
+        self.contents.extend_from_slice(&part);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn finish(&mut self) -> StorageFuture<'_, ()> {
+        let put = self.put.take();
+        let contents = std::mem::take(&mut self.contents);
+        Box::pin(async move {
+            match put {
+                Some(put) => put(contents).await,
+                None => Err(StorageError::Io("upload already finished".to_string())),
+            }
+        })
+    }
+
+    fn abort(&mut self) -> StorageFuture<'_, ()> {
+        self.put = None;
+        self.contents = Vec::new();
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// How far an [`upload`] got. `total` is 0 when the size isn't known up
+/// front, as for [`upload_stream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub sent: u64,
+    pub total: u64,
+}
+
+/// Write `contents` to `path` in [`PART_SIZE`] parts, reporting progress
+/// after each one. A failed upload is aborted before the error is returned.
+pub async fn upload(
+    disk: &dyn Disk,
+    path: &str,
+    contents: Bytes,
+    progress: impl FnMut(UploadProgress) + Send,
+) -> Result<(), StorageError> {
+    let total = contents.len() as u64;
+    let parts = (0..contents.len())
+        .step_by(PART_SIZE)
+        .map(|start| Ok::<_, StorageError>(contents.slice(start..(start + PART_SIZE).min(contents.len()))));
+    send_parts(disk, path, futures::stream::iter(parts), total, progress).await
+}
+
+/// Write the chunks of `contents` to `path` as they arrive, in parts of at
+/// least [`PART_SIZE`], reporting progress after each part. Only one part is
+/// held in memory at a time, and when the stream fails, e.g. because the
+/// client sending the file disconnected, the upload is aborted.
+pub async fn upload_stream<E: std::fmt::Display>(
+    disk: &dyn Disk,
+    path: &str,
+    contents: impl futures::Stream<Item = Result<Bytes, E>> + Send,
+    progress: impl FnMut(UploadProgress) + Send,
+) -> Result<(), StorageError> {
+    send_parts(disk, path, contents, 0, progress).await
+}
+
+async fn send_parts<E: std::fmt::Display>(
+    disk: &dyn Disk,
+    path: &str,
+    contents: impl futures::Stream<Item = Result<Bytes, E>> + Send,
+    total: u64,
+    mut progress: impl FnMut(UploadProgress) + Send,
+) -> Result<(), StorageError> {
+    use futures::StreamExt;
+
+    let mut upload = disk.start_upload(path).await?;
+    let result = async {
+        let mut contents = std::pin::pin!(contents);
+        let mut pending = Vec::new();
+        let mut sent = 0;
+        loop {
+            let chunk = match contents.next().await {
+                Some(Ok(chunk)) => Some(chunk),
+                Some(Err(error)) => return Err(StorageError::Io(format!("upload interrupted: {}", error))),
+                None => None,
+            };
+            let last = chunk.is_none();
+            let part = match chunk {
+                // Whole parts go through without being copied
+                Some(chunk) if pending.is_empty() && chunk.len() >= PART_SIZE => chunk,
+                Some(chunk) => {
+                    pending.extend_from_slice(&chunk);
+                    if pending.len() < PART_SIZE {
+                        continue;
+                    }
+                    Bytes::from(std::mem::take(&mut pending))
+                }
+                None if pending.is_empty() => break,
+                None => Bytes::from(std::mem::take(&mut pending)),
+            };
+            sent += part.len() as u64;
+            upload.write(part).await?;
+            progress(UploadProgress { sent, total });
+            if last {
+                break;
+            }
+        }
+        upload.finish().await
+    }
+    .await;
+
+    if let Err(error) = &result {
+        if let Err(abort_error) = upload.abort().await {
+            eprintln!("⚠️  Could not clean up failed upload of {} ({}): {}", path, error, abort_error);
+        }
+    }
+    result
+}
+
+/// Normalize a disk-relative path, rejecting anything that escapes the root
+fn clean_path(path: &str) -> Result<String, StorageError> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(StorageError::InvalidPath(path.to_string()));
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(StorageError::InvalidPath(path.to_string()));
+    }
+    Ok(parts.join("/"))
+}
+
+/// Disk backed by a directory on the local filesystem
+#[derive(Debug, Clone)]
+pub struct LocalDisk {
+    root: PathBuf,
+    url: Option<String>,
+}
+
+impl LocalDisk {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            url: None,
+        }
+    }
+
+    /// Serve files from this disk under `base_url`
+    pub fn with_url(mut self, base_url: impl Into<String>) -> Self {
+        self.url = Some(base_url.into());
+        self
+    }
+
+    fn full_path(&self, path: &str) -> Result<PathBuf, StorageError> {
+        Ok(self.root.join(clean_path(path)?))
+    }
+}
+
+impl Disk for LocalDisk {
+    fn put(&self, path: &str, contents: Vec<u8>) -> StorageFuture<'_, ()> {
+        let full_path = self.full_path(path);
+        Box::pin(async move {
+            let full_path = full_path?;
+            if let Some(parent) = full_path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(|e| StorageError::Io(e.to_string()))?;
+            }
+            tokio::fs::write(&full_path, contents).await.map_err(|e| StorageError::Io(e.to_string()))
+        })
+    }
+
+    fn get(&self, path: &str) -> StorageFuture<'_, Vec<u8>> {
+        let full_path = self.full_path(path);
+        let path = path.to_string();
+        Box::pin(async move {
+            tokio::fs::read(full_path?).await.map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => StorageError::NotFound(path),
+                _ => StorageError::Io(e.to_string()),
+            })
+        })
+    }
+
+    fn exists(&self, path: &str) -> StorageFuture<'_, bool> {
+        let full_path = self.full_path(path);
+        Box::pin(async move {
+            tokio::fs::try_exists(full_path?).await.map_err(|e| StorageError::Io(e.to_string()))
+        })
+    }
+
+    fn delete(&self, path: &str) -> StorageFuture<'_, bool> {
+        let full_path = self.full_path(path);
+        Box::pin(async move {
+            match tokio::fs::remove_file(full_path?).await {
+                Ok(()) => Ok(true),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(StorageError::Io(e.to_string())),
+            }
+        })
+    }
+
+    fn url(&self, path: &str) -> Option<String> {
+        let base = self.url.as_ref()?;
+        let path = clean_path(path).ok()?;
+        Some(format!("{}/{}", base.trim_end_matches('/'), path))
+    }
+
+    /// Parts go to a `.part` file next to the target, renamed into place
+    /// once finished
+    fn start_upload(&self, path: &str) -> StorageFuture<'_, Box<dyn Upload + '_>> {
+        let full_path = self.full_path(path);
+        Box::pin(async move {
+            let full_path = full_path?;
+            if let Some(parent) = full_path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(|e| StorageError::Io(e.to_string()))?;
+            }
+            let mut partial = full_path.clone().into_os_string();
+            partial.push(".part");
+            let partial = PathBuf::from(partial);
+            let file = tokio::fs::File::create(&partial).await.map_err(|e| StorageError::Io(e.to_string()))?;
+            let upload: Box<dyn Upload + '_> = Box::new(LocalUpload {
+                file: Some(file),
+                partial,
+                full_path,
+            });
+            Ok(upload)
+        })
+    }
+}
+
+struct LocalUpload {
+    /// `None` once finished or aborted
+    file: Option<tokio::fs::File>,
+    partial: PathBuf,
+    full_path: PathBuf,
+}
+
+impl Upload for LocalUpload {
+    fn write(&mut self, part: Bytes) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            use tokio::io::AsyncWriteExt;
+
+            let file = self.file.as_mut().ok_or_else(|| StorageError::Io("upload already finished".to_string()))?;
+            file.write_all(&part).await.map_err(|e| StorageError::Io(e.to_string()))
+        })
+    }
+
+    fn finish(&mut self) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            use tokio::io::AsyncWriteExt;
+
+            let mut file = self.file.take().ok_or_else(|| StorageError::Io("upload already finished".to_string()))?;
+            file.flush().await.map_err(|e| StorageError::Io(e.to_string()))?;
+            drop(file);
+            tokio::fs::rename(&self.partial, &self.full_path).await.map_err(|e| StorageError::Io(e.to_string()))
+        })
+    }
+
+    fn abort(&mut self) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            if self.file.take().is_some() {
+                let _ = tokio::fs::remove_file(&self.partial).await;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for LocalUpload {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// Disk that keeps files in memory; used by [`Storage::fake`]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDisk {
+    files: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemoryDisk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths of every stored file, sorted
+    pub fn files(&self) -> Vec<String> {
+        let mut files: Vec<String> = self.files.read().unwrap().keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Assert a file exists on this disk
+    pub fn assert_exists(&self, path: &str) {
+        let path = clean_path(path).unwrap_or_else(|e| panic!("{}", e));
+        assert!(
+            self.files.read().unwrap().contains_key(&path),
+            "expected file {} to exist; stored files: {:?}",
+            path,
+            self.files()
+        );
+    }
+
+    /// Assert a file does not exist on this disk
+    pub fn assert_missing(&self, path: &str) {
+        let path = clean_path(path).unwrap_or_else(|e| panic!("{}", e));
+        assert!(
+            !self.files.read().unwrap().contains_key(&path),
+            "expected file {} not to exist",
+            path
+        );
+    }
+}
+
+impl Disk for MemoryDisk {
+    fn put(&self, path: &str, contents: Vec<u8>) -> StorageFuture<'_, ()> {
+        let result = clean_path(path).map(|path| {
+            self.files.write().unwrap().insert(path, contents);
+        });
+        Box::pin(async move { result })
+    }
+
+    fn get(&self, path: &str) -> StorageFuture<'_, Vec<u8>> {
+        let result = clean_path(path).and_then(|path| {
+            self.files
+                .read()
+                .unwrap()
+                .get(&path)
+                .cloned()
+                .ok_or(StorageError::NotFound(path))
+        });
+        Box::pin(async move { result })
+    }
+
+    fn exists(&self, path: &str) -> StorageFuture<'_, bool> {
+        let result = clean_path(path).map(|path| self.files.read().unwrap().contains_key(&path));
+        Box::pin(async move { result })
+    }
+
+    fn delete(&self, path: &str) -> StorageFuture<'_, bool> {
+        let result = clean_path(path).map(|path| self.files.write().unwrap().remove(&path).is_some());
+        Box::pin(async move { result })
+    }
+
+    fn url(&self, path: &str) -> Option<String> {
+        clean_path(path).ok().map(|path| format!("/storage/{}", path))
+    }
+}
+
+fn disks() -> &'static RwLock<HashMap<String, Arc<dyn Disk>>> {
+    static DISKS: OnceLock<RwLock<HashMap<String, Arc<dyn Disk>>>> = OnceLock::new();
+    DISKS.get_or_init(|| {
+        let mut disks: HashMap<String, Arc<dyn Disk>> = HashMap::new();
+        disks.insert("local".to_string(), Arc::new(LocalDisk::new("storage/app")));
+        disks.insert(
+            "public".to_string(),
+            Arc::new(LocalDisk::new("storage/app/public").with_url("/storage")),
+        );
+        RwLock::new(disks)
+    })
+}
+
+thread_local! {
+    static FAKES: RefCell<HashMap<String, MemoryDisk>> = RefCell::new(HashMap::new());
+}
+
+/// Storage facade
+pub struct Storage;
+
+impl Storage {
+    /// Register (or replace) a named disk
+    pub fn register<D: Disk>(name: &str, disk: D) {
+        disks().write().unwrap().insert(name.to_string(), Arc::new(disk));
+    }
+
+    /// Look up a disk by name
+    pub fn disk(name: &str) -> Result<Arc<dyn Disk>, StorageError> {
+        if let Some(fake) = FAKES.with(|fakes| fakes.borrow().get(name).cloned()) {
+            return Ok(Arc::new(fake));
+        }
+
+        disks()
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| StorageError::UnknownDisk(name.to_string()))
+    }
+
+    /// Swap `name` for an empty in-memory disk until the returned guard is
+    /// dropped
+    pub fn fake(name: &str) -> StorageFake {
+        let disk = MemoryDisk::new();
+        FAKES.with(|fakes| fakes.borrow_mut().insert(name.to_string(), disk.clone()));
+        StorageFake {
+            name: name.to_string(),
+            disk,
+        }
+    }
+}
+
+/// Guard returned by [`Storage::fake`]; dereferences to the fake
+/// [`MemoryDisk`] for assertions
+#[must_use = "the storage fake is removed when this guard is dropped"]
+pub struct StorageFake {
+    name: String,
+    disk: MemoryDisk,
+}
+
+impl std::ops::Deref for StorageFake {
+    type Target = MemoryDisk;
+
+    fn deref(&self) -> &MemoryDisk {
+        &self.disk
+    }
+}
+
+impl Drop for StorageFake {
+    fn drop(&mut self) {
+        FAKES.with(|fakes| fakes.borrow_mut().remove(&self.name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_path() {
+        assert_eq!(clean_path("/avatars//1.png").unwrap(), "avatars/1.png");
+        assert_eq!(clean_path("./a/b.txt").unwrap(), "a/b.txt");
+        assert!(clean_path("../etc/passwd").is_err());
+        assert!(clean_path("a/../../b").is_err());
+        assert!(clean_path("/").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fake_disk() {
+        let public = Storage::fake("public");
+        let disk = Storage::disk("public").unwrap();
+
+        disk.put("avatars/1.png", vec![1, 2, 3]).await.unwrap();
+
+        public.assert_exists("avatars/1.png");
+        public.assert_missing("avatars/2.png");
+        assert_eq!(disk.get("/avatars/1.png").await.unwrap(), vec![1, 2, 3]);
+        assert!(disk.delete("avatars/1.png").await.unwrap());
+        public.assert_missing("avatars/1.png");
+    }
+
+    #[tokio::test]
+    async fn test_uploads_go_in_parts_and_clean_up() {
+        let contents = Bytes::from(vec![7u8; PART_SIZE * 2 + 10]);
+        let memory = MemoryDisk::new();
+        let mut reports = Vec::new();
+        upload(&memory, "videos/a.mp4", contents.clone(), |progress| reports.push(progress)).await.unwrap();
+        assert_eq!(memory.get("videos/a.mp4").await.unwrap().len(), contents.len());
+        let total = contents.len() as u64;
+        assert_eq!(
+            reports,
+            vec![
+                UploadProgress { sent: PART_SIZE as u64, total },
+                UploadProgress { sent: PART_SIZE as u64 * 2, total },
+                UploadProgress { sent: total, total },
+            ]
+        );
+
+        let root = std::env::temp_dir().join(format!("torch-storage-upload-{}", std::process::id()));
+        let disk = LocalDisk::new(&root);
+        upload(&disk, "videos/a.mp4", Bytes::from_static(b"frames"), |_| {}).await.unwrap();
+        assert_eq!(disk.get("videos/a.mp4").await.unwrap(), b"frames");
+
+        // Cancelled half way: the partial file goes away
+        let mut unfinished = disk.start_upload("videos/b.mp4").await.unwrap();
+        unfinished.write(Bytes::from_static(b"fra")).await.unwrap();
+        assert!(root.join("videos/b.mp4.part").exists());
+        drop(unfinished);
+        assert!(!root.join("videos/b.mp4.part").exists());
+        assert!(!disk.exists("videos/b.mp4").await.unwrap());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_uploads_buffer_parts_and_abort_when_interrupted() {
+        let chunk = Bytes::from(vec![1u8; PART_SIZE / 2 + 1]);
+        let chunks = vec![Ok::<_, String>(chunk.clone()), Ok(chunk.clone()), Ok(chunk.clone())];
+        let memory = MemoryDisk::new();
+        let mut reports = Vec::new();
+        upload_stream(&memory, "videos/a.mp4", futures::stream::iter(chunks), |progress| reports.push(progress.sent))
+            .await
+            .unwrap();
+        assert_eq!(memory.get("videos/a.mp4").await.unwrap().len(), chunk.len() * 3);
+        assert_eq!(reports, vec![chunk.len() as u64 * 2, chunk.len() as u64 * 3]);
+
+        let root = std::env::temp_dir().join(format!("torch-storage-stream-{}", std::process::id()));
+        let disk = LocalDisk::new(&root);
+        let disconnected = vec![Ok(Bytes::from_static(b"fra")), Err("connection reset")];
+        let result = upload_stream(&disk, "videos/b.mp4", futures::stream::iter(disconnected), |_| {}).await;
+        assert!(matches!(result, Err(StorageError::Io(message)) if message.contains("connection reset")));
+        assert!(!root.join("videos/b.mp4.part").exists());
+        assert!(!disk.exists("videos/b.mp4").await.unwrap());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_unknown_disk() {
+        assert!(matches!(Storage::disk("nope"), Err(StorageError::UnknownDisk(_))));
+    }
+
+    #[tokio::test]
+    async fn test_local_disk_round_trip() {
+        let root = std::env::temp_dir().join(format!("torch-storage-{}", std::process::id()));
+        let disk = LocalDisk::new(&root).with_url("/files/");
+
+        disk.put("docs/readme.txt", b"hello".to_vec()).await.unwrap();
+        assert!(disk.exists("docs/readme.txt").await.unwrap());
+        assert_eq!(disk.get("docs/readme.txt").await.unwrap(), b"hello");
+        assert_eq!(disk.url("docs/readme.txt").as_deref(), Some("/files/docs/readme.txt"));
+        assert!(disk.delete("docs/readme.txt").await.unwrap());
+        assert!(matches!(disk.get("docs/readme.txt").await, Err(StorageError::NotFound(_))));
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
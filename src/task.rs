@@ -0,0 +1,264 @@
+//! # Blocking Work
+//!
+//! Handlers run on the async runtime's worker threads, so a handler that
+//! hashes a password or resizes an image for 200ms stalls every other
+//! request on that thread. Move such work onto the blocking thread pool:
+//!
+//! ```rust,no_run
+//! use torch_web::App;
+//! use torch_web::task::{self, Blocking};
+//!
+//! # fn hash_password(password: &str) -> String { password.to_string() }
+//! # fn resize(image: &[u8], width: u32) -> Vec<u8> { image.to_vec() }
+//! # async fn example(image: Vec<u8>) -> Result<(), task::BlockingError> {
+//! // Owned data: runs on tokio's blocking pool
+//! let hash = task::spawn_blocking("hash-password", move || hash_password("hunter2")).await?;
+//!
+//! // Borrowed data: runs in place after handing the worker thread's other
+//! // tasks to another thread
+//! let thumbnail = task::spawn_blocking_scoped("resize", || resize(&image, 200))?;
+//! # Ok(())
+//! # }
+//!
+//! // At most four resizes at once, whatever the traffic
+//! let app = App::new()
+//!     .with_state(Blocking::limited(4))
+//!     .post("/avatar", |blocking: Blocking| async move {
+//!         blocking.run("resize", || vec![0u8; 16]).await.map(|_| "resized")
+//!     });
+//! ```
+//!
+//! Rules of thumb: anything that takes more than about 100µs without an
+//! `.await` (hashing, compression, image work, big JSON or CSV documents)
+//! belongs here. `std::fs` and other blocking calls also belong here when no
+//! async version is at hand.
+//!
+//! Each run is timed. With the `monitoring` feature
+//! `torch_blocking_task_wait_seconds` records how long the work waited for a
+//! thread or a [`Blocking`] slot, and `torch_blocking_task_duration_seconds`
+//! how long it ran, both labelled with the task name. Runs slower than
+//! [`SLOW_TASK`] are logged either way.
+
+use std::fmt;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::Semaphore;
+
+use crate::extractors::state::RequestStateExt;
+use crate::extractors::{FromRequestParts, IntoResponse};
+use crate::{Request, Response};
+
+/// Runs longer than this are logged, as a hint to split the work up or move
+/// it to a queued job
+pub const SLOW_TASK: Duration = Duration::from_secs(2);
+
+/// Errors from running blocking work
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockingError {
+    /// The work panicked; holds the panic message
+    Panicked(String),
+    /// The runtime shut down before the work ran
+    Cancelled,
+}
+
+impl fmt::Display for BlockingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockingError::Panicked(message) => write!(f, "Blocking task panicked: {}", message),
+            BlockingError::Cancelled => write!(f, "Blocking task was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for BlockingError {}
+
+/// A failed blocking task is a server error
+impl IntoResponse for BlockingError {
+    fn into_response(self) -> Response {
+        eprintln!("⚠️  {}", self);
+        #[cfg(feature = "json")]
+        crate::dev::inspector::record_exception(&self);
+        Response::internal_error()
+    }
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => panic.downcast_ref::<&str>().map_or_else(|| "unknown panic".to_string(), |message| message.to_string()),
+    }
+}
+
+#[cfg(feature = "monitoring")]
+fn record(name: &'static str, waited: Duration, ran: Duration) {
+    metrics::histogram!("torch_blocking_task_wait_seconds", waited.as_secs_f64(), "task" => name);
+    metrics::histogram!("torch_blocking_task_duration_seconds", ran.as_secs_f64(), "task" => name);
+    log_slow(name, ran);
+}
+
+#[cfg(not(feature = "monitoring"))]
+fn record(name: &'static str, _waited: Duration, ran: Duration) {
+    log_slow(name, ran);
+}
+
+fn log_slow(name: &'static str, ran: Duration) {
+    if ran > SLOW_TASK {
+        eprintln!("🐢 Blocking task {} took {:.2}s", name, ran.as_secs_f64());
+    }
+}
+
+/// Run `work` on the blocking thread pool and wait for its result
+pub async fn spawn_blocking<F, T>(name: &'static str, work: F) -> Result<T, BlockingError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    run_blocking(name, Instant::now(), work).await
+}
+
+/// Run `work` on the blocking pool, counting the time since `queued` as
+/// waiting
+async fn run_blocking<F, T>(name: &'static str, queued: Instant, work: F) -> Result<T, BlockingError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || {
+        let waited = queued.elapsed();
+        let started = Instant::now();
+        let output = catch_unwind(AssertUnwindSafe(work));
+        record(name, waited, started.elapsed());
+        output
+    })
+    .await;
+
+    match result {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(panic)) => Err(BlockingError::Panicked(panic_message(panic))),
+        Err(error) if error.is_panic() => Err(BlockingError::Panicked(panic_message(error.into_panic()))),
+        Err(_) => Err(BlockingError::Cancelled),
+    }
+}
+
+/// Run `work`, which may borrow from the caller, without stalling other
+/// tasks on this worker thread.
+///
+/// On the multi-threaded runtime the thread's other tasks move elsewhere
+/// while `work` runs in place. The current-thread runtime has nowhere to
+/// move them, so there `work` simply runs; prefer [`spawn_blocking`] in code
+/// that may run on it.
+pub fn spawn_blocking_scoped<F, T>(name: &'static str, work: F) -> Result<T, BlockingError>
+where
+    F: FnOnce() -> T + Send,
+    T: Send,
+{
+    let run = || {
+        let started = Instant::now();
+        let output = catch_unwind(AssertUnwindSafe(work));
+        record(name, Duration::ZERO, started.elapsed());
+        output.map_err(|panic| BlockingError::Panicked(panic_message(panic)))
+    };
+
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(run),
+        _ => run(),
+    }
+}
+
+/// A share of the blocking thread pool, taken by handlers as an extractor.
+///
+/// Register [`Blocking::limited`] as app state to cap how much blocking work
+/// requests may run at once; without it the extractor gives an unlimited
+/// share.
+#[derive(Clone, Debug, Default)]
+pub struct Blocking {
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl Blocking {
+    /// No cap beyond tokio's own blocking pool size
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Run at most `limit` pieces of work at once; the rest wait their turn
+    pub fn limited(limit: usize) -> Self {
+        Self {
+            permits: Some(Arc::new(Semaphore::new(limit.max(1)))),
+        }
+    }
+
+    /// Run `work` on the blocking pool once a slot is free
+    pub async fn run<F, T>(&self, name: &'static str, work: F) -> Result<T, BlockingError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let Some(permits) = &self.permits else {
+            return spawn_blocking(name, work).await;
+        };
+        let queued = Instant::now();
+        let _permit = permits.acquire().await.map_err(|_| BlockingError::Cancelled)?;
+        run_blocking(name, queued, work).await
+    }
+}
+
+impl FromRequestParts for Blocking {
+    type Error = std::convert::Infallible;
+
+    fn from_request_parts(req: &mut Request) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let blocking = req
+            .get_state(std::any::TypeId::of::<Blocking>())
+            .and_then(|state| state.downcast_ref::<Blocking>())
+            .cloned()
+            .unwrap_or_default();
+        Box::pin(async move { Ok(blocking) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_blocking_work_runs_off_the_runtime() {
+        let words = ["torch"; 3];
+        let joined = spawn_blocking_scoped("join", || words.join(" ")).unwrap();
+        assert_eq!(joined, "torch torch torch");
+
+        let hashed = spawn_blocking("hash", || (0..1_000u64).sum::<u64>()).await;
+        assert_eq!(hashed, Ok(499_500));
+        assert_eq!(
+            spawn_blocking("explode", || -> u32 { panic!("bad pixel") }).await,
+            Err(BlockingError::Panicked("bad pixel".to_string()))
+        );
+
+        // Two slots: never more than two running at once
+        let blocking = Blocking::limited(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let runs = (0..6).map(|_| {
+            let (blocking, running, peak) = (blocking.clone(), running.clone(), peak.clone());
+            tokio::spawn(async move {
+                blocking
+                    .run("resize", move || {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(20));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                    .await
+            })
+        });
+        for run in runs.collect::<Vec<_>>() {
+            run.await.unwrap().unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,590 @@
+//! # Multi-Tenancy
+//!
+//! Serve many tenants (customers, organisations, workspaces) from one app.
+//! [`ResolveTenant`] works out the tenant of each request from its subdomain,
+//! a header or the first path segment, and makes it available while the
+//! request is handled:
+//!
+//! - handlers take it as a [`Tenant`] extractor, and anything else can ask
+//!   [`Tenancy::current`];
+//! - with the `database` feature, `tenant_scope` limits a model's queries to
+//!   the tenant's rows (and to none outside a tenant), and tenants with a
+//!   database or schema of their own get their own connection pool from
+//!   `orm::connection::get_pool`;
+//! - [`TenantCache`] and `CacheMiddleware` prefix cache keys with the tenant,
+//!   so tenants never see each other's cached data.
+//!
+//! ```rust,no_run
+//! use torch_web::tenancy::{ResolveTenant, Tenant};
+//! use torch_web::{App, Response};
+//!
+//! let app = App::new()
+//!     .middleware(ResolveTenant::subdomain("example.com").lookup(|id: String| async move {
+//!         // Look the tenant up in the central database
+//!         match id.as_str() {
+//!             "acme" => Some(Tenant::new("acme").schema("tenant_acme")),
+//!             _ => None,
+//!         }
+//!     }))
+//!     .get("/", |tenant: Tenant| async move { Response::ok().body(format!("Welcome, {}", tenant.id)) });
+//! ```
+//!
+//! Work done outside a request, like queued jobs and console commands, runs
+//! for a tenant inside [`Tenancy::scope`], or across every tenant inside
+//! [`Tenancy::without_tenant`].
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cache::{Cache, CacheFuture};
+use crate::extractors::FromRequestParts;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+
+/// A tenant and where its data lives
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub id: String,
+    /// Database of its own, instead of the app's
+    pub database_url: Option<String>,
+    /// Postgres schema (or MySQL database) on the app's database server
+    pub schema: Option<String>,
+}
+
+impl Tenant {
+    pub fn new(id: impl ToString) -> Self {
+        Self {
+            id: id.to_string(),
+            database_url: None,
+            schema: None,
+        }
+    }
+
+    /// Connect to `url` while handling this tenant's requests
+    pub fn database(mut self, url: &str) -> Self {
+        self.database_url = Some(url.to_string());
+        self
+    }
+
+    /// Use `schema` while handling this tenant's requests
+    pub fn schema(mut self, schema: &str) -> Self {
+        self.schema = Some(schema.to_string());
+        self
+    }
+
+    /// Prefix for this tenant's cache keys
+    pub fn cache_prefix(&self) -> String {
+        format!("tenant:{}:", self.id)
+    }
+}
+
+impl FromRequestParts for Tenant {
+    type Error = Response;
+
+    fn from_request_parts(
+        req: &mut Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Error>> + Send + 'static>> {
+        let tenant = req.get_extension::<Tenant>().cloned();
+        Box::pin(async move { tenant.ok_or_else(|| Response::not_found().body("Tenant not found")) })
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Tenant;
+    static ALL_TENANTS: ();
+}
+
+/// Access to the tenant being served
+pub struct Tenancy;
+
+impl Tenancy {
+    /// Tenant of the current request or [`Tenancy::scope`]
+    pub fn current() -> Option<Tenant> {
+        CURRENT.try_with(Tenant::clone).ok()
+    }
+
+    /// Run `future` on behalf of `tenant`
+    pub async fn scope<F: Future>(tenant: Tenant, future: F) -> F::Output {
+        CURRENT.scope(tenant, future).await
+    }
+
+    /// Run `future` on behalf of no tenant in particular, so `tenant_scope`
+    /// lets its queries see every tenant's rows, e.g. for an admin console
+    /// or a report across tenants. Work done for a tenant, in a request or
+    /// [`Tenancy::scope`], stays limited to that tenant's rows.
+    pub async fn without_tenant<F: Future>(future: F) -> F::Output {
+        ALL_TENANTS.scope((), future).await
+    }
+
+    /// Whether the caller opted out of tenant scoping with
+    /// [`Tenancy::without_tenant`]
+    #[cfg(feature = "database")]
+    fn is_without_tenant() -> bool {
+        ALL_TENANTS.try_with(|_| ()).is_ok()
+    }
+}
+
+/// How the tenant is identified
+#[derive(Debug, Clone)]
+enum Identify {
+    /// `acme.example.com` is tenant `acme`
+    Subdomain(String),
+    Header(String),
+    /// `/acme/...` is tenant `acme`
+    Path,
+}
+
+type TenantLookup = Arc<dyn Fn(String) -> Pin<Box<dyn Future<Output = Option<Tenant>> + Send>> + Send + Sync>;
+
+/// Middleware that identifies each request's tenant.
+///
+/// Requests whose tenant can't be identified or isn't found get
+/// `404 Not Found`, unless the middleware is [`optional`](Self::optional).
+///
+/// Headers and paths are chosen by the client, so [`header`](Self::header)
+/// and [`path`](Self::path) need a [`lookup`](Self::lookup) or a
+/// [`tenants`](Self::tenants) list that only finds tenants the request may
+/// use; without one every request gets `500 Internal Server Error`. Without
+/// a lookup, [`subdomain`](Self::subdomain) accepts any subdomain as a
+/// tenant.
+#[derive(Clone)]
+pub struct ResolveTenant {
+    identify: Identify,
+    lookup: Option<TenantLookup>,
+    optional: bool,
+}
+
+impl ResolveTenant {
+    /// Identify tenants by the subdomain of `base_domain`
+    pub fn subdomain(base_domain: &str) -> Self {
+        Self::identify_by(Identify::Subdomain(base_domain.trim_start_matches('.').to_lowercase()))
+    }
+
+    /// Identify tenants by the value of the `name` header.
+    ///
+    /// Needs a [`lookup`](Self::lookup): any client can send any header, so
+    /// the lookup has to check the tenant exists and, with the request's
+    /// credentials, that the caller belongs to it.
+    pub fn header(name: &str) -> Self {
+        Self::identify_by(Identify::Header(name.to_string()))
+    }
+
+    /// Identify tenants by the first path segment; define the routes under
+    /// `/:tenant/`.
+    ///
+    /// Needs a [`lookup`](Self::lookup), as with [`header`](Self::header).
+    pub fn path() -> Self {
+        Self::identify_by(Identify::Path)
+    }
+
+    fn identify_by(identify: Identify) -> Self {
+        Self {
+            identify,
+            lookup: None,
+            optional: false,
+        }
+    }
+
+    /// Find the tenant for an identifier, returning `None` for unknown ones
+    pub fn lookup<F, Fut>(mut self, lookup: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Tenant>> + Send + 'static,
+    {
+        self.lookup = Some(Arc::new(move |id| Box::pin(lookup(id))));
+        self
+    }
+
+    /// Only accept these tenants
+    pub fn tenants(self, tenants: impl IntoIterator<Item = Tenant>) -> Self {
+        let tenants: Arc<HashMap<String, Tenant>> =
+            Arc::new(tenants.into_iter().map(|tenant| (tenant.id.clone(), tenant)).collect());
+        self.lookup(move |id| {
+            let tenant = tenants.get(&id).cloned();
+            async move { tenant }
+        })
+    }
+
+    /// Let requests without a tenant through, e.g. for a landing page on the
+    /// bare domain
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// The tenant identifier of a request, before lookup
+    pub fn identify(&self, req: &Request) -> Option<String> {
+        let id = match &self.identify {
+            Identify::Subdomain(base) => {
+                let host = req.header("host")?;
+                let host = host.rsplit_once(':').map_or(host, |(name, port)| {
+                    if port.chars().all(|c| c.is_ascii_digit()) { name } else { host }
+                });
+                let host = host.to_lowercase();
+                let subdomain = host.strip_suffix(base.as_str())?.strip_suffix('.')?;
+                (!subdomain.contains('.')).then(|| subdomain.to_string())
+            }
+            Identify::Header(name) => req.header(name).map(|value| value.trim().to_string()),
+            Identify::Path => req.path().trim_start_matches('/').split('/').next().map(str::to_string),
+        };
+        id.filter(|id| !id.is_empty())
+    }
+}
+
+impl Middleware for ResolveTenant {
+    fn call(
+        &self,
+        mut req: Request,
+        next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let id = self.identify(&req);
+        let lookup = self.lookup.clone();
+        let identify = self.identify.clone();
+        let optional = self.optional;
+
+        Box::pin(async move {
+            let tenant = match (id, lookup) {
+                (Some(id), Some(lookup)) => lookup(id).await,
+                (Some(id), None) if matches!(identify, Identify::Subdomain(_)) => Some(Tenant::new(id)),
+                (_, None) if !matches!(identify, Identify::Subdomain(_)) => {
+                    eprintln!("⚠️  ResolveTenant::header and ResolveTenant::path need a lookup; refusing the request");
+                    return Response::internal_error().body("Tenant lookup not configured");
+                }
+                _ => None,
+            };
+
+            match tenant {
+                Some(tenant) => {
+                    req.insert_extension(tenant.clone());
+                    Tenancy::scope(tenant, next(req)).await
+                }
+                None if optional => next(req).await,
+                None => Response::not_found().body("Tenant not found"),
+            }
+        })
+    }
+}
+
+/// Cache that keeps each tenant's keys apart by prefixing them with
+/// [`Tenant::cache_prefix`]. Keys used outside a tenant are left as they are.
+#[derive(Clone)]
+pub struct TenantCache {
+    inner: Arc<dyn Cache>,
+}
+
+impl TenantCache {
+    pub fn new(inner: Arc<dyn Cache>) -> Self {
+        Self { inner }
+    }
+
+    fn key(key: &str) -> String {
+        match Tenancy::current() {
+            Some(tenant) => format!("{}{}", tenant.cache_prefix(), key),
+            None => key.to_string(),
+        }
+    }
+}
+
+// Keys are prefixed when the future runs, so a future created outside
+// `Tenancy::scope` and awaited inside it still uses the tenant's keys
+impl Cache for TenantCache {
+    fn get(&self, key: &str) -> CacheFuture<'_, Option<String>> {
+        let key = key.to_string();
+        Box::pin(async move { self.inner.get(&Self::key(&key)).await })
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        let (key, value) = (key.to_string(), value.to_string());
+        Box::pin(async move { self.inner.set(&Self::key(&key), &value, ttl).await })
+    }
+
+    fn delete(&self, key: &str) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move { self.inner.delete(&Self::key(&key)).await })
+    }
+
+    fn add(&self, key: &str, value: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<bool, Box<dyn std::error::Error>>> {
+        let (key, value) = (key.to_string(), value.to_string());
+        Box::pin(async move { self.inner.add(&Self::key(&key), &value, ttl).await })
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> CacheFuture<'_, Result<i64, Box<dyn std::error::Error>>> {
+        let key = key.to_string();
+        Box::pin(async move { self.inner.increment(&Self::key(&key), ttl).await })
+    }
+
+    /// The current tenant's keys starting with `prefix`, without the
+    /// tenant's prefix
+    fn keys(&self, prefix: &str) -> CacheFuture<'_, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        let prefix = prefix.to_string();
+        Box::pin(async move {
+            let tenant_prefix = Self::key("");
+            let keys = self.inner.keys(&format!("{}{}", tenant_prefix, prefix)).await?;
+            Ok(keys.into_iter().map(|key| key[tenant_prefix.len()..].to_string()).collect())
+        })
+    }
+}
+
+/// Global scope limiting a model's queries to the current tenant's rows,
+/// by the tenant id stored in `column`
+///
+/// ```rust,ignore
+/// fn global_scopes() -> Vec<GlobalScope<Self>> {
+///     vec![tenancy::tenant_scope("tenant_id")]
+/// }
+/// ```
+///
+/// Queries made outside a tenant find no rows, so code that forgot to pick
+/// a tenant can't read everyone's data. Run it inside
+/// [`Tenancy::without_tenant`], or drop the scope from one query with
+/// `without_global_scope("tenant")`, to see every tenant's rows.
+#[cfg(feature = "database")]
+pub fn tenant_scope<M: crate::orm::Model>(column: &'static str) -> crate::orm::GlobalScope<M> {
+    crate::orm::GlobalScope::new("tenant", move |query| match Tenancy::current() {
+        Some(tenant) => query.where_eq(column, tenant.id),
+        None if Tenancy::is_without_tenant() => query,
+        None => query.where_raw("1 = 0", Vec::<serde_json::Value>::new()),
+    })
+}
+
+/// Most tenant pools kept open at once; the least recently used one is
+/// dropped to make room for another
+#[cfg(feature = "database")]
+const MAX_TENANT_POOLS: usize = 64;
+
+/// Pools for tenants with their own database or schema, by URL and schema,
+/// with the tick they were last used at
+#[cfg(feature = "database")]
+#[derive(Default)]
+struct TenantPools {
+    pools: HashMap<(String, Option<String>), (crate::orm::ConnectionPool, u64)>,
+    tick: u64,
+}
+
+#[cfg(feature = "database")]
+impl TenantPools {
+    fn get(&mut self, key: &(String, Option<String>)) -> Option<crate::orm::ConnectionPool> {
+        self.tick += 1;
+        let (pool, last_used) = self.pools.get_mut(key)?;
+        *last_used = self.tick;
+        Some(pool.clone())
+    }
+
+    /// Keep `pool`, dropping the least recently used pools over the limit.
+    /// Requests still holding a dropped pool finish with it; its connections
+    /// close once they are done.
+    fn insert(&mut self, key: (String, Option<String>), pool: crate::orm::ConnectionPool) {
+        while self.pools.len() >= MAX_TENANT_POOLS {
+            let Some(oldest) = self.pools.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(key, _)| key.clone()) else {
+                break;
+            };
+            self.pools.remove(&oldest);
+        }
+        self.tick += 1;
+        self.pools.insert(key, (pool, self.tick));
+    }
+}
+
+#[cfg(feature = "database")]
+static TENANT_POOLS: std::sync::Mutex<Option<TenantPools>> = std::sync::Mutex::new(None);
+
+/// Pool for the current tenant, when it doesn't use the app's database
+#[cfg(feature = "database")]
+pub(crate) fn tenant_pool() -> crate::orm::Result<Option<crate::orm::ConnectionPool>> {
+    let Some(tenant) = Tenancy::current() else {
+        return Ok(None);
+    };
+    if tenant.database_url.is_none() && tenant.schema.is_none() {
+        return Ok(None);
+    }
+    let Some(config) = crate::orm::connection::config() else {
+        return Ok(None);
+    };
+    let url = tenant.database_url.clone().unwrap_or_else(|| config.database_url.clone());
+
+    let mut pools = TENANT_POOLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let pools = pools.get_or_insert_with(TenantPools::default);
+    let key = (url, tenant.schema.clone());
+    if let Some(pool) = pools.get(&key) {
+        return Ok(Some(pool));
+    }
+
+    let pool = crate::orm::connection::lazy_pool(config, &key.0, key.1.as_deref()).map_err(|error| {
+        crate::orm::OrmError::Connection(format!("Could not set up the database for tenant {}: {}", tenant.id, error))
+    })?;
+    pools.insert(key, pool.clone());
+    Ok(Some(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::App;
+    use http::Method;
+
+    #[tokio::test]
+    async fn test_tenants_are_resolved_from_subdomain_header_and_path() {
+        let handler = |tenant: Tenant| async move { Response::ok().body(tenant.id) };
+        let body = |response: Response| String::from_utf8(response.body_data().to_vec()).unwrap();
+
+        let app = App::new()
+            .middleware(ResolveTenant::subdomain("example.com").tenants([Tenant::new("acme")]))
+            .get("/", handler);
+        let request = |host: &str| Request::mock(Method::GET, "/").with_header("host", host);
+        assert_eq!(body(app.handle_request(request("ACME.example.com:8080")).await), "acme");
+        assert_eq!(app.handle_request(request("globex.example.com")).await.status_code(), 404);
+        assert_eq!(app.handle_request(request("example.com")).await.status_code(), 404);
+        assert_eq!(app.handle_request(request("a.b.example.com")).await.status_code(), 404);
+
+        let app = App::new()
+            .middleware(ResolveTenant::header("x-tenant").tenants([Tenant::new("globex")]))
+            .get("/", handler);
+        let request = |tenant: &str| Request::mock(Method::GET, "/").with_header("x-tenant", tenant);
+        assert_eq!(body(app.handle_request(request("globex")).await), "globex");
+        assert_eq!(app.handle_request(request("acme")).await.status_code(), 404);
+
+        let app = App::new()
+            .middleware(ResolveTenant::path().tenants([Tenant::new("initech")]))
+            .get("/:tenant/home", handler);
+        assert_eq!(body(app.handle_request(Request::mock(Method::GET, "/initech/home")).await), "initech");
+        assert_eq!(app.handle_request(Request::mock(Method::GET, "/acme/home")).await.status_code(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_client_chosen_tenants_need_a_lookup() {
+        let handler = |tenant: Tenant| async move { Response::ok().body(tenant.id) };
+
+        let app = App::new().middleware(ResolveTenant::header("x-tenant")).get("/", handler);
+        let response = app.handle_request(Request::mock(Method::GET, "/").with_header("x-tenant", "globex")).await;
+        assert_eq!(response.status_code(), 500);
+
+        let app = App::new().middleware(ResolveTenant::path()).get("/:tenant/home", handler);
+        assert_eq!(app.handle_request(Request::mock(Method::GET, "/initech/home")).await.status_code(), 500);
+    }
+
+    #[cfg(feature = "database")]
+    #[test]
+    fn test_tenant_scope_finds_nothing_outside_a_tenant() {
+        use crate::orm::{GlobalScope, Model, ModelState};
+
+        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+        struct Project {
+            id: Option<i64>,
+        }
+
+        impl<'r> sqlx::FromRow<'r, sqlx::any::AnyRow> for Project {
+            fn from_row(row: &'r sqlx::any::AnyRow) -> std::result::Result<Self, sqlx::Error> {
+                use sqlx::Row;
+                Ok(Self { id: row.try_get("id")? })
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl Model for Project {
+            type PrimaryKey = i64;
+
+            fn table_name() -> &'static str {
+                "projects"
+            }
+
+            fn id(&self) -> Option<i64> {
+                self.id
+            }
+
+            fn set_id(&mut self, id: i64) {
+                self.id = Some(id);
+            }
+
+            fn state(&self) -> ModelState {
+                ModelState::New
+            }
+
+            fn set_state(&mut self, _state: ModelState) {}
+
+            fn global_scopes() -> Vec<GlobalScope<Self>> {
+                vec![tenant_scope("tenant_id")]
+            }
+
+            async fn create_in_database(&mut self) -> crate::orm::Result<()> {
+                Ok(())
+            }
+
+            async fn update_in_database(&mut self) -> crate::orm::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sql = || Project::query().to_sql().0;
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        assert_eq!(sql(), "SELECT * FROM projects WHERE 1 = 0");
+        assert_eq!(
+            runtime.block_on(Tenancy::scope(Tenant::new("acme"), async move { sql() })),
+            "SELECT * FROM projects WHERE tenant_id = ?"
+        );
+        assert_eq!(runtime.block_on(Tenancy::without_tenant(async move { sql() })), "SELECT * FROM projects");
+        assert_eq!(
+            runtime.block_on(Tenancy::without_tenant(Tenancy::scope(Tenant::new("acme"), async move { sql() }))),
+            "SELECT * FROM projects WHERE tenant_id = ?"
+        );
+        assert_eq!(Project::query().without_global_scope("tenant").to_sql().0, "SELECT * FROM projects");
+    }
+
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_tenant_pools_drop_the_least_recently_used() {
+        sqlx::any::install_default_drivers();
+        let pool = || sqlx::any::AnyPoolOptions::new().connect_lazy("sqlite::memory:").unwrap();
+        let key = |n: usize| (format!("sqlite://tenant-{}.db", n), None);
+
+        let mut pools = TenantPools::default();
+        for n in 0..MAX_TENANT_POOLS {
+            pools.insert(key(n), pool());
+        }
+        assert!(pools.get(&key(0)).is_some());
+        pools.insert(key(MAX_TENANT_POOLS), pool());
+
+        assert_eq!(pools.pools.len(), MAX_TENANT_POOLS);
+        assert!(pools.get(&key(0)).is_some());
+        assert!(pools.get(&key(1)).is_none());
+        assert!(pools.get(&key(MAX_TENANT_POOLS)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cache_keys_are_prefixed_per_tenant() {
+        let memory: Arc<dyn Cache> = Arc::new(MemoryCache::new(None));
+        let cache = TenantCache::new(memory.clone());
+
+        Tenancy::scope(Tenant::new("acme"), cache.set("plan", "pro", None)).await.unwrap();
+        Tenancy::scope(Tenant::new("globex"), cache.set("plan", "free", None)).await.unwrap();
+
+        assert_eq!(memory.get("tenant:acme:plan").await.as_deref(), Some("pro"));
+        assert_eq!(Tenancy::scope(Tenant::new("globex"), cache.get("plan")).await.as_deref(), Some("free"));
+        assert_eq!(cache.get("plan").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_cache_counters_and_key_listings_stay_within_the_tenant() {
+        let memory: Arc<dyn Cache> = Arc::new(MemoryCache::new(None));
+        let cache = TenantCache::new(memory.clone());
+
+        assert_eq!(Tenancy::scope(Tenant::new("acme"), cache.increment("hits", None)).await.unwrap(), 1);
+        assert_eq!(Tenancy::scope(Tenant::new("acme"), cache.increment("hits", None)).await.unwrap(), 2);
+        assert_eq!(Tenancy::scope(Tenant::new("globex"), cache.increment("hits", None)).await.unwrap(), 1);
+        assert_eq!(memory.get("tenant:acme:hits").await.as_deref(), Some("2"));
+        assert_eq!(cache.increment("hits", None).await.unwrap(), 1);
+
+        Tenancy::scope(Tenant::new("acme"), cache.set("report:1", "ready", None)).await.unwrap();
+        Tenancy::scope(Tenant::new("globex"), cache.set("report:2", "ready", None)).await.unwrap();
+        let mut keys = Tenancy::scope(Tenant::new("acme"), cache.keys("")).await.unwrap();
+        keys.sort();
+        assert_eq!(keys, ["hits", "report:1"]);
+        assert_eq!(Tenancy::scope(Tenant::new("acme"), cache.keys("report:")).await.unwrap(), ["report:1"]);
+        let mut everything = cache.keys("tenant:").await.unwrap();
+        everything.sort();
+        assert_eq!(everything, ["tenant:acme:hits", "tenant:acme:report:1", "tenant:globex:hits", "tenant:globex:report:2"]);
+    }
+}
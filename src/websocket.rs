@@ -10,6 +10,9 @@
 //! - **Connection Management**: Automatic connection tracking and cleanup
 //! - **Message Broadcasting**: Send messages to all connected clients
 //! - **Room Support**: Group clients into rooms for targeted messaging
+//! - **Presence**: Track who is in a room, with join and leave events and heartbeat timeouts
+//! - **Backpressure**: Bounded per-connection send queues with a configurable [`OverflowPolicy`]
+//! - **Multiple Instances**: `send_to` reaches clients on other instances through a shared [`ConnectionRegistry`]
 //! - **JSON Messaging**: Automatic JSON serialization/deserialization
 //! - **Ping/Pong**: Built-in connection health monitoring
 //! - **Error Handling**: Robust error handling and reconnection support
@@ -205,21 +208,75 @@ use crate::{Request, Response};
 use std::sync::Arc;
 
 #[cfg(feature = "websocket")]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[cfg(feature = "websocket")]
 use {
-    tokio_tungstenite::{accept_async, tungstenite::Message},
+    tokio_tungstenite::{accept_async, tungstenite::{protocol::{frame::coding::CloseCode, CloseFrame, Role}, Message}, WebSocketStream},
     futures_util::{SinkExt, StreamExt},
+    tokio::io::{AsyncRead, AsyncWrite},
     tokio::sync::{RwLock, broadcast},
     sha1::{Sha1, Digest},
     base64::{Engine as _, engine::general_purpose},
 };
 
+#[cfg(feature = "websocket")]
+pub mod testing;
+
+#[cfg(feature = "websocket")]
+pub mod queue;
+
+#[cfg(feature = "websocket")]
+pub use queue::{OverflowPolicy, QueueStats, DEFAULT_QUEUE_CAPACITY};
+
+#[cfg(all(feature = "websocket", feature = "json"))]
+pub mod codec;
+
+#[cfg(all(feature = "websocket", feature = "json"))]
+pub use codec::{CodecError, WireFormat};
+
+#[cfg(all(feature = "websocket", feature = "json"))]
+pub mod presence;
+
+#[cfg(feature = "websocket")]
+pub mod sse;
+
+#[cfg(feature = "websocket")]
+pub mod cluster;
+
+#[cfg(feature = "websocket")]
+pub use cluster::{ConnectionRegistry, Forwarded, HashRing, Inbox, MemoryRegistry, RegistryError};
+
+#[cfg(all(feature = "websocket", feature = "cache"))]
+pub use cluster::RedisRegistry;
+
+#[cfg(all(feature = "websocket", feature = "json"))]
+pub use presence::{PresenceMember, DEFAULT_PRESENCE_TIMEOUT};
+#[cfg(feature = "websocket")]
+use queue::SendQueue;
+
 /// WebSocket connection manager
+///
+/// Cloning is cheap and every clone shares the same connections and rooms.
+#[derive(Clone)]
 pub struct WebSocketManager {
     #[cfg(feature = "websocket")]
-    connections: Arc<RwLock<HashMap<String, broadcast::Sender<String>>>>,
+    connections: Arc<RwLock<HashMap<String, Arc<SendQueue>>>>,
+    #[cfg(feature = "websocket")]
+    rooms: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+    #[cfg(feature = "websocket")]
+    sse: Arc<RwLock<Vec<sse::SseSubscriber>>>,
+    #[cfg(feature = "websocket")]
+    queue_capacity: usize,
+    #[cfg(feature = "websocket")]
+    overflow_policy: OverflowPolicy,
+    #[cfg(all(feature = "websocket", feature = "json"))]
+    presence: Arc<presence::PresenceRooms>,
+    #[cfg(all(feature = "websocket", feature = "json"))]
+    presence_timeout: std::time::Duration,
+    /// Set by [`cluster`](Self::cluster)
+    #[cfg(feature = "websocket")]
+    cluster: Option<Arc<cluster::Cluster>>,
     #[cfg(not(feature = "websocket"))]
     _phantom: std::marker::PhantomData<()>,
 }
@@ -229,36 +286,246 @@ impl WebSocketManager {
         Self {
             #[cfg(feature = "websocket")]
             connections: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "websocket")]
+            rooms: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "websocket")]
+            sse: Arc::default(),
+            #[cfg(feature = "websocket")]
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            #[cfg(feature = "websocket")]
+            overflow_policy: OverflowPolicy::default(),
+            #[cfg(all(feature = "websocket", feature = "json"))]
+            presence: Arc::default(),
+            #[cfg(all(feature = "websocket", feature = "json"))]
+            presence_timeout: DEFAULT_PRESENCE_TIMEOUT,
+            #[cfg(feature = "websocket")]
+            cluster: None,
             #[cfg(not(feature = "websocket"))]
             _phantom: std::marker::PhantomData,
         }
     }
 
-    /// Broadcast a message to all connected clients
+    /// Set how many messages each connection can fall behind by before the
+    /// overflow policy applies. Affects connections made after this call.
+    #[cfg(feature = "websocket")]
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self
+    }
+
+    /// Set what happens to messages for a connection whose queue is full
+    #[cfg(feature = "websocket")]
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Share connections with the other instances through `registry`, so
+    /// [`send_to`](Self::send_to) reaches clients connected to any of them.
+    /// `instance` must be unique to this instance, see the
+    /// [`cluster`](cluster) module.
+    #[cfg(feature = "websocket")]
+    pub fn cluster<R: ConnectionRegistry>(mut self, instance: impl Into<String>, registry: R) -> Self {
+        self.cluster = Some(Arc::new(cluster::Cluster::new(instance.into(), Arc::new(registry))));
+        self
+    }
+
+    /// The name this instance registers its connections under
+    #[cfg(feature = "websocket")]
+    pub fn instance(&self) -> Option<&str> {
+        self.cluster.as_ref().map(|cluster| cluster.instance.as_str())
+    }
+
+    /// Track a new connection and return its send queue
+    #[cfg(feature = "websocket")]
+    pub(crate) async fn register(&self, client_id: &str) -> Arc<SendQueue> {
+        let queue = Arc::new(SendQueue::new(self.queue_capacity, self.overflow_policy));
+        self.connections.write().await.insert(client_id.to_string(), queue.clone());
+        if let Some(cluster) = &self.cluster {
+            cluster.listen(self);
+            if let Err(error) = cluster.registry.register(client_id, &cluster.instance).await {
+                eprintln!("⚠️  Could not register WebSocket client {}: {}", client_id, error);
+            }
+        }
+        queue
+    }
+
+    /// Register every local connection again, before registry entries expire
+    #[cfg(feature = "websocket")]
+    pub(crate) async fn refresh_registrations(&self) {
+        let Some(cluster) = &self.cluster else { return };
+        let client_ids: Vec<String> = self.connections.read().await.keys().cloned().collect();
+        for client_id in client_ids {
+            if let Err(error) = cluster.registry.register(&client_id, &cluster.instance).await {
+                eprintln!("⚠️  Could not register WebSocket client {}: {}", client_id, error);
+            }
+        }
+    }
+
+    /// Forget a connection and remove it from every room it joined
+    #[cfg(feature = "websocket")]
+    pub(crate) async fn unregister(&self, client_id: &str) {
+        if let Some(queue) = self.connections.write().await.remove(client_id) {
+            queue.close();
+        }
+        if let Some(cluster) = &self.cluster {
+            if let Err(error) = cluster.registry.unregister(client_id, &cluster.instance).await {
+                eprintln!("⚠️  Could not unregister WebSocket client {}: {}", client_id, error);
+            }
+        }
+        #[cfg(feature = "json")]
+        self.leave_all_presence(client_id).await;
+
+        let mut rooms = self.rooms.write().await;
+        rooms.retain(|_, members| {
+            members.remove(client_id);
+            !members.is_empty()
+        });
+    }
+
+    /// Add a client to a room
+    #[cfg(feature = "websocket")]
+    pub async fn join_room(&self, room: &str, client_id: &str) {
+        self.rooms
+            .write()
+            .await
+            .entry(room.to_string())
+            .or_default()
+            .insert(client_id.to_string());
+    }
+
+    /// Remove a client from a room
+    #[cfg(feature = "websocket")]
+    pub async fn leave_room(&self, room: &str, client_id: &str) {
+        let mut rooms = self.rooms.write().await;
+        if let Some(members) = rooms.get_mut(room) {
+            members.remove(client_id);
+            if members.is_empty() {
+                rooms.remove(room);
+            }
+        }
+    }
+
+    /// Get the ids of the clients currently in a room
+    #[cfg(feature = "websocket")]
+    pub async fn room_members(&self, room: &str) -> Vec<String> {
+        let mut members: Vec<String> = self.rooms
+            .read()
+            .await
+            .get(room)
+            .map(|members| members.iter().cloned().collect())
+            .unwrap_or_default();
+        members.sort();
+        members
+    }
+
+    /// Broadcast a message to every client in a room, and to event streams
+    /// following it as a channel. Returns how many accepted it.
+    #[cfg(feature = "websocket")]
+    pub async fn broadcast_to_room(&self, room: &str, message: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(self.send_to_room(room, message).await)
+    }
+
+    #[cfg(feature = "websocket")]
+    async fn send_to_room(&self, room: &str, message: &str) -> usize {
+        let queues: Vec<Arc<SendQueue>> = {
+            let rooms = self.rooms.read().await;
+            let connections = self.connections.read().await;
+            rooms
+                .get(room)
+                .map(|members| members.iter().filter_map(|id| connections.get(id).cloned()).collect())
+                .unwrap_or_default()
+        };
+        Self::enqueue(&queues, message).await + self.publish_sse(Some(room), message).await
+    }
+
+    /// Send a message to every client in a room except one, and to event
+    /// streams following the room
+    #[cfg(feature = "websocket")]
+    pub async fn send_to_room_except(&self, room: &str, except: &str, message: &str) -> usize {
+        let queues: Vec<Arc<SendQueue>> = {
+            let rooms = self.rooms.read().await;
+            let connections = self.connections.read().await;
+            rooms
+                .get(room)
+                .map(|members| {
+                    members
+                        .iter()
+                        .filter(|id| id.as_str() != except)
+                        .filter_map(|id| connections.get(id).cloned())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Self::enqueue(&queues, message).await + self.publish_sse(Some(room), message).await
+    }
+
+    /// Broadcast a message to all connected clients and event streams
     #[cfg(feature = "websocket")]
     pub async fn broadcast(&self, message: &str) -> Result<usize, Box<dyn std::error::Error>> {
-        let connections = self.connections.read().await;
+        let queues: Vec<Arc<SendQueue>> = self.connections.read().await.values().cloned().collect();
+        Ok(Self::enqueue(&queues, message).await + self.publish_sse(None, message).await)
+    }
+
+    /// Queue a message on each connection, outside the connections lock so
+    /// a blocking queue doesn't hold up registration. Returns how many
+    /// accepted it.
+    #[cfg(feature = "websocket")]
+    async fn enqueue(queues: &[Arc<SendQueue>], message: &str) -> usize {
         let mut sent_count = 0;
-        
-        for sender in connections.values() {
-            if sender.send(message.to_string()).is_ok() {
+        for queue in queues {
+            if queue.push(message.to_string()).await {
                 sent_count += 1;
             }
         }
-        
-        Ok(sent_count)
+        sent_count
     }
 
-    /// Send a message to a specific client
+    /// Send a message to a specific client, on whichever instance it is
+    /// connected to when the manager is part of a [`cluster`](Self::cluster)
     #[cfg(feature = "websocket")]
     pub async fn send_to(&self, client_id: &str, message: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let connections = self.connections.read().await;
-        if let Some(sender) = connections.get(client_id) {
-            sender.send(message.to_string())?;
+        let queue = self.connections.read().await.get(client_id).cloned();
+        if let Some(queue) = queue {
+            if !queue.push(message.to_string()).await {
+                return Err(format!("WebSocket client {} was disconnected", client_id).into());
+            }
+            return Ok(());
+        }
+        if let Some(cluster) = &self.cluster {
+            match cluster.registry.locate(client_id).await.map_err(|error| error.to_string())? {
+                Some(instance) if instance != cluster.instance => {
+                    cluster.registry.forward(&instance, client_id, message).await.map_err(|error| error.to_string())?;
+                }
+                _ => {}
+            }
         }
         Ok(())
     }
 
+    /// Queue a message forwarded by another instance for a local client
+    #[cfg(feature = "websocket")]
+    pub(crate) async fn send_local(&self, client_id: &str, message: &str) {
+        let queue = self.connections.read().await.get(client_id).cloned();
+        if let Some(queue) = queue {
+            queue.push(message.to_string()).await;
+        }
+    }
+
+    /// Send queue depth and dropped message counts for every connection,
+    /// ordered by client id
+    #[cfg(feature = "websocket")]
+    pub async fn queue_stats(&self) -> Vec<QueueStats> {
+        let mut stats: Vec<QueueStats> = self.connections
+            .read()
+            .await
+            .iter()
+            .map(|(id, queue)| queue.stats(id))
+            .collect();
+        stats.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        stats
+    }
+
     /// Get the number of connected clients
     #[cfg(feature = "websocket")]
     pub async fn connection_count(&self) -> usize {
@@ -279,6 +546,28 @@ impl WebSocketManager {
     pub async fn connection_count(&self) -> usize {
         0
     }
+
+    #[cfg(not(feature = "websocket"))]
+    pub async fn join_room(&self, _room: &str, _client_id: &str) {}
+
+    #[cfg(not(feature = "websocket"))]
+    pub async fn leave_room(&self, _room: &str, _client_id: &str) {}
+
+    #[cfg(not(feature = "websocket"))]
+    pub async fn room_members(&self, _room: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[cfg(not(feature = "websocket"))]
+    pub async fn broadcast_to_room(&self, _room: &str, _message: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        Err("WebSocket feature not enabled".into())
+    }
+}
+
+impl Default for WebSocketManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// WebSocket upgrade handler
@@ -291,21 +580,10 @@ pub async fn websocket_upgrade(req: Request) -> Response {
         }
 
         // 2. Get the WebSocket key
-        let websocket_key = match req.header("sec-websocket-key") {
-            Some(key) => key,
-            None => return Response::bad_request().body("Missing Sec-WebSocket-Key header"),
-        };
-
-        // 3. Generate the accept key
-        let accept_key = generate_websocket_accept_key(websocket_key);
-
-        // 4. Return the upgrade response
-        Response::with_status(http::StatusCode::SWITCHING_PROTOCOLS)
-            .header("Upgrade", "websocket")
-            .header("Connection", "Upgrade")
-            .header("Sec-WebSocket-Accept", &accept_key)
-            .header("Sec-WebSocket-Version", "13")
-            .body("")
+        match req.header("sec-websocket-key") {
+            Some(key) => switching_protocols(key),
+            None => Response::bad_request().body("Missing Sec-WebSocket-Key header"),
+        }
     }
 
     #[cfg(not(feature = "websocket"))]
@@ -316,6 +594,77 @@ pub async fn websocket_upgrade(req: Request) -> Response {
     }
 }
 
+/// The 101 response completing the handshake for `websocket_key`
+#[cfg(feature = "websocket")]
+fn switching_protocols(websocket_key: &str) -> Response {
+    Response::with_status(http::StatusCode::SWITCHING_PROTOCOLS)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", &generate_websocket_accept_key(websocket_key))
+        .header("Sec-WebSocket-Version", "13")
+        .body("")
+}
+
+/// Identity an `on_upgrade` callback accepted a connection for, carried
+/// from the 101 response to the [`WebSocketConnection`]
+#[cfg(feature = "websocket")]
+#[derive(Clone)]
+pub(crate) struct UpgradeIdentity(pub(crate) Arc<dyn std::any::Any + Send + Sync>);
+
+/// Type-erased callback registered with `App::on_upgrade`
+#[cfg(feature = "websocket")]
+pub(crate) type UpgradeAuthorizer = Arc<
+    dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<UpgradeIdentity, Response>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Subprotocol selected during the handshake, carried from the 101 response
+/// to the [`WebSocketConnection`]
+#[cfg(feature = "websocket")]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Subprotocol(pub(crate) String);
+
+/// The first of the server's `supported` subprotocols that the client
+/// offered in `Sec-WebSocket-Protocol`
+#[cfg(feature = "websocket")]
+pub(crate) fn negotiate_subprotocol(offered: Option<&str>, supported: &[String]) -> Option<String> {
+    let offered: Vec<&str> = offered?.split(',').map(str::trim).collect();
+    supported
+        .iter()
+        .find(|protocol| offered.iter().any(|offer| offer.eq_ignore_ascii_case(protocol)))
+        .cloned()
+}
+
+/// Answer an upgrade request for a route, asking `authorize` (when the
+/// route has one) whether to complete the handshake, and accepting one of
+/// the route's `subprotocols` if the client offered any
+#[cfg(feature = "websocket")]
+pub(crate) async fn authorized_upgrade(req: Request, authorize: Option<UpgradeAuthorizer>, subprotocols: &[String]) -> Response {
+    if !is_websocket_upgrade_request(&req) {
+        return Response::bad_request().body("WebSocket upgrade required");
+    }
+
+    let key = req.header("sec-websocket-key").unwrap_or_default().to_string();
+    let protocol = negotiate_subprotocol(req.header("sec-websocket-protocol"), subprotocols);
+    let mut response = match authorize {
+        Some(authorize) => match authorize(req).await {
+            Ok(identity) => {
+                let mut response = switching_protocols(&key);
+                response.extensions_mut().insert(identity);
+                response
+            }
+            Err(rejection) => return rejection,
+        },
+        None => switching_protocols(&key),
+    };
+    if let Some(protocol) = protocol {
+        response = response.header("Sec-WebSocket-Protocol", &protocol);
+        response.extensions_mut().insert(Subprotocol(protocol));
+    }
+    response
+}
+
 #[cfg(feature = "websocket")]
 pub fn is_websocket_upgrade_request(req: &Request) -> bool {
     // Check required headers for WebSocket upgrade
@@ -358,23 +707,237 @@ where
     Fut: std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send,
 {
     // Accept the WebSocket connection
-    let ws_stream = accept_async(stream).await?;
-    let connection = WebSocketConnection::new(ws_stream);
+    let ws_stream = accept_async(Box::new(stream) as BoxedIo).await?;
+    let connection = WebSocketConnection::attach(ws_stream, WebSocketManager::new(), &http::Extensions::new()).await;
 
     // Call the user-provided handler
     handler(connection).await
 }
 
+/// Byte stream a WebSocket can run over: a TCP socket, an upgraded HTTP
+/// connection, or an in-memory pipe in tests
+#[cfg(feature = "websocket")]
+pub(crate) trait WebSocketIo: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+#[cfg(feature = "websocket")]
+impl<T> WebSocketIo for T where T: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+#[cfg(feature = "websocket")]
+type BoxedIo = Box<dyn WebSocketIo>;
+
+/// Type-erased handler registered with `App::websocket`
+#[cfg(feature = "websocket")]
+pub(crate) type WebSocketHandlerFn = Arc<
+    dyn Fn(WebSocketConnection) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Run a registered handler on an already-upgraded stream.
+///
+/// The connection is registered with `manager` before this returns, so its
+/// id can be used to join rooms straight away. The handler itself runs on a
+/// spawned task and the connection is unregistered once it finishes.
+#[cfg(feature = "websocket")]
+pub(crate) async fn serve_connection<S: WebSocketIo>(
+    io: S,
+    handler: WebSocketHandlerFn,
+    manager: WebSocketManager,
+    upgrade: &http::Extensions,
+) -> String {
+    let stream = WebSocketStream::from_raw_socket(Box::new(io) as BoxedIo, Role::Server, None).await;
+    let connection = WebSocketConnection::attach(stream, manager.clone(), upgrade).await;
+    let client_id = connection.id().to_string();
+
+    let task_id = client_id.clone();
+    tokio::spawn(async move {
+        if let Err(e) = handler(connection).await {
+            eprintln!("WebSocket handler error ({}): {}", task_id, e);
+        }
+        manager.unregister(&task_id).await;
+    });
+
+    client_id
+}
+
 /// WebSocket connection wrapper
+///
+/// Messages broadcast to this connection (directly or through a room) are
+/// queued and written to the socket while the handler awaits [`receive`].
+///
+/// [`receive`]: WebSocketConnection::receive
 #[cfg(feature = "websocket")]
 pub struct WebSocketConnection {
-    stream: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+    id: String,
+    stream: WebSocketStream<BoxedIo>,
+    manager: WebSocketManager,
+    outbox: Arc<SendQueue>,
+    identity: Option<UpgradeIdentity>,
+    protocol: Option<String>,
 }
 
 #[cfg(feature = "websocket")]
 impl WebSocketConnection {
-    fn new(stream: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>) -> Self {
-        Self { stream }
+    /// Wrap an accepted stream, taking the identity and subprotocol from the
+    /// extensions of the 101 response
+    async fn attach(stream: WebSocketStream<BoxedIo>, manager: WebSocketManager, upgrade: &http::Extensions) -> Self {
+        let id = uuid::Uuid::new_v4().to_string();
+        let outbox = manager.register(&id).await;
+        Self {
+            id,
+            stream,
+            manager,
+            outbox,
+            identity: upgrade.get::<UpgradeIdentity>().cloned(),
+            protocol: upgrade.get::<Subprotocol>().map(|protocol| protocol.0.clone()),
+        }
+    }
+
+    /// Unique id of this connection within its manager
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The identity the route's `on_upgrade` callback accepted this
+    /// connection for, if it is a `T`
+    pub fn user<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.identity.as_ref()?.0.downcast_ref()
+    }
+
+    /// The subprotocol accepted during the handshake, if the route offers
+    /// any and the client asked for one of them
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// The format [`send_value`](Self::send_value) and
+    /// [`receive_value`](Self::receive_value) use: the negotiated
+    /// subprotocol's, or JSON
+    #[cfg(feature = "json")]
+    pub fn format(&self) -> WireFormat {
+        self.protocol().and_then(WireFormat::from_subprotocol).unwrap_or_default()
+    }
+
+    /// The manager this connection is registered with
+    pub fn manager(&self) -> &WebSocketManager {
+        &self.manager
+    }
+
+    /// How far behind this connection's send queue is
+    pub fn queue_stats(&self) -> QueueStats {
+        self.outbox.stats(&self.id)
+    }
+
+    // The room helpers return owned futures rather than borrowing `self`:
+    // the underlying stream isn't `Sync`, so a future holding `&self` across
+    // an await could not be sent to another task.
+
+    /// Join a room so broadcasts to it reach this connection
+    pub fn join_room(&self, room: &str) -> impl std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'static {
+        let (manager, id, room) = (self.manager.clone(), self.id.clone(), room.to_string());
+        async move {
+            manager.join_room(&room, &id).await;
+            Ok(())
+        }
+    }
+
+    /// Leave a previously joined room
+    pub fn leave_room(&self, room: &str) -> impl std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'static {
+        let (manager, id, room) = (self.manager.clone(), self.id.clone(), room.to_string());
+        async move {
+            manager.leave_room(&room, &id).await;
+            Ok(())
+        }
+    }
+
+    /// Broadcast a message to every connection in a room, including this one
+    /// if it has joined
+    pub fn broadcast_to_room(&self, room: &str, message: &str) -> impl std::future::Future<Output = Result<usize, Box<dyn std::error::Error + Send + Sync>>> + Send + 'static {
+        let (manager, room, message) = (self.manager.clone(), room.to_string(), message.to_string());
+        async move { Ok(manager.send_to_room(&room, &message).await) }
+    }
+
+    /// Join a presence room with metadata about this connection. It is sent
+    /// the current members and the others are told it joined.
+    #[cfg(feature = "json")]
+    pub fn join_presence(&self, room: &str, info: serde_json::Value) -> impl std::future::Future<Output = Result<Vec<PresenceMember>, Box<dyn std::error::Error + Send + Sync>>> + Send + 'static {
+        let (manager, id, room) = (self.manager.clone(), self.id.clone(), room.to_string());
+        async move { Ok(manager.join_presence(&room, &id, info).await) }
+    }
+
+    /// Leave a presence room, telling the remaining members
+    #[cfg(feature = "json")]
+    pub fn leave_presence(&self, room: &str) -> impl std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send + 'static {
+        let (manager, id, room) = (self.manager.clone(), self.id.clone(), room.to_string());
+        async move {
+            manager.leave_presence(&room, &id).await;
+            Ok(())
+        }
+    }
+
+    /// Serialize a value and send it as a text message
+    #[cfg(feature = "json")]
+    pub async fn send_json<T: serde::Serialize>(&mut self, value: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let text = serde_json::to_string(value)?;
+        self.send_text(&text).await
+    }
+
+    /// Serialize a value in the negotiated [`format`](Self::format)
+    #[cfg(feature = "json")]
+    pub async fn send_value<T: serde::Serialize>(&mut self, value: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.send_encoded(self.format(), value).await
+    }
+
+    /// Wait for the next data message and deserialize it from the
+    /// negotiated [`format`](Self::format). Returns `Ok(None)` once the
+    /// connection closes.
+    #[cfg(feature = "json")]
+    pub async fn receive_value<T: serde::de::DeserializeOwned>(&mut self) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>> {
+        self.receive_decoded(self.format()).await
+    }
+
+    /// Serialize a value as MessagePack and send it as a binary message
+    #[cfg(feature = "msgpack")]
+    pub async fn send_msgpack<T: serde::Serialize>(&mut self, value: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.send_encoded(WireFormat::MessagePack, value).await
+    }
+
+    /// Wait for the next binary message and deserialize it from MessagePack
+    #[cfg(feature = "msgpack")]
+    pub async fn receive_msgpack<T: serde::de::DeserializeOwned>(&mut self) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>> {
+        self.receive_decoded(WireFormat::MessagePack).await
+    }
+
+    /// Serialize a value as CBOR and send it as a binary message
+    #[cfg(feature = "cbor")]
+    pub async fn send_cbor<T: serde::Serialize>(&mut self, value: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.send_encoded(WireFormat::Cbor, value).await
+    }
+
+    /// Wait for the next binary message and deserialize it from CBOR
+    #[cfg(feature = "cbor")]
+    pub async fn receive_cbor<T: serde::de::DeserializeOwned>(&mut self) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>> {
+        self.receive_decoded(WireFormat::Cbor).await
+    }
+
+    #[cfg(feature = "json")]
+    async fn send_encoded<T: serde::Serialize>(&mut self, format: WireFormat, value: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match format.encode(value)? {
+            WebSocketMessage::Text(text) => self.send_text(&text).await,
+            WebSocketMessage::Binary(data) => self.stream.send(Message::Binary(data)).await.map_err(Into::into),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    async fn receive_decoded<T: serde::de::DeserializeOwned>(&mut self, format: WireFormat) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>> {
+        loop {
+            match self.receive().await? {
+                None | Some(WebSocketMessage::Close) => return Ok(None),
+                Some(WebSocketMessage::Ping(_)) | Some(WebSocketMessage::Pong(_)) => continue,
+                Some(message) => return Ok(Some(format.decode(&message)?)),
+            }
+        }
     }
 
     /// Send a text message
@@ -390,8 +953,43 @@ impl WebSocketConnection {
     }
 
     /// Receive the next message
+    ///
+    /// Queued broadcasts are flushed to the socket while waiting. If the
+    /// manager's [`OverflowPolicy::Disconnect`] closed the queue, a close
+    /// frame is sent and this returns `Ok(None)`.
     pub async fn receive(&mut self) -> Result<Option<WebSocketMessage>, Box<dyn std::error::Error + Send + Sync>> {
-        match self.stream.next().await {
+        if self.outbox.is_closed() {
+            return Self::incoming(self.stream.next().await);
+        }
+        // Anything the client sends, pongs included, counts as a heartbeat
+        loop {
+            let queued = tokio::select! {
+                incoming = self.stream.next() => {
+                    self.outbox.touch();
+                    return Self::incoming(incoming);
+                }
+                queued = self.outbox.pop() => queued,
+            };
+
+            match queued {
+                Some(text) => self.stream.send(Message::Text(text)).await?,
+                None => {
+                    self.stream
+                        .send(Message::Close(Some(CloseFrame {
+                            code: CloseCode::Policy,
+                            reason: "client fell too far behind".into(),
+                        })))
+                        .await?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn incoming(
+        incoming: Option<Result<Message, tokio_tungstenite::tungstenite::Error>>,
+    ) -> Result<Option<WebSocketMessage>, Box<dyn std::error::Error + Send + Sync>> {
+        match incoming {
             Some(Ok(msg)) => Ok(Some(WebSocketMessage::from_tungstenite(msg))),
             Some(Err(e)) => Err(e.into()),
             None => Ok(None), // Connection closed
@@ -407,6 +1005,7 @@ impl WebSocketConnection {
 
 /// WebSocket message types
 #[cfg(feature = "websocket")]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WebSocketMessage {
     Text(String),
     Binary(Vec<u8>),
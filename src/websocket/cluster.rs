@@ -0,0 +1,395 @@
+//! # Running WebSockets on Several Instances
+//!
+//! A WebSocket stays on the instance that accepted it, so with more than one
+//! instance behind a load balancer, [`WebSocketManager::send_to`] only finds
+//! the clients connected to the instance it runs on. Give every instance's
+//! manager the same [`ConnectionRegistry`] and a name of its own, and
+//! `send_to` looks up where the client is connected and forwards the message
+//! there:
+//!
+//! ```rust,no_run
+//! use torch_web::websocket::{RedisRegistry, WebSocketManager};
+//!
+//! let instance = std::env::var("HOSTNAME").unwrap_or_else(|_| "ws-1".to_string());
+//! let registry = RedisRegistry::new("redis://127.0.0.1/").unwrap();
+//! let manager = WebSocketManager::new().cluster(instance, registry);
+//! ```
+//!
+//! Rooms, broadcasts and presence still only reach the local instance.
+//!
+//! The handshake itself needs no sticky sessions, since the upgrade request
+//! and the connection use the same TCP stream. Clients that fall back to
+//! server-sent events or reconnect often are cheaper to serve from the same
+//! instance each time, which the load balancer can do with a cookie or by
+//! hashing a stable id. [`HashRing`] gives the same mapping in the app, e.g.
+//! to send all members of a room to one instance:
+//!
+//! ```rust
+//! use torch_web::websocket::HashRing;
+//!
+//! let ring = HashRing::new(["ws-1", "ws-2", "ws-3"]);
+//! let instance = ring.node_for("room:lobby").unwrap();
+//! assert_eq!(ring.node_for("room:lobby"), Some(instance));
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use super::WebSocketManager;
+
+/// Errors from a [`ConnectionRegistry`]
+pub type RegistryError = Box<dyn std::error::Error + Send + Sync>;
+
+pub(crate) type RegistryFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, RegistryError>> + Send + 'a>>;
+
+/// A message forwarded to another instance, as (client id, message)
+pub type Forwarded = (String, String);
+
+/// Messages forwarded to an instance
+pub type Inbox = mpsc::Receiver<Forwarded>;
+
+/// How many forwarded messages an instance buffers before senders wait
+const INBOX_CAPACITY: usize = 1024;
+
+/// Where each client is connected, shared by every instance
+pub trait ConnectionRegistry: Send + Sync + 'static {
+    /// Record that `client_id` is connected to `instance`
+    fn register<'a>(&'a self, client_id: &'a str, instance: &'a str) -> RegistryFuture<'a, ()>;
+
+    /// Forget `client_id`, unless it has reconnected to another instance since
+    fn unregister<'a>(&'a self, client_id: &'a str, instance: &'a str) -> RegistryFuture<'a, ()>;
+
+    /// The instance `client_id` is connected to
+    fn locate<'a>(&'a self, client_id: &'a str) -> RegistryFuture<'a, Option<String>>;
+
+    /// Hand `message` for `client_id` to `instance`
+    fn forward<'a>(&'a self, instance: &'a str, client_id: &'a str, message: &'a str) -> RegistryFuture<'a, ()>;
+
+    /// Start receiving the messages forwarded to `instance`
+    fn inbox(&self, instance: &str) -> Inbox;
+
+    /// How often instances register their clients again, for registries
+    /// whose entries expire so crashed instances' clients are forgotten
+    fn refresh_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// The registry a manager shares with the other instances, and its own name
+pub(crate) struct Cluster {
+    pub(crate) instance: String,
+    pub(crate) registry: Arc<dyn ConnectionRegistry>,
+    listening: std::sync::Once,
+}
+
+impl Cluster {
+    pub(crate) fn new(instance: String, registry: Arc<dyn ConnectionRegistry>) -> Self {
+        Self { instance, registry, listening: std::sync::Once::new() }
+    }
+
+    /// Deliver messages forwarded to this instance to `manager`'s
+    /// connections, and keep its registrations fresh. Runs once per manager,
+    /// started by its first connection.
+    pub(crate) fn listen(&self, manager: &WebSocketManager) {
+        self.listening.call_once(|| {
+            let mut inbox = self.registry.inbox(&self.instance);
+            let refresh = self.registry.refresh_interval();
+            let manager = manager.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(refresh.unwrap_or(Duration::from_secs(3600)));
+                ticker.tick().await;
+                loop {
+                    tokio::select! {
+                        forwarded = inbox.recv() => match forwarded {
+                            Some((client_id, message)) => manager.send_local(&client_id, &message).await,
+                            None => break,
+                        },
+                        _ = ticker.tick(), if refresh.is_some() => manager.refresh_registrations().await,
+                    }
+                }
+            });
+        });
+    }
+}
+
+/// A registry kept in process memory, for tests and for several managers
+/// in one process. Clones share their registrations.
+#[derive(Clone, Default)]
+pub struct MemoryRegistry {
+    clients: Arc<Mutex<HashMap<String, String>>>,
+    inboxes: Arc<Mutex<HashMap<String, mpsc::Sender<Forwarded>>>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConnectionRegistry for MemoryRegistry {
+    fn register<'a>(&'a self, client_id: &'a str, instance: &'a str) -> RegistryFuture<'a, ()> {
+        self.clients.lock().unwrap().insert(client_id.to_string(), instance.to_string());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn unregister<'a>(&'a self, client_id: &'a str, instance: &'a str) -> RegistryFuture<'a, ()> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.get(client_id).is_some_and(|registered| registered == instance) {
+            clients.remove(client_id);
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn locate<'a>(&'a self, client_id: &'a str) -> RegistryFuture<'a, Option<String>> {
+        let instance = self.clients.lock().unwrap().get(client_id).cloned();
+        Box::pin(async move { Ok(instance) })
+    }
+
+    fn forward<'a>(&'a self, instance: &'a str, client_id: &'a str, message: &'a str) -> RegistryFuture<'a, ()> {
+        let inbox = self.inboxes.lock().unwrap().get(instance).cloned();
+        Box::pin(async move {
+            let inbox = inbox.ok_or_else(|| format!("no WebSocket instance called {}", instance))?;
+            inbox
+                .send((client_id.to_string(), message.to_string()))
+                .await
+                .map_err(|_| format!("WebSocket instance {} stopped", instance).into())
+        })
+    }
+
+    fn inbox(&self, instance: &str) -> Inbox {
+        let (sender, receiver) = mpsc::channel(INBOX_CAPACITY);
+        self.inboxes.lock().unwrap().insert(instance.to_string(), sender);
+        receiver
+    }
+}
+
+/// A registry in Redis. Each client is a key naming its instance, and each
+/// instance reads forwarded messages from a list of its own. Entries expire
+/// after [`ttl`](Self::ttl) unless their instance is still running.
+#[cfg(feature = "cache")]
+pub struct RedisRegistry {
+    client: redis::Client,
+    prefix: String,
+    ttl: Duration,
+}
+
+#[cfg(feature = "cache")]
+impl RedisRegistry {
+    pub fn new(redis_url: &str) -> Result<Self, redis::RedisError> {
+        Ok(Self {
+            client: redis::Client::open(redis_url)?,
+            prefix: "torch:ws:".to_string(),
+            ttl: Duration::from_secs(300),
+        })
+    }
+
+    /// Prefix for the registry's keys (default `torch:ws:`)
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// How long registrations and undelivered messages outlive a stopped
+    /// instance (default five minutes)
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl.max(Duration::from_secs(3));
+        self
+    }
+
+    fn client_key(&self, client_id: &str) -> String {
+        format!("{}client:{}", self.prefix, client_id)
+    }
+
+    fn inbox_key(&self, instance: &str) -> String {
+        format!("{}inbox:{}", self.prefix, instance)
+    }
+}
+
+#[cfg(feature = "cache")]
+impl ConnectionRegistry for RedisRegistry {
+    fn register<'a>(&'a self, client_id: &'a str, instance: &'a str) -> RegistryFuture<'a, ()> {
+        Box::pin(async move {
+            let mut conn = self.client.get_connection()?;
+            redis::Commands::set_ex::<_, _, ()>(&mut conn, self.client_key(client_id), instance, self.ttl.as_secs())?;
+            Ok(())
+        })
+    }
+
+    fn unregister<'a>(&'a self, client_id: &'a str, instance: &'a str) -> RegistryFuture<'a, ()> {
+        Box::pin(async move {
+            let mut conn = self.client.get_connection()?;
+            redis::Script::new("if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) end return 0")
+                .key(self.client_key(client_id))
+                .arg(instance)
+                .invoke::<i64>(&mut conn)?;
+            Ok(())
+        })
+    }
+
+    fn locate<'a>(&'a self, client_id: &'a str) -> RegistryFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let mut conn = self.client.get_connection()?;
+            Ok(redis::Commands::get(&mut conn, self.client_key(client_id))?)
+        })
+    }
+
+    fn forward<'a>(&'a self, instance: &'a str, client_id: &'a str, message: &'a str) -> RegistryFuture<'a, ()> {
+        Box::pin(async move {
+            let mut conn = self.client.get_connection()?;
+            let key = self.inbox_key(instance);
+            redis::pipe()
+                .lpush(&key, format!("{}\n{}", client_id, message))
+                .ignore()
+                .expire(&key, self.ttl.as_secs() as i64)
+                .ignore()
+                .query::<()>(&mut conn)?;
+            Ok(())
+        })
+    }
+
+    /// Reads the instance's list on a blocking thread until the receiver
+    /// is dropped
+    fn inbox(&self, instance: &str) -> Inbox {
+        let (sender, receiver) = mpsc::channel(INBOX_CAPACITY);
+        let client = self.client.clone();
+        let key = self.inbox_key(instance);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = None;
+            while !sender.is_closed() {
+                let popped: redis::RedisResult<Option<(String, String)>> = match conn.as_mut() {
+                    Some(conn) => redis::Commands::brpop(conn, &key, 1.0),
+                    None => client.get_connection().map(|connected| {
+                        conn = Some(connected);
+                        None
+                    }),
+                };
+                match popped {
+                    Ok(Some((_, forwarded))) => {
+                        let Some((client_id, message)) = forwarded.split_once('\n') else { continue };
+                        if sender.blocking_send((client_id.to_string(), message.to_string())).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(error) => {
+                        eprintln!("⚠️  WebSocket registry inbox {}: {}", key, error);
+                        conn = None;
+                        std::thread::sleep(Duration::from_secs(1));
+                    }
+                }
+            }
+        });
+        receiver
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        Some(self.ttl / 3)
+    }
+}
+
+/// Consistent hashing of keys, such as client or room ids, onto instances.
+/// Adding or removing an instance only moves the keys that hashed to it.
+#[derive(Debug, Clone, Default)]
+pub struct HashRing {
+    ring: BTreeMap<u64, String>,
+    replicas: usize,
+}
+
+impl HashRing {
+    /// Points each instance gets on the ring, which evens out the share of
+    /// keys each one gets
+    pub const DEFAULT_REPLICAS: usize = 160;
+
+    pub fn new<I, S>(nodes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::with_replicas(nodes, Self::DEFAULT_REPLICAS)
+    }
+
+    pub fn with_replicas<I, S>(nodes: I, replicas: usize) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut ring = Self { ring: BTreeMap::new(), replicas: replicas.max(1) };
+        for node in nodes {
+            ring.add(node);
+        }
+        ring
+    }
+
+    pub fn add(&mut self, node: impl Into<String>) {
+        let node = node.into();
+        for replica in 0..self.replicas {
+            self.ring.insert(ring_hash(&format!("{}#{}", node, replica)), node.clone());
+        }
+    }
+
+    pub fn remove(&mut self, node: &str) {
+        self.ring.retain(|_, owner| owner != node);
+    }
+
+    /// The instance `key` belongs to, `None` when the ring is empty
+    pub fn node_for(&self, key: &str) -> Option<&str> {
+        let hash = ring_hash(key);
+        self.ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, node)| node.as_str())
+    }
+}
+
+/// FNV-1a with a final mix, the same in every process and version
+fn ring_hash(key: &str) -> u64 {
+    let mut hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_to_reaches_clients_on_other_instances() {
+        let registry = MemoryRegistry::new();
+        let first = WebSocketManager::new().cluster("ws-1", registry.clone());
+        let second = WebSocketManager::new().cluster("ws-2", registry.clone());
+
+        let outbox = first.register("ada").await;
+        second.register("grace").await;
+        second.send_to("ada", "hello from ws-2").await.unwrap();
+        let delivered = tokio::time::timeout(Duration::from_secs(1), outbox.pop()).await.unwrap();
+        assert_eq!(delivered.as_deref(), Some("hello from ws-2"));
+
+        // A stale unregister from the old instance keeps a reconnect's entry
+        second.register("ada").await;
+        first.unregister("ada").await;
+        assert_eq!(registry.locate("ada").await.unwrap().as_deref(), Some("ws-2"));
+
+        // Unknown clients are ignored like local ones
+        first.send_to("nobody", "hi").await.unwrap();
+
+        let ring = HashRing::new(["ws-1", "ws-2", "ws-3"]);
+        let keys: Vec<String> = (0..300).map(|i| format!("client-{}", i)).collect();
+        let before: Vec<&str> = keys.iter().map(|key| ring.node_for(key).unwrap()).collect();
+        assert!(["ws-1", "ws-2", "ws-3"].iter().all(|node| before.iter().filter(|owner| *owner == node).count() > 50));
+        let mut smaller = ring.clone();
+        smaller.remove("ws-3");
+        for (key, owner) in keys.iter().zip(&before) {
+            if *owner != "ws-3" {
+                assert_eq!(smaller.node_for(key), Some(*owner));
+            }
+        }
+        assert_eq!(HashRing::default().node_for("x"), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! # Binary Formats
+//!
+//! Typed messages can be sent as JSON text frames or, for bandwidth-sensitive
+//! apps like games and tickers, as MessagePack (`msgpack` feature) or CBOR
+//! (`cbor` feature) binary frames. Clients pick a format by offering
+//! subprotocols in the handshake, and the route accepts the first of its own
+//! [`subprotocols`](crate::App::subprotocols) the client offered:
+//!
+//! ```rust,ignore
+//! use torch_web::App;
+//!
+//! let app = App::new()
+//!     .websocket("/ticker", |mut connection| async move {
+//!         // MessagePack for clients that asked for it, JSON otherwise
+//!         while let Some(order) = connection.receive_value::<Order>().await? {
+//!             connection.send_value(&fill(order)).await?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .subprotocols(&["msgpack", "cbor", "json"]);
+//! ```
+//!
+//! A browser opts in with `new WebSocket(url, ["msgpack", "json"])` and reads
+//! `socket.protocol` to see which one was chosen. Handlers that always use
+//! one format can call `send_msgpack`/`receive_msgpack` or
+//! `send_cbor`/`receive_cbor` directly.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::WebSocketMessage;
+
+/// A typed message that couldn't be encoded or decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecError(pub String);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WebSocket codec error: {}", self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// How typed messages are written to frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    /// JSON in text frames
+    #[default]
+    Json,
+    /// MessagePack in binary frames
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR in binary frames
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl WireFormat {
+    /// The `Sec-WebSocket-Protocol` name clients offer for this format
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            #[cfg(feature = "msgpack")]
+            WireFormat::MessagePack => "msgpack",
+            #[cfg(feature = "cbor")]
+            WireFormat::Cbor => "cbor",
+        }
+    }
+
+    /// The format for a negotiated subprotocol, if it names one this build
+    /// supports
+    pub fn from_subprotocol(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(WireFormat::Json),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "messagepack" => Some(WireFormat::MessagePack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(WireFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Serialize a value into a frame
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<WebSocketMessage, CodecError> {
+        let error = |e: &dyn fmt::Display| CodecError(e.to_string());
+        match self {
+            WireFormat::Json => serde_json::to_string(value).map(WebSocketMessage::Text).map_err(|e| error(&e)),
+            #[cfg(feature = "msgpack")]
+            WireFormat::MessagePack => rmp_serde::to_vec_named(value).map(WebSocketMessage::Binary).map_err(|e| error(&e)),
+            #[cfg(feature = "cbor")]
+            WireFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| error(&e))?;
+                Ok(WebSocketMessage::Binary(bytes))
+            }
+        }
+    }
+
+    /// Deserialize a frame. JSON is read from text or binary frames, the
+    /// binary formats only from binary frames.
+    pub fn decode<T: DeserializeOwned>(&self, message: &WebSocketMessage) -> Result<T, CodecError> {
+        let error = |e: &dyn fmt::Display| CodecError(e.to_string());
+        let bytes = match message {
+            WebSocketMessage::Text(text) if *self == WireFormat::Json => text.as_bytes(),
+            WebSocketMessage::Binary(data) => data.as_slice(),
+            other => {
+                return Err(CodecError(format!("expected a {} message, got {:?}", self.subprotocol(), other)));
+            }
+        };
+        match self {
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(|e| error(&e)),
+            #[cfg(feature = "msgpack")]
+            WireFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| error(&e)),
+            #[cfg(feature = "cbor")]
+            WireFormat::Cbor => ciborium::from_reader(bytes).map_err(|e| error(&e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::negotiate_subprotocol;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Tick {
+        symbol: String,
+        price: f64,
+    }
+
+    #[test]
+    fn test_formats_round_trip_and_negotiate() {
+        let tick = Tick { symbol: "ACME".into(), price: 12.5 };
+        let json = WireFormat::Json.encode(&tick).unwrap();
+        assert_eq!(json, WebSocketMessage::Text(r#"{"symbol":"ACME","price":12.5}"#.into()));
+        assert_eq!(WireFormat::Json.decode::<Tick>(&json).unwrap(), tick);
+        assert!(WireFormat::Json.decode::<Tick>(&WebSocketMessage::Ping(Vec::new())).is_err());
+
+        #[cfg(feature = "msgpack")]
+        {
+            let packed = WireFormat::MessagePack.encode(&tick).unwrap();
+            assert!(packed.as_binary().unwrap().len() < json.as_text().unwrap().len());
+            assert_eq!(WireFormat::MessagePack.decode::<Tick>(&packed).unwrap(), tick);
+            assert!(WireFormat::MessagePack.decode::<Tick>(&json).is_err());
+        }
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = WireFormat::Cbor.encode(&tick).unwrap();
+            assert_eq!(WireFormat::Cbor.decode::<Tick>(&cbor).unwrap(), tick);
+            assert_eq!(WireFormat::from_subprotocol("CBOR"), Some(WireFormat::Cbor));
+        }
+
+        let supported = vec!["msgpack".to_string(), "json".to_string()];
+        assert_eq!(negotiate_subprotocol(Some("json, msgpack"), &supported).as_deref(), Some("msgpack"));
+        assert_eq!(negotiate_subprotocol(Some("json"), &supported).as_deref(), Some("json"));
+        assert_eq!(negotiate_subprotocol(Some("graphql-ws"), &supported), None);
+        assert_eq!(negotiate_subprotocol(None, &supported), None);
+    }
+}
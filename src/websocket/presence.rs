@@ -0,0 +1,248 @@
+//! # Presence Channels
+//!
+//! A presence room knows who is in it. Joining one with some metadata about
+//! the user tells the newcomer who is already there and tells everyone else
+//! who joined, so chat UIs can show who's online:
+//!
+//! ```rust,ignore
+//! use torch_web::App;
+//!
+//! let app = App::new()
+//!     .websocket("/chat/:room", |mut connection| async move {
+//!         let name = connection.user::<User>().map(|user| user.name.clone()).unwrap_or_default();
+//!         connection.join_presence("chat", serde_json::json!({ "name": name })).await?;
+//!
+//!         while let Some(message) = connection.receive().await? {
+//!             // ...
+//!         }
+//!         Ok(())
+//!     });
+//! ```
+//!
+//! Members receive events as JSON text messages:
+//!
+//! ```text
+//! {"type":"presence","event":"here","room":"chat","members":[{"client_id":"...","info":{"name":"Ada"}}]}
+//! {"type":"presence","event":"joining","room":"chat","member":{"client_id":"...","info":{"name":"Grace"}}}
+//! {"type":"presence","event":"leaving","room":"chat","member":{"client_id":"...","info":{"name":"Grace"}}}
+//! ```
+//!
+//! Members leave when they call `leave_presence`, when their connection
+//! closes, or when nothing has been heard from them for the manager's
+//! [`presence_timeout`](WebSocketManager::presence_timeout). Any frame from
+//! the client counts as a heartbeat, so clients that are otherwise quiet
+//! should send one (for example `{"type":"ping"}`) more often than that.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+use super::WebSocketManager;
+
+/// How long a presence member can stay silent before it is evicted
+pub const DEFAULT_PRESENCE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A connection in a presence room
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresenceMember {
+    pub client_id: String,
+    /// Whatever the connection joined with, such as a user's id and name
+    pub info: Value,
+}
+
+/// Members of each presence room, in the order they joined
+#[derive(Default)]
+pub(crate) struct PresenceRooms {
+    rooms: RwLock<HashMap<String, Vec<PresenceMember>>>,
+    /// Whether a task is evicting stale members
+    sweeping: AtomicBool,
+}
+
+fn presence_event(room: &str, event: &str, body: Value) -> String {
+    let mut message = json!({ "type": "presence", "event": event, "room": room });
+    if let (Some(message), Value::Object(body)) = (message.as_object_mut(), body) {
+        message.extend(body);
+    }
+    message.to_string()
+}
+
+impl WebSocketManager {
+    /// Set how long a presence member can go without sending anything
+    /// before it is removed from its rooms (default 60 seconds)
+    pub fn presence_timeout(mut self, timeout: Duration) -> Self {
+        self.presence_timeout = timeout;
+        self
+    }
+
+    /// Add a client to a presence room with some metadata about it.
+    ///
+    /// The client is sent a `here` event listing every member, including
+    /// itself, and the other members a `joining` event. Joining again
+    /// replaces the client's metadata. Returns the members.
+    pub async fn join_presence(&self, room: &str, client_id: &str, info: Value) -> Vec<PresenceMember> {
+        self.join_room(room, client_id).await;
+        let member = PresenceMember {
+            client_id: client_id.to_string(),
+            info,
+        };
+        let members = {
+            let mut rooms = self.presence.rooms.write().await;
+            let members = rooms.entry(room.to_string()).or_default();
+            members.retain(|existing| existing.client_id != client_id);
+            members.push(member.clone());
+            members.clone()
+        };
+
+        let _ = self.send_to(client_id, &presence_event(room, "here", json!({ "members": members }))).await;
+        self.send_to_room_except(room, client_id, &presence_event(room, "joining", json!({ "member": member })))
+            .await;
+        self.start_presence_sweep();
+        members
+    }
+
+    /// Remove a client from a presence room, sending the remaining members
+    /// a `leaving` event
+    pub async fn leave_presence(&self, room: &str, client_id: &str) {
+        self.leave_room(room, client_id).await;
+        let member = {
+            let mut rooms = self.presence.rooms.write().await;
+            let Some(members) = rooms.get_mut(room) else {
+                return;
+            };
+            let position = members.iter().position(|member| member.client_id == client_id);
+            let member = position.map(|position| members.remove(position));
+            if members.is_empty() {
+                rooms.remove(room);
+            }
+            member
+        };
+
+        if let Some(member) = member {
+            self.send_to_room(room, &presence_event(room, "leaving", json!({ "member": member }))).await;
+        }
+    }
+
+    /// Members of a presence room, in the order they joined
+    pub async fn here(&self, room: &str) -> Vec<PresenceMember> {
+        self.presence.rooms.read().await.get(room).cloned().unwrap_or_default()
+    }
+
+    /// Remove a client from every presence room it is in
+    pub(crate) async fn leave_all_presence(&self, client_id: &str) {
+        let rooms: Vec<String> = self.presence
+            .rooms
+            .read()
+            .await
+            .iter()
+            .filter(|(_, members)| members.iter().any(|member| member.client_id == client_id))
+            .map(|(room, _)| room.clone())
+            .collect();
+        for room in rooms {
+            self.leave_presence(&room, client_id).await;
+        }
+    }
+
+    /// Remove members whose connection has closed or gone quiet for longer
+    /// than the presence timeout. Runs in the background while any presence
+    /// room has members; returns how many clients were evicted.
+    pub async fn evict_stale_members(&self) -> usize {
+        let members: Vec<String> = {
+            let rooms = self.presence.rooms.read().await;
+            let mut members: Vec<String> = rooms.values().flatten().map(|member| member.client_id.clone()).collect();
+            members.sort();
+            members.dedup();
+            members
+        };
+        let stale: Vec<String> = {
+            let connections = self.connections.read().await;
+            members
+                .into_iter()
+                .filter(|id| connections.get(id).map_or(true, |queue| queue.idle_for() > self.presence_timeout))
+                .collect()
+        };
+
+        for client_id in &stale {
+            self.leave_all_presence(client_id).await;
+        }
+        stale.len()
+    }
+
+    /// Start the eviction task unless it is already running. It stops once
+    /// no presence room has members.
+    fn start_presence_sweep(&self) {
+        if self.presence.sweeping.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let manager = self.clone();
+        let period = (self.presence_timeout / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                manager.evict_stale_members().await;
+
+                // Checked under the lock a join takes, so a join either sees
+                // the flag cleared and starts a new task or is seen here
+                let rooms = manager.presence.rooms.read().await;
+                if rooms.is_empty() {
+                    manager.presence.sweeping.store(false, Ordering::SeqCst);
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::testing::WebSocketTestClient;
+    use crate::websocket::WebSocketMessage;
+    use crate::App;
+
+    #[tokio::test]
+    async fn test_presence_events_and_eviction() {
+        let manager = WebSocketManager::new().presence_timeout(Duration::from_millis(200));
+        let app = App::new()
+            .with_state(manager.clone())
+            .websocket("/chat", |mut connection| async move {
+                while let Some(message) = connection.receive().await? {
+                    if let WebSocketMessage::Text(text) = message {
+                        connection.send_text(&text).await?;
+                    }
+                }
+                Ok(())
+            });
+
+        let mut ada = WebSocketTestClient::connect(&app, "/chat").await.unwrap();
+        let mut grace = WebSocketTestClient::connect(&app, "/chat").await.unwrap();
+        manager.join_presence("lobby", ada.id(), json!({ "name": "Ada" })).await;
+        let here = ada.receive_json::<Value>().await.unwrap();
+        assert_eq!((here["event"].as_str(), here["members"][0]["info"]["name"].as_str()), (Some("here"), Some("Ada")));
+
+        manager.join_presence("lobby", grace.id(), json!({ "name": "Grace" })).await;
+        let joining = ada.receive_json::<Value>().await.unwrap();
+        assert_eq!((joining["event"].as_str(), joining["member"]["info"]["name"].as_str()), (Some("joining"), Some("Grace")));
+        assert_eq!(grace.receive_json::<Value>().await.unwrap()["members"].as_array().unwrap().len(), 2);
+        let names: Vec<Value> = manager.here("lobby").await.into_iter().map(|member| member.info["name"].clone()).collect();
+        assert_eq!(names, vec![json!("Ada"), json!("Grace")]);
+
+        // Ada keeps talking while Grace goes quiet past the timeout
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        ada.send_text("still here").await.unwrap();
+        ada.assert_received_text("still here").await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        manager.evict_stale_members().await;
+
+        let ids: Vec<String> = manager.here("lobby").await.into_iter().map(|member| member.client_id).collect();
+        assert_eq!(ids, vec![ada.id().to_string()]);
+        let leaving = ada.receive_json::<Value>().await.unwrap();
+        assert_eq!((leaving["event"].as_str(), leaving["member"]["client_id"].as_str()), (Some("leaving"), Some(grace.id())));
+    }
+}
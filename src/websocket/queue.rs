@@ -0,0 +1,226 @@
+//! # Send Queues
+//!
+//! Every connection has a bounded queue of messages waiting to be written to
+//! its socket. Broadcasts only append to the queues, so one slow client can't
+//! hold up the others, and the queue's capacity caps how much memory a client
+//! that stops reading can take. What happens when a queue is full is set by
+//! the manager's [`OverflowPolicy`]:
+//!
+//! ```rust
+//! use torch_web::websocket::{OverflowPolicy, WebSocketManager};
+//!
+//! let manager = WebSocketManager::new()
+//!     .queue_capacity(64)
+//!     .overflow_policy(OverflowPolicy::Disconnect);
+//! ```
+//!
+//! [`WebSocketManager::queue_stats`] reports how far behind each connection
+//! is and how many messages it has lost.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+/// Messages a connection can fall behind by before the overflow policy applies
+pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+/// What to do with a message for a connection whose send queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room, so the client skips
+    /// ahead to recent messages
+    #[default]
+    DropOldest,
+    /// Close the connection with a policy violation (1008) frame
+    Disconnect,
+    /// Wait until the client catches up. Every sender to that client waits
+    /// with it, so only use this when slow clients are expected to recover.
+    Block,
+}
+
+impl OverflowPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::Disconnect => "disconnect",
+            OverflowPolicy::Block => "block",
+        }
+    }
+}
+
+/// How far behind a connection is, from [`WebSocketManager::queue_stats`]
+///
+/// [`WebSocketManager::queue_stats`]: crate::websocket::WebSocketManager::queue_stats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueStats {
+    pub client_id: String,
+    /// Messages waiting to be written to the socket
+    pub queued: usize,
+    pub capacity: usize,
+    /// Messages discarded or refused because the queue was full
+    pub dropped: u64,
+}
+
+/// A connection's outbound messages. Being the manager's handle on the
+/// connection, it also records when the client was last heard from.
+pub(crate) struct SendQueue {
+    messages: Mutex<VecDeque<String>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    readable: Notify,
+    writable: Notify,
+    closed: AtomicBool,
+    dropped: AtomicU64,
+    last_seen: Mutex<Instant>,
+}
+
+impl SendQueue {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            messages: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            policy,
+            readable: Notify::new(),
+            writable: Notify::new(),
+            closed: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+            last_seen: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Queue a message, applying the overflow policy if the queue is full.
+    /// Returns whether the message was queued.
+    pub(crate) async fn push(&self, message: String) -> bool {
+        loop {
+            // Registered before checking, so a pop or close in between still wakes us
+            let writable = self.writable.notified();
+            tokio::pin!(writable);
+            writable.as_mut().enable();
+
+            if self.is_closed() {
+                return false;
+            }
+            {
+                let mut messages = self.messages.lock().unwrap();
+                if messages.len() < self.capacity {
+                    messages.push_back(message);
+                    drop(messages);
+                    self.readable.notify_one();
+                    return true;
+                }
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        messages.pop_front();
+                        messages.push_back(message);
+                        drop(messages);
+                        self.record_drop();
+                        self.readable.notify_one();
+                        return true;
+                    }
+                    OverflowPolicy::Disconnect => {
+                        drop(messages);
+                        self.record_drop();
+                        self.close();
+                        return false;
+                    }
+                    OverflowPolicy::Block => {}
+                }
+            }
+            writable.await;
+        }
+    }
+
+    /// The next message to write, or `None` once the queue is closed
+    pub(crate) async fn pop(&self) -> Option<String> {
+        loop {
+            let readable = self.readable.notified();
+            tokio::pin!(readable);
+            readable.as_mut().enable();
+
+            if self.is_closed() {
+                return None;
+            }
+            let next = self.messages.lock().unwrap().pop_front();
+            if let Some(message) = next {
+                self.writable.notify_one();
+                return Some(message);
+            }
+            readable.await;
+        }
+    }
+
+    /// Stop accepting messages and wake everything waiting on the queue
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.readable.notify_waiters();
+        self.writable.notify_waiters();
+    }
+
+    /// Record that the client sent something
+    pub(crate) fn touch(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the client last sent anything
+    pub(crate) fn idle_for(&self) -> Duration {
+        self.last_seen.lock().unwrap().elapsed()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn stats(&self, client_id: &str) -> QueueStats {
+        QueueStats {
+            client_id: client_id.to_string(),
+            queued: self.messages.lock().unwrap().len(),
+            capacity: self.capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "monitoring")]
+        metrics::counter!("torch_websocket_dropped_messages_total", 1, "policy" => self.policy.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let queue = SendQueue::new(2, OverflowPolicy::DropOldest);
+        for message in ["a", "b", "c"] {
+            assert!(queue.push(message.to_string()).await);
+        }
+        assert_eq!(queue.stats("x"), QueueStats { client_id: "x".into(), queued: 2, capacity: 2, dropped: 1 });
+        assert_eq!(queue.pop().await.as_deref(), Some("b"));
+
+        let queue = SendQueue::new(1, OverflowPolicy::Disconnect);
+        assert!(queue.push("a".into()).await);
+        assert!(!queue.push("b".into()).await);
+        assert!(queue.is_closed());
+        assert_eq!(queue.pop().await, None);
+
+        let queue = Arc::new(SendQueue::new(1, OverflowPolicy::Block));
+        assert!(queue.push("a".into()).await);
+        let blocked = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push("b".into()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!blocked.is_finished());
+        assert_eq!(queue.pop().await.as_deref(), Some("a"));
+        assert!(blocked.await.unwrap());
+        assert_eq!(queue.pop().await.as_deref(), Some("b"));
+        assert_eq!(queue.stats("x").dropped, 0);
+    }
+}
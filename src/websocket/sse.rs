@@ -0,0 +1,179 @@
+//! # Server-Sent Events Bridge
+//!
+//! Messages broadcast through a [`WebSocketManager`] also reach browsers
+//! listening with a plain `EventSource`, so dashboards can follow channels
+//! without a WebSocket client. A room broadcast goes to SSE subscribers of
+//! the room's channel as an event named after it; a broadcast to everyone
+//! goes to every subscriber as a default `message` event.
+//!
+//! ```rust,ignore
+//! use torch_web::{App, Request, extractors::State, websocket::WebSocketManager};
+//!
+//! let app = App::new()
+//!     // GET /events?channels=orders,stats, for the channels `allow` accepts
+//!     .sse("/events", |_req: &Request, channel: &str| matches!(channel, "orders" | "stats"))
+//!     .post("/orders", |State(manager): State<WebSocketManager>| async move {
+//!         manager.broadcast_to_room("orders", r#"{"id":42}"#).await.ok();
+//!         "queued"
+//!     });
+//! ```
+//!
+//! ```js
+//! const events = new EventSource("/events?channels=orders,stats");
+//! events.addEventListener("orders", (event) => render(JSON.parse(event.data)));
+//! ```
+//!
+//! Each subscriber has a bounded queue like a WebSocket connection, with the
+//! manager's capacity and [`OverflowPolicy`](super::OverflowPolicy). A comment
+//! line is sent every [`SSE_KEEP_ALIVE`] so proxies don't close idle streams.
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use futures::stream;
+use hyper::body::Bytes;
+
+use super::queue::SendQueue;
+use super::WebSocketManager;
+use crate::{Request, Response};
+
+/// How often an idle stream is sent a comment to keep it open
+pub const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// An open `EventSource` and the channels it listens to. The stream owns
+/// the queue, so the subscriber lapses once the client disconnects.
+pub(crate) struct SseSubscriber {
+    channels: Vec<String>,
+    queue: Weak<SendQueue>,
+}
+
+/// One event in the `text/event-stream` format
+fn sse_frame(event: Option<&str>, data: &str) -> String {
+    let mut frame = String::new();
+    if let Some(event) = event {
+        frame.push_str("event: ");
+        frame.push_str(event);
+        frame.push('\n');
+    }
+    for line in data.lines() {
+        frame.push_str("data: ");
+        frame.push_str(line);
+        frame.push('\n');
+    }
+    if data.is_empty() {
+        frame.push_str("data: \n");
+    }
+    frame.push('\n');
+    frame
+}
+
+/// Channels named by `?channels=a,b` and/or `?channel=a&channel=b`
+pub(crate) fn requested_channels(req: &Request) -> Vec<String> {
+    let mut channels: Vec<String> = req
+        .query_string()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(name, _)| *name == "channels" || *name == "channel")
+        .filter_map(|(_, value)| urlencoding::decode(&value.replace('+', " ")).ok().map(|value| value.into_owned()))
+        .flat_map(|value| value.split(',').map(|channel| channel.trim().to_string()).collect::<Vec<_>>())
+        .filter(|channel| !channel.is_empty())
+        .collect();
+    channels.sort();
+    channels.dedup();
+    channels
+}
+
+impl WebSocketManager {
+    /// Open an event stream receiving broadcasts to `channels` and to
+    /// everyone. Return it from a handler to serve an `EventSource`.
+    pub async fn sse(&self, channels: &[String]) -> Response {
+        let queue = Arc::new(SendQueue::new(self.queue_capacity, self.overflow_policy));
+        self.sse.write().await.push(SseSubscriber {
+            channels: channels.to_vec(),
+            queue: Arc::downgrade(&queue),
+        });
+
+        let opening = stream::once(async { Bytes::from_static(b"retry: 3000\n\n") });
+        let events = stream::unfold(queue, |queue| async move {
+            let chunk = tokio::select! {
+                frame = queue.pop() => Bytes::from(frame?),
+                _ = tokio::time::sleep(SSE_KEEP_ALIVE) => Bytes::from_static(b": keep-alive\n\n"),
+            };
+            Some((chunk, queue))
+        });
+
+        Response::ok()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("X-Accel-Buffering", "no")
+            .stream(futures::StreamExt::chain(opening, events))
+    }
+
+    /// Number of open event streams
+    pub async fn sse_count(&self) -> usize {
+        let mut subscribers = self.sse.write().await;
+        subscribers.retain(|subscriber| subscriber.queue.strong_count() > 0);
+        subscribers.len()
+    }
+
+    /// Queue a broadcast on the event streams listening to `channel`, or on
+    /// every stream when `channel` is `None`. Returns how many accepted it.
+    pub(crate) async fn publish_sse(&self, channel: Option<&str>, message: &str) -> usize {
+        let queues: Vec<Arc<SendQueue>> = {
+            let mut subscribers = self.sse.write().await;
+            subscribers.retain(|subscriber| subscriber.queue.strong_count() > 0);
+            subscribers
+                .iter()
+                .filter(|subscriber| channel.map_or(true, |channel| subscriber.channels.iter().any(|c| c == channel)))
+                .filter_map(|subscriber| subscriber.queue.upgrade())
+                .collect()
+        };
+        if queues.is_empty() {
+            return 0;
+        }
+        Self::enqueue(&queues, &sse_frame(channel, message)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_broadcasts_reach_subscribed_event_streams() {
+        let manager = WebSocketManager::new();
+        let req = Request::mock(http::Method::GET, "/events?channels=orders,%20stats&channel=orders");
+        assert_eq!(requested_channels(&req), vec!["orders", "stats"]);
+
+        let mut response = manager.sse(&["orders".to_string()]).await;
+        assert_eq!(response.headers().get("content-type").unwrap(), "text/event-stream");
+        let mut events = response.take_stream().unwrap();
+        assert_eq!(events.next().await.unwrap(), "retry: 3000\n\n");
+
+        assert_eq!(manager.broadcast_to_room("stats", "ignored").await.unwrap(), 0);
+        assert_eq!(manager.broadcast_to_room("orders", "{\"id\":42}\nsecond line").await.unwrap(), 1);
+        manager.broadcast("everyone").await.unwrap();
+        assert_eq!(events.next().await.unwrap(), "event: orders\ndata: {\"id\":42}\ndata: second line\n\n");
+        assert_eq!(events.next().await.unwrap(), "data: everyone\n\n");
+
+        assert_eq!(manager.sse_count().await, 1);
+        drop(events);
+        assert_eq!(manager.sse_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_app_sse_refuses_channels_it_does_not_allow() {
+        let app = crate::App::new().sse("/events", |req: &Request, channel: &str| {
+            channel == "status" || (channel == "billing" && req.header("authorization").is_some())
+        });
+
+        let status = app.handle_request(Request::mock(http::Method::GET, "/events?channels=status")).await;
+        assert_eq!(status.headers().get("content-type").unwrap(), "text/event-stream");
+        let billing = app.handle_request(Request::mock(http::Method::GET, "/events?channels=status,billing")).await;
+        assert_eq!(billing.status_code(), http::StatusCode::FORBIDDEN);
+        let signed_in = Request::mock(http::Method::GET, "/events?channel=billing").with_header("authorization", "Bearer ada");
+        assert_eq!(app.handle_request(signed_in).await.status_code(), http::StatusCode::OK);
+    }
+}
@@ -0,0 +1,429 @@
+//! # WebSocket Test Client
+//!
+//! Drive `App::websocket` endpoints from ordinary `#[tokio::test]` functions
+//! without binding a port. The client performs the HTTP upgrade against the
+//! in-process app (so routing, middleware and the handshake are exercised),
+//! then talks to the registered handler over an in-memory pipe.
+//!
+//! ```rust,no_run
+//! use torch_web::{App, websocket::{WebSocketManager, WebSocketMessage}};
+//! use torch_web::websocket::testing::WebSocketTestClient;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let manager = WebSocketManager::new();
+//! let app = App::new()
+//!     .with_state(manager.clone())
+//!     .websocket("/chat", |mut connection| async move {
+//!         while let Some(message) = connection.receive().await? {
+//!             if let WebSocketMessage::Text(text) = message {
+//!                 connection.broadcast_to_room("lobby", &text).await?;
+//!             }
+//!         }
+//!         Ok(())
+//!     });
+//!
+//! let mut alice = WebSocketTestClient::connect(&app, "/chat").await?;
+//! let mut bob = WebSocketTestClient::connect(&app, "/chat").await?;
+//! alice.join_room("lobby").await;
+//! bob.join_room("lobby").await;
+//!
+//! alice.send_text("hello").await?;
+//! alice.assert_received_text("hello").await;
+//! bob.assert_received_text("hello").await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::DuplexStream;
+use tokio_tungstenite::tungstenite::{protocol::Role, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use super::{generate_websocket_accept_key, WebSocketManager, WebSocketMessage};
+use crate::{App, Request};
+
+type TestResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Size of the in-memory pipe between the test client and the handler
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// In-process WebSocket client for testing
+pub struct WebSocketTestClient {
+    id: String,
+    stream: WebSocketStream<DuplexStream>,
+    manager: WebSocketManager,
+    timeout: Duration,
+    protocol: Option<String>,
+}
+
+impl WebSocketTestClient {
+    /// Upgrade a connection to `path` and start the matching handler
+    pub async fn connect(app: &App, path: &str) -> TestResult<Self> {
+        Self::connect_with_headers(app, path, &[]).await
+    }
+
+    /// Like [`connect`](Self::connect), sending extra headers (cookies,
+    /// authorization, ...) with the upgrade request
+    pub async fn connect_with_headers(app: &App, path: &str, headers: &[(&str, &str)]) -> TestResult<Self> {
+        use base64::{engine::general_purpose, Engine as _};
+
+        let key = general_purpose::STANDARD.encode(uuid::Uuid::new_v4().as_bytes());
+
+        let mut builder = http::Request::builder()
+            .method(http::Method::GET)
+            .uri(path)
+            .header("Upgrade", "websocket")
+            .header("Connection", "Upgrade")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", &key);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let (parts, _) = builder.body(())?.into_parts();
+        let request = Request::from_parts(parts, Vec::new());
+        let route_path = request.path().to_string();
+
+        let response = app.handle_request(request).await;
+        if response.status_code() != http::StatusCode::SWITCHING_PROTOCOLS {
+            return Err(format!(
+                "WebSocket upgrade to {} was rejected with status {}",
+                path,
+                response.status_code()
+            ).into());
+        }
+
+        let accept = response
+            .headers()
+            .get("sec-websocket-accept")
+            .and_then(|value| value.to_str().ok());
+        if accept != Some(generate_websocket_accept_key(&key).as_str()) {
+            return Err("Upgrade response carried an invalid Sec-WebSocket-Accept header".into());
+        }
+
+        let protocol = response
+            .headers()
+            .get("sec-websocket-protocol")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let (client_io, server_io) = tokio::io::duplex(PIPE_CAPACITY);
+        let id = app
+            .accept_websocket(&route_path, server_io, response.extensions())
+            .await
+            .ok_or_else(|| format!("No WebSocket handler registered for {}", route_path))?;
+
+        Ok(Self {
+            id,
+            stream: WebSocketStream::from_raw_socket(client_io, Role::Client, None).await,
+            manager: app.websocket_manager(),
+            timeout: Duration::from_secs(1),
+            protocol,
+        })
+    }
+
+    /// How long receives and assertions wait for a message (default 1s)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Server-side id of this connection
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The subprotocol the server accepted, when the upgrade request
+    /// offered some in `Sec-WebSocket-Protocol`
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Put the server side of this connection into a room
+    pub async fn join_room(&self, room: &str) {
+        self.manager.join_room(room, &self.id).await;
+    }
+
+    /// Take the server side of this connection out of a room
+    pub async fn leave_room(&self, room: &str) {
+        self.manager.leave_room(room, &self.id).await;
+    }
+
+    /// Send a text message to the handler
+    pub async fn send_text(&mut self, text: &str) -> TestResult<()> {
+        self.stream.send(Message::Text(text.to_string())).await?;
+        Ok(())
+    }
+
+    /// Send a binary message to the handler
+    pub async fn send_binary(&mut self, data: &[u8]) -> TestResult<()> {
+        self.stream.send(Message::Binary(data.to_vec())).await?;
+        Ok(())
+    }
+
+    /// Serialize a value and send it as a text message
+    #[cfg(feature = "json")]
+    pub async fn send_json<T: serde::Serialize>(&mut self, value: &T) -> TestResult<()> {
+        let text = serde_json::to_string(value)?;
+        self.send_text(&text).await
+    }
+
+    /// Wait for the next text, binary or close message, skipping ping/pong
+    pub async fn receive(&mut self) -> TestResult<WebSocketMessage> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, async {
+            loop {
+                match self.stream.next().await {
+                    Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => continue,
+                    Some(Ok(message)) => return Ok(WebSocketMessage::from_tungstenite(message)),
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Err("WebSocket connection closed".into()),
+                }
+            }
+        })
+        .await
+        .map_err(|_| format!("No WebSocket message received within {:?}", timeout))?
+    }
+
+    /// Wait for the next message and require it to be text
+    pub async fn receive_text(&mut self) -> TestResult<String> {
+        match self.receive().await? {
+            WebSocketMessage::Text(text) => Ok(text),
+            other => Err(format!("Expected a text message, got {:?}", other).into()),
+        }
+    }
+
+    /// Wait for the next text message and deserialize it
+    #[cfg(feature = "json")]
+    pub async fn receive_json<T: serde::de::DeserializeOwned>(&mut self) -> TestResult<T> {
+        let text = self.receive_text().await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Wait for the next binary message and deserialize it from MessagePack
+    #[cfg(feature = "msgpack")]
+    pub async fn receive_msgpack<T: serde::de::DeserializeOwned>(&mut self) -> TestResult<T> {
+        let message = self.receive().await?;
+        Ok(super::WireFormat::MessagePack.decode(&message)?)
+    }
+
+    /// Serialize a value as MessagePack and send it as a binary message
+    #[cfg(feature = "msgpack")]
+    pub async fn send_msgpack<T: serde::Serialize>(&mut self, value: &T) -> TestResult<()> {
+        self.send_binary(&rmp_serde::to_vec_named(value)?).await
+    }
+
+    /// Assert that the next message is exactly `expected`
+    pub async fn assert_received_text(&mut self, expected: &str) {
+        match self.receive_text().await {
+            Ok(text) => assert_eq!(text, expected, "unexpected WebSocket message for {}", self.id),
+            Err(e) => panic!("expected WebSocket message {:?} for {}: {}", expected, self.id, e),
+        }
+    }
+
+    /// Assert that the next message deserializes to `expected`
+    #[cfg(feature = "json")]
+    pub async fn assert_received_json<T>(&mut self, expected: &T)
+    where
+        T: serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        match self.receive_json::<T>().await {
+            Ok(value) => assert_eq!(&value, expected, "unexpected WebSocket message for {}", self.id),
+            Err(e) => panic!("expected WebSocket message {:?} for {}: {}", expected, self.id, e),
+        }
+    }
+
+    /// Assert that nothing arrives within the client's timeout
+    pub async fn assert_nothing_received(&mut self) {
+        if let Ok(message) = self.receive().await {
+            panic!("expected no WebSocket message for {}, got {:?}", self.id, message);
+        }
+    }
+
+    /// Close the connection from the client side
+    pub async fn close(mut self) -> TestResult<()> {
+        self.stream.close(None).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+    use std::future::Future;
+    use std::pin::Pin;
+
+    fn echo_app() -> App {
+        App::new()
+            .websocket("/echo", |mut connection| async move {
+                while let Some(message) = connection.receive().await? {
+                    match message {
+                        WebSocketMessage::Text(text) => connection.send_text(&text).await?,
+                        WebSocketMessage::Close => break,
+                        _ => {}
+                    }
+                }
+                Ok(())
+            })
+            .get("/plain", |_req: Request| async { Response::ok().body("plain") })
+    }
+
+    #[tokio::test]
+    async fn test_echo_round_trip() {
+        let app = echo_app();
+        let mut client = WebSocketTestClient::connect(&app, "/echo").await.unwrap();
+
+        client.send_text("ping").await.unwrap();
+        client.assert_received_text("ping").await;
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_non_websocket_route() {
+        let app = echo_app();
+
+        assert!(WebSocketTestClient::connect(&app, "/plain").await.is_err());
+        assert!(WebSocketTestClient::connect(&app, "/missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_room_broadcasts() {
+        let manager = WebSocketManager::new();
+        let app = echo_app().with_state(manager.clone());
+
+        let mut alice = WebSocketTestClient::connect(&app, "/echo").await.unwrap();
+        let mut bob = WebSocketTestClient::connect(&app, "/echo")
+            .await
+            .unwrap()
+            .timeout(Duration::from_millis(50));
+        alice.join_room("lobby").await;
+
+        assert_eq!(manager.room_members("lobby").await, vec![alice.id().to_string()]);
+        assert_eq!(manager.broadcast_to_room("lobby", "welcome").await.unwrap(), 1);
+
+        alice.assert_received_text("welcome").await;
+        bob.assert_nothing_received().await;
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_typed_messages() {
+        let app = echo_app();
+        let mut client = WebSocketTestClient::connect(&app, "/echo").await.unwrap();
+
+        let message = serde_json::json!({ "type": "move", "x": 3 });
+        client.send_json(&message).await.unwrap();
+        client.assert_received_json(&message).await;
+    }
+
+    #[tokio::test]
+    async fn test_on_upgrade_authorizes_and_attaches_user() {
+        struct User(String);
+
+        let app = App::new()
+            .websocket("/private", |mut connection| async move {
+                let name = connection.user::<User>().map(|user| user.0.clone()).unwrap_or_default();
+                connection.send_text(&format!("hello {}", name)).await?;
+                while connection.receive().await?.is_some() {}
+                Ok(())
+            })
+            .on_upgrade(|req: Request| async move {
+                match req.header("authorization") {
+                    Some("Bearer ada") => Ok(User("Ada".to_string())),
+                    Some(_) => Err(Response::forbidden()),
+                    None => Err(Response::unauthorized()),
+                }
+            });
+
+        let err = WebSocketTestClient::connect(&app, "/private").await.err().unwrap();
+        assert!(err.to_string().contains("401"), "{}", err);
+        let err = WebSocketTestClient::connect_with_headers(&app, "/private", &[("authorization", "Bearer eve")])
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("403"), "{}", err);
+
+        let mut client = WebSocketTestClient::connect_with_headers(&app, "/private", &[("authorization", "Bearer ada")])
+            .await
+            .unwrap();
+        client.assert_received_text("hello Ada").await;
+    }
+
+    #[tokio::test]
+    async fn test_websocket_with_auth_refuses_unauthorized_upgrades() {
+        let app = App::new().websocket_with_auth(
+            "/admin",
+            |req: Request| async move { req.header("authorization").map(str::to_string).ok_or_else(Response::unauthorized) },
+            |mut connection| async move {
+                let token = connection.user::<String>().cloned().unwrap_or_default();
+                connection.send_text(&token).await?;
+                while connection.receive().await?.is_some() {}
+                Ok(())
+            },
+        );
+
+        let err = WebSocketTestClient::connect(&app, "/admin").await.err().unwrap();
+        assert!(err.to_string().contains("401"), "{}", err);
+        let mut client = WebSocketTestClient::connect_with_headers(&app, "/admin", &[("authorization", "Bearer ada")])
+            .await
+            .unwrap();
+        client.assert_received_text("Bearer ada").await;
+    }
+
+    #[tokio::test]
+    async fn test_upgrades_go_through_app_middleware() {
+        let app = echo_app().middleware(|req: Request, next: Box<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> + Send + Sync>| -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+            Box::pin(async move {
+                match req.header("x-api-key") {
+                    Some("secret") => next(req).await,
+                    _ => Response::unauthorized(),
+                }
+            })
+        });
+
+        let err = WebSocketTestClient::connect(&app, "/echo").await.err().unwrap();
+        assert!(err.to_string().contains("401"), "{}", err);
+        let mut client = WebSocketTestClient::connect_with_headers(&app, "/echo", &[("x-api-key", "secret")])
+            .await
+            .unwrap();
+        client.send_text("ping").await.unwrap();
+        client.assert_received_text("ping").await;
+    }
+
+    #[test]
+    #[should_panic(expected = "must directly follow")]
+    fn test_on_upgrade_after_another_route_panics() {
+        let _ = App::new()
+            .websocket("/ws", |_connection| async move { Ok(()) })
+            .get("/health", |_req: Request| async { Response::ok() })
+            .on_upgrade(|_req: Request| async move { Err::<(), _>(Response::forbidden()) });
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[tokio::test]
+    async fn test_subprotocol_negotiation_selects_format() {
+        let app = App::new()
+            .websocket("/ticker", |mut connection| async move {
+                let protocol = connection.protocol().unwrap_or("none").to_string();
+                while let Some(symbol) = connection.receive_value::<String>().await? {
+                    connection.send_value(&(symbol, protocol.clone())).await?;
+                }
+                Ok(())
+            })
+            .subprotocols(&["msgpack", "json"]);
+
+        let mut packed = WebSocketTestClient::connect_with_headers(&app, "/ticker", &[("sec-websocket-protocol", "json, msgpack")])
+            .await
+            .unwrap();
+        assert_eq!(packed.protocol(), Some("msgpack"));
+        packed.send_msgpack(&"ACME").await.unwrap();
+        assert_eq!(packed.receive_msgpack::<(String, String)>().await.unwrap(), ("ACME".to_string(), "msgpack".to_string()));
+
+        let mut plain = WebSocketTestClient::connect(&app, "/ticker").await.unwrap();
+        assert_eq!(plain.protocol(), None);
+        plain.send_json(&"ACME").await.unwrap();
+        plain.assert_received_json(&("ACME".to_string(), "none".to_string())).await;
+    }
+}
@@ -0,0 +1,23 @@
+//! The profiler's allocation counts, with the [`CountingAllocator`] as this
+//! test binary's global allocator
+
+use torch_web::dev::profiler::{CountingAllocator, Profiler};
+use torch_web::{App, Request};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator::system();
+
+#[tokio::test]
+async fn test_profiler_counts_allocations() {
+    let app = App::new().middleware(Profiler::new().log(false)).get("/posts", |_req: Request| async {
+        tokio::task::yield_now().await;
+        let posts: Vec<String> = (0..100).map(|i| format!("post {}", i)).collect();
+        posts.join("\n")
+    });
+
+    let response = app.handle_request(Request::mock(http::Method::GET, "/posts")).await;
+    let timing = response.headers().get("server-timing").unwrap().to_str().unwrap();
+    assert!(timing.starts_with("db;dur=0.00;desc=\"0 queries\", render;dur=0.00;desc=\"0 renders\", other;dur="), "{}", timing);
+    let allocations: u64 = timing.split("alloc;desc=\"").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap();
+    assert!(allocations >= 100, "{}", timing);
+}